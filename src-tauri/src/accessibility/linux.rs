@@ -0,0 +1,37 @@
+//! The accessibility bus reports whether a screen reader is running; the
+//! announcement itself goes to Speech Dispatcher through [`crate::tts`]
+
+use tauri::{AppHandle, Manager};
+use zbus::Connection;
+
+use crate::tts::Tts;
+
+pub(super) fn announce(app: &AppHandle, text: &str) {
+    let app = app.clone();
+    let text = text.to_string();
+    tauri::async_runtime::spawn(async move {
+        match screen_reader_enabled().await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                log::debug!("Couldn't tell if a screen reader is running: {}", e);
+                return;
+            }
+        }
+        let result =
+            tauri::async_runtime::spawn_blocking(move || app.state::<Tts>().speak(&text, true))
+                .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::warn!("Failed to announce voice state: {}", e),
+            Err(e) => log::warn!("Failed to announce voice state: {}", e),
+        }
+    });
+}
+
+async fn screen_reader_enabled() -> zbus::Result<bool> {
+    let session = Connection::session().await?;
+    let proxy =
+        zbus::Proxy::new(&session, "org.a11y.Bus", "/org/a11y/bus", "org.a11y.Status").await?;
+    proxy.get_property("ScreenReaderEnabled").await
+}
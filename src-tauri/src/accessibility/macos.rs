@@ -0,0 +1,40 @@
+//! VoiceOver reads announcement notifications posted on the application
+//! object, at high priority so they interrupt what it's reading
+
+use objc2::runtime::AnyObject;
+use objc2::MainThreadMarker;
+use objc2_app_kit::{
+    NSAccessibilityAnnouncementKey, NSAccessibilityAnnouncementRequestedNotification,
+    NSAccessibilityPostNotificationWithUserInfo, NSAccessibilityPriorityKey,
+    NSAccessibilityPriorityLevel, NSApplication,
+};
+use objc2_foundation::{NSDictionary, NSNumber, NSString};
+use tauri::AppHandle;
+
+pub(super) fn announce(app: &AppHandle, text: &str) {
+    let text = text.to_string();
+    let result = app.run_on_main_thread(move || {
+        let Some(mtm) = MainThreadMarker::new() else {
+            return;
+        };
+        let application = NSApplication::sharedApplication(mtm);
+        let announcement = NSString::from_str(&text);
+        let priority = NSNumber::new_isize(NSAccessibilityPriorityLevel::High.0);
+        // SAFETY: the statics are provided by AppKit, and the user info
+        // holds the value types the notification documents
+        unsafe {
+            let info = NSDictionary::<NSString, AnyObject>::from_slices(
+                &[NSAccessibilityAnnouncementKey, NSAccessibilityPriorityKey],
+                &[announcement.as_ref(), priority.as_ref()],
+            );
+            NSAccessibilityPostNotificationWithUserInfo(
+                &application,
+                NSAccessibilityAnnouncementRequestedNotification,
+                Some(&info),
+            );
+        }
+    });
+    if let Err(e) = result {
+        log::warn!("Failed to post accessibility announcement: {}", e);
+    }
+}
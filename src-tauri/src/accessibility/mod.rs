@@ -0,0 +1,120 @@
+//! Screen reader announcements for voice state
+//!
+//! Muting, deafening and push-to-talk are often toggled with global
+//! shortcuts, the hardware mute key or the dock and jump list while another
+//! app is focused, where the webview's live regions aren't read out. The
+//! frontend reports the voice state after every change, and changes made
+//! while the main window isn't focused are announced through the
+//! platform's accessibility API:
+//! - macOS: an `NSAccessibilityAnnouncementRequestedNotification`, which
+//!   VoiceOver reads whichever app is focused
+//! - Windows: a UI Automation notification event, read by Narrator and
+//!   NVDA
+//! - Linux: AT-SPI has no announcements for apps outside their own
+//!   accessibility tree, so they go to Speech Dispatcher, which Orca speaks
+//!   through, while a screen reader is running
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(windows)]
+mod windows;
+
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use tauri::{AppHandle, Manager};
+
+use crate::db::Database;
+use crate::settings;
+
+/// Setting that turns the announcements off; on unless set to `false`
+pub const ANNOUNCE_VOICE_STATE_SETTING: &str = "accessibility.announce_voice_state";
+
+/// Voice state as the frontend reports it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub struct VoiceState {
+    pub muted: bool,
+    pub deafened: bool,
+    /// Push-to-talk is held
+    pub transmitting: bool,
+}
+
+impl VoiceState {
+    /// What changed since `previous`, as it's read out
+    fn changes(self, previous: VoiceState) -> Vec<&'static str> {
+        let mut changes = Vec::new();
+        if self.deafened != previous.deafened {
+            changes.push(if self.deafened {
+                "Deafened"
+            } else {
+                "Undeafened"
+            });
+        }
+        // Deafening mutes as well, which would be redundant to read out
+        if self.muted != previous.muted && self.deafened == previous.deafened {
+            changes.push(if self.muted { "Muted" } else { "Unmuted" });
+        }
+        if self.transmitting != previous.transmitting {
+            changes.push(if self.transmitting {
+                "Talking"
+            } else {
+                "Stopped talking"
+            });
+        }
+        changes
+    }
+}
+
+/// The voice state last reported
+#[derive(Default)]
+pub struct VoiceAnnouncer {
+    last: Mutex<Option<VoiceState>>,
+}
+
+/// Record the voice state and announce what changed if the app isn't
+/// focused. The first report is only recorded.
+pub fn report(app: &AppHandle, state: VoiceState) {
+    let previous = {
+        let announcer = app.state::<VoiceAnnouncer>();
+        let mut last = announcer.last.lock().unwrap_or_else(|e| e.into_inner());
+        last.replace(state)
+    };
+    let Some(previous) = previous else {
+        return;
+    };
+    let changes = state.changes(previous);
+    if changes.is_empty() || !enabled(&app.state::<Database>()) {
+        return;
+    }
+    // The frontend announces changes made while it's focused itself
+    let focused = app
+        .get_webview_window("main")
+        .and_then(|window| window.is_focused().ok())
+        .unwrap_or(false);
+    if !focused {
+        announce(app, &changes.join(", "));
+    }
+}
+
+/// Have the screen reader read `text`
+pub fn announce(app: &AppHandle, text: &str) {
+    #[cfg(target_os = "linux")]
+    linux::announce(app, text);
+    #[cfg(target_os = "macos")]
+    macos::announce(app, text);
+    #[cfg(windows)]
+    windows::announce(app, text);
+    #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+    let _ = (app, text);
+}
+
+fn enabled(db: &Database) -> bool {
+    db.with(|conn| settings::get_value(conn, ANNOUNCE_VOICE_STATE_SETTING))
+        .map_err(|e| log::error!("Failed to read voice state announcement setting: {}", e))
+        .ok()
+        .flatten()
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+}
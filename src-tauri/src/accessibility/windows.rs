@@ -0,0 +1,41 @@
+//! UI Automation notification events are raised on the main window's host
+//! provider; screen readers read them even while another window has focus
+
+use tauri::{AppHandle, Manager};
+use windows::core::BSTR;
+use windows::Win32::Foundation::HWND;
+use windows::Win32::UI::Accessibility::{
+    NotificationKind_ActionCompleted, NotificationProcessing_ImportantMostRecent,
+    UiaHostProviderFromHwnd, UiaRaiseNotificationEvent,
+};
+
+/// Lets screen readers tell voice state announcements from others
+const ACTIVITY_ID: &str = "redoubt.voice-state";
+
+pub(super) fn announce(app: &AppHandle, text: &str) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    let hwnd = match window.hwnd() {
+        Ok(hwnd) => HWND(hwnd.0),
+        Err(e) => {
+            log::warn!("No window to announce from: {}", e);
+            return;
+        }
+    };
+    // SAFETY: the window handle belongs to the live main window
+    let result = unsafe {
+        UiaHostProviderFromHwnd(hwnd).and_then(|provider| {
+            UiaRaiseNotificationEvent(
+                &provider,
+                NotificationKind_ActionCompleted,
+                NotificationProcessing_ImportantMostRecent,
+                &BSTR::from(text),
+                &BSTR::from(ACTIVITY_ID),
+            )
+        })
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to raise accessibility notification: {}", e);
+    }
+}
@@ -0,0 +1,35 @@
+//! App actions
+//!
+//! The actions that can be triggered from outside the window: global
+//! shortcuts, hardware keys, the launcher menus and the control API (see
+//! [`crate::control_api`]). Whatever triggers one, it reaches the frontend
+//! as the same event, which the frontend keeps the state for.
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    PttPressed,
+    PttReleased,
+    ToggleMute,
+    ToggleDeafen,
+}
+
+impl Action {
+    /// The event the frontend gets for the action
+    pub fn event(self) -> &'static str {
+        match self {
+            Self::PttPressed => "ptt-pressed",
+            Self::PttReleased => "ptt-released",
+            Self::ToggleMute => "toggle-mute",
+            Self::ToggleDeafen => "toggle-deafen",
+        }
+    }
+}
+
+/// Carry out an action
+pub fn dispatch(app: &AppHandle, action: Action) {
+    let _ = app.emit(action.event(), ());
+}
@@ -0,0 +1,197 @@
+//! Minimal REST client for backend services
+//!
+//! The frontend's `ApiClient` remains the main way the app talks to a
+//! server; this covers the calls the backend needs to make on its own.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("{title} ({status}): {detail}")]
+    Status {
+        status: u16,
+        title: String,
+        detail: String,
+    },
+}
+
+impl ApiError {
+    /// True when the server rejected our credentials
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, ApiError::Status { status: 401, .. })
+    }
+}
+
+/// RFC 7807 problem document returned by the server on errors
+#[derive(Deserialize, Default)]
+struct Problem {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    detail: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RefreshResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// An uploaded file as returned by the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: u64,
+    pub url: String,
+    pub is_image: bool,
+}
+
+/// The signed-in user
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+}
+
+#[derive(Serialize)]
+struct RefreshRequest<'a> {
+    refresh_token: &'a str,
+}
+
+#[derive(Serialize)]
+struct MessageRequest<'a> {
+    content: &'a str,
+    nonce: &'a str,
+}
+
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl ApiClient {
+    /// Create a client for the instance at `instance_url` (without `/api/v1`)
+    pub fn new(instance_url: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: format!("{}/api/v1", instance_url.trim_end_matches('/')),
+        }
+    }
+
+    /// Exchange a refresh token for a new token pair
+    pub async fn refresh(&self, refresh_token: &str) -> Result<RefreshResponse, ApiError> {
+        let response = self
+            .http
+            .post(format!("{}/auth/refresh", self.base_url))
+            .json(&RefreshRequest { refresh_token })
+            .send()
+            .await?;
+
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// The user the token belongs to
+    pub async fn me(&self, token: &str) -> Result<User, ApiError> {
+        let response = self
+            .http
+            .get(format!("{}/users/me", self.base_url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Post a message. The server drops a repeated `nonce`, so a message
+    /// that's also sent from the outbox only shows up once.
+    pub async fn send_message(
+        &self,
+        token: &str,
+        channel_id: &str,
+        content: &str,
+        nonce: &str,
+    ) -> Result<(), ApiError> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/channels/{}/messages",
+                self.base_url, channel_id
+            ))
+            .bearer_auth(token)
+            .json(&MessageRequest { content, nonce })
+            .send()
+            .await?;
+
+        check(response).await?;
+        Ok(())
+    }
+
+    /// Start downloading an attachment; the body is left for the caller to stream
+    pub async fn attachment(
+        &self,
+        token: &str,
+        attachment_id: &str,
+    ) -> Result<reqwest::Response, ApiError> {
+        let response = self
+            .http
+            .get(format!("{}/attachments/{}", self.base_url, attachment_id))
+            .bearer_auth(token)
+            .send()
+            .await?;
+
+        check(response).await
+    }
+
+    /// Attach a file to a message. `fields` are sent as extra form fields
+    /// alongside the file.
+    pub async fn upload_attachment(
+        &self,
+        token: &str,
+        message_id: &str,
+        file: reqwest::multipart::Part,
+        fields: Vec<(&'static str, String)>,
+    ) -> Result<Attachment, ApiError> {
+        let mut form = reqwest::multipart::Form::new().part("file", file);
+        for (name, value) in fields {
+            form = form.text(name, value);
+        }
+
+        let response = self
+            .http
+            .post(format!(
+                "{}/messages/{}/attachments",
+                self.base_url, message_id
+            ))
+            .bearer_auth(token)
+            .multipart(form)
+            .send()
+            .await?;
+
+        Ok(check(response).await?.json().await?)
+    }
+}
+
+/// Turn non-success responses into `ApiError::Status`
+async fn check(response: reqwest::Response) -> Result<reqwest::Response, ApiError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let problem: Problem = response.json().await.unwrap_or_default();
+    Err(ApiError::Status {
+        status: status.as_u16(),
+        title: if problem.title.is_empty() {
+            status.canonical_reason().unwrap_or("Error").to_string()
+        } else {
+            problem.title
+        },
+        detail: problem.detail,
+    })
+}
@@ -0,0 +1,488 @@
+//! Local call recordings
+//!
+//! Call audio is mixed in the webview, so it hands finished 48 kHz mono
+//! blocks over here: either the whole call on the [`MIX_TRACK`], or one
+//! track per speaker. Each track is encoded to its own Ogg Opus file on the
+//! recording's thread, next to a `markers.jsonl` log of who joined, left,
+//! and gave or withdrew consent, timed from the start of the recording.
+//!
+//! Everything is written as it arrives: Opus pages are flushed about once
+//! a second and markers on every line. `recording.json` describes the
+//! recording and its tracks from the start and is marked interrupted until
+//! the recording stops, so one cut off by a crash still plays and is
+//! completed from its marker log the next time the app starts.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::opus_file::{OpusFileError, OpusWriter, FRAME_SAMPLES};
+use crate::automation::{self, HookEvent};
+
+/// Track the whole call goes on when it's recorded mixed
+pub const MIX_TRACK: &str = "mix";
+
+const MANIFEST: &str = "recording.json";
+const MARKERS: &str = "markers.jsonl";
+/// Most tracks in one recording
+const MAX_TRACKS: usize = 32;
+/// Longest track name; names become file names
+const MAX_TRACK_LEN: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CallRecordingError {
+    #[error("a call is already being recorded")]
+    AlreadyRecording,
+    #[error("recording {0} isn't running")]
+    NotRecording(String),
+    #[error("invalid track name {0:?}")]
+    InvalidTrack(String),
+    #[error("only the {MIX_TRACK:?} track can be written to a mixed recording")]
+    NotMixed,
+    #[error("only {0} tracks can be recorded at once")]
+    TooManyTracks(usize),
+    #[error("no directory for recordings: {0}")]
+    NoDirectory(#[from] tauri::Error),
+    #[error("failed to write recording: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to write recording: {0}")]
+    File(#[from] OpusFileError),
+    #[error("failed to write recording: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("recording stopped unexpectedly")]
+    Writer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    /// One track with everyone
+    Mixed,
+    /// A track per speaker
+    Multitrack,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkerKind {
+    Started,
+    Joined,
+    Left,
+    ConsentGiven,
+    ConsentWithdrawn,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Marker {
+    pub kind: MarkerKind,
+    /// The participant it's about; unset for the recording itself
+    #[serde(default)]
+    pub participant: Option<String>,
+    /// Milliseconds into the recording; filled in when it's added
+    #[serde(default)]
+    pub at_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackInfo {
+    pub name: String,
+    pub file: String,
+    /// When the track's first audio arrived, in milliseconds into the
+    /// recording, so speakers who joined late line up
+    pub offset_ms: u64,
+}
+
+/// `recording.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub id: String,
+    pub instance_id: String,
+    pub channel_id: String,
+    pub layout: Layout,
+    pub started_at: DateTime<Utc>,
+    /// Unset while recording
+    pub stopped_at: Option<DateTime<Utc>>,
+    /// It was cut off rather than stopped; also set while recording
+    pub interrupted: bool,
+    pub tracks: Vec<TrackInfo>,
+    pub markers: Vec<Marker>,
+}
+
+/// A stopped recording
+#[derive(Debug, Clone, Serialize)]
+pub struct FinishedRecording {
+    pub dir: PathBuf,
+    #[serde(flatten)]
+    pub manifest: Manifest,
+}
+
+/// Payload of "recording-active"
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingActive {
+    pub active: bool,
+    pub id: String,
+    pub instance_id: String,
+    pub channel_id: String,
+    pub layout: Layout,
+}
+
+enum Message {
+    Audio(String, Vec<f32>),
+    Marker(Marker),
+}
+
+/// The recording in progress
+struct Running {
+    info: RecordingActive,
+    dir: PathBuf,
+    started: Instant,
+    tx: mpsc::Sender<Message>,
+    writer: JoinHandle<Result<Manifest, CallRecordingError>>,
+}
+
+/// The call being recorded, if any; only one at a time
+#[derive(Default)]
+pub struct CallRecordings {
+    running: Mutex<Option<Running>>,
+}
+
+impl CallRecordings {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Running>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Start recording a call. Emits "recording-active".
+pub fn start(
+    app: &AppHandle,
+    instance_id: String,
+    channel_id: String,
+    layout: Layout,
+) -> Result<RecordingActive, CallRecordingError> {
+    let recordings = app.state::<CallRecordings>();
+    let mut running = recordings.lock();
+    if running.is_some() {
+        return Err(CallRecordingError::AlreadyRecording);
+    }
+
+    let started_at = Utc::now();
+    let id = uuid::Uuid::new_v4().to_string();
+    let dir = recordings_dir(app)?.join(format!(
+        "{} {}",
+        started_at.format("%Y-%m-%d %H-%M-%S"),
+        &id[..8]
+    ));
+    fs::create_dir_all(&dir)?;
+    let markers = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(MARKERS))?;
+
+    let manifest = Manifest {
+        id: id.clone(),
+        instance_id: instance_id.clone(),
+        channel_id: channel_id.clone(),
+        layout,
+        started_at,
+        stopped_at: None,
+        interrupted: true,
+        tracks: Vec::new(),
+        markers: Vec::new(),
+    };
+    write_manifest(&dir, &manifest)?;
+
+    let started = Instant::now();
+    let (tx, rx) = mpsc::channel();
+    let writer_dir = dir.clone();
+    let writer = std::thread::Builder::new()
+        .name("call-recording".into())
+        .spawn(move || write(&writer_dir, started, markers, manifest, rx))?;
+
+    let _ = tx.send(Message::Marker(Marker {
+        kind: MarkerKind::Started,
+        participant: None,
+        at_ms: 0,
+    }));
+
+    let info = RecordingActive {
+        active: true,
+        id,
+        instance_id,
+        channel_id,
+        layout,
+    };
+    *running = Some(Running {
+        info: info.clone(),
+        dir,
+        started,
+        tx,
+        writer,
+    });
+    drop(running);
+
+    let _ = app.emit("recording-active", info.clone());
+    automation::fire(
+        app,
+        HookEvent::RecordingStarted,
+        vec![
+            ("instance_id", info.instance_id.clone()),
+            ("channel_id", info.channel_id.clone()),
+            ("recording_id", info.id.clone()),
+        ],
+    );
+    Ok(info)
+}
+
+/// Add a block of audio to a track of the running recording
+pub fn push_audio(
+    app: &AppHandle,
+    id: &str,
+    track: String,
+    samples: Vec<f32>,
+) -> Result<(), CallRecordingError> {
+    let recordings = app.state::<CallRecordings>();
+    let running = recordings.lock();
+    let running = current(&running, id)?;
+    if !is_valid_track(&track) {
+        return Err(CallRecordingError::InvalidTrack(track));
+    }
+    if running.info.layout == Layout::Mixed && track != MIX_TRACK {
+        return Err(CallRecordingError::NotMixed);
+    }
+    running
+        .tx
+        .send(Message::Audio(track, samples))
+        .map_err(|_| CallRecordingError::Writer)
+}
+
+/// Note a join, leave or consent change in the running recording
+pub fn add_marker(
+    app: &AppHandle,
+    id: &str,
+    mut marker: Marker,
+) -> Result<Marker, CallRecordingError> {
+    let recordings = app.state::<CallRecordings>();
+    let running = recordings.lock();
+    let running = current(&running, id)?;
+    marker.at_ms = running.started.elapsed().as_millis() as u64;
+    running
+        .tx
+        .send(Message::Marker(marker.clone()))
+        .map_err(|_| CallRecordingError::Writer)?;
+    Ok(marker)
+}
+
+/// Stop the running recording and finish its files. Blocks until they're
+/// written; emits "recording-active".
+pub fn stop(app: &AppHandle) -> Result<Option<FinishedRecording>, CallRecordingError> {
+    let Some(running) = app.state::<CallRecordings>().lock().take() else {
+        return Ok(None);
+    };
+
+    let _ = running.tx.send(Message::Marker(Marker {
+        kind: MarkerKind::Stopped,
+        participant: None,
+        at_ms: running.started.elapsed().as_millis() as u64,
+    }));
+    drop(running.tx);
+    let _ = app.emit(
+        "recording-active",
+        RecordingActive {
+            active: false,
+            ..running.info
+        },
+    );
+
+    let manifest = running
+        .writer
+        .join()
+        .map_err(|_| CallRecordingError::Writer)??;
+    Ok(Some(FinishedRecording {
+        dir: running.dir,
+        manifest,
+    }))
+}
+
+/// Write manifests for recordings a crash cut off; call once at startup
+pub fn recover(app: &AppHandle) {
+    let dir = match recordings_dir(app) {
+        Ok(dir) => dir,
+        Err(e) => {
+            log::warn!("Not recovering call recordings: {}", e);
+            return;
+        }
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.join(MANIFEST).is_file() {
+            continue;
+        }
+        match recover_one(&path) {
+            Ok(true) => log::info!("Recovered interrupted call recording {}", path.display()),
+            Ok(false) => {}
+            Err(e) => log::warn!("Failed to recover call recording {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Where recordings are kept: a Redoubt folder in the user's music or
+/// audio directory, or the app's data directory if there is none
+fn recordings_dir(app: &AppHandle) -> Result<PathBuf, CallRecordingError> {
+    match app.path().audio_dir() {
+        Ok(dir) => Ok(dir.join("Redoubt Recordings")),
+        Err(_) => Ok(app.path().app_data_dir()?.join("recordings")),
+    }
+}
+
+fn current<'a>(running: &'a Option<Running>, id: &str) -> Result<&'a Running, CallRecordingError> {
+    running
+        .as_ref()
+        .filter(|running| running.info.id == id)
+        .ok_or_else(|| CallRecordingError::NotRecording(id.to_string()))
+}
+
+fn is_valid_track(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_TRACK_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+struct Track {
+    writer: OpusWriter,
+    frame: Vec<f32>,
+    samples: u64,
+}
+
+/// Encode audio and log markers from `rx` until the recording stops
+fn write(
+    dir: &Path,
+    started: Instant,
+    mut markers: File,
+    mut manifest: Manifest,
+    rx: mpsc::Receiver<Message>,
+) -> Result<Manifest, CallRecordingError> {
+    let mut tracks: HashMap<String, Track> = HashMap::new();
+    // The manifest is still written if a track fails partway
+    let mut error = None;
+
+    for message in rx {
+        match message {
+            Message::Audio(name, samples) => {
+                if !tracks.contains_key(&name) {
+                    if tracks.len() >= MAX_TRACKS {
+                        log::warn!("{}", CallRecordingError::TooManyTracks(MAX_TRACKS));
+                        continue;
+                    }
+                    let file = format!("{}.ogg", name);
+                    let writer = match OpusWriter::create(&dir.join(&file)) {
+                        Ok(writer) => writer,
+                        Err(e) => {
+                            error.get_or_insert(e.into());
+                            continue;
+                        }
+                    };
+                    manifest.tracks.push(TrackInfo {
+                        name: name.clone(),
+                        file,
+                        offset_ms: started.elapsed().as_millis() as u64,
+                    });
+                    // Keeps the offset if the recording is cut off later
+                    if let Err(e) = write_manifest(dir, &manifest) {
+                        log::warn!("Failed to update recording manifest: {}", e);
+                    }
+                    let track = Track {
+                        writer,
+                        frame: Vec::with_capacity(FRAME_SAMPLES),
+                        samples: 0,
+                    };
+                    tracks.insert(name.clone(), track);
+                }
+                let Some(track) = tracks.get_mut(&name) else {
+                    continue;
+                };
+                for sample in samples {
+                    track.frame.push(sample);
+                    track.samples += 1;
+                    if track.frame.len() == FRAME_SAMPLES {
+                        if let Err(e) = track.writer.write_frame(&track.frame) {
+                            error.get_or_insert(e.into());
+                        }
+                        track.frame.clear();
+                    }
+                }
+            }
+            Message::Marker(marker) => {
+                let mut line = serde_json::to_vec(&marker)?;
+                line.push(b'\n');
+                markers.write_all(&line)?;
+                markers.flush()?;
+                manifest.markers.push(marker);
+            }
+        }
+    }
+
+    for (name, track) in tracks {
+        if let Err(e) = track.writer.finish(track.frame, track.samples) {
+            log::error!("Failed to finish recording track {}: {}", name, e);
+            error.get_or_insert(e.into());
+        }
+    }
+
+    manifest.stopped_at = Some(Utc::now());
+    manifest.interrupted = false;
+    write_manifest(dir, &manifest)?;
+    match error {
+        Some(e) => Err(e),
+        None => Ok(manifest),
+    }
+}
+
+fn write_manifest(dir: &Path, manifest: &Manifest) -> Result<(), CallRecordingError> {
+    // Written aside and renamed so a crash here doesn't leave half a manifest
+    let partial = dir.join(format!("{}.partial", MANIFEST));
+    fs::write(&partial, serde_json::to_vec_pretty(manifest)?)?;
+    fs::rename(&partial, dir.join(MANIFEST))?;
+    Ok(())
+}
+
+/// Complete the manifest of a recording that was cut off with the markers
+/// it logged. Returns whether it needed that.
+fn recover_one(dir: &Path) -> Result<bool, CallRecordingError> {
+    let mut manifest: Manifest = serde_json::from_slice(&fs::read(dir.join(MANIFEST))?)?;
+    if manifest.stopped_at.is_some() {
+        return Ok(false);
+    }
+
+    manifest.markers.clear();
+    if let Ok(file) = File::open(dir.join(MARKERS)) {
+        for line in BufReader::new(file).lines() {
+            // The last line may have been cut off mid-write
+            match serde_json::from_str::<Marker>(&line?) {
+                Ok(marker) => manifest.markers.push(marker),
+                Err(_) => break,
+            }
+        }
+    }
+    manifest.stopped_at = manifest
+        .markers
+        .last()
+        .map(|marker| manifest.started_at + chrono::Duration::milliseconds(marker.at_ms as i64))
+        .or(Some(manifest.started_at));
+    manifest.interrupted = true;
+    write_manifest(dir, &manifest)?;
+    Ok(true)
+}
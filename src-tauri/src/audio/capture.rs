@@ -0,0 +1,242 @@
+//! Microphone capture
+//!
+//! Opens an input device with cpal and turns whatever it delivers into mono
+//! `f32` samples at [`SAMPLE_RATE`], passed through the noise gate. cpal
+//! streams can't move between threads, so each capture owns a thread that
+//! builds the stream and keeps it alive until the capture is dropped,
+//! rebuilding it if it stalls (see [`super::watchdog`]).
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, StreamConfig};
+
+use super::gate::NoiseGate;
+use super::watchdog::{self, Health, StreamKind};
+use super::{load, SAMPLE_RATE};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureError {
+    #[error("no microphone available")]
+    NoDevice,
+    #[error("microphone sample format {0} isn't supported")]
+    UnsupportedFormat(SampleFormat),
+    #[error("failed to read microphone config: {0}")]
+    Config(#[from] cpal::DefaultStreamConfigError),
+    #[error("failed to open microphone: {0}")]
+    Build(#[from] cpal::BuildStreamError),
+    #[error("failed to start microphone: {0}")]
+    Play(#[from] cpal::PlayStreamError),
+    #[error("failed to list microphones: {0}")]
+    Devices(#[from] cpal::DevicesError),
+    #[error("failed to start capture thread: {0}")]
+    Thread(#[from] std::io::Error),
+}
+
+/// Receives each block of captured samples on the audio thread
+pub type Sink = Box<dyn FnMut(&[f32]) + Send + 'static>;
+
+/// A running capture; stops when dropped
+pub struct Capture {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Capture {
+    /// Start capturing from the named input device, or the default one
+    pub fn start(device_name: Option<String>, sink: Sink) -> Result<Self, CaptureError> {
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread = std::thread::Builder::new()
+            .name("audio-capture".into())
+            .spawn(move || {
+                // Shared so a rebuilt stream keeps feeding the same sink
+                let sink = Arc::new(Mutex::new(sink));
+                let health = Health::new();
+                let open = || open(device_name.as_deref(), sink.clone(), health.clone());
+                let stream = match open() {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = ready_tx.send(Ok(()));
+                // Runs until the capture is dropped
+                watchdog::supervise(
+                    StreamKind::Capture,
+                    device_name.as_deref(),
+                    &health,
+                    stream,
+                    &stop_rx,
+                    open,
+                );
+            })?;
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self {
+                stop: Some(stop_tx),
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                Err(e)
+            }
+            Err(_) => {
+                let _ = thread.join();
+                Err(CaptureError::NoDevice)
+            }
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Names of the connected input devices
+pub fn input_devices() -> Result<Vec<String>, CaptureError> {
+    Ok(cpal::default_host()
+        .input_devices()?
+        .filter_map(|device| device.description().ok())
+        .map(|description| description.name().to_string())
+        .collect())
+}
+
+fn find_device(name: Option<&str>) -> Result<Device, CaptureError> {
+    let host = cpal::default_host();
+    if let Some(name) = name {
+        let found = host.input_devices()?.find(|device| {
+            device
+                .description()
+                .is_ok_and(|description| description.name() == name)
+        });
+        match found {
+            Some(device) => return Ok(device),
+            None => log::warn!("Microphone {} not found, using the default", name),
+        }
+    }
+    host.default_input_device().ok_or(CaptureError::NoDevice)
+}
+
+fn open(
+    device_name: Option<&str>,
+    sink: Arc<Mutex<Sink>>,
+    health: Arc<Health>,
+) -> Result<cpal::Stream, CaptureError> {
+    let device = find_device(device_name)?;
+    let supported = device.default_input_config()?;
+    let config = supported.config();
+
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build::<f32>(&device, &config, sink, health)?,
+        SampleFormat::I16 => build::<i16>(&device, &config, sink, health)?,
+        SampleFormat::U16 => build::<u16>(&device, &config, sink, health)?,
+        SampleFormat::I32 => build::<i32>(&device, &config, sink, health)?,
+        SampleFormat::I8 => build::<i8>(&device, &config, sink, health)?,
+        SampleFormat::U8 => build::<u8>(&device, &config, sink, health)?,
+        SampleFormat::F64 => build::<f64>(&device, &config, sink, health)?,
+        format => return Err(CaptureError::UnsupportedFormat(format)),
+    };
+    stream.play()?;
+    Ok(stream)
+}
+
+fn build<T>(
+    device: &Device,
+    config: &StreamConfig,
+    sink: Arc<Mutex<Sink>>,
+    health: Arc<Health>,
+) -> Result<cpal::Stream, CaptureError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    let device_rate = config.sample_rate.max(1) as f64;
+    let mut resampler = Resampler::new(config.sample_rate, SAMPLE_RATE);
+    let mut mono = Vec::new();
+    let mut resampled = Vec::new();
+    let mut gate = NoiseGate::new(SAMPLE_RATE);
+    let errors = health.clone();
+
+    let stream = device.build_input_stream::<T, _, _>(
+        config,
+        move |data: &[T], _| {
+            let started = Instant::now();
+            health.beat();
+            mono.clear();
+            mono.extend(data.chunks(channels).map(|frame| {
+                frame.iter().map(|s| s.to_sample::<f32>()).sum::<f32>() / frame.len() as f32
+            }));
+            resampled.clear();
+            resampler.process(&mono, &mut resampled);
+            gate.process(&mut resampled);
+            // Only contended while the stream is being rebuilt
+            (sink.lock().unwrap_or_else(|e| e.into_inner()))(&resampled);
+            let audio = Duration::from_secs_f64(mono.len() as f64 / device_rate);
+            load::record(started.elapsed(), audio);
+        },
+        move |e| {
+            log::error!("Microphone stream error: {}", e);
+            errors.error(&e);
+        },
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Linear-interpolating sample rate converter. Good enough for speech; the
+/// encoder low-passes well below where interpolation artefacts show up.
+struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Position of the next output sample, where 0 is the last sample of the
+    /// previous block
+    position: f64,
+    last: f32,
+}
+
+impl Resampler {
+    fn new(from: u32, to: u32) -> Self {
+        Self {
+            step: from as f64 / to as f64,
+            position: 1.0,
+            last: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if self.step == 1.0 {
+            output.extend_from_slice(input);
+            return;
+        }
+        let Some(&last) = input.last() else {
+            return;
+        };
+
+        let len = input.len() as f64;
+        while self.position <= len {
+            let index = self.position.floor() as usize;
+            let fraction = (self.position - index as f64) as f32;
+            let a = if index == 0 {
+                self.last
+            } else {
+                input[index - 1]
+            };
+            let b = input.get(index).copied().unwrap_or(a);
+            output.push(a + (b - a) * fraction);
+            self.position += self.step;
+        }
+        self.position -= len;
+        self.last = last;
+    }
+}
@@ -0,0 +1,264 @@
+//! Per-channel audio
+//!
+//! Voice channels can have their own devices and noise gate, say a
+//! "Music" channel on an audio interface with the gate off. Overrides are
+//! kept in [`CHANNEL_OVERRIDES_SETTING`] and take effect when the frontend
+//! reports joining the channel: native captures (voice messages, captions)
+//! switch to the channel's microphone, the gate to its settings, and
+//! "audio-devices" tells the webview, which owns call media, which input
+//! and output to put the call on. Leaving goes back to the global
+//! settings.
+//!
+//! A device named by an override that isn't connected is skipped as if it
+//! weren't set, and listed as missing so the UI can say so.
+
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::gate::{self, NoiseGateSettings};
+use super::{capture, playback, INPUT_DEVICE_SETTING};
+use crate::automation::{self, HookEvent};
+use crate::db::Database;
+use crate::settings;
+
+/// Setting holding the [`ChannelOverride`]s
+pub const CHANNEL_OVERRIDES_SETTING: &str = "devices.channel_overrides";
+
+/// Most channels that can have overrides
+pub const MAX_OVERRIDES: usize = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OverrideError {
+    #[error("at most {MAX_OVERRIDES} channels can have audio overrides")]
+    TooMany,
+    #[error("an override needs an instance and a channel")]
+    Incomplete,
+    #[error("channel {0} has more than one override")]
+    Duplicate(String),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelOverride {
+    pub instance_id: String,
+    pub channel_id: String,
+    /// Microphone name, as listed by `list_audio_input_devices`
+    #[serde(default)]
+    pub input_device: Option<String>,
+    /// Output name, as listed by `list_audio_output_devices`
+    #[serde(default)]
+    pub output_device: Option<String>,
+    #[serde(default)]
+    pub noise_gate: Option<NoiseGateSettings>,
+}
+
+/// What a channel uses, and the payload of "audio-devices"
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChannelAudioState {
+    /// The joined channel, if any
+    pub instance_id: Option<String>,
+    pub channel_id: Option<String>,
+    /// `None` is the system default
+    pub input_device: Option<String>,
+    /// Output for the call; `None` leaves it as the call has it
+    pub output_device: Option<String>,
+    pub noise_gate: NoiseGateSettings,
+    /// Whether any of this comes from an override
+    pub overridden: bool,
+    /// Devices the override names that aren't connected
+    pub missing: Vec<String>,
+}
+
+/// The channel joined and its override, if it has one
+#[derive(Default)]
+pub struct ChannelAudio {
+    joined: Mutex<Option<ChannelOverride>>,
+}
+
+impl ChannelAudio {
+    /// Whether the frontend has reported being in a voice channel
+    pub fn is_joined(&self) -> bool {
+        self.lock().is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<ChannelOverride>> {
+        self.joined.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub fn overrides(db: &Database) -> Result<Vec<ChannelOverride>, OverrideError> {
+    let stored = db.with(|conn| settings::get_value(conn, CHANNEL_OVERRIDES_SETTING))?;
+    Ok(stored
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| log::warn!("Ignoring malformed channel audio overrides: {}", e))
+                .ok()
+        })
+        .unwrap_or_default())
+}
+
+/// Replace every override. A change to the joined channel's takes effect
+/// on the next join.
+pub fn set_overrides(db: &Database, overrides: Vec<ChannelOverride>) -> Result<(), OverrideError> {
+    if overrides.len() > MAX_OVERRIDES {
+        return Err(OverrideError::TooMany);
+    }
+    for (i, o) in overrides.iter().enumerate() {
+        if o.instance_id.is_empty() || o.channel_id.is_empty() {
+            return Err(OverrideError::Incomplete);
+        }
+        if overrides[..i]
+            .iter()
+            .any(|other| other.instance_id == o.instance_id && other.channel_id == o.channel_id)
+        {
+            return Err(OverrideError::Duplicate(o.channel_id.clone()));
+        }
+    }
+    let overrides: Vec<ChannelOverride> = overrides
+        .into_iter()
+        .map(|o| ChannelOverride {
+            noise_gate: o.noise_gate.map(NoiseGateSettings::clamped),
+            ..o
+        })
+        .collect();
+    let value = serde_json::to_value(&overrides).expect("overrides serialize");
+    db.with(|conn| settings::set(conn, CHANNEL_OVERRIDES_SETTING, &value))?;
+    Ok(())
+}
+
+/// Switch to a channel's devices and gate on joining it; blocks while the
+/// devices are listed
+/// Emits "audio-devices"
+pub fn join(
+    app: &AppHandle,
+    instance_id: &str,
+    channel_id: &str,
+) -> Result<ChannelAudioState, OverrideError> {
+    let db = app.state::<Database>();
+    let found = overrides(&db)?
+        .into_iter()
+        .find(|o| o.instance_id == instance_id && o.channel_id == channel_id);
+
+    let mut missing = Vec::new();
+    let joined = found.map(|mut o| {
+        if let Some(name) = o.input_device.take() {
+            match capture::input_devices() {
+                Ok(devices) if !devices.contains(&name) => missing.push(name),
+                _ => o.input_device = Some(name),
+            }
+        }
+        if let Some(name) = o.output_device.take() {
+            match playback::output_devices() {
+                Ok(devices) if !devices.contains(&name) => missing.push(name),
+                _ => o.output_device = Some(name),
+            }
+        }
+        o
+    });
+    if !missing.is_empty() {
+        log::warn!(
+            "Channel {} wants audio devices that aren't connected: {}",
+            channel_id,
+            missing.join(", ")
+        );
+    }
+
+    *app.state::<ChannelAudio>().lock() = Some(joined.unwrap_or_else(|| ChannelOverride {
+        instance_id: instance_id.to_string(),
+        channel_id: channel_id.to_string(),
+        input_device: None,
+        output_device: None,
+        noise_gate: None,
+    }));
+    let state = state(app, missing)?;
+    gate::configure(state.noise_gate);
+    let _ = app.emit("audio-devices", &state);
+    automation::fire(
+        app,
+        HookEvent::CallStarted,
+        vec![
+            ("instance_id", instance_id.to_string()),
+            ("channel_id", channel_id.to_string()),
+            ("caller", String::new()),
+        ],
+    );
+    Ok(state)
+}
+
+/// Go back to the global devices and gate on leaving the channel
+/// Emits "audio-devices"
+pub fn leave(app: &AppHandle) -> Result<ChannelAudioState, OverrideError> {
+    *app.state::<ChannelAudio>().lock() = None;
+    let state = state(app, Vec::new())?;
+    gate::configure(state.noise_gate);
+    let _ = app.emit("audio-devices", &state);
+    Ok(state)
+}
+
+/// The devices and gate in use now
+pub fn current(app: &AppHandle) -> Result<ChannelAudioState, OverrideError> {
+    state(app, Vec::new())
+}
+
+/// Microphone for native captures: the joined channel's, or the global one
+pub fn input_device(app: &AppHandle) -> rusqlite::Result<Option<String>> {
+    let joined = app
+        .state::<ChannelAudio>()
+        .lock()
+        .as_ref()
+        .and_then(|o| o.input_device.clone());
+    match joined {
+        Some(name) => Ok(Some(name)),
+        None => global(&app.state::<Database>(), INPUT_DEVICE_SETTING),
+    }
+}
+
+/// Put the joined channel's gate back after the global one was changed
+pub fn restore_gate(app: &AppHandle) {
+    let gate = app
+        .state::<ChannelAudio>()
+        .lock()
+        .as_ref()
+        .and_then(|o| o.noise_gate);
+    if let Some(gate) = gate {
+        gate::configure(gate);
+    }
+}
+
+fn state(app: &AppHandle, missing: Vec<String>) -> Result<ChannelAudioState, OverrideError> {
+    let db = app.state::<Database>();
+    let joined = app.state::<ChannelAudio>().lock().clone();
+    let global_gate = gate::stored(&db);
+    let Some(joined) = joined else {
+        return Ok(ChannelAudioState {
+            input_device: global(&db, INPUT_DEVICE_SETTING)?,
+            noise_gate: global_gate,
+            ..ChannelAudioState::default()
+        });
+    };
+
+    let overridden = joined.input_device.is_some()
+        || joined.output_device.is_some()
+        || joined.noise_gate.is_some();
+    Ok(ChannelAudioState {
+        input_device: match joined.input_device {
+            Some(name) => Some(name),
+            None => global(&db, INPUT_DEVICE_SETTING)?,
+        },
+        output_device: joined.output_device,
+        noise_gate: joined.noise_gate.unwrap_or(global_gate),
+        instance_id: Some(joined.instance_id),
+        channel_id: Some(joined.channel_id),
+        overridden,
+        missing,
+    })
+}
+
+fn global(db: &Database, key: &str) -> rusqlite::Result<Option<String>> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, key))?
+        .and_then(|v| v.as_str().map(str::to_string)))
+}
@@ -0,0 +1,125 @@
+//! Priority speaker
+//!
+//! While the designated priority speaker is talking, everyone else in the
+//! call is turned down by a set number of decibels. Call audio is mixed in
+//! the webview, which reports who's speaking as its detection sees it; the
+//! ducking decision is made here and sent back as a "ducking" event with
+//! the gain to apply to every other participant.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::coalesce;
+
+/// Most ducking allowed, in dB
+pub const MAX_DUCK_DB: f32 = 40.0;
+
+/// Payload of "ducking"
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuckingState {
+    pub priority_user_id: Option<String>,
+    pub duck_db: f32,
+    /// The priority speaker is talking, so others are turned down
+    pub active: bool,
+    /// Linear gain for participants other than the priority speaker
+    pub gain: f32,
+}
+
+#[derive(Default)]
+pub struct Ducking {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    priority: Option<(String, f32)>,
+    speaking: HashSet<String>,
+}
+
+impl Inner {
+    fn state(&self) -> DuckingState {
+        let (user_id, duck_db) = match &self.priority {
+            Some((user_id, duck_db)) => (Some(user_id.clone()), *duck_db),
+            None => (None, 0.0),
+        };
+        let active = user_id
+            .as_ref()
+            .is_some_and(|user_id| self.speaking.contains(user_id));
+        DuckingState {
+            priority_user_id: user_id,
+            duck_db,
+            active,
+            gain: if active {
+                10f32.powf(-duck_db / 20.0)
+            } else {
+                1.0
+            },
+        }
+    }
+}
+
+impl Ducking {
+    pub fn state(&self) -> DuckingState {
+        self.lock().state()
+    }
+
+    /// Participants currently speaking
+    pub fn speaking(&self) -> Vec<String> {
+        self.lock().speaking.iter().cloned().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Make `user_id` the priority speaker, ducking others by `duck_db` while
+/// they talk, or clear it with `None`. Emits "ducking".
+pub fn set_priority_speaker(
+    app: &AppHandle,
+    user_id: Option<String>,
+    duck_db: f32,
+) -> DuckingState {
+    let duck_db = if duck_db.is_finite() {
+        duck_db.abs().min(MAX_DUCK_DB)
+    } else {
+        0.0
+    };
+    update(app, |inner| {
+        inner.priority = user_id.map(|user_id| (user_id, duck_db))
+    })
+}
+
+/// Note whether a participant is speaking. Emits "ducking" when that
+/// starts or stops the ducking.
+pub fn set_speaking(app: &AppHandle, user_id: String, speaking: bool) -> DuckingState {
+    update(app, |inner| {
+        if speaking {
+            inner.speaking.insert(user_id);
+        } else {
+            inner.speaking.remove(&user_id);
+        }
+    })
+}
+
+/// Forget who's speaking, e.g. once the call is over
+pub fn reset(app: &AppHandle) -> DuckingState {
+    update(app, |inner| *inner = Inner::default())
+}
+
+fn update(app: &AppHandle, change: impl FnOnce(&mut Inner)) -> DuckingState {
+    let ducking = app.state::<Ducking>();
+    let (before, after) = {
+        let mut inner = ducking.lock();
+        let before = inner.state();
+        change(&mut inner);
+        (before, inner.state())
+    };
+    if before != after {
+        coalesce::emit(app, "ducking", "", after.clone());
+    }
+    after
+}
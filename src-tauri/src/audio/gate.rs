@@ -0,0 +1,177 @@
+//! Noise gate
+//!
+//! A classic gate in the capture pipeline: the microphone is muted while
+//! its level stays under a threshold, opens over the attack time once it
+//! rises above, and closes over the release time after staying quiet for
+//! the hold time. It only looks at level, so steady low hums that noise
+//! suppression leaves alone are cut while speech still comes through.
+//!
+//! The settings live in atomics so capture callbacks can pick up changes
+//! on the audio thread without taking a lock.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use crate::settings;
+
+/// Setting holding the gate's [`NoiseGateSettings`]
+pub const NOISE_GATE_SETTING: &str = "audio.noise_gate";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// f32 bits; only read while enabled, which `configure` sets last
+static THRESHOLD_DB: AtomicU32 = AtomicU32::new(0);
+static ATTACK_MS: AtomicU32 = AtomicU32::new(0);
+static HOLD_MS: AtomicU32 = AtomicU32::new(0);
+static RELEASE_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Time constant of the level detector's decay
+const DETECTOR_RELEASE_MS: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseGateSettings {
+    pub enabled: bool,
+    /// Level the microphone has to reach to open the gate, in dBFS
+    pub threshold_db: f32,
+    pub attack_ms: f32,
+    /// How long the level has to stay under the threshold before closing
+    pub hold_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for NoiseGateSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -50.0,
+            attack_ms: 2.0,
+            hold_ms: 150.0,
+            release_ms: 100.0,
+        }
+    }
+}
+
+impl NoiseGateSettings {
+    /// The settings with every value in its usable range
+    pub fn clamped(self) -> Self {
+        let or_default = |value: f32, default: f32| if value.is_finite() { value } else { default };
+        let defaults = Self::default();
+        Self {
+            enabled: self.enabled,
+            threshold_db: or_default(self.threshold_db, defaults.threshold_db).clamp(-90.0, 0.0),
+            attack_ms: or_default(self.attack_ms, defaults.attack_ms).clamp(0.1, 200.0),
+            hold_ms: or_default(self.hold_ms, defaults.hold_ms).clamp(0.0, 2000.0),
+            release_ms: or_default(self.release_ms, defaults.release_ms).clamp(1.0, 2000.0),
+        }
+    }
+}
+
+/// The settings captures are using
+pub fn current() -> NoiseGateSettings {
+    let load = |value: &AtomicU32| f32::from_bits(value.load(Ordering::Relaxed));
+    NoiseGateSettings {
+        enabled: ENABLED.load(Ordering::Relaxed),
+        threshold_db: load(&THRESHOLD_DB),
+        attack_ms: load(&ATTACK_MS),
+        hold_ms: load(&HOLD_MS),
+        release_ms: load(&RELEASE_MS),
+    }
+}
+
+/// Use `settings` in running and future captures
+pub fn configure(settings: NoiseGateSettings) {
+    let settings = settings.clamped();
+    let store = |value: &AtomicU32, v: f32| value.store(v.to_bits(), Ordering::Relaxed);
+    store(&THRESHOLD_DB, settings.threshold_db);
+    store(&ATTACK_MS, settings.attack_ms);
+    store(&HOLD_MS, settings.hold_ms);
+    store(&RELEASE_MS, settings.release_ms);
+    ENABLED.store(settings.enabled, Ordering::Relaxed);
+}
+
+/// Load the stored settings; call at startup
+pub fn apply(db: &Database) {
+    configure(stored(db));
+}
+
+/// The stored settings, which captures use outside channels with their own
+pub fn stored(db: &Database) -> NoiseGateSettings {
+    db.with(|conn| settings::get_value(conn, NOISE_GATE_SETTING))
+        .unwrap_or_else(|e| {
+            log::error!("Failed to read noise gate settings: {}", e);
+            None
+        })
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| log::warn!("Ignoring malformed noise gate settings: {}", e))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+/// Store and use new settings, returning them as clamped
+pub fn set(db: &Database, settings: NoiseGateSettings) -> rusqlite::Result<NoiseGateSettings> {
+    let settings = settings.clamped();
+    let value = serde_json::to_value(settings).expect("gate settings serialize");
+    db.with(|conn| settings::set(conn, NOISE_GATE_SETTING, &value))?;
+    configure(settings);
+    Ok(settings)
+}
+
+/// Gate state for one capture
+pub(super) struct NoiseGate {
+    sample_rate: f32,
+    /// Detected level, linear
+    level: f32,
+    gain: f32,
+    /// Samples left before closing once the level has dropped
+    hold_left: u32,
+}
+
+impl NoiseGate {
+    pub(super) fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate: sample_rate as f32,
+            level: 0.0,
+            gain: 1.0,
+            hold_left: 0,
+        }
+    }
+
+    /// Gate a block in place with the current settings
+    pub(super) fn process(&mut self, samples: &mut [f32]) {
+        let settings = current();
+        if !settings.enabled {
+            self.gain = 1.0;
+            return;
+        }
+
+        let per_ms = self.sample_rate / 1000.0;
+        let threshold = 10f32.powf(settings.threshold_db / 20.0);
+        let detector_decay = (-1.0 / (DETECTOR_RELEASE_MS * per_ms)).exp();
+        let attack_step = 1.0 / (settings.attack_ms * per_ms).max(1.0);
+        let release_step = 1.0 / (settings.release_ms * per_ms).max(1.0);
+        let hold = (settings.hold_ms * per_ms) as u32;
+
+        for sample in samples {
+            let magnitude = sample.abs();
+            self.level = if magnitude > self.level {
+                magnitude
+            } else {
+                self.level * detector_decay
+            };
+
+            if self.level >= threshold {
+                self.hold_left = hold;
+                self.gain = (self.gain + attack_step).min(1.0);
+            } else if self.hold_left > 0 {
+                self.hold_left -= 1;
+            } else {
+                self.gain = (self.gain - release_step).max(0.0);
+            }
+            *sample *= self.gain;
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Audio callback load
+//!
+//! Capture callbacks have to finish before the next block arrives or audio
+//! drops out. Each one records how long it took against how much audio it
+//! handled, and [`take`] reports the share of the time spent working since
+//! it was last called. Atomics rather than a lock, as callbacks run on the
+//! audio thread.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+static BUSY_NANOS: AtomicU64 = AtomicU64::new(0);
+static AUDIO_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Note a callback that took `busy` to process `audio` worth of samples
+pub(super) fn record(busy: Duration, audio: Duration) {
+    BUSY_NANOS.fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+    AUDIO_NANOS.fetch_add(audio.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Share of the audio time spent in callbacks since the last call, from 0
+/// to 1 and above when callbacks fall behind; `None` if nothing was
+/// captured in between
+pub fn take() -> Option<f32> {
+    let busy = BUSY_NANOS.swap(0, Ordering::Relaxed);
+    let audio = AUDIO_NANOS.swap(0, Ordering::Relaxed);
+    (audio > 0).then(|| busy as f32 / audio as f32)
+}
@@ -0,0 +1,30 @@
+//! Native audio
+//!
+//! Microphone capture that runs in the Rust process rather than the webview,
+//! so it keeps going while the window is minimized or throttled, and output
+//! for sounds that have to play regardless, such as the ringtone. Call
+//! audio itself is still handled by the webview, which hands it over when a
+//! call is recorded locally.
+
+pub mod call_recording;
+pub mod capture;
+pub mod channel_overrides;
+pub mod ducking;
+pub mod gate;
+pub mod load;
+pub mod opus_file;
+pub mod playback;
+pub mod voice_message;
+pub mod watchdog;
+
+/// Sample rate everything downstream of capture works at
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Setting holding the name of the input device to capture from; the
+/// system default is used if unset or no longer connected
+pub const INPUT_DEVICE_SETTING: &str = "devices.audio_input";
+
+/// Setting holding the name of the output device notification sounds and
+/// the ringtone play on; the system default is used if unset or no longer
+/// connected
+pub const NOTIFICATION_OUTPUT_SETTING: &str = "devices.notification_output";
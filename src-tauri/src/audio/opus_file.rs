@@ -0,0 +1,134 @@
+//! Ogg Opus files
+//!
+//! Mono 48 kHz audio encoded to Opus and written to Ogg pages of about a
+//! second each. Pages are flushed to disk as they're completed, so a file
+//! cut off by a crash still plays up to its last second.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use opus::{Application, Bitrate, Channels, Encoder};
+
+use super::SAMPLE_RATE;
+use crate::performance;
+
+/// Samples per Opus packet (20 ms)
+pub const FRAME_SAMPLES: usize = SAMPLE_RATE as usize / 50;
+/// Packets per Ogg page, so an interrupted recording loses at most a second
+const PACKETS_PER_PAGE: u64 = 50;
+const BITRATE: i32 = 32_000;
+/// Encoder complexity on the low performance tier, out of 10
+const LOW_TIER_COMPLEXITY: i32 = 3;
+/// Largest Opus packet, per RFC 6716
+const MAX_PACKET: usize = 1275;
+const SERIAL: u32 = 0x7265_6462;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OpusFileError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to encode audio: {0}")]
+    Encode(#[from] opus::Error),
+}
+
+/// Ogg Opus stream writer (RFC 7845)
+pub struct OpusWriter {
+    encoder: Encoder,
+    packets: PacketWriter<'static, BufWriter<File>>,
+    /// Samples the decoder drops from the start for the encoder's lookahead
+    pre_skip: u64,
+    /// Samples encoded so far, including padding
+    encoded: u64,
+    /// Previous packet, held back so the last one can end the stream
+    pending: Option<Vec<u8>>,
+    /// Audio packets written, for ending pages
+    count: u64,
+}
+
+impl OpusWriter {
+    pub fn create(path: &Path) -> Result<Self, OpusFileError> {
+        let mut encoder = Encoder::new(SAMPLE_RATE, Channels::Mono, Application::Voip)?;
+        encoder.set_bitrate(Bitrate::Bits(BITRATE))?;
+        if performance::is_low() {
+            encoder.set_complexity(LOW_TIER_COMPLEXITY)?;
+        }
+        let pre_skip = encoder.get_lookahead()?.max(0) as u64;
+
+        let mut packets = PacketWriter::new(BufWriter::new(File::create(path)?));
+
+        let mut head = Vec::with_capacity(19);
+        head.extend_from_slice(b"OpusHead");
+        head.push(1); // version
+        head.push(1); // channels
+        head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+        head.push(0); // mono/stereo mapping
+        packets.write_packet(head, SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        let vendor = concat!("Redoubt ", env!("CARGO_PKG_VERSION"));
+        let mut tags = Vec::new();
+        tags.extend_from_slice(b"OpusTags");
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor.as_bytes());
+        tags.extend_from_slice(&0u32.to_le_bytes()); // no comments
+        packets.write_packet(tags, SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(Self {
+            encoder,
+            packets,
+            pre_skip,
+            encoded: 0,
+            pending: None,
+            count: 0,
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &[f32]) -> Result<(), OpusFileError> {
+        let mut packet = vec![0; MAX_PACKET];
+        let len = self.encoder.encode_float(frame, &mut packet)?;
+        packet.truncate(len);
+
+        if let Some(previous) = self.pending.replace(packet) {
+            self.count += 1;
+            let page_end = self.count % PACKETS_PER_PAGE == 0;
+            let end = if page_end {
+                PacketWriteEndInfo::EndPage
+            } else {
+                PacketWriteEndInfo::NormalPacket
+            };
+            self.packets
+                .write_packet(previous, SERIAL, end, self.encoded)?;
+            if page_end {
+                self.packets.inner_mut().flush()?;
+            }
+        }
+        self.encoded += FRAME_SAMPLES as u64;
+        Ok(())
+    }
+
+    /// Encode the partial `frame` plus enough silence to flush the encoder's
+    /// lookahead, then end the stream at `samples` real samples
+    pub fn finish(mut self, mut tail: Vec<f32>, samples: u64) -> Result<(), OpusFileError> {
+        tail.resize(tail.len() + self.pre_skip as usize, 0.0);
+        tail.resize(tail.len().div_ceil(FRAME_SAMPLES) * FRAME_SAMPLES, 0.0);
+        for frame in tail.chunks(FRAME_SAMPLES) {
+            self.write_frame(frame)?;
+        }
+
+        // The last granule position trims the padding off the end
+        if let Some(last) = self.pending.take() {
+            self.packets.write_packet(
+                last,
+                SERIAL,
+                PacketWriteEndInfo::EndStream,
+                self.pre_skip + samples,
+            )?;
+        }
+        let mut file = self.packets.into_inner();
+        file.flush()?;
+        Ok(())
+    }
+}
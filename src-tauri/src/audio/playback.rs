@@ -0,0 +1,186 @@
+//! Sound output
+//!
+//! Plays generated audio on an output device. Like capture, each playback
+//! owns a thread that keeps its stream alive until it's dropped and
+//! rebuilds it if it stalls.
+
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, StreamConfig};
+
+use super::watchdog::{self, Health, StreamKind};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PlaybackError {
+    #[error("no output device available")]
+    NoDevice,
+    #[error("output sample format {0} isn't supported")]
+    UnsupportedFormat(SampleFormat),
+    #[error("failed to read output config: {0}")]
+    Config(#[from] cpal::DefaultStreamConfigError),
+    #[error("failed to open output device: {0}")]
+    Build(#[from] cpal::BuildStreamError),
+    #[error("failed to start output: {0}")]
+    Play(#[from] cpal::PlayStreamError),
+    #[error("failed to list output devices: {0}")]
+    Devices(#[from] cpal::DevicesError),
+    #[error("failed to start playback thread: {0}")]
+    Thread(#[from] std::io::Error),
+}
+
+/// Fills each block of mono samples on the audio thread, given the
+/// device's sample rate when the playback starts
+pub type Source = Box<dyn FnMut(&mut [f32]) + Send + 'static>;
+
+/// A running playback; stops when dropped
+pub struct Playback {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Playback {
+    /// Start playing on the named output device, or the default one.
+    /// `source` makes the sound once the device's sample rate is known,
+    /// and again if the stream has to be rebuilt.
+    pub fn start<F>(device_name: Option<String>, mut source: F) -> Result<Self, PlaybackError>
+    where
+        F: FnMut(u32) -> Source + Send + 'static,
+    {
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread = std::thread::Builder::new()
+            .name("audio-playback".into())
+            .spawn(move || {
+                let health = Health::new();
+                let mut open = || open(device_name.as_deref(), &mut source, health.clone());
+                let stream = match open() {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = ready_tx.send(Ok(()));
+                // Runs until the playback is dropped
+                watchdog::supervise(
+                    StreamKind::Playback,
+                    device_name.as_deref(),
+                    &health,
+                    stream,
+                    &stop_rx,
+                    open,
+                );
+            })?;
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self {
+                stop: Some(stop_tx),
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                Err(e)
+            }
+            Err(_) => {
+                let _ = thread.join();
+                Err(PlaybackError::NoDevice)
+            }
+        }
+    }
+}
+
+impl Drop for Playback {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Names of the connected output devices
+pub fn output_devices() -> Result<Vec<String>, PlaybackError> {
+    Ok(cpal::default_host()
+        .output_devices()?
+        .filter_map(|device| device.description().ok())
+        .map(|description| description.name().to_string())
+        .collect())
+}
+
+fn find_device(name: Option<&str>) -> Result<Device, PlaybackError> {
+    let host = cpal::default_host();
+    if let Some(name) = name {
+        let found = host.output_devices()?.find(|device| {
+            device
+                .description()
+                .is_ok_and(|description| description.name() == name)
+        });
+        match found {
+            Some(device) => return Ok(device),
+            None => log::warn!("Output device {} not found, using the default", name),
+        }
+    }
+    host.default_output_device().ok_or(PlaybackError::NoDevice)
+}
+
+fn open<F>(
+    device_name: Option<&str>,
+    source: &mut F,
+    health: Arc<Health>,
+) -> Result<cpal::Stream, PlaybackError>
+where
+    F: FnMut(u32) -> Source,
+{
+    let device = find_device(device_name)?;
+    let supported = device.default_output_config()?;
+    let config = supported.config();
+    let source = source(config.sample_rate);
+
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build::<f32>(&device, &config, source, health)?,
+        SampleFormat::I16 => build::<i16>(&device, &config, source, health)?,
+        SampleFormat::U16 => build::<u16>(&device, &config, source, health)?,
+        SampleFormat::I32 => build::<i32>(&device, &config, source, health)?,
+        SampleFormat::I8 => build::<i8>(&device, &config, source, health)?,
+        SampleFormat::U8 => build::<u8>(&device, &config, source, health)?,
+        SampleFormat::F64 => build::<f64>(&device, &config, source, health)?,
+        format => return Err(PlaybackError::UnsupportedFormat(format)),
+    };
+    stream.play()?;
+    Ok(stream)
+}
+
+fn build<T>(
+    device: &Device,
+    config: &StreamConfig,
+    mut source: Source,
+    health: Arc<Health>,
+) -> Result<cpal::Stream, PlaybackError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels.max(1) as usize;
+    let mut mono = Vec::new();
+    let errors = health.clone();
+
+    let stream = device.build_output_stream::<T, _, _>(
+        config,
+        move |data: &mut [T], _| {
+            health.beat();
+            mono.resize(data.len() / channels, 0.0);
+            source(&mut mono);
+            for (frame, sample) in data.chunks_mut(channels).zip(&mono) {
+                frame.iter_mut().for_each(|s| *s = T::from_sample(*sample));
+            }
+        },
+        move |e| {
+            log::error!("Output stream error: {}", e);
+            errors.error(&e);
+        },
+        None,
+    )?;
+    Ok(stream)
+}
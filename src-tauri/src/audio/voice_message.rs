@@ -0,0 +1,236 @@
+//! Voice messages
+//!
+//! Records the microphone to an Opus-in-Ogg file and keeps per-frame peaks
+//! so the chat bubble can draw a waveform without decoding the file.
+//! Encoding happens on its own thread, fed from the capture callback, and
+//! stops by itself once the maximum duration is reached.
+
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::capture::{Capture, CaptureError};
+use super::opus_file::{OpusFileError, OpusWriter, FRAME_SAMPLES};
+use super::SAMPLE_RATE;
+
+/// Longest voice message, unless the caller asks for less
+pub const MAX_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// Number of bars in the waveform sent to the UI
+const WAVEFORM_BARS: usize = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum VoiceMessageError {
+    #[error("{0}")]
+    Capture(#[from] CaptureError),
+    #[error("failed to write voice message: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to write voice message: {0}")]
+    File(#[from] OpusFileError),
+    #[error("encoder stopped unexpectedly")]
+    Encoder,
+}
+
+/// A finished recording
+#[derive(Debug, Clone, Serialize)]
+pub struct VoiceMessage {
+    pub id: String,
+    pub path: PathBuf,
+    pub mime_type: &'static str,
+    pub duration_ms: u64,
+    /// Peak level per bar, between 0 and 1, normalized to the loudest bar
+    pub waveform: Vec<f32>,
+}
+
+/// A recording in progress
+pub struct Recording {
+    pub id: String,
+    pub path: PathBuf,
+    capture: Capture,
+    encoder: JoinHandle<Result<Encoded, VoiceMessageError>>,
+}
+
+struct Encoded {
+    samples: u64,
+    peaks: Vec<f32>,
+}
+
+/// The voice message being recorded, if any; only one at a time
+#[derive(Default)]
+pub struct VoiceMessages {
+    recording: Mutex<Option<Recording>>,
+}
+
+impl VoiceMessages {
+    /// Start a recording unless one is already running.
+    /// Returns its id, or `None` if another recording is in progress.
+    pub fn start(
+        &self,
+        dir: &Path,
+        device_name: Option<String>,
+        max_duration: Duration,
+    ) -> Result<Option<String>, VoiceMessageError> {
+        let mut recording = self.lock();
+        if recording.is_some() {
+            return Ok(None);
+        }
+        let started = Recording::start(dir, device_name, max_duration)?;
+        let id = started.id.clone();
+        *recording = Some(started);
+        Ok(Some(id))
+    }
+
+    /// Take the running recording, or only the one with `id` if given
+    pub fn take(&self, id: Option<&str>) -> Option<Recording> {
+        let mut recording = self.lock();
+        match (recording.as_ref(), id) {
+            (Some(current), Some(id)) if current.id != id => None,
+            _ => recording.take(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Recording>> {
+        self.recording.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Recording {
+    /// Start recording into `dir`, stopping the encoder after `max_duration`
+    pub fn start(
+        dir: &Path,
+        device_name: Option<String>,
+        max_duration: Duration,
+    ) -> Result<Self, VoiceMessageError> {
+        std::fs::create_dir_all(dir)?;
+        let id = uuid::Uuid::new_v4().to_string();
+        let path = dir.join(format!("{}.ogg", id));
+
+        let writer = OpusWriter::create(&path)?;
+        let max_samples = (max_duration.as_millis() as u64) * SAMPLE_RATE as u64 / 1000;
+        let (tx, rx) = mpsc::channel::<Vec<f32>>();
+        let encoder = std::thread::Builder::new()
+            .name("voice-message".into())
+            .spawn(move || encode(writer, rx, max_samples))?;
+
+        let capture = Capture::start(
+            device_name,
+            Box::new(move |samples| {
+                let _ = tx.send(samples.to_vec());
+            }),
+        );
+        let capture = match capture {
+            Ok(capture) => capture,
+            Err(e) => {
+                // Dropping the sender lets the encoder finish on its own
+                let _ = encoder.join();
+                let _ = std::fs::remove_file(&path);
+                return Err(e.into());
+            }
+        };
+
+        Ok(Self {
+            id,
+            path,
+            capture,
+            encoder,
+        })
+    }
+
+    /// Stop capturing and finish the file. Blocks until the encoder is done.
+    pub fn finish(self) -> Result<VoiceMessage, VoiceMessageError> {
+        drop(self.capture);
+        let encoded = match self.encoder.join() {
+            Ok(result) => result,
+            Err(_) => Err(VoiceMessageError::Encoder),
+        };
+        let encoded = match encoded {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                let _ = std::fs::remove_file(&self.path);
+                return Err(e);
+            }
+        };
+
+        Ok(VoiceMessage {
+            id: self.id,
+            path: self.path,
+            mime_type: "audio/ogg",
+            duration_ms: encoded.samples * 1000 / SAMPLE_RATE as u64,
+            waveform: waveform(&encoded.peaks, WAVEFORM_BARS),
+        })
+    }
+
+    /// Stop capturing and delete the file
+    pub fn discard(self) {
+        let path = self.path.clone();
+        drop(self.capture);
+        let _ = self.encoder.join();
+        if let Err(e) = std::fs::remove_file(&path) {
+            log::warn!("Failed to delete voice message {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Encode samples from `rx` until capture stops or `max_samples` is reached
+fn encode(
+    mut writer: OpusWriter,
+    rx: mpsc::Receiver<Vec<f32>>,
+    max_samples: u64,
+) -> Result<Encoded, VoiceMessageError> {
+    let mut frame = Vec::with_capacity(FRAME_SAMPLES);
+    let mut peaks = Vec::new();
+    let mut samples = 0u64;
+
+    'capture: for block in rx {
+        for sample in block {
+            if samples >= max_samples {
+                break 'capture;
+            }
+            frame.push(sample);
+            samples += 1;
+            if frame.len() == FRAME_SAMPLES {
+                peaks.push(peak(&frame));
+                writer.write_frame(&frame)?;
+                frame.clear();
+            }
+        }
+    }
+
+    if !frame.is_empty() {
+        peaks.push(peak(&frame));
+    }
+    writer.finish(frame, samples)?;
+
+    Ok(Encoded { samples, peaks })
+}
+
+fn peak(frame: &[f32]) -> f32 {
+    frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+}
+
+/// Reduce per-frame peaks to `bars` values normalized to the loudest one
+fn waveform(peaks: &[f32], bars: usize) -> Vec<f32> {
+    if peaks.is_empty() {
+        return Vec::new();
+    }
+    let bars = bars.min(peaks.len());
+    let values: Vec<f32> = (0..bars)
+        .map(|i| {
+            let start = i * peaks.len() / bars;
+            let end = ((i + 1) * peaks.len() / bars).max(start + 1);
+            peak(&peaks[start..end])
+        })
+        .collect();
+
+    let loudest = peak(&values);
+    if loudest <= f32::EPSILON {
+        return vec![0.0; values.len()];
+    }
+    values
+        .into_iter()
+        .map(|v| ((v / loudest) * 100.0).round() / 100.0)
+        .collect()
+}
@@ -0,0 +1,160 @@
+//! Stream watchdog
+//!
+//! cpal streams can stop without much warning: a USB headset drops off the
+//! bus for a moment, a driver stalls, the device goes away. The thread
+//! that owns each capture or playback stream watches its callbacks, and
+//! when none arrive for [`STALL_TIMEOUT`] or the stream reports that its
+//! device is gone, rebuilds it on the preferred device (or the default, as
+//! when it started). Emits "audio-recovered" once a rebuilt stream runs
+//! and "audio-failed" after [`MAX_ATTEMPTS`] failed rebuilds, after which
+//! the stream stays silent until it's started again.
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+/// How long a stream can go without callbacks before it's rebuilt
+pub const STALL_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Rebuilds tried in a row before giving up
+pub const MAX_ATTEMPTS: u32 = 5;
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+static APP: OnceLock<AppHandle> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamKind {
+    Capture,
+    Playback,
+}
+
+/// Payload of "audio-recovered" and "audio-failed"
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamEvent {
+    pub stream: StreamKind,
+    /// The preferred device, or `None` for the default
+    pub device: Option<String>,
+    /// Why the last rebuild failed, for "audio-failed"
+    pub error: Option<String>,
+}
+
+/// Let the watchdog emit events; call once during setup
+pub fn install(app: &AppHandle) {
+    let _ = APP.set(app.clone());
+}
+
+/// Callback activity of one stream, shared with its callbacks
+pub(super) struct Health {
+    epoch: Instant,
+    /// Milliseconds from `epoch` to the last callback
+    last_ms: AtomicU64,
+    lost: AtomicBool,
+}
+
+impl Health {
+    pub(super) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            epoch: Instant::now(),
+            last_ms: AtomicU64::new(0),
+            lost: AtomicBool::new(false),
+        })
+    }
+
+    /// Note a callback; cheap enough for the audio thread
+    pub(super) fn beat(&self) {
+        self.last_ms
+            .store(self.epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Handle an error reported by the stream
+    pub(super) fn error(&self, e: &cpal::StreamError) {
+        if matches!(
+            e,
+            cpal::StreamError::DeviceNotAvailable | cpal::StreamError::StreamInvalidated
+        ) {
+            self.lost.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn stalled(&self) -> bool {
+        let last = Duration::from_millis(self.last_ms.load(Ordering::Relaxed));
+        self.lost.load(Ordering::Relaxed)
+            || self.epoch.elapsed().saturating_sub(last) > STALL_TIMEOUT
+    }
+
+    fn reset(&self) {
+        self.lost.store(false, Ordering::Relaxed);
+        self.beat();
+    }
+}
+
+/// Keep `stream` going until `stop` is signalled or dropped, rebuilding it
+/// with `open` whenever it stalls
+pub(super) fn supervise<S, E: Display>(
+    kind: StreamKind,
+    device_name: Option<&str>,
+    health: &Health,
+    stream: S,
+    stop: &Receiver<()>,
+    mut open: impl FnMut() -> Result<S, E>,
+) {
+    health.reset();
+    let mut stream = Some(stream);
+    loop {
+        if stop.recv_timeout(CHECK_INTERVAL) != Err(RecvTimeoutError::Timeout) {
+            return;
+        }
+        if !health.stalled() {
+            continue;
+        }
+
+        log::warn!("{:?} stream stalled, rebuilding it", kind);
+        drop(stream.take());
+        let mut attempt = 1;
+        loop {
+            match open() {
+                Ok(rebuilt) => {
+                    health.reset();
+                    stream = Some(rebuilt);
+                    log::info!("{:?} stream recovered", kind);
+                    emit(kind, device_name, "audio-recovered", None);
+                    break;
+                }
+                Err(e) if attempt >= MAX_ATTEMPTS => {
+                    log::error!("Giving up on the {:?} stream: {}", kind, e);
+                    emit(kind, device_name, "audio-failed", Some(e.to_string()));
+                    return;
+                }
+                Err(e) => {
+                    log::warn!("Failed to rebuild the {:?} stream: {}", kind, e);
+                    attempt += 1;
+                    if stop.recv_timeout(RETRY_INTERVAL) != Err(RecvTimeoutError::Timeout) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn emit(kind: StreamKind, device_name: Option<&str>, event: &str, error: Option<String>) {
+    let Some(app) = APP.get() else {
+        return;
+    };
+    let _ = app.emit(
+        event,
+        StreamEvent {
+            stream: kind,
+            device: device_name.map(str::to_string),
+            error,
+        },
+    );
+}
@@ -0,0 +1,125 @@
+//! Access tokens for backend requests
+//!
+//! Reads an instance's credentials from the keychain and refreshes them
+//! when they're about to expire, so backend services can call the server
+//! without going through the webview. Refreshes are serialized per profile
+//! and instance: servers rotate refresh tokens, so two refreshes with the
+//! same token would sign the loser out.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::api::{ApiClient, ApiError};
+use crate::instances::Instance;
+use crate::secrets::{self, Credentials};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("not signed in")]
+    NotSignedIn,
+    #[error("session expired")]
+    Expired,
+    #[error("keychain: {0}")]
+    Keychain(#[from] keyring::Error),
+    #[error("{0}")]
+    Api(ApiError),
+    #[error("{0}")]
+    Task(#[from] tauri::Error),
+}
+
+impl AuthError {
+    /// True when retrying won't help until the user signs in again
+    pub fn needs_sign_in(&self) -> bool {
+        matches!(self, AuthError::NotSignedIn | AuthError::Expired)
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct CredentialsRefreshed<'a> {
+    profile_id: &'a str,
+    instance_id: &'a str,
+}
+
+/// One lock per profile and instance, held while refreshing
+type RefreshLocks = Mutex<HashMap<(String, String), Arc<tokio::sync::Mutex<()>>>>;
+
+fn refresh_lock(profile_id: &str, instance_id: &str) -> Arc<tokio::sync::Mutex<()>> {
+    static LOCKS: OnceLock<RefreshLocks> = OnceLock::new();
+    LOCKS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry((profile_id.to_string(), instance_id.to_string()))
+        .or_default()
+        .clone()
+}
+
+/// Return a usable access token for an instance, refreshing it first if needed
+/// Emits "credentials-refreshed" when the stored tokens change
+pub async fn access_token(
+    app: &AppHandle,
+    profile_id: &str,
+    instance: &Instance,
+) -> Result<String, AuthError> {
+    if let Some(token) = fresh_token(&load(profile_id, &instance.id).await?) {
+        return Ok(token);
+    }
+
+    let lock = refresh_lock(profile_id, &instance.id);
+    let _refreshing = lock.lock().await;
+    // Another caller may have refreshed while this one waited, spending the
+    // refresh token read above
+    let credentials = load(profile_id, &instance.id).await?;
+    if let Some(token) = fresh_token(&credentials) {
+        return Ok(token);
+    }
+
+    let refreshed = ApiClient::new(&instance.url)
+        .refresh(&credentials.refresh_token)
+        .await
+        .map_err(|e| {
+            if e.is_unauthorized() {
+                AuthError::Expired
+            } else {
+                AuthError::Api(e)
+            }
+        })?;
+
+    let credentials = Credentials {
+        access_token: Some(refreshed.access_token.clone()),
+        refresh_token: refreshed.refresh_token,
+        expires_at: refreshed.expires_at,
+    };
+    let (profile, instance_id) = (profile_id.to_string(), instance.id.clone());
+    tauri::async_runtime::spawn_blocking(move || {
+        secrets::store(&profile, &instance_id, &credentials)
+    })
+    .await??;
+
+    let _ = app.emit(
+        "credentials-refreshed",
+        CredentialsRefreshed {
+            profile_id,
+            instance_id: &instance.id,
+        },
+    );
+
+    Ok(refreshed.access_token)
+}
+
+async fn load(profile_id: &str, instance_id: &str) -> Result<Credentials, AuthError> {
+    let (profile, instance_id) = (profile_id.to_string(), instance_id.to_string());
+    tauri::async_runtime::spawn_blocking(move || secrets::load(&profile, &instance_id))
+        .await??
+        .ok_or(AuthError::NotSignedIn)
+}
+
+fn fresh_token(credentials: &Credentials) -> Option<String> {
+    if credentials.needs_refresh() {
+        return None;
+    }
+    credentials.access_token.clone()
+}
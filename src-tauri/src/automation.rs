@@ -0,0 +1,490 @@
+//! Automation hooks
+//!
+//! Hooks do something outside the app when something happens in it, for
+//! home automation or switching OBS scenes: a call starts (answered, or a
+//! voice channel joined), a message notification mentions the user, or a
+//! call recording starts. A hook either runs a program or writes a line to
+//! a named pipe that something is already reading: a FIFO on Linux and
+//! macOS, `\\.\pipe\...` on Windows. Nothing is written when nobody is
+//! reading, rather than waiting for a reader.
+//!
+//! Hooks are kept in [`HOOKS_SETTING`]. A hook can only run a program
+//! whose path is also in [`ALLOWED_PROGRAMS_SETTING`], which is checked
+//! when hooks are saved and again before each run, so editing the hooks
+//! alone can't start anything new. Programs are only allowed by the user
+//! picking them in a native file dialog, never by a path the webview
+//! passes, and both settings stay on this machine and out of
+//! `set_setting`. Programs get their arguments directly,
+//! without a shell; each argument, and a pipe's line, is a template where
+//! `{name}` is replaced by one of the event's [`HookEvent::variables`]
+//! and `{{` and `}}` stand for braces. A program that's still running
+//! after [`RUN_TIMEOUT`] is killed.
+//!
+//! Mentions follow do not disturb and the conversation's notification
+//! rules as the notification does, and in streamer mode hooks only hear
+//! that a message arrived, not who sent it or what it said.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::db::Database;
+use crate::notifications::Notification;
+use crate::settings;
+use crate::streamer_mode::StreamerMode;
+
+/// Setting holding the [`Hook`]s
+pub const HOOKS_SETTING: &str = "local.automation.hooks";
+/// Setting holding the programs hooks may run, as absolute paths
+pub const ALLOWED_PROGRAMS_SETTING: &str = "local.automation.allowed_programs";
+
+pub const MAX_HOOKS: usize = 32;
+pub const MAX_ALLOWED_PROGRAMS: usize = 32;
+pub const MAX_ARGS: usize = 32;
+/// Longest an argument or line template can be
+pub const MAX_TEMPLATE_LEN: usize = 1024;
+
+/// How long a program may run before it's killed
+pub const RUN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Most programs running at once; hooks that fire beyond that are skipped
+/// so a burst of mentions can't pile processes up
+const MAX_RUNNING: usize = 4;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AutomationError {
+    #[error("there can be at most {MAX_HOOKS} hooks and {MAX_ALLOWED_PROGRAMS} allowed programs")]
+    TooMany,
+    #[error("a hook can have at most {MAX_ARGS} arguments")]
+    TooManyArgs,
+    #[error("invalid template \"{template}\": {reason}")]
+    Template { template: String, reason: String },
+    #[error("{0} isn't an absolute path")]
+    Relative(String),
+    #[error("{0} isn't a program that exists")]
+    NotAProgram(String),
+    #[error("{0} isn't an allowed program")]
+    NotAllowed(String),
+    #[error("{0} isn't a named pipe")]
+    NotAPipe(String),
+    #[error("too many hooks are running already")]
+    Busy,
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    CallStarted,
+    Mention,
+    RecordingStarted,
+}
+
+impl HookEvent {
+    /// Names templates can use for this event, besides `event`
+    pub fn variables(self) -> &'static [&'static str] {
+        match self {
+            HookEvent::CallStarted => &["instance_id", "channel_id", "caller"],
+            HookEvent::Mention => &["instance_id", "channel_id", "title", "body"],
+            HookEvent::RecordingStarted => &["instance_id", "channel_id", "recording_id"],
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            HookEvent::CallStarted => "call-started",
+            HookEvent::Mention => "mention",
+            HookEvent::RecordingStarted => "recording-started",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum HookAction {
+    Run {
+        program: PathBuf,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    Pipe {
+        path: PathBuf,
+        line: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hook {
+    pub event: HookEvent,
+    #[serde(flatten)]
+    pub action: HookAction,
+}
+
+impl Hook {
+    fn templates(&self) -> Vec<&str> {
+        match &self.action {
+            HookAction::Run { args, .. } => args.iter().map(String::as_str).collect(),
+            HookAction::Pipe { line, .. } => vec![line],
+        }
+    }
+
+    fn validate(&self, allowed: &[PathBuf]) -> Result<(), AutomationError> {
+        match &self.action {
+            HookAction::Run { program, args } => {
+                if args.len() > MAX_ARGS {
+                    return Err(AutomationError::TooManyArgs);
+                }
+                check_allowed(program, allowed)?;
+            }
+            HookAction::Pipe { path, .. } => check_pipe_path(path)?,
+        }
+        let example = self
+            .event
+            .variables()
+            .iter()
+            .map(|name| (*name, String::new()))
+            .collect::<Vec<_>>();
+        for template in self.templates() {
+            if template.chars().count() > MAX_TEMPLATE_LEN {
+                return Err(AutomationError::Template {
+                    template: template.to_string(),
+                    reason: format!("longer than {} characters", MAX_TEMPLATE_LEN),
+                });
+            }
+            render(template, self.event, &example)?;
+        }
+        Ok(())
+    }
+}
+
+/// Programs being run by hooks
+#[derive(Default)]
+pub struct Automation {
+    running: AtomicUsize,
+}
+
+pub fn hooks(db: &Database) -> Result<Vec<Hook>, AutomationError> {
+    Ok(list(db, HOOKS_SETTING)?)
+}
+
+/// Replace the hooks, refusing the lot if any of them is invalid
+pub fn set_hooks(db: &Database, hooks: Vec<Hook>) -> Result<(), AutomationError> {
+    if hooks.len() > MAX_HOOKS {
+        return Err(AutomationError::TooMany);
+    }
+    let allowed = allowed_programs(db)?;
+    for hook in &hooks {
+        hook.validate(&allowed)?;
+    }
+    let value = serde_json::to_value(&hooks).expect("hooks serialize");
+    db.with(|conn| settings::set(conn, HOOKS_SETTING, &value))?;
+    Ok(())
+}
+
+pub fn allowed_programs(db: &Database) -> Result<Vec<PathBuf>, AutomationError> {
+    Ok(list(db, ALLOWED_PROGRAMS_SETTING)?)
+}
+
+/// Let the user pick a program for hooks to run in a native file dialog and
+/// allow it, stored with links resolved; returns it, or `None` if the
+/// dialog is cancelled
+/// Blocks until the dialog is answered, so don't call it on the main thread
+pub fn allow_program(app: &AppHandle) -> Result<Option<PathBuf>, AutomationError> {
+    let Some(picked) = app
+        .dialog()
+        .file()
+        .set_title("Allow a program for automation hooks")
+        .blocking_pick_file()
+        .and_then(|path| path.into_path().ok())
+    else {
+        return Ok(None);
+    };
+    let program = resolve(&picked)?;
+
+    let db = app.state::<Database>();
+    let mut allowed = allowed_programs(&db)?;
+    if !allowed.contains(&program) {
+        if allowed.len() >= MAX_ALLOWED_PROGRAMS {
+            return Err(AutomationError::TooMany);
+        }
+        allowed.push(program.clone());
+        save_allowed_programs(&db, &allowed)?;
+    }
+    Ok(Some(program))
+}
+
+/// Stop allowing a program; hooks that run it fail until it's allowed again
+pub fn disallow_program(db: &Database, program: &Path) -> Result<(), AutomationError> {
+    let mut allowed = allowed_programs(db)?;
+    allowed.retain(|allowed| allowed != program);
+    save_allowed_programs(db, &allowed)
+}
+
+fn save_allowed_programs(db: &Database, programs: &[PathBuf]) -> Result<(), AutomationError> {
+    let value = serde_json::to_value(programs).expect("paths serialize");
+    db.with(|conn| settings::set(conn, ALLOWED_PROGRAMS_SETTING, &value))?;
+    Ok(())
+}
+
+/// Run one hook straight away, with every variable set to its own name, so
+/// it can be tried out from the settings
+pub async fn test(app: &AppHandle, hook: Hook) -> Result<(), AutomationError> {
+    hook.validate(&allowed_programs(&app.state::<Database>())?)?;
+    let variables = hook
+        .event
+        .variables()
+        .iter()
+        .map(|name| (*name, name.to_string()))
+        .collect::<Vec<_>>();
+    run(app, &hook, &variables).await
+}
+
+/// Run the hooks for an event in the background
+pub fn fire(app: &AppHandle, event: HookEvent, variables: Vec<(&'static str, String)>) {
+    let hooks = match hooks(&app.state::<Database>()) {
+        Ok(hooks) => hooks,
+        Err(e) => {
+            log::error!("Failed to read automation hooks: {}", e);
+            return;
+        }
+    };
+    for hook in hooks.into_iter().filter(|hook| hook.event == event) {
+        let app = app.clone();
+        let variables = variables.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = run(&app, &hook, &variables).await {
+                log::warn!("Automation hook for {} failed: {}", event.as_str(), e);
+            }
+        });
+    }
+}
+
+/// Fire mention hooks for a notification that's being posted
+pub fn mentioned(app: &AppHandle, notification: &Notification) {
+    let notification = if app.state::<StreamerMode>().is_enabled() {
+        notification.clone().masked()
+    } else {
+        notification.clone()
+    };
+    fire(
+        app,
+        HookEvent::Mention,
+        vec![
+            ("instance_id", notification.instance_id),
+            ("channel_id", notification.channel_id),
+            ("title", notification.title),
+            ("body", notification.body),
+        ],
+    );
+}
+
+async fn run(
+    app: &AppHandle,
+    hook: &Hook,
+    variables: &[(&str, String)],
+) -> Result<(), AutomationError> {
+    match &hook.action {
+        HookAction::Run { program, args } => {
+            // The allowlist may have changed since the hook was saved
+            let allowed = allowed_programs(&app.state::<Database>())?;
+            let program = check_allowed(program, &allowed)?;
+            let args = args
+                .iter()
+                .map(|arg| render(arg, hook.event, variables))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let automation = app.state::<Automation>();
+            if automation.running.fetch_add(1, Ordering::SeqCst) >= MAX_RUNNING {
+                automation.running.fetch_sub(1, Ordering::SeqCst);
+                return Err(AutomationError::Busy);
+            }
+            let result = execute(&program, &args).await;
+            automation.running.fetch_sub(1, Ordering::SeqCst);
+            result
+        }
+        HookAction::Pipe { path, line } => {
+            check_pipe_path(path)?;
+            let mut line = render(line, hook.event, variables)?;
+            line.push('\n');
+            write_pipe(path, line.as_bytes()).await
+        }
+    }
+}
+
+async fn execute(program: &Path, args: &[String]) -> Result<(), AutomationError> {
+    let mut command = tokio::process::Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    // Don't flash a console window for every run
+    #[cfg(windows)]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let mut child = command.spawn()?;
+    match tokio::time::timeout(RUN_TIMEOUT, child.wait()).await {
+        Ok(status) => {
+            let status = status?;
+            if !status.success() {
+                log::info!(
+                    "Automation hook {} exited with {}",
+                    program.display(),
+                    status
+                );
+            }
+        }
+        Err(_) => {
+            log::warn!(
+                "Automation hook {} ran for over {:?}, killing it",
+                program.display(),
+                RUN_TIMEOUT
+            );
+            let _ = child.kill().await;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn write_pipe(path: &Path, bytes: &[u8]) -> Result<(), AutomationError> {
+    use tokio::io::AsyncWriteExt;
+
+    // Opens without waiting, and fails if nothing is reading or the path
+    // isn't a FIFO
+    let mut pipe = tokio::net::unix::pipe::OpenOptions::new()
+        .open_sender(path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::InvalidInput => {
+                AutomationError::NotAPipe(path.display().to_string())
+            }
+            _ => AutomationError::Io(e),
+        })?;
+    tokio::time::timeout(RUN_TIMEOUT, pipe.write_all(bytes))
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+    Ok(())
+}
+
+#[cfg(windows)]
+async fn write_pipe(path: &Path, bytes: &[u8]) -> Result<(), AutomationError> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut pipe = tokio::net::windows::named_pipe::ClientOptions::new()
+        .read(false)
+        .open(path)?;
+    tokio::time::timeout(RUN_TIMEOUT, pipe.write_all(bytes))
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+    Ok(())
+}
+
+/// Fill in a template, refusing names the event doesn't have
+fn render(
+    template: &str,
+    event: HookEvent,
+    variables: &[(&str, String)],
+) -> Result<String, AutomationError> {
+    let invalid = |reason: String| AutomationError::Template {
+        template: template.to_string(),
+        reason,
+    };
+
+    let mut rendered = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                rendered.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                rendered.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| invalid("a \"{\" isn't closed".to_string()))?;
+                let name = &rest[..end];
+                if name == "event" {
+                    rendered.push_str(event.as_str());
+                } else {
+                    let value = variables
+                        .iter()
+                        .find(|(variable, _)| *variable == name)
+                        .map(|(_, value)| value)
+                        .ok_or_else(|| {
+                            invalid(format!(
+                                "{{{}}} isn't one of event, {}",
+                                name,
+                                event.variables().join(", ")
+                            ))
+                        })?;
+                    rendered.push_str(value);
+                }
+                chars = rest[end + 1..].chars();
+            }
+            '}' => return Err(invalid("a \"}\" isn't opened".to_string())),
+            c => rendered.push(c),
+        }
+    }
+    Ok(rendered)
+}
+
+/// The program as allowed, with links resolved
+fn check_allowed(program: &Path, allowed: &[PathBuf]) -> Result<PathBuf, AutomationError> {
+    let program = resolve(program)?;
+    if !allowed.contains(&program) {
+        return Err(AutomationError::NotAllowed(program.display().to_string()));
+    }
+    Ok(program)
+}
+
+fn resolve(program: &Path) -> Result<PathBuf, AutomationError> {
+    if !program.is_absolute() {
+        return Err(AutomationError::Relative(program.display().to_string()));
+    }
+    std::fs::canonicalize(program)
+        .ok()
+        .filter(|path| path.is_file())
+        .ok_or_else(|| AutomationError::NotAProgram(program.display().to_string()))
+}
+
+/// Whether a path names a pipe, as far as can be told without opening it;
+/// a FIFO that isn't there yet may be created by its reader later
+fn check_pipe_path(path: &Path) -> Result<(), AutomationError> {
+    #[cfg(windows)]
+    let is_pipe = path.to_string_lossy().starts_with(r"\\.\pipe\");
+    #[cfg(unix)]
+    let is_pipe = path.is_absolute() && {
+        use std::os::unix::fs::FileTypeExt;
+
+        std::fs::metadata(path).map_or(true, |metadata| metadata.file_type().is_fifo())
+    };
+    if !is_pipe {
+        return Err(AutomationError::NotAPipe(path.display().to_string()));
+    }
+    Ok(())
+}
+
+fn list<T: serde::de::DeserializeOwned>(db: &Database, key: &str) -> rusqlite::Result<Vec<T>> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, key))?
+        .and_then(|value: Value| serde_json::from_value(value).ok())
+        .unwrap_or_default())
+}
@@ -0,0 +1,243 @@
+//! Background delivery
+//!
+//! With the window closed to the tray (see [`crate::launcher`]) the webview
+//! may be suspended, so the backend takes over the active profile's gateway
+//! connections until the window comes back. Profiles that stay connected
+//! are already on the backend. The server keeps one session per user, so
+//! the webview's own connection is replaced when the backend connects; the
+//! frontend gets "background-mode" on both transitions and reconnects once
+//! it's over.
+//!
+//! While in the background, messages that mention the user or have one of
+//! their highlights in them (see [`crate::notification_rules`]) produce
+//! native notifications from here. The user is looked up from the instance the
+//! first time it's needed. On platforms without native notifications (see
+//! [`crate::notifications`]) they're emitted as "background-notification"
+//! for the webview to show when it can.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::api::{ApiClient, ApiError, User};
+use crate::auth::{self, AuthError};
+use crate::gateway::{Gateway, WsEvent};
+use crate::instances;
+use crate::notification_rules;
+use crate::notifications::{self, Notification, Shown};
+use crate::profiles::{ProfileError, Profiles};
+
+/// Longest message text shown in a notification
+const MAX_BODY_CHARS: usize = 200;
+
+/// Payload of "background-mode"
+#[derive(Debug, Clone, Serialize)]
+pub struct BackgroundMode {
+    pub active: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum LookupError {
+    #[error("{0}")]
+    Profile(#[from] ProfileError),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("instance {0} isn't registered")]
+    NotRegistered(String),
+    #[error("{0}")]
+    Auth(#[from] AuthError),
+    #[error("failed to look up the signed-in user: {0}")]
+    Api(#[from] ApiError),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CreatedMessage {
+    channel_id: String,
+    author: User,
+    content: String,
+}
+
+#[derive(Default)]
+pub struct Background {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    active: bool,
+    /// Profile whose connections were opened for the background
+    connected: Option<String>,
+    /// Signed-in user by profile and instance
+    users: HashMap<(String, String), User>,
+}
+
+impl Background {
+    pub fn is_active(&self) -> bool {
+        self.lock().active
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Take over delivery once the window has been closed to the tray.
+/// Emits "background-mode".
+pub fn enter(app: &AppHandle) {
+    let background = app.state::<Background>();
+    let profiles = app.state::<Profiles>();
+    let profile = profiles.active();
+    {
+        let mut inner = background.lock();
+        if inner.active {
+            return;
+        }
+        inner.active = true;
+        if !profile.stay_connected {
+            inner.connected = Some(profile.id.clone());
+        }
+    }
+
+    if !profile.stay_connected {
+        if let Err(e) = app
+            .state::<Gateway>()
+            .connect_profile(app, &profiles, &profile.id)
+        {
+            log::error!("Failed to connect in the background: {}", e);
+        }
+    }
+    let _ = app.emit("background-mode", BackgroundMode { active: true });
+}
+
+/// Hand delivery back to the webview once the window is in use again.
+/// Emits "background-mode".
+pub fn leave(app: &AppHandle) {
+    let connected = {
+        let background = app.state::<Background>();
+        let mut inner = background.lock();
+        if !inner.active {
+            return;
+        }
+        inner.active = false;
+        inner.connected.take()
+    };
+
+    if let Some(profile_id) = connected {
+        // Unless it was set to stay connected in the meantime
+        let stays = app
+            .state::<Profiles>()
+            .list()
+            .iter()
+            .any(|p| p.id == profile_id && p.stay_connected);
+        if !stays {
+            app.state::<Gateway>().disconnect_profile(&profile_id);
+        }
+    }
+    let _ = app.emit("background-mode", BackgroundMode { active: false });
+}
+
+/// Look at an event from a backend connection; only acts in the background
+pub(crate) fn on_event(app: &AppHandle, profile_id: &str, instance_id: &str, event: &WsEvent) {
+    if event.kind != "message.create" || !app.state::<Background>().is_active() {
+        return;
+    }
+    let Some(message) = event
+        .payload
+        .clone()
+        .and_then(|payload| serde_json::from_value::<CreatedMessage>(payload).ok())
+    else {
+        return;
+    };
+
+    let app = app.clone();
+    let key = (profile_id.to_string(), instance_id.to_string());
+    tauri::async_runtime::spawn(async move {
+        let user = match user(&app, &key).await {
+            Ok(user) => user,
+            Err(e) => {
+                log::warn!("Not notifying in the background: {}", e);
+                return;
+            }
+        };
+        if message.author.id == user.id {
+            return;
+        }
+        let mentioned = mentions(&message.content, &user.username);
+        let title = if mentioned {
+            format!("{} mentioned you", message.author.username)
+        } else if notification_rules::highlighted(&app, &message.content) {
+            format!("{} said a highlighted word", message.author.username)
+        } else {
+            return;
+        };
+
+        let notification = Notification {
+            title,
+            body: message.content.chars().take(MAX_BODY_CHARS).collect(),
+            instance_id: key.1,
+            channel_id: message.channel_id,
+            buttons: Vec::new(),
+            mentioned,
+            call_id: None,
+        };
+        let payload = BackgroundNotification {
+            title: notification.title.clone(),
+            body: notification.body.clone(),
+            instance_id: notification.instance_id.clone(),
+            channel_id: notification.channel_id.clone(),
+        };
+        match notifications::show(&app, notification).await {
+            Ok(Shown::Native { .. } | Shown::Suppressed | Shown::Filtered) => {}
+            Ok(Shown::Webview) => {
+                let _ = app.emit("background-notification", payload);
+            }
+            Err(e) => log::warn!("Failed to show a background notification: {}", e),
+        }
+    });
+}
+
+/// Payload of "background-notification"
+#[derive(Debug, Clone, Serialize)]
+struct BackgroundNotification {
+    title: String,
+    body: String,
+    instance_id: String,
+    channel_id: String,
+}
+
+/// The signed-in user for a profile and instance, looked up once
+pub(crate) async fn user(app: &AppHandle, key: &(String, String)) -> Result<User, LookupError> {
+    if let Some(user) = app.state::<Background>().lock().users.get(key) {
+        return Ok(user.clone());
+    }
+
+    let (profile_id, instance_id) = key;
+    let instance = app
+        .state::<Profiles>()
+        .open_database(profile_id)?
+        .with(|conn| instances::get(conn, instance_id))?
+        .ok_or_else(|| LookupError::NotRegistered(instance_id.clone()))?;
+    let token = auth::access_token(app, profile_id, &instance).await?;
+    let user = ApiClient::new(&instance.url).me(&token).await?;
+
+    app.state::<Background>()
+        .lock()
+        .users
+        .insert(key.clone(), user.clone());
+    Ok(user)
+}
+
+/// Whether `content` has `@username` in it, as a whole word, so neither
+/// `bob@alice.example` nor `@alicex` mention `alice`
+pub(crate) fn mentions(content: &str, username: &str) -> bool {
+    let content = content.to_lowercase();
+    let mention = format!("@{}", username.to_lowercase());
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    content.match_indices(&mention).any(|(at, _)| {
+        let before = content[..at].chars().next_back();
+        let after = content[at + mention.len()..].chars().next();
+        !before.is_some_and(is_word) && !after.is_some_and(is_word)
+    })
+}
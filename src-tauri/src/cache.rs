@@ -0,0 +1,166 @@
+//! Message cache and outbox
+//!
+//! The cache keeps a local copy of message history (including history
+//! imported from other apps), the outbox holds messages that have been
+//! composed locally but not yet delivered to a server.
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+/// A message stored in the local cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedMessage {
+    pub id: String,
+    pub instance_id: Option<String>,
+    pub channel_id: String,
+    pub author_id: Option<String>,
+    pub author_name: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    /// Where the message came from, e.g. "server" or "import:matrix"
+    pub source: String,
+}
+
+impl CachedMessage {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            instance_id: row.get("instance_id")?,
+            channel_id: row.get("channel_id")?,
+            author_id: row.get("author_id")?,
+            author_name: row.get("author_name")?,
+            content: row.get("content")?,
+            created_at: row.get("created_at")?,
+            source: row.get("source")?,
+        })
+    }
+}
+
+/// A message waiting to be sent
+#[derive(Debug, Clone, Serialize)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub instance_id: String,
+    pub channel_id: String,
+    pub content: String,
+    /// Client nonce sent with the message so the server echo can be matched
+    pub nonce: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OutboxEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            instance_id: row.get("instance_id")?,
+            channel_id: row.get("channel_id")?,
+            content: row.get("content")?,
+            nonce: row.get("nonce")?,
+            created_at: row.get("created_at")?,
+        })
+    }
+}
+
+/// Insert messages, skipping any whose id is already cached.
+/// Returns the number of rows actually inserted.
+pub fn insert_messages(conn: &Connection, messages: &[CachedMessage]) -> rusqlite::Result<usize> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT OR IGNORE INTO messages
+            (id, instance_id, channel_id, author_id, author_name, content, created_at, source)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?;
+
+    let mut inserted = 0;
+    for message in messages {
+        inserted += stmt.execute(params![
+            message.id,
+            message.instance_id,
+            message.channel_id,
+            message.author_id,
+            message.author_name,
+            message.content,
+            message.created_at,
+            message.source,
+        ])?;
+    }
+
+    Ok(inserted)
+}
+
+/// Check whether a message id is already cached
+pub fn message_exists(conn: &Connection, id: &str) -> rusqlite::Result<bool> {
+    conn.prepare_cached("SELECT 1 FROM messages WHERE id = ?1")?
+        .query_row([id], |_| Ok(()))
+        .optional()
+        .map(|row| row.is_some())
+}
+
+/// List cached messages in a channel, newest first, optionally before a timestamp
+pub fn list_messages(
+    conn: &Connection,
+    channel_id: &str,
+    before: Option<DateTime<Utc>>,
+    limit: u32,
+) -> rusqlite::Result<Vec<CachedMessage>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT * FROM messages
+         WHERE channel_id = ?1 AND (?2 IS NULL OR created_at < ?2)
+         ORDER BY created_at DESC
+         LIMIT ?3",
+    )?;
+
+    let rows = stmt.query_map(params![channel_id, before, limit], CachedMessage::from_row)?;
+    rows.collect()
+}
+
+/// Queue a message for sending. Returns false if the nonce is already queued.
+pub fn enqueue_outbox(
+    conn: &Connection,
+    instance_id: &str,
+    channel_id: &str,
+    content: &str,
+    nonce: &str,
+) -> rusqlite::Result<bool> {
+    let inserted = conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO outbox (instance_id, channel_id, content, nonce, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?
+        .execute(params![instance_id, channel_id, content, nonce, Utc::now()])?;
+
+    Ok(inserted > 0)
+}
+
+/// Check whether a nonce is already queued
+pub fn outbox_contains(conn: &Connection, nonce: &str) -> rusqlite::Result<bool> {
+    conn.prepare_cached("SELECT 1 FROM outbox WHERE nonce = ?1")?
+        .query_row([nonce], |_| Ok(()))
+        .optional()
+        .map(|row| row.is_some())
+}
+
+/// List queued messages for an instance in the order they were queued
+pub fn list_outbox(conn: &Connection, instance_id: &str) -> rusqlite::Result<Vec<OutboxEntry>> {
+    let mut stmt =
+        conn.prepare_cached("SELECT * FROM outbox WHERE instance_id = ?1 ORDER BY id")?;
+    let rows = stmt.query_map([instance_id], OutboxEntry::from_row)?;
+    rows.collect()
+}
+
+/// Remove a delivered message from the outbox by its nonce
+pub fn remove_outbox_nonce(conn: &Connection, nonce: &str) -> rusqlite::Result<()> {
+    conn.prepare_cached("DELETE FROM outbox WHERE nonce = ?1")?
+        .execute([nonce])?;
+    Ok(())
+}
+
+/// Remove delivered messages from the outbox
+pub fn remove_outbox(conn: &Connection, ids: &[i64]) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare_cached("DELETE FROM outbox WHERE id = ?1")?;
+    for id in ids {
+        stmt.execute([id])?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,336 @@
+//! Incoming calls
+//!
+//! Ringing is driven from here rather than the webview, which may be
+//! throttled or hidden when a call comes in. The frontend reports the call;
+//! the ringtone then loops on the notification output device, a critical
+//! notification offers Answer and Decline, and the ring gives up after the
+//! configured timeout. Answering or declining from any surface (the call
+//! UI, the notification) goes through the same state machine, which stops
+//! the ringtone and removes the notification straight away and emits
+//! "call-state" so every surface catches up.
+//!
+//! Only ringing is handled natively; the call's media stays in the webview.
+//! During do not disturb (see [`crate::dnd`]) calls are still reported but
+//! don't ring or notify.
+
+mod ringtone;
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::audio::ducking;
+use crate::audio::playback::Playback;
+use crate::audio::NOTIFICATION_OUTPUT_SETTING;
+use crate::automation::{self, HookEvent};
+use crate::db::Database;
+use crate::deep_link;
+use crate::diagnostics::connectivity;
+use crate::dnd;
+use crate::media_session;
+use crate::native_theme;
+use crate::notifications::{self, Notification, NotificationButton, Shown};
+use crate::settings;
+
+/// Setting holding how many seconds a call rings before giving up
+pub const RING_TIMEOUT_SETTING: &str = "calls.ring_timeout_secs";
+
+/// Notification button ids
+pub const ANSWER_ACTION: &str = "answer-call";
+pub const DECLINE_ACTION: &str = "decline-call";
+
+const DEFAULT_RING_TIMEOUT: u64 = 30;
+const MIN_RING_TIMEOUT: u64 = 5;
+const MAX_RING_TIMEOUT: u64 = 120;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IncomingCall {
+    pub call_id: String,
+    pub instance_id: String,
+    pub channel_id: String,
+    /// Who's calling, as shown on the notification
+    pub caller: String,
+    /// Set when the call is reported
+    #[serde(default)]
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(tag = "status", content = "call", rename_all = "snake_case")]
+pub enum CallState {
+    #[default]
+    Idle,
+    Ringing(IncomingCall),
+    Active(IncomingCall),
+}
+
+/// Why the state last changed
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallReason {
+    Ringing,
+    Answered,
+    Declined,
+    TimedOut,
+    Cancelled,
+    Ended,
+}
+
+/// Payload of "call-state"
+#[derive(Debug, Clone, Serialize)]
+pub struct CallStateChange {
+    pub state: CallState,
+    pub reason: CallReason,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CallError {
+    #[error("another call is already ringing or in progress")]
+    Busy,
+    #[error("call {0} isn't ringing")]
+    NotRinging(String),
+    #[error("call {0} isn't in progress")]
+    NotActive(String),
+}
+
+#[derive(Default)]
+pub struct Calls {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    state: CallState,
+    /// Bumped on every ring so a late timeout or notification doesn't
+    /// touch a later call
+    generation: u64,
+    ringer: Option<Playback>,
+    notification: Option<u32>,
+}
+
+impl Calls {
+    pub fn state(&self) -> CallState {
+        self.lock().state.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Start ringing for a call reported by the frontend
+pub fn incoming(app: &AppHandle, mut call: IncomingCall) -> Result<CallState, CallError> {
+    call.started_at = Some(chrono::Utc::now());
+
+    let calls = app.state::<Calls>();
+    let generation = {
+        let mut inner = calls.lock();
+        if !matches!(inner.state, CallState::Idle) {
+            return Err(CallError::Busy);
+        }
+        inner.generation += 1;
+        inner.state = CallState::Ringing(call.clone());
+        inner.generation
+    };
+    let state = CallState::Ringing(call.clone());
+    let _ = app.emit(
+        "call-state",
+        CallStateChange {
+            state: state.clone(),
+            reason: CallReason::Ringing,
+        },
+    );
+    native_theme::refresh_tray(app);
+
+    // Opening the device can take a moment, so the call may already be
+    // over by the time the ringtone starts
+    let device_name = app
+        .state::<Database>()
+        .with(|conn| settings::get_value(conn, NOTIFICATION_OUTPUT_SETTING))
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to read the notification output device: {}", e);
+            None
+        })
+        .and_then(|v| v.as_str().map(str::to_string));
+    let ringtone = if dnd::is_active(app) {
+        Ok(None)
+    } else {
+        let custom = native_theme::ringtone(app);
+        Playback::start(device_name, move |rate| match &custom {
+            Some(sound) => sound.looping(rate),
+            None => ringtone::source(rate),
+        })
+        .map(Some)
+    };
+    match ringtone {
+        Ok(None) => {}
+        Ok(Some(ringer)) => {
+            let mut inner = calls.lock();
+            if inner.generation == generation && matches!(inner.state, CallState::Ringing(_)) {
+                inner.ringer = Some(ringer);
+            } else {
+                drop(inner);
+                drop(ringer);
+            }
+        }
+        Err(e) => log::warn!("Failed to play the ringtone: {}", e),
+    }
+
+    let notification = Notification {
+        title: call.caller.clone(),
+        body: "Incoming call".to_string(),
+        instance_id: call.instance_id.clone(),
+        channel_id: call.channel_id.clone(),
+        buttons: vec![
+            NotificationButton {
+                id: ANSWER_ACTION.to_string(),
+                label: "Answer".to_string(),
+            },
+            NotificationButton {
+                id: DECLINE_ACTION.to_string(),
+                label: "Decline".to_string(),
+            },
+        ],
+        mentioned: false,
+        call_id: Some(call.call_id.clone()),
+    };
+    let handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let id = match notifications::show(&handle, notification).await {
+            Ok(Shown::Native { id }) => id,
+            Ok(Shown::Webview | Shown::Suppressed | Shown::Filtered) => return,
+            Err(e) => {
+                log::warn!("Failed to show the call notification: {}", e);
+                return;
+            }
+        };
+        let current = {
+            let calls = handle.state::<Calls>();
+            let mut inner = calls.lock();
+            let current =
+                inner.generation == generation && matches!(inner.state, CallState::Ringing(_));
+            if current {
+                inner.notification = Some(id);
+            }
+            current
+        };
+        if !current {
+            let _ = notifications::close(&handle, id).await;
+        }
+    });
+
+    let timeout = ring_timeout(app);
+    let handle = app.clone();
+    let call_id = call.call_id.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(timeout).await;
+        let _ = transition(&handle, CallReason::TimedOut, |inner| match &inner.state {
+            CallState::Ringing(_) if inner.generation == generation => Ok(CallState::Idle),
+            _ => Err(CallError::NotRinging(call_id)),
+        });
+    });
+
+    Ok(state)
+}
+
+/// Answer the ringing call and raise the window
+pub fn answer(app: &AppHandle, call_id: &str) -> Result<CallState, CallError> {
+    let state = transition(app, CallReason::Answered, |inner| match &inner.state {
+        CallState::Ringing(call) if call.call_id == call_id => Ok(CallState::Active(call.clone())),
+        _ => Err(CallError::NotRinging(call_id.to_string())),
+    })?;
+    deep_link::focus_main_window(app);
+    connectivity::check_during_call(app);
+    if let CallState::Active(call) = &state {
+        automation::fire(
+            app,
+            HookEvent::CallStarted,
+            vec![
+                ("instance_id", call.instance_id.clone()),
+                ("channel_id", call.channel_id.clone()),
+                ("caller", call.caller.clone()),
+            ],
+        );
+    }
+    Ok(state)
+}
+
+/// Turn down the ringing call
+pub fn decline(app: &AppHandle, call_id: &str) -> Result<CallState, CallError> {
+    transition(app, CallReason::Declined, |inner| match &inner.state {
+        CallState::Ringing(call) if call.call_id == call_id => Ok(CallState::Idle),
+        _ => Err(CallError::NotRinging(call_id.to_string())),
+    })
+}
+
+/// Stop ringing because the caller gave up or the call was picked up on
+/// another device
+pub fn cancel(app: &AppHandle, call_id: &str) -> Result<CallState, CallError> {
+    transition(app, CallReason::Cancelled, |inner| match &inner.state {
+        CallState::Ringing(call) if call.call_id == call_id => Ok(CallState::Idle),
+        _ => Err(CallError::NotRinging(call_id.to_string())),
+    })
+}
+
+/// Mark the answered call as over
+pub fn end(app: &AppHandle, call_id: &str) -> Result<CallState, CallError> {
+    let state = transition(app, CallReason::Ended, |inner| match &inner.state {
+        CallState::Active(call) if call.call_id == call_id => Ok(CallState::Idle),
+        _ => Err(CallError::NotActive(call_id.to_string())),
+    })?;
+    ducking::reset(app);
+    media_session::set(app, None);
+    Ok(state)
+}
+
+/// Move to the state `next` picks, stop ringing and emit "call-state"
+fn transition(
+    app: &AppHandle,
+    reason: CallReason,
+    next: impl FnOnce(&Inner) -> Result<CallState, CallError>,
+) -> Result<CallState, CallError> {
+    let calls = app.state::<Calls>();
+    let (state, ringer, notification) = {
+        let mut inner = calls.lock();
+        let state = next(&inner)?;
+        inner.state = state.clone();
+        (state, inner.ringer.take(), inner.notification.take())
+    };
+
+    // Stopped outside the lock; dropping waits for the audio thread
+    drop(ringer);
+    if let Some(id) = notification {
+        let handle = app.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = notifications::close(&handle, id).await {
+                log::warn!("Failed to remove the call notification: {}", e);
+            }
+        });
+    }
+
+    let _ = app.emit(
+        "call-state",
+        CallStateChange {
+            state: state.clone(),
+            reason,
+        },
+    );
+    native_theme::refresh_tray(app);
+    Ok(state)
+}
+
+fn ring_timeout(app: &AppHandle) -> Duration {
+    let secs = app
+        .state::<Database>()
+        .with(|conn| settings::get_value(conn, RING_TIMEOUT_SETTING))
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to read the ring timeout: {}", e);
+            None
+        })
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_RING_TIMEOUT)
+        .clamp(MIN_RING_TIMEOUT, MAX_RING_TIMEOUT);
+    Duration::from_secs(secs)
+}
@@ -0,0 +1,50 @@
+//! The ringtone
+//!
+//! Generated rather than shipped as a file: two short bursts of a two-tone
+//! chord and a pause, repeated until the ringing stops.
+
+use std::f32::consts::TAU;
+
+use crate::audio::playback::Source;
+
+const TONES: [f32; 2] = [440.0, 480.0];
+/// Seconds on or off in one ring
+const PATTERN: [(f32, bool); 4] = [(0.4, true), (0.2, false), (0.4, true), (2.0, false)];
+const VOLUME: f32 = 0.25;
+/// Fade at the edges of each burst so it doesn't click
+const RAMP_SECS: f32 = 0.01;
+
+/// An endless ringtone at the device's sample rate
+pub fn source(sample_rate: u32) -> Source {
+    let rate = sample_rate as f32;
+    let cycle: f32 = PATTERN.iter().map(|(secs, _)| secs).sum();
+    let cycle_len = (cycle * rate) as u64;
+    let mut position = 0_u64;
+
+    Box::new(move |out: &mut [f32]| {
+        for sample in out {
+            *sample = at((position % cycle_len) as f32 / rate);
+            position += 1;
+        }
+    })
+}
+
+/// The sample `t` seconds into a ring
+fn at(t: f32) -> f32 {
+    let mut start = 0.0;
+    for (secs, on) in PATTERN {
+        if t < start + secs {
+            if !on {
+                return 0.0;
+            }
+            let offset = t - start;
+            let envelope = (offset / RAMP_SECS)
+                .min((secs - offset) / RAMP_SECS)
+                .min(1.0);
+            let chord = TONES.iter().map(|f| (TAU * f * t).sin()).sum::<f32>() / TONES.len() as f32;
+            return chord * envelope * VOLUME;
+        }
+        start += secs;
+    }
+    0.0
+}
@@ -0,0 +1,467 @@
+//! Live captions
+//!
+//! Speech in a call is transcribed on the device with whisper.cpp, so no
+//! audio leaves it; only the model is downloaded, once. The webview hands
+//! over the call's mixed audio as it plays it, and the microphone can be
+//! captioned too, captured natively. Audio is cut into phrases at pauses,
+//! each phrase is transcribed on the captions thread and emitted as a
+//! "caption" event. Call captions are attributed to whoever the call's
+//! speaking detection reported during the phrase (see
+//! [`crate::audio::ducking`]); microphone captions to the local user.
+//!
+//! whisper.cpp is only built with the `captions` feature, as it needs CMake
+//! and a C++ toolchain. Without it captions can't be started.
+
+#[cfg(not(feature = "captions"))]
+mod unavailable;
+#[cfg(feature = "captions")]
+mod whisper;
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+#[cfg(not(feature = "captions"))]
+use unavailable::Transcriber;
+#[cfg(feature = "captions")]
+use whisper::Transcriber;
+
+use crate::audio::capture::{Capture, CaptureError};
+use crate::audio::channel_overrides;
+use crate::audio::ducking::Ducking;
+use crate::audio::SAMPLE_RATE;
+use crate::coalesce;
+use crate::data_usage::{self, Category};
+use crate::db::Database;
+use crate::settings;
+
+/// Setting holding the name of the model to caption with
+pub const MODEL_SETTING: &str = "captions.model";
+/// Setting holding the spoken language's code; detected when unset
+pub const LANGUAGE_SETTING: &str = "captions.language";
+
+/// Models that can be downloaded: name and approximate size in MB
+const CATALOG: &[(&str, u64)] = &[
+    ("tiny", 75),
+    ("tiny.en", 75),
+    ("base", 142),
+    ("base.en", 142),
+    ("small", 466),
+    ("small.en", 466),
+];
+const DEFAULT_MODEL: &str = "base";
+const SOURCE_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main";
+/// Larger than any model in the catalog
+const MAX_MODEL_BYTES: u64 = 600 * 1024 * 1024;
+
+/// Rate whisper works at
+const WHISPER_RATE: u32 = 16_000;
+/// A phrase is cut at the first pause after this much audio
+const MIN_PHRASE: Duration = Duration::from_millis(1500);
+/// and at this length regardless
+const MAX_PHRASE: Duration = Duration::from_secs(8);
+/// Quiet this long counts as a pause
+const PAUSE: Duration = Duration::from_millis(400);
+/// RMS level under which audio counts as quiet
+const QUIET_RMS: f32 = 0.01;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptionsError {
+    #[error("this build doesn't include live captions")]
+    #[cfg_attr(feature = "captions", allow(dead_code))]
+    Unavailable,
+    #[error("captions are already running")]
+    AlreadyRunning,
+    #[error("captions aren't running")]
+    NotRunning,
+    #[error("unknown caption model: {0}")]
+    UnknownModel(String),
+    #[error("caption model {0} isn't downloaded")]
+    NotDownloaded(String),
+    #[error("model download failed with status {0}")]
+    Status(u16),
+    #[error("model file is too large")]
+    TooLarge,
+    #[error("model path isn't valid UTF-8")]
+    #[cfg_attr(not(feature = "captions"), allow(dead_code))]
+    ModelPath,
+    #[error("no directory for caption models: {0}")]
+    NoDirectory(#[from] tauri::Error),
+    #[error("model download failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("{0}")]
+    Capture(#[from] CaptureError),
+    #[error("failed to access caption model: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[cfg(feature = "captions")]
+    #[error("transcription failed: {0}")]
+    Whisper(#[from] whisper_rs::WhisperError),
+}
+
+/// A catalog model and whether it's downloaded
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptionModel {
+    pub name: String,
+    pub size_mb: u64,
+    pub downloaded: bool,
+    pub selected: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptionSource {
+    Call,
+    Microphone,
+}
+
+/// Payload of "caption"
+#[derive(Debug, Clone, Serialize)]
+pub struct Caption {
+    pub source: CaptionSource,
+    /// Who was speaking during the phrase, as far as is known
+    pub speakers: Vec<String>,
+    pub text: String,
+    /// Milliseconds since captions started
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+/// Payload of "caption-model-progress"
+#[derive(Debug, Clone, Serialize)]
+struct DownloadProgress<'a> {
+    name: &'a str,
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+enum Input {
+    Call(Vec<f32>, Vec<String>),
+    Microphone(Vec<f32>),
+}
+
+struct Running {
+    tx: mpsc::Sender<Input>,
+    /// Kept for as long as the microphone is captioned
+    microphone: Option<Capture>,
+    worker: JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct Captions {
+    running: Mutex<Option<Running>>,
+}
+
+impl Captions {
+    pub fn is_running(&self) -> bool {
+        self.lock().is_some()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Running>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// List the catalog, marking what's downloaded and selected
+pub fn list_models(app: &AppHandle, db: &Database) -> Result<Vec<CaptionModel>, CaptionsError> {
+    let dir = models_dir(app)?;
+    let selected = selected_model(db)?;
+    Ok(CATALOG
+        .iter()
+        .map(|(name, size_mb)| CaptionModel {
+            name: name.to_string(),
+            size_mb: *size_mb,
+            downloaded: dir.join(file_name(name)).is_file(),
+            selected: *name == selected,
+        })
+        .collect())
+}
+
+/// Download a catalog model, emitting "caption-model-progress" as it
+/// arrives
+pub async fn download_model(app: &AppHandle, name: &str) -> Result<(), CaptionsError> {
+    let name = catalog_name(name)?;
+    let dir = models_dir(app)?;
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let url = format!("{}/{}", SOURCE_URL, file_name(name));
+    let mut response = reqwest::get(&url).await?;
+    if !response.status().is_success() {
+        return Err(CaptionsError::Status(response.status().as_u16()));
+    }
+    let total = response.content_length();
+    if total.is_some_and(|len| len > MAX_MODEL_BYTES) {
+        return Err(CaptionsError::TooLarge);
+    }
+
+    // Written aside so a failed download doesn't leave half a model
+    let partial = dir.join(format!("{}.partial", file_name(name)));
+    let mut file = tokio::fs::File::create(&partial).await?;
+    let mut downloaded = 0u64;
+    let result = async {
+        use tokio::io::AsyncWriteExt;
+
+        while let Some(chunk) = response.chunk().await? {
+            downloaded += chunk.len() as u64;
+            data_usage::record(app, Category::Updates, 0, chunk.len() as u64);
+            if downloaded > MAX_MODEL_BYTES {
+                return Err(CaptionsError::TooLarge);
+            }
+            file.write_all(&chunk).await?;
+            coalesce::emit(
+                app,
+                "caption-model-progress",
+                name,
+                DownloadProgress {
+                    name,
+                    downloaded,
+                    total,
+                },
+            );
+        }
+        file.flush().await?;
+        Ok(())
+    }
+    .await;
+    drop(file);
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&partial, dir.join(file_name(name))).await?;
+    Ok(())
+}
+
+/// Delete a downloaded model
+pub fn remove_model(app: &AppHandle, name: &str) -> Result<(), CaptionsError> {
+    let name = catalog_name(name)?;
+    match std::fs::remove_file(models_dir(app)?.join(file_name(name))) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Start captioning with the selected model. The microphone is captioned
+/// too if `local_user_id` is given, attributed to it.
+pub fn start(
+    app: &AppHandle,
+    db: &Database,
+    local_user_id: Option<String>,
+) -> Result<(), CaptionsError> {
+    let captions = app.state::<Captions>();
+    let mut running = captions.lock();
+    if running.is_some() {
+        return Err(CaptionsError::AlreadyRunning);
+    }
+
+    let model = selected_model(db)?;
+    let path = models_dir(app)?.join(file_name(&model));
+    if !path.is_file() {
+        return Err(CaptionsError::NotDownloaded(model));
+    }
+    let language = db
+        .with(|conn| settings::get_value(conn, LANGUAGE_SETTING))?
+        .and_then(|v| v.as_str().map(str::to_string))
+        .filter(|language| !language.is_empty());
+    // Loading takes a moment, but a model that can't be loaded should fail
+    // here rather than on the captions thread
+    let transcriber = Transcriber::load(&path, language)?;
+
+    let (tx, rx) = mpsc::channel();
+    let microphone = match &local_user_id {
+        Some(_) => {
+            let device_name = channel_overrides::input_device(app)?;
+            let tx = tx.clone();
+            Some(Capture::start(
+                device_name,
+                Box::new(move |samples| {
+                    let _ = tx.send(Input::Microphone(samples.to_vec()));
+                }),
+            )?)
+        }
+        None => None,
+    };
+
+    let handle = app.clone();
+    let worker = std::thread::Builder::new()
+        .name("captions".into())
+        .spawn(move || caption(&handle, transcriber, rx, local_user_id))?;
+
+    *running = Some(Running {
+        tx,
+        microphone,
+        worker,
+    });
+    Ok(())
+}
+
+/// Add a block of the call's mixed 48 kHz mono audio
+pub fn push_call_audio(app: &AppHandle, samples: Vec<f32>) -> Result<(), CaptionsError> {
+    let speakers = app.state::<Ducking>().speaking();
+    let captions = app.state::<Captions>();
+    let running = captions.lock();
+    let running = running.as_ref().ok_or(CaptionsError::NotRunning)?;
+    running
+        .tx
+        .send(Input::Call(samples, speakers))
+        .map_err(|_| CaptionsError::NotRunning)
+}
+
+/// Stop captioning; phrases already cut are still emitted
+pub fn stop(app: &AppHandle) {
+    let Some(running) = app.state::<Captions>().lock().take() else {
+        return;
+    };
+    drop(running.microphone);
+    drop(running.tx);
+    let _ = running.worker.join();
+}
+
+fn models_dir(app: &AppHandle) -> Result<PathBuf, CaptionsError> {
+    Ok(app.path().app_data_dir()?.join("whisper"))
+}
+
+fn file_name(name: &str) -> String {
+    format!("ggml-{}.bin", name)
+}
+
+fn catalog_name(name: &str) -> Result<&'static str, CaptionsError> {
+    CATALOG
+        .iter()
+        .map(|(n, _)| *n)
+        .find(|n| *n == name)
+        .ok_or_else(|| CaptionsError::UnknownModel(name.to_string()))
+}
+
+fn selected_model(db: &Database) -> Result<String, CaptionsError> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, MODEL_SETTING))?
+        .and_then(|v| v.as_str().map(str::to_string))
+        .filter(|name| catalog_name(name).is_ok())
+        .unwrap_or_else(|| DEFAULT_MODEL.to_string()))
+}
+
+/// Audio for one source, collected until a phrase is complete
+struct Phrase {
+    source: CaptionSource,
+    /// At [`WHISPER_RATE`]
+    samples: Vec<f32>,
+    /// Samples since captions started, at [`WHISPER_RATE`]
+    position: u64,
+    /// Leftover input samples not yet downsampled
+    pending: Vec<f32>,
+    quiet: usize,
+    speakers: BTreeSet<String>,
+}
+
+impl Phrase {
+    fn new(source: CaptionSource) -> Self {
+        Self {
+            source,
+            samples: Vec::new(),
+            position: 0,
+            pending: Vec::new(),
+            quiet: 0,
+            speakers: BTreeSet::new(),
+        }
+    }
+
+    /// Add 48 kHz audio; returns a finished phrase's audio and where it started
+    fn push(&mut self, input: &[f32]) -> Option<(Vec<f32>, u64, Vec<String>)> {
+        // 48 kHz to 16 kHz by averaging, which also keeps aliasing down
+        let ratio = (SAMPLE_RATE / WHISPER_RATE) as usize;
+        self.pending.extend_from_slice(input);
+        let whole = self.pending.len() / ratio * ratio;
+        let start = self.samples.len();
+        self.samples.extend(
+            self.pending[..whole]
+                .chunks(ratio)
+                .map(|chunk| chunk.iter().sum::<f32>() / ratio as f32),
+        );
+        self.pending.drain(..whole);
+
+        let added = &self.samples[start..];
+        if !added.is_empty() {
+            let rms = (added.iter().map(|s| s * s).sum::<f32>() / added.len() as f32).sqrt();
+            if rms < QUIET_RMS {
+                self.quiet += added.len();
+            } else {
+                self.quiet = 0;
+            }
+        }
+
+        let len = self.samples.len();
+        let samples_in = |d: Duration| (d.as_millis() as u64 * WHISPER_RATE as u64 / 1000) as usize;
+        let paused = len >= samples_in(MIN_PHRASE) && self.quiet >= samples_in(PAUSE);
+        if !paused && len < samples_in(MAX_PHRASE) {
+            // Nothing but quiet so far isn't worth keeping
+            if self.quiet >= len && len >= samples_in(PAUSE) {
+                self.position += len as u64;
+                self.samples.clear();
+            }
+            return None;
+        }
+
+        let started = self.position;
+        self.position += len as u64;
+        self.quiet = 0;
+        let speakers = std::mem::take(&mut self.speakers).into_iter().collect();
+        Some((std::mem::take(&mut self.samples), started, speakers))
+    }
+}
+
+/// Transcribe phrases from `rx` until captions stop
+fn caption(
+    app: &AppHandle,
+    mut transcriber: Transcriber,
+    rx: mpsc::Receiver<Input>,
+    local_user_id: Option<String>,
+) {
+    let mut call = Phrase::new(CaptionSource::Call);
+    let mut microphone = Phrase::new(CaptionSource::Microphone);
+
+    for input in rx {
+        let (source, finished) = match input {
+            Input::Call(samples, speakers) => {
+                call.speakers.extend(speakers);
+                (call.source, call.push(&samples))
+            }
+            Input::Microphone(samples) => (microphone.source, microphone.push(&samples)),
+        };
+        let Some((samples, started, speakers)) = finished else {
+            continue;
+        };
+        let speakers = match source {
+            CaptionSource::Call => speakers,
+            CaptionSource::Microphone => local_user_id.iter().cloned().collect(),
+        };
+
+        let text = match transcriber.transcribe(&samples) {
+            Ok(text) => text,
+            Err(e) => {
+                log::warn!("Failed to transcribe a phrase: {}", e);
+                continue;
+            }
+        };
+        if text.is_empty() {
+            continue;
+        }
+        let to_ms = |samples: u64| samples * 1000 / WHISPER_RATE as u64;
+        let _ = app.emit(
+            "caption",
+            Caption {
+                source,
+                speakers,
+                text,
+                start_ms: to_ms(started),
+                end_ms: to_ms(started + samples.len() as u64),
+            },
+        );
+    }
+}
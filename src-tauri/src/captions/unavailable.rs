@@ -0,0 +1,17 @@
+//! Stand-in for builds without the `captions` feature
+
+use std::path::Path;
+
+use super::CaptionsError;
+
+pub struct Transcriber;
+
+impl Transcriber {
+    pub fn load(_model: &Path, _language: Option<String>) -> Result<Self, CaptionsError> {
+        Err(CaptionsError::Unavailable)
+    }
+
+    pub fn transcribe(&mut self, _samples: &[f32]) -> Result<String, CaptionsError> {
+        Err(CaptionsError::Unavailable)
+    }
+}
@@ -0,0 +1,61 @@
+//! Transcription with whisper.cpp
+
+use std::path::Path;
+
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+};
+
+use super::CaptionsError;
+
+/// Segments whisper is this sure contain no speech are dropped
+const NO_SPEECH_THRESHOLD: f32 = 0.6;
+
+pub struct Transcriber {
+    state: WhisperState,
+    language: Option<String>,
+    threads: i32,
+}
+
+impl Transcriber {
+    /// Load a ggml model; `language` is a code such as "en", or `None` to
+    /// detect it
+    pub fn load(model: &Path, language: Option<String>) -> Result<Self, CaptionsError> {
+        let path = model.to_str().ok_or(CaptionsError::ModelPath)?;
+        let context = WhisperContext::new_with_params(path, WhisperContextParameters::default())?;
+        let state = context.create_state()?;
+        // Leave cores for the call itself
+        let threads = std::thread::available_parallelism()
+            .map_or(2, |n| n.get() / 2)
+            .clamp(1, 8) as i32;
+        Ok(Self {
+            state,
+            language,
+            threads,
+        })
+    }
+
+    /// Transcribe 16 kHz mono audio, returning its text
+    pub fn transcribe(&mut self, samples: &[f32]) -> Result<String, CaptionsError> {
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_language(Some(self.language.as_deref().unwrap_or("auto")));
+        params.set_n_threads(self.threads);
+        params.set_no_context(true);
+        params.set_single_segment(true);
+        params.set_suppress_blank(true);
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_timestamps(false);
+
+        self.state.full(params, samples)?;
+        let mut text = String::new();
+        for segment in self.state.as_iter() {
+            if segment.no_speech_probability() > NO_SPEECH_THRESHOLD {
+                continue;
+            }
+            text.push_str(&segment.to_str_lossy()?);
+        }
+        Ok(text.trim().to_string())
+    }
+}
@@ -0,0 +1,89 @@
+//! Clipboard attachments
+//!
+//! Reads what's been copied straight from the OS clipboard (X11, Wayland,
+//! Windows and macOS) instead of relying on the webview's paste event,
+//! which only carries images on some platforms and never file lists.
+//! Copied files are attached as they are; bitmaps, such as screenshots,
+//! are encoded to PNG and written to a scratch directory so they can go
+//! through the same upload pipeline as any other file.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use image::{ImageFormat, RgbaImage};
+
+/// Pasted images older than this are removed when the next one is written
+const PASTE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClipboardError {
+    #[error("clipboard is unavailable: {0}")]
+    Clipboard(#[from] arboard::Error),
+    #[error("clipboard image is malformed")]
+    Malformed,
+    #[error("failed to encode clipboard image: {0}")]
+    Encode(#[from] image::ImageError),
+    #[error("failed to save clipboard image: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Files on the clipboard, or the copied image saved as a PNG under
+/// `paste_dir`. Empty if there's nothing that can be attached.
+pub fn read_files(paste_dir: &Path) -> Result<Vec<PathBuf>, ClipboardError> {
+    let mut clipboard = arboard::Clipboard::new()?;
+
+    match clipboard.get().file_list() {
+        Ok(files) if !files.is_empty() => return Ok(files),
+        Ok(_) | Err(arboard::Error::ContentNotAvailable) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let image = match clipboard.get().image() {
+        Ok(image) => image,
+        Err(arboard::Error::ContentNotAvailable) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let image = RgbaImage::from_raw(
+        image.width as u32,
+        image.height as u32,
+        image.bytes.into_owned(),
+    )
+    .ok_or(ClipboardError::Malformed)?;
+
+    let mut encoded = Vec::new();
+    image.write_to(&mut Cursor::new(&mut encoded), ImageFormat::Png)?;
+
+    fs::create_dir_all(paste_dir)?;
+    remove_old(paste_dir);
+    let name = format!(
+        "Pasted image {}.png",
+        chrono::Local::now().format("%Y-%m-%d %H-%M-%S")
+    );
+    // Pastes within the same second get a directory each so the name stays readable
+    let dir = paste_dir.join(uuid::Uuid::new_v4().to_string());
+    fs::create_dir(&dir)?;
+    let path = dir.join(name);
+    fs::write(&path, encoded)?;
+
+    Ok(vec![path])
+}
+
+/// Best effort: a paste that's still uploading is well under the TTL
+fn remove_old(paste_dir: &Path) {
+    let Ok(entries) = fs::read_dir(paste_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > PASTE_TTL);
+        if expired {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
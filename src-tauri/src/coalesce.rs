@@ -0,0 +1,168 @@
+//! Coalesced events
+//!
+//! Progress and state events can fire far more often than the frontend
+//! can draw them, and each one crosses the IPC boundary on its own. Events
+//! sent through [`emit`] are collected for a frame ([`FRAME`], or longer
+//! on the low performance tier, see [`crate::performance`]) and then
+//! sent according to their type's [`Policy`]: only the latest payload for
+//! each key, or every payload together as one array. Event types without
+//! a policy are emitted straight away.
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::logging;
+use crate::performance;
+
+/// How long events are collected before they're sent
+pub const FRAME: Duration = Duration::from_millis(16);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// Each frame sends the latest payload per key, dropping older ones
+    Latest,
+    /// Each frame sends every payload as one array
+    Batch,
+}
+
+const POLICIES: &[(&str, Policy)] = &[
+    ("caption-model-progress", Policy::Latest),
+    ("download-progress", Policy::Latest),
+    ("ducking", Policy::Latest),
+    ("log-event", Policy::Batch),
+    ("update-progress", Policy::Latest),
+    ("upload-status", Policy::Latest),
+];
+
+struct Queued {
+    event: &'static str,
+    policy: Policy,
+    key: String,
+    payload: Value,
+}
+
+pub struct Coalescer {
+    tx: mpsc::Sender<Queued>,
+}
+
+/// Start sending coalesced events; call once during setup
+pub fn spawn(app: &AppHandle) {
+    let (tx, rx) = mpsc::channel::<Queued>();
+    let handle = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("event-coalescer".to_string())
+        .spawn(move || {
+            // Waits for the first event of a frame, then collects until it ends
+            while let Ok(first) = rx.recv() {
+                let mut frame = Frame::default();
+                frame.add(first);
+                let length = if performance::is_low() {
+                    performance::LOW_TIER_FRAME
+                } else {
+                    FRAME
+                };
+                let end = Instant::now() + length;
+                loop {
+                    match rx.recv_timeout(end.saturating_duration_since(Instant::now())) {
+                        Ok(queued) => frame.add(queued),
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => {
+                            frame.send(&handle);
+                            return;
+                        }
+                    }
+                }
+                frame.send(&handle);
+            }
+        });
+    match spawned {
+        Ok(_) => {
+            app.manage(Coalescer { tx });
+        }
+        Err(e) => log::error!("Failed to start event coalescing: {}", e),
+    }
+}
+
+/// Emit `payload` as `event`, coalesced with others of the same `key` when
+/// the event type has a policy
+pub fn emit<S: Serialize + Clone>(app: &AppHandle, event: &'static str, key: &str, payload: S) {
+    let policy = POLICIES
+        .iter()
+        .find(|(name, _)| *name == event)
+        .map(|(_, policy)| *policy);
+    let (policy, coalescer) = match (policy, app.try_state::<Coalescer>()) {
+        (Some(policy), Some(coalescer)) => (policy, coalescer),
+        // Batched events keep their shape when sent on their own
+        (Some(Policy::Batch), None) => {
+            let _ = app.emit(event, [payload]);
+            return;
+        }
+        _ => {
+            let _ = app.emit(event, payload);
+            return;
+        }
+    };
+    let payload = match serde_json::to_value(payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            log::error!("Failed to serialize {}: {}", event, e);
+            return;
+        }
+    };
+    let _ = coalescer.tx.send(Queued {
+        event,
+        policy,
+        key: key.to_string(),
+        payload,
+    });
+}
+
+#[derive(Default)]
+struct Frame {
+    /// In the order they were first seen
+    latest: Vec<Queued>,
+    batches: Vec<(&'static str, Vec<Value>)>,
+}
+
+impl Frame {
+    fn add(&mut self, queued: Queued) {
+        match queued.policy {
+            Policy::Batch => {
+                match self
+                    .batches
+                    .iter_mut()
+                    .find(|(event, _)| *event == queued.event)
+                {
+                    Some((_, payloads)) => payloads.push(queued.payload),
+                    None => self.batches.push((queued.event, vec![queued.payload])),
+                }
+            }
+            Policy::Latest => {
+                let seen = self
+                    .latest
+                    .iter_mut()
+                    .find(|q| q.event == queued.event && q.key == queued.key);
+                match seen {
+                    Some(seen) => seen.payload = queued.payload,
+                    None => self.latest.push(queued),
+                }
+            }
+        }
+    }
+
+    fn send(self, app: &AppHandle) {
+        // Logging while sending a batch of log events would make another
+        logging::without_streaming(|| {
+            for queued in self.latest {
+                let _ = app.emit(queued.event, queued.payload);
+            }
+            for (event, payloads) in self.batches {
+                let _ = app.emit(event, payloads);
+            }
+        });
+    }
+}
@@ -0,0 +1,11 @@
+use tauri::AppHandle;
+
+use crate::accessibility::{self, VoiceState};
+
+/// Report the voice state after it changes, so changes made from outside
+/// the window can be announced to screen readers
+#[tauri::command]
+pub async fn report_voice_state(app: AppHandle, state: VoiceState) -> Result<(), String> {
+    accessibility::report(&app, state);
+    Ok(())
+}
@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::audio::call_recording::{self, FinishedRecording, Layout, Marker, RecordingActive};
+use crate::audio::channel_overrides::{self, ChannelAudioState, ChannelOverride};
+use crate::audio::ducking::{self, Ducking, DuckingState};
+use crate::audio::gate::{self, NoiseGateSettings};
+use crate::audio::voice_message::{VoiceMessage, VoiceMessages, MAX_DURATION};
+use crate::audio::{capture, playback};
+use crate::db::Database;
+use crate::logging::LogErr;
+use crate::startup::{self, Subsystem};
+use crate::uploads::ingest::Offered;
+
+/// List the names of connected microphones, for the "devices.audio_input" setting
+#[tauri::command]
+pub async fn list_audio_input_devices(app: AppHandle) -> Result<Vec<String>, String> {
+    startup::wait(&app, Subsystem::Audio).await.log_err()?;
+    tauri::async_runtime::spawn_blocking(capture::input_devices)
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// List the names of connected output devices, for the
+/// "devices.notification_output" setting
+#[tauri::command]
+pub async fn list_audio_output_devices(app: AppHandle) -> Result<Vec<String>, String> {
+    startup::wait(&app, Subsystem::Audio).await.log_err()?;
+    tauri::async_runtime::spawn_blocking(playback::output_devices)
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Get the noise gate applied to the microphone
+#[tauri::command]
+pub async fn get_noise_gate() -> Result<NoiseGateSettings, String> {
+    Ok(gate::current())
+}
+
+/// Set the noise gate's threshold, attack, hold and release, which apply
+/// to running captures straight away; returns them clamped to their ranges
+#[tauri::command]
+pub async fn set_noise_gate(
+    app: AppHandle,
+    db: State<'_, Database>,
+    settings: NoiseGateSettings,
+) -> Result<NoiseGateSettings, String> {
+    let settings = gate::set(&db, settings).log_err()?;
+    // A channel with its own gate keeps it until it's left
+    channel_overrides::restore_gate(&app);
+    Ok(settings)
+}
+
+/// Start recording a voice message from the microphone
+/// Recording stops after `max_duration_secs` (at most five minutes), then
+/// emits "voice-message-stopped" with the finished message
+#[tauri::command]
+pub async fn start_voice_message(
+    app: AppHandle,
+    max_duration_secs: Option<u64>,
+) -> Result<String, String> {
+    startup::wait(&app, Subsystem::Audio).await.log_err()?;
+    let device_name = channel_overrides::input_device(&app).log_err()?;
+    let max_duration = max_duration_secs
+        .map(Duration::from_secs)
+        .unwrap_or(MAX_DURATION)
+        .min(MAX_DURATION);
+    let dir = app.path().app_cache_dir().log_err()?.join("voice");
+
+    let handle = app.clone();
+    let id = tauri::async_runtime::spawn_blocking(move || {
+        handle
+            .state::<VoiceMessages>()
+            .start(&dir, device_name, max_duration)
+    })
+    .await
+    .log_err()?
+    .log_err()?
+    .ok_or_else(|| "a voice message is already being recorded".to_string())?;
+
+    let recording = id.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(max_duration).await;
+        let Some(recording) = app.state::<VoiceMessages>().take(Some(&recording)) else {
+            return;
+        };
+        match tauri::async_runtime::spawn_blocking(move || recording.finish()).await {
+            Ok(Ok(message)) => {
+                app.state::<Offered>().add(&message.path);
+                let _ = app.emit("voice-message-stopped", message);
+            }
+            Ok(Err(e)) => log::error!("Failed to finish voice message: {}", e),
+            Err(e) => log::error!("Failed to finish voice message: {}", e),
+        }
+    });
+
+    Ok(id)
+}
+
+/// Stop recording and return the finished voice message
+#[tauri::command]
+pub async fn stop_voice_message(
+    voice_messages: State<'_, VoiceMessages>,
+    offered: State<'_, Offered>,
+) -> Result<VoiceMessage, String> {
+    let recording = voice_messages
+        .take(None)
+        .ok_or_else(|| "no voice message is being recorded".to_string())?;
+
+    let message = tauri::async_runtime::spawn_blocking(move || recording.finish())
+        .await
+        .log_err()?
+        .log_err()?;
+    offered.add(&message.path);
+    Ok(message)
+}
+
+/// Stop recording and throw the voice message away
+#[tauri::command]
+pub async fn cancel_voice_message(voice_messages: State<'_, VoiceMessages>) -> Result<(), String> {
+    if let Some(recording) = voice_messages.take(None) {
+        tauri::async_runtime::spawn_blocking(move || recording.discard())
+            .await
+            .log_err()?;
+    }
+    Ok(())
+}
+
+/// Start recording the call in a channel to local files, mixed or with a
+/// track per speaker
+/// Emits "recording-active" as recording starts and stops
+#[tauri::command]
+pub async fn start_call_recording(
+    app: AppHandle,
+    instance_id: String,
+    channel_id: String,
+    layout: Layout,
+) -> Result<RecordingActive, String> {
+    startup::wait(&app, Subsystem::Recordings).await.log_err()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        call_recording::start(&app, instance_id, channel_id, layout)
+    })
+    .await
+    .log_err()?
+    .log_err()
+}
+
+/// Add a block of 48 kHz mono call audio to a track of the recording
+#[tauri::command]
+pub async fn push_call_recording_audio(
+    app: AppHandle,
+    recording_id: String,
+    track: String,
+    samples: Vec<f32>,
+) -> Result<(), String> {
+    call_recording::push_audio(&app, &recording_id, track, samples).log_err()
+}
+
+/// Note a participant joining, leaving, or giving or withdrawing consent
+#[tauri::command]
+pub async fn add_call_recording_marker(
+    app: AppHandle,
+    recording_id: String,
+    marker: Marker,
+) -> Result<Marker, String> {
+    call_recording::add_marker(&app, &recording_id, marker).log_err()
+}
+
+/// Stop recording and finish the files; null if nothing was being recorded
+#[tauri::command]
+pub async fn stop_call_recording(app: AppHandle) -> Result<Option<FinishedRecording>, String> {
+    tauri::async_runtime::spawn_blocking(move || call_recording::stop(&app))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Make a participant the priority speaker, turning everyone else down by
+/// `duck_db` (at most 40) while they talk; null clears it
+/// Emits "ducking" with the gain for other participants whenever it changes
+#[tauri::command]
+pub async fn set_priority_speaker(
+    app: AppHandle,
+    user_id: Option<String>,
+    duck_db: f32,
+) -> Result<DuckingState, String> {
+    Ok(ducking::set_priority_speaker(&app, user_id, duck_db))
+}
+
+/// Report a participant starting or stopping speaking, from the call's
+/// speaking detection
+#[tauri::command]
+pub async fn set_participant_speaking(
+    app: AppHandle,
+    user_id: String,
+    speaking: bool,
+) -> Result<DuckingState, String> {
+    Ok(ducking::set_speaking(&app, user_id, speaking))
+}
+
+/// Get the priority speaker and whether others are being ducked
+#[tauri::command]
+pub async fn get_ducking(ducking: State<'_, Ducking>) -> Result<DuckingState, String> {
+    Ok(ducking.state())
+}
+
+/// List the voice channels that have their own devices or noise gate
+#[tauri::command]
+pub async fn get_channel_audio_overrides(
+    db: State<'_, Database>,
+) -> Result<Vec<ChannelOverride>, String> {
+    channel_overrides::overrides(&db).log_err()
+}
+
+/// Replace the per-channel devices and noise gates; a change to the joined
+/// channel's applies the next time it's joined
+#[tauri::command]
+pub async fn set_channel_audio_overrides(
+    db: State<'_, Database>,
+    overrides: Vec<ChannelOverride>,
+) -> Result<(), String> {
+    channel_overrides::set_overrides(&db, overrides).log_err()
+}
+
+/// Report joining a voice channel, switching to its devices and noise gate
+/// Emits "audio-devices" with what the call should use
+#[tauri::command]
+pub async fn join_voice_channel(
+    app: AppHandle,
+    instance_id: String,
+    channel_id: String,
+) -> Result<ChannelAudioState, String> {
+    startup::wait(&app, Subsystem::Audio).await.log_err()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        channel_overrides::join(&app, &instance_id, &channel_id)
+    })
+    .await
+    .log_err()?
+    .log_err()
+}
+
+/// Report leaving the voice channel, going back to the global devices
+/// Emits "audio-devices"
+#[tauri::command]
+pub async fn leave_voice_channel(app: AppHandle) -> Result<ChannelAudioState, String> {
+    channel_overrides::leave(&app).log_err()
+}
+
+/// Get the devices and noise gate in use for the joined channel, or the
+/// global ones outside a channel
+#[tauri::command]
+pub async fn get_channel_audio(app: AppHandle) -> Result<ChannelAudioState, String> {
+    channel_overrides::current(&app).log_err()
+}
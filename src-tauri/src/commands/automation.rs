@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use tauri::{AppHandle, State};
+
+use crate::automation::{self, Hook};
+use crate::db::Database;
+use crate::logging::LogErr;
+
+/// Get the automation hooks
+#[tauri::command]
+pub async fn get_automation_hooks(db: State<'_, Database>) -> Result<Vec<Hook>, String> {
+    automation::hooks(&db).log_err()
+}
+
+/// Replace the automation hooks; programs they run have to be allowed first
+#[tauri::command]
+pub async fn set_automation_hooks(db: State<'_, Database>, hooks: Vec<Hook>) -> Result<(), String> {
+    automation::set_hooks(&db, hooks).log_err()
+}
+
+/// Get the programs automation hooks may run
+#[tauri::command]
+pub async fn get_allowed_programs(db: State<'_, Database>) -> Result<Vec<PathBuf>, String> {
+    automation::allowed_programs(&db).log_err()
+}
+
+/// Let the user pick a program automation hooks may run in a native dialog
+/// Returns the program allowed, or null if the dialog is cancelled
+#[tauri::command]
+pub async fn add_allowed_program(app: AppHandle) -> Result<Option<PathBuf>, String> {
+    tauri::async_runtime::spawn_blocking(move || automation::allow_program(&app))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Stop allowing a program automation hooks could run
+#[tauri::command]
+pub async fn remove_allowed_program(
+    db: State<'_, Database>,
+    program: PathBuf,
+) -> Result<(), String> {
+    automation::disallow_program(&db, &program).log_err()
+}
+
+/// Run a hook once with placeholder values to try it out
+#[tauri::command]
+pub async fn test_automation_hook(app: AppHandle, hook: Hook) -> Result<(), String> {
+    automation::test(&app, hook).await.log_err()
+}
@@ -0,0 +1,34 @@
+use chrono::{DateTime, Utc};
+use tauri::State;
+
+use crate::cache::{self, CachedMessage, OutboxEntry};
+use crate::db::Database;
+use crate::logging::LogErr;
+
+/// List cached messages in a channel, newest first
+#[tauri::command]
+pub async fn list_cached_messages(
+    db: State<'_, Database>,
+    channel_id: String,
+    before: Option<DateTime<Utc>>,
+    limit: Option<u32>,
+) -> Result<Vec<CachedMessage>, String> {
+    db.with(|conn| cache::list_messages(conn, &channel_id, before, limit.unwrap_or(50)))
+        .log_err()
+}
+
+/// List messages waiting in the outbox for an instance
+#[tauri::command]
+pub async fn list_outbox(
+    db: State<'_, Database>,
+    instance_id: String,
+) -> Result<Vec<OutboxEntry>, String> {
+    db.with(|conn| cache::list_outbox(conn, &instance_id))
+        .log_err()
+}
+
+/// Remove outbox entries once they have been delivered
+#[tauri::command]
+pub async fn ack_outbox(db: State<'_, Database>, ids: Vec<i64>) -> Result<(), String> {
+    db.with(|conn| cache::remove_outbox(conn, &ids)).log_err()
+}
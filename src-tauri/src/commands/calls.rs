@@ -0,0 +1,57 @@
+use tauri::{AppHandle, State};
+
+use crate::calls::{self, CallState, Calls, IncomingCall};
+use crate::logging::LogErr;
+use crate::media_session::{self, CallSession};
+
+/// Start ringing for an incoming call: loops the ringtone and shows a
+/// notification with Answer and Decline until the call is handled or the
+/// "calls.ring_timeout_secs" setting runs out
+/// Emits "call-state" whenever the call's state changes
+#[tauri::command]
+pub async fn report_incoming_call(app: AppHandle, call: IncomingCall) -> Result<CallState, String> {
+    tauri::async_runtime::spawn_blocking(move || calls::incoming(&app, call))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Answer the ringing call
+#[tauri::command]
+pub async fn answer_call(app: AppHandle, call_id: String) -> Result<CallState, String> {
+    calls::answer(&app, &call_id).log_err()
+}
+
+/// Decline the ringing call
+#[tauri::command]
+pub async fn decline_call(app: AppHandle, call_id: String) -> Result<CallState, String> {
+    calls::decline(&app, &call_id).log_err()
+}
+
+/// Stop ringing because the caller hung up or the call was answered elsewhere
+#[tauri::command]
+pub async fn cancel_incoming_call(app: AppHandle, call_id: String) -> Result<CallState, String> {
+    calls::cancel(&app, &call_id).log_err()
+}
+
+/// Mark the answered call as over
+#[tauri::command]
+pub async fn end_call(app: AppHandle, call_id: String) -> Result<CallState, String> {
+    calls::end(&app, &call_id).log_err()
+}
+
+/// Whether a call is ringing or in progress
+#[tauri::command]
+pub async fn get_call_state(calls: State<'_, Calls>) -> Result<CallState, String> {
+    Ok(calls.state())
+}
+
+/// Show the call in the OS media controls, or take it out with `null`
+#[tauri::command]
+pub async fn set_call_media_session(
+    app: AppHandle,
+    session: Option<CallSession>,
+) -> Result<(), String> {
+    media_session::set(&app, session);
+    Ok(())
+}
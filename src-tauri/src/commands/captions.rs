@@ -0,0 +1,62 @@
+use tauri::{AppHandle, Manager, State};
+
+use crate::captions::{self, CaptionModel, Captions};
+use crate::db::Database;
+use crate::logging::LogErr;
+use crate::startup::{self, Subsystem};
+
+/// List the speech models live captions can use
+#[tauri::command]
+pub async fn list_caption_models(
+    app: AppHandle,
+    db: State<'_, Database>,
+) -> Result<Vec<CaptionModel>, String> {
+    captions::list_models(&app, &db).log_err()
+}
+
+/// Download a speech model for live captions
+/// Emits "caption-model-progress" as it arrives
+#[tauri::command]
+pub async fn download_caption_model(app: AppHandle, name: String) -> Result<(), String> {
+    captions::download_model(&app, &name).await.log_err()
+}
+
+/// Delete a downloaded speech model
+#[tauri::command]
+pub async fn remove_caption_model(app: AppHandle, name: String) -> Result<(), String> {
+    captions::remove_model(&app, &name).log_err()
+}
+
+/// Start transcribing the call locally with the "captions.model" model,
+/// and the microphone too if `local_user_id` is given
+/// Emits "caption" for each transcribed phrase
+#[tauri::command]
+pub async fn start_captions(app: AppHandle, local_user_id: Option<String>) -> Result<(), String> {
+    startup::wait(&app, Subsystem::Audio).await.log_err()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        captions::start(&app, &app.state::<Database>(), local_user_id)
+    })
+    .await
+    .log_err()?
+    .log_err()
+}
+
+/// Add a block of the call's mixed 48 kHz mono audio to be captioned
+#[tauri::command]
+pub async fn push_caption_audio(app: AppHandle, samples: Vec<f32>) -> Result<(), String> {
+    captions::push_call_audio(&app, samples).log_err()
+}
+
+/// Stop live captions
+#[tauri::command]
+pub async fn stop_captions(app: AppHandle) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || captions::stop(&app))
+        .await
+        .log_err()
+}
+
+/// Whether live captions are running
+#[tauri::command]
+pub async fn get_captions_running(captions: State<'_, Captions>) -> Result<bool, String> {
+    Ok(captions.is_running())
+}
@@ -0,0 +1,32 @@
+use tauri::{AppHandle, Manager, State};
+
+use crate::clipboard;
+use crate::db::Database;
+use crate::logging::LogErr;
+use crate::profiles::Profiles;
+use crate::uploads::ingest::{self, Ingested, Limits, Offered};
+
+/// Read copied files or a copied image from the OS clipboard and describe
+/// them for the composer, ready to pass to `queue_upload`
+/// Returns no attachments if the clipboard holds neither
+#[tauri::command]
+pub async fn read_clipboard_attachment(
+    app: AppHandle,
+    db: State<'_, Database>,
+    profiles: State<'_, Profiles>,
+    offered: State<'_, Offered>,
+) -> Result<Ingested, String> {
+    let cache_dir = app.path().app_cache_dir().log_err()?;
+    let limits = Limits::load(&db).log_err()?;
+
+    let paste_dir = cache_dir.join("pastes");
+    let paths = tauri::async_runtime::spawn_blocking(move || clipboard::read_files(&paste_dir))
+        .await
+        .log_err()?
+        .log_err()?;
+
+    let thumbnail_dir = profiles.active_cache_dir().join("thumbnails");
+    let ingested = ingest::ingest(paths, limits, thumbnail_dir).await;
+    offered.add_ingested(&ingested);
+    Ok(ingested)
+}
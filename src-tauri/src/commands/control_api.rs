@@ -0,0 +1,25 @@
+use tauri::AppHandle;
+
+use crate::control_api::{self, ControlApiInfo};
+use crate::logging::LogErr;
+
+/// Whether the local control API is on, its port and its token
+#[tauri::command]
+pub async fn get_control_api(app: AppHandle) -> Result<ControlApiInfo, String> {
+    control_api::info(&app).await.log_err()
+}
+
+/// Turn the local control API on or off
+#[tauri::command]
+pub async fn set_control_api_enabled(
+    app: AppHandle,
+    enabled: bool,
+) -> Result<ControlApiInfo, String> {
+    control_api::set_enabled(&app, enabled).await.log_err()
+}
+
+/// Make a new control API token; clients using the old one stop working
+#[tauri::command]
+pub async fn reset_control_api_token(app: AppHandle) -> Result<ControlApiInfo, String> {
+    control_api::reset_token(&app).await.log_err()
+}
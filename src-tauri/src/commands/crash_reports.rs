@@ -0,0 +1,41 @@
+use tauri::{AppHandle, State};
+
+use crate::crash_reports::{self, CrashReport};
+use crate::db::Database;
+use crate::logging::LogErr;
+
+/// Get whether the user has opted in to crash reporting
+#[tauri::command]
+pub async fn get_crash_reporting(db: State<'_, Database>) -> Result<bool, String> {
+    crash_reports::is_enabled(&db).log_err()
+}
+
+/// Opt in or out of crash reporting
+#[tauri::command]
+pub async fn set_crash_reporting(app: AppHandle, enabled: bool) -> Result<(), String> {
+    crash_reports::set_enabled(&app, enabled).log_err()
+}
+
+/// List crash reports kept on disk, newest first
+#[tauri::command]
+pub async fn list_crash_reports(app: AppHandle) -> Result<Vec<CrashReport>, String> {
+    crash_reports::list(&app).log_err()
+}
+
+/// Get a crash report with everything submitting it would send
+#[tauri::command]
+pub async fn get_crash_report(app: AppHandle, id: String) -> Result<CrashReport, String> {
+    crash_reports::get(&app, &id).log_err()
+}
+
+/// Send a crash report to the developers
+#[tauri::command]
+pub async fn submit_crash_report(app: AppHandle, id: String) -> Result<CrashReport, String> {
+    crash_reports::submit(&app, &id).await.log_err()
+}
+
+/// Delete a crash report without sending it
+#[tauri::command]
+pub async fn delete_crash_report(app: AppHandle, id: String) -> Result<(), String> {
+    crash_reports::delete(&app, &id).log_err()
+}
@@ -0,0 +1,39 @@
+use tauri::{AppHandle, State};
+
+use crate::data_usage::{self, Category, UsageRange, UsageReport};
+use crate::db::Database;
+use crate::logging::LogErr;
+
+/// Get bytes sent and received per day and category over a range of days
+#[tauri::command]
+pub async fn get_data_usage(app: AppHandle, range: UsageRange) -> Result<UsageReport, String> {
+    tauri::async_runtime::spawn_blocking(move || data_usage::report(&app, range))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Count traffic the webview moved itself, such as call media
+#[tauri::command]
+pub async fn record_data_usage(
+    app: AppHandle,
+    category: Category,
+    sent: u64,
+    received: u64,
+) -> Result<(), String> {
+    data_usage::record(&app, category, sent, received);
+    Ok(())
+}
+
+/// Get whether metered mode is on
+#[tauri::command]
+pub async fn get_metered_mode(db: State<'_, Database>) -> Result<bool, String> {
+    Ok(data_usage::is_metered(&db))
+}
+
+/// Turn metered mode on or off
+/// Emits "metered-changed"
+#[tauri::command]
+pub async fn set_metered_mode(app: AppHandle, metered: bool) -> Result<(), String> {
+    data_usage::set_metered(&app, metered).log_err()
+}
@@ -0,0 +1,14 @@
+use tauri::State;
+
+use crate::deep_link::{DeepLinks, ReceivedLink};
+use crate::streamer_mode::StreamerMode;
+
+/// Get the links the app was opened with before the frontend loaded
+/// Links opened after this call arrive as "deep-link" events
+#[tauri::command]
+pub async fn take_pending_deep_links(
+    deep_links: State<'_, DeepLinks>,
+    streamer_mode: State<'_, StreamerMode>,
+) -> Result<Vec<ReceivedLink>, String> {
+    Ok(deep_links.take_pending(streamer_mode.is_enabled()))
+}
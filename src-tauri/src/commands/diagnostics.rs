@@ -0,0 +1,35 @@
+use tauri::AppHandle;
+
+use crate::diagnostics::connectivity::{self, ConnectivityReport};
+use crate::diagnostics::self_test::{self, SelfTestReport};
+use crate::diagnostics::{self, DebugBundle};
+use crate::logging::LogErr;
+use crate::startup::{self, Subsystem};
+
+/// Collect logs, audio and connection details and system info into a zip
+/// in the downloads directory, for attaching to a bug report
+/// Tokens and message content are always removed; IP addresses too if
+/// `redact_ips` is set
+#[tauri::command]
+pub async fn generate_debug_bundle(
+    app: AppHandle,
+    redact_ips: bool,
+) -> Result<DebugBundle, String> {
+    diagnostics::generate(&app, redact_ips).await.log_err()
+}
+
+/// Check the microphone, playback, the Opus encoder, UDP and STUN, and each
+/// instance's gateway, and report how each went
+/// Plays a short, quiet test tone
+#[tauri::command]
+pub async fn run_diagnostics(app: AppHandle) -> Result<SelfTestReport, String> {
+    startup::wait(&app, Subsystem::Audio).await.log_err()?;
+    Ok(self_test::run(&app).await)
+}
+
+/// Look for an active VPN and try UDP, TCP and port 443 to the voice
+/// relays, with findings on what that means for calls
+#[tauri::command]
+pub async fn diagnose_connectivity(app: AppHandle) -> Result<ConnectivityReport, String> {
+    connectivity::diagnose(&app).await.log_err()
+}
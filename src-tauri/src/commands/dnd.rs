@@ -0,0 +1,26 @@
+use tauri::{AppHandle, State};
+
+use crate::db::Database;
+use crate::dnd::{self, DndSchedule, DndState};
+use crate::logging::LogErr;
+
+/// Get the do not disturb schedule
+#[tauri::command]
+pub async fn get_dnd_schedule(db: State<'_, Database>) -> Result<DndSchedule, String> {
+    dnd::schedule(&db).log_err()
+}
+
+/// Replace the do not disturb schedule; returns whether it's quiet now
+/// Emits "do-not-disturb" if that changed
+#[tauri::command]
+pub async fn set_dnd_schedule(app: AppHandle, schedule: DndSchedule) -> Result<DndState, String> {
+    dnd::set_schedule(&app, schedule).log_err()
+}
+
+/// Whether do not disturb is on right now
+#[tauri::command]
+pub async fn get_dnd_state(app: AppHandle) -> Result<DndState, String> {
+    Ok(DndState {
+        active: dnd::is_active(&app),
+    })
+}
@@ -0,0 +1,87 @@
+use tauri::{AppHandle, State};
+
+use crate::db::Database;
+use crate::downloads::{self, Download};
+use crate::instances;
+use crate::logging::LogErr;
+use crate::profiles::Profiles;
+
+/// Download an attachment into the download directory
+/// Emits "download-progress" while downloading
+#[tauri::command]
+pub async fn download_attachment(
+    app: AppHandle,
+    db: State<'_, Database>,
+    profiles: State<'_, Profiles>,
+    instance_id: String,
+    attachment_id: String,
+    filename: String,
+) -> Result<Download, String> {
+    let instance = db
+        .with(|conn| instances::get(conn, &instance_id))
+        .log_err()?
+        .ok_or_else(|| format!("unknown instance: {}", instance_id))?;
+
+    downloads::download(
+        &app,
+        &db,
+        &profiles.active().id,
+        &instance,
+        &attachment_id,
+        &filename,
+    )
+    .await
+    .log_err()
+}
+
+/// List downloaded attachments, newest first
+#[tauri::command]
+pub async fn list_downloads(db: State<'_, Database>) -> Result<Vec<Download>, String> {
+    db.with(|conn| downloads::list(conn)).log_err()
+}
+
+/// Open a download with the OS
+/// Fails for files flagged as unsafe until confirm_open_unsafe_file is called
+#[tauri::command]
+pub async fn open_download(
+    app: AppHandle,
+    db: State<'_, Database>,
+    id: String,
+) -> Result<Download, String> {
+    downloads::open(&app, &db, &id).log_err()
+}
+
+/// Ask the user in a native dialog whether a download flagged as unsafe
+/// should be opened anyway, and open it if they say yes
+#[tauri::command]
+pub async fn confirm_open_unsafe_file(
+    app: AppHandle,
+    db: State<'_, Database>,
+    id: String,
+) -> Result<Download, String> {
+    let db = db.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || downloads::confirm_and_open(&app, &db, &id))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Show a download in the system file manager
+#[tauri::command]
+pub async fn reveal_download(
+    app: AppHandle,
+    db: State<'_, Database>,
+    id: String,
+) -> Result<(), String> {
+    downloads::reveal(&app, &db, &id).log_err()
+}
+
+/// Remove a download from the list, optionally deleting the file
+#[tauri::command]
+pub async fn remove_download(
+    db: State<'_, Database>,
+    id: String,
+    delete_file: bool,
+) -> Result<(), String> {
+    downloads::remove(&db, &id, delete_file).log_err()
+}
@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use tauri::{AppHandle, State};
+
+use crate::drafts::{Draft, Drafts};
+use crate::logging::LogErr;
+use crate::uploads::ingest::Offered;
+
+/// Save a conversation's draft; call on every edit, writes are debounced
+/// Saving an empty draft removes it. Attachments that weren't dropped,
+/// picked, pasted, shared or recorded are left out
+#[tauri::command]
+pub async fn save_draft(
+    app: AppHandle,
+    drafts: State<'_, Drafts>,
+    offered: State<'_, Offered>,
+    instance_id: String,
+    channel_id: String,
+    content: String,
+    mut attachments: Vec<PathBuf>,
+) -> Result<(), String> {
+    attachments.retain(|path| offered.contains(path));
+    drafts.save(
+        &app,
+        Draft {
+            instance_id,
+            channel_id,
+            content,
+            attachments,
+            updated_at: chrono::Utc::now(),
+        },
+    );
+    Ok(())
+}
+
+/// Get a conversation's draft; its attachments can be queued again
+#[tauri::command]
+pub async fn get_draft(
+    drafts: State<'_, Drafts>,
+    offered: State<'_, Offered>,
+    instance_id: String,
+    channel_id: String,
+) -> Result<Option<Draft>, String> {
+    let draft = drafts.get(&instance_id, &channel_id).log_err()?;
+    if let Some(draft) = &draft {
+        offer(&offered, draft);
+    }
+    Ok(draft)
+}
+
+/// List drafts for an instance, most recently edited first; their
+/// attachments can be queued again
+#[tauri::command]
+pub async fn list_drafts(
+    drafts: State<'_, Drafts>,
+    offered: State<'_, Offered>,
+    instance_id: String,
+) -> Result<Vec<Draft>, String> {
+    let drafts = drafts.list(&instance_id).log_err()?;
+    for draft in &drafts {
+        offer(&offered, draft);
+    }
+    Ok(drafts)
+}
+
+/// Delete a conversation's draft, e.g. after sending it
+#[tauri::command]
+pub async fn clear_draft(
+    drafts: State<'_, Drafts>,
+    instance_id: String,
+    channel_id: String,
+) -> Result<(), String> {
+    drafts.clear(&instance_id, &channel_id).log_err()
+}
+
+/// Attachments were offered when the draft was saved, so they still are
+/// after a restart
+fn offer(offered: &Offered, draft: &Draft) {
+    for path in &draft.attachments {
+        offered.add(path);
+    }
+}
@@ -0,0 +1,55 @@
+use tauri::{AppHandle, State};
+
+use crate::gateway::Gateway;
+use crate::instances;
+use crate::logging::LogErr;
+use crate::profiles::Profiles;
+
+/// Open a backend gateway connection for an instance
+/// Events are emitted as "gateway-event" and connection changes as
+/// "gateway-status", both tagged with the profile and instance
+#[tauri::command]
+pub async fn connect_gateway(
+    app: AppHandle,
+    profiles: State<'_, Profiles>,
+    gateway: State<'_, Gateway>,
+    instance_id: String,
+    profile_id: Option<String>,
+) -> Result<(), String> {
+    let profile_id = profile_id.unwrap_or_else(|| profiles.active().id);
+    let db = profiles.open_database(&profile_id).log_err()?;
+
+    let instance = db
+        .with(|conn| instances::get(conn, &instance_id))
+        .log_err()?
+        .ok_or_else(|| format!("unknown instance: {}", instance_id))?;
+
+    gateway.connect(&app, &profile_id, instance);
+
+    Ok(())
+}
+
+/// Close a backend gateway connection
+#[tauri::command]
+pub async fn disconnect_gateway(
+    profiles: State<'_, Profiles>,
+    gateway: State<'_, Gateway>,
+    instance_id: String,
+    profile_id: Option<String>,
+) -> Result<(), String> {
+    let profile_id = profile_id.unwrap_or_else(|| profiles.active().id);
+    gateway.disconnect(&profile_id, &instance_id);
+
+    Ok(())
+}
+
+/// List instances with an open backend connection for a profile
+#[tauri::command]
+pub async fn list_gateway_connections(
+    profiles: State<'_, Profiles>,
+    gateway: State<'_, Gateway>,
+    profile_id: Option<String>,
+) -> Result<Vec<String>, String> {
+    let profile_id = profile_id.unwrap_or_else(|| profiles.active().id);
+    Ok(gateway.connected_instances(&profile_id))
+}
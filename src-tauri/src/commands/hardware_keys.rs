@@ -0,0 +1,40 @@
+use tauri::AppHandle;
+use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Shortcut, ShortcutState};
+
+use crate::actions::{self, Action};
+use crate::hardware_keys;
+use crate::logging::LogErr;
+
+/// Turn the mic mute key's LED on or off to match the app's mute state
+/// Returns false if the LED can't be set on this system
+#[tauri::command]
+pub async fn set_mic_mute_led(muted: bool) -> Result<bool, String> {
+    Ok(hardware_keys::set_led(muted))
+}
+
+/// Let the play/pause media key, such as a headset's button, toggle mute
+/// while in a call; it's given back to media players when turned off
+/// Emits "toggle-mute" when pressed
+#[tauri::command]
+pub async fn set_call_media_keys(app: AppHandle, enabled: bool) -> Result<(), String> {
+    let shortcut = Shortcut::new(None, Code::MediaPlayPause);
+    let shortcuts = app.global_shortcut();
+
+    if !enabled {
+        if shortcuts.is_registered(shortcut) {
+            shortcuts.unregister(shortcut).log_err()?;
+        }
+        return Ok(());
+    }
+    if shortcuts.is_registered(shortcut) {
+        return Ok(());
+    }
+
+    shortcuts
+        .on_shortcut(shortcut, move |app, _shortcut, event| {
+            if event.state() == ShortcutState::Pressed {
+                actions::dispatch(app, Action::ToggleMute);
+            }
+        })
+        .log_err()
+}
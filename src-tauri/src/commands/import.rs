@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use tauri::State;
+
+use crate::db::Database;
+use crate::importer::{self, ImportFormat, ImportReport, ImportTarget};
+use crate::logging::LogErr;
+
+/// Parse an export and report what would be imported, without writing anything
+/// The format is detected from the file contents when not given
+#[tauri::command]
+pub async fn preview_import(
+    db: State<'_, Database>,
+    path: PathBuf,
+    format: Option<ImportFormat>,
+    target: ImportTarget,
+) -> Result<ImportReport, String> {
+    run(db.inner().clone(), path, format, target, true).await
+}
+
+/// Import an export into the local cache or the outbox
+#[tauri::command]
+pub async fn run_import(
+    db: State<'_, Database>,
+    path: PathBuf,
+    format: Option<ImportFormat>,
+    target: ImportTarget,
+) -> Result<ImportReport, String> {
+    run(db.inner().clone(), path, format, target, false).await
+}
+
+async fn run(
+    db: Database,
+    path: PathBuf,
+    format: Option<ImportFormat>,
+    target: ImportTarget,
+    dry_run: bool,
+) -> Result<ImportReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        importer::import(&db, &path, format, &target, dry_run)
+    })
+    .await
+    .log_err()?
+    .log_err()
+}
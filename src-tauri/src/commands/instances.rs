@@ -0,0 +1,85 @@
+use tauri::State;
+
+use crate::db::Database;
+use crate::gateway::Gateway;
+use crate::instances::{self, Instance};
+use crate::logging::LogErr;
+use crate::profiles::Profiles;
+use crate::secrets::{self, Credentials};
+
+/// List instances registered in the active profile
+#[tauri::command]
+pub async fn list_instances(db: State<'_, Database>) -> Result<Vec<Instance>, String> {
+    db.with(|conn| instances::list(conn)).log_err()
+}
+
+/// Register (or update) an instance in the active profile
+#[tauri::command]
+pub async fn register_instance(db: State<'_, Database>, instance: Instance) -> Result<(), String> {
+    db.with(|conn| instances::upsert(conn, &instance)).log_err()
+}
+
+/// Remove an instance from the active profile, closing its connection and
+/// deleting its credentials
+#[tauri::command]
+pub async fn remove_instance(
+    db: State<'_, Database>,
+    profiles: State<'_, Profiles>,
+    gateway: State<'_, Gateway>,
+    id: String,
+) -> Result<(), String> {
+    let profile_id = profiles.active().id;
+    gateway.disconnect(&profile_id, &id);
+
+    db.with(|conn| instances::remove(conn, &id)).log_err()?;
+
+    tauri::async_runtime::spawn_blocking(move || secrets::delete(&profile_id, &id))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Store an instance's credentials in the keychain for the active profile
+#[tauri::command]
+pub async fn set_instance_credentials(
+    profiles: State<'_, Profiles>,
+    instance_id: String,
+    credentials: Credentials,
+) -> Result<(), String> {
+    let profile_id = profiles.active().id;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        secrets::store(&profile_id, &instance_id, &credentials)
+    })
+    .await
+    .log_err()?
+    .log_err()
+}
+
+/// Read an instance's credentials from the keychain for the active profile
+#[tauri::command]
+pub async fn get_instance_credentials(
+    profiles: State<'_, Profiles>,
+    instance_id: String,
+) -> Result<Option<Credentials>, String> {
+    let profile_id = profiles.active().id;
+
+    tauri::async_runtime::spawn_blocking(move || secrets::load(&profile_id, &instance_id))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Delete an instance's credentials from the keychain for the active profile
+#[tauri::command]
+pub async fn clear_instance_credentials(
+    profiles: State<'_, Profiles>,
+    instance_id: String,
+) -> Result<(), String> {
+    let profile_id = profiles.active().id;
+
+    tauri::async_runtime::spawn_blocking(move || secrets::delete(&profile_id, &instance_id))
+        .await
+        .log_err()?
+        .log_err()
+}
@@ -0,0 +1,68 @@
+use tauri::{AppHandle, State};
+
+use crate::db::Database;
+use crate::launcher::unread::{self, ChannelUnread, ConversationUnread};
+use crate::launcher::{self, Launcher, RecentConversation};
+use crate::logging::LogErr;
+use crate::native_theme;
+
+/// Update the mute state shown by the launcher menus' mute item and the
+/// tray icon
+#[tauri::command]
+pub async fn set_launcher_muted(
+    app: AppHandle,
+    launcher: State<'_, Launcher>,
+    muted: bool,
+) -> Result<(), String> {
+    launcher.set_muted(muted);
+    launcher::refresh(&app);
+    native_theme::refresh_tray(&app);
+    Ok(())
+}
+
+/// Replace the pinned and recent conversations listed in the dock menu and
+/// jump list, most recent first; the pinned ones are also in the tray menu
+/// and kept for the next start
+/// Emits "open-conversation" when one is chosen from the dock or tray menu;
+/// the jump list opens them as "deep-link" events
+#[tauri::command]
+pub async fn set_recent_conversations(
+    app: AppHandle,
+    db: State<'_, Database>,
+    launcher: State<'_, Launcher>,
+    conversations: Vec<RecentConversation>,
+) -> Result<(), String> {
+    launcher.set_conversations(&db, conversations).log_err()?;
+    launcher::refresh(&app);
+    Ok(())
+}
+
+/// Replace an instance's unread counts, as loaded from the server, for the
+/// launcher menus and badge
+#[tauri::command]
+pub async fn set_unread_counts(
+    app: AppHandle,
+    instance_id: String,
+    counts: Vec<ChannelUnread>,
+) -> Result<(), String> {
+    unread::set_counts(&app, &instance_id, counts);
+    Ok(())
+}
+
+/// Clear a conversation's unread count once it's been read
+#[tauri::command]
+pub async fn mark_conversation_read(
+    app: AppHandle,
+    instance_id: String,
+    channel_id: String,
+) -> Result<(), String> {
+    unread::mark_read(&app, &instance_id, &channel_id);
+    Ok(())
+}
+
+/// List the unread counts the backend knows of, including messages that
+/// arrived on its own gateway connections
+#[tauri::command]
+pub async fn get_unread_counts(app: AppHandle) -> Result<Vec<ConversationUnread>, String> {
+    Ok(unread::list(&app))
+}
@@ -0,0 +1,53 @@
+use tauri::{AppHandle, State};
+
+use crate::data_usage::{self, Category};
+use crate::db::Database;
+use crate::link_preview::{self, Fetcher, LinkPreview, LINK_PREVIEWS_SETTING, PROXY_SETTING};
+use crate::logging::LogErr;
+use crate::profiles::Profiles;
+use crate::settings;
+
+/// Get a preview of a linked page, fetched by the backend with local and
+/// private destinations refused
+/// Returns nothing if the page has no preview or the "privacy.link_previews"
+/// setting is turned off; cached previews are reused unless `refresh` is set
+/// In metered mode the preview's image isn't fetched
+#[tauri::command]
+pub async fn get_link_preview(
+    app: AppHandle,
+    db: State<'_, Database>,
+    profiles: State<'_, Profiles>,
+    url: String,
+    refresh: Option<bool>,
+) -> Result<Option<LinkPreview>, String> {
+    let setting = |key: &str| db.with(|conn| settings::get_value(conn, key)).log_err();
+
+    let enabled = setting(LINK_PREVIEWS_SETTING)?
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    if !enabled {
+        return Ok(None);
+    }
+
+    let thumbnail_dir = profiles.active_cache_dir().join("thumbnails");
+
+    if !refresh.unwrap_or(false) {
+        let cached = db.with(|conn| link_preview::cached(conn, &url)).log_err()?;
+        if let Some(preview) = cached {
+            return Ok(preview.map(|p| p.into_link_preview(&url, &thumbnail_dir)));
+        }
+    }
+
+    let proxy = setting(PROXY_SETTING)?.and_then(|v| v.as_str().map(str::to_string));
+    let mut fetcher = Fetcher::new(proxy.as_deref()).log_err()?;
+    if data_usage::is_metered(&db) {
+        fetcher = fetcher.without_images();
+    }
+    let preview = fetcher.fetch(&thumbnail_dir, &url).await;
+    data_usage::record(&app, Category::Media, 0, fetcher.received());
+    let preview = preview.log_err()?;
+
+    db.with(|conn| link_preview::store(conn, &url, preview.as_ref()))
+        .log_err()?;
+    Ok(preview.map(|p| p.into_link_preview(&url, &thumbnail_dir)))
+}
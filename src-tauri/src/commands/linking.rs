@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use base64::Engine;
+use serde::Deserialize;
+use tauri::State;
+
+use crate::db::Database;
+use crate::instances;
+use crate::linking::{self, LinkingPayload, LinkingQr};
+use crate::logging::LogErr;
+use crate::media;
+
+/// Largest image accepted for scanning
+const MAX_SCAN_BYTES: usize = 16 * 1024 * 1024;
+
+/// Image to look for a linking code in
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ScanSource {
+    /// A camera preview frame captured by the webview, as a `data:` URL
+    Frame { data_url: String },
+    /// An image file, e.g. a screenshot of the code
+    File { path: PathBuf },
+}
+
+/// Render a QR code for linking a new device to an instance
+#[tauri::command]
+pub async fn render_linking_qr(
+    db: State<'_, Database>,
+    instance_id: String,
+    code: String,
+    key: Option<String>,
+) -> Result<LinkingQr, String> {
+    let instance = db
+        .with(|conn| instances::get(conn, &instance_id))
+        .log_err()?
+        .ok_or_else(|| format!("unknown instance: {}", instance_id))?;
+
+    linking::render(&LinkingPayload {
+        instance_url: instance.url,
+        code,
+        key,
+    })
+    .log_err()
+}
+
+/// Look for a linking QR code in a camera frame or image file
+/// Returns nothing if no code could be read, so the next frame can be tried
+#[tauri::command]
+pub async fn scan_linking_qr(source: ScanSource) -> Result<Option<LinkingPayload>, String> {
+    let bytes = match source {
+        ScanSource::Frame { data_url } => {
+            let (_, data) = data_url
+                .split_once(";base64,")
+                .ok_or_else(|| "frame isn't a base64 data URL".to_string())?;
+            if data.len() / 4 * 3 > MAX_SCAN_BYTES {
+                return Err("frame is too large to scan".to_string());
+            }
+            base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .log_err()?
+        }
+        ScanSource::File { path } => {
+            let metadata = std::fs::metadata(&path).log_err()?;
+            if metadata.len() > MAX_SCAN_BYTES as u64 {
+                return Err(format!("{} is too large to scan", path.display()));
+            }
+            std::fs::read(&path).log_err()?
+        }
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let image = media::decode(&bytes).log_err()?;
+        linking::scan(&image).log_err()
+    })
+    .await
+    .log_err()?
+}
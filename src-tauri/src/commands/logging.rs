@@ -0,0 +1,27 @@
+use tauri::{AppHandle, State};
+
+use crate::logging::{self, LogErr, LogLevel, Logging};
+
+/// List the log levels set per target
+#[tauri::command]
+pub async fn get_log_levels(logging: State<'_, Logging>) -> Result<Vec<LogLevel>, String> {
+    Ok(logging.levels())
+}
+
+/// Set the log level for a target, or clear it with no level
+#[tauri::command]
+pub async fn set_log_level(
+    app: AppHandle,
+    target: String,
+    level: Option<String>,
+) -> Result<(), String> {
+    logging::set_level(&app, &target, level.as_deref()).log_err()
+}
+
+/// Start or stop streaming log events to the frontend
+/// Emits "log-event" for each event logged while streaming
+#[tauri::command]
+pub async fn set_log_streaming(logging: State<'_, Logging>, enabled: bool) -> Result<(), String> {
+    logging.set_streaming(enabled);
+    Ok(())
+}
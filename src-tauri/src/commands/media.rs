@@ -0,0 +1,135 @@
+use tauri::{AppHandle, State};
+
+use crate::api::ApiClient;
+use crate::auth;
+use crate::db::Database;
+use crate::instances;
+use crate::logging::LogErr;
+use crate::media::animation::{self, FrameStrip};
+use crate::media::thumbnail::{self, ImageSource, Thumbnail, ThumbnailSize};
+use crate::profiles::Profiles;
+
+/// Largest source image read for a thumbnail
+const MAX_SOURCE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Get a downscaled, upright copy of an image for the media grid or a preview
+#[tauri::command]
+pub async fn get_thumbnail(
+    app: AppHandle,
+    db: State<'_, Database>,
+    profiles: State<'_, Profiles>,
+    source: ImageSource,
+    size: Option<ThumbnailSize>,
+) -> Result<Thumbnail, String> {
+    let size = size.unwrap_or_default();
+    let cache_dir = profiles.active_cache_dir().join("thumbnails");
+    let key = thumbnail::cache_key(&source, &source_version(&source)?, size.as_str());
+
+    let dir = cache_dir.clone();
+    let cache_key = key.clone();
+    let hit = tauri::async_runtime::spawn_blocking(move || thumbnail::cached(&dir, &cache_key))
+        .await
+        .log_err()?
+        .log_err()?;
+    if let Some(hit) = hit {
+        return Ok(hit);
+    }
+
+    let bytes = read_source(&app, &db, &profiles, &source).await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        thumbnail::generate(&cache_dir, &key, &bytes, size)
+    })
+    .await
+    .log_err()?
+    .log_err()
+}
+
+/// Get the frames of an animated GIF, APNG or WebP as one image grid with
+/// frame delays, scaled so the longest edge is at most `edge` pixels
+#[tauri::command]
+pub async fn get_animation_frames(
+    app: AppHandle,
+    db: State<'_, Database>,
+    profiles: State<'_, Profiles>,
+    source: ImageSource,
+    edge: Option<u32>,
+) -> Result<FrameStrip, String> {
+    let edge = edge
+        .unwrap_or(animation::DEFAULT_EDGE)
+        .clamp(1, animation::MAX_EDGE);
+    let cache_dir = profiles.active_cache_dir().join("animations");
+    let key = thumbnail::cache_key(
+        &source,
+        &source_version(&source)?,
+        &format!("frames:{}", edge),
+    );
+
+    let dir = cache_dir.clone();
+    let cache_key = key.clone();
+    let hit = tauri::async_runtime::spawn_blocking(move || animation::cached(&dir, &cache_key))
+        .await
+        .log_err()?
+        .log_err()?;
+    if let Some(hit) = hit {
+        return Ok(hit);
+    }
+
+    let bytes = read_source(&app, &db, &profiles, &source).await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        animation::generate(&cache_dir, &key, &bytes, edge)
+    })
+    .await
+    .log_err()?
+    .log_err()
+}
+
+/// Attachments never change once uploaded; local files are keyed by their
+/// modification time so edits produce a new output
+fn source_version(source: &ImageSource) -> Result<String, String> {
+    match source {
+        ImageSource::File { path } => {
+            let metadata = std::fs::metadata(path).log_err()?;
+            if metadata.len() > MAX_SOURCE_BYTES {
+                return Err(format!("{} is too large to preview", path.display()));
+            }
+            Ok(thumbnail::file_version(&metadata))
+        }
+        ImageSource::Attachment { .. } => Ok(String::new()),
+    }
+}
+
+async fn read_source(
+    app: &AppHandle,
+    db: &Database,
+    profiles: &Profiles,
+    source: &ImageSource,
+) -> Result<Vec<u8>, String> {
+    match source {
+        ImageSource::File { path } => std::fs::read(path).log_err(),
+        ImageSource::Attachment {
+            instance_id,
+            attachment_id,
+        } => {
+            let instance = db
+                .with(|conn| instances::get(conn, instance_id))
+                .log_err()?
+                .ok_or_else(|| format!("unknown instance: {}", instance_id))?;
+            let token = auth::access_token(app, &profiles.active().id, &instance)
+                .await
+                .log_err()?;
+            let mut response = ApiClient::new(&instance.url)
+                .attachment(&token, attachment_id)
+                .await
+                .log_err()?;
+
+            let mut bytes = Vec::new();
+            while let Some(chunk) = response.chunk().await.log_err()? {
+                if (bytes.len() + chunk.len()) as u64 > MAX_SOURCE_BYTES {
+                    return Err("attachment is too large to preview".to_string());
+                }
+                bytes.extend_from_slice(&chunk);
+            }
+            Ok(bytes)
+        }
+    }
+}
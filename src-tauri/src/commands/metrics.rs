@@ -0,0 +1,12 @@
+use tauri::State;
+
+use crate::metrics::{ResourceMonitor, ResourceUsage};
+
+/// Get the latest sample of the app's CPU, memory, GPU and audio load
+/// `None` until the first sample has been taken, shortly after startup
+#[tauri::command]
+pub async fn get_resource_usage(
+    monitor: State<'_, ResourceMonitor>,
+) -> Result<Option<ResourceUsage>, String> {
+    Ok(monitor.latest())
+}
@@ -1,3 +1,95 @@
+pub mod accessibility;
+pub mod audio;
+pub mod automation;
+pub mod cache;
+pub mod calls;
+pub mod captions;
+pub mod clipboard;
+pub mod control_api;
+pub mod crash_reports;
+pub mod data_usage;
+pub mod deep_link;
+pub mod diagnostics;
+pub mod dnd;
+pub mod downloads;
+pub mod drafts;
+pub mod gateway;
+pub mod hardware_keys;
+pub mod import;
+pub mod instances;
+pub mod launcher;
+pub mod link_preview;
+pub mod linking;
+pub mod logging;
+pub mod media;
+pub mod metrics;
+pub mod native_theme;
+pub mod notifications;
+pub mod packs;
+pub mod performance;
+pub mod process_scan;
+pub mod profiles;
+pub mod relays;
+pub mod remote_control;
+pub mod retention;
+pub mod scheduled;
+pub mod screenshots;
+pub mod settings;
+pub mod share;
 pub mod shortcuts;
+pub mod spellcheck;
+pub mod startup;
+pub mod streamer_mode;
+pub mod temp_files;
+pub mod tts;
+pub mod updater;
+pub mod uploads;
+pub mod wake_lock;
 
+pub use accessibility::*;
+pub use audio::*;
+pub use automation::*;
+pub use cache::*;
+pub use calls::*;
+pub use captions::*;
+pub use clipboard::*;
+pub use control_api::*;
+pub use crash_reports::*;
+pub use data_usage::*;
+pub use deep_link::*;
+pub use diagnostics::*;
+pub use dnd::*;
+pub use downloads::*;
+pub use drafts::*;
+pub use gateway::*;
+pub use hardware_keys::*;
+pub use import::*;
+pub use instances::*;
+pub use launcher::*;
+pub use link_preview::*;
+pub use linking::*;
+pub use logging::*;
+pub use media::*;
+pub use metrics::*;
+pub use native_theme::*;
+pub use notifications::*;
+pub use packs::*;
+pub use performance::*;
+pub use process_scan::*;
+pub use profiles::*;
+pub use relays::*;
+pub use remote_control::*;
+pub use retention::*;
+pub use scheduled::*;
+pub use screenshots::*;
+pub use settings::*;
+pub use share::*;
 pub use shortcuts::*;
+pub use spellcheck::*;
+pub use startup::*;
+pub use streamer_mode::*;
+pub use temp_files::*;
+pub use tts::*;
+pub use updater::*;
+pub use uploads::*;
+pub use wake_lock::*;
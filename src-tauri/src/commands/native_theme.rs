@@ -0,0 +1,33 @@
+use tauri::AppHandle;
+
+use crate::logging::LogErr;
+use crate::native_theme::{self, ThemePayload, ThemeSummary};
+
+/// List the installed theme packs for the tray, ringtone and overlay, and
+/// why any can't be applied
+#[tauri::command]
+pub async fn list_native_themes(app: AppHandle) -> Result<Vec<ThemeSummary>, String> {
+    tauri::async_runtime::spawn_blocking(move || native_theme::list(&app))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Apply a theme pack, or the built-in look with `null`
+/// Emits "native-theme" once it's applied, and again whenever its files change
+#[tauri::command]
+pub async fn apply_native_theme(
+    app: AppHandle,
+    pack_id: Option<String>,
+) -> Result<ThemePayload, String> {
+    tauri::async_runtime::spawn_blocking(move || native_theme::apply(&app, pack_id.as_deref()))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Get the applied theme pack's overlay colors and sounds
+#[tauri::command]
+pub async fn get_native_theme(app: AppHandle) -> Result<ThemePayload, String> {
+    Ok(native_theme::current(&app))
+}
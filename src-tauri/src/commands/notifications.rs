@@ -0,0 +1,40 @@
+use tauri::{AppHandle, State};
+
+use crate::db::Database;
+use crate::logging::LogErr;
+use crate::notification_rules::{self, NotificationRules};
+use crate::notifications::{self, Notification, Shown};
+
+/// Show a message notification through the desktop's notification server
+/// Returns its id when shown natively, or whether the webview should show
+/// it instead or it was held back for do not disturb or by a rule
+/// Clicking it emits "deep-link" for its conversation; its buttons emit
+/// "notification-action"
+#[tauri::command]
+pub async fn show_notification(
+    app: AppHandle,
+    notification: Notification,
+) -> Result<Shown, String> {
+    notifications::show(&app, notification).await.log_err()
+}
+
+/// Remove a notification shown by `show_notification`
+#[tauri::command]
+pub async fn close_notification(app: AppHandle, id: u32) -> Result<(), String> {
+    notifications::close(&app, id).await.log_err()
+}
+
+/// Get the per-conversation notification rules and highlights
+#[tauri::command]
+pub async fn get_notification_rules(db: State<'_, Database>) -> Result<NotificationRules, String> {
+    notification_rules::rules(&db).log_err()
+}
+
+/// Replace the notification rules; fails if a highlight doesn't compile
+#[tauri::command]
+pub async fn set_notification_rules(
+    app: AppHandle,
+    rules: NotificationRules,
+) -> Result<(), String> {
+    notification_rules::set_rules(&app, rules).log_err()
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use tauri::State;
+
+use crate::db::Database;
+use crate::link_preview::PROXY_SETTING;
+use crate::logging::LogErr;
+use crate::packs::{self, Pack, PackItem, PackKind};
+use crate::profiles::Profiles;
+use crate::settings;
+
+const DEFAULT_SEARCH_LIMIT: u32 = 50;
+const MAX_SEARCH_LIMIT: u32 = 500;
+
+/// List installed emoji and sticker packs by name
+#[tauri::command]
+pub async fn list_packs(db: State<'_, Database>) -> Result<Vec<Pack>, String> {
+    db.with(|conn| packs::list(conn)).log_err()
+}
+
+/// Install a pack from an http(s) URL or a local file, replacing any
+/// installed pack with the same id
+/// When `sha256` is given the archive has to match it
+#[tauri::command]
+pub async fn install_pack(
+    db: State<'_, Database>,
+    profiles: State<'_, Profiles>,
+    source: String,
+    sha256: Option<String>,
+) -> Result<Pack, String> {
+    let proxy = db
+        .with(|conn| settings::get_value(conn, PROXY_SETTING))
+        .log_err()?
+        .and_then(|v| v.as_str().map(str::to_string));
+    packs::install(
+        &db,
+        &packs_dir(&profiles),
+        &source,
+        sha256.as_deref(),
+        proxy.as_deref(),
+    )
+    .await
+    .log_err()
+}
+
+/// Find emoji or stickers by name or keyword
+#[tauri::command]
+pub async fn search_pack_items(
+    db: State<'_, Database>,
+    profiles: State<'_, Profiles>,
+    query: String,
+    kind: Option<PackKind>,
+    limit: Option<u32>,
+) -> Result<Vec<PackItem>, String> {
+    let limit = limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+    let dir = packs_dir(&profiles);
+    db.with(|conn| packs::search(conn, &dir, &query, kind, limit))
+        .log_err()
+}
+
+/// Uninstall a pack and delete its images
+#[tauri::command]
+pub async fn remove_pack(
+    db: State<'_, Database>,
+    profiles: State<'_, Profiles>,
+    id: String,
+) -> Result<(), String> {
+    packs::remove(&db, &packs_dir(&profiles), &id).log_err()
+}
+
+fn packs_dir(profiles: &Profiles) -> PathBuf {
+    profiles.dir(&profiles.active().id).join("packs")
+}
@@ -0,0 +1,21 @@
+use tauri::AppHandle;
+
+use crate::logging::LogErr;
+use crate::performance::{self, PerformanceProfile, PerformanceState};
+
+/// Get the performance profile, the tier it's on and how call media should
+/// run on it
+#[tauri::command]
+pub async fn get_performance_profile(app: AppHandle) -> Result<PerformanceState, String> {
+    performance::state(&app).log_err()
+}
+
+/// Choose the performance profile: auto, low or high
+/// Emits "performance-profile"
+#[tauri::command]
+pub async fn set_performance_profile(
+    app: AppHandle,
+    profile: PerformanceProfile,
+) -> Result<PerformanceState, String> {
+    performance::set_profile(&app, profile).log_err()
+}
@@ -0,0 +1,12 @@
+use tauri::State;
+
+use crate::process_scan::{DetectedGame, RunningGames};
+
+/// List the games detected as running, oldest first
+/// Later changes arrive as "game-detected" and "game-stopped" events
+#[tauri::command]
+pub async fn list_running_games(
+    running: State<'_, RunningGames>,
+) -> Result<Vec<DetectedGame>, String> {
+    Ok(running.list())
+}
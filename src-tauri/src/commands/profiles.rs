@@ -0,0 +1,132 @@
+use tauri::{AppHandle, Emitter, State};
+
+use crate::data_usage;
+use crate::drafts::Drafts;
+use crate::gateway::Gateway;
+use crate::instances;
+use crate::launcher;
+use crate::logging::LogErr;
+use crate::profiles::{Profile, Profiles};
+use crate::secrets;
+
+/// List all profiles
+#[tauri::command]
+pub async fn list_profiles(profiles: State<'_, Profiles>) -> Result<Vec<Profile>, String> {
+    Ok(profiles.list())
+}
+
+/// Get the active profile
+#[tauri::command]
+pub async fn get_active_profile(profiles: State<'_, Profiles>) -> Result<Profile, String> {
+    Ok(profiles.active())
+}
+
+/// Create a new, empty profile
+#[tauri::command]
+pub async fn create_profile(
+    profiles: State<'_, Profiles>,
+    name: String,
+) -> Result<Profile, String> {
+    profiles.create(&name).log_err()
+}
+
+/// Rename a profile
+#[tauri::command]
+pub async fn rename_profile(
+    profiles: State<'_, Profiles>,
+    id: String,
+    name: String,
+) -> Result<Profile, String> {
+    profiles.rename(&id, &name).log_err()
+}
+
+/// Choose whether a profile keeps its gateway connections open while another profile is active
+#[tauri::command]
+pub async fn set_profile_stay_connected(
+    app: AppHandle,
+    profiles: State<'_, Profiles>,
+    gateway: State<'_, Gateway>,
+    id: String,
+    stay_connected: bool,
+) -> Result<Profile, String> {
+    let profile = profiles.set_stay_connected(&id, stay_connected).log_err()?;
+
+    // The active profile is connected through the webview
+    if profiles.active().id != id {
+        if stay_connected {
+            gateway.connect_profile(&app, &profiles, &id).log_err()?;
+        } else {
+            gateway.disconnect_profile(&id);
+        }
+    }
+
+    Ok(profile)
+}
+
+/// Delete a profile along with its data directory and keychain entries
+#[tauri::command]
+pub async fn delete_profile(
+    profiles: State<'_, Profiles>,
+    gateway: State<'_, Gateway>,
+    id: String,
+) -> Result<(), String> {
+    if profiles.active().id == id {
+        return Err("the active profile can't be deleted".to_string());
+    }
+
+    let db = profiles.open_database(&id).log_err()?;
+    let instances = db.with(|conn| instances::list(conn)).log_err()?;
+    drop(db);
+
+    gateway.disconnect_profile(&id);
+
+    let profile_id = id.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        instances
+            .iter()
+            .try_for_each(|instance| secrets::delete(&profile_id, &instance.id))
+    })
+    .await
+    .log_err()?
+    .log_err()?;
+
+    profiles.delete(&id).log_err()
+}
+
+/// Switch the active profile
+/// Emits "profile-switched" with the newly active profile
+#[tauri::command]
+pub async fn switch_profile(
+    app: AppHandle,
+    profiles: State<'_, Profiles>,
+    gateway: State<'_, Gateway>,
+    drafts: State<'_, Drafts>,
+    id: String,
+) -> Result<Profile, String> {
+    let previous = profiles.active();
+    if previous.id == id {
+        return Ok(previous);
+    }
+
+    // Buffered drafts and usage belong to the database that's about to be
+    // swapped out
+    drafts.flush().log_err()?;
+    data_usage::flush(&app).log_err()?;
+    let profile = profiles.switch(&id).log_err()?;
+
+    // The webview takes over the new profile's connections; the previous
+    // profile either moves to the background or goes offline
+    gateway.disconnect_profile(&profile.id);
+    if previous.stay_connected {
+        gateway
+            .connect_profile(&app, &profiles, &previous.id)
+            .log_err()?;
+    } else {
+        gateway.disconnect_profile(&previous.id);
+    }
+
+    launcher::profile_switched(&app);
+    let _ = app.emit("profile-switched", &profile);
+
+    Ok(profile)
+}
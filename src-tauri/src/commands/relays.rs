@@ -0,0 +1,40 @@
+use tauri::{AppHandle, State};
+
+use crate::db::Database;
+use crate::logging::LogErr;
+use crate::relays::{self, Relay, RelaySelection};
+
+/// List the configured voice relays
+#[tauri::command]
+pub async fn get_relays(db: State<'_, Database>) -> Result<Vec<Relay>, String> {
+    relays::relays(&db).log_err()
+}
+
+/// Replace the configured voice relays, forgetting the last probe
+#[tauri::command]
+pub async fn set_relays(app: AppHandle, relays: Vec<Relay>) -> Result<(), String> {
+    relays::set_relays(&app, relays).log_err()
+}
+
+/// Time a round trip to every relay over UDP and pick the best one
+/// Emits "relay-probe" for each relay as it finishes
+#[tauri::command]
+pub async fn probe_relays(app: AppHandle) -> Result<RelaySelection, String> {
+    relays::probe(&app).await.log_err()
+}
+
+/// Only pick relays in `region`, or in any region again with `null`
+/// Returns the last probe with its pick redone, if there's been one
+#[tauri::command]
+pub async fn pin_relay_region(
+    app: AppHandle,
+    region: Option<String>,
+) -> Result<Option<RelaySelection>, String> {
+    relays::pin_region(&app, region).log_err()
+}
+
+/// The last probe and the relay it picked
+#[tauri::command]
+pub async fn get_relay_selection(app: AppHandle) -> Result<Option<RelaySelection>, String> {
+    Ok(relays::last(&app))
+}
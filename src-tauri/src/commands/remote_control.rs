@@ -0,0 +1,77 @@
+use tauri::AppHandle;
+
+use crate::logging::LogErr;
+use crate::remote_control::{self, EndReason, RemoteControlState, RemoteInput};
+
+/// Report a peer's request to control the shared screen; the user has to
+/// grant it before anything is injected
+/// Emits "remote-control-state" and raises the window
+#[tauri::command]
+pub async fn request_remote_control(
+    app: AppHandle,
+    session_id: String,
+    peer: String,
+) -> Result<RemoteControlState, String> {
+    remote_control::request(&app, &session_id, &peer).log_err()
+}
+
+/// Allow or disallow remote assistance; allowing it asks the user to confirm
+/// in a native dialog. Returns whether it's now allowed
+#[tauri::command]
+pub async fn set_remote_control_enabled(app: AppHandle, enabled: bool) -> Result<bool, String> {
+    tauri::async_runtime::spawn_blocking(move || remote_control::set_enabled(&app, enabled))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Let the peer of a pending request control `monitor`, or the primary
+/// monitor, until the session is stopped, if the user says yes in a native
+/// dialog
+/// Emits "remote-control-state"
+#[tauri::command]
+pub async fn grant_remote_control(
+    app: AppHandle,
+    session_id: String,
+    monitor: Option<String>,
+) -> Result<RemoteControlState, String> {
+    tauri::async_runtime::spawn_blocking(move || remote_control::grant(&app, &session_id, monitor))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Turn down a pending request to control the shared screen
+/// Emits "remote-control-state"
+#[tauri::command]
+pub async fn deny_remote_control(app: AppHandle, session_id: String) -> Result<(), String> {
+    remote_control::deny(&app, &session_id).log_err()
+}
+
+/// Inject mouse and keyboard events from the peer of the active session
+/// Returns how many were injected
+#[tauri::command]
+pub async fn inject_remote_input(
+    app: AppHandle,
+    session_id: String,
+    events: Vec<RemoteInput>,
+) -> Result<usize, String> {
+    tauri::async_runtime::spawn_blocking(move || remote_control::inject(&app, &session_id, events))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// End remote control, or turn down a pending request
+/// Emits "remote-control-state"
+#[tauri::command]
+pub async fn stop_remote_control(app: AppHandle) -> Result<(), String> {
+    remote_control::stop(&app, EndReason::Stopped);
+    Ok(())
+}
+
+/// Get whether anyone is controlling the screen or asking to
+#[tauri::command]
+pub async fn get_remote_control_state(app: AppHandle) -> Result<RemoteControlState, String> {
+    Ok(remote_control::state(&app))
+}
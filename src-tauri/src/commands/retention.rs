@@ -0,0 +1,23 @@
+use tauri::AppHandle;
+
+use crate::logging::LogErr;
+use crate::retention::{self, RetentionReport};
+
+/// The active profile's disappearing-message policies, how many messages
+/// each conversation still has cached, and what the purges have removed
+#[tauri::command]
+pub async fn get_retention_report(app: AppHandle) -> Result<RetentionReport, String> {
+    retention::report(&app).log_err()
+}
+
+/// Keep a conversation's cached messages for at most `max_age_secs`, or
+/// keep them indefinitely again with `null`
+#[tauri::command]
+pub async fn set_retention_policy(
+    app: AppHandle,
+    instance_id: String,
+    channel_id: String,
+    max_age_secs: Option<u64>,
+) -> Result<(), String> {
+    retention::set_policy(&app, &instance_id, &channel_id, max_age_secs).log_err()
+}
@@ -0,0 +1,37 @@
+use chrono::{DateTime, Utc};
+use tauri::{AppHandle, State};
+
+use crate::db::Database;
+use crate::logging::LogErr;
+use crate::scheduled::{self, ScheduledMessage};
+
+/// Send a message to a conversation at `send_at`, from the backend so it
+/// goes out with the window closed or the app restarted in between
+/// Emits "scheduled-message-sent" once it's due
+#[tauri::command]
+pub async fn schedule_message(
+    app: AppHandle,
+    instance_id: String,
+    channel_id: String,
+    content: String,
+    send_at: DateTime<Utc>,
+) -> Result<ScheduledMessage, String> {
+    scheduled::schedule(&app, &instance_id, &channel_id, &content, send_at).log_err()
+}
+
+/// List messages waiting to be sent, soonest first, optionally for one
+/// instance
+#[tauri::command]
+pub async fn list_scheduled_messages(
+    db: State<'_, Database>,
+    instance_id: Option<String>,
+) -> Result<Vec<ScheduledMessage>, String> {
+    db.with(|conn| scheduled::list(conn, instance_id.as_deref()))
+        .log_err()
+}
+
+/// Cancel a scheduled message; returns false if it was already sent
+#[tauri::command]
+pub async fn cancel_scheduled_message(db: State<'_, Database>, id: String) -> Result<bool, String> {
+    db.with(|conn| scheduled::cancel(conn, &id)).log_err()
+}
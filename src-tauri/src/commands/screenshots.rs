@@ -0,0 +1,54 @@
+use tauri::AppHandle;
+
+use crate::logging::LogErr;
+use crate::screenshots::{self, FolderStatus, ScreenshotOffer};
+use crate::uploads::Upload;
+
+/// Get the folder watched for new screenshots, and a suggestion if none is
+#[tauri::command]
+pub async fn get_screenshot_folder(app: AppHandle) -> Result<FolderStatus, String> {
+    screenshots::status(&app).log_err()
+}
+
+/// Let the user pick a folder to watch for new screenshots in a native
+/// dialog; cancelling it leaves the folder as it was
+/// Emits "screenshot-offered" for each new one, and "screenshot-rejected"
+/// for ones too large to send
+#[tauri::command]
+pub async fn pick_screenshot_folder(app: AppHandle) -> Result<FolderStatus, String> {
+    tauri::async_runtime::spawn_blocking(move || screenshots::pick_folder(&app))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Stop watching for new screenshots
+#[tauri::command]
+pub async fn clear_screenshot_folder(app: AppHandle) -> Result<FolderStatus, String> {
+    screenshots::clear_folder(&app).log_err()
+}
+
+/// List screenshots offered and not yet sent or dismissed
+#[tauri::command]
+pub async fn list_screenshot_offers(app: AppHandle) -> Result<Vec<ScreenshotOffer>, String> {
+    Ok(screenshots::offers(&app))
+}
+
+/// Send an offered screenshot as an attachment to a message, with its
+/// metadata stripped
+/// Emits "upload-status" as the upload is prepared, sent and finished
+#[tauri::command]
+pub async fn send_screenshot(
+    app: AppHandle,
+    offer_id: String,
+    instance_id: String,
+    message_id: String,
+) -> Result<Upload, String> {
+    screenshots::send(&app, &offer_id, &instance_id, message_id).log_err()
+}
+
+/// Dismiss an offered screenshot without sending it
+#[tauri::command]
+pub async fn dismiss_screenshot(app: AppHandle, offer_id: String) -> Result<(), String> {
+    screenshots::dismiss(&app, &offer_id).log_err()
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use tauri::{AppHandle, Emitter, State};
+
+use crate::db::Database;
+use crate::logging::LogErr;
+use crate::settings::{self, bundle, bundle::BundleSummary};
+
+/// Read a setting, returning null when unset
+#[tauri::command]
+pub async fn get_setting(
+    db: State<'_, Database>,
+    key: String,
+) -> Result<Option<serde_json::Value>, String> {
+    db.with(|conn| settings::get_value(conn, &key)).log_err()
+}
+
+/// Read every setting whose key starts with `prefix`
+#[tauri::command]
+pub async fn list_settings(
+    db: State<'_, Database>,
+    prefix: Option<String>,
+) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    db.with(|conn| settings::list(conn, prefix.as_deref().unwrap_or("")))
+        .map(|settings| settings.into_iter().collect())
+        .log_err()
+}
+
+/// Write a setting, or remove it when `value` is null. Protected keys are
+/// refused
+#[tauri::command]
+pub async fn set_setting(
+    db: State<'_, Database>,
+    key: String,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    if settings::is_protected(&key) {
+        return Err(format!("{key} can't be changed from here"));
+    }
+
+    db.with(|conn| match value {
+        serde_json::Value::Null => settings::remove(conn, &key),
+        value => settings::set(conn, &key, &value),
+    })
+    .log_err()
+}
+
+/// Export settings, shortcut bindings, device preferences and notification
+/// rules to a passphrase-encrypted bundle
+#[tauri::command]
+pub async fn export_settings_bundle(
+    app: AppHandle,
+    db: State<'_, Database>,
+    path: PathBuf,
+    passphrase: String,
+) -> Result<BundleSummary, String> {
+    let db = db.inner().clone();
+    let version = app.package_info().version.to_string();
+
+    tauri::async_runtime::spawn_blocking(move || bundle::export(&db, &path, &passphrase, &version))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Restore settings from a bundle created by `export_settings_bundle`
+/// Emits "settings-changed" once the settings have been replaced
+#[tauri::command]
+pub async fn import_settings_bundle(
+    app: AppHandle,
+    db: State<'_, Database>,
+    path: PathBuf,
+    passphrase: String,
+) -> Result<BundleSummary, String> {
+    let db = db.inner().clone();
+
+    let summary =
+        tauri::async_runtime::spawn_blocking(move || bundle::import(&db, &path, &passphrase))
+            .await
+            .log_err()?
+            .log_err()?;
+
+    let _ = app.emit("settings-changed", ());
+
+    Ok(summary)
+}
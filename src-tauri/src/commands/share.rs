@@ -0,0 +1,11 @@
+use tauri::State;
+
+use crate::share::Shares;
+use crate::uploads::ingest::Ingested;
+
+/// Get the files sent to the app before the frontend loaded
+/// Files sent after this call arrive as "files-shared" events
+#[tauri::command]
+pub async fn take_pending_shares(shares: State<'_, Shares>) -> Result<Vec<Ingested>, String> {
+    Ok(shares.take_pending())
+}
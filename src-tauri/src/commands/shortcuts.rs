@@ -1,24 +1,24 @@
-use tauri::{AppHandle, Emitter};
+use tauri::AppHandle;
 use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
 
+use crate::actions::{self, Action};
+use crate::keyboard_layout::{self, BindingAction, Layout, ShortcutBinding};
+use crate::logging::LogErr;
+
 /// Register the Push-to-Talk shortcut
 /// Emits "ptt-pressed" when pressed and "ptt-released" when released
 #[tauri::command]
 pub async fn register_ptt_shortcut(app: AppHandle, shortcut: String) -> Result<(), String> {
-    let shortcut: Shortcut = shortcut.parse().map_err(|e| format!("{}", e))?;
+    let shortcut: Shortcut = normalized(&app, shortcut).await?.parse().log_err()?;
 
     app.global_shortcut()
         .on_shortcut(shortcut, move |app, _shortcut, event| {
             match event.state() {
-                ShortcutState::Pressed => {
-                    let _ = app.emit("ptt-pressed", ());
-                }
-                ShortcutState::Released => {
-                    let _ = app.emit("ptt-released", ());
-                }
+                ShortcutState::Pressed => actions::dispatch(app, Action::PttPressed),
+                ShortcutState::Released => actions::dispatch(app, Action::PttReleased),
             }
         })
-        .map_err(|e| format!("{}", e))?;
+        .log_err()?;
 
     Ok(())
 }
@@ -27,15 +27,15 @@ pub async fn register_ptt_shortcut(app: AppHandle, shortcut: String) -> Result<(
 /// Emits "toggle-mute" when pressed
 #[tauri::command]
 pub async fn register_mute_shortcut(app: AppHandle, shortcut: String) -> Result<(), String> {
-    let shortcut: Shortcut = shortcut.parse().map_err(|e| format!("{}", e))?;
+    let shortcut: Shortcut = normalized(&app, shortcut).await?.parse().log_err()?;
 
     app.global_shortcut()
         .on_shortcut(shortcut, move |app, _shortcut, event| {
             if event.state() == ShortcutState::Pressed {
-                let _ = app.emit("toggle-mute", ());
+                actions::dispatch(app, Action::ToggleMute);
             }
         })
-        .map_err(|e| format!("{}", e))?;
+        .log_err()?;
 
     Ok(())
 }
@@ -44,15 +44,15 @@ pub async fn register_mute_shortcut(app: AppHandle, shortcut: String) -> Result<
 /// Emits "toggle-deafen" when pressed
 #[tauri::command]
 pub async fn register_deafen_shortcut(app: AppHandle, shortcut: String) -> Result<(), String> {
-    let shortcut: Shortcut = shortcut.parse().map_err(|e| format!("{}", e))?;
+    let shortcut: Shortcut = normalized(&app, shortcut).await?.parse().log_err()?;
 
     app.global_shortcut()
         .on_shortcut(shortcut, move |app, _shortcut, event| {
             if event.state() == ShortcutState::Pressed {
-                let _ = app.emit("toggle-deafen", ());
+                actions::dispatch(app, Action::ToggleDeafen);
             }
         })
-        .map_err(|e| format!("{}", e))?;
+        .log_err()?;
 
     Ok(())
 }
@@ -60,11 +60,11 @@ pub async fn register_deafen_shortcut(app: AppHandle, shortcut: String) -> Resul
 /// Unregister a specific shortcut
 #[tauri::command]
 pub async fn unregister_shortcut(app: AppHandle, shortcut: String) -> Result<(), String> {
-    let shortcut: Shortcut = shortcut.parse().map_err(|e| format!("{}", e))?;
+    let shortcut: Shortcut = normalized(&app, shortcut).await?.parse().log_err()?;
 
     app.global_shortcut()
         .unregister(shortcut)
-        .map_err(|e| format!("{}", e))?;
+        .log_err()?;
 
     Ok(())
 }
@@ -74,7 +74,50 @@ pub async fn unregister_shortcut(app: AppHandle, shortcut: String) -> Result<(),
 pub async fn unregister_all_shortcuts(app: AppHandle) -> Result<(), String> {
     app.global_shortcut()
         .unregister_all()
-        .map_err(|e| format!("{}", e))?;
+        .log_err()?;
 
     Ok(())
 }
+
+/// Store a shortcut as physical keys, or clear it with `null`; registering
+/// it is still up to the `register_*_shortcut` commands
+#[tauri::command]
+pub async fn set_shortcut_binding(
+    app: AppHandle,
+    action: BindingAction,
+    shortcut: Option<String>,
+) -> Result<ShortcutBinding, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        keyboard_layout::set_binding(&app, action, shortcut.as_deref())
+    })
+    .await
+    .log_err()?
+    .log_err()
+}
+
+/// Get the stored shortcuts, labelled in the current keyboard layout
+/// "keyboard-layout-changed" carries them again when the layout changes
+#[tauri::command]
+pub async fn get_shortcut_bindings(app: AppHandle) -> Result<Vec<ShortcutBinding>, String> {
+    tauri::async_runtime::spawn_blocking(move || keyboard_layout::bindings(&app))
+        .await
+        .log_err()?
+        .log_err()
+}
+
+/// Get the current keyboard layout's key labels
+#[tauri::command]
+pub async fn get_keyboard_layout(app: AppHandle) -> Result<Layout, String> {
+    tauri::async_runtime::spawn_blocking(move || keyboard_layout::current(&app))
+        .await
+        .log_err()
+}
+
+/// A shortcut as typed, in physical keys; reading the layout can block
+async fn normalized(app: &AppHandle, shortcut: String) -> Result<String, String> {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || keyboard_layout::normalize(&app, &shortcut))
+        .await
+        .log_err()?
+        .log_err()
+}
@@ -0,0 +1,72 @@
+use tauri::{AppHandle, State};
+
+use crate::db::Database;
+use crate::logging::LogErr;
+use crate::spellcheck::{self, Dictionary};
+
+/// List the dictionaries that can be downloaded for the spellchecker
+#[tauri::command]
+pub async fn list_dictionaries(
+    app: AppHandle,
+    db: State<'_, Database>,
+) -> Result<Vec<Dictionary>, String> {
+    spellcheck::list(&app, &db).log_err()
+}
+
+/// Download a dictionary and install it for the spellchecker
+/// On macOS and Windows it's then enabled in the system's settings
+#[tauri::command]
+pub async fn download_dictionary(
+    app: AppHandle,
+    db: State<'_, Database>,
+    code: String,
+) -> Result<(), String> {
+    spellcheck::download(&app, &db, &code).await.log_err()
+}
+
+/// Delete a downloaded dictionary
+#[tauri::command]
+pub async fn remove_dictionary(
+    app: AppHandle,
+    db: State<'_, Database>,
+    code: String,
+) -> Result<(), String> {
+    spellcheck::remove(&app, &db, &code).log_err()
+}
+
+/// Choose the languages the spellchecker checks, by dictionary code
+/// Only Linux lets the app choose; elsewhere the system's settings apply
+#[tauri::command]
+pub async fn set_spellcheck_languages(
+    app: AppHandle,
+    db: State<'_, Database>,
+    languages: Vec<String>,
+) -> Result<(), String> {
+    spellcheck::set_languages(&app, &db, languages).log_err()
+}
+
+/// List the words the user added to the spellchecker
+#[tauri::command]
+pub async fn list_custom_words(db: State<'_, Database>) -> Result<Vec<String>, String> {
+    spellcheck::custom_words(&db).log_err()
+}
+
+/// Stop the spellchecker flagging a word in downloaded dictionaries
+#[tauri::command]
+pub async fn add_custom_word(
+    app: AppHandle,
+    db: State<'_, Database>,
+    word: String,
+) -> Result<(), String> {
+    spellcheck::add_custom_word(&app, &db, &word).log_err()
+}
+
+/// Remove a word added with `add_custom_word`
+#[tauri::command]
+pub async fn remove_custom_word(
+    app: AppHandle,
+    db: State<'_, Database>,
+    word: String,
+) -> Result<(), String> {
+    spellcheck::remove_custom_word(&app, &db, &word).log_err()
+}
@@ -0,0 +1,17 @@
+use tauri::AppHandle;
+
+use crate::logging::LogErr;
+use crate::startup::{self, Subsystem, SubsystemStatus};
+
+/// List the subsystems started after the first paint and which are up
+#[tauri::command]
+pub async fn get_startup_status(app: AppHandle) -> Result<Vec<SubsystemStatus>, String> {
+    Ok(startup::status(&app))
+}
+
+/// Wait for a subsystem to be up, for at most 30 seconds
+/// Each one also emits "subsystem-ready" as it comes up
+#[tauri::command]
+pub async fn await_subsystem(app: AppHandle, subsystem: Subsystem) -> Result<(), String> {
+    startup::wait(&app, subsystem).await.log_err()
+}
@@ -0,0 +1,19 @@
+use tauri::{AppHandle, State};
+
+use crate::streamer_mode::{self, StreamerMode, StreamerModeState};
+
+/// Get whether streamer mode is on
+#[tauri::command]
+pub async fn get_streamer_mode(
+    streamer_mode: State<'_, StreamerMode>,
+) -> Result<StreamerModeState, String> {
+    Ok(streamer_mode.state())
+}
+
+/// Turn streamer mode on or off
+/// Emits "streamer-mode-changed" when it changes
+#[tauri::command]
+pub async fn set_streamer_mode(app: AppHandle, enabled: bool) -> Result<(), String> {
+    streamer_mode::set(&app, enabled);
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use tauri::AppHandle;
+
+use crate::logging::LogErr;
+use crate::temp_files::{self, PurgeReport};
+
+/// Securely delete temporary files that aren't in use, including any left
+/// behind by a crash
+#[tauri::command]
+pub async fn purge_temp_files(app: AppHandle) -> Result<PurgeReport, String> {
+    tauri::async_runtime::spawn_blocking(move || temp_files::purge(&app))
+        .await
+        .log_err()?
+        .log_err()
+}
@@ -0,0 +1,50 @@
+use tauri::State;
+
+use crate::db::Database;
+use crate::logging::LogErr;
+use crate::tts::{self, Announcement, Tts, TtsRule};
+
+/// Speak text with the system voice, cutting off what's being spoken if
+/// `interrupt` is set
+#[tauri::command]
+pub async fn speak(tts: State<'_, Tts>, text: String, interrupt: bool) -> Result<(), String> {
+    tts.speak(&text, interrupt).log_err()
+}
+
+/// Stop speaking and drop anything queued
+#[tauri::command]
+pub async fn stop_speaking(tts: State<'_, Tts>) -> Result<(), String> {
+    tts.stop().log_err()
+}
+
+/// Speak a message or voice event if its channel's rule asks for it
+/// Returns whether anything was spoken
+#[tauri::command]
+pub async fn announce(
+    tts: State<'_, Tts>,
+    db: State<'_, Database>,
+    announcement: Announcement,
+) -> Result<bool, String> {
+    tts.announce(&db, &announcement).log_err()
+}
+
+/// Read what gets announced in a channel
+#[tauri::command]
+pub async fn get_tts_rule(
+    db: State<'_, Database>,
+    instance_id: String,
+    channel_id: String,
+) -> Result<TtsRule, String> {
+    tts::rule(&db, &instance_id, &channel_id).log_err()
+}
+
+/// Set what gets announced in a channel, or pass null to use the default
+#[tauri::command]
+pub async fn set_tts_rule(
+    db: State<'_, Database>,
+    instance_id: String,
+    channel_id: String,
+    rule: Option<TtsRule>,
+) -> Result<(), String> {
+    tts::set_rule(&db, &instance_id, &channel_id, rule).log_err()
+}
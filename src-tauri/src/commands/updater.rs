@@ -0,0 +1,30 @@
+use tauri::AppHandle;
+
+use crate::logging::LogErr;
+use crate::updater::{self, UpdateInfo};
+
+/// Look for an update on the chosen release channel
+/// Emits "update-available" when a new one is found
+#[tauri::command]
+pub async fn check_for_update(app: AppHandle) -> Result<Option<UpdateInfo>, String> {
+    updater::check(&app).await.log_err()
+}
+
+/// Download the available update, as a delta patch where possible
+/// Emits "update-progress" while downloading and "update-ready" when done
+#[tauri::command]
+pub async fn download_update(app: AppHandle) -> Result<(), String> {
+    updater::download(&app).await.log_err()
+}
+
+/// Install the downloaded update and restart
+#[tauri::command]
+pub async fn install_update_now(app: AppHandle) -> Result<(), String> {
+    updater::install_now(&app).log_err()
+}
+
+/// Install the downloaded update when the app quits, or stop doing so
+#[tauri::command]
+pub async fn install_update_on_quit(app: AppHandle, enabled: bool) -> Result<(), String> {
+    updater::set_install_on_quit(&app, enabled).log_err()
+}
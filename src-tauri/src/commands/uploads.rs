@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+
+use tauri::{AppHandle, State};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::db::Database;
+use crate::instances;
+use crate::logging::LogErr;
+use crate::performance;
+use crate::profiles::Profiles;
+use crate::settings;
+use crate::uploads::ingest::{self, Ingested, Offered};
+use crate::uploads::transcode::{
+    TranscodeOptions, CODEC_SETTING, FFMPEG_PATH_SETTING, QUALITY_SETTING,
+};
+use crate::uploads::{
+    TranscodePlan, Upload, UploadRequest, Uploads, MAX_ATTACHMENT_BYTES, STRIP_METADATA_SETTING,
+    TRANSCODE_LARGE_VIDEOS_SETTING,
+};
+
+/// Queue a file for upload as an attachment to a message
+/// Only files dropped, picked, pasted, shared or recorded can be queued
+/// Location and device metadata is removed unless `strip_metadata` is false
+/// or the "privacy.strip_metadata" setting is turned off
+/// Videos are transcoded if `transcode` is true, or when too large unless
+/// it's false or the "media.transcode_large_videos" setting is turned off
+/// Emits "upload-status" as the upload is prepared, sent and finished
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn queue_upload(
+    app: AppHandle,
+    db: State<'_, Database>,
+    profiles: State<'_, Profiles>,
+    uploads: State<'_, Uploads>,
+    offered: State<'_, Offered>,
+    instance_id: String,
+    message_id: String,
+    path: PathBuf,
+    order: Option<u32>,
+    strip_metadata: Option<bool>,
+    transcode: Option<bool>,
+) -> Result<Upload, String> {
+    if !offered.contains(&path) {
+        return Err(format!("{} wasn't attached", path.display()));
+    }
+
+    let instance = db
+        .with(|conn| instances::get(conn, &instance_id))
+        .log_err()?
+        .ok_or_else(|| format!("unknown instance: {}", instance_id))?;
+
+    let setting = |key: &str| db.with(|conn| settings::get_value(conn, key)).log_err();
+
+    let strip_metadata = match strip_metadata {
+        Some(strip_metadata) => strip_metadata,
+        None => setting(STRIP_METADATA_SETTING)?
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true),
+    };
+
+    // None: don't transcode; Some(always): transcode always, or only over the limit
+    let transcode = match transcode {
+        Some(true) => Some(true),
+        Some(false) => None,
+        None => setting(TRANSCODE_LARGE_VIDEOS_SETTING)?
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true)
+            .then_some(false),
+    };
+    let transcode = match transcode {
+        Some(always) => Some(TranscodePlan {
+            options: TranscodeOptions {
+                ffmpeg_path: setting(FFMPEG_PATH_SETTING)?
+                    .and_then(|v| serde_json::from_value(v).ok()),
+                codec: setting(CODEC_SETTING)?
+                    .and_then(|v| serde_json::from_value(v).ok())
+                    .unwrap_or_default(),
+                quality: setting(QUALITY_SETTING)?
+                    .and_then(|v| serde_json::from_value(v).ok())
+                    .unwrap_or_default(),
+                target_size: MAX_ATTACHMENT_BYTES,
+                strip_metadata,
+                fast: performance::is_low(),
+            },
+            always,
+        }),
+        None => None,
+    };
+
+    Ok(uploads.queue(
+        &app,
+        &profiles.active().id,
+        instance,
+        UploadRequest {
+            message_id,
+            path,
+            order,
+            strip_metadata,
+            transcode,
+        },
+    ))
+}
+
+/// List uploads that haven't finished yet
+#[tauri::command]
+pub async fn list_uploads(uploads: State<'_, Uploads>) -> Result<Vec<Upload>, String> {
+    Ok(uploads.list())
+}
+
+/// Cancel an upload that hasn't finished yet
+#[tauri::command]
+pub async fn cancel_upload(uploads: State<'_, Uploads>, id: String) -> Result<(), String> {
+    if uploads.cancel(&id) {
+        Ok(())
+    } else {
+        Err(format!("upload not found: {}", id))
+    }
+}
+
+/// Let the user pick files for a message in a native dialog and describe
+/// them for the composer, as is done for files dropped on the window
+/// Returns no attachments if the dialog is cancelled
+#[tauri::command]
+pub async fn pick_attachments(app: AppHandle) -> Result<Ingested, String> {
+    let dialog = app.clone();
+    let picked = tauri::async_runtime::spawn_blocking(move || {
+        dialog
+            .dialog()
+            .file()
+            .set_title("Attach files")
+            .blocking_pick_files()
+    })
+    .await
+    .log_err()?;
+
+    let paths = picked
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|path| path.into_path().ok())
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        return Ok(Ingested::default());
+    }
+
+    ingest::ingest_for(&app, paths)
+        .await
+        .ok_or_else(|| "failed to read upload limits".to_string())
+}
+
+/// Let the user pick the ffmpeg binary used for transcoding in a native
+/// dialog, returning the path saved or null if the dialog is cancelled
+#[tauri::command]
+pub async fn pick_ffmpeg_path(
+    app: AppHandle,
+    db: State<'_, Database>,
+) -> Result<Option<PathBuf>, String> {
+    let dialog = app.clone();
+    let picked = tauri::async_runtime::spawn_blocking(move || {
+        dialog
+            .dialog()
+            .file()
+            .set_title("Choose ffmpeg")
+            .blocking_pick_file()
+    })
+    .await
+    .log_err()?;
+
+    let Some(path) = picked.and_then(|path| path.into_path().ok()) else {
+        return Ok(None);
+    };
+    let path = std::fs::canonicalize(&path).log_err()?;
+    db.with(|conn| settings::set(conn, FFMPEG_PATH_SETTING, &path))
+        .log_err()?;
+    Ok(Some(path))
+}
+
+/// Go back to looking for ffmpeg on the PATH
+#[tauri::command]
+pub async fn clear_ffmpeg_path(db: State<'_, Database>) -> Result<(), String> {
+    db.with(|conn| settings::remove(conn, FFMPEG_PATH_SETTING))
+        .log_err()
+}
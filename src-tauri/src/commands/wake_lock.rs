@@ -0,0 +1,32 @@
+use tauri::State;
+
+use crate::logging::LogErr;
+use crate::wake_lock::{WakeLockInfo, WakeLocks};
+
+/// Keep the system from sleeping, e.g. while in a call, until the returned
+/// lock is released
+#[tauri::command]
+pub async fn acquire_wake_lock(
+    wake_locks: State<'_, WakeLocks>,
+    reason: String,
+) -> Result<WakeLockInfo, String> {
+    wake_locks.acquire(&reason).await.log_err()
+}
+
+/// Let the system sleep again once no other lock is held
+#[tauri::command]
+pub async fn release_wake_lock(wake_locks: State<'_, WakeLocks>, id: String) -> Result<(), String> {
+    if wake_locks.release(&id) {
+        Ok(())
+    } else {
+        Err(format!("wake lock not found: {}", id))
+    }
+}
+
+/// List the wake locks currently held
+#[tauri::command]
+pub async fn list_wake_locks(
+    wake_locks: State<'_, WakeLocks>,
+) -> Result<Vec<WakeLockInfo>, String> {
+    Ok(wake_locks.list())
+}
@@ -0,0 +1,418 @@
+//! Local control API
+//!
+//! An optional HTTP endpoint on the loopback interface for Stream Deck
+//! plugins and scripts. It's off unless [`CONTROL_API_SETTING`] is set, and
+//! every request needs the token kept in the keychain as
+//! `Authorization: Bearer <token>`. Requests from browsers (anything with
+//! an `Origin`) and for other host names are refused, so web pages can't
+//! reach it through DNS rebinding.
+//!
+//! Endpoints, all answering JSON:
+//! - `GET /v1/status`: mute as last reported by the frontend, the call
+//!   state and whether do not disturb is on
+//! - `POST /v1/mute`, `POST /v1/deafen`: toggle them
+//! - `POST /v1/ptt/press`, `POST /v1/ptt/release`
+//! - `POST /v1/join` with `{"instance": <url>, "channel_id": <id>}`, which
+//!   joins the call as a `redoubt://call` link would (see
+//!   [`crate::deep_link`])
+//!
+//! Mute, deafen and push-to-talk are the same [`crate::actions`] the
+//! shortcuts trigger.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use url::Url;
+
+use crate::actions::{self, Action};
+use crate::calls::Calls;
+use crate::db::Database;
+use crate::deep_link::{self, DeepLink};
+use crate::launcher::Launcher;
+use crate::{dnd, settings};
+
+/// Setting for whether the control API runs; off unless set to true
+pub const CONTROL_API_SETTING: &str = "control_api.enabled";
+
+/// Setting holding the port to listen on, [`DEFAULT_PORT`] if unset
+pub const CONTROL_API_PORT_SETTING: &str = "control_api.port";
+
+pub const DEFAULT_PORT: u16 = 48733;
+
+const KEYCHAIN_SERVICE: &str = "com.redoubt.desktop.control-api";
+
+/// Longest request head and body read
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+const MAX_BODY_BYTES: usize = 4 * 1024;
+
+/// How long a client has to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ControlApiError {
+    #[error("failed to listen on port {port}: {source}")]
+    Bind { port: u16, source: std::io::Error },
+    #[error("keychain error: {0}")]
+    Keyring(#[from] keyring::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("keychain task failed: {0}")]
+    Task(String),
+}
+
+/// What the settings page shows for the control API
+#[derive(Debug, Clone, Serialize)]
+pub struct ControlApiInfo {
+    pub enabled: bool,
+    pub port: u16,
+    /// Only while it's enabled
+    pub token: Option<String>,
+}
+
+#[derive(Default)]
+pub struct ControlApi {
+    server: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ControlApi {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<JoinHandle<()>>> {
+        self.server.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Start the API if it's enabled; call once during setup
+pub fn apply(app: &AppHandle) {
+    match enabled(&app.state::<Database>()) {
+        Ok(false) => {}
+        Ok(true) => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = start(&app).await {
+                    log::error!("Failed to start the control API: {}", e);
+                }
+            });
+        }
+        Err(e) => log::error!("Failed to read the control API setting: {}", e),
+    }
+}
+
+pub async fn info(app: &AppHandle) -> Result<ControlApiInfo, ControlApiError> {
+    let db = app.state::<Database>();
+    let enabled = enabled(&db)?;
+    Ok(ControlApiInfo {
+        enabled,
+        port: port(&db)?,
+        token: if enabled {
+            Some(token(false).await?)
+        } else {
+            None
+        },
+    })
+}
+
+/// Turn the API on or off and remember it
+pub async fn set_enabled(
+    app: &AppHandle,
+    enabled: bool,
+) -> Result<ControlApiInfo, ControlApiError> {
+    if enabled {
+        start(app).await?;
+    } else {
+        stop(app);
+    }
+    app.state::<Database>()
+        .with(|conn| settings::set(conn, CONTROL_API_SETTING, &enabled))?;
+    info(app).await
+}
+
+/// Replace the token, cutting off everything that has the old one
+pub async fn reset_token(app: &AppHandle) -> Result<ControlApiInfo, ControlApiError> {
+    token(true).await?;
+    if enabled(&app.state::<Database>())? {
+        start(app).await?;
+    }
+    info(app).await
+}
+
+fn enabled(db: &Database) -> rusqlite::Result<bool> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, CONTROL_API_SETTING))?
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false))
+}
+
+fn port(db: &Database) -> rusqlite::Result<u16> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, CONTROL_API_PORT_SETTING))?
+        .and_then(|v| v.as_u64())
+        .and_then(|port| u16::try_from(port).ok())
+        .filter(|port| *port != 0)
+        .unwrap_or(DEFAULT_PORT))
+}
+
+/// The token, made on first use or when `reset`
+async fn token(reset: bool) -> Result<String, ControlApiError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let entry = Entry::new(KEYCHAIN_SERVICE, "token")?;
+        if !reset {
+            match entry.get_password() {
+                Ok(token) => return Ok(token),
+                Err(keyring::Error::NoEntry) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let token = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        entry.set_password(&token)?;
+        Ok(token)
+    })
+    .await
+    .map_err(|e| ControlApiError::Task(e.to_string()))?
+}
+
+/// (Re)start listening with the current port and token
+async fn start(app: &AppHandle) -> Result<(), ControlApiError> {
+    // Let go of the port first in case it's the same one
+    stop(app);
+    let port = port(&app.state::<Database>())?;
+    let token = Arc::new(token(false).await?);
+    let listener = bind(port).await?;
+    log::info!("Control API listening on 127.0.0.1:{}", port);
+
+    let handle = app.clone();
+    let server = tauri::async_runtime::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log::warn!("Control API failed to accept a connection: {}", e);
+                    continue;
+                }
+            };
+            let app = handle.clone();
+            let token = token.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = serve(&app, stream, port, &token).await {
+                    log::debug!("Control API connection failed: {}", e);
+                }
+            });
+        }
+    });
+    if let Some(previous) = app.state::<ControlApi>().lock().replace(server) {
+        previous.abort();
+    }
+    Ok(())
+}
+
+async fn bind(port: u16) -> Result<TcpListener, ControlApiError> {
+    // A server that was just stopped can take a moment to let go of the port
+    let mut attempts = 0;
+    loop {
+        match TcpListener::bind(("127.0.0.1", port)).await {
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && attempts < 10 => {
+                attempts += 1;
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            result => return result.map_err(|source| ControlApiError::Bind { port, source }),
+        }
+    }
+}
+
+fn stop(app: &AppHandle) {
+    if let Some(server) = app.state::<ControlApi>().lock().take() {
+        server.abort();
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    /// Names in lowercase
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Deserialize)]
+struct JoinRequest {
+    instance: String,
+    channel_id: String,
+}
+
+async fn serve(
+    app: &AppHandle,
+    mut stream: TcpStream,
+    port: u16,
+    token: &str,
+) -> std::io::Result<()> {
+    let (status, body) = match tokio::time::timeout(READ_TIMEOUT, read(&mut stream)).await {
+        Ok(Ok(Some(request))) => respond(app, &request, port, token),
+        Ok(Ok(None)) => (400, json!({ "error": "malformed request" })),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => (408, json!({ "error": "timed out" })),
+    };
+    let body = body.to_string();
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        _ => "Error",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Read one request, or `None` if it isn't one this API accepts
+async fn read(stream: &mut TcpStream) -> std::io::Result<Option<Request>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let head_end = loop {
+        if let Some(at) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break at;
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Ok(None);
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+
+    let Ok(head) = std::str::from_utf8(&buf[..head_end]) else {
+        return Ok(None);
+    };
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(path)) = (request_line.next(), request_line.next()) else {
+        return Ok(None);
+    };
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect::<Vec<_>>();
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        headers,
+        body: buf[head_end + 4..].to_vec(),
+    };
+
+    let length = match request.header("content-length") {
+        Some(length) => match length.parse::<usize>() {
+            Ok(length) if length <= MAX_BODY_BYTES => length,
+            _ => return Ok(None),
+        },
+        None => 0,
+    };
+    while request.body.len() < length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        request.body.extend_from_slice(&chunk[..read]);
+    }
+    request.body.truncate(length);
+    Ok(Some(request))
+}
+
+fn respond(app: &AppHandle, request: &Request, port: u16, token: &str) -> (u16, Value) {
+    let host_allowed = request.header("host").is_some_and(|host| {
+        host == format!("127.0.0.1:{}", port) || host == format!("localhost:{}", port)
+    });
+    if !host_allowed || request.header("origin").is_some() {
+        log::warn!("Control API refused a request from a browser or another host");
+        return (403, json!({ "error": "forbidden" }));
+    }
+    let authorized = request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()));
+    if !authorized {
+        log::warn!("Control API refused a request without a valid token");
+        return (401, json!({ "error": "invalid token" }));
+    }
+
+    let ok = || (200, json!({ "ok": true }));
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/v1/status") => (
+            200,
+            json!({
+                "muted": app.state::<Launcher>().is_muted(),
+                "call": app.state::<Calls>().state(),
+                "do_not_disturb": dnd::is_active(app),
+            }),
+        ),
+        ("POST", "/v1/mute") => {
+            actions::dispatch(app, Action::ToggleMute);
+            ok()
+        }
+        ("POST", "/v1/deafen") => {
+            actions::dispatch(app, Action::ToggleDeafen);
+            ok()
+        }
+        ("POST", "/v1/ptt/press") => {
+            actions::dispatch(app, Action::PttPressed);
+            ok()
+        }
+        ("POST", "/v1/ptt/release") => {
+            actions::dispatch(app, Action::PttReleased);
+            ok()
+        }
+        ("POST", "/v1/join") => match join_url(&request.body) {
+            Ok(url) => {
+                deep_link::handle(app, vec![url]);
+                ok()
+            }
+            Err(e) => (400, json!({ "error": e })),
+        },
+        _ => (404, json!({ "error": "not found" })),
+    }
+}
+
+/// The `redoubt://call` link for a join request, checked the same way
+fn join_url(body: &[u8]) -> Result<Url, String> {
+    let join: JoinRequest = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    let mut url = Url::parse("redoubt://call/").expect("valid base link");
+    url.path_segments_mut()
+        .map_err(|_| "invalid link".to_string())?
+        .pop()
+        .push(&join.channel_id);
+    url.query_pairs_mut()
+        .append_pair("instance", &join.instance);
+    DeepLink::parse(&url).map_err(|e| e.to_string())?;
+    Ok(url)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
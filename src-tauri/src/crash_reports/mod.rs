@@ -0,0 +1,392 @@
+//! Crash reports
+//!
+//! Off unless the user opts in. When on, a crash handler is attached to the
+//! app's process and a monitor process (see [`monitor`]) writes a minidump
+//! when it crashes. Reports stay on disk under the app data directory until
+//! the user looks at them and chooses to submit or delete them; nothing is
+//! ever sent on its own.
+//!
+//! Besides the minidump, a report only records the app version, OS and
+//! architecture, and the panic message if a panic led to the crash, with
+//! the home directory and user name scrubbed from it. Minidumps hold the
+//! crashed threads' stacks, which can include whatever the app was working
+//! on, which is the other reason they're only sent when asked.
+
+pub mod monitor;
+
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::db::Database;
+use crate::settings;
+
+/// Setting that turns crash reporting on; off unless set to `true`
+pub const ENABLED_SETTING: &str = "privacy.crash_reports";
+
+/// Where submitted reports go, baked in at build time
+const SUBMIT_URL: Option<&str> = option_env!("REDOUBT_CRASH_REPORT_URL");
+
+/// Messages from the app to the monitor
+const MESSAGE_METADATA: u32 = 1;
+const MESSAGE_PANIC: u32 = 2;
+
+/// How long the monitor gets to start listening
+const CONNECT_ATTEMPTS: u32 = 20;
+const CONNECT_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, thiserror::Error)]
+pub enum CrashReportError {
+    #[error("crash reports can't be submitted from this build")]
+    SubmitDisabled,
+    #[error("no such crash report")]
+    NotFound,
+    #[error("crash monitor didn't start")]
+    MonitorUnavailable,
+    #[error("failed to attach crash handler: {0}")]
+    Handler(#[from] crash_handler::Error),
+    #[error("submitting crash report failed with status {0}")]
+    Status(u16),
+    #[error("submitting crash report failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("crash report is malformed: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("failed to read crash report: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// What's known about the crashed app, besides the minidump
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashMetadata {
+    pub app_version: String,
+    pub os: String,
+    pub os_version: Option<String>,
+    pub arch: String,
+    /// Scrubbed panic message, if a panic led to the crash
+    pub panic: Option<String>,
+}
+
+impl CrashMetadata {
+    fn current() -> Self {
+        Self {
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            os_version: sysinfo::System::long_os_version(),
+            arch: std::env::consts::ARCH.to_string(),
+            panic: None,
+        }
+    }
+}
+
+/// A report's record, stored as `<id>.json` next to `<id>.dmp`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Record {
+    crashed_at: DateTime<Utc>,
+    #[serde(flatten)]
+    metadata: CrashMetadata,
+    submitted_at: Option<DateTime<Utc>>,
+}
+
+impl Record {
+    fn load(path: &Path) -> Result<Self, CrashReportError> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), CrashReportError> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashReport {
+    pub id: String,
+    pub crashed_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub metadata: CrashMetadata,
+    pub submitted_at: Option<DateTime<Utc>>,
+    /// Size of the minidump in bytes
+    pub size: u64,
+    /// Where the minidump is, so the user can look at it before submitting
+    pub minidump_path: PathBuf,
+}
+
+/// Attached crash handler, if crash reporting is on
+#[derive(Default)]
+pub struct CrashReporter {
+    attached: Mutex<Option<Attached>>,
+}
+
+struct Attached {
+    _handler: crash_handler::CrashHandler,
+    client: Arc<minidumper::Client>,
+    monitor: Child,
+}
+
+impl CrashReporter {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Attached>> {
+        self.attached.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Whether the user has opted in
+pub fn is_enabled(db: &Database) -> rusqlite::Result<bool> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, ENABLED_SETTING))?
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false))
+}
+
+/// Attach the crash handler if the user has opted in; call once during setup
+pub fn apply(app: &AppHandle) {
+    match is_enabled(&app.state::<Database>()) {
+        Ok(true) => {
+            if let Err(e) = start(app) {
+                log::error!("Failed to start crash reporting: {}", e);
+            }
+        }
+        Ok(false) => {}
+        Err(e) => log::error!("Failed to read crash reporting setting: {}", e),
+    }
+}
+
+/// Opt in or out of crash reporting, taking effect straight away
+pub fn set_enabled(app: &AppHandle, enabled: bool) -> Result<(), CrashReportError> {
+    app.state::<Database>()
+        .with(|conn| settings::set(conn, ENABLED_SETTING, &enabled))?;
+    if enabled {
+        start(app)
+    } else {
+        stop(app);
+        Ok(())
+    }
+}
+
+/// Reports on disk, newest first
+pub fn list(app: &AppHandle) -> Result<Vec<CrashReport>, CrashReportError> {
+    let dir = reports_dir(app)?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut reports = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("dmp") {
+            continue;
+        }
+        let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        match load(&dir, id) {
+            Ok(report) => reports.push(report),
+            Err(e) => log::warn!("Skipping crash report {}: {}", id, e),
+        }
+    }
+    reports.sort_by_key(|r| std::cmp::Reverse(r.crashed_at));
+    Ok(reports)
+}
+
+/// A single report
+pub fn get(app: &AppHandle, id: &str) -> Result<CrashReport, CrashReportError> {
+    load(&reports_dir(app)?, id)
+}
+
+/// Send a report to the crash collector and note that it was sent
+pub async fn submit(app: &AppHandle, id: &str) -> Result<CrashReport, CrashReportError> {
+    let url = SUBMIT_URL.ok_or(CrashReportError::SubmitDisabled)?;
+    let dir = reports_dir(app)?;
+    let report = load(&dir, id)?;
+    let minidump = tokio::fs::read(&report.minidump_path).await?;
+
+    let metadata = &report.metadata;
+    let mut form = reqwest::multipart::Form::new()
+        .text("app_version", metadata.app_version.clone())
+        .text("os", metadata.os.clone())
+        .text("arch", metadata.arch.clone())
+        .text("crashed_at", report.crashed_at.to_rfc3339())
+        .part(
+            "upload_file_minidump",
+            reqwest::multipart::Part::bytes(minidump).file_name(format!("{}.dmp", report.id)),
+        );
+    if let Some(os_version) = &metadata.os_version {
+        form = form.text("os_version", os_version.clone());
+    }
+    if let Some(panic) = &metadata.panic {
+        form = form.text("panic", panic.clone());
+    }
+
+    let response = reqwest::Client::new()
+        .post(url)
+        .multipart(form)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(CrashReportError::Status(response.status().as_u16()));
+    }
+
+    let record_path = dir.join(format!("{}.json", report.id));
+    let mut record = Record::load(&record_path)?;
+    record.submitted_at = Some(Utc::now());
+    record.save(&record_path)?;
+    load(&dir, id)
+}
+
+/// Delete a report from disk
+pub fn delete(app: &AppHandle, id: &str) -> Result<(), CrashReportError> {
+    let report = get(app, id)?;
+    std::fs::remove_file(&report.minidump_path)?;
+    std::fs::remove_file(report.minidump_path.with_extension("json"))?;
+    Ok(())
+}
+
+fn start(app: &AppHandle) -> Result<(), CrashReportError> {
+    let reporter = app.state::<CrashReporter>();
+    let mut attached = reporter.lock();
+    if attached.is_some() {
+        return Ok(());
+    }
+
+    let dir = reports_dir(app)?;
+    let socket = format!("redoubt-crash-{}", std::process::id());
+    let mut monitor = std::process::Command::new(std::env::current_exe()?)
+        .arg(monitor::MONITOR_ARG)
+        .arg(&socket)
+        .arg(&dir)
+        .spawn()?;
+
+    let client = match connect(&socket) {
+        Some(client) => Arc::new(client),
+        None => {
+            let _ = monitor.kill();
+            let _ = monitor.wait();
+            return Err(CrashReportError::MonitorUnavailable);
+        }
+    };
+    let metadata = serde_json::to_vec(&CrashMetadata::current())?;
+    if let Err(e) = client.send_message(MESSAGE_METADATA, metadata) {
+        log::warn!("Failed to send crash metadata: {}", e);
+    }
+
+    let crashed = client.clone();
+    // SAFETY: the closure runs in a crash context, so it only talks to the
+    // already connected monitor and doesn't allocate or take locks
+    let handler = crash_handler::CrashHandler::attach(unsafe {
+        crash_handler::make_crash_event(move |context: &crash_handler::CrashContext| {
+            // Messages and dump requests go different ways on macOS; make
+            // sure the panic message arrives first
+            let _ = crashed.ping();
+            crash_handler::CrashEventResult::Handled(crashed.request_dump(context).is_ok())
+        })
+    })?;
+    // Yama restricts ptrace to parents, and the monitor is a child
+    #[cfg(target_os = "linux")]
+    handler.set_ptracer(Some(monitor.id()));
+
+    install_panic_hook(app);
+    *attached = Some(Attached {
+        _handler: handler,
+        client,
+        monitor,
+    });
+    Ok(())
+}
+
+fn stop(app: &AppHandle) {
+    let Some(mut attached) = app.state::<CrashReporter>().lock().take() else {
+        return;
+    };
+    drop(attached.client);
+    drop(attached._handler);
+    let _ = attached.monitor.kill();
+    let _ = attached.monitor.wait();
+}
+
+fn connect(socket: &str) -> Option<minidumper::Client> {
+    for _ in 0..CONNECT_ATTEMPTS {
+        if let Ok(client) = minidumper::Client::with_name(socket) {
+            return Some(client);
+        }
+        std::thread::sleep(CONNECT_INTERVAL);
+    }
+    None
+}
+
+/// Pass panic messages on to the monitor, in case the panic ends in a crash
+fn install_panic_hook(app: &AppHandle) {
+    static INSTALLED: Once = Once::new();
+    let app = app.clone();
+    INSTALLED.call_once(move || {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            // The panic may have happened while the state was locked
+            if let Ok(attached) = app.state::<CrashReporter>().attached.try_lock() {
+                if let Some(attached) = attached.as_ref() {
+                    let _ = attached
+                        .client
+                        .send_message(MESSAGE_PANIC, scrub(&info.to_string()));
+                }
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// Remove the home directory and user name, which show up in paths
+fn scrub(message: &str) -> String {
+    let mut scrubbed = message.to_string();
+    let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+    if let Ok(home) = home.as_deref() {
+        if !home.is_empty() {
+            scrubbed = scrubbed.replace(home, "~");
+        }
+    }
+    let user = std::env::var("USER").or_else(|_| std::env::var("USERNAME"));
+    if let Ok(user) = user.as_deref() {
+        // Short names would take out unrelated parts of the message
+        if user.len() >= 3 {
+            scrubbed = scrubbed.replace(user, "<user>");
+        }
+    }
+    scrubbed
+}
+
+fn load(dir: &Path, id: &str) -> Result<CrashReport, CrashReportError> {
+    // Only ids made by the monitor, so they can't point outside `dir`
+    let id = uuid::Uuid::parse_str(id)
+        .map_err(|_| CrashReportError::NotFound)?
+        .to_string();
+    let minidump_path = dir.join(format!("{}.dmp", id));
+    let size = match std::fs::metadata(&minidump_path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(CrashReportError::NotFound)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let record = Record::load(&minidump_path.with_extension("json"))?;
+    Ok(CrashReport {
+        id,
+        crashed_at: record.crashed_at,
+        metadata: record.metadata,
+        submitted_at: record.submitted_at,
+        size,
+        minidump_path,
+    })
+}
+
+fn reports_dir(app: &AppHandle) -> Result<PathBuf, CrashReportError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join("crashes"))
+        .map_err(|e| CrashReportError::Io(std::io::Error::other(e)))
+}
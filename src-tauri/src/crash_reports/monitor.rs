@@ -0,0 +1,113 @@
+//! Crash monitor process
+//!
+//! A crashed process can't be trusted to write its own minidump, so the app
+//! starts itself again as a monitor that waits on a socket. When the app
+//! crashes, its crash handler asks the monitor to write the dump and the
+//! monitor saves it next to a record of the metadata the app sent earlier.
+//! The monitor exits once the app disconnects, crashed or not.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+
+use super::{CrashMetadata, Record, MESSAGE_METADATA, MESSAGE_PANIC};
+
+/// Argument that starts the app as a crash monitor, followed by the socket
+/// name and the directory reports are written to
+pub const MONITOR_ARG: &str = "--crash-monitor";
+
+/// Run as the crash monitor if the arguments ask for it; returns whether
+/// the process was a monitor and should exit
+pub fn run_if_requested() -> bool {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() != Some(MONITOR_ARG) {
+        return false;
+    }
+    let (Some(socket), Some(dir)) = (args.next(), args.next()) else {
+        eprintln!("{} needs a socket name and a directory", MONITOR_ARG);
+        return true;
+    };
+    if let Err(e) = run(&socket, Path::new(&dir)) {
+        eprintln!("Crash monitor failed: {}", e);
+    }
+    true
+}
+
+fn run(socket: &str, dir: &Path) -> Result<(), minidumper::Error> {
+    let mut server = minidumper::Server::with_name(socket)?;
+    let handler = Handler {
+        dir: dir.to_path_buf(),
+        state: Mutex::default(),
+    };
+    server.run(Box::new(handler), &AtomicBool::new(false), None)
+}
+
+struct Handler {
+    dir: PathBuf,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    metadata: Option<CrashMetadata>,
+    panic: Option<String>,
+}
+
+impl Handler {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl minidumper::ServerHandler for Handler {
+    fn create_minidump_file(&self) -> Result<(File, PathBuf), std::io::Error> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}.dmp", uuid::Uuid::new_v4()));
+        Ok((File::create(&path)?, path))
+    }
+
+    fn on_minidump_created(
+        &self,
+        result: Result<minidumper::MinidumpBinary, minidumper::Error>,
+    ) -> minidumper::LoopAction {
+        let binary = match result {
+            Ok(binary) => binary,
+            Err(e) => {
+                eprintln!("Failed to write minidump: {}", e);
+                return minidumper::LoopAction::Exit;
+            }
+        };
+
+        let state = std::mem::take(&mut *self.lock());
+        let mut metadata = state.metadata.unwrap_or_else(CrashMetadata::current);
+        metadata.panic = state.panic;
+        let record = Record {
+            crashed_at: chrono::Utc::now(),
+            metadata,
+            submitted_at: None,
+        };
+        if let Err(e) = record.save(&binary.path.with_extension("json")) {
+            eprintln!("Failed to write crash record: {}", e);
+        }
+        // The app is gone; nothing more will arrive
+        minidumper::LoopAction::Exit
+    }
+
+    fn on_message(&self, kind: u32, buffer: Vec<u8>) {
+        let mut state = self.lock();
+        match kind {
+            MESSAGE_METADATA => state.metadata = serde_json::from_slice(&buffer).ok(),
+            MESSAGE_PANIC => state.panic = String::from_utf8(buffer).ok(),
+            _ => {}
+        }
+    }
+
+    fn on_client_disconnected(&self, num_clients: usize) -> minidumper::LoopAction {
+        if num_clients == 0 {
+            minidumper::LoopAction::Exit
+        } else {
+            minidumper::LoopAction::Continue
+        }
+    }
+}
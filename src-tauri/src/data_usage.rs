@@ -0,0 +1,333 @@
+//! Data usage
+//!
+//! Bytes sent and received are tallied per [`Category`] by the code that
+//! moves them: gateway connections count their WebSocket frames,
+//! attachment downloads and uploads and link previews count as media, and
+//! update packages, patches, dictionaries and caption models as updates.
+//! Call media runs in the webview, so its transport reports voice traffic
+//! itself with `record_data_usage`. What's counted is bodies and frame
+//! payloads, without headers or TLS, so totals run a little low.
+//!
+//! Tallies are kept per profile and local day, buffered in memory and
+//! added to the database of the profile the traffic was for (the active
+//! one, for traffic that isn't any profile's own) every [`FLUSH_INTERVAL`],
+//! on exit, before a profile switch and before they're read, so counting a
+//! chunk never touches the disk.
+//!
+//! With [`METERED_SETTING`] on, nothing is downloaded that the user didn't
+//! ask for: link previews come without their image and the background
+//! update check doesn't download what it finds. The frontend reads it
+//! with `get_metered_mode` to hold back inline attachments.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{Local, NaiveDate};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::db::Database;
+use crate::profiles::{ProfileError, Profiles};
+use crate::settings;
+
+/// Setting that holds off on downloads the user didn't ask for; off unless
+/// set to `true`
+pub const METERED_SETTING: &str = "network.metered";
+
+/// How often buffered tallies are written
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest range `get_data_usage` covers
+pub const MAX_RANGE_DAYS: i64 = 366;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DataUsageError {
+    #[error("the range has to start before it ends and cover at most {MAX_RANGE_DAYS} days")]
+    InvalidRange,
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("{0}")]
+    Profile(#[from] ProfileError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Voice,
+    Media,
+    Gateway,
+    Updates,
+}
+
+impl Category {
+    fn as_str(self) -> &'static str {
+        match self {
+            Category::Voice => "voice",
+            Category::Media => "media",
+            Category::Gateway => "gateway",
+            Category::Updates => "updates",
+        }
+    }
+
+    fn parse(category: &str) -> Option<Self> {
+        match category {
+            "voice" => Some(Category::Voice),
+            "media" => Some(Category::Media),
+            "gateway" => Some(Category::Gateway),
+            "updates" => Some(Category::Updates),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Tally {
+    pub sent: u64,
+    pub received: u64,
+}
+
+impl Tally {
+    fn add(&mut self, other: Tally) {
+        self.sent += other.sent;
+        self.received += other.received;
+    }
+}
+
+/// Days to report on, both included
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct UsageRange {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+/// One category's traffic on one day
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyUsage {
+    pub day: NaiveDate,
+    pub category: Category,
+    pub sent: u64,
+    pub received: u64,
+}
+
+/// Result of `get_data_usage`
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// Oldest first; days and categories without traffic are left out
+    pub days: Vec<DailyUsage>,
+    /// Each category over the whole range
+    pub totals: BTreeMap<Category, Tally>,
+}
+
+type Tallies = HashMap<(NaiveDate, Category), Tally>;
+
+/// Tallies not written yet, by profile id
+#[derive(Default)]
+pub struct DataUsage {
+    pending: Mutex<HashMap<String, Tallies>>,
+}
+
+impl DataUsage {
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Tallies>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Count traffic against today for the active profile; traffic that
+/// belongs to a profile goes through [`record_for`] instead
+pub fn record(app: &AppHandle, category: Category, sent: u64, received: u64) {
+    if sent == 0 && received == 0 {
+        return;
+    }
+    let Some(profiles) = app.try_state::<Profiles>() else {
+        return;
+    };
+    record_for(app, &profiles.active_id(), category, sent, received);
+}
+
+/// Count traffic against today for `profile_id`, active or not
+pub fn record_for(app: &AppHandle, profile_id: &str, category: Category, sent: u64, received: u64) {
+    if sent == 0 && received == 0 {
+        return;
+    }
+    // Connections made during setup can get here before it's managed
+    let Some(usage) = app.try_state::<DataUsage>() else {
+        return;
+    };
+    usage
+        .lock()
+        .entry(profile_id.to_string())
+        .or_default()
+        .entry((Local::now().date_naive(), category))
+        .or_default()
+        .add(Tally { sent, received });
+}
+
+/// Write buffered tallies now, each to its profile's database
+pub fn flush(app: &AppHandle) -> Result<(), DataUsageError> {
+    let usage = app.state::<DataUsage>();
+    let pending = std::mem::take(&mut *usage.lock());
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let profiles = app.state::<Profiles>();
+    let active = profiles.active_id();
+    let mut result = Ok(());
+    for (profile_id, tallies) in pending {
+        let written = if profile_id == active {
+            write(profiles.database(), &tallies)
+        } else {
+            match profiles.open_database(&profile_id) {
+                Ok(db) => write(&db, &tallies),
+                // Deleted since, along with its usage
+                Err(ProfileError::NotFound(_)) => continue,
+                Err(e) => Err(e.into()),
+            }
+        };
+        if let Err(e) = written {
+            // Kept for the next flush rather than lost
+            let mut buffered = usage.lock();
+            let buffered = buffered.entry(profile_id).or_default();
+            for (key, tally) in tallies {
+                buffered.entry(key).or_default().add(tally);
+            }
+            result = Err(e);
+        }
+    }
+    result
+}
+
+fn write(db: &Database, tallies: &Tallies) -> Result<(), DataUsageError> {
+    db.with(|conn| {
+        let tx = conn.transaction()?;
+        for ((day, category), tally) in tallies {
+            add(&tx, *day, *category, *tally)?;
+        }
+        tx.commit()
+    })?;
+    Ok(())
+}
+
+pub fn report(app: &AppHandle, range: UsageRange) -> Result<UsageReport, DataUsageError> {
+    let days = (range.to - range.from).num_days();
+    if !(0..MAX_RANGE_DAYS).contains(&days) {
+        return Err(DataUsageError::InvalidRange);
+    }
+    flush(app)?;
+
+    let days = app
+        .state::<Database>()
+        .with(|conn| list(conn, range.from, range.to))?;
+    let mut totals = BTreeMap::new();
+    for day in &days {
+        totals
+            .entry(day.category)
+            .or_insert_with(Tally::default)
+            .add(Tally {
+                sent: day.sent,
+                received: day.received,
+            });
+    }
+    Ok(UsageReport {
+        from: range.from,
+        to: range.to,
+        days,
+        totals,
+    })
+}
+
+pub fn is_metered(db: &Database) -> bool {
+    db.with(|conn| settings::get_value(conn, METERED_SETTING))
+        .map_err(|e| log::error!("Failed to read metered setting: {}", e))
+        .ok()
+        .flatten()
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Emits "metered-changed"
+pub fn set_metered(app: &AppHandle, metered: bool) -> rusqlite::Result<()> {
+    app.state::<Database>()
+        .with(|conn| settings::set(conn, METERED_SETTING, &metered))?;
+    let _ = app.emit("metered-changed", metered);
+    Ok(())
+}
+
+/// Write tallies every [`FLUSH_INTERVAL`] for as long as the app runs
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("data-usage".to_string())
+        .spawn(move || loop {
+            std::thread::sleep(FLUSH_INTERVAL);
+            if let Err(e) = flush(&app) {
+                log::error!("Failed to save data usage: {}", e);
+            }
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to start data usage accounting: {}", e);
+    }
+}
+
+/// Write what's still buffered; call on exit
+pub fn on_exit(app: &AppHandle) {
+    if let Err(e) = flush(app) {
+        log::error!("Failed to save data usage: {}", e);
+    }
+}
+
+fn add(
+    conn: &Connection,
+    day: NaiveDate,
+    category: Category,
+    tally: Tally,
+) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO data_usage (day, category, sent, received) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (day, category) DO UPDATE SET
+             sent = sent + excluded.sent,
+             received = received + excluded.received",
+    )?
+    .execute(params![
+        day,
+        category.as_str(),
+        tally.sent as i64,
+        tally.received as i64
+    ])?;
+    Ok(())
+}
+
+fn list(conn: &Connection, from: NaiveDate, to: NaiveDate) -> rusqlite::Result<Vec<DailyUsage>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT day, category, sent, received FROM data_usage
+         WHERE day BETWEEN ?1 AND ?2 ORDER BY day, category",
+    )?;
+    let rows = stmt.query_map(params![from, to], |row| {
+        Ok((
+            row.get::<_, NaiveDate>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, i64>(3)?,
+        ))
+    })?;
+
+    let mut days = Vec::new();
+    for row in rows {
+        let (day, category, sent, received) = row?;
+        // Left by a newer version
+        let Some(category) = Category::parse(&category) else {
+            continue;
+        };
+        days.push(DailyUsage {
+            day,
+            category,
+            sent: sent as u64,
+            received: received as u64,
+        });
+    }
+    Ok(days)
+}
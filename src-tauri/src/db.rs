@@ -0,0 +1,183 @@
+//! Local SQLite store
+//!
+//! Holds everything the desktop client keeps on disk between runs: the
+//! message cache, the outbox of messages waiting to be sent, settings,
+//! downloaded attachments, drafts, link previews, the index of installed
+//! emoji and sticker packs, messages scheduled for later, how long each
+//! conversation's messages are kept, and data used per day. Schema
+//! changes are appended to `MIGRATIONS` and applied in order on open,
+//! tracked through `PRAGMA user_version`.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::Connection;
+
+const MIGRATIONS: &[&str] = &[
+    // 1: message cache and outbox
+    "CREATE TABLE messages (
+        id TEXT PRIMARY KEY,
+        instance_id TEXT,
+        channel_id TEXT NOT NULL,
+        author_id TEXT,
+        author_name TEXT NOT NULL,
+        content TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        source TEXT NOT NULL
+    );
+    CREATE INDEX messages_channel_created ON messages (channel_id, created_at);
+    CREATE TABLE outbox (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        instance_id TEXT NOT NULL,
+        channel_id TEXT NOT NULL,
+        content TEXT NOT NULL,
+        nonce TEXT NOT NULL UNIQUE,
+        created_at TEXT NOT NULL
+    );",
+    // 2: settings
+    "CREATE TABLE settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    // 3: servers this profile is signed in to
+    "CREATE TABLE instances (
+        id TEXT PRIMARY KEY,
+        url TEXT NOT NULL,
+        name TEXT NOT NULL
+    );",
+    // 4: downloaded attachments
+    "CREATE TABLE downloads (
+        id TEXT PRIMARY KEY,
+        instance_id TEXT NOT NULL,
+        attachment_id TEXT NOT NULL,
+        filename TEXT NOT NULL,
+        path TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        sha256 TEXT NOT NULL,
+        file_check TEXT NOT NULL,
+        confirmed INTEGER NOT NULL DEFAULT 0,
+        created_at TEXT NOT NULL
+    );",
+    // 5: composer drafts
+    "CREATE TABLE drafts (
+        instance_id TEXT NOT NULL,
+        channel_id TEXT NOT NULL,
+        content TEXT NOT NULL,
+        attachments TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (instance_id, channel_id)
+    );",
+    // 6: link preview cache; pages without a preview have no title or description
+    "CREATE TABLE link_previews (
+        url TEXT PRIMARY KEY,
+        title TEXT,
+        description TEXT,
+        site_name TEXT,
+        kind TEXT,
+        image_key TEXT,
+        fetched_at TEXT NOT NULL
+    );",
+    // 7: installed emoji and sticker packs, with their items for search
+    "CREATE TABLE packs (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        version TEXT NOT NULL,
+        source TEXT NOT NULL,
+        sha256 TEXT NOT NULL,
+        item_count INTEGER NOT NULL,
+        installed_at TEXT NOT NULL
+    );
+    CREATE TABLE pack_items (
+        pack_id TEXT NOT NULL REFERENCES packs(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        keywords TEXT NOT NULL,
+        file TEXT NOT NULL,
+        PRIMARY KEY (pack_id, name)
+    );",
+    // 8: messages to be moved into the outbox and sent at a set time
+    "CREATE TABLE scheduled_messages (
+        id TEXT PRIMARY KEY,
+        instance_id TEXT NOT NULL,
+        channel_id TEXT NOT NULL,
+        content TEXT NOT NULL,
+        send_at TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX scheduled_messages_send_at ON scheduled_messages (send_at);",
+    // 9: disappearing-message policies
+    "CREATE TABLE retention_policies (
+        instance_id TEXT NOT NULL,
+        channel_id TEXT NOT NULL,
+        max_age_secs INTEGER NOT NULL,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (instance_id, channel_id)
+    );",
+    // 10: bytes sent and received per local day and traffic category
+    "CREATE TABLE data_usage (
+        day TEXT NOT NULL,
+        category TEXT NOT NULL,
+        sent INTEGER NOT NULL,
+        received INTEGER NOT NULL,
+        PRIMARY KEY (day, category)
+    );",
+    // 11: device choices move under `devices.` so settings bundles carry
+    // them in their own section
+    "UPDATE settings SET key = 'devices.audio_input' WHERE key = 'audio.input_device';
+    UPDATE settings SET key = 'devices.notification_output'
+        WHERE key = 'audio.notification_output_device';
+    UPDATE settings SET key = 'devices.channel_overrides' WHERE key = 'audio.channel_overrides';",
+];
+
+/// Shared handle to the local database
+#[derive(Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    /// Open (or create) the database at `path` and bring the schema up to date
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        Ok(Self {
+            conn: Arc::new(Mutex::new(connect(path)?)),
+        })
+    }
+
+    /// Point this handle, and every clone of it, at a different database file
+    pub fn reopen(&self, path: &Path) -> rusqlite::Result<()> {
+        let conn = connect(path)?;
+        *self.conn.lock().unwrap_or_else(|e| e.into_inner()) = conn;
+        Ok(())
+    }
+
+    /// Run `f` with exclusive access to the connection
+    pub fn with<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>,
+    ) -> rusqlite::Result<T> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut conn)
+    }
+}
+
+fn connect(path: &Path) -> rusqlite::Result<Connection> {
+    let mut conn = Connection::open(path)?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+    migrate(&mut conn)?;
+    Ok(conn)
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index as i64 + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+//! Session bus integration on Linux
+//!
+//! The running app owns `org.redoubt.Desktop` on the session bus and serves
+//! two interfaces at `/org/redoubt/Desktop`:
+//! - `org.redoubt.Desktop`, with `Activate` and `ShowConversation`, for
+//!   scripts and shell extensions
+//! - `org.freedesktop.Application`, which GNOME and KDE use to activate a
+//!   D-Bus activatable app, open `redoubt://` links in it and run the
+//!   actions listed in its desktop file (`gapplication launch` and
+//!   `gapplication action` call it as well)
+//!
+//! The installed D-Bus service file starts the app when either is called
+//! while it isn't running. Notifications are sent to
+//! `org.freedesktop.Notifications` over the same connection, and clicks on
+//! them are routed back here (see [`notifications`]).
+
+pub mod notifications;
+mod service;
+
+use std::sync::OnceLock;
+
+use tauri::{AppHandle, Manager};
+use zbus::Connection;
+
+pub const BUS_NAME: &str = "org.redoubt.Desktop";
+pub const OBJECT_PATH: &str = "/org/redoubt/Desktop";
+
+/// The app's session bus connection, once it's been made
+#[derive(Default)]
+pub struct SessionBus {
+    connection: OnceLock<Connection>,
+    pub(crate) notifications: notifications::Sent,
+}
+
+impl SessionBus {
+    pub fn connection(&self) -> Option<&Connection> {
+        self.connection.get()
+    }
+}
+
+/// Connect to the session bus and serve the app's interfaces for as long
+/// as it runs; call once, after [`SessionBus`] is managed
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let connection = match service::serve(&app).await {
+            Ok(connection) => connection,
+            Err(e) => {
+                log::warn!("D-Bus integration is off: {}", e);
+                return;
+            }
+        };
+        let _ = app.state::<SessionBus>().connection.set(connection.clone());
+
+        if let Err(e) = notifications::route_actions(&app, &connection).await {
+            log::warn!("Notification actions are unavailable: {}", e);
+        }
+    });
+}
@@ -0,0 +1,174 @@
+//! Notifications through `org.freedesktop.Notifications`
+//!
+//! Sent with an action for clicking the notification itself and one per
+//! button. The server reports clicks as `ActionInvoked` signals carrying
+//! the notification's id, which is looked up here to find the conversation
+//! it was about.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use futures_util::StreamExt;
+use tauri::{AppHandle, Emitter, Manager};
+use zbus::zvariant::Value;
+use zbus::{Connection, Proxy};
+
+use super::SessionBus;
+use crate::notifications::{Notification, NotificationAction};
+use crate::{calls, deep_link};
+
+const DESTINATION: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+const INTERFACE: &str = "org.freedesktop.Notifications";
+
+/// Action key servers use for a click on the notification's body
+const DEFAULT_ACTION: &str = "default";
+
+/// `urgency` hint for notifications that shouldn't be missed
+const CRITICAL: u8 = 2;
+
+/// Notifications still shown, by server id
+#[derive(Default)]
+pub(crate) struct Sent {
+    shown: Mutex<HashMap<u32, Notification>>,
+}
+
+impl Sent {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u32, Notification>> {
+        self.shown.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Show a notification, returning the server's id for it
+pub async fn show(
+    connection: &Connection,
+    sent: &Sent,
+    notification: Notification,
+) -> zbus::Result<u32> {
+    let mut actions = vec![DEFAULT_ACTION, "Open"];
+    for button in &notification.buttons {
+        actions.push(&button.id);
+        actions.push(&button.label);
+    }
+
+    let mut hints: HashMap<&str, Value> = HashMap::new();
+    // Lets the server group notifications under the app and use its icon
+    hints.insert("desktop-entry", Value::from("redoubt"));
+    hints.insert("category", Value::from("im.received"));
+    // Calls stay up until they stop ringing, at which point they're closed
+    let expire_timeout = if notification.call_id.is_some() {
+        hints.insert("urgency", Value::from(CRITICAL));
+        0i32
+    } else {
+        -1i32
+    };
+
+    let reply = connection
+        .call_method(
+            Some(DESTINATION),
+            PATH,
+            Some(INTERFACE),
+            "Notify",
+            &(
+                "Redoubt",
+                0u32,
+                "",
+                notification.title.as_str(),
+                notification.body.as_str(),
+                actions,
+                hints,
+                expire_timeout,
+            ),
+        )
+        .await?;
+    let id: u32 = reply.body().deserialize()?;
+    sent.lock().insert(id, notification);
+    Ok(id)
+}
+
+/// Remove a notification that's no longer relevant, e.g. once its
+/// conversation has been read
+pub async fn close(connection: &Connection, sent: &Sent, id: u32) -> zbus::Result<()> {
+    sent.lock().remove(&id);
+    connection
+        .call_method(
+            Some(DESTINATION),
+            PATH,
+            Some(INTERFACE),
+            "CloseNotification",
+            &(id,),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Handle clicks on the app's notifications until the connection closes.
+/// The body opens the conversation; buttons emit "notification-action",
+/// except on call notifications, where they answer or decline the call.
+pub(super) async fn route_actions(app: &AppHandle, connection: &Connection) -> zbus::Result<()> {
+    let proxy = Proxy::new(connection, DESTINATION, PATH, INTERFACE).await?;
+    let mut invoked = proxy.receive_signal("ActionInvoked").await?;
+    let mut closed = proxy.receive_signal("NotificationClosed").await?;
+
+    loop {
+        tokio::select! {
+            Some(message) = invoked.next() => {
+                let (id, action): (u32, String) = match message.body().deserialize() {
+                    Ok(args) => args,
+                    Err(e) => {
+                        log::warn!("Malformed ActionInvoked signal: {}", e);
+                        continue;
+                    }
+                };
+                // The signal goes to every client; ids not in the map are
+                // someone else's notifications
+                let bus = app.state::<SessionBus>();
+                let Some(notification) = bus.notifications.lock().remove(&id) else {
+                    continue;
+                };
+                invoked_action(app, notification, action);
+            }
+            Some(message) = closed.next() => {
+                if let Ok((id, _reason)) = message.body().deserialize::<(u32, u32)>() {
+                    app.state::<SessionBus>().notifications.lock().remove(&id);
+                }
+            }
+            else => return Ok(()),
+        }
+    }
+}
+
+fn invoked_action(app: &AppHandle, notification: Notification, action: String) {
+    if let Some(call_id) = &notification.call_id {
+        let result = match action.as_str() {
+            calls::ANSWER_ACTION => calls::answer(app, call_id),
+            calls::DECLINE_ACTION => calls::decline(app, call_id),
+            _ => {
+                deep_link::focus_main_window(app);
+                return;
+            }
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to handle call notification: {}", e);
+        }
+        return;
+    }
+    if action == DEFAULT_ACTION {
+        if let Err(e) = super::service::show_conversation(
+            app,
+            &notification.instance_id,
+            &notification.channel_id,
+        ) {
+            log::warn!("Failed to open notification's conversation: {}", e);
+        }
+        return;
+    }
+    let _ = app.emit(
+        "notification-action",
+        NotificationAction {
+            action,
+            instance_id: notification.instance_id,
+            channel_id: notification.channel_id,
+        },
+    );
+}
@@ -0,0 +1,103 @@
+//! The interfaces served at [`super::OBJECT_PATH`]
+
+use std::collections::HashMap;
+
+use tauri::{AppHandle, Manager};
+use url::Url;
+use zbus::zvariant::OwnedValue;
+use zbus::{connection, fdo, interface, Connection};
+
+use super::{BUS_NAME, OBJECT_PATH};
+use crate::db::Database;
+use crate::launcher::{self, LauncherAction};
+use crate::{deep_link, instances};
+
+pub(super) async fn serve(app: &AppHandle) -> zbus::Result<Connection> {
+    connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Desktop { app: app.clone() })?
+        .serve_at(OBJECT_PATH, Application { app: app.clone() })?
+        .build()
+        .await
+}
+
+struct Desktop {
+    app: AppHandle,
+}
+
+#[interface(name = "org.redoubt.Desktop")]
+impl Desktop {
+    /// Raise the main window
+    fn activate(&self) {
+        deep_link::focus_main_window(&self.app);
+    }
+
+    /// Raise the main window on a channel of a registered instance
+    fn show_conversation(&self, instance_id: String, channel_id: String) -> fdo::Result<()> {
+        show_conversation(&self.app, &instance_id, &channel_id)
+    }
+}
+
+/// `org.freedesktop.Application`; the platform data carries startup
+/// notification tokens, which the webview window can't be handed
+struct Application {
+    app: AppHandle,
+}
+
+#[interface(name = "org.freedesktop.Application")]
+impl Application {
+    fn activate(&self, _platform_data: HashMap<String, OwnedValue>) {
+        deep_link::focus_main_window(&self.app);
+    }
+
+    fn open(&self, uris: Vec<String>, _platform_data: HashMap<String, OwnedValue>) {
+        let urls = uris
+            .iter()
+            .filter_map(|uri| Url::parse(uri).ok())
+            .collect::<Vec<_>>();
+        if urls.is_empty() {
+            deep_link::focus_main_window(&self.app);
+        } else {
+            deep_link::handle(&self.app, urls);
+        }
+    }
+
+    /// Actions from the desktop file's `Actions=` list
+    fn activate_action(
+        &self,
+        action_name: String,
+        _parameter: Vec<OwnedValue>,
+        _platform_data: HashMap<String, OwnedValue>,
+    ) -> fdo::Result<()> {
+        match action_name.as_str() {
+            "toggle-mute" => launcher::activate(&self.app, LauncherAction::ToggleMute),
+            "settings" => open_link(&self.app, deep_link::SETTINGS_URL)?,
+            other => return Err(fdo::Error::InvalidArgs(format!("unknown action {}", other))),
+        }
+        Ok(())
+    }
+}
+
+/// Open a channel through the deep link handler, so it reaches the frontend
+/// the way the jump list's conversations do
+pub(super) fn show_conversation(
+    app: &AppHandle,
+    instance_id: &str,
+    channel_id: &str,
+) -> fdo::Result<()> {
+    let known = app
+        .state::<Database>()
+        .with(|conn| instances::list(conn))
+        .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+    let instance = known
+        .iter()
+        .find(|i| i.id == instance_id)
+        .ok_or_else(|| fdo::Error::InvalidArgs(format!("unknown instance {}", instance_id)))?;
+    open_link(app, &deep_link::conversation_url(&instance.url, channel_id))
+}
+
+fn open_link(app: &AppHandle, link: &str) -> fdo::Result<()> {
+    let url = Url::parse(link).map_err(|e| fdo::Error::InvalidArgs(e.to_string()))?;
+    deep_link::handle(app, vec![url]);
+    Ok(())
+}
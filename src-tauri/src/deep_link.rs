@@ -0,0 +1,411 @@
+//! `redoubt://` links
+//!
+//! Links opened anywhere on the system reach the running app: macOS
+//! delivers them directly, on Windows and Linux the OS starts a second
+//! instance that hands its arguments over through the single-instance
+//! channel and exits. Each URL is parsed and validated here, the main
+//! window is raised, and the frontend receives a "deep-link" event with the
+//! typed payload. Links that arrive before the frontend has loaded are held
+//! until it calls `take_pending_deep_links`. In streamer mode links are
+//! marked as masked so the frontend doesn't show what's in them.
+//!
+//! Recognized links:
+//! - `redoubt://invite/<code>?instance=<url>`
+//! - `redoubt://call/<channel id>?instance=<url>`
+//! - `redoubt://verify/<token>?instance=<url>`
+//! - `redoubt://channel/<channel id>?instance=<url>`
+//! - `redoubt://settings`
+//! - `redoubt://link?...`, a device linking code (see [`crate::linking`])
+
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use url::Url;
+
+use crate::db::Database;
+use crate::instances;
+use crate::launcher;
+use crate::linking::LinkingPayload;
+use crate::streamer_mode::StreamerMode;
+
+/// Longest invite code, channel id or token accepted
+const MAX_ID_LEN: usize = 128;
+
+/// Opens the settings
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+pub const SETTINGS_URL: &str = "redoubt://settings";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DeepLink {
+    Invite {
+        instance_url: String,
+        /// Set if the instance is already registered
+        instance_id: Option<String>,
+        code: String,
+    },
+    JoinCall {
+        instance_url: String,
+        instance_id: Option<String>,
+        channel_id: String,
+    },
+    Verify {
+        instance_url: String,
+        instance_id: Option<String>,
+        token: String,
+    },
+    OpenConversation {
+        instance_url: String,
+        instance_id: Option<String>,
+        channel_id: String,
+    },
+    OpenSettings,
+    LinkDevice(LinkingPayload),
+}
+
+/// A link as handed to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceivedLink {
+    #[serde(flatten)]
+    pub link: DeepLink,
+    /// Streamer mode is on, so invite codes and tokens shouldn't be shown
+    pub masked: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DeepLinkError {
+    #[error("not a redoubt:// link")]
+    Scheme,
+    #[error("unknown link type: {0}")]
+    Unknown(String),
+    #[error("link is missing its {0}")]
+    Missing(&'static str),
+    #[error("link has an invalid {0}")]
+    Invalid(&'static str),
+    #[error("{0}")]
+    Linking(#[from] crate::linking::LinkingError),
+}
+
+impl DeepLink {
+    /// Parse and validate a `redoubt://` URL
+    pub fn parse(url: &Url) -> Result<Self, DeepLinkError> {
+        if url.scheme() != "redoubt" {
+            return Err(DeepLinkError::Scheme);
+        }
+        let kind = url.host_str().unwrap_or_default();
+        match kind {
+            "link" => return Ok(DeepLink::LinkDevice(LinkingPayload::parse(url.as_str())?)),
+            "settings" => return Ok(DeepLink::OpenSettings),
+            _ => {}
+        }
+
+        let id = || -> Result<String, DeepLinkError> {
+            let mut segments = url.path_segments().into_iter().flatten();
+            let id = segments
+                .next()
+                .filter(|s| !s.is_empty())
+                .ok_or(DeepLinkError::Missing("id"))?;
+            if segments.any(|s| !s.is_empty()) || !is_valid_id(id) {
+                return Err(DeepLinkError::Invalid("id"));
+            }
+            Ok(id.to_string())
+        };
+        let instance_url = || -> Result<String, DeepLinkError> {
+            let instance = url
+                .query_pairs()
+                .find(|(name, _)| name == "instance")
+                .map(|(_, value)| value.into_owned())
+                .ok_or(DeepLinkError::Missing("instance"))?;
+            let parsed = Url::parse(&instance).map_err(|_| DeepLinkError::Invalid("instance"))?;
+            if !matches!(parsed.scheme(), "https" | "http") || parsed.host_str().is_none() {
+                return Err(DeepLinkError::Invalid("instance"));
+            }
+            Ok(instance.trim_end_matches('/').to_string())
+        };
+
+        match kind {
+            "invite" => Ok(DeepLink::Invite {
+                instance_url: instance_url()?,
+                instance_id: None,
+                code: id()?,
+            }),
+            "call" => Ok(DeepLink::JoinCall {
+                instance_url: instance_url()?,
+                instance_id: None,
+                channel_id: id()?,
+            }),
+            "verify" => Ok(DeepLink::Verify {
+                instance_url: instance_url()?,
+                instance_id: None,
+                token: id()?,
+            }),
+            "channel" => Ok(DeepLink::OpenConversation {
+                instance_url: instance_url()?,
+                instance_id: None,
+                channel_id: id()?,
+            }),
+            other => Err(DeepLinkError::Unknown(other.to_string())),
+        }
+    }
+
+    /// Fill in the registered instance the link points at, if any
+    fn resolve_instance(&mut self, known: &[instances::Instance]) {
+        let (url, id) = match self {
+            DeepLink::Invite {
+                instance_url,
+                instance_id,
+                ..
+            }
+            | DeepLink::JoinCall {
+                instance_url,
+                instance_id,
+                ..
+            }
+            | DeepLink::Verify {
+                instance_url,
+                instance_id,
+                ..
+            }
+            | DeepLink::OpenConversation {
+                instance_url,
+                instance_id,
+                ..
+            } => (instance_url, instance_id),
+            DeepLink::OpenSettings | DeepLink::LinkDevice(_) => return,
+        };
+        *id = known
+            .iter()
+            .find(|i| i.url.trim_end_matches('/').eq_ignore_ascii_case(url))
+            .map(|i| i.id.clone());
+    }
+}
+
+/// Link that opens a channel on an instance
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+pub fn conversation_url(instance_url: &str, channel_id: &str) -> String {
+    let mut url = Url::parse("redoubt://channel").expect("static URL is valid");
+    url.path_segments_mut()
+        .expect("redoubt:// URLs have paths")
+        .push(channel_id);
+    url.query_pairs_mut().append_pair("instance", instance_url);
+    url.into()
+}
+
+fn is_valid_id(id: &str) -> bool {
+    id.len() <= MAX_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Links received before the frontend was ready for them
+#[derive(Default)]
+pub struct DeepLinks {
+    queue: Mutex<Queue>,
+}
+
+#[derive(Default)]
+struct Queue {
+    /// The frontend has collected the pending links and listens for events
+    frontend_ready: bool,
+    pending: Vec<DeepLink>,
+}
+
+impl DeepLinks {
+    /// Hand over links held since startup; later ones arrive as events
+    pub fn take_pending(&self, masked: bool) -> Vec<ReceivedLink> {
+        let mut queue = self.lock();
+        queue.frontend_ready = true;
+        std::mem::take(&mut queue.pending)
+            .into_iter()
+            .map(|link| ReceivedLink { link, masked })
+            .collect()
+    }
+
+    /// Hold links again until a new frontend collects them
+    pub fn frontend_gone(&self) {
+        self.lock().frontend_ready = false;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Handle URLs opened with the app, raising the window and emitting
+/// "deep-link" for each valid one
+pub fn handle(app: &AppHandle, urls: Vec<Url>) {
+    let known = app
+        .state::<Database>()
+        .with(|conn| instances::list(conn))
+        .unwrap_or_else(|e| {
+            log::error!("Failed to list instances: {}", e);
+            Vec::new()
+        });
+
+    let mut received = false;
+    for url in urls {
+        let mut link = match DeepLink::parse(&url) {
+            Ok(link) => link,
+            Err(e) => {
+                // Don't log the URL itself; it can carry invite codes and tokens
+                log::warn!("Ignoring {}:// link: {}", url.scheme(), e);
+                continue;
+            }
+        };
+        link.resolve_instance(&known);
+        received = true;
+
+        let deep_links = app.state::<DeepLinks>();
+        let mut queue = deep_links.lock();
+        if queue.frontend_ready {
+            let masked = app.state::<StreamerMode>().is_enabled();
+            let _ = app.emit("deep-link", ReceivedLink { link, masked });
+        } else {
+            queue.pending.push(link);
+        }
+    }
+
+    if received {
+        focus_main_window(app);
+    }
+}
+
+/// Show, unminimize and focus the main window, building it again if it
+/// was destroyed in the background
+pub fn focus_main_window(app: &AppHandle) {
+    let Some(window) = launcher::main_window(app) else {
+        return;
+    };
+    let _ = window.unminimize();
+    let _ = window.show();
+    let _ = window.set_focus();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(url: &str) -> Result<DeepLink, DeepLinkError> {
+        DeepLink::parse(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn parses_known_links() {
+        assert_eq!(
+            parse("redoubt://invite/abc-123?instance=https://chat.example.com/").unwrap(),
+            DeepLink::Invite {
+                instance_url: "https://chat.example.com".to_string(),
+                instance_id: None,
+                code: "abc-123".to_string(),
+            }
+        );
+        assert_eq!(
+            parse("redoubt://call/chan_1/?instance=http://10.0.0.2:8080").unwrap(),
+            DeepLink::JoinCall {
+                instance_url: "http://10.0.0.2:8080".to_string(),
+                instance_id: None,
+                channel_id: "chan_1".to_string(),
+            }
+        );
+        assert_eq!(parse("redoubt://settings").unwrap(), DeepLink::OpenSettings);
+    }
+
+    #[test]
+    fn round_trips_conversation_urls() {
+        let url = conversation_url("https://chat.example.com", "general");
+        assert_eq!(
+            parse(&url).unwrap(),
+            DeepLink::OpenConversation {
+                instance_url: "https://chat.example.com".to_string(),
+                instance_id: None,
+                channel_id: "general".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_extra_segments() {
+        for url in [
+            "redoubt://invite/abc/def?instance=https://chat.example.com",
+            "redoubt://channel//abc?instance=https://chat.example.com",
+        ] {
+            assert!(parse(url).is_err(), "{}", url);
+        }
+        assert!(matches!(
+            parse("redoubt://invite/abc/def?instance=https://chat.example.com"),
+            Err(DeepLinkError::Invalid("id"))
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_ids() {
+        let long = "a".repeat(MAX_ID_LEN + 1);
+        assert!(matches!(
+            parse(&format!(
+                "redoubt://invite/{}?instance=https://chat.example.com",
+                long
+            )),
+            Err(DeepLinkError::Invalid("id"))
+        ));
+        let longest = "a".repeat(MAX_ID_LEN);
+        assert!(parse(&format!(
+            "redoubt://invite/{}?instance=https://chat.example.com",
+            longest
+        ))
+        .is_ok());
+        for id in ["a%20b", "a.b", "caf%C3%A9"] {
+            assert!(matches!(
+                parse(&format!(
+                    "redoubt://call/{}?instance=https://chat.example.com",
+                    id
+                )),
+                Err(DeepLinkError::Invalid("id"))
+            ));
+        }
+        assert!(matches!(
+            parse("redoubt://call/?instance=https://chat.example.com"),
+            Err(DeepLinkError::Missing("id"))
+        ));
+    }
+
+    #[test]
+    fn rejects_non_http_instances() {
+        for instance in [
+            "javascript:alert(1)",
+            "file:///etc/passwd",
+            "ftp://chat.example.com",
+            "redoubt://settings",
+            "data:text/html,hi",
+            "not a url",
+        ] {
+            let url =
+                Url::parse_with_params("redoubt://invite/abc", [("instance", instance)]).unwrap();
+            assert!(
+                matches!(
+                    DeepLink::parse(&url),
+                    Err(DeepLinkError::Invalid("instance"))
+                ),
+                "{}",
+                instance
+            );
+        }
+        assert!(matches!(
+            parse("redoubt://invite/abc"),
+            Err(DeepLinkError::Missing("instance"))
+        ));
+    }
+
+    #[test]
+    fn rejects_other_links() {
+        assert!(matches!(
+            parse("https://chat.example.com/invite/abc"),
+            Err(DeepLinkError::Scheme)
+        ));
+        assert!(matches!(
+            parse("redoubt://delete/abc?instance=https://chat.example.com"),
+            Err(DeepLinkError::Unknown(_))
+        ));
+    }
+}
@@ -0,0 +1,384 @@
+//! Connectivity troubleshooter
+//!
+//! Works out how call traffic can get out from where the user is: whether
+//! a VPN is up, and whether the voice relays (see [`crate::relays`]) answer
+//! over UDP, over TCP on their own port, or only on 443, the port
+//! firewalls nearly always leave open. The findings say what that means
+//! for calls in words a user can act on, e.g. "UDP is blocked, calls are
+//! using the TCP fallback".
+//!
+//! Without configured relays, UDP is tried against a public STUN server
+//! and TCP against the instances the user is signed in to.
+//!
+//! Answering a call runs it in the background and emits
+//! "connectivity-diagnosis" if anything isn't as it should be.
+
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::self_test::{CheckStatus, STUN_SERVERS};
+use super::stun;
+use crate::db::Database;
+use crate::{instances, relays};
+
+const UDP_TIMEOUT: Duration = Duration::from_secs(2);
+const UDP_ATTEMPTS: u32 = 3;
+const TCP_TIMEOUT: Duration = Duration::from_secs(3);
+const FALLBACK_PORT: u16 = 443;
+
+/// Interface name fragments VPN clients use: OpenVPN and WireGuard
+/// (`tun`, `tap`, `wg`), macOS and iOS tunnels (`utun`), PPTP and L2TP
+/// (`ppp`), and the commercial clients' own names
+const VPN_INTERFACES: &[&str] = &[
+    "tun",
+    "tap",
+    "wg",
+    "utun",
+    "ppp",
+    "ipsec",
+    "tailscale",
+    "zt",
+    "nordlynx",
+    "mullvad",
+    "proton",
+    "openvpn",
+    "cscotun",
+    "gpd",
+];
+
+/// How call traffic gets out, best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    Udp,
+    Tcp,
+    /// TCP to port 443
+    Tcp443,
+}
+
+/// One way of reaching a target
+#[derive(Debug, Clone, Serialize)]
+pub struct Probe {
+    pub port: u16,
+    pub reachable: bool,
+    pub rtt_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetResult {
+    /// The relay's region, or the host for the fallback targets
+    pub label: String,
+    pub host: String,
+    pub udp: Option<Probe>,
+    pub tcp: Option<Probe>,
+    pub tcp_443: Option<Probe>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+/// Result of `diagnose_connectivity`, and the payload of
+/// "connectivity-diagnosis"
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectivityReport {
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub duration_ms: u64,
+    /// Interfaces that look like a VPN and have an address
+    pub vpn_interfaces: Vec<String>,
+    pub targets: Vec<TargetResult>,
+    /// The best way out found, if any
+    pub transport: Option<Transport>,
+    pub findings: Vec<Finding>,
+}
+
+impl ConnectivityReport {
+    /// Whether calls work the way they're meant to
+    fn is_healthy(&self) -> bool {
+        self.transport == Some(Transport::Udp) && self.vpn_interfaces.is_empty()
+    }
+}
+
+/// What to try for one host
+struct Target {
+    label: String,
+    host: String,
+    udp_port: Option<u16>,
+    tcp_port: Option<u16>,
+}
+
+/// Detect VPNs and try every way out to the voice infrastructure
+pub async fn diagnose(app: &AppHandle) -> Result<ConnectivityReport, tauri::Error> {
+    let targets = targets(app);
+    tauri::async_runtime::spawn_blocking(move || run(targets)).await
+}
+
+/// Diagnose in the background once a call is up, and report it if calls
+/// aren't getting the direct UDP path
+/// Emits "connectivity-diagnosis" when they aren't
+pub fn check_during_call(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        match diagnose(&app).await {
+            Ok(report) if !report.is_healthy() => {
+                let _ = app.emit("connectivity-diagnosis", report);
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Failed to diagnose connectivity: {}", e),
+        }
+    });
+}
+
+fn targets(app: &AppHandle) -> Vec<Target> {
+    let db = app.state::<Database>();
+    let relays = relays::relays(&db).unwrap_or_else(|e| {
+        log::warn!("Failed to read the voice relays: {}", e);
+        Vec::new()
+    });
+    if !relays.is_empty() {
+        return relays
+            .into_iter()
+            .filter_map(|relay| {
+                let (host, port) = relay.address.rsplit_once(':')?;
+                Some(Target {
+                    label: relay.region,
+                    host: host.trim_matches(['[', ']']).to_string(),
+                    udp_port: port.parse().ok(),
+                    tcp_port: port.parse().ok(),
+                })
+            })
+            .collect();
+    }
+
+    let mut targets = Vec::new();
+    if let Some((host, port)) = STUN_SERVERS[0].rsplit_once(':') {
+        targets.push(Target {
+            label: host.to_string(),
+            host: host.to_string(),
+            udp_port: port.parse().ok(),
+            tcp_port: None,
+        });
+    }
+    let instances = db.with(|conn| instances::list(conn)).unwrap_or_default();
+    for instance in instances {
+        let Ok(url) = url::Url::parse(&instance.url) else {
+            continue;
+        };
+        let Some(host) = url.host_str() else {
+            continue;
+        };
+        targets.push(Target {
+            label: host.to_string(),
+            host: host.to_string(),
+            udp_port: None,
+            tcp_port: url.port_or_known_default(),
+        });
+    }
+    targets
+}
+
+fn run(targets: Vec<Target>) -> ConnectivityReport {
+    let started_at = chrono::Utc::now();
+    let started = Instant::now();
+    let vpn_interfaces = vpn_interfaces();
+
+    let results: Vec<TargetResult> = targets
+        .into_iter()
+        .map(|target| {
+            let addr = |port| format!("{}:{}", target.host, port);
+            let tcp_443 = (target.tcp_port != Some(FALLBACK_PORT))
+                .then(|| tcp(&addr(FALLBACK_PORT), FALLBACK_PORT));
+            TargetResult {
+                udp: target.udp_port.map(|port| udp(&addr(port), port)),
+                tcp: target.tcp_port.map(|port| tcp(&addr(port), port)),
+                tcp_443,
+                label: target.label,
+                host: target.host,
+            }
+        })
+        .collect();
+
+    let reaches = |probe: fn(&TargetResult) -> Option<&Probe>| {
+        results
+            .iter()
+            .filter_map(probe)
+            .any(|probe| probe.reachable)
+    };
+    let tried =
+        |probe: fn(&TargetResult) -> Option<&Probe>| results.iter().any(|r| probe(r).is_some());
+    let udp = reaches(|r| r.udp.as_ref());
+    let tcp = reaches(|r| r.tcp.as_ref().filter(|p| p.port != FALLBACK_PORT));
+    let tcp_443 = reaches(|r| {
+        r.tcp_443
+            .as_ref()
+            .or(r.tcp.as_ref().filter(|p| p.port == FALLBACK_PORT))
+    });
+    let transport = if udp {
+        Some(Transport::Udp)
+    } else if tcp {
+        Some(Transport::Tcp)
+    } else if tcp_443 {
+        Some(Transport::Tcp443)
+    } else {
+        None
+    };
+
+    let mut findings = Vec::new();
+    let mut finding = |status, message: String| findings.push(Finding { status, message });
+    if !vpn_interfaces.is_empty() {
+        finding(
+            CheckStatus::Warn,
+            format!(
+                "A VPN looks to be on ({}); call audio goes through it, which can add delay \
+                 or block UDP. If calls sound bad, try them with the VPN off or set to let \
+                 this app bypass it.",
+                vpn_interfaces.join(", ")
+            ),
+        );
+    }
+    match transport {
+        _ if results.is_empty() => finding(
+            CheckStatus::Skipped,
+            "There's nothing to test against: no voice relays are configured and no \
+             instance is signed in to."
+                .to_string(),
+        ),
+        Some(Transport::Udp) => finding(
+            CheckStatus::Pass,
+            "UDP gets through, so calls use the direct, lowest-delay path.".to_string(),
+        ),
+        Some(Transport::Tcp) => finding(
+            CheckStatus::Warn,
+            if tried(|r| r.udp.as_ref()) {
+                "UDP is blocked, calls are using the TCP fallback. Audio may lag a little; \
+                 allowing outgoing UDP in the firewall fixes it."
+            } else {
+                "TCP gets through; UDP couldn't be tested without a voice relay."
+            }
+            .to_string(),
+        ),
+        Some(Transport::Tcp443) => finding(
+            CheckStatus::Warn,
+            if tried(|r| r.tcp.as_ref().filter(|p| p.port != FALLBACK_PORT)) {
+                "Only port 443 gets out, so calls are going through the relay's fallback on \
+                 443. Expect more delay; a firewall or proxy is blocking the other ports."
+            } else if tried(|r| r.udp.as_ref()) {
+                "UDP is blocked and only port 443 was tried for TCP, which gets through. \
+                 Calls will use a relay's fallback on 443 and may lag a little."
+            } else {
+                "Port 443 gets through; UDP couldn't be tested without a voice relay."
+            }
+            .to_string(),
+        ),
+        None => finding(
+            CheckStatus::Fail,
+            "The voice servers can't be reached over UDP, TCP or port 443. Calls won't \
+             connect; check the firewall, proxy or VPN settings."
+                .to_string(),
+        ),
+    }
+
+    ConnectivityReport {
+        started_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        vpn_interfaces,
+        targets: results,
+        transport,
+        findings,
+    }
+}
+
+fn udp(address: &str, port: u16) -> Probe {
+    let server = match resolve(address) {
+        Ok(server) => server,
+        Err(e) => return unreachable(port, e),
+    };
+    let local = if server.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let socket = match UdpSocket::bind(local) {
+        Ok(socket) => socket,
+        Err(e) => return unreachable(port, e.to_string()),
+    };
+
+    let mut last_error = String::new();
+    for _ in 0..UDP_ATTEMPTS {
+        match stun::round_trip(&socket, server, UDP_TIMEOUT / UDP_ATTEMPTS) {
+            Ok(rtt) => return reachable(port, rtt),
+            Err(e) => last_error = e.to_string(),
+        }
+    }
+    unreachable(port, last_error)
+}
+
+fn tcp(address: &str, port: u16) -> Probe {
+    let server = match resolve(address) {
+        Ok(server) => server,
+        Err(e) => return unreachable(port, e),
+    };
+    let started = Instant::now();
+    match TcpStream::connect_timeout(&server, TCP_TIMEOUT) {
+        Ok(_) => reachable(port, started.elapsed()),
+        Err(e) => unreachable(port, e.to_string()),
+    }
+}
+
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    address
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("{} doesn't resolve", address))
+}
+
+fn reachable(port: u16, rtt: Duration) -> Probe {
+    Probe {
+        port,
+        reachable: true,
+        rtt_ms: Some(rtt.as_millis() as u64),
+        error: None,
+    }
+}
+
+fn unreachable(port: u16, error: String) -> Probe {
+    Probe {
+        port,
+        reachable: false,
+        rtt_ms: None,
+        error: Some(error),
+    }
+}
+
+/// Interfaces named like a VPN's that have a routable address; macOS has
+/// idle `utun` interfaces with only link-local ones
+fn vpn_interfaces() -> Vec<String> {
+    let networks = sysinfo::Networks::new_with_refreshed_list();
+    let mut found: Vec<String> = networks
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.to_lowercase();
+            VPN_INTERFACES.iter().any(|vpn| name.starts_with(vpn))
+                || name.contains("vpn")
+                || name.contains("wireguard")
+        })
+        .filter(|(_, data)| {
+            data.ip_networks().iter().any(|network| match network.addr {
+                std::net::IpAddr::V4(ip) => !ip.is_link_local() && !ip.is_loopback(),
+                std::net::IpAddr::V6(ip) => {
+                    !ip.is_loopback() && (ip.segments()[0] & 0xffc0) != 0xfe80
+                }
+            })
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    found.sort();
+    found
+}
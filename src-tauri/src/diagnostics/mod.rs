@@ -0,0 +1,184 @@
+//! Debug bundles
+//!
+//! One zip a user can attach to a bug report, with what's usually asked
+//! for first:
+//! - `system.json`: app, OS and webview versions, CPU and memory
+//! - `audio.json`: microphones and the audio settings
+//! - `connections.json`: how each gateway connection has been doing
+//! - `logs/`: the most recent log files
+//!
+//! Everything is scrubbed on the way in (see [`scrub`]): tokens and
+//! credentials, message content and the home directory always, IP
+//! addresses if the user asks for it. The bundle is written to the
+//! downloads directory and only leaves the machine if the user sends it.
+//!
+//! The self-test in [`self_test`] is the other half of what support asks
+//! for, and [`connectivity`] narrows down why calls won't connect.
+
+pub mod connectivity;
+mod scrub;
+pub mod self_test;
+pub(crate) mod stun;
+
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::audio::capture;
+use crate::db::Database;
+use crate::gateway::Gateway;
+use crate::{logging, settings};
+use scrub::Scrubber;
+
+/// Log files included, most recent first
+const MAX_LOG_FILES: usize = 3;
+/// The end of each log file is kept if it's larger than this
+const MAX_LOG_BYTES: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiagnosticsError {
+    #[error("no download directory to save the bundle in")]
+    NoDownloadDir,
+    #[error("failed to write debug bundle: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("failed to write debug bundle: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to encode debug bundle: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("debug bundle task failed: {0}")]
+    Task(#[from] tauri::Error),
+}
+
+/// A written bundle
+#[derive(Debug, Clone, Serialize)]
+pub struct DebugBundle {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct SystemInfo {
+    app_version: &'static str,
+    os: &'static str,
+    os_version: Option<String>,
+    kernel_version: Option<String>,
+    arch: &'static str,
+    webview_version: Option<String>,
+    cpus: usize,
+    total_memory: u64,
+    generated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Serialize)]
+struct AudioInfo {
+    input_devices: Result<Vec<String>, String>,
+    settings: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Collect a debug bundle into the downloads directory
+pub async fn generate(app: &AppHandle, redact_ips: bool) -> Result<DebugBundle, DiagnosticsError> {
+    let dir = app
+        .path()
+        .download_dir()
+        .map_err(|_| DiagnosticsError::NoDownloadDir)?;
+    let path = dir.join(format!(
+        "redoubt-debug-{}.zip",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+
+    let audio_settings = app
+        .state::<Database>()
+        .with(|conn| {
+            let mut audio = settings::list(conn, "audio.")?;
+            audio.extend(settings::list(conn, "devices.")?);
+            Ok(audio)
+        })?
+        .into_iter()
+        .collect();
+    let connections = serde_json::to_value(app.state::<Gateway>().stats())?;
+    let logs = logging::recent_files(app)
+        .map_err(|e| log::warn!("No log files for the debug bundle: {}", e))
+        .unwrap_or_default();
+
+    let bundle = tauri::async_runtime::spawn_blocking(move || {
+        let scrubber = Scrubber::new(redact_ips);
+        std::fs::create_dir_all(&dir)?;
+        let mut zip = ZipWriter::new(File::create(&path)?);
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+        let mut add_json = |name: &str, value: serde_json::Value| -> Result<(), DiagnosticsError> {
+            let mut value = value;
+            scrubber.json(&mut value);
+            zip.start_file(name, options)?;
+            zip.write_all(&serde_json::to_vec_pretty(&value)?)?;
+            Ok(())
+        };
+        add_json("system.json", serde_json::to_value(system_info())?)?;
+        add_json(
+            "audio.json",
+            serde_json::to_value(AudioInfo {
+                input_devices: capture::input_devices().map_err(|e| e.to_string()),
+                settings: audio_settings,
+            })?,
+        )?;
+        add_json("connections.json", connections)?;
+
+        for file in logs.iter().take(MAX_LOG_FILES) {
+            let Some(name) = file.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let contents = match std::fs::read(file) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    log::warn!("Skipping log file {} in the debug bundle: {}", name, e);
+                    continue;
+                }
+            };
+            zip.start_file(format!("logs/{}", name), options)?;
+            for line in tail(&contents).lines() {
+                writeln!(zip, "{}", scrubber.log_line(line))?;
+            }
+        }
+
+        zip.finish()?;
+        let size = std::fs::metadata(&path)?.len();
+        Ok::<_, DiagnosticsError>(DebugBundle { path, size })
+    })
+    .await??;
+
+    Ok(bundle)
+}
+
+fn system_info() -> SystemInfo {
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    SystemInfo {
+        app_version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        os_version: sysinfo::System::long_os_version(),
+        kernel_version: sysinfo::System::kernel_version(),
+        arch: std::env::consts::ARCH,
+        webview_version: tauri::webview_version().ok(),
+        cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        total_memory: system.total_memory(),
+        generated_at: chrono::Utc::now(),
+    }
+}
+
+/// The last whole lines of a log file that fit the size limit
+fn tail(contents: &[u8]) -> std::borrow::Cow<'_, str> {
+    if contents.len() <= MAX_LOG_BYTES {
+        return String::from_utf8_lossy(contents);
+    }
+    let tail = &contents[contents.len() - MAX_LOG_BYTES..];
+    let start = tail.iter().position(|b| *b == b'\n').map_or(0, |i| i + 1);
+    String::from_utf8_lossy(&tail[start..])
+}
@@ -0,0 +1,120 @@
+//! Removing personal data from what goes into a debug bundle
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde_json::Value;
+
+const REDACTED: &str = "[redacted]";
+
+/// Keys whose values are never kept: credentials, and message content
+const SENSITIVE_KEYS: &[&str] = &[
+    "authorization",
+    "body",
+    "content",
+    "cookie",
+    "passphrase",
+    "password",
+    "secret",
+    "text",
+];
+
+struct Patterns {
+    jwt: Regex,
+    bearer: Regex,
+    query: Regex,
+    ipv4: Regex,
+    ipv6: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        let pattern = |pattern| Regex::new(pattern).expect("static pattern is valid");
+        Patterns {
+            jwt: pattern(r"eyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+\.[A-Za-z0-9_-]*"),
+            bearer: pattern(r"(?i)\bbearer\s+[A-Za-z0-9\-._~+/]+=*"),
+            query: pattern(
+                r#"(?i)([?&](?:token|access_token|refresh_token|code|key|signature)=)[^&\s"']+"#,
+            ),
+            ipv4: pattern(r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b"),
+            // Full addresses, or ones shortened with `::`
+            ipv6: pattern(concat!(
+                r"\b(?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}\b",
+                r"|\b(?:[0-9A-Fa-f]{1,4}:){1,6}:(?:[0-9A-Fa-f]{1,4}:){0,5}[0-9A-Fa-f]{1,4}\b",
+            )),
+        }
+    })
+}
+
+pub(super) struct Scrubber {
+    redact_ips: bool,
+    home: Option<String>,
+}
+
+impl Scrubber {
+    pub(super) fn new(redact_ips: bool) -> Self {
+        let home = std::env::var("HOME")
+            .or_else(|_| std::env::var("USERPROFILE"))
+            .ok()
+            .filter(|home| !home.is_empty());
+        Self { redact_ips, home }
+    }
+
+    /// Tokens, and IP addresses if asked to, wherever they are in the text;
+    /// the home directory becomes `~`
+    pub(super) fn text(&self, text: &str) -> String {
+        let patterns = patterns();
+        let mut text = patterns.jwt.replace_all(text, REDACTED).into_owned();
+        text = patterns
+            .bearer
+            .replace_all(&text, format!("Bearer {}", REDACTED))
+            .into_owned();
+        text = patterns
+            .query
+            .replace_all(&text, format!("${{1}}{}", REDACTED))
+            .into_owned();
+        if self.redact_ips {
+            text = patterns.ipv4.replace_all(&text, REDACTED).into_owned();
+            text = patterns.ipv6.replace_all(&text, REDACTED).into_owned();
+        }
+        if let Some(home) = &self.home {
+            text = text.replace(home.as_str(), "~");
+        }
+        text
+    }
+
+    /// Sensitive keys blanked, and every string scrubbed as text
+    pub(super) fn json(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.text(text),
+            Value::Array(values) => values.iter_mut().for_each(|value| self.json(value)),
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if is_sensitive(key) {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.json(value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A line from a log file: JSON if it parses, text otherwise
+    pub(super) fn log_line(&self, line: &str) -> String {
+        match serde_json::from_str::<Value>(line) {
+            Ok(mut value) => {
+                self.json(&mut value);
+                value.to_string()
+            }
+            Err(_) => self.text(line),
+        }
+    }
+}
+
+fn is_sensitive(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    key.contains("token") || SENSITIVE_KEYS.contains(&key.as_str())
+}
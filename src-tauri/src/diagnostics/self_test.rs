@@ -0,0 +1,497 @@
+//! Self-test
+//!
+//! Runs the checks support would otherwise walk a user through one
+//! question at a time, and reports each as passed, passed with a warning,
+//! failed or skipped:
+//! - the microphone delivers audio, and not only silence
+//! - the output device plays a short, quiet tone
+//! - Opus encodes and decodes a test tone without losing it
+//! - UDP gets out, by way of a STUN server
+//! - STUN maps the socket the same way for two servers, without which
+//!   direct call connections are unlikely to work
+//! - each instance's gateway answers, and how quickly
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample};
+use opus::{Application, Bitrate, Channels, Decoder, Encoder};
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, Manager};
+
+use super::stun;
+use crate::audio::capture::Capture;
+use crate::audio::{INPUT_DEVICE_SETTING, SAMPLE_RATE};
+use crate::db::Database;
+use crate::{instances, settings};
+
+/// STUN servers asked for the mapped address; two, so the mappings can be
+/// compared
+pub(super) const STUN_SERVERS: [&str; 2] = ["stun.l.google.com:19302", "stun.cloudflare.com:3478"];
+const STUN_TIMEOUT: Duration = Duration::from_secs(3);
+
+const CAPTURE_DURATION: Duration = Duration::from_secs(1);
+const TONE_DURATION: Duration = Duration::from_millis(500);
+const TONE_HZ: f32 = 440.0;
+/// About -30 dBFS, audible without being startling
+const TONE_AMPLITUDE: f32 = 0.03;
+/// A microphone whose peak stays under this is probably muted
+const SILENCE_DBFS: f32 = -60.0;
+
+const GATEWAY_TIMEOUT: Duration = Duration::from_secs(5);
+const GATEWAY_PINGS: usize = 3;
+/// Round trips slower than this get a warning
+const SLOW_GATEWAY: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    Microphone,
+    Playback,
+    Encoder,
+    Udp,
+    Stun,
+    Gateway,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub check: Check,
+    pub status: CheckStatus,
+    /// What was found, in words support can read back
+    pub detail: String,
+    /// Measurements behind the result
+    pub data: serde_json::Value,
+    pub duration_ms: u64,
+    /// The instance a gateway check is for
+    pub instance_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub duration_ms: u64,
+    /// Nothing failed
+    pub passed: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+/// Outcome of a check before it's timed and labelled
+struct Outcome {
+    status: CheckStatus,
+    detail: String,
+    data: serde_json::Value,
+}
+
+impl Outcome {
+    fn new(status: CheckStatus, detail: impl Into<String>, data: serde_json::Value) -> Self {
+        Self {
+            status,
+            detail: detail.into(),
+            data,
+        }
+    }
+
+    fn fail(detail: impl std::fmt::Display) -> Self {
+        Self::new(CheckStatus::Fail, detail.to_string(), json!({}))
+    }
+}
+
+/// Run every check, one after another
+pub async fn run(app: &AppHandle) -> SelfTestReport {
+    let started_at = chrono::Utc::now();
+    let started = Instant::now();
+    let db = app.state::<Database>();
+    let mut checks = Vec::new();
+
+    let device = db
+        .with(|conn| settings::get_value(conn, INPUT_DEVICE_SETTING))
+        .ok()
+        .flatten()
+        .and_then(|v| v.as_str().map(str::to_string));
+    checks.push(blocking(Check::Microphone, move || microphone(device)).await);
+    checks.push(blocking(Check::Playback, playback).await);
+    checks.push(blocking(Check::Encoder, encoder_round_trip).await);
+
+    let network = tauri::async_runtime::spawn_blocking(network).await;
+    match network {
+        Ok(results) => checks.extend(results),
+        Err(e) => {
+            for check in [Check::Udp, Check::Stun] {
+                checks.push(result(check, Outcome::fail(&e), Duration::ZERO, None));
+            }
+        }
+    }
+
+    let known = db.with(|conn| instances::list(conn)).unwrap_or_else(|e| {
+        log::error!("Failed to list instances: {}", e);
+        Vec::new()
+    });
+    for instance in known {
+        let started = Instant::now();
+        let outcome = gateway(&instance.url).await;
+        checks.push(result(
+            Check::Gateway,
+            outcome,
+            started.elapsed(),
+            Some(instance.id),
+        ));
+    }
+
+    SelfTestReport {
+        started_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        passed: checks.iter().all(|c| c.status != CheckStatus::Fail),
+        checks,
+    }
+}
+
+fn result(
+    check: Check,
+    outcome: Outcome,
+    duration: Duration,
+    instance_id: Option<String>,
+) -> CheckResult {
+    CheckResult {
+        check,
+        status: outcome.status,
+        detail: outcome.detail,
+        data: outcome.data,
+        duration_ms: duration.as_millis() as u64,
+        instance_id,
+    }
+}
+
+async fn blocking<F>(check: Check, f: F) -> CheckResult
+where
+    F: FnOnce() -> Outcome + Send + 'static,
+{
+    let started = Instant::now();
+    let outcome = tauri::async_runtime::spawn_blocking(f)
+        .await
+        .unwrap_or_else(Outcome::fail);
+    result(check, outcome, started.elapsed(), None)
+}
+
+fn microphone(device: Option<String>) -> Outcome {
+    #[derive(Default)]
+    struct Levels {
+        samples: u64,
+        peak: f32,
+        sum_squares: f64,
+    }
+
+    let levels = Arc::new(Mutex::new(Levels::default()));
+    let sink = levels.clone();
+    let capture = Capture::start(
+        device.clone(),
+        Box::new(move |samples| {
+            let mut levels = sink.lock().unwrap_or_else(|e| e.into_inner());
+            levels.samples += samples.len() as u64;
+            for sample in samples {
+                levels.peak = levels.peak.max(sample.abs());
+                levels.sum_squares += (*sample as f64).powi(2);
+            }
+        }),
+    );
+    let capture = match capture {
+        Ok(capture) => capture,
+        Err(e) => return Outcome::fail(e),
+    };
+    std::thread::sleep(CAPTURE_DURATION);
+    drop(capture);
+
+    let levels = levels.lock().unwrap_or_else(|e| e.into_inner());
+    let peak_dbfs = dbfs(levels.peak);
+    let rms_dbfs = dbfs((levels.sum_squares / levels.samples.max(1) as f64).sqrt() as f32);
+    let data = json!({
+        "device": device,
+        "samples": levels.samples,
+        "peak_dbfs": peak_dbfs,
+        "rms_dbfs": rms_dbfs,
+    });
+    let expected = CAPTURE_DURATION.as_secs_f64() * SAMPLE_RATE as f64;
+    if levels.samples == 0 {
+        Outcome::new(
+            CheckStatus::Fail,
+            "The microphone didn't deliver any audio",
+            data,
+        )
+    } else if (levels.samples as f64) < expected / 2.0 {
+        Outcome::new(
+            CheckStatus::Warn,
+            "The microphone delivered less audio than expected",
+            data,
+        )
+    } else if peak_dbfs < SILENCE_DBFS {
+        Outcome::new(
+            CheckStatus::Warn,
+            "The microphone only picked up silence; it may be muted",
+            data,
+        )
+    } else {
+        Outcome::new(CheckStatus::Pass, "The microphone works", data)
+    }
+}
+
+fn playback() -> Outcome {
+    let Some(device) = cpal::default_host().default_output_device() else {
+        return Outcome::fail("No output device");
+    };
+    let name = device
+        .description()
+        .map(|description| description.name().to_string())
+        .ok();
+    let supported = match device.default_output_config() {
+        Ok(supported) => supported,
+        Err(e) => return Outcome::fail(e),
+    };
+    let config = supported.config();
+
+    let frames = Arc::new(Mutex::new(0u64));
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => tone::<f32>(&device, &config, frames.clone()),
+        SampleFormat::I16 => tone::<i16>(&device, &config, frames.clone()),
+        SampleFormat::U16 => tone::<u16>(&device, &config, frames.clone()),
+        SampleFormat::I32 => tone::<i32>(&device, &config, frames.clone()),
+        format => return Outcome::fail(format!("Output sample format {} isn't supported", format)),
+    };
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => return Outcome::fail(e),
+    };
+    if let Err(e) = stream.play() {
+        return Outcome::fail(e);
+    }
+    std::thread::sleep(TONE_DURATION);
+    drop(stream);
+
+    let frames = *frames.lock().unwrap_or_else(|e| e.into_inner());
+    let data = json!({
+        "device": name,
+        "sample_rate": config.sample_rate,
+        "channels": config.channels,
+        "frames": frames,
+    });
+    if frames == 0 {
+        Outcome::new(
+            CheckStatus::Fail,
+            "The output device didn't play anything",
+            data,
+        )
+    } else {
+        Outcome::new(CheckStatus::Pass, "A test tone was played", data)
+    }
+}
+
+fn tone<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    frames: Arc<Mutex<u64>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels.max(1) as usize;
+    let step = TONE_HZ * std::f32::consts::TAU / config.sample_rate.max(1) as f32;
+    let mut phase = 0.0_f32;
+    device.build_output_stream::<T, _, _>(
+        config,
+        move |data: &mut [T], _| {
+            for frame in data.chunks_mut(channels) {
+                let sample = T::from_sample(phase.sin() * TONE_AMPLITUDE);
+                frame.iter_mut().for_each(|s| *s = sample);
+                phase = (phase + step) % std::f32::consts::TAU;
+            }
+            *frames.lock().unwrap_or_else(|e| e.into_inner()) += (data.len() / channels) as u64;
+        },
+        |e| log::error!("Self-test output stream error: {}", e),
+        None,
+    )
+}
+
+/// Encode a second of test tone and decode it again; the decoded tone
+/// should carry about the same energy as the original
+fn encoder_round_trip() -> Outcome {
+    const FRAME: usize = SAMPLE_RATE as usize / 50;
+    const MAX_PACKET: usize = 1275;
+
+    let run = || -> Result<(f32, usize), opus::Error> {
+        let mut encoder = Encoder::new(SAMPLE_RATE, Channels::Mono, Application::Voip)?;
+        encoder.set_bitrate(Bitrate::Bits(32_000))?;
+        let mut decoder = Decoder::new(SAMPLE_RATE, Channels::Mono)?;
+
+        let step = TONE_HZ * std::f32::consts::TAU / SAMPLE_RATE as f32;
+        let input = (0..SAMPLE_RATE as usize)
+            .map(|i| (i as f32 * step).sin() * 0.5)
+            .collect::<Vec<_>>();
+
+        let mut packet = [0u8; MAX_PACKET];
+        let mut decoded = vec![0.0; FRAME];
+        let mut bytes = 0;
+        let mut energy_in = 0.0_f64;
+        let mut energy_out = 0.0_f64;
+        for (i, frame) in input.chunks_exact(FRAME).enumerate() {
+            let len = encoder.encode_float(frame, &mut packet)?;
+            bytes += len;
+            let decoded_len = decoder.decode_float(&packet[..len], &mut decoded, false)?;
+            // The first frames are the codec settling in
+            if i >= 5 {
+                energy_in += frame.iter().map(|s| (*s as f64).powi(2)).sum::<f64>();
+                energy_out += decoded[..decoded_len]
+                    .iter()
+                    .map(|s| (*s as f64).powi(2))
+                    .sum::<f64>();
+            }
+        }
+        Ok(((energy_out / energy_in.max(f64::EPSILON)) as f32, bytes))
+    };
+
+    match run() {
+        Ok((ratio, bytes)) => {
+            let data = json!({ "energy_ratio": ratio, "encoded_bytes": bytes });
+            if (0.5..2.0).contains(&ratio) {
+                Outcome::new(CheckStatus::Pass, "Opus encodes and decodes audio", data)
+            } else {
+                Outcome::new(
+                    CheckStatus::Fail,
+                    "Decoded audio doesn't match what was encoded",
+                    data,
+                )
+            }
+        }
+        Err(e) => Outcome::fail(e),
+    }
+}
+
+fn network() -> Vec<CheckResult> {
+    let started = Instant::now();
+    let socket = match std::net::UdpSocket::bind("0.0.0.0:0") {
+        Ok(socket) => socket,
+        Err(e) => {
+            return vec![
+                result(Check::Udp, Outcome::fail(e), started.elapsed(), None),
+                skipped(Check::Stun, "UDP isn't available"),
+            ]
+        }
+    };
+
+    let first = stun::binding(&socket, STUN_SERVERS[0], STUN_TIMEOUT);
+    let (mapped, rtt) = match first {
+        Ok(reply) => reply,
+        Err(e) => {
+            return vec![
+                result(
+                    Check::Udp,
+                    Outcome::new(
+                        CheckStatus::Fail,
+                        format!("UDP seems to be blocked: {}", e),
+                        json!({ "server": STUN_SERVERS[0] }),
+                    ),
+                    started.elapsed(),
+                    None,
+                ),
+                skipped(Check::Stun, "UDP seems to be blocked"),
+            ]
+        }
+    };
+    let udp = result(
+        Check::Udp,
+        Outcome::new(
+            CheckStatus::Pass,
+            "UDP gets through",
+            json!({ "server": STUN_SERVERS[0], "rtt_ms": rtt.as_millis() as u64 }),
+        ),
+        started.elapsed(),
+        None,
+    );
+
+    let started = Instant::now();
+    let outcome = match stun::binding(&socket, STUN_SERVERS[1], STUN_TIMEOUT) {
+        Ok((second, _)) => {
+            let data = json!({ "mapped_addresses": [mapped.to_string(), second.to_string()] });
+            if second == mapped {
+                Outcome::new(
+                    CheckStatus::Pass,
+                    "The network maps UDP consistently; direct call connections should work",
+                    data,
+                )
+            } else {
+                Outcome::new(
+                    CheckStatus::Warn,
+                    "The network maps UDP differently for each server; calls may need a relay",
+                    data,
+                )
+            }
+        }
+        Err(e) => Outcome::new(
+            CheckStatus::Warn,
+            format!("Only one STUN server answered: {}", e),
+            json!({ "mapped_addresses": [mapped.to_string()] }),
+        ),
+    };
+    vec![udp, result(Check::Stun, outcome, started.elapsed(), None)]
+}
+
+fn skipped(check: Check, detail: &str) -> CheckResult {
+    result(
+        check,
+        Outcome::new(CheckStatus::Skipped, detail, json!({})),
+        Duration::ZERO,
+        None,
+    )
+}
+
+/// Time round trips to the instance's gateway endpoint. Without a token or
+/// an upgrade the server turns the request down straight away, which is
+/// all it takes to measure.
+async fn gateway(instance_url: &str) -> Outcome {
+    let url = format!("{}/ws", instance_url.trim_end_matches('/'));
+    let client = match reqwest::Client::builder().timeout(GATEWAY_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => return Outcome::fail(e),
+    };
+
+    let mut rtts = Vec::new();
+    for _ in 0..GATEWAY_PINGS {
+        let started = Instant::now();
+        match client.get(&url).send().await {
+            Ok(_) => rtts.push(started.elapsed()),
+            Err(e) => return Outcome::fail(format!("The gateway didn't answer: {}", e)),
+        }
+    }
+    rtts.sort();
+    let median = rtts[rtts.len() / 2];
+    let data = json!({
+        "rtt_ms": rtts.iter().map(|rtt| rtt.as_millis() as u64).collect::<Vec<_>>(),
+        "median_ms": median.as_millis() as u64,
+    });
+    if median > SLOW_GATEWAY {
+        Outcome::new(
+            CheckStatus::Warn,
+            format!("The gateway is slow to answer ({} ms)", median.as_millis()),
+            data,
+        )
+    } else {
+        Outcome::new(
+            CheckStatus::Pass,
+            format!("The gateway answers in {} ms", median.as_millis()),
+            data,
+        )
+    }
+}
+
+fn dbfs(level: f32) -> f32 {
+    20.0 * level.max(1e-6).log10()
+}
@@ -0,0 +1,162 @@
+//! STUN binding requests (RFC 5389), just enough to learn the address a
+//! UDP socket is seen from outside, or to time a round trip to a relay
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_RESPONSE: u16 = 0x0101;
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const MAPPED_ADDRESS: u16 = 0x0001;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const HEADER_LEN: usize = 20;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum StunError {
+    #[error("no reply from {0}")]
+    Timeout(String),
+    #[error("{0} sent a malformed reply")]
+    Malformed(String),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Ask `server` for the socket's mapped address, retrying within `timeout`
+pub(crate) fn binding(
+    socket: &UdpSocket,
+    server: &str,
+    timeout: Duration,
+) -> Result<(SocketAddr, Duration), StunError> {
+    let (transaction, request) = request();
+    let started = Instant::now();
+    let mut buffer = [0u8; 512];
+    // UDP can drop packets; resend a few times before giving up
+    let attempts = 3;
+    socket.set_read_timeout(Some(timeout / attempts))?;
+    for _ in 0..attempts {
+        socket.send_to(&request, server)?;
+        let len = match socket.recv(&mut buffer) {
+            Ok(len) => len,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let reply = &buffer[..len];
+        if reply.len() < HEADER_LEN || reply[8..20] != transaction {
+            // Something else, or a late reply to an earlier attempt
+            continue;
+        }
+        let address =
+            parse(reply, &transaction).ok_or_else(|| StunError::Malformed(server.to_string()))?;
+        return Ok((address, started.elapsed()));
+    }
+    Err(StunError::Timeout(server.to_string()))
+}
+
+/// Time a single binding request to `server`, without resending, so a
+/// lost packet shows up as a timeout rather than a slow reply
+pub(crate) fn round_trip(
+    socket: &UdpSocket,
+    server: SocketAddr,
+    timeout: Duration,
+) -> Result<Duration, StunError> {
+    let (transaction, request) = request();
+    let started = Instant::now();
+    let mut buffer = [0u8; 512];
+    socket.send_to(&request, server)?;
+    loop {
+        let Some(left) = timeout
+            .checked_sub(started.elapsed())
+            .filter(|left| !left.is_zero())
+        else {
+            return Err(StunError::Timeout(server.to_string()));
+        };
+        socket.set_read_timeout(Some(left))?;
+        let len = match socket.recv(&mut buffer) {
+            Ok(len) => len,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Err(StunError::Timeout(server.to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let reply = &buffer[..len];
+        if reply.len() >= HEADER_LEN && reply[8..20] == transaction {
+            return Ok(started.elapsed());
+        }
+    }
+}
+
+/// A binding request with a fresh transaction id
+fn request() -> ([u8; 12], Vec<u8>) {
+    let transaction = *uuid::Uuid::new_v4().as_bytes();
+    let transaction: [u8; 12] = transaction[..12].try_into().expect("slice is 12 bytes");
+    let mut request = Vec::with_capacity(HEADER_LEN);
+    request.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+    request.extend_from_slice(&0u16.to_be_bytes());
+    request.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request.extend_from_slice(&transaction);
+    (transaction, request)
+}
+
+fn parse(reply: &[u8], transaction: &[u8; 12]) -> Option<SocketAddr> {
+    if u16::from_be_bytes([reply[0], reply[1]]) != BINDING_RESPONSE {
+        return None;
+    }
+    let len = u16::from_be_bytes([reply[2], reply[3]]) as usize;
+    let mut attributes = reply.get(HEADER_LEN..HEADER_LEN + len)?;
+
+    let mut mapped = None;
+    while attributes.len() >= 4 {
+        let kind = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let len = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+        let value = attributes.get(4..4 + len)?;
+        match kind {
+            XOR_MAPPED_ADDRESS => return address(value, Some(transaction)),
+            MAPPED_ADDRESS => mapped = address(value, None),
+            _ => {}
+        }
+        // Attributes are padded to four bytes
+        attributes = attributes
+            .get((4 + len).next_multiple_of(4)..)
+            .unwrap_or_default();
+    }
+    mapped
+}
+
+/// Decode a (XOR-)MAPPED-ADDRESS value; XORed if `transaction` is given
+fn address(value: &[u8], transaction: Option<&[u8; 12]>) -> Option<SocketAddr> {
+    let family = *value.get(1)?;
+    let mut port = u16::from_be_bytes([*value.get(2)?, *value.get(3)?]);
+    let mut key = [0u8; 16];
+    if let Some(transaction) = transaction {
+        port ^= (MAGIC_COOKIE >> 16) as u16;
+        key[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        key[4..].copy_from_slice(transaction);
+    }
+
+    let ip = match family {
+        0x01 => {
+            let mut octets: [u8; 4] = value.get(4..8)?.try_into().ok()?;
+            octets.iter_mut().zip(&key).for_each(|(o, k)| *o ^= k);
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        0x02 => {
+            let mut octets: [u8; 16] = value.get(4..20)?.try_into().ok()?;
+            octets.iter_mut().zip(&key).for_each(|(o, k)| *o ^= k);
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
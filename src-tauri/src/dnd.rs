@@ -0,0 +1,191 @@
+//! Do not disturb
+//!
+//! A schedule of quiet hours kept in the settings and evaluated here, so
+//! notifications and the ringtone stay quiet on schedule whether or not
+//! the frontend is loaded. The schedule has weekly rules ("22:00 to 07:00
+//! on weeknights", where a rule ending before it starts runs into the next
+//! day) and exceptions covering a stretch of local time, which win over
+//! the rules in either direction: quiet through a holiday, or not quiet
+//! for an evening that would otherwise be.
+//!
+//! Native notifications are dropped while it's quiet (see
+//! [`crate::notifications`]) and calls don't ring, though they're still
+//! reported. A thread checks the schedule every [`CHECK_INTERVAL`] and
+//! emits "do-not-disturb" when it starts or ends, for the frontend to hold
+//! back its own sounds.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::db::Database;
+use crate::{native_theme, settings};
+
+/// Setting holding the [`DndSchedule`]
+pub const DND_SCHEDULE_SETTING: &str = "notifications.dnd_schedule";
+
+/// Most rules and exceptions a schedule can have
+pub const MAX_RULES: usize = 32;
+pub const MAX_EXCEPTIONS: usize = 64;
+
+/// How often the schedule is checked for "do-not-disturb"
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, thiserror::Error)]
+pub enum DndError {
+    #[error("a schedule can have at most {MAX_RULES} rules and {MAX_EXCEPTIONS} exceptions")]
+    TooMany,
+    #[error("a rule needs at least one day")]
+    NoDays,
+    #[error("an exception has to end after it starts")]
+    EmptyException,
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DndSchedule {
+    pub enabled: bool,
+    pub rules: Vec<QuietRule>,
+    pub exceptions: Vec<QuietException>,
+}
+
+/// Quiet hours on some days of the week, in local time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuietRule {
+    /// Days the quiet hours start on
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    /// At or before `start` for quiet hours that run past midnight
+    pub end: NaiveTime,
+}
+
+/// A stretch of local time that's quiet, or not, whatever the rules say
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuietException {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub quiet: bool,
+    #[serde(default)]
+    pub label: String,
+}
+
+/// Payload of "do-not-disturb"
+#[derive(Debug, Clone, Serialize)]
+pub struct DndState {
+    pub active: bool,
+}
+
+impl QuietRule {
+    fn covers(&self, at: NaiveDateTime) -> bool {
+        let time = at.time();
+        let today = self.days.contains(&at.weekday());
+        if self.start < self.end {
+            return today && self.start <= time && time < self.end;
+        }
+        // Runs into the next day, or all day when start and end are equal
+        let yesterday = self.days.contains(&at.weekday().pred());
+        (today && time >= self.start) || (yesterday && time < self.end)
+    }
+}
+
+impl DndSchedule {
+    /// Whether it's quiet at `at`, in local time
+    pub fn is_quiet(&self, at: NaiveDateTime) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        // Later exceptions are the more specific ones when they overlap
+        if let Some(exception) = self
+            .exceptions
+            .iter()
+            .rev()
+            .find(|e| e.start <= at && at < e.end)
+        {
+            return exception.quiet;
+        }
+        self.rules.iter().any(|rule| rule.covers(at))
+    }
+
+    fn validate(&self) -> Result<(), DndError> {
+        if self.rules.len() > MAX_RULES || self.exceptions.len() > MAX_EXCEPTIONS {
+            return Err(DndError::TooMany);
+        }
+        if self.rules.iter().any(|rule| rule.days.is_empty()) {
+            return Err(DndError::NoDays);
+        }
+        if self.exceptions.iter().any(|e| e.end <= e.start) {
+            return Err(DndError::EmptyException);
+        }
+        Ok(())
+    }
+}
+
+/// Last state reported in "do-not-disturb"
+#[derive(Default)]
+pub struct DoNotDisturb {
+    active: AtomicBool,
+}
+
+pub fn schedule(db: &Database) -> Result<DndSchedule, DndError> {
+    let stored = db.with(|conn| settings::get_value(conn, DND_SCHEDULE_SETTING))?;
+    Ok(stored
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| log::warn!("Ignoring malformed do not disturb schedule: {}", e))
+                .ok()
+        })
+        .unwrap_or_default())
+}
+
+/// Store a new schedule. Emits "do-not-disturb" if that changes whether
+/// it's quiet.
+pub fn set_schedule(app: &AppHandle, schedule: DndSchedule) -> Result<DndState, DndError> {
+    schedule.validate()?;
+    let value = serde_json::to_value(&schedule).expect("schedules serialize");
+    app.state::<Database>()
+        .with(|conn| settings::set(conn, DND_SCHEDULE_SETTING, &value))?;
+    Ok(check(app))
+}
+
+/// Whether notifications and sounds should be held back right now
+pub fn is_active(app: &AppHandle) -> bool {
+    match schedule(&app.state::<Database>()) {
+        Ok(schedule) => schedule.is_quiet(chrono::Local::now().naive_local()),
+        Err(e) => {
+            log::error!("Failed to read the do not disturb schedule: {}", e);
+            false
+        }
+    }
+}
+
+/// Start checking the schedule; call once during setup
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("dnd-schedule".to_string())
+        .spawn(move || loop {
+            check(&app);
+            std::thread::sleep(CHECK_INTERVAL);
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to start the do not disturb schedule: {}", e);
+    }
+}
+
+fn check(app: &AppHandle) -> DndState {
+    let active = is_active(app);
+    let was = app
+        .state::<DoNotDisturb>()
+        .active
+        .swap(active, Ordering::Relaxed);
+    if was != active {
+        let _ = app.emit("do-not-disturb", DndState { active });
+        native_theme::refresh_tray(app);
+    }
+    DndState { active }
+}
@@ -0,0 +1,195 @@
+//! File-type verification
+//!
+//! Compares a downloaded file's contents against the extension it was saved
+//! with and flags anything the OS would run rather than display.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// How many leading bytes are read to identify a file
+const SNIFF_LEN: usize = 8192;
+
+/// Extensions the OS executes, installs or follows when opened
+const DANGEROUS_EXTENSIONS: &[(&str, Danger)] = &[
+    ("exe", Danger::Executable),
+    ("com", Danger::Executable),
+    ("scr", Danger::Executable),
+    ("pif", Danger::Executable),
+    ("cpl", Danger::Executable),
+    ("dll", Danger::Executable),
+    ("sys", Danger::Executable),
+    ("elf", Danger::Executable),
+    ("bin", Danger::Executable),
+    ("run", Danger::Executable),
+    ("appimage", Danger::Executable),
+    ("app", Danger::Executable),
+    ("jar", Danger::Executable),
+    ("msc", Danger::Executable),
+    ("bat", Danger::Script),
+    ("cmd", Danger::Script),
+    ("ps1", Danger::Script),
+    ("psm1", Danger::Script),
+    ("vbs", Danger::Script),
+    ("vbe", Danger::Script),
+    ("js", Danger::Script),
+    ("jse", Danger::Script),
+    ("wsf", Danger::Script),
+    ("wsh", Danger::Script),
+    ("hta", Danger::Script),
+    ("sh", Danger::Script),
+    ("bash", Danger::Script),
+    ("zsh", Danger::Script),
+    ("command", Danger::Script),
+    ("applescript", Danger::Script),
+    ("scpt", Danger::Script),
+    ("py", Danger::Script),
+    ("pl", Danger::Script),
+    ("rb", Danger::Script),
+    ("reg", Danger::Script),
+    ("chm", Danger::Script),
+    ("terminal", Danger::Script),
+    ("msi", Danger::Installer),
+    ("msix", Danger::Installer),
+    ("msp", Danger::Installer),
+    ("appx", Danger::Installer),
+    ("appxbundle", Danger::Installer),
+    ("pkg", Danger::Installer),
+    ("mpkg", Danger::Installer),
+    ("dmg", Danger::Installer),
+    ("deb", Danger::Installer),
+    ("rpm", Danger::Installer),
+    ("apk", Danger::Installer),
+    // Disk images mount with one click and can carry any of the above
+    ("iso", Danger::Installer),
+    ("img", Danger::Installer),
+    ("vhd", Danger::Installer),
+    ("vhdx", Danger::Installer),
+    ("lnk", Danger::Shortcut),
+    ("url", Danger::Shortcut),
+    ("desktop", Danger::Shortcut),
+    ("webloc", Danger::Shortcut),
+    ("scf", Danger::Shortcut),
+    ("inf", Danger::Shortcut),
+];
+
+/// Extensions that legitimately hold a container of a different detected type
+const CONTAINER_EXTENSIONS: &[(&str, &[&str])] = &[
+    (
+        "zip",
+        &[
+            "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk", "xpi", "cbz", "kmz",
+            "msix", "appx", "vsix", "nupkg", "whl",
+        ],
+    ),
+    ("cfb", &["doc", "xls", "ppt", "msi", "msg"]),
+    ("mp4", &["m4v", "m4a", "mov"]),
+    ("mov", &["mp4", "m4v"]),
+    ("mkv", &["webm"]),
+    ("ogg", &["oga", "ogv", "opus"]),
+    ("xml", &["svg", "plist"]),
+    ("gz", &["tgz"]),
+];
+
+/// Different spellings of the same extension
+const EXTENSION_ALIASES: &[&[&str]] = &[
+    &["jpg", "jpeg", "jpe", "jfif"],
+    &["tif", "tiff"],
+    &["htm", "html"],
+    &["mid", "midi"],
+    &["heic", "heif"],
+];
+
+/// Why a file shouldn't be opened without asking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Danger {
+    Executable,
+    Script,
+    Installer,
+    /// Links and launchers that open something else
+    Shortcut,
+}
+
+/// Result of inspecting a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileCheck {
+    /// Extension of the name the file was saved under
+    pub claimed_extension: Option<String>,
+    /// Type identified from the file's contents
+    pub detected_mime: Option<String>,
+    pub detected_extension: Option<String>,
+    /// The contents don't match the claimed extension
+    pub mismatch: bool,
+    pub danger: Option<Danger>,
+}
+
+impl FileCheck {
+    /// Opening the file needs explicit confirmation from the user
+    pub fn requires_confirmation(&self) -> bool {
+        self.mismatch || self.danger.is_some()
+    }
+}
+
+/// Inspect the file at `path` against the name it was saved under, which is
+/// what the OS goes by when opening it, not the name it was sent with
+pub fn inspect(path: &Path) -> io::Result<FileCheck> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)?;
+    let filename = path.file_name().unwrap_or_default().to_string_lossy();
+    Ok(check(&head, &filename))
+}
+
+/// Check the leading bytes of a file against its name
+pub fn check(head: &[u8], filename: &str) -> FileCheck {
+    let claimed = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let detected = infer::get(head);
+
+    let mismatch = match (&claimed, &detected) {
+        (Some(claimed), Some(detected)) => !extension_matches(claimed, detected.extension()),
+        // Binary formats we can identify should be identified
+        (Some(claimed), None) => infer::is_supported(claimed) && !head.is_empty(),
+        (None, _) => false,
+    };
+
+    let danger = claimed
+        .as_deref()
+        .and_then(|ext| {
+            DANGEROUS_EXTENSIONS
+                .iter()
+                .find(|(e, _)| *e == ext)
+                .map(|(_, danger)| *danger)
+        })
+        .or_else(|| {
+            detected.and_then(|t| match t.matcher_type() {
+                infer::MatcherType::App => Some(Danger::Executable),
+                _ => None,
+            })
+        })
+        .or_else(|| head.starts_with(b"#!").then_some(Danger::Script));
+
+    FileCheck {
+        claimed_extension: claimed,
+        detected_mime: detected.map(|t| t.mime_type().to_string()),
+        detected_extension: detected.map(|t| t.extension().to_string()),
+        mismatch,
+        danger,
+    }
+}
+
+fn extension_matches(claimed: &str, detected: &str) -> bool {
+    claimed == detected
+        || EXTENSION_ALIASES
+            .iter()
+            .any(|group| group.contains(&claimed) && group.contains(&detected))
+        || CONTAINER_EXTENSIONS
+            .iter()
+            .any(|(container, inner)| *container == detected && inner.contains(&claimed))
+}
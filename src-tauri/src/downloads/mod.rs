@@ -0,0 +1,425 @@
+//! Attachment downloads
+//!
+//! Downloads are streamed into the user's download directory and checked
+//! with [`filetype`] once complete. Files whose contents don't match their
+//! extension, or that the OS would execute, are only handed to the OS after
+//! the user has explicitly confirmed opening them in a native dialog that
+//! names the file and why it was flagged, which the webview can't answer.
+
+pub mod filetype;
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tauri_plugin_opener::OpenerExt;
+use tokio::io::AsyncWriteExt;
+
+use crate::api::{ApiClient, ApiError};
+use crate::auth::{self, AuthError};
+use crate::coalesce;
+use crate::data_usage::{self, Category};
+use crate::db::Database;
+use crate::instances::Instance;
+use filetype::{Danger, FileCheck};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A downloaded attachment
+#[derive(Debug, Clone, Serialize)]
+pub struct Download {
+    pub id: String,
+    pub instance_id: String,
+    pub attachment_id: String,
+    /// File name the attachment was sent with
+    pub filename: String,
+    /// Where the file was saved, which may differ from `filename` to avoid collisions
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+    pub check: FileCheck,
+    /// The user chose to open the file despite the check flagging it
+    pub confirmed: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Download {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let check: String = row.get("file_check")?;
+        Ok(Self {
+            id: row.get("id")?,
+            instance_id: row.get("instance_id")?,
+            attachment_id: row.get("attachment_id")?,
+            filename: row.get("filename")?,
+            path: PathBuf::from(row.get::<_, String>("path")?),
+            size: row.get::<_, i64>("size")? as u64,
+            sha256: row.get("sha256")?,
+            check: serde_json::from_str(&check).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())
+            })?,
+            confirmed: row.get("confirmed")?,
+            created_at: row.get("created_at")?,
+        })
+    }
+
+    /// Opening needs confirmation the user hasn't given yet
+    pub fn is_blocked(&self) -> bool {
+        self.check.requires_confirmation() && !self.confirmed
+    }
+}
+
+/// Payload of the "download-progress" event
+#[derive(Debug, Clone, Serialize)]
+struct DownloadProgress<'a> {
+    attachment_id: &'a str,
+    received: u64,
+    total: Option<u64>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    #[error("download not found: {0}")]
+    NotFound(String),
+    #[error("{0} may be unsafe to open and needs confirmation")]
+    Unsafe(String),
+    #[error("{0} wasn't opened")]
+    Declined(String),
+    #[error("{0} no longer exists")]
+    Missing(PathBuf),
+    #[error("no download directory available")]
+    NoDownloadDir,
+    #[error("{0}")]
+    Auth(#[from] AuthError),
+    #[error("{0}")]
+    Api(#[from] ApiError),
+    #[error("failed to save download: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to open file: {0}")]
+    Opener(#[from] tauri_plugin_opener::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// Download an attachment into the download directory and check it
+/// Emits "download-progress" while the body is streamed
+pub async fn download(
+    app: &AppHandle,
+    db: &Database,
+    profile_id: &str,
+    instance: &Instance,
+    attachment_id: &str,
+    filename: &str,
+) -> Result<Download, DownloadError> {
+    let token = auth::access_token(app, profile_id, instance).await?;
+    let mut response = ApiClient::new(&instance.url)
+        .attachment(&token, attachment_id)
+        .await?;
+
+    let dir = app
+        .path()
+        .download_dir()
+        .map_err(|_| DownloadError::NoDownloadDir)?;
+    tokio::fs::create_dir_all(&dir).await?;
+    let path = unique_path(&dir, &sanitize_filename(filename));
+    let partial = path.with_extension(match path.extension() {
+        Some(ext) => format!("{}.part", ext.to_string_lossy()),
+        None => "part".to_string(),
+    });
+
+    let total = response.content_length();
+    let mut file = tokio::fs::File::create(&partial).await?;
+    let mut hasher = Sha256::new();
+    let mut received = 0u64;
+    let mut last_progress = Instant::now();
+
+    let result: Result<(), DownloadError> = async {
+        while let Some(chunk) = response.chunk().await.map_err(ApiError::from)? {
+            file.write_all(&chunk).await?;
+            hasher.update(&chunk);
+            received += chunk.len() as u64;
+            data_usage::record_for(app, profile_id, Category::Media, 0, chunk.len() as u64);
+
+            if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                last_progress = Instant::now();
+                emit_progress(app, attachment_id, received, total);
+            }
+        }
+        file.flush().await?;
+        Ok(())
+    }
+    .await;
+    drop(file);
+
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&partial, &path).await?;
+    emit_progress(app, attachment_id, received, total);
+
+    if let Err(e) = mark_downloaded(&path, &instance.url) {
+        log::warn!("Failed to mark {} as downloaded: {}", path.display(), e);
+    }
+
+    let download = Download {
+        id: uuid::Uuid::new_v4().to_string(),
+        instance_id: instance.id.clone(),
+        attachment_id: attachment_id.to_string(),
+        filename: filename.to_string(),
+        check: filetype::inspect(&path)?,
+        path,
+        size: received,
+        sha256: hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+        confirmed: false,
+        created_at: Utc::now(),
+    };
+    db.with(|conn| insert(conn, &download))?;
+
+    Ok(download)
+}
+
+/// Open a download with the OS default handler, refusing flagged files the
+/// user hasn't confirmed
+pub fn open(app: &AppHandle, db: &Database, id: &str) -> Result<Download, DownloadError> {
+    let download = rechecked(db, id)?;
+    if download.is_blocked() {
+        return Err(DownloadError::Unsafe(download.filename));
+    }
+
+    app.opener()
+        .open_path(download.path.to_string_lossy(), None::<&str>)?;
+    Ok(download)
+}
+
+/// Ask the user in a native dialog whether a flagged download should be
+/// opened anyway, naming it and why it was flagged, and open it if so
+/// Blocks until the dialog is answered, so don't call it on the main thread
+pub fn confirm_and_open(
+    app: &AppHandle,
+    db: &Database,
+    id: &str,
+) -> Result<Download, DownloadError> {
+    let download = rechecked(db, id)?;
+    if download.is_blocked() {
+        let confirmed = app
+            .dialog()
+            .message(format!(
+                "{} may be unsafe to open: {}. Only open it if you trust whoever sent it.",
+                download
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+                flagged_because(&download.check)
+            ))
+            .title("Open this file?")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::OkCancelCustom(
+                "Open".to_string(),
+                "Cancel".to_string(),
+            ))
+            .blocking_show();
+        if !confirmed {
+            return Err(DownloadError::Declined(download.filename));
+        }
+        db.with(|conn| confirm(conn, id))?;
+    }
+    open(app, db, id)
+}
+
+/// Get a download that's still there, checking the file again in case it
+/// was replaced since it was downloaded
+fn rechecked(db: &Database, id: &str) -> Result<Download, DownloadError> {
+    let mut download = db
+        .with(|conn| get(conn, id))?
+        .ok_or_else(|| DownloadError::NotFound(id.to_string()))?;
+
+    if !download.path.exists() {
+        return Err(DownloadError::Missing(download.path));
+    }
+
+    let check = filetype::inspect(&download.path)?;
+    if check != download.check {
+        download.check = check;
+        download.confirmed = false;
+        db.with(|conn| update_check(conn, &download))?;
+    }
+    Ok(download)
+}
+
+/// Why a file needs confirmation, for the dialog
+fn flagged_because(check: &FileCheck) -> String {
+    let danger = check.danger.map(|danger| match danger {
+        Danger::Executable => "it's a program".to_string(),
+        Danger::Script => "it's a script that can run commands".to_string(),
+        Danger::Installer => "it installs or mounts software".to_string(),
+        Danger::Shortcut => "it's a shortcut that opens something else".to_string(),
+    });
+    let mismatch = check.mismatch.then(|| match &check.claimed_extension {
+        Some(extension) => format!("its contents don't match its .{} extension", extension),
+        None => "its contents don't match its name".to_string(),
+    });
+    [danger, mismatch]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", and ")
+}
+
+fn confirm(conn: &Connection, id: &str) -> rusqlite::Result<()> {
+    conn.prepare_cached("UPDATE downloads SET confirmed = 1 WHERE id = ?1")?
+        .execute([id])?;
+    Ok(())
+}
+
+/// Show a download in the system file manager
+pub fn reveal(app: &AppHandle, db: &Database, id: &str) -> Result<(), DownloadError> {
+    let download = db
+        .with(|conn| get(conn, id))?
+        .ok_or_else(|| DownloadError::NotFound(id.to_string()))?;
+    if !download.path.exists() {
+        return Err(DownloadError::Missing(download.path));
+    }
+
+    app.opener().reveal_item_in_dir(&download.path)?;
+    Ok(())
+}
+
+pub fn list(conn: &Connection) -> rusqlite::Result<Vec<Download>> {
+    let mut stmt = conn.prepare_cached("SELECT * FROM downloads ORDER BY created_at DESC")?;
+    let rows = stmt.query_map([], Download::from_row)?;
+    rows.collect()
+}
+
+pub fn get(conn: &Connection, id: &str) -> rusqlite::Result<Option<Download>> {
+    conn.prepare_cached("SELECT * FROM downloads WHERE id = ?1")?
+        .query_row([id], Download::from_row)
+        .optional()
+}
+
+/// Forget a download, optionally deleting the file as well
+pub fn remove(db: &Database, id: &str, delete_file: bool) -> Result<(), DownloadError> {
+    let download = db
+        .with(|conn| get(conn, id))?
+        .ok_or_else(|| DownloadError::NotFound(id.to_string()))?;
+    if delete_file && download.path.exists() {
+        std::fs::remove_file(&download.path)?;
+    }
+
+    db.with(|conn| {
+        conn.prepare_cached("DELETE FROM downloads WHERE id = ?1")?
+            .execute([id])
+    })?;
+    Ok(())
+}
+
+fn insert(conn: &Connection, download: &Download) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO downloads
+            (id, instance_id, attachment_id, filename, path, size, sha256, file_check, confirmed, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )?
+    .execute(params![
+        download.id,
+        download.instance_id,
+        download.attachment_id,
+        download.filename,
+        download.path.to_string_lossy(),
+        download.size as i64,
+        download.sha256,
+        serde_json::to_string(&download.check).expect("file checks always serialize"),
+        download.confirmed,
+        download.created_at,
+    ])?;
+    Ok(())
+}
+
+fn update_check(conn: &Connection, download: &Download) -> rusqlite::Result<()> {
+    conn.prepare_cached("UPDATE downloads SET file_check = ?2, confirmed = ?3 WHERE id = ?1")?
+        .execute(params![
+            download.id,
+            serde_json::to_string(&download.check).expect("file checks always serialize"),
+            download.confirmed,
+        ])?;
+    Ok(())
+}
+
+fn emit_progress(app: &AppHandle, attachment_id: &str, received: u64, total: Option<u64>) {
+    coalesce::emit(
+        app,
+        "download-progress",
+        attachment_id,
+        DownloadProgress {
+            attachment_id,
+            received,
+            total,
+        },
+    );
+}
+
+/// Reduce a server-supplied file name to a single safe path component
+fn sanitize_filename(filename: &str) -> String {
+    let name: String = filename
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+
+    if name.is_empty() {
+        "attachment".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// `dir/name`, or `dir/name (n).ext` if that's already taken
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return candidate;
+    }
+
+    let stem = Path::new(name)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = Path::new(name)
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .expect("some numbered name is free")
+}
+
+/// Tag the file as downloaded from the internet so the OS applies its own
+/// checks (SmartScreen, Office protected view) when it's opened
+#[cfg(windows)]
+fn mark_downloaded(path: &Path, source_url: &str) -> std::io::Result<()> {
+    let mut stream = path.as_os_str().to_owned();
+    stream.push(":Zone.Identifier");
+    std::fs::write(
+        stream,
+        format!("[ZoneTransfer]\r\nZoneId=3\r\nHostUrl={}\r\n", source_url),
+    )
+}
+
+#[cfg(not(windows))]
+fn mark_downloaded(_path: &Path, _source_url: &str) -> std::io::Result<()> {
+    Ok(())
+}
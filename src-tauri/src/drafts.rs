@@ -0,0 +1,216 @@
+//! Message drafts
+//!
+//! The composer calls `save_draft` on every edit. Saves are buffered in
+//! memory and written to the database once a conversation has been idle
+//! for [`DEBOUNCE`], so typing doesn't hit the disk per keystroke but a
+//! crash or webview reload loses at most the last moment of input.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::db::Database;
+
+/// How long a conversation has to be idle before its draft is written
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Unsent composer state for one conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Draft {
+    pub instance_id: String,
+    pub channel_id: String,
+    pub content: String,
+    /// Local files attached to the draft
+    #[serde(default)]
+    pub attachments: Vec<PathBuf>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Draft {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let attachments: String = row.get("attachments")?;
+        Ok(Self {
+            instance_id: row.get("instance_id")?,
+            channel_id: row.get("channel_id")?,
+            content: row.get("content")?,
+            attachments: serde_json::from_str(&attachments).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())
+            })?,
+            updated_at: row.get("updated_at")?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.content.trim().is_empty() && self.attachments.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DraftKey {
+    instance_id: String,
+    channel_id: String,
+}
+
+struct Pending {
+    draft: Draft,
+    /// Bumped on every save so only the latest one's timer writes
+    generation: u64,
+}
+
+pub struct Drafts {
+    db: Database,
+    pending: Mutex<HashMap<DraftKey, Pending>>,
+    generations: AtomicU64,
+}
+
+impl Drafts {
+    pub fn new(db: Database) -> Self {
+        Self {
+            db,
+            pending: Mutex::new(HashMap::new()),
+            generations: AtomicU64::new(0),
+        }
+    }
+
+    /// Buffer a draft and write it once the conversation has been idle for
+    /// [`DEBOUNCE`]. An empty draft removes the stored one.
+    pub fn save(&self, app: &AppHandle, draft: Draft) {
+        let key = DraftKey {
+            instance_id: draft.instance_id.clone(),
+            channel_id: draft.channel_id.clone(),
+        };
+
+        let generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        self.lock()
+            .insert(key.clone(), Pending { draft, generation });
+
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(DEBOUNCE).await;
+            app.state::<Drafts>().write_if_current(&key, generation);
+        });
+    }
+
+    /// The latest draft for a conversation, including one not yet written
+    pub fn get(&self, instance_id: &str, channel_id: &str) -> rusqlite::Result<Option<Draft>> {
+        let key = DraftKey {
+            instance_id: instance_id.to_string(),
+            channel_id: channel_id.to_string(),
+        };
+        if let Some(pending) = self.lock().get(&key) {
+            return Ok((!pending.draft.is_empty()).then(|| pending.draft.clone()));
+        }
+
+        self.db.with(|conn| get(conn, instance_id, channel_id))
+    }
+
+    /// Every stored draft for an instance, most recently edited first
+    pub fn list(&self, instance_id: &str) -> rusqlite::Result<Vec<Draft>> {
+        self.flush()?;
+        self.db.with(|conn| list(conn, instance_id))
+    }
+
+    /// Drop a conversation's draft right away, e.g. once the message is sent
+    pub fn clear(&self, instance_id: &str, channel_id: &str) -> rusqlite::Result<()> {
+        self.lock().remove(&DraftKey {
+            instance_id: instance_id.to_string(),
+            channel_id: channel_id.to_string(),
+        });
+        self.db.with(|conn| remove(conn, instance_id, channel_id))
+    }
+
+    /// Write every buffered draft now
+    pub fn flush(&self) -> rusqlite::Result<()> {
+        let drafts: Vec<Draft> = self.lock().drain().map(|(_, p)| p.draft).collect();
+        if drafts.is_empty() {
+            return Ok(());
+        }
+
+        self.db.with(|conn| {
+            let tx = conn.transaction()?;
+            for draft in &drafts {
+                write(&tx, draft)?;
+            }
+            tx.commit()
+        })
+    }
+
+    fn write_if_current(&self, key: &DraftKey, generation: u64) {
+        let draft = {
+            let mut pending = self.lock();
+            match pending.get(key) {
+                Some(p) if p.generation == generation => pending.remove(key).map(|p| p.draft),
+                _ => None,
+            }
+        };
+
+        if let Some(draft) = draft {
+            if let Err(e) = self.db.with(|conn| write(conn, &draft)) {
+                log::error!(
+                    "Failed to save draft for {}:{}: {}",
+                    key.instance_id,
+                    key.channel_id,
+                    e
+                );
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<DraftKey, Pending>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub fn get(
+    conn: &Connection,
+    instance_id: &str,
+    channel_id: &str,
+) -> rusqlite::Result<Option<Draft>> {
+    conn.prepare_cached("SELECT * FROM drafts WHERE instance_id = ?1 AND channel_id = ?2")?
+        .query_row([instance_id, channel_id], Draft::from_row)
+        .optional()
+}
+
+pub fn list(conn: &Connection, instance_id: &str) -> rusqlite::Result<Vec<Draft>> {
+    let mut stmt = conn
+        .prepare_cached("SELECT * FROM drafts WHERE instance_id = ?1 ORDER BY updated_at DESC")?;
+    let rows = stmt.query_map([instance_id], Draft::from_row)?;
+    rows.collect()
+}
+
+pub fn remove(conn: &Connection, instance_id: &str, channel_id: &str) -> rusqlite::Result<()> {
+    conn.prepare_cached("DELETE FROM drafts WHERE instance_id = ?1 AND channel_id = ?2")?
+        .execute([instance_id, channel_id])?;
+    Ok(())
+}
+
+/// Store a draft, or remove the stored one if the draft is empty
+fn write(conn: &Connection, draft: &Draft) -> rusqlite::Result<()> {
+    if draft.is_empty() {
+        return remove(conn, &draft.instance_id, &draft.channel_id);
+    }
+
+    conn.prepare_cached(
+        "INSERT INTO drafts (instance_id, channel_id, content, attachments, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (instance_id, channel_id) DO UPDATE SET
+            content = excluded.content,
+            attachments = excluded.attachments,
+            updated_at = excluded.updated_at",
+    )?
+    .execute(params![
+        draft.instance_id,
+        draft.channel_id,
+        draft.content,
+        serde_json::to_string(&draft.attachments).expect("paths always serialize"),
+        draft.updated_at,
+    ])?;
+    Ok(())
+}
@@ -0,0 +1,411 @@
+//! Gateway connections from the backend
+//!
+//! Mirrors the frontend's `WebSocketManager`: one WebSocket per instance,
+//! answering pings and reconnecting with backoff. Connections are keyed
+//! by profile so several profiles can stay connected at once; every event
+//! is forwarded to the frontend tagged with the profile and instance it
+//! came from.
+//!
+//! The server allows one session per user and closes the older one with
+//! code 4000, so the backend only connects for profiles the webview isn't
+//! already connected for.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::watch;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::auth;
+use crate::data_usage::{self, Category};
+use crate::instances::{self, Instance};
+use crate::profiles::{ProfileError, Profiles};
+
+const RECONNECT_DELAYS: [Duration; 5] = [
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+    Duration::from_secs(30),
+];
+
+/// Close code the server uses when a newer session replaces this one
+const CLOSE_SESSION_REPLACED: u16 = 4000;
+
+/// Event as sent by the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsEvent {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    #[serde(default)]
+    pub payload: Option<serde_json::Value>,
+}
+
+/// Payload of the "gateway-event" event
+#[derive(Debug, Clone, Serialize)]
+pub struct GatewayEvent {
+    pub profile_id: String,
+    pub instance_id: String,
+    pub event: WsEvent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionStatus {
+    Connecting,
+    Connected,
+    Reconnecting,
+    Disconnected,
+}
+
+/// Payload of the "gateway-status" event
+#[derive(Debug, Clone, Serialize)]
+pub struct GatewayStatus {
+    pub profile_id: String,
+    pub instance_id: String,
+    pub status: ConnectionStatus,
+    pub error: Option<String>,
+}
+
+/// How a connection has been doing, for diagnostics
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionStats {
+    pub profile_id: String,
+    pub instance_id: String,
+    pub status: ConnectionStatus,
+    /// When the connection entered its current status
+    pub since: chrono::DateTime<chrono::Utc>,
+    pub reconnects: u64,
+    pub events_received: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ConnectionKey {
+    profile_id: String,
+    instance_id: String,
+}
+
+#[derive(Default)]
+pub struct Gateway {
+    connections: Mutex<HashMap<ConnectionKey, watch::Sender<bool>>>,
+    stats: Mutex<HashMap<ConnectionKey, ConnectionStats>>,
+}
+
+impl Gateway {
+    /// Open a connection for an instance unless one is already running
+    pub fn connect(&self, app: &AppHandle, profile_id: &str, instance: Instance) {
+        let key = ConnectionKey {
+            profile_id: profile_id.to_string(),
+            instance_id: instance.id.clone(),
+        };
+
+        let mut connections = self.lock();
+        if connections.contains_key(&key) {
+            return;
+        }
+
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        connections.insert(key.clone(), shutdown);
+        tauri::async_runtime::spawn(run(app.clone(), key, instance, shutdown_rx));
+    }
+
+    /// Close the connection for one instance
+    pub fn disconnect(&self, profile_id: &str, instance_id: &str) {
+        let key = ConnectionKey {
+            profile_id: profile_id.to_string(),
+            instance_id: instance_id.to_string(),
+        };
+        if let Some(shutdown) = self.lock().remove(&key) {
+            let _ = shutdown.send(true);
+        }
+    }
+
+    /// Close every connection belonging to a profile
+    pub fn disconnect_profile(&self, profile_id: &str) {
+        self.lock().retain(|key, shutdown| {
+            if key.profile_id == profile_id {
+                let _ = shutdown.send(true);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Instances with an open (or reconnecting) connection for a profile
+    pub fn connected_instances(&self, profile_id: &str) -> Vec<String> {
+        self.lock()
+            .keys()
+            .filter(|key| key.profile_id == profile_id)
+            .map(|key| key.instance_id.clone())
+            .collect()
+    }
+
+    /// Stats for every connection opened since the app started, including
+    /// ones that have been closed
+    pub fn stats(&self) -> Vec<ConnectionStats> {
+        let mut stats = self.lock_stats().values().cloned().collect::<Vec<_>>();
+        stats.sort_by(|a, b| (&a.profile_id, &a.instance_id).cmp(&(&b.profile_id, &b.instance_id)));
+        stats
+    }
+
+    /// Open connections for every instance registered in a profile's database
+    pub fn connect_profile(
+        &self,
+        app: &AppHandle,
+        profiles: &Profiles,
+        profile_id: &str,
+    ) -> Result<(), ProfileError> {
+        let db = profiles.open_database(profile_id)?;
+        for instance in db.with(|conn| instances::list(conn))? {
+            self.connect(app, profile_id, instance);
+        }
+        Ok(())
+    }
+
+    /// Drop a connection that ended on its own, unless it has already been
+    /// replaced by a newer one for the same key
+    fn forget(&self, key: &ConnectionKey, shutdown: &watch::Receiver<bool>) {
+        let mut connections = self.lock();
+        if connections
+            .get(key)
+            .is_some_and(|sender| sender.subscribe().same_channel(shutdown))
+        {
+            connections.remove(key);
+        }
+    }
+
+    fn record_status(&self, key: &ConnectionKey, status: ConnectionStatus, error: Option<&str>) {
+        let mut stats = self.lock_stats();
+        let stats = stats.entry(key.clone()).or_insert_with(|| ConnectionStats {
+            profile_id: key.profile_id.clone(),
+            instance_id: key.instance_id.clone(),
+            status,
+            since: chrono::Utc::now(),
+            reconnects: 0,
+            events_received: 0,
+            last_error: None,
+        });
+        if stats.status != status {
+            stats.since = chrono::Utc::now();
+        }
+        if status == ConnectionStatus::Reconnecting {
+            stats.reconnects += 1;
+        }
+        stats.status = status;
+        if let Some(error) = error {
+            stats.last_error = Some(error.to_string());
+        }
+    }
+
+    fn record_event(&self, key: &ConnectionKey) {
+        if let Some(stats) = self.lock_stats().get_mut(key) {
+            stats.events_received += 1;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<ConnectionKey, watch::Sender<bool>>> {
+        self.connections.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_stats(&self) -> std::sync::MutexGuard<'_, HashMap<ConnectionKey, ConnectionStats>> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Why a session ended
+enum Ended {
+    /// Don't reconnect: asked to stop, clean close, session replaced, or no usable credentials
+    Stop(Option<String>),
+    /// Reconnect after a backoff delay
+    Retry(String),
+}
+
+async fn run(
+    app: AppHandle,
+    key: ConnectionKey,
+    instance: Instance,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut attempts = 0;
+    let mut status = ConnectionStatus::Connecting;
+
+    let error = loop {
+        emit_status(&app, &key, status, None);
+
+        let ended = match access_token(&app, &key, &instance).await {
+            Ok(token) => session(&app, &key, &instance, &token, &mut shutdown, &mut attempts).await,
+            Err(ended) => ended,
+        };
+
+        let error = match ended {
+            Ended::Stop(error) => break error,
+            Ended::Retry(error) => error,
+        };
+
+        log::warn!("[gateway:{}:{}] {}", key.profile_id, key.instance_id, error);
+        status = ConnectionStatus::Reconnecting;
+        emit_status(&app, &key, status, Some(error));
+
+        let delay = RECONNECT_DELAYS[attempts.min(RECONNECT_DELAYS.len() - 1)];
+        attempts += 1;
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.changed() => break None,
+        }
+    };
+
+    app.state::<Gateway>().forget(&key, &shutdown);
+    emit_status(&app, &key, ConnectionStatus::Disconnected, error);
+}
+
+async fn session(
+    app: &AppHandle,
+    key: &ConnectionKey,
+    instance: &Instance,
+    token: &str,
+    shutdown: &mut watch::Receiver<bool>,
+    attempts: &mut usize,
+) -> Ended {
+    let mut url = match tauri::Url::parse(&format!("{}/ws", instance.url.replacen("http", "ws", 1)))
+    {
+        Ok(url) => url,
+        Err(e) => return Ended::Stop(Some(format!("invalid instance URL: {}", e))),
+    };
+    url.query_pairs_mut().append_pair("token", token);
+
+    let mut socket = match tokio_tungstenite::connect_async(url.as_str()).await {
+        Ok((socket, _)) => socket,
+        Err(e) => return Ended::Retry(format!("{}", e)),
+    };
+
+    loop {
+        let message = tokio::select! {
+            message = socket.next() => message,
+            _ = shutdown.changed() => {
+                let _ = socket.close(None).await;
+                return Ended::Stop(None);
+            }
+        };
+        if let Some(Ok(message)) = &message {
+            data_usage::record_for(
+                app,
+                &key.profile_id,
+                Category::Gateway,
+                0,
+                message.len() as u64,
+            );
+        }
+
+        let text = match message {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(frame))) => {
+                return match frame.map(|f| f.code) {
+                    Some(CloseCode::Normal) => Ended::Stop(None),
+                    Some(CloseCode::Library(CLOSE_SESSION_REPLACED)) => {
+                        Ended::Stop(Some("session replaced by another client".to_string()))
+                    }
+                    code => Ended::Retry(format!("connection closed ({:?})", code)),
+                };
+            }
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Ended::Retry(format!("{}", e)),
+            None => return Ended::Retry("connection closed".to_string()),
+        };
+
+        let event: WsEvent = match serde_json::from_str(&text) {
+            Ok(event) => event,
+            Err(e) => {
+                log::warn!(
+                    "[gateway:{}:{}] failed to parse event: {}",
+                    key.profile_id,
+                    key.instance_id,
+                    e
+                );
+                continue;
+            }
+        };
+
+        match event.kind.as_str() {
+            "auth.success" => {
+                *attempts = 0;
+                emit_status(app, key, ConnectionStatus::Connected, None);
+            }
+            "ping" => {
+                let pong = serde_json::json!({ "type": "pong" }).to_string();
+                data_usage::record_for(
+                    app,
+                    &key.profile_id,
+                    Category::Gateway,
+                    pong.len() as u64,
+                    0,
+                );
+                if let Err(e) = socket.send(Message::text(pong)).await {
+                    return Ended::Retry(format!("{}", e));
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(gateway) = app.try_state::<Gateway>() {
+            gateway.record_event(key);
+        }
+        crate::background::on_event(app, &key.profile_id, &key.instance_id, &event);
+        crate::launcher::unread::on_event(app, &key.profile_id, &key.instance_id, &event);
+        let _ = app.emit(
+            "gateway-event",
+            GatewayEvent {
+                profile_id: key.profile_id.clone(),
+                instance_id: key.instance_id.clone(),
+                event,
+            },
+        );
+    }
+}
+
+/// Fetch a token for the session, deciding whether a failure is worth retrying
+async fn access_token(
+    app: &AppHandle,
+    key: &ConnectionKey,
+    instance: &Instance,
+) -> Result<String, Ended> {
+    auth::access_token(app, &key.profile_id, instance)
+        .await
+        .map_err(|e| {
+            if e.needs_sign_in() {
+                Ended::Stop(Some(format!("{}", e)))
+            } else {
+                Ended::Retry(format!("{}", e))
+            }
+        })
+}
+
+fn emit_status(
+    app: &AppHandle,
+    key: &ConnectionKey,
+    status: ConnectionStatus,
+    error: Option<String>,
+) {
+    // Connections made during setup can get here before it's managed
+    if let Some(gateway) = app.try_state::<Gateway>() {
+        gateway.record_status(key, status, error.as_deref());
+    }
+    let _ = app.emit(
+        "gateway-status",
+        GatewayStatus {
+            profile_id: key.profile_id.clone(),
+            instance_id: key.instance_id.clone(),
+            status,
+            error,
+        },
+    );
+}
@@ -0,0 +1,99 @@
+use std::fs;
+
+use tauri::AppHandle;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt, GrabMode, ModMask};
+use x11rb::protocol::Event;
+
+use super::HardwareKeyError;
+
+/// `XF86XK_AudioMicMute`
+const XF86_AUDIO_MIC_MUTE: u32 = 0x1008_ffb2;
+
+/// Where the kernel exposes LEDs; mic mute ones are named `<device>::micmute`
+const LEDS_DIR: &str = "/sys/class/leds";
+
+/// Grab the mic mute key on the root window and handle presses; doesn't
+/// return while the X connection is up
+pub(super) fn listen(app: AppHandle) -> Result<(), HardwareKeyError> {
+    let (conn, screen) = x11rb::connect(None).map_err(listen_error)?;
+    let root = conn.setup().roots[screen].root;
+
+    let keycodes = keycodes(&conn, XF86_AUDIO_MIC_MUTE)?;
+    if keycodes.is_empty() {
+        return Err(HardwareKeyError::NoKey);
+    }
+    // Grabs match modifiers exactly, so also grab with Caps and Num Lock on
+    for &keycode in &keycodes {
+        for modifiers in [
+            ModMask::default(),
+            ModMask::LOCK,
+            ModMask::M2,
+            ModMask::LOCK | ModMask::M2,
+        ] {
+            conn.grab_key(
+                false,
+                root,
+                modifiers,
+                keycode,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )
+            .map_err(listen_error)?
+            .check()
+            .map_err(listen_error)?;
+        }
+    }
+    conn.flush().map_err(listen_error)?;
+
+    loop {
+        if let Event::KeyPress(event) = conn.wait_for_event().map_err(listen_error)? {
+            if keycodes.contains(&event.detail) {
+                super::pressed(&app);
+            }
+        }
+    }
+}
+
+/// Keycodes that produce `keysym` in the current keyboard mapping
+fn keycodes(conn: &impl Connection, keysym: u32) -> Result<Vec<u8>, HardwareKeyError> {
+    let setup = conn.setup();
+    let (min, max) = (setup.min_keycode, setup.max_keycode);
+    let mapping = conn
+        .get_keyboard_mapping(min, max - min + 1)
+        .map_err(listen_error)?
+        .reply()
+        .map_err(listen_error)?;
+
+    let per_keycode = (mapping.keysyms_per_keycode as usize).max(1);
+    Ok(mapping
+        .keysyms
+        .chunks(per_keycode)
+        .zip(min..=max)
+        .filter(|(keysyms, _)| keysyms.contains(&keysym))
+        .map(|(_, keycode)| keycode)
+        .collect())
+}
+
+/// Set every mic mute LED the user is allowed to write
+pub(super) fn set_led(muted: bool) -> bool {
+    let Ok(entries) = fs::read_dir(LEDS_DIR) else {
+        return false;
+    };
+    let mut set = false;
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().ends_with("::micmute") {
+            continue;
+        }
+        set |= fs::write(
+            entry.path().join("brightness"),
+            if muted { "1" } else { "0" },
+        )
+        .is_ok();
+    }
+    set
+}
+
+fn listen_error(e: impl std::fmt::Display) -> HardwareKeyError {
+    HardwareKeyError::Listen(e.to_string())
+}
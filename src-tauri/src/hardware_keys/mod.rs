@@ -0,0 +1,97 @@
+//! Hardware mute keys
+//!
+//! Many laptops have a dedicated microphone mute key, which the global
+//! shortcut plugin can't bind because it has no portable key code. It's
+//! listened for here at the OS level and emitted as "toggle-mute", the same
+//! event as the mute shortcut:
+//! - Windows reports it as an `APPCOMMAND_MICROPHONE_VOLUME_MUTE` app
+//!   command, which a shell hook sees whichever window has focus
+//! - on X11 it's the `XF86AudioMicMute` key, grabbed on the root window.
+//!   Wayland compositors keep the key for themselves and mute the default
+//!   input instead, so it doesn't reach the app there
+//! - Mac keyboards don't have one
+//!
+//! During calls the headset play/pause button can toggle mute as well, and
+//! the key's LED can be kept in sync with the app's mute state where the
+//! OS lets applications set it.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+use tauri::AppHandle;
+#[cfg(any(windows, target_os = "linux"))]
+use tauri::Manager;
+
+#[cfg(any(windows, target_os = "linux"))]
+use crate::actions::{self, Action};
+#[cfg(any(windows, target_os = "linux"))]
+use crate::db::Database;
+#[cfg(any(windows, target_os = "linux"))]
+use crate::settings;
+
+/// Setting that stops the mic mute key toggling mute; on unless set to `false`
+#[cfg(any(windows, target_os = "linux"))]
+pub const MIC_MUTE_KEY_SETTING: &str = "shortcuts.mic_mute_key";
+
+#[cfg(any(windows, target_os = "linux"))]
+#[derive(Debug, thiserror::Error)]
+pub enum HardwareKeyError {
+    #[error("couldn't listen for the mic mute key: {0}")]
+    Listen(String),
+    #[cfg(target_os = "linux")]
+    #[error("no key is mapped to microphone mute")]
+    NoKey,
+}
+
+/// Listen for the mic mute key for as long as the app runs
+pub fn spawn(app: &AppHandle) {
+    #[cfg(any(windows, target_os = "linux"))]
+    {
+        let app = app.clone();
+        let spawned = std::thread::Builder::new()
+            .name("hardware-keys".to_string())
+            .spawn(move || {
+                #[cfg(windows)]
+                let result = windows::listen(app);
+                #[cfg(target_os = "linux")]
+                let result = linux::listen(app);
+                if let Err(e) = result {
+                    log::info!("Mic mute key is unavailable: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            log::error!("Failed to start mic mute key listener: {}", e);
+        }
+    }
+    #[cfg(not(any(windows, target_os = "linux")))]
+    let _ = app;
+}
+
+/// Set the mic mute key's LED. Returns false if it can't be set here.
+pub fn set_led(muted: bool) -> bool {
+    #[cfg(target_os = "linux")]
+    return linux::set_led(muted);
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = muted;
+        false
+    }
+}
+
+/// Emit "toggle-mute" for a press of the mic mute key
+#[cfg(any(windows, target_os = "linux"))]
+fn pressed(app: &AppHandle) {
+    let enabled = app
+        .state::<Database>()
+        .with(|conn| settings::get_value(conn, MIC_MUTE_KEY_SETTING))
+        .map_err(|e| log::error!("Failed to read mic mute key setting: {}", e))
+        .ok()
+        .flatten()
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    if enabled {
+        actions::dispatch(app, Action::ToggleMute);
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::OnceLock;
+
+use tauri::AppHandle;
+use windows_sys::w;
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW,
+    RegisterShellHookWindow, RegisterWindowMessageW, FAPPCOMMAND_MASK, HSHELL_APPCOMMAND, MSG,
+    WNDCLASSW,
+};
+
+use super::HardwareKeyError;
+
+/// From `winuser.h`
+const APPCOMMAND_MICROPHONE_VOLUME_MUTE: u32 = 24;
+
+/// The window procedure has no other way to reach these
+static APP: OnceLock<AppHandle> = OnceLock::new();
+static SHELL_HOOK_MESSAGE: OnceLock<u32> = OnceLock::new();
+
+/// Create a hidden window that receives shell hook messages and run its
+/// message loop; doesn't return while the app is running
+pub(super) fn listen(app: AppHandle) -> Result<(), HardwareKeyError> {
+    let _ = APP.set(app);
+
+    let class_name = w!("RedoubtHardwareKeys");
+    // SAFETY: plain Win32 calls with valid, NUL-terminated strings; the
+    // window and class live until the process exits
+    unsafe {
+        let instance = GetModuleHandleW(std::ptr::null());
+        let class = WNDCLASSW {
+            style: 0,
+            lpfnWndProc: Some(window_proc),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hInstance: instance,
+            hIcon: std::ptr::null_mut(),
+            hCursor: std::ptr::null_mut(),
+            hbrBackground: std::ptr::null_mut(),
+            lpszMenuName: std::ptr::null(),
+            lpszClassName: class_name,
+        };
+        if RegisterClassW(&class) == 0 {
+            return Err(last_error());
+        }
+
+        // Shell hooks aren't delivered to message-only windows, so this is
+        // an ordinary top-level window that's never shown
+        let window = CreateWindowExW(
+            0,
+            class_name,
+            class_name,
+            0,
+            0,
+            0,
+            0,
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            instance,
+            std::ptr::null(),
+        );
+        if window.is_null() {
+            return Err(last_error());
+        }
+
+        let _ = SHELL_HOOK_MESSAGE.set(RegisterWindowMessageW(w!("SHELLHOOK")));
+        if RegisterShellHookWindow(window) == 0 {
+            return Err(last_error());
+        }
+
+        let mut message: MSG = std::mem::zeroed();
+        while GetMessageW(&mut message, std::ptr::null_mut(), 0, 0) > 0 {
+            DispatchMessageW(&message);
+        }
+    }
+    Ok(())
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if Some(&message) == SHELL_HOOK_MESSAGE.get() && wparam as u32 == HSHELL_APPCOMMAND {
+        // GET_APPCOMMAND_LPARAM: the command is in the high word, minus
+        // the bits saying which device sent it
+        let command = (lparam as u32 >> 16) & !FAPPCOMMAND_MASK & 0xffff;
+        if command == APPCOMMAND_MICROPHONE_VOLUME_MUTE {
+            if let Some(app) = APP.get() {
+                super::pressed(app);
+            }
+        }
+    }
+    DefWindowProcW(window, message, wparam, lparam)
+}
+
+fn last_error() -> HardwareKeyError {
+    HardwareKeyError::Listen(std::io::Error::last_os_error().to_string())
+}
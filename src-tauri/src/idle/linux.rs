@@ -0,0 +1,93 @@
+//! Linux has no single API for this: GNOME's Mutter and KDE (through the
+//! freedesktop screensaver interface) report an exact idle time on the
+//! session bus, and logind knows whether the session has gone idle and
+//! since when, which covers most other desktops.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use zbus::Connection;
+
+use super::IdleError;
+
+pub(super) struct Source {
+    session: Option<Connection>,
+    system: Option<Connection>,
+}
+
+impl Source {
+    pub(super) async fn new() -> Result<Self, IdleError> {
+        let session = Connection::session().await.ok();
+        let system = Connection::system().await.ok();
+        if session.is_none() && system.is_none() {
+            return Err(IdleError::Unavailable("no D-Bus connection".to_string()));
+        }
+        Ok(Self { session, system })
+    }
+
+    pub(super) async fn idle_time(&self) -> Result<Duration, IdleError> {
+        if let Some(session) = &self.session {
+            if let Ok(milliseconds) = mutter(session).await {
+                return Ok(Duration::from_millis(milliseconds));
+            }
+            if let Ok(seconds) = screensaver(session).await {
+                return Ok(Duration::from_secs(seconds as u64));
+            }
+        }
+
+        match &self.system {
+            Some(system) => logind(system).await,
+            None => Err(IdleError::Unavailable(
+                "the desktop doesn't report idle time".to_string(),
+            )),
+        }
+    }
+}
+
+async fn mutter(session: &Connection) -> Result<u64, IdleError> {
+    let reply = session
+        .call_method(
+            Some("org.gnome.Mutter.IdleMonitor"),
+            "/org/gnome/Mutter/IdleMonitor/Core",
+            Some("org.gnome.Mutter.IdleMonitor"),
+            "GetIdletime",
+            &(),
+        )
+        .await?;
+    Ok(reply.body().deserialize()?)
+}
+
+async fn screensaver(session: &Connection) -> Result<u32, IdleError> {
+    let reply = session
+        .call_method(
+            Some("org.freedesktop.ScreenSaver"),
+            "/org/freedesktop/ScreenSaver",
+            Some("org.freedesktop.ScreenSaver"),
+            "GetSessionIdleTime",
+            &(),
+        )
+        .await?;
+    Ok(reply.body().deserialize()?)
+}
+
+/// logind only flips `IdleHint` once the desktop considers the session
+/// idle, so this is coarser than the session bus interfaces
+async fn logind(system: &Connection) -> Result<Duration, IdleError> {
+    let session = zbus::Proxy::new(
+        system,
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1/session/auto",
+        "org.freedesktop.login1.Session",
+    )
+    .await?;
+
+    let idle: bool = session.get_property("IdleHint").await?;
+    if !idle {
+        return Ok(Duration::ZERO);
+    }
+    // Wall clock time in microseconds
+    let since: u64 = session.get_property("IdleSinceHint").await?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(now.saturating_sub(Duration::from_micros(since)))
+}
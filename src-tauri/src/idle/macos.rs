@@ -0,0 +1,80 @@
+use std::ffi::{c_char, c_void};
+use std::time::Duration;
+
+use core_foundation::base::{CFType, TCFType};
+use core_foundation::number::CFNumber;
+use core_foundation::string::CFString;
+use core_foundation_sys::base::{CFAllocatorRef, CFTypeRef};
+use core_foundation_sys::string::CFStringRef;
+
+use super::IdleError;
+
+type IoObject = u32;
+
+/// `kIOMainPortDefault`
+const MAIN_PORT_DEFAULT: u32 = 0;
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOServiceMatching(name: *const c_char) -> *mut c_void;
+    fn IOServiceGetMatchingService(main_port: u32, matching: *mut c_void) -> IoObject;
+    fn IORegistryEntryCreateCFProperty(
+        entry: IoObject,
+        key: CFStringRef,
+        allocator: CFAllocatorRef,
+        options: u32,
+    ) -> CFTypeRef;
+    fn IOObjectRelease(object: IoObject) -> i32;
+}
+
+pub(super) struct Source;
+
+impl Source {
+    pub(super) async fn new() -> Result<Self, IdleError> {
+        Ok(Source)
+    }
+
+    pub(super) async fn idle_time(&self) -> Result<Duration, IdleError> {
+        idle_time()
+    }
+}
+
+/// Reads `HIDIdleTime` from the HID system, which tracks input from every
+/// keyboard and pointing device
+fn idle_time() -> Result<Duration, IdleError> {
+    // SAFETY: the name is NUL-terminated; IOServiceGetMatchingService takes
+    // ownership of the matching dictionary, and the service is released below
+    let service = unsafe {
+        let matching = IOServiceMatching(c"IOHIDSystem".as_ptr());
+        IOServiceGetMatchingService(MAIN_PORT_DEFAULT, matching)
+    };
+    if service == 0 {
+        return Err(IdleError::Unavailable("no IOHIDSystem service".to_string()));
+    }
+
+    let key = CFString::from_static_string("HIDIdleTime");
+    // SAFETY: `service` is a valid registry entry and `key` a valid CFString
+    let property = unsafe {
+        let property = IORegistryEntryCreateCFProperty(
+            service,
+            key.as_concrete_TypeRef(),
+            std::ptr::null(),
+            0,
+        );
+        IOObjectRelease(service);
+        property
+    };
+    if property.is_null() {
+        return Err(IdleError::Unavailable(
+            "no HIDIdleTime property".to_string(),
+        ));
+    }
+
+    // SAFETY: the property was created for us, so we own the reference
+    let property = unsafe { CFType::wrap_under_create_rule(property) };
+    let nanoseconds = property
+        .downcast::<CFNumber>()
+        .and_then(|n| n.to_i64())
+        .ok_or_else(|| IdleError::Unavailable("HIDIdleTime isn't a number".to_string()))?;
+    Ok(Duration::from_nanos(nanoseconds.max(0) as u64))
+}
@@ -0,0 +1,119 @@
+//! System idle detection
+//!
+//! Polls how long it's been since the last keyboard or mouse input, as the
+//! OS reports it, so presence can turn to "away" while the window is in
+//! the background and the webview's own timers are throttled. Emits
+//! "user-idle" once input has stopped for the configured time and
+//! "user-active" when it resumes.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+use linux::Source;
+#[cfg(target_os = "macos")]
+use macos::Source;
+#[cfg(windows)]
+use windows::Source;
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::db::Database;
+use crate::settings;
+
+/// Setting holding the seconds without input before "user-idle"; 0 turns
+/// idle detection off
+pub const IDLE_AFTER_SETTING: &str = "presence.idle_after";
+const DEFAULT_IDLE_AFTER: Duration = Duration::from_secs(5 * 60);
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error)]
+pub enum IdleError {
+    #[error("idle time isn't available: {0}")]
+    Unavailable(String),
+    #[cfg(target_os = "linux")]
+    #[error("D-Bus error: {0}")]
+    DBus(#[from] zbus::Error),
+}
+
+/// Payload of "user-idle"
+#[derive(Debug, Clone, Serialize)]
+pub struct UserIdle {
+    pub idle_seconds: u64,
+}
+
+/// Watch for the user going idle for as long as the app runs
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let source = match Source::new().await {
+            Ok(source) => source,
+            Err(e) => {
+                log::warn!("Idle detection is off: {}", e);
+                return;
+            }
+        };
+
+        let mut idle = false;
+        let mut failing = false;
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let threshold = match idle_after(&app.state::<Database>()) {
+                Ok(threshold) => threshold,
+                Err(e) => {
+                    log::error!("Failed to read idle threshold: {}", e);
+                    continue;
+                }
+            };
+            let idle_time = match source.idle_time().await {
+                Ok(idle_time) => {
+                    failing = false;
+                    idle_time
+                }
+                Err(e) => {
+                    // Only log the first of a run of failures
+                    if !failing {
+                        log::warn!("Failed to read idle time: {}", e);
+                        failing = true;
+                    }
+                    continue;
+                }
+            };
+
+            let now_idle = threshold.is_some_and(|threshold| idle_time >= threshold);
+            if now_idle && !idle {
+                let _ = app.emit(
+                    "user-idle",
+                    UserIdle {
+                        idle_seconds: idle_time.as_secs(),
+                    },
+                );
+            } else if !now_idle && idle {
+                let _ = app.emit("user-active", ());
+            }
+            idle = now_idle;
+        }
+    });
+}
+
+/// The configured idle threshold, or `None` if detection is turned off
+fn idle_after(db: &Database) -> rusqlite::Result<Option<Duration>> {
+    let seconds = db
+        .with(|conn| settings::get_value(conn, IDLE_AFTER_SETTING))?
+        .and_then(|v| v.as_u64());
+    Ok(match seconds {
+        Some(0) => None,
+        Some(seconds) => Some(Duration::from_secs(seconds)),
+        None => Some(DEFAULT_IDLE_AFTER),
+    })
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use windows_sys::Win32::System::SystemInformation::GetTickCount;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+use super::IdleError;
+
+pub(super) struct Source;
+
+impl Source {
+    pub(super) async fn new() -> Result<Self, IdleError> {
+        Ok(Source)
+    }
+
+    pub(super) async fn idle_time(&self) -> Result<Duration, IdleError> {
+        idle_time()
+    }
+}
+
+fn idle_time() -> Result<Duration, IdleError> {
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    // SAFETY: `info` is a valid LASTINPUTINFO with its size set
+    if unsafe { GetLastInputInfo(&mut info) } == 0 {
+        return Err(IdleError::Unavailable(
+            std::io::Error::last_os_error().to_string(),
+        ));
+    }
+    // SAFETY: no preconditions
+    let now = unsafe { GetTickCount() };
+    // Both are 32-bit tick counts, so this is right across the ~49 day wrap
+    Ok(Duration::from_millis(now.wrapping_sub(info.dwTime) as u64))
+}
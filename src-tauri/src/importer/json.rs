@@ -0,0 +1,85 @@
+//! Plain JSON history
+//!
+//! A simple format for anything without a dedicated parser:
+//!
+//! ```json
+//! {
+//!   "conversations": [
+//!     {
+//!       "id": "general",
+//!       "name": "General",
+//!       "messages": [
+//!         { "author": "alex", "content": "hi", "timestamp": "2024-01-01T12:00:00Z" }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `timestamp` may also be a Unix timestamp in milliseconds.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use super::{from_millis, ImportedConversation, ImportedMessage, Parsed};
+
+#[derive(Deserialize)]
+struct Export {
+    conversations: Vec<Conversation>,
+}
+
+#[derive(Deserialize)]
+struct Conversation {
+    id: Option<String>,
+    name: String,
+    messages: Vec<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    id: Option<String>,
+    author: String,
+    content: String,
+    timestamp: Timestamp,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Timestamp {
+    Millis(i64),
+    Text(DateTime<Utc>),
+}
+
+pub fn parse(document: serde_json::Value) -> Result<Parsed, serde_json::Error> {
+    let export: Export = serde_json::from_value(document)?;
+
+    let mut parsed = Parsed::default();
+    for conversation in export.conversations {
+        let mut messages = Vec::new();
+        for message in conversation.messages {
+            let sent_at = match message.timestamp {
+                Timestamp::Millis(ms) => from_millis(ms),
+                Timestamp::Text(at) => Some(at),
+            };
+            let Some(sent_at) = sent_at.filter(|_| !message.content.is_empty()) else {
+                parsed.skipped += 1;
+                continue;
+            };
+
+            messages.push(ImportedMessage {
+                source_id: message.id,
+                author: message.author,
+                content: message.content,
+                sent_at,
+            });
+        }
+
+        parsed.conversations.push(ImportedConversation {
+            id: conversation.id.unwrap_or_else(|| conversation.name.clone()),
+            name: conversation.name,
+            messages,
+        });
+    }
+
+    Ok(parsed)
+}
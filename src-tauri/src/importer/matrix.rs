@@ -0,0 +1,78 @@
+//! Matrix room exports
+//!
+//! Reads the JSON produced by Element's "Export chat" feature.
+
+use serde::Deserialize;
+
+use super::{from_millis, ImportedConversation, ImportedMessage, Parsed};
+
+#[derive(Deserialize)]
+struct Export {
+    room_name: Option<String>,
+    messages: Vec<Event>,
+}
+
+#[derive(Deserialize)]
+struct Event {
+    #[serde(rename = "type")]
+    kind: String,
+    event_id: Option<String>,
+    room_id: Option<String>,
+    sender: String,
+    origin_server_ts: i64,
+    #[serde(default)]
+    content: Content,
+}
+
+#[derive(Deserialize, Default)]
+struct Content {
+    msgtype: Option<String>,
+    body: Option<String>,
+}
+
+pub fn parse(document: serde_json::Value) -> Result<Parsed, serde_json::Error> {
+    let export: Export = serde_json::from_value(document)?;
+
+    let mut parsed = Parsed::default();
+    let room_id = export.messages.iter().find_map(|e| e.room_id.clone());
+    let id = room_id
+        .or_else(|| export.room_name.clone())
+        .unwrap_or_else(|| "matrix-room".to_string());
+    let name = export.room_name.unwrap_or_else(|| id.clone());
+
+    let mut messages = Vec::new();
+    for event in export.messages {
+        // Membership changes, redactions, state events and media are not carried over
+        let content = match (
+            event.kind.as_str(),
+            event.content.msgtype.as_deref(),
+            event.content.body,
+        ) {
+            ("m.room.message", Some("m.text") | Some("m.notice"), Some(body)) => body,
+            ("m.room.message", Some("m.emote"), Some(body)) => {
+                format!("* {} {}", event.sender, body)
+            }
+            _ => {
+                parsed.skipped += 1;
+                continue;
+            }
+        };
+
+        let Some(sent_at) = from_millis(event.origin_server_ts) else {
+            parsed.skipped += 1;
+            continue;
+        };
+
+        messages.push(ImportedMessage {
+            source_id: event.event_id,
+            author: event.sender,
+            content,
+            sent_at,
+        });
+    }
+
+    parsed
+        .conversations
+        .push(ImportedConversation { id, name, messages });
+    Ok(parsed)
+}
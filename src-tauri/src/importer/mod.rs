@@ -0,0 +1,393 @@
+//! History import from other messengers
+//!
+//! Each supported export format has its own parser that turns the export
+//! into a list of [`ImportedConversation`]s. The importer then either
+//! reports what it found (dry run) or writes the messages to the local
+//! cache or the outbox.
+
+mod json;
+mod matrix;
+mod signal;
+
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cache::{self, CachedMessage};
+use crate::db::Database;
+
+/// Largest single JSON document read from an export, in bytes
+pub const MAX_DOCUMENT_BYTES: u64 = 256 * 1024 * 1024;
+/// Most bytes decompressed from one archive, over all its documents
+pub const MAX_EXPORT_BYTES: u64 = 1024 * 1024 * 1024;
+/// Most entries an archive can list
+pub const MAX_ENTRIES: usize = 10_000;
+
+/// Supported export formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    /// Message JSON from a decrypted Signal Desktop database
+    Signal,
+    /// Element's "Export chat" JSON
+    Matrix,
+    /// Redoubt's own plain JSON schema
+    Json,
+}
+
+impl ImportFormat {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Signal => "signal",
+            Self::Matrix => "matrix",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// Where imported messages end up
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ImportTarget {
+    /// Keep the history locally, one cached channel per imported conversation
+    Cache,
+    /// Queue every message for posting into an existing channel
+    Outbox {
+        instance_id: String,
+        channel_id: String,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("failed to read export: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to read archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("{file}: {source}")]
+    Json {
+        file: String,
+        source: serde_json::Error,
+    },
+    #[error("{0}: unrecognized export format")]
+    UnknownFormat(String),
+    #[error("{0}: export is too large")]
+    TooLarge(String),
+    #[error("{0}: archive has more than {MAX_ENTRIES} entries")]
+    TooManyEntries(String),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// A single message as read from an export
+#[derive(Debug, Clone)]
+pub struct ImportedMessage {
+    /// Identifier assigned by the source app, if the export has one
+    pub source_id: Option<String>,
+    pub author: String,
+    pub content: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+/// A conversation as read from an export
+#[derive(Debug, Clone)]
+pub struct ImportedConversation {
+    /// Identifier assigned by the source app, stable across exports
+    pub id: String,
+    pub name: String,
+    pub messages: Vec<ImportedMessage>,
+}
+
+/// Output of a format parser
+#[derive(Debug, Default)]
+pub struct Parsed {
+    pub conversations: Vec<ImportedConversation>,
+    /// Events in the export that aren't importable (calls, key changes, media-only, ...)
+    pub skipped: usize,
+    pub warnings: Vec<String>,
+}
+
+impl Parsed {
+    fn extend(&mut self, other: Parsed) {
+        self.conversations.extend(other.conversations);
+        self.skipped += other.skipped;
+        self.warnings.extend(other.warnings);
+    }
+}
+
+/// Per-conversation summary in an [`ImportReport`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationReport {
+    pub name: String,
+    /// Cached channel (or outbox channel) the messages go to
+    pub channel_id: String,
+    pub message_count: usize,
+    /// Messages not already imported by an earlier run
+    pub new_messages: usize,
+    pub participants: Vec<String>,
+    pub first_message_at: Option<DateTime<Utc>>,
+    pub last_message_at: Option<DateTime<Utc>>,
+}
+
+/// What an import did, or would do when `dry_run` is set
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub format: ImportFormat,
+    pub dry_run: bool,
+    pub conversations: Vec<ConversationReport>,
+    pub total_messages: usize,
+    pub new_messages: usize,
+    pub duplicate_messages: usize,
+    pub skipped_events: usize,
+    pub warnings: Vec<String>,
+}
+
+/// Parse the export at `path` and import it into `target`.
+/// With `dry_run` set nothing is written and the report describes what would happen.
+pub fn import(
+    db: &Database,
+    path: &Path,
+    format: Option<ImportFormat>,
+    target: &ImportTarget,
+    dry_run: bool,
+) -> Result<ImportReport, ImportError> {
+    let (format, parsed) = parse(path, format)?;
+
+    let mut report = ImportReport {
+        format,
+        dry_run,
+        conversations: Vec::new(),
+        total_messages: 0,
+        new_messages: 0,
+        duplicate_messages: 0,
+        skipped_events: parsed.skipped,
+        warnings: parsed.warnings,
+    };
+
+    db.with(|conn| {
+        let tx = conn.transaction()?;
+
+        for conversation in &parsed.conversations {
+            let channel_id = match target {
+                ImportTarget::Cache => format!("import:{}:{}", format.as_str(), conversation.id),
+                ImportTarget::Outbox { channel_id, .. } => channel_id.clone(),
+            };
+
+            let mut participants: Vec<String> = Vec::new();
+            let mut new_messages = 0;
+
+            for message in &conversation.messages {
+                if !participants.contains(&message.author) {
+                    participants.push(message.author.clone());
+                }
+
+                let id = message_id(format, &conversation.id, message);
+                let inserted = match target {
+                    ImportTarget::Cache if dry_run => !cache::message_exists(&tx, &id)?,
+                    ImportTarget::Cache => {
+                        let cached = CachedMessage {
+                            id,
+                            instance_id: None,
+                            channel_id: channel_id.clone(),
+                            author_id: None,
+                            author_name: message.author.clone(),
+                            content: message.content.clone(),
+                            created_at: message.sent_at,
+                            source: format!("import:{}", format.as_str()),
+                        };
+                        cache::insert_messages(&tx, &[cached])? > 0
+                    }
+                    ImportTarget::Outbox { .. } if dry_run => !cache::outbox_contains(&tx, &id)?,
+                    ImportTarget::Outbox {
+                        instance_id,
+                        channel_id,
+                    } => cache::enqueue_outbox(
+                        &tx,
+                        instance_id,
+                        channel_id,
+                        &outbox_content(message),
+                        &id,
+                    )?,
+                };
+
+                if inserted {
+                    new_messages += 1;
+                }
+            }
+
+            report.total_messages += conversation.messages.len();
+            report.new_messages += new_messages;
+            report.conversations.push(ConversationReport {
+                name: conversation.name.clone(),
+                channel_id,
+                message_count: conversation.messages.len(),
+                new_messages,
+                participants,
+                first_message_at: conversation.messages.iter().map(|m| m.sent_at).min(),
+                last_message_at: conversation.messages.iter().map(|m| m.sent_at).max(),
+            });
+        }
+
+        if !dry_run {
+            tx.commit()?;
+        }
+
+        Ok(())
+    })?;
+
+    report.duplicate_messages = report.total_messages - report.new_messages;
+
+    Ok(report)
+}
+
+/// Read and parse an export, detecting the format when none is given.
+/// Zip archives are searched for JSON files, each parsed on its own.
+pub fn parse(
+    path: &Path,
+    format: Option<ImportFormat>,
+) -> Result<(ImportFormat, Parsed), ImportError> {
+    let mut detected = format;
+    let mut parsed = Parsed::default();
+
+    for (name, bytes) in read_export(path)? {
+        let document: serde_json::Value =
+            serde_json::from_slice(&bytes).map_err(|source| ImportError::Json {
+                file: name.clone(),
+                source,
+            })?;
+
+        let format = match detected.or_else(|| detect_format(&document)) {
+            Some(format) => format,
+            None => return Err(ImportError::UnknownFormat(name)),
+        };
+        detected = Some(format);
+
+        let result = match format {
+            ImportFormat::Signal => signal::parse(document),
+            ImportFormat::Matrix => matrix::parse(document),
+            ImportFormat::Json => json::parse(document),
+        };
+        parsed.extend(result.map_err(|source| ImportError::Json { file: name, source })?);
+    }
+
+    match detected {
+        Some(format) => Ok((format, parsed)),
+        None => Err(ImportError::UnknownFormat(path.display().to_string())),
+    }
+}
+
+/// Guess the export format from the shape of a JSON document
+pub fn detect_format(document: &serde_json::Value) -> Option<ImportFormat> {
+    let first_message = match document {
+        serde_json::Value::Array(items) => items.first(),
+        serde_json::Value::Object(map) => {
+            if map.contains_key("room_name") || map.contains_key("room_creator") {
+                return Some(ImportFormat::Matrix);
+            }
+            if let Some(conversations) = map.get("conversations").and_then(|c| c.as_array()) {
+                if conversations.iter().any(|c| c.get("messages").is_some()) {
+                    return Some(ImportFormat::Json);
+                }
+            }
+            map.get("messages")
+                .and_then(|m| m.as_array())
+                .and_then(|m| m.first())
+        }
+        _ => None,
+    }?;
+
+    if first_message.get("conversationId").is_some() {
+        Some(ImportFormat::Signal)
+    } else if first_message.get("origin_server_ts").is_some() {
+        Some(ImportFormat::Matrix)
+    } else {
+        None
+    }
+}
+
+/// Load the JSON documents contained in an export file
+fn read_export(path: &Path) -> Result<Vec<(String, Vec<u8>)>, ImportError> {
+    let name = path.display().to_string();
+    if fs::metadata(path)?.len() > MAX_EXPORT_BYTES {
+        return Err(ImportError::TooLarge(name));
+    }
+    let bytes = fs::read(path)?;
+
+    if !bytes.starts_with(b"PK\x03\x04") {
+        if bytes.len() as u64 > MAX_DOCUMENT_BYTES {
+            return Err(ImportError::TooLarge(name));
+        }
+        return Ok(vec![(name, bytes)]);
+    }
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    if archive.len() > MAX_ENTRIES {
+        return Err(ImportError::TooManyEntries(name));
+    }
+    let mut documents = Vec::new();
+    let mut total = 0;
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+        if !entry.is_file() || !entry.name().to_ascii_lowercase().ends_with(".json") {
+            continue;
+        }
+        let file = format!("{}/{}", name, entry.name());
+
+        // The sizes in the archive's headers aren't trusted; what's actually
+        // decompressed is counted instead
+        let limit = MAX_DOCUMENT_BYTES.min(MAX_EXPORT_BYTES - total);
+        let mut contents = Vec::new();
+        entry.take(limit + 1).read_to_end(&mut contents)?;
+        if contents.len() as u64 > limit {
+            return Err(ImportError::TooLarge(file));
+        }
+        total += contents.len() as u64;
+        documents.push((file, contents));
+    }
+
+    if documents.is_empty() {
+        return Err(ImportError::UnknownFormat(name));
+    }
+
+    Ok(documents)
+}
+
+/// Deterministic id so re-importing the same export doesn't duplicate messages
+fn message_id(format: ImportFormat, conversation_id: &str, message: &ImportedMessage) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(conversation_id.as_bytes());
+    hasher.update([0]);
+    match &message.source_id {
+        Some(source_id) => hasher.update(source_id.as_bytes()),
+        None => {
+            hasher.update(message.sent_at.timestamp_millis().to_be_bytes());
+            hasher.update(message.author.as_bytes());
+            hasher.update([0]);
+            hasher.update(message.content.as_bytes());
+        }
+    }
+
+    let digest = hasher.finalize();
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("import:{}:{}", format.as_str(), hex)
+}
+
+/// Imported messages are posted by the importing user, so keep the original
+/// author and time visible in the message body
+fn outbox_content(message: &ImportedMessage) -> String {
+    format!(
+        "[{}] {}: {}",
+        message.sent_at.format("%Y-%m-%d %H:%M"),
+        message.author,
+        message.content
+    )
+}
+
+/// Convert a millisecond Unix timestamp, as used by most exports
+fn from_millis(ms: i64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(ms)
+}
@@ -0,0 +1,125 @@
+//! Signal Desktop messages
+//!
+//! Signal Desktop has no export feature of its own; this reads the message
+//! JSON stored in its (decrypted) database, either as a bare array of
+//! messages or wrapped together with the conversation list.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use super::{from_millis, ImportedConversation, ImportedMessage, Parsed};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Export {
+    Wrapped {
+        #[serde(default)]
+        conversations: Vec<Conversation>,
+        messages: Vec<Message>,
+    },
+    Bare(Vec<Message>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Conversation {
+    id: String,
+    name: Option<String>,
+    profile_name: Option<String>,
+    e164: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Message {
+    id: Option<String>,
+    conversation_id: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    body: Option<String>,
+    sent_at: Option<i64>,
+    source: Option<String>,
+    source_service_id: Option<String>,
+}
+
+pub fn parse(document: serde_json::Value) -> Result<Parsed, serde_json::Error> {
+    let (conversations, messages) = match serde_json::from_value(document)? {
+        Export::Wrapped {
+            conversations,
+            messages,
+        } => (conversations, messages),
+        Export::Bare(messages) => (Vec::new(), messages),
+    };
+
+    let names: HashMap<String, String> = conversations
+        .into_iter()
+        .filter_map(|c| {
+            let name = c.name.or(c.profile_name).or(c.e164)?;
+            Some((c.id, name))
+        })
+        .collect();
+
+    let mut parsed = Parsed::default();
+    let mut by_conversation: Vec<ImportedConversation> = Vec::new();
+
+    for message in messages {
+        // Only chat messages carry a body; everything else is call history,
+        // safety number changes, group updates and the like
+        let is_chat = matches!(
+            message.kind.as_deref(),
+            Some("incoming") | Some("outgoing") | None
+        );
+        let body = message.body.filter(|b| !b.is_empty());
+        let (Some(body), Some(sent_at), true) =
+            (body, message.sent_at.and_then(from_millis), is_chat)
+        else {
+            parsed.skipped += 1;
+            continue;
+        };
+
+        let author = match message.kind.as_deref() {
+            Some("outgoing") => "You".to_string(),
+            _ => message
+                .source
+                .or(message.source_service_id)
+                .unwrap_or_else(|| "Unknown".to_string()),
+        };
+
+        let conversation = match by_conversation
+            .iter_mut()
+            .position(|c| c.id == message.conversation_id)
+        {
+            Some(index) => &mut by_conversation[index],
+            None => {
+                let name = names
+                    .get(&message.conversation_id)
+                    .cloned()
+                    .unwrap_or_else(|| message.conversation_id.clone());
+                by_conversation.push(ImportedConversation {
+                    id: message.conversation_id.clone(),
+                    name,
+                    messages: Vec::new(),
+                });
+                by_conversation.last_mut().expect("just pushed")
+            }
+        };
+
+        conversation.messages.push(ImportedMessage {
+            source_id: message.id,
+            author,
+            content: body,
+            sent_at,
+        });
+    }
+
+    if names.is_empty() && !by_conversation.is_empty() {
+        parsed.warnings.push(
+            "Export has no conversation list; conversations are named by their Signal id"
+                .to_string(),
+        );
+    }
+
+    parsed.conversations = by_conversation;
+    Ok(parsed)
+}
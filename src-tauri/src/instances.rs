@@ -0,0 +1,57 @@
+//! Servers a profile is signed in to
+//!
+//! The frontend owns the instance list shown in the tab bar; it registers
+//! each instance here so backend services (the gateway connection, the
+//! API client) know where the server lives.
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Instance {
+    pub id: String,
+    /// Base URL without the `/api/v1` suffix
+    pub url: String,
+    pub name: String,
+}
+
+impl Instance {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            url: row.get("url")?,
+            name: row.get("name")?,
+        })
+    }
+}
+
+pub fn list(conn: &Connection) -> rusqlite::Result<Vec<Instance>> {
+    let mut stmt = conn.prepare_cached("SELECT * FROM instances ORDER BY name")?;
+    let rows = stmt.query_map([], Instance::from_row)?;
+    rows.collect()
+}
+
+pub fn get(conn: &Connection, id: &str) -> rusqlite::Result<Option<Instance>> {
+    conn.prepare_cached("SELECT * FROM instances WHERE id = ?1")?
+        .query_row([id], Instance::from_row)
+        .optional()
+}
+
+pub fn upsert(conn: &Connection, instance: &Instance) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO instances (id, url, name) VALUES (?1, ?2, ?3)
+         ON CONFLICT (id) DO UPDATE SET url = excluded.url, name = excluded.name",
+    )?
+    .execute(params![
+        instance.id,
+        instance.url.trim_end_matches('/'),
+        instance.name
+    ])?;
+    Ok(())
+}
+
+pub fn remove(conn: &Connection, id: &str) -> rusqlite::Result<()> {
+    conn.prepare_cached("DELETE FROM instances WHERE id = ?1")?
+        .execute([id])?;
+    Ok(())
+}
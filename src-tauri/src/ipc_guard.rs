@@ -0,0 +1,320 @@
+//! Command gating
+//!
+//! Sits in front of the generated command handler so a compromised webview
+//! can do less with the backend. Each window may only call the commands
+//! listed for it in [`WINDOWS`], and commands that read secrets, delete
+//! data or record audio are rate limited per command. Denied calls are
+//! rejected with an error string like the commands' own and logged,
+//! without their arguments. Tauri's own capabilities still cover plugin
+//! commands; this covers the app's commands, which capabilities don't
+//! split up.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tauri::ipc::Invoke;
+use tauri::Runtime;
+
+/// Commands each window may call by label; windows not listed may call none
+const WINDOWS: &[(&str, &[&str])] = &[("main", MAIN)];
+
+/// Commands the main window may call. A new command has to be added here as
+/// well as to the handler before the window can reach it
+const MAIN: &[&str] = &[
+    "register_ptt_shortcut",
+    "register_mute_shortcut",
+    "register_deafen_shortcut",
+    "unregister_shortcut",
+    "unregister_all_shortcuts",
+    "preview_import",
+    "run_import",
+    "list_cached_messages",
+    "list_outbox",
+    "ack_outbox",
+    "get_setting",
+    "list_settings",
+    "set_setting",
+    "export_settings_bundle",
+    "import_settings_bundle",
+    "list_profiles",
+    "get_active_profile",
+    "create_profile",
+    "rename_profile",
+    "set_profile_stay_connected",
+    "delete_profile",
+    "switch_profile",
+    "list_instances",
+    "register_instance",
+    "remove_instance",
+    "set_instance_credentials",
+    "get_instance_credentials",
+    "clear_instance_credentials",
+    "connect_gateway",
+    "disconnect_gateway",
+    "list_gateway_connections",
+    "download_attachment",
+    "list_downloads",
+    "open_download",
+    "confirm_open_unsafe_file",
+    "reveal_download",
+    "remove_download",
+    "save_draft",
+    "get_draft",
+    "list_drafts",
+    "clear_draft",
+    "get_thumbnail",
+    "get_animation_frames",
+    "queue_upload",
+    "list_uploads",
+    "cancel_upload",
+    "pick_attachments",
+    "read_clipboard_attachment",
+    "list_audio_input_devices",
+    "start_voice_message",
+    "stop_voice_message",
+    "cancel_voice_message",
+    "get_link_preview",
+    "render_linking_qr",
+    "scan_linking_qr",
+    "take_pending_deep_links",
+    "take_pending_shares",
+    "acquire_wake_lock",
+    "release_wake_lock",
+    "list_wake_locks",
+    "set_mic_mute_led",
+    "set_call_media_keys",
+    "set_launcher_muted",
+    "set_recent_conversations",
+    "show_notification",
+    "close_notification",
+    "list_dictionaries",
+    "download_dictionary",
+    "remove_dictionary",
+    "set_spellcheck_languages",
+    "list_custom_words",
+    "add_custom_word",
+    "remove_custom_word",
+    "list_running_games",
+    "get_streamer_mode",
+    "set_streamer_mode",
+    "speak",
+    "stop_speaking",
+    "announce",
+    "get_tts_rule",
+    "set_tts_rule",
+    "report_voice_state",
+    "check_for_update",
+    "download_update",
+    "install_update_now",
+    "install_update_on_quit",
+    "get_crash_reporting",
+    "set_crash_reporting",
+    "list_crash_reports",
+    "get_crash_report",
+    "submit_crash_report",
+    "delete_crash_report",
+    "get_log_levels",
+    "set_log_level",
+    "set_log_streaming",
+    "generate_debug_bundle",
+    "run_diagnostics",
+    "list_audio_output_devices",
+    "report_incoming_call",
+    "answer_call",
+    "decline_call",
+    "cancel_incoming_call",
+    "end_call",
+    "get_call_state",
+    "start_call_recording",
+    "push_call_recording_audio",
+    "add_call_recording_marker",
+    "stop_call_recording",
+    "get_noise_gate",
+    "set_noise_gate",
+    "set_priority_speaker",
+    "set_participant_speaking",
+    "get_ducking",
+    "list_caption_models",
+    "download_caption_model",
+    "remove_caption_model",
+    "start_captions",
+    "push_caption_audio",
+    "stop_captions",
+    "get_captions_running",
+    "get_resource_usage",
+    "get_dnd_schedule",
+    "set_dnd_schedule",
+    "get_dnd_state",
+    "get_control_api",
+    "set_control_api_enabled",
+    "reset_control_api_token",
+    "set_call_media_session",
+    "get_notification_rules",
+    "set_notification_rules",
+    "list_packs",
+    "install_pack",
+    "search_pack_items",
+    "remove_pack",
+    "schedule_message",
+    "list_scheduled_messages",
+    "cancel_scheduled_message",
+    "get_retention_report",
+    "set_retention_policy",
+    "get_relays",
+    "set_relays",
+    "probe_relays",
+    "pin_relay_region",
+    "get_relay_selection",
+    "diagnose_connectivity",
+    "list_native_themes",
+    "apply_native_theme",
+    "get_native_theme",
+    "get_channel_audio_overrides",
+    "set_channel_audio_overrides",
+    "join_voice_channel",
+    "leave_voice_channel",
+    "get_channel_audio",
+    "get_startup_status",
+    "await_subsystem",
+    "set_unread_counts",
+    "mark_conversation_read",
+    "get_unread_counts",
+    "set_shortcut_binding",
+    "get_shortcut_bindings",
+    "get_keyboard_layout",
+    "purge_temp_files",
+    "get_screenshot_folder",
+    "pick_screenshot_folder",
+    "clear_screenshot_folder",
+    "list_screenshot_offers",
+    "send_screenshot",
+    "dismiss_screenshot",
+    "request_remote_control",
+    "grant_remote_control",
+    "deny_remote_control",
+    "inject_remote_input",
+    "stop_remote_control",
+    "get_remote_control_state",
+    "get_data_usage",
+    "record_data_usage",
+    "get_metered_mode",
+    "set_metered_mode",
+    "get_performance_profile",
+    "set_performance_profile",
+    "get_automation_hooks",
+    "set_automation_hooks",
+    "get_allowed_programs",
+    "add_allowed_program",
+    "remove_allowed_program",
+    "test_automation_hook",
+    "pick_ffmpeg_path",
+    "clear_ffmpeg_path",
+    "set_remote_control_enabled",
+];
+
+struct Limit {
+    command: &'static str,
+    calls: usize,
+    per: Duration,
+}
+
+const fn limit(command: &'static str, calls: usize, per_secs: u64) -> Limit {
+    Limit {
+        command,
+        calls,
+        per: Duration::from_secs(per_secs),
+    }
+}
+
+/// Most calls allowed within a window of time, counted across windows
+const LIMITS: &[Limit] = &[
+    limit("get_instance_credentials", 20, 60),
+    limit("set_instance_credentials", 10, 60),
+    limit("clear_instance_credentials", 10, 60),
+    limit("get_control_api", 20, 60),
+    limit("reset_control_api_token", 5, 60),
+    limit("export_settings_bundle", 5, 60),
+    limit("import_settings_bundle", 5, 60),
+    limit("delete_profile", 3, 60),
+    limit("remove_instance", 5, 60),
+    limit("delete_crash_report", 20, 60),
+    limit("generate_debug_bundle", 3, 60),
+    limit("start_call_recording", 5, 60),
+    limit("stop_call_recording", 5, 60),
+    limit("add_allowed_program", 5, 60),
+    limit("test_automation_hook", 10, 60),
+    limit("set_remote_control_enabled", 5, 60),
+    limit("confirm_open_unsafe_file", 10, 60),
+];
+
+#[derive(Debug, thiserror::Error)]
+enum Denied {
+    #[error("{command} isn't available to this window")]
+    Window { command: String },
+    #[error("{command} was called too often; try again shortly")]
+    RateLimited { command: String },
+}
+
+/// Recent calls to rate limited commands
+#[derive(Default)]
+struct Limiter {
+    calls: Mutex<HashMap<&'static str, VecDeque<Instant>>>,
+}
+
+impl Limiter {
+    /// Count a call, unless it goes over the command's limit
+    fn allow(&self, limit: &Limit) -> bool {
+        let mut calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+        let recent = calls.entry(limit.command).or_default();
+        let now = Instant::now();
+        while recent
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= limit.per)
+        {
+            recent.pop_front();
+        }
+        if recent.len() >= limit.calls {
+            return false;
+        }
+        recent.push_back(now);
+        true
+    }
+}
+
+/// Wrap the generated command handler in the window and rate checks
+pub fn guard<R: Runtime>(
+    handler: impl Fn(Invoke<R>) -> bool + Send + Sync + 'static,
+) -> impl Fn(Invoke<R>) -> bool + Send + Sync + 'static {
+    let limiter = Limiter::default();
+    move |invoke| {
+        let label = invoke.message.webview_ref().label().to_string();
+        match check(&limiter, &label, invoke.message.command()) {
+            Ok(()) => handler(invoke),
+            Err(e) => {
+                log::warn!("Denied command from window {}: {}", label, e);
+                invoke.resolver.reject(e.to_string());
+                true
+            }
+        }
+    }
+}
+
+fn check(limiter: &Limiter, label: &str, command: &str) -> Result<(), Denied> {
+    let allowed = WINDOWS
+        .iter()
+        .find(|(window, _)| *window == label)
+        .is_some_and(|(_, commands)| commands.contains(&command));
+    if !allowed {
+        return Err(Denied::Window {
+            command: command.to_string(),
+        });
+    }
+
+    match LIMITS.iter().find(|limit| limit.command == command) {
+        Some(limit) if !limiter.allow(limit) => Err(Denied::RateLimited {
+            command: command.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,79 @@
+//! X11 reports the keysyms each key produces in the core keyboard mapping,
+//! whose first column is the active layout's unshifted one. X keycodes are
+//! evdev codes plus 8. Wayland keeps the keymap to the focused client, so
+//! there the US layout is assumed.
+
+use std::collections::BTreeMap;
+
+use tauri::AppHandle;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::ConnectionExt;
+use x11rb::rust_connection::RustConnection;
+
+use super::{Layout, LayoutError, KEYS};
+
+/// Offset between evdev codes and X keycodes
+const X_KEYCODE_OFFSET: u8 = 8;
+
+pub(super) struct Source {
+    conn: Option<RustConnection>,
+}
+
+impl Source {
+    pub(super) fn new(_app: &AppHandle) -> Self {
+        let conn = match x11rb::connect(None) {
+            Ok((conn, _)) => Some(conn),
+            Err(e) => {
+                log::debug!("No X display for the keyboard layout: {}", e);
+                None
+            }
+        };
+        Self { conn }
+    }
+
+    pub(super) fn layout(&self) -> Result<Layout, LayoutError> {
+        let conn = self
+            .conn
+            .as_ref()
+            .ok_or_else(|| LayoutError::Unavailable("no X display".to_string()))?;
+        let setup = conn.setup();
+        let (min, max) = (setup.min_keycode, setup.max_keycode);
+        let mapping = conn
+            .get_keyboard_mapping(min, max - min + 1)
+            .map_err(unavailable)?
+            .reply()
+            .map_err(unavailable)?;
+
+        let per_keycode = (mapping.keysyms_per_keycode as usize).max(1);
+        let labels: BTreeMap<&'static str, String> = KEYS
+            .iter()
+            .filter_map(|key| {
+                let keycode = key.evdev + X_KEYCODE_OFFSET;
+                let index = keycode.checked_sub(min)? as usize * per_keycode;
+                let keysym = *mapping.keysyms.get(index)?;
+                Some((key.code, keysym_char(keysym)?.to_string()))
+            })
+            .collect();
+
+        // The core mapping has no layout name, and a group switch only
+        // changes the keysyms, so they're what tells layouts apart
+        let id = format!(
+            "x11:{}",
+            labels.values().map(String::as_str).collect::<String>()
+        );
+        Ok(Layout { id, labels })
+    }
+}
+
+/// The character a keysym types, for Latin-1 and Unicode keysyms
+fn keysym_char(keysym: u32) -> Option<char> {
+    match keysym {
+        0x20..=0x7e | 0xa0..=0xff => char::from_u32(keysym),
+        0x0100_0000..=0x0110_ffff => char::from_u32(keysym - 0x0100_0000),
+        _ => None,
+    }
+}
+
+fn unavailable(e: impl std::fmt::Display) -> LayoutError {
+    LayoutError::Unavailable(e.to_string())
+}
@@ -0,0 +1,136 @@
+//! The current keyboard input source's `uchr` data says what each virtual
+//! keycode types. Text Input Sources has to be used from the main thread.
+
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use core_foundation::base::{CFType, TCFType};
+use core_foundation::string::CFString;
+use core_foundation_sys::base::CFTypeRef;
+use core_foundation_sys::data::{CFDataGetBytePtr, CFDataRef};
+use core_foundation_sys::string::CFStringRef;
+use tauri::AppHandle;
+
+use super::{Layout, LayoutError, KEYS};
+
+/// `kUCKeyActionDisplay`
+const KEY_ACTION_DISPLAY: u16 = 3;
+/// `kUCKeyTranslateNoDeadKeysMask`
+const NO_DEAD_KEYS: u32 = 1;
+
+/// How long to wait for the main thread to read the layout
+const MAIN_THREAD_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    static kTISPropertyUnicodeKeyLayoutData: CFStringRef;
+    static kTISPropertyInputSourceID: CFStringRef;
+    fn TISCopyCurrentKeyboardLayoutInputSource() -> CFTypeRef;
+    fn TISGetInputSourceProperty(source: CFTypeRef, key: CFStringRef) -> *const c_void;
+    fn LMGetKbdType() -> u8;
+    fn UCKeyTranslate(
+        layout: *const c_void,
+        key_code: u16,
+        key_action: u16,
+        modifier_key_state: u32,
+        keyboard_type: u32,
+        options: u32,
+        dead_key_state: *mut u32,
+        max_length: usize,
+        actual_length: *mut usize,
+        string: *mut u16,
+    ) -> i32;
+}
+
+pub(super) struct Source {
+    app: AppHandle,
+}
+
+impl Source {
+    pub(super) fn new(app: &AppHandle) -> Self {
+        Self { app: app.clone() }
+    }
+
+    pub(super) fn layout(&self) -> Result<Layout, LayoutError> {
+        let (tx, rx) = mpsc::channel();
+        self.app
+            .run_on_main_thread(move || {
+                let _ = tx.send(read());
+            })
+            .map_err(|e| LayoutError::Unavailable(e.to_string()))?;
+        rx.recv_timeout(MAIN_THREAD_TIMEOUT)
+            .map_err(|_| LayoutError::Unavailable("the main thread is busy".to_string()))?
+    }
+}
+
+fn read() -> Result<Layout, LayoutError> {
+    // SAFETY: called on the main thread; the copied source is owned and
+    // released when `source` drops
+    let source = unsafe {
+        let source = TISCopyCurrentKeyboardLayoutInputSource();
+        if source.is_null() {
+            return Err(LayoutError::Unavailable(
+                "no keyboard input source".to_string(),
+            ));
+        }
+        CFType::wrap_under_create_rule(source)
+    };
+
+    // SAFETY: the properties are owned by `source`, which outlives them here
+    let (id, data) = unsafe {
+        let raw = source.as_CFTypeRef();
+        let id = TISGetInputSourceProperty(raw, kTISPropertyInputSourceID) as CFStringRef;
+        let data = TISGetInputSourceProperty(raw, kTISPropertyUnicodeKeyLayoutData) as CFDataRef;
+        let id = if id.is_null() {
+            String::new()
+        } else {
+            CFString::wrap_under_get_rule(id).to_string()
+        };
+        (id, data)
+    };
+    if data.is_null() {
+        return Err(LayoutError::Unavailable(format!(
+            "{} has no Unicode layout",
+            id
+        )));
+    }
+
+    // SAFETY: `data` is valid `uchr` data while `source` is alive
+    let layout = unsafe { CFDataGetBytePtr(data) } as *const c_void;
+    // SAFETY: no preconditions
+    let keyboard_type = unsafe { LMGetKbdType() } as u32;
+    let labels: BTreeMap<&'static str, String> = KEYS
+        .iter()
+        .filter_map(|key| {
+            let mut dead_key_state = 0u32;
+            let mut buffer = [0u16; 8];
+            let mut length = 0usize;
+            // SAFETY: the buffer length is passed with it and the out
+            // pointers are to locals
+            let status = unsafe {
+                UCKeyTranslate(
+                    layout,
+                    key.mac,
+                    KEY_ACTION_DISPLAY,
+                    0,
+                    keyboard_type,
+                    NO_DEAD_KEYS,
+                    &mut dead_key_state,
+                    buffer.len(),
+                    &mut length,
+                    buffer.as_mut_ptr(),
+                )
+            };
+            if status != 0 {
+                return None;
+            }
+            let label = String::from_utf16(&buffer[..length.min(buffer.len())]).ok()?;
+            (!label.trim().is_empty()).then_some((key.code, label))
+        })
+        .collect();
+    drop(source);
+
+    Ok(Layout { id, labels })
+}
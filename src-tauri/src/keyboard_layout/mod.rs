@@ -0,0 +1,538 @@
+//! Keyboard layouts
+//!
+//! The global shortcut plugin binds physical keys, named after the key in
+//! that position on a US keyboard, but the frontend records what the user
+//! pressed as characters. On AZERTY the key labelled "A" is `KeyQ`, so a
+//! shortcut saved as "Ctrl+A" there fires on the wrong key, shows the wrong
+//! label, and means something different again once imported on a Dvorak
+//! machine. Shortcuts are therefore normalized here to physical key codes
+//! ("Control+KeyQ") before they're registered or stored, and rendered back
+//! in the layout in use when they're shown.
+//!
+//! The layout is read from the OS and polled, since switching layouts
+//! isn't an event every platform delivers to background apps. On a change
+//! the stored bindings are normalized again (older versions and settings
+//! bundles stored characters) and "keyboard-layout-changed" carries their
+//! new labels. Where the layout can't be read, e.g. on Wayland, the US one
+//! is assumed, which is what the plugin would have done anyway.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+use linux::Source;
+#[cfg(target_os = "macos")]
+use macos::Source;
+#[cfg(windows)]
+use windows::Source;
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_global_shortcut::Shortcut;
+
+use crate::db::Database;
+use crate::settings;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Id of the layout assumed when the OS's can't be read
+pub const FALLBACK_LAYOUT: &str = "us";
+
+#[derive(Debug, thiserror::Error)]
+pub enum LayoutError {
+    #[error("the keyboard layout isn't available: {0}")]
+    Unavailable(String),
+    #[error("\"{0}\" isn't a shortcut")]
+    Invalid(String),
+    #[error("no key on this keyboard types \"{0}\"")]
+    UnknownKey(String),
+    #[error("\"{0}\" isn't a modifier")]
+    UnknownModifier(String),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// A key whose label depends on the layout, and where it is on each
+/// platform
+struct Key {
+    /// W3C `KeyboardEvent.code`, which the shortcut plugin also uses
+    code: &'static str,
+    /// Linux evdev code; X11 keycodes are 8 more
+    evdev: u8,
+    /// Windows set 1 scancode
+    scancode: u16,
+    /// macOS virtual keycode
+    mac: u16,
+    /// What it types on a US layout
+    us: char,
+}
+
+const fn key(code: &'static str, evdev: u8, scancode: u16, mac: u16, us: char) -> Key {
+    Key {
+        code,
+        evdev,
+        scancode,
+        mac,
+        us,
+    }
+}
+
+/// The printable keys. Others (F1, Space, arrows) have the same name in
+/// every layout.
+const KEYS: &[Key] = &[
+    key("KeyA", 30, 0x1e, 0x00, 'a'),
+    key("KeyB", 48, 0x30, 0x0b, 'b'),
+    key("KeyC", 46, 0x2e, 0x08, 'c'),
+    key("KeyD", 32, 0x20, 0x02, 'd'),
+    key("KeyE", 18, 0x12, 0x0e, 'e'),
+    key("KeyF", 33, 0x21, 0x03, 'f'),
+    key("KeyG", 34, 0x22, 0x05, 'g'),
+    key("KeyH", 35, 0x23, 0x04, 'h'),
+    key("KeyI", 23, 0x17, 0x22, 'i'),
+    key("KeyJ", 36, 0x24, 0x26, 'j'),
+    key("KeyK", 37, 0x25, 0x28, 'k'),
+    key("KeyL", 38, 0x26, 0x25, 'l'),
+    key("KeyM", 50, 0x32, 0x2e, 'm'),
+    key("KeyN", 49, 0x31, 0x2d, 'n'),
+    key("KeyO", 24, 0x18, 0x1f, 'o'),
+    key("KeyP", 25, 0x19, 0x23, 'p'),
+    key("KeyQ", 16, 0x10, 0x0c, 'q'),
+    key("KeyR", 19, 0x13, 0x0f, 'r'),
+    key("KeyS", 31, 0x1f, 0x01, 's'),
+    key("KeyT", 20, 0x14, 0x11, 't'),
+    key("KeyU", 22, 0x16, 0x20, 'u'),
+    key("KeyV", 47, 0x2f, 0x09, 'v'),
+    key("KeyW", 17, 0x11, 0x0d, 'w'),
+    key("KeyX", 45, 0x2d, 0x07, 'x'),
+    key("KeyY", 21, 0x15, 0x10, 'y'),
+    key("KeyZ", 44, 0x2c, 0x06, 'z'),
+    key("Digit1", 2, 0x02, 0x12, '1'),
+    key("Digit2", 3, 0x03, 0x13, '2'),
+    key("Digit3", 4, 0x04, 0x14, '3'),
+    key("Digit4", 5, 0x05, 0x15, '4'),
+    key("Digit5", 6, 0x06, 0x17, '5'),
+    key("Digit6", 7, 0x07, 0x16, '6'),
+    key("Digit7", 8, 0x08, 0x1a, '7'),
+    key("Digit8", 9, 0x09, 0x1c, '8'),
+    key("Digit9", 10, 0x0a, 0x19, '9'),
+    key("Digit0", 11, 0x0b, 0x1d, '0'),
+    key("Minus", 12, 0x0c, 0x1b, '-'),
+    key("Equal", 13, 0x0d, 0x18, '='),
+    key("BracketLeft", 26, 0x1a, 0x21, '['),
+    key("BracketRight", 27, 0x1b, 0x1e, ']'),
+    key("Backslash", 43, 0x2b, 0x2a, '\\'),
+    key("Semicolon", 39, 0x27, 0x29, ';'),
+    key("Quote", 40, 0x28, 0x27, '\''),
+    key("Backquote", 41, 0x29, 0x32, '`'),
+    key("Comma", 51, 0x33, 0x2b, ','),
+    key("Period", 52, 0x34, 0x2f, '.'),
+    key("Slash", 53, 0x35, 0x2c, '/'),
+];
+
+/// Where a key is on each platform, for [`crate::remote_control`] to
+/// press it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Position {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub evdev: u8,
+    /// Set 1, with `0xe0` in the high byte for extended keys
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub scancode: u16,
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub mac: u16,
+}
+
+const fn at(evdev: u8, scancode: u16, mac: u16) -> Position {
+    Position {
+        evdev,
+        scancode,
+        mac,
+    }
+}
+
+/// Keys that aren't in [`KEYS`] because they type nothing
+const NAMED_KEYS: &[(&str, Position)] = &[
+    ("Escape", at(1, 0x01, 0x35)),
+    ("Tab", at(15, 0x0f, 0x30)),
+    ("Enter", at(28, 0x1c, 0x24)),
+    ("Space", at(57, 0x39, 0x31)),
+    ("Backspace", at(14, 0x0e, 0x33)),
+    ("Delete", at(111, 0xe053, 0x75)),
+    ("Insert", at(110, 0xe052, 0x72)),
+    ("Home", at(102, 0xe047, 0x73)),
+    ("End", at(107, 0xe04f, 0x77)),
+    ("PageUp", at(104, 0xe049, 0x74)),
+    ("PageDown", at(109, 0xe051, 0x79)),
+    ("ArrowUp", at(103, 0xe048, 0x7e)),
+    ("ArrowDown", at(108, 0xe050, 0x7d)),
+    ("ArrowLeft", at(105, 0xe04b, 0x7b)),
+    ("ArrowRight", at(106, 0xe04d, 0x7c)),
+    ("ShiftLeft", at(42, 0x2a, 0x38)),
+    ("ShiftRight", at(54, 0x36, 0x3c)),
+    ("ControlLeft", at(29, 0x1d, 0x3b)),
+    ("ControlRight", at(97, 0xe01d, 0x3e)),
+    ("AltLeft", at(56, 0x38, 0x3a)),
+    ("AltRight", at(100, 0xe038, 0x3d)),
+    ("MetaLeft", at(125, 0xe05b, 0x37)),
+    ("MetaRight", at(126, 0xe05c, 0x36)),
+    ("CapsLock", at(58, 0x3a, 0x39)),
+    ("F1", at(59, 0x3b, 0x7a)),
+    ("F2", at(60, 0x3c, 0x78)),
+    ("F3", at(61, 0x3d, 0x63)),
+    ("F4", at(62, 0x3e, 0x76)),
+    ("F5", at(63, 0x3f, 0x60)),
+    ("F6", at(64, 0x40, 0x61)),
+    ("F7", at(65, 0x41, 0x62)),
+    ("F8", at(66, 0x42, 0x64)),
+    ("F9", at(67, 0x43, 0x65)),
+    ("F10", at(68, 0x44, 0x6d)),
+    ("F11", at(87, 0x57, 0x67)),
+    ("F12", at(88, 0x58, 0x6f)),
+];
+
+/// Where the key with a W3C code is
+pub(crate) fn position(code: &str) -> Option<Position> {
+    KEYS.iter()
+        .find(|key| key.code == code)
+        .map(|key| at(key.evdev, key.scancode, key.mac))
+        .or_else(|| {
+            NAMED_KEYS
+                .iter()
+                .find(|(name, _)| *name == code)
+                .map(|(_, position)| *position)
+        })
+}
+
+/// Modifiers as stored, in the order they're written
+const MODIFIERS: [&str; 5] = ["CommandOrControl", "Control", "Alt", "Shift", "Super"];
+
+/// What a shortcut does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingAction {
+    Ptt,
+    Mute,
+    Deafen,
+}
+
+impl BindingAction {
+    pub const ALL: [BindingAction; 3] = [Self::Ptt, Self::Mute, Self::Deafen];
+
+    /// Setting holding the normalized shortcut
+    pub fn setting(self) -> &'static str {
+        match self {
+            Self::Ptt => "shortcuts.ptt",
+            Self::Mute => "shortcuts.mute",
+            Self::Deafen => "shortcuts.deafen",
+        }
+    }
+}
+
+/// A stored shortcut and how it reads in the current layout; the payload
+/// of `get_shortcut_bindings`
+#[derive(Debug, Clone, Serialize)]
+pub struct ShortcutBinding {
+    pub action: BindingAction,
+    /// Normalized, e.g. "Control+Shift+KeyQ"; `None` if it isn't bound
+    pub shortcut: Option<String>,
+    /// For display, e.g. "Ctrl+Shift+A" on AZERTY
+    pub label: Option<String>,
+}
+
+/// Payload of "keyboard-layout-changed"
+#[derive(Debug, Clone, Serialize)]
+pub struct LayoutChanged {
+    pub layout: String,
+    pub bindings: Vec<ShortcutBinding>,
+}
+
+/// A layout's labels for the keys in [`KEYS`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Layout {
+    /// The OS's name for it, only meaningful for telling layouts apart
+    pub id: String,
+    /// Key code to what the key types without modifiers; keys the OS
+    /// doesn't report are left out and shown by their US label
+    pub labels: BTreeMap<&'static str, String>,
+}
+
+impl Layout {
+    fn us() -> Self {
+        Self {
+            id: FALLBACK_LAYOUT.to_string(),
+            labels: KEYS
+                .iter()
+                .map(|key| (key.code, key.us.to_string()))
+                .collect(),
+        }
+    }
+
+    fn label(&self, key: &Key) -> String {
+        self.labels
+            .get(key.code)
+            .cloned()
+            .unwrap_or_else(|| key.us.to_string())
+            .to_uppercase()
+    }
+
+    /// The key that types `text` in this layout, or failing that on a US
+    /// keyboard, so digits still work on layouts that shift them
+    fn code_for(&self, text: &str) -> Option<&'static str> {
+        let text = text.to_lowercase();
+        KEYS.iter()
+            .find(|key| {
+                self.labels
+                    .get(key.code)
+                    .is_some_and(|l| l.to_lowercase() == text)
+            })
+            .or_else(|| KEYS.iter().find(|key| key.us.to_string() == text))
+            .map(|key| key.code)
+    }
+}
+
+/// The layout last read from the OS
+#[derive(Default)]
+pub struct KeyboardLayout {
+    current: Mutex<Option<Layout>>,
+}
+
+impl KeyboardLayout {
+    fn lock(&self) -> MutexGuard<'_, Option<Layout>> {
+        self.current.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The layout in use; blocks while it's read the first time
+pub fn current(app: &AppHandle) -> Layout {
+    if let Some(layout) = app.state::<KeyboardLayout>().lock().clone() {
+        return layout;
+    }
+    let layout = read(&Source::new(app));
+    *app.state::<KeyboardLayout>().lock() = Some(layout.clone());
+    layout
+}
+
+/// Turn a shortcut as typed in the current layout into physical key codes
+pub fn normalize(app: &AppHandle, shortcut: &str) -> Result<String, LayoutError> {
+    normalize_in(&current(app), shortcut)
+}
+
+/// How a normalized shortcut reads in the current layout
+pub fn render(app: &AppHandle, shortcut: &str) -> String {
+    render_in(&current(app), shortcut)
+}
+
+pub fn bindings(app: &AppHandle) -> Result<Vec<ShortcutBinding>, LayoutError> {
+    let layout = current(app);
+    let db = app.state::<Database>();
+    BindingAction::ALL
+        .iter()
+        .map(|&action| {
+            let shortcut = stored(&db, action)?;
+            Ok(ShortcutBinding {
+                action,
+                label: shortcut.as_deref().map(|s| render_in(&layout, s)),
+                shortcut,
+            })
+        })
+        .collect()
+}
+
+/// Normalize and store a shortcut, or clear it with `None`
+pub fn set_binding(
+    app: &AppHandle,
+    action: BindingAction,
+    shortcut: Option<&str>,
+) -> Result<ShortcutBinding, LayoutError> {
+    let layout = current(app);
+    let shortcut = shortcut.map(|s| normalize_in(&layout, s)).transpose()?;
+    let value = match &shortcut {
+        Some(shortcut) => serde_json::Value::String(shortcut.clone()),
+        None => serde_json::Value::Null,
+    };
+    app.state::<Database>()
+        .with(|conn| settings::set(conn, action.setting(), &value))?;
+    Ok(ShortcutBinding {
+        action,
+        label: shortcut.as_deref().map(|s| render_in(&layout, s)),
+        shortcut,
+    })
+}
+
+/// Watch for the layout changing for as long as the app runs
+/// Emits "keyboard-layout-changed"
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("keyboard-layout".to_string())
+        .spawn(move || {
+            let source = Source::new(&app);
+            let mut first = true;
+            loop {
+                let layout = read(&source);
+                let changed = {
+                    let state = app.state::<KeyboardLayout>();
+                    let mut current = state.lock();
+                    let changed = current.as_ref() != Some(&layout);
+                    *current = Some(layout.clone());
+                    changed
+                };
+                if changed || first {
+                    on_change(&app, &layout, !first);
+                }
+                first = false;
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to start keyboard layout watcher: {}", e);
+    }
+}
+
+fn read(source: &Source) -> Layout {
+    match source.layout() {
+        Ok(layout) => layout,
+        Err(e) => {
+            log::debug!("Assuming a US keyboard layout: {}", e);
+            Layout::us()
+        }
+    }
+}
+
+/// Normalize whatever's stored in characters and tell the frontend the
+/// new labels
+fn on_change(app: &AppHandle, layout: &Layout, emit: bool) {
+    let db = app.state::<Database>();
+    for action in BindingAction::ALL {
+        let stored = match stored(&db, action) {
+            Ok(Some(stored)) => stored,
+            Ok(None) => continue,
+            Err(e) => {
+                log::error!("Failed to read {:?} shortcut: {}", action, e);
+                continue;
+            }
+        };
+        match normalize_in(layout, &stored) {
+            Ok(normalized) if normalized != stored => {
+                let value = serde_json::Value::String(normalized);
+                if let Err(e) = db.with(|conn| settings::set(conn, action.setting(), &value)) {
+                    log::error!("Failed to store {:?} shortcut: {}", action, e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Leaving {:?} shortcut as is: {}", action, e),
+        }
+    }
+
+    if !emit {
+        return;
+    }
+    log::info!("Keyboard layout changed to {}", layout.id);
+    match bindings(app) {
+        Ok(bindings) => {
+            let _ = app.emit(
+                "keyboard-layout-changed",
+                LayoutChanged {
+                    layout: layout.id.clone(),
+                    bindings,
+                },
+            );
+        }
+        Err(e) => log::error!("Failed to read shortcuts: {}", e),
+    }
+}
+
+fn stored(db: &Database, action: BindingAction) -> rusqlite::Result<Option<String>> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, action.setting()))?
+        .and_then(|v| v.as_str().map(str::to_string)))
+}
+
+fn normalize_in(layout: &Layout, shortcut: &str) -> Result<String, LayoutError> {
+    let invalid = || LayoutError::Invalid(shortcut.to_string());
+    // "+" is itself a key on some layouts, so the last token is the key
+    // even when empty
+    let (modifiers, key) = match shortcut.trim().rsplit_once('+') {
+        Some((modifiers, "")) => (modifiers.strip_suffix('+').unwrap_or(modifiers), "+"),
+        Some((modifiers, key)) => (modifiers, key.trim()),
+        None => ("", shortcut.trim()),
+    };
+    if key.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut held = [false; MODIFIERS.len()];
+    if !modifiers.is_empty() {
+        for token in modifiers.split('+').map(str::trim) {
+            let modifier = match token.to_lowercase().as_str() {
+                "commandorcontrol" | "commandorctrl" | "cmdorctrl" | "cmdorcontrol" => 0,
+                "control" | "ctrl" => 1,
+                "alt" | "option" => 2,
+                "shift" => 3,
+                "super" | "command" | "cmd" | "meta" | "win" => 4,
+                "" => return Err(invalid()),
+                _ => return Err(LayoutError::UnknownModifier(token.to_string())),
+            };
+            held[modifier] = true;
+        }
+    }
+
+    let code = if key.chars().count() == 1 {
+        layout
+            .code_for(key)
+            .ok_or_else(|| LayoutError::UnknownKey(key.to_string()))?
+            .to_string()
+    } else {
+        // Key codes and named keys; the plugin knows which are real
+        KEYS.iter()
+            .find(|k| k.code.eq_ignore_ascii_case(key))
+            .map(|k| k.code.to_string())
+            .unwrap_or_else(|| key.to_string())
+    };
+
+    let normalized = MODIFIERS
+        .iter()
+        .zip(held)
+        .filter(|(_, held)| *held)
+        .map(|(name, _)| *name)
+        .chain([code.as_str()])
+        .collect::<Vec<_>>()
+        .join("+");
+    normalized.parse::<Shortcut>().map_err(|_| invalid())?;
+    Ok(normalized)
+}
+
+fn render_in(layout: &Layout, shortcut: &str) -> String {
+    shortcut
+        .split('+')
+        .map(|token| match KEYS.iter().find(|k| k.code == token) {
+            Some(key) => layout.label(key),
+            None => modifier_label(token).to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+fn modifier_label(token: &str) -> &str {
+    match token {
+        #[cfg(target_os = "macos")]
+        "CommandOrControl" | "Super" => "Cmd",
+        #[cfg(not(target_os = "macos"))]
+        "CommandOrControl" | "Control" => "Ctrl",
+        #[cfg(target_os = "macos")]
+        "Control" => "Ctrl",
+        #[cfg(target_os = "macos")]
+        "Alt" => "Option",
+        #[cfg(windows)]
+        "Super" => "Win",
+        other => other,
+    }
+}
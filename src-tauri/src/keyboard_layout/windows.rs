@@ -0,0 +1,71 @@
+//! Windows keeps a layout per thread, so the one that matters is the
+//! foreground window's. Each scancode is mapped to its virtual key in that
+//! layout and translated without modifiers.
+
+use std::collections::BTreeMap;
+
+use tauri::AppHandle;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    GetKeyboardLayout, MapVirtualKeyExW, ToUnicodeEx, MAPVK_VSC_TO_VK_EX,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+
+use super::{Layout, LayoutError, KEYS};
+
+/// `ToUnicodeEx` flag that leaves the keyboard state, including a pending
+/// dead key, alone
+const DONT_CHANGE_STATE: u32 = 1 << 2;
+
+pub(super) struct Source;
+
+impl Source {
+    pub(super) fn new(_app: &AppHandle) -> Self {
+        Source
+    }
+
+    pub(super) fn layout(&self) -> Result<Layout, LayoutError> {
+        // SAFETY: a null process id pointer is allowed, and thread 0 is the
+        // calling thread's layout when there's no foreground window
+        let hkl = unsafe {
+            let thread = GetWindowThreadProcessId(GetForegroundWindow(), std::ptr::null_mut());
+            GetKeyboardLayout(thread)
+        };
+        if hkl.is_null() {
+            return Err(LayoutError::Unavailable("no keyboard layout".to_string()));
+        }
+
+        let state = [0u8; 256];
+        let labels: BTreeMap<&'static str, String> = KEYS
+            .iter()
+            .filter_map(|key| {
+                let mut buffer = [0u16; 8];
+                // SAFETY: the state has the 256 entries ToUnicodeEx reads
+                // and the buffer length is passed with it
+                let written = unsafe {
+                    let vk = MapVirtualKeyExW(key.scancode as u32, MAPVK_VSC_TO_VK_EX, hkl);
+                    if vk == 0 {
+                        return None;
+                    }
+                    ToUnicodeEx(
+                        vk,
+                        key.scancode as u32,
+                        state.as_ptr(),
+                        buffer.as_mut_ptr(),
+                        buffer.len() as i32,
+                        DONT_CHANGE_STATE,
+                        hkl,
+                    )
+                };
+                // Negative for a dead key, which still writes its accent
+                let written = written.unsigned_abs() as usize;
+                let label = String::from_utf16(&buffer[..written.min(buffer.len())]).ok()?;
+                (!label.trim().is_empty()).then_some((key.code, label))
+            })
+            .collect();
+
+        Ok(Layout {
+            id: format!("{:08x}", hkl as usize),
+            labels,
+        })
+    }
+}
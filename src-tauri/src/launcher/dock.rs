@@ -0,0 +1,143 @@
+//! AppKit asks the application delegate for the dock menu each time it's
+//! opened. The delegate belongs to the windowing library, so the
+//! `applicationDockMenu:` method is added to its class at runtime and
+//! builds the menu from the current state.
+
+use std::cell::OnceCell;
+use std::sync::OnceLock;
+
+use objc2::rc::Retained;
+use objc2::runtime::{AnyClass, AnyObject, Imp, NSObject, Sel};
+use objc2::{define_class, msg_send, sel, MainThreadMarker, MainThreadOnly};
+use objc2_app_kit::{NSApplication, NSMenu, NSMenuItem};
+use objc2_foundation::NSString;
+use tauri::{AppHandle, Manager};
+
+use super::{Launcher, LauncherAction, LauncherState};
+
+/// Menu item tags; recent conversations are tagged from `TAG_RECENT` up
+const TAG_NEW_MESSAGE: isize = 1;
+const TAG_TOGGLE_MUTE: isize = 2;
+const TAG_RECENT: isize = 100;
+
+/// The Objective-C callbacks have no other way to reach this
+static APP: OnceLock<AppHandle> = OnceLock::new();
+
+thread_local! {
+    /// Menu items only hold their target weakly, so it's kept alive here
+    static TARGET: OnceCell<Retained<MenuTarget>> = const { OnceCell::new() };
+}
+
+define_class!(
+    // SAFETY: NSObject has no subclassing requirements and MenuTarget
+    // doesn't implement Drop
+    #[unsafe(super(NSObject))]
+    #[thread_kind = MainThreadOnly]
+    #[name = "RedoubtDockMenuTarget"]
+    struct MenuTarget;
+
+    impl MenuTarget {
+        #[unsafe(method(itemClicked:))]
+        fn item_clicked(&self, item: &NSMenuItem) {
+            let Some(app) = APP.get() else {
+                return;
+            };
+            let state = app.state::<Launcher>().state();
+            let action = match item.tag() {
+                TAG_NEW_MESSAGE => LauncherAction::NewMessage,
+                TAG_TOGGLE_MUTE => LauncherAction::ToggleMute,
+                tag => match state.conversations.get((tag - TAG_RECENT) as usize) {
+                    Some(conversation) if tag >= TAG_RECENT => {
+                        LauncherAction::OpenConversation(conversation.clone())
+                    }
+                    _ => return,
+                },
+            };
+            super::activate(app, action);
+        }
+    }
+);
+
+impl MenuTarget {
+    fn new(mtm: MainThreadMarker) -> Retained<Self> {
+        // SAFETY: plain NSObject initializer
+        unsafe { msg_send![Self::alloc(mtm), init] }
+    }
+}
+
+pub(super) fn install(app: &AppHandle) {
+    let _ = APP.set(app.clone());
+    let Some(mtm) = MainThreadMarker::new() else {
+        log::error!("Dock menu must be installed from the main thread");
+        return;
+    };
+    TARGET.with(|target| {
+        target.get_or_init(|| MenuTarget::new(mtm));
+    });
+
+    let Some(delegate) = NSApplication::sharedApplication(mtm).delegate() else {
+        log::warn!("No application delegate to add the dock menu to");
+        return;
+    };
+    let class: *const AnyClass = AsRef::<AnyObject>::as_ref(&*delegate).class();
+
+    let method: DockMenuFn = dock_menu;
+    // SAFETY: the signature matches the "@@:@" type encoding, which is
+    // that of `applicationDockMenu:`; the class is only added to, never
+    // changed otherwise
+    let added = unsafe {
+        objc2::ffi::class_addMethod(
+            class as *mut AnyClass,
+            sel!(applicationDockMenu:),
+            std::mem::transmute::<DockMenuFn, Imp>(method),
+            c"@@:@".as_ptr(),
+        )
+    };
+    if !added.as_bool() {
+        log::warn!("Application delegate already provides a dock menu");
+    }
+}
+
+type DockMenuFn = unsafe extern "C-unwind" fn(&AnyObject, Sel, &AnyObject) -> *mut NSMenu;
+
+unsafe extern "C-unwind" fn dock_menu(
+    _this: &AnyObject,
+    _cmd: Sel,
+    _sender: &AnyObject,
+) -> *mut NSMenu {
+    let (Some(mtm), Some(app)) = (MainThreadMarker::new(), APP.get()) else {
+        return std::ptr::null_mut();
+    };
+    Retained::autorelease_return(build(mtm, &app.state::<Launcher>().state()))
+}
+
+fn build(mtm: MainThreadMarker, state: &LauncherState) -> Retained<NSMenu> {
+    let menu = NSMenu::new(mtm);
+    let target = TARGET.with(|target| target.get().cloned());
+    let add = |title: &str, tag: isize| {
+        // SAFETY: `itemClicked:` is implemented by the target
+        let item = unsafe {
+            NSMenuItem::initWithTitle_action_keyEquivalent(
+                NSMenuItem::alloc(mtm),
+                &NSString::from_str(title),
+                Some(sel!(itemClicked:)),
+                &NSString::new(),
+            )
+        };
+        // SAFETY: the target outlives every menu, see `TARGET`
+        unsafe { item.setTarget(target.as_deref().map(|t| t.as_ref())) };
+        item.setTag(tag);
+        menu.addItem(&item);
+    };
+
+    for (i, conversation) in state.conversations.iter().enumerate() {
+        add(&state.title(conversation), TAG_RECENT + i as isize);
+    }
+    if !state.conversations.is_empty() {
+        menu.addItem(&NSMenuItem::separatorItem(mtm));
+    }
+    add("New Message", TAG_NEW_MESSAGE);
+    add(if state.muted { "Unmute" } else { "Mute" }, TAG_TOGGLE_MUTE);
+
+    menu
+}
@@ -0,0 +1,91 @@
+//! The taskbar jump list is replaced as a whole through
+//! `ICustomDestinationList`. Items are shell links back to the app's own
+//! executable, so choosing one starts a second instance whose arguments
+//! reach the running one through the single-instance channel.
+
+use std::path::Path;
+
+use windows::core::{Interface, HSTRING, PWSTR};
+use windows::Win32::Storage::EnhancedStorage::PKEY_Title;
+use windows::Win32::System::Com::StructuredStorage::PROPVARIANT;
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER,
+    COINIT_APARTMENTTHREADED,
+};
+use windows::Win32::System::Variant::VT_LPWSTR;
+use windows::Win32::UI::Shell::Common::{IObjectArray, IObjectCollection};
+use windows::Win32::UI::Shell::PropertiesSystem::IPropertyStore;
+use windows::Win32::UI::Shell::{
+    DestinationList, EnumerableObjectCollection, ICustomDestinationList, IShellLinkW, ShellLink,
+};
+
+pub(super) struct JumpItem {
+    pub title: String,
+    /// Command line arguments the app is started with
+    pub arguments: String,
+}
+
+pub(super) struct JumpList {
+    pub tasks: Vec<JumpItem>,
+    /// Named groups shown above the tasks, in order
+    pub categories: Vec<(&'static str, Vec<JumpItem>)>,
+}
+
+/// Replace the jump list. COM is set up for the calling thread, so call
+/// this from a thread of its own.
+pub(super) fn update(exe: &Path, list: &JumpList) -> windows::core::Result<()> {
+    // SAFETY: balanced by CoUninitialize below; every COM object is
+    // released before it
+    unsafe {
+        CoInitializeEx(None, COINIT_APARTMENTTHREADED).ok()?;
+        let result = build(exe, list);
+        CoUninitialize();
+        result
+    }
+}
+
+unsafe fn build(exe: &Path, list: &JumpList) -> windows::core::Result<()> {
+    let destinations: ICustomDestinationList =
+        CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER)?;
+    let mut min_slots = 0;
+    // Items the user removed from the list; they're left out on their own
+    let _removed: IObjectArray = destinations.BeginList(&mut min_slots)?;
+
+    for (name, items) in &list.categories {
+        if !items.is_empty() {
+            destinations.AppendCategory(&HSTRING::from(*name), &collection(exe, items)?)?;
+        }
+    }
+    destinations.AddUserTasks(&collection(exe, &list.tasks)?)?;
+    destinations.CommitList()
+}
+
+unsafe fn collection(exe: &Path, items: &[JumpItem]) -> windows::core::Result<IObjectArray> {
+    let collection: IObjectCollection =
+        CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER)?;
+    for item in items {
+        collection.AddObject(&link(exe, item)?)?;
+    }
+    collection.cast()
+}
+
+unsafe fn link(exe: &Path, item: &JumpItem) -> windows::core::Result<IShellLinkW> {
+    let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+    let exe = HSTRING::from(exe);
+    link.SetPath(&exe)?;
+    link.SetIconLocation(&exe, 0)?;
+    link.SetArguments(&HSTRING::from(item.arguments.as_str()))?;
+    link.SetDescription(&HSTRING::from(item.title.as_str()))?;
+
+    // The title shown in the list is a property of the link, not its name
+    let mut title: Vec<u16> = item.title.encode_utf16().chain([0]).collect();
+    let mut value = PROPVARIANT::default();
+    (*value.Anonymous.Anonymous).vt = VT_LPWSTR;
+    (*value.Anonymous.Anonymous).Anonymous.pwszVal = PWSTR(title.as_mut_ptr());
+    // The string is owned by `title`, so `value` is never cleared
+    let properties: IPropertyStore = link.cast()?;
+    properties.SetValue(&PKEY_Title, &value)?;
+    properties.Commit()?;
+
+    Ok(link)
+}
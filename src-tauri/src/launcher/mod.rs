@@ -0,0 +1,386 @@
+//! Launcher menus
+//!
+//! The menus the OS shows for the app outside its windows: the dock menu on
+//! macOS, the taskbar jump list on Windows and the tray icon everywhere.
+//! They offer toggling mute and jumping back into a pinned or recent
+//! conversation, plus starting a new message from the dock, opening
+//! settings from the jump list, or showing the window and quitting from
+//! the tray. The frontend keeps the mute state and the conversations up to
+//! date; choosing an item raises the window where needed and emits the
+//! matching event. Pinned conversations are saved so the menus have them
+//! before the frontend has loaded, and unread counts are kept here too
+//! (see [`unread`]) for the menus and the dock or launcher badge.
+//!
+//! Closing the main window hides it to the tray unless
+//! [`CLOSE_TO_TRAY_SETTING`] is off, handing message delivery to
+//! [`crate::background`] until it's shown again. With
+//! [`HEADLESS_SETTING`] on the window is destroyed instead, taking the
+//! webview and its processes with it, and [`main_window`] builds a new one
+//! from the config when it's next needed. Anything the frontend hasn't
+//! collected yet waits for the new one; events emitted while it loads are
+//! missed.
+//!
+//! Jump list items start the app again with arguments, which reach the
+//! running instance through the single-instance channel: conversations and
+//! settings as `redoubt://` links (see [`crate::deep_link`]) and mute as
+//! [`TOGGLE_MUTE_ARG`]. Mute isn't a link so web pages can't toggle it.
+
+#[cfg(target_os = "macos")]
+mod dock;
+#[cfg(windows)]
+mod jump_list;
+mod tray;
+pub mod unread;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, WebviewWindow, WebviewWindowBuilder};
+
+use crate::actions::{self, Action};
+use crate::db::Database;
+use crate::deep_link::{self, DeepLinks};
+use crate::share::{self, Shares};
+use crate::{background, remote_control, settings, spellcheck};
+
+/// Most conversations listed
+pub const MAX_CONVERSATIONS: usize = 8;
+
+/// Setting holding the pinned [`RecentConversation`]s as last reported
+pub const PINNED_SETTING: &str = "launcher.pinned_conversations";
+
+/// Id of the tray icon, for updating it
+pub const TRAY_ID: &str = "main";
+
+/// Argument that makes a second instance toggle mute in the running one
+pub const TOGGLE_MUTE_ARG: &str = "--toggle-mute";
+
+/// Setting for whether closing the main window hides it to the tray
+/// instead of quitting; on unless set to false
+pub const CLOSE_TO_TRAY_SETTING: &str = "window.close_to_tray";
+
+/// Setting for whether closing to the tray destroys the window rather than
+/// hiding it, to save memory; off unless set to true
+pub const HEADLESS_SETTING: &str = "window.headless_background";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentConversation {
+    pub instance_id: String,
+    pub channel_id: String,
+    pub title: String,
+    /// Listed under "Pinned" rather than "Recent" where the OS groups them
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LauncherState {
+    pub muted: bool,
+    pub conversations: Vec<RecentConversation>,
+    /// By instance and channel; conversations that are read aren't in it
+    pub unread: HashMap<(String, String), unread::UnreadCount>,
+    /// Who's controlling the computer (see [`crate::remote_control`])
+    pub remote_controller: Option<String>,
+}
+
+impl LauncherState {
+    fn pinned(&self) -> impl Iterator<Item = &RecentConversation> {
+        self.conversations.iter().filter(|c| c.pinned)
+    }
+
+    /// A conversation's title with its unread count, as menus show it
+    fn title(&self, conversation: &RecentConversation) -> String {
+        let key = (
+            conversation.instance_id.clone(),
+            conversation.channel_id.clone(),
+        );
+        match self.unread.get(&key) {
+            Some(unread) if unread.count > 0 => {
+                format!("{} ({})", conversation.title, unread.count)
+            }
+            _ => conversation.title.clone(),
+        }
+    }
+
+    fn total_unread(&self) -> u32 {
+        self.unread.values().map(|u| u.count).sum()
+    }
+}
+
+/// Something chosen from a launcher menu
+#[derive(Debug, Clone)]
+pub enum LauncherAction {
+    #[cfg(target_os = "macos")]
+    NewMessage,
+    ToggleMute,
+    OpenConversation(RecentConversation),
+    ShowWindow,
+    StopRemoteControl,
+    Quit,
+}
+
+#[derive(Default)]
+pub struct Launcher {
+    state: Mutex<LauncherState>,
+}
+
+impl Launcher {
+    pub fn state(&self) -> LauncherState {
+        self.lock().clone()
+    }
+
+    /// Mute as the frontend last reported it
+    pub fn is_muted(&self) -> bool {
+        self.lock().muted
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.lock().muted = muted;
+    }
+
+    pub fn set_remote_controller(&self, peer: Option<String>) {
+        self.lock().remote_controller = peer;
+    }
+
+    /// Replace the conversations, saving the pinned ones
+    pub fn set_conversations(
+        &self,
+        db: &Database,
+        mut conversations: Vec<RecentConversation>,
+    ) -> rusqlite::Result<()> {
+        conversations.truncate(MAX_CONVERSATIONS);
+        let pinned: Vec<&RecentConversation> = conversations.iter().filter(|c| c.pinned).collect();
+        let value = serde_json::to_value(&pinned).expect("conversations serialize");
+        db.with(|conn| settings::set(conn, PINNED_SETTING, &value))?;
+        self.lock().conversations = conversations;
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LauncherState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Hook the launcher menu into the OS; call once during setup
+pub fn install(app: &AppHandle) {
+    restore_pinned(app);
+    if let Err(e) = tray::install(app) {
+        log::error!("Failed to create the tray icon: {}", e);
+    }
+    #[cfg(target_os = "macos")]
+    dock::install(app);
+    // The tasks and pinned conversations are there before the frontend has
+    // sent anything
+    #[cfg(windows)]
+    refresh(app);
+}
+
+/// Rebuild menus that don't read the state when they're opened, and the
+/// badge
+pub fn refresh(app: &AppHandle) {
+    let state = app.state::<Launcher>().state();
+    if let Err(e) = tray::refresh(app, &state) {
+        log::warn!("Failed to update the tray menu: {}", e);
+    }
+    // Windows has no badge count, only overlay icons
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    if let Some(window) = app.get_webview_window("main") {
+        let total = state.total_unread();
+        if let Err(e) = window.set_badge_count((total > 0).then_some(total as i64)) {
+            log::debug!("Failed to set the badge count: {}", e);
+        }
+    }
+    #[cfg(windows)]
+    {
+        let list = jump_list(app, &state);
+        let spawned = std::thread::Builder::new()
+            .name("jump-list".to_string())
+            .spawn(move || {
+                let result = std::env::current_exe()
+                    .map_err(|e| e.to_string())
+                    .and_then(|exe| jump_list::update(&exe, &list).map_err(|e| e.to_string()));
+                if let Err(e) = result {
+                    log::warn!("Failed to update the jump list: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            log::error!("Failed to start jump list update: {}", e);
+        }
+    }
+}
+
+/// Swap in the new profile's pinned conversations and drop the previous
+/// one's unread counts
+pub fn profile_switched(app: &AppHandle) {
+    {
+        let launcher = app.state::<Launcher>();
+        let mut state = launcher.lock();
+        state.conversations.clear();
+        state.unread.clear();
+    }
+    restore_pinned(app);
+    refresh(app);
+    crate::native_theme::refresh_tray(app);
+}
+
+/// Load the pinned conversations saved last time
+fn restore_pinned(app: &AppHandle) {
+    let pinned: Vec<RecentConversation> = app
+        .state::<Database>()
+        .with(|conn| settings::get_value(conn, PINNED_SETTING))
+        .unwrap_or_else(|e| {
+            log::error!("Failed to read pinned conversations: {}", e);
+            None
+        })
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| log::warn!("Ignoring malformed pinned conversations: {}", e))
+                .ok()
+        })
+        .unwrap_or_default();
+    let launcher = app.state::<Launcher>();
+    let mut state = launcher.lock();
+    if state.conversations.is_empty() {
+        state.conversations = pinned;
+    }
+}
+
+/// Handle another launch of the app, forwarded by the single-instance
+/// channel with the directory it was started in; links among its
+/// arguments are handled by the deep link plugin
+pub fn second_instance(app: &AppHandle, args: &[String], cwd: &Path) {
+    if let Some(files) = share::files_from_args(args, cwd) {
+        share::handle(app, files);
+    } else if args.iter().any(|arg| arg == TOGGLE_MUTE_ARG) {
+        activate(app, LauncherAction::ToggleMute);
+    } else {
+        deep_link::focus_main_window(app);
+    }
+}
+
+/// Carry out a launcher menu choice
+pub fn activate(app: &AppHandle, action: LauncherAction) {
+    match action {
+        #[cfg(target_os = "macos")]
+        LauncherAction::NewMessage => {
+            deep_link::focus_main_window(app);
+            let _ = app.emit("new-message", ());
+        }
+        // Muting shouldn't pull the window in front of whatever's in use
+        LauncherAction::ToggleMute => {
+            actions::dispatch(app, Action::ToggleMute);
+        }
+        LauncherAction::OpenConversation(conversation) => {
+            deep_link::focus_main_window(app);
+            let _ = app.emit("open-conversation", conversation);
+        }
+        LauncherAction::ShowWindow => deep_link::focus_main_window(app),
+        LauncherAction::StopRemoteControl => {
+            remote_control::stop(app, remote_control::EndReason::Stopped)
+        }
+        LauncherAction::Quit => app.exit(0),
+    }
+}
+
+/// Handle a request to close the main window. Returns whether it went to
+/// the tray instead, in which case the close should be prevented.
+pub fn close_requested(app: &AppHandle) -> bool {
+    if !setting(app, CLOSE_TO_TRAY_SETTING, true) || app.tray_by_id(TRAY_ID).is_none() {
+        return false;
+    }
+
+    if let Some(window) = app.get_webview_window("main") {
+        if setting(app, HEADLESS_SETTING, false) {
+            // The next frontend collects what arrives in the meantime
+            app.state::<DeepLinks>().frontend_gone();
+            app.state::<Shares>().frontend_gone();
+            let _ = window.destroy();
+        } else {
+            let _ = window.hide();
+        }
+    }
+    background::enter(app);
+    true
+}
+
+/// The main window, built again if it was destroyed in the background
+pub fn main_window(app: &AppHandle) -> Option<WebviewWindow> {
+    if let Some(window) = app.get_webview_window("main") {
+        return Some(window);
+    }
+
+    let config = app
+        .config()
+        .app
+        .windows
+        .iter()
+        .find(|config| config.label == "main")?
+        .clone();
+    match WebviewWindowBuilder::from_config(app, &config).and_then(|builder| builder.build()) {
+        Ok(window) => {
+            spellcheck::apply(app, &app.state::<Database>());
+            Some(window)
+        }
+        Err(e) => {
+            log::error!("Failed to recreate the main window: {}", e);
+            None
+        }
+    }
+}
+
+fn setting(app: &AppHandle, key: &str, default: bool) -> bool {
+    app.state::<Database>()
+        .with(|conn| settings::get_value(conn, key))
+        .unwrap_or_else(|e| {
+            log::error!("Failed to read {}: {}", key, e);
+            None
+        })
+        .and_then(|v| v.as_bool())
+        .unwrap_or(default)
+}
+
+#[cfg(windows)]
+fn jump_list(app: &AppHandle, state: &LauncherState) -> jump_list::JumpList {
+    use crate::instances;
+
+    let known = app
+        .state::<Database>()
+        .with(|conn| instances::list(conn))
+        .unwrap_or_else(|e| {
+            log::error!("Failed to list instances: {}", e);
+            Vec::new()
+        });
+
+    let mut pinned = Vec::new();
+    let mut recent = Vec::new();
+    for conversation in &state.conversations {
+        let Some(instance) = known.iter().find(|i| i.id == conversation.instance_id) else {
+            continue;
+        };
+        let item = jump_list::JumpItem {
+            title: state.title(conversation),
+            arguments: deep_link::conversation_url(&instance.url, &conversation.channel_id),
+        };
+        if conversation.pinned {
+            pinned.push(item);
+        } else {
+            recent.push(item);
+        }
+    }
+
+    jump_list::JumpList {
+        tasks: vec![
+            jump_list::JumpItem {
+                title: "Toggle Mute".to_string(),
+                arguments: TOGGLE_MUTE_ARG.to_string(),
+            },
+            jump_list::JumpItem {
+                title: "Settings".to_string(),
+                arguments: deep_link::SETTINGS_URL.to_string(),
+            },
+        ],
+        categories: vec![("Pinned", pinned), ("Recent", recent)],
+    }
+}
@@ -0,0 +1,135 @@
+//! The tray icon, or menu bar extra on macOS. Clicking it brings the
+//! window back; its menu lists the pinned conversations and offers the
+//! same actions as the other launcher menus plus quitting, which is the
+//! only way out once the window closes to the tray. Unlike the dock menu
+//! it's built ahead of time, so it's rebuilt whenever the state changes.
+
+use tauri::menu::{IsMenuItem, Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager, Wry};
+
+use super::{Launcher, LauncherAction, LauncherState, TRAY_ID};
+
+const SHOW_ID: &str = "show";
+const STOP_REMOTE_CONTROL_ID: &str = "stop-remote-control";
+const TOGGLE_MUTE_ID: &str = "toggle-mute";
+const QUIT_ID: &str = "quit";
+/// Pinned conversations are `conversation:<index among the pinned>`
+const CONVERSATION_PREFIX: &str = "conversation:";
+
+pub(super) fn install(app: &AppHandle) -> tauri::Result<()> {
+    let state = app.state::<Launcher>().state();
+    let mut tray = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(tooltip(&state))
+        .menu(&menu(app, &state)?)
+        .show_menu_on_left_click(false)
+        .on_menu_event(|app, event| {
+            let id = event.id().as_ref();
+            let action = match id {
+                SHOW_ID => LauncherAction::ShowWindow,
+                STOP_REMOTE_CONTROL_ID => LauncherAction::StopRemoteControl,
+                TOGGLE_MUTE_ID => LauncherAction::ToggleMute,
+                QUIT_ID => LauncherAction::Quit,
+                _ => {
+                    let state = app.state::<Launcher>().state();
+                    let conversation = id
+                        .strip_prefix(CONVERSATION_PREFIX)
+                        .and_then(|i| i.parse().ok())
+                        .and_then(|i| state.pinned().nth(i));
+                    match conversation {
+                        Some(conversation) => {
+                            LauncherAction::OpenConversation(conversation.clone())
+                        }
+                        None => return,
+                    }
+                }
+            };
+            super::activate(app, action);
+        })
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                super::activate(tray.app_handle(), LauncherAction::ShowWindow);
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+    Ok(())
+}
+
+/// Rebuild the menu and tooltip for `state`
+pub(super) fn refresh(app: &AppHandle, state: &LauncherState) -> tauri::Result<()> {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return Ok(());
+    };
+    tray.set_menu(Some(menu(app, state)?))?;
+    tray.set_tooltip(Some(tooltip(state)))
+}
+
+fn menu(app: &AppHandle, state: &LauncherState) -> tauri::Result<Menu<Wry>> {
+    let mut items: Vec<Box<dyn IsMenuItem<Wry>>> = Vec::new();
+    if let Some(peer) = &state.remote_controller {
+        items.push(Box::new(MenuItem::with_id(
+            app,
+            STOP_REMOTE_CONTROL_ID,
+            format!("Stop {} Controlling This Computer", peer),
+            true,
+            None::<&str>,
+        )?));
+        items.push(Box::new(PredefinedMenuItem::separator(app)?));
+    }
+    for (i, conversation) in state.pinned().enumerate() {
+        items.push(Box::new(MenuItem::with_id(
+            app,
+            format!("{}{}", CONVERSATION_PREFIX, i),
+            state.title(conversation),
+            true,
+            None::<&str>,
+        )?));
+    }
+    if !items.is_empty() {
+        items.push(Box::new(PredefinedMenuItem::separator(app)?));
+    }
+    items.push(Box::new(MenuItem::with_id(
+        app,
+        SHOW_ID,
+        "Show Redoubt",
+        true,
+        None::<&str>,
+    )?));
+    items.push(Box::new(MenuItem::with_id(
+        app,
+        TOGGLE_MUTE_ID,
+        if state.muted { "Unmute" } else { "Mute" },
+        true,
+        None::<&str>,
+    )?));
+    items.push(Box::new(PredefinedMenuItem::separator(app)?));
+    items.push(Box::new(MenuItem::with_id(
+        app,
+        QUIT_ID,
+        "Quit Redoubt",
+        true,
+        None::<&str>,
+    )?));
+
+    let items: Vec<&dyn IsMenuItem<Wry>> = items.iter().map(|item| item.as_ref()).collect();
+    Menu::with_items(app, &items)
+}
+
+fn tooltip(state: &LauncherState) -> String {
+    if let Some(peer) = &state.remote_controller {
+        return format!("Redoubt - {} is controlling this computer", peer);
+    }
+    match state.total_unread() {
+        0 => "Redoubt".to_string(),
+        1 => "Redoubt - 1 unread message".to_string(),
+        n => format!("Redoubt - {} unread messages", n),
+    }
+}
@@ -0,0 +1,150 @@
+//! Unread counts
+//!
+//! Kept in the backend so the launcher menus, the tray and the badge can
+//! show them without asking the webview, which may be asleep or gone. The
+//! frontend sets the counts it loads from the server and clears a
+//! conversation once it's read; messages arriving on the backend's own
+//! gateway connections (see [`crate::gateway`]) while it's away are counted
+//! here as they come in. Only the active profile's are counted, since
+//! that's whose conversations the menus list.
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use super::Launcher;
+use crate::background;
+use crate::gateway::WsEvent;
+use crate::native_theme;
+use crate::profiles::Profiles;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnreadCount {
+    pub count: u32,
+    /// How many of them mention the user
+    #[serde(default)]
+    pub mentions: u32,
+}
+
+/// One channel's count, as the frontend reports it
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelUnread {
+    pub channel_id: String,
+    #[serde(flatten)]
+    pub unread: UnreadCount,
+}
+
+/// Result of `get_unread_counts`
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationUnread {
+    pub instance_id: String,
+    pub channel_id: String,
+    #[serde(flatten)]
+    pub unread: UnreadCount,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreatedMessage {
+    channel_id: String,
+    author: Author,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Author {
+    id: String,
+}
+
+/// Replace every count for an instance
+pub fn set_counts(app: &AppHandle, instance_id: &str, counts: Vec<ChannelUnread>) {
+    {
+        let launcher = app.state::<Launcher>();
+        let mut state = launcher.lock();
+        state
+            .unread
+            .retain(|(instance, _), _| instance != instance_id);
+        for channel in counts {
+            if channel.unread.count > 0 {
+                state.unread.insert(
+                    (instance_id.to_string(), channel.channel_id),
+                    channel.unread,
+                );
+            }
+        }
+    }
+    changed(app);
+}
+
+pub fn mark_read(app: &AppHandle, instance_id: &str, channel_id: &str) {
+    let removed = app
+        .state::<Launcher>()
+        .lock()
+        .unread
+        .remove(&(instance_id.to_string(), channel_id.to_string()));
+    if removed.is_some() {
+        changed(app);
+    }
+}
+
+pub fn list(app: &AppHandle) -> Vec<ConversationUnread> {
+    let mut counts: Vec<ConversationUnread> = app
+        .state::<Launcher>()
+        .lock()
+        .unread
+        .iter()
+        .map(|((instance_id, channel_id), unread)| ConversationUnread {
+            instance_id: instance_id.clone(),
+            channel_id: channel_id.clone(),
+            unread: *unread,
+        })
+        .collect();
+    counts.sort_by(|a, b| (&a.instance_id, &a.channel_id).cmp(&(&b.instance_id, &b.channel_id)));
+    counts
+}
+
+/// Whether anything is unread, for the tray icon
+pub fn any(app: &AppHandle) -> bool {
+    !app.state::<Launcher>().lock().unread.is_empty()
+}
+
+/// Count a message that arrived on a backend gateway connection
+pub(crate) fn on_event(app: &AppHandle, profile_id: &str, instance_id: &str, event: &WsEvent) {
+    if event.kind != "message.create" || app.state::<Profiles>().active().id != profile_id {
+        return;
+    }
+    let Some(message) = event
+        .payload
+        .clone()
+        .and_then(|payload| serde_json::from_value::<CreatedMessage>(payload).ok())
+    else {
+        return;
+    };
+
+    let app = app.clone();
+    let key = (profile_id.to_string(), instance_id.to_string());
+    tauri::async_runtime::spawn(async move {
+        let user = match background::user(&app, &key).await {
+            Ok(user) => user,
+            Err(e) => {
+                log::warn!("Not counting an unread message: {}", e);
+                return;
+            }
+        };
+        if message.author.id == user.id {
+            return;
+        }
+        let mentioned = background::mentions(&message.content, &user.username);
+        {
+            let launcher = app.state::<Launcher>();
+            let mut state = launcher.lock();
+            let unread = state.unread.entry((key.1, message.channel_id)).or_default();
+            unread.count += 1;
+            unread.mentions += mentioned as u32;
+        }
+        changed(&app);
+    });
+}
+
+fn changed(app: &AppHandle) {
+    super::refresh(app);
+    native_theme::refresh_tray(app);
+}
@@ -1,22 +1,416 @@
+mod accessibility;
+mod actions;
+mod api;
+mod audio;
+mod auth;
+mod automation;
+mod background;
+mod cache;
+mod calls;
+mod captions;
+mod clipboard;
+mod coalesce;
 mod commands;
+mod control_api;
+mod crash_reports;
+mod data_usage;
+mod db;
+#[cfg(target_os = "linux")]
+mod dbus;
+mod deep_link;
+mod diagnostics;
+mod dnd;
+mod downloads;
+mod drafts;
+mod gateway;
+mod hardware_keys;
+#[cfg(any(windows, target_os = "macos", target_os = "linux"))]
+mod idle;
+mod importer;
+mod instances;
+mod ipc_guard;
+mod keyboard_layout;
+mod launcher;
+mod link_preview;
+mod linking;
+mod logging;
+mod media;
+mod media_session;
+mod metrics;
+mod native_theme;
+mod notification_rules;
+mod notifications;
+mod packs;
+mod performance;
+mod process_scan;
+mod profiles;
+mod relays;
+mod remote_control;
+mod retention;
+mod scheduled;
+mod screenshots;
+mod secrets;
+mod settings;
+mod share;
+mod spellcheck;
+mod startup;
+mod streamer_mode;
+mod temp_files;
+mod tts;
+mod updater;
+mod uploads;
+mod wake_lock;
+
+use std::path::Path;
+
+use tauri::webview::PageLoadEvent;
+use tauri::{DragDropEvent, Manager, RunEvent, WindowEvent};
+use tauri_plugin_deep_link::DeepLinkExt;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    if crash_reports::monitor::run_if_requested() {
+        return;
+    }
+
     tauri::Builder::default()
+        // Must come first so a second instance hands over before any other
+        // plugin starts up in it
+        .plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
+            launcher::second_instance(app, &argv, Path::new(&cwd))
+        }))
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
-        .invoke_handler(tauri::generate_handler![
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_updater::Builder::new().build())
+        .setup(|app| {
+            app.manage(startup::Startup::default());
+            app.manage(logging::init(app.handle())?);
+            coalesce::spawn(app.handle());
+
+            let data_dir = app.path().app_data_dir()?;
+            std::fs::create_dir_all(&data_dir)?;
+
+            let profiles = profiles::Profiles::load(&data_dir, &app.path().app_cache_dir()?)?;
+            app.manage(drafts::Drafts::new(profiles.database().clone()));
+            app.manage(profiles.database().clone());
+            app.manage(profiles);
+            logging::restore_levels(app.handle());
+            app.manage(gateway::Gateway::default());
+            app.manage(uploads::Uploads::default());
+            app.manage(uploads::ingest::Offered::default());
+            app.manage(audio::voice_message::VoiceMessages::default());
+            app.manage(deep_link::DeepLinks::default());
+            app.manage(share::Shares::default());
+            app.manage(wake_lock::WakeLocks::default());
+            app.manage(launcher::Launcher::default());
+            app.manage(process_scan::RunningGames::default());
+            app.manage(streamer_mode::StreamerMode::default());
+            app.manage(tts::Tts::default());
+            app.manage(accessibility::VoiceAnnouncer::default());
+            app.manage(updater::Updates::default());
+            app.manage(crash_reports::CrashReporter::default());
+            app.manage(metrics::ResourceMonitor::default());
+            app.manage(calls::Calls::default());
+            app.manage(audio::call_recording::CallRecordings::default());
+            app.manage(audio::ducking::Ducking::default());
+            app.manage(captions::Captions::default());
+            app.manage(background::Background::default());
+            app.manage(dnd::DoNotDisturb::default());
+            app.manage(control_api::ControlApi::default());
+            app.manage(media_session::MediaSession::default());
+            app.manage(notification_rules::Rules::default());
+            app.manage(scheduled::Scheduler::default());
+            app.manage(retention::Retention::default());
+            app.manage(relays::Relays::default());
+            app.manage(native_theme::NativeTheme::default());
+            app.manage(audio::channel_overrides::ChannelAudio::default());
+            app.manage(keyboard_layout::KeyboardLayout::default());
+            app.manage(temp_files::TempFiles::default());
+            app.manage(screenshots::Screenshots::default());
+            app.manage(remote_control::RemoteControl::default());
+            app.manage(data_usage::DataUsage::default());
+            app.manage(performance::Performance::default());
+            app.manage(automation::Automation::default());
+            #[cfg(target_os = "linux")]
+            app.manage(dbus::SessionBus::default());
+            performance::apply(app.handle());
+            launcher::install(app.handle());
+            #[cfg(any(windows, target_os = "macos", target_os = "linux"))]
+            idle::spawn(app.handle());
+            hardware_keys::spawn(app.handle());
+            process_scan::spawn(app.handle());
+            metrics::spawn(app.handle());
+            dnd::spawn(app.handle());
+            scheduled::spawn(app.handle());
+            retention::spawn(app.handle());
+            data_usage::spawn(app.handle());
+            control_api::apply(app.handle());
+            audio::watchdog::install(app.handle());
+
+            let handle = app.handle().clone();
+            app.deep_link()
+                .on_open_url(move |event| deep_link::handle(&handle, event.urls()));
+            if let Some(urls) = app.deep_link().get_current()? {
+                deep_link::handle(app.handle(), urls);
+            }
+            share::install(app.handle());
+            let args = std::env::args().collect::<Vec<_>>();
+            if let Some(files) = share::files_from_args(&args, &std::env::current_dir()?) {
+                share::handle(app.handle(), files);
+            }
+            startup::schedule(app.handle());
+            Ok(())
+        })
+        .on_page_load(|webview, payload| {
+            if webview.label() == "main" && payload.event() == PageLoadEvent::Finished {
+                startup::first_paint(webview.app_handle());
+            }
+        })
+        .on_window_event(|window, event| match event {
+            WindowEvent::DragDrop(DragDropEvent::Drop { paths, .. }) => {
+                uploads::ingest::handle_drop(window.app_handle(), paths.clone());
+            }
+            WindowEvent::CloseRequested { api, .. }
+                if window.label() == "main" && launcher::close_requested(window.app_handle()) =>
+            {
+                api.prevent_close();
+            }
+            // However the window came back, the webview can take over again
+            WindowEvent::Focused(true) if window.label() == "main" => {
+                background::leave(window.app_handle());
+            }
+            _ => {}
+        })
+        .invoke_handler(ipc_guard::guard(tauri::generate_handler![
             commands::register_ptt_shortcut,
             commands::register_mute_shortcut,
             commands::register_deafen_shortcut,
             commands::unregister_shortcut,
             commands::unregister_all_shortcuts,
-        ])
-        .plugin(
-            tauri_plugin_log::Builder::default()
-                .level(log::LevelFilter::Info)
-                .build(),
-        )
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+            commands::preview_import,
+            commands::run_import,
+            commands::list_cached_messages,
+            commands::list_outbox,
+            commands::ack_outbox,
+            commands::get_setting,
+            commands::list_settings,
+            commands::set_setting,
+            commands::export_settings_bundle,
+            commands::import_settings_bundle,
+            commands::list_profiles,
+            commands::get_active_profile,
+            commands::create_profile,
+            commands::rename_profile,
+            commands::set_profile_stay_connected,
+            commands::delete_profile,
+            commands::switch_profile,
+            commands::list_instances,
+            commands::register_instance,
+            commands::remove_instance,
+            commands::set_instance_credentials,
+            commands::get_instance_credentials,
+            commands::clear_instance_credentials,
+            commands::connect_gateway,
+            commands::disconnect_gateway,
+            commands::list_gateway_connections,
+            commands::download_attachment,
+            commands::list_downloads,
+            commands::open_download,
+            commands::confirm_open_unsafe_file,
+            commands::reveal_download,
+            commands::remove_download,
+            commands::save_draft,
+            commands::get_draft,
+            commands::list_drafts,
+            commands::clear_draft,
+            commands::get_thumbnail,
+            commands::get_animation_frames,
+            commands::queue_upload,
+            commands::list_uploads,
+            commands::cancel_upload,
+            commands::pick_attachments,
+            commands::read_clipboard_attachment,
+            commands::list_audio_input_devices,
+            commands::start_voice_message,
+            commands::stop_voice_message,
+            commands::cancel_voice_message,
+            commands::get_link_preview,
+            commands::render_linking_qr,
+            commands::scan_linking_qr,
+            commands::take_pending_deep_links,
+            commands::take_pending_shares,
+            commands::acquire_wake_lock,
+            commands::release_wake_lock,
+            commands::list_wake_locks,
+            commands::set_mic_mute_led,
+            commands::set_call_media_keys,
+            commands::set_launcher_muted,
+            commands::set_recent_conversations,
+            commands::show_notification,
+            commands::close_notification,
+            commands::list_dictionaries,
+            commands::download_dictionary,
+            commands::remove_dictionary,
+            commands::set_spellcheck_languages,
+            commands::list_custom_words,
+            commands::add_custom_word,
+            commands::remove_custom_word,
+            commands::list_running_games,
+            commands::get_streamer_mode,
+            commands::set_streamer_mode,
+            commands::speak,
+            commands::stop_speaking,
+            commands::announce,
+            commands::get_tts_rule,
+            commands::set_tts_rule,
+            commands::report_voice_state,
+            commands::check_for_update,
+            commands::download_update,
+            commands::install_update_now,
+            commands::install_update_on_quit,
+            commands::get_crash_reporting,
+            commands::set_crash_reporting,
+            commands::list_crash_reports,
+            commands::get_crash_report,
+            commands::submit_crash_report,
+            commands::delete_crash_report,
+            commands::get_log_levels,
+            commands::set_log_level,
+            commands::set_log_streaming,
+            commands::generate_debug_bundle,
+            commands::run_diagnostics,
+            commands::list_audio_output_devices,
+            commands::report_incoming_call,
+            commands::answer_call,
+            commands::decline_call,
+            commands::cancel_incoming_call,
+            commands::end_call,
+            commands::get_call_state,
+            commands::start_call_recording,
+            commands::push_call_recording_audio,
+            commands::add_call_recording_marker,
+            commands::stop_call_recording,
+            commands::get_noise_gate,
+            commands::set_noise_gate,
+            commands::set_priority_speaker,
+            commands::set_participant_speaking,
+            commands::get_ducking,
+            commands::list_caption_models,
+            commands::download_caption_model,
+            commands::remove_caption_model,
+            commands::start_captions,
+            commands::push_caption_audio,
+            commands::stop_captions,
+            commands::get_captions_running,
+            commands::get_resource_usage,
+            commands::get_dnd_schedule,
+            commands::set_dnd_schedule,
+            commands::get_dnd_state,
+            commands::get_control_api,
+            commands::set_control_api_enabled,
+            commands::reset_control_api_token,
+            commands::set_call_media_session,
+            commands::get_notification_rules,
+            commands::set_notification_rules,
+            commands::list_packs,
+            commands::install_pack,
+            commands::search_pack_items,
+            commands::remove_pack,
+            commands::schedule_message,
+            commands::list_scheduled_messages,
+            commands::cancel_scheduled_message,
+            commands::get_retention_report,
+            commands::set_retention_policy,
+            commands::get_relays,
+            commands::set_relays,
+            commands::probe_relays,
+            commands::pin_relay_region,
+            commands::get_relay_selection,
+            commands::diagnose_connectivity,
+            commands::list_native_themes,
+            commands::apply_native_theme,
+            commands::get_native_theme,
+            commands::get_channel_audio_overrides,
+            commands::set_channel_audio_overrides,
+            commands::join_voice_channel,
+            commands::leave_voice_channel,
+            commands::get_channel_audio,
+            commands::get_startup_status,
+            commands::await_subsystem,
+            commands::set_unread_counts,
+            commands::mark_conversation_read,
+            commands::get_unread_counts,
+            commands::set_shortcut_binding,
+            commands::get_shortcut_bindings,
+            commands::get_keyboard_layout,
+            commands::purge_temp_files,
+            commands::get_screenshot_folder,
+            commands::pick_screenshot_folder,
+            commands::clear_screenshot_folder,
+            commands::list_screenshot_offers,
+            commands::send_screenshot,
+            commands::dismiss_screenshot,
+            commands::request_remote_control,
+            commands::grant_remote_control,
+            commands::deny_remote_control,
+            commands::inject_remote_input,
+            commands::stop_remote_control,
+            commands::get_remote_control_state,
+            commands::get_data_usage,
+            commands::record_data_usage,
+            commands::get_metered_mode,
+            commands::set_metered_mode,
+            commands::get_performance_profile,
+            commands::set_performance_profile,
+            commands::get_automation_hooks,
+            commands::set_automation_hooks,
+            commands::get_allowed_programs,
+            commands::add_allowed_program,
+            commands::remove_allowed_program,
+            commands::test_automation_hook,
+            commands::pick_ffmpeg_path,
+            commands::clear_ffmpeg_path,
+            commands::set_remote_control_enabled,
+        ]))
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| match event {
+            RunEvent::Exit => {
+                if let Err(e) = app.state::<drafts::Drafts>().flush() {
+                    log::error!("Failed to save drafts: {}", e);
+                }
+                if let Err(e) = audio::call_recording::stop(app) {
+                    log::error!("Failed to finish call recording: {}", e);
+                }
+                updater::on_exit(app);
+                temp_files::on_exit(app);
+                data_usage::on_exit(app);
+            }
+            // Closing the last window doesn't quit while it's in the background
+            RunEvent::ExitRequested {
+                code: None, api, ..
+            } if app.state::<background::Background>().is_active() => api.prevent_exit(),
+            // Clicking the dock icon with the window closed brings it back
+            #[cfg(target_os = "macos")]
+            RunEvent::Reopen {
+                has_visible_windows: false,
+                ..
+            } => deep_link::focus_main_window(app),
+            // Files opened with the app; its own links go to the deep link
+            // plugin
+            #[cfg(target_os = "macos")]
+            RunEvent::Opened { urls } => {
+                let files = urls
+                    .iter()
+                    .filter_map(|url| url.to_file_path().ok())
+                    .collect::<Vec<_>>();
+                if !files.is_empty() {
+                    share::handle(app, files);
+                }
+            }
+            _ => {}
+        });
 }
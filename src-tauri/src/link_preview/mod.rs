@@ -0,0 +1,384 @@
+//! Link previews
+//!
+//! Fetches a linked page's OpenGraph metadata from the backend so the
+//! webview never requests URLs picked by other people. Every request and
+//! redirect goes through [`guard`], bodies are size-capped and requests
+//! time out quickly. The preview image is fetched the same way and
+//! downscaled into the thumbnail cache, so it's shown as a `data:` URL.
+//!
+//! When a proxy is configured under [`PROXY_SETTING`] (e.g.
+//! `socks5h://127.0.0.1:9050` for Tor) all fetches go through it and host
+//! names are resolved by the proxy, so only URLs naming a local host or
+//! private address directly can be refused here.
+//!
+//! Results, including pages without a preview, are kept for [`CACHE_TTL`].
+
+pub mod guard;
+pub mod opengraph;
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{ACCEPT, CONTENT_TYPE, LOCATION};
+use reqwest::{redirect, Proxy};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::media::thumbnail::{self, Thumbnail, ThumbnailSize};
+use crate::media::MediaError;
+use guard::GuardError;
+
+/// Setting that turns link previews off; on unless set to `false`
+pub const LINK_PREVIEWS_SETTING: &str = "privacy.link_previews";
+/// Setting holding a proxy URL that link previews are fetched through
+pub const PROXY_SETTING: &str = "network.proxy";
+
+/// How long a fetched preview is reused
+pub const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REDIRECTS: usize = 5;
+/// Only the head is needed, so pages are cut off here
+const MAX_PAGE_BYTES: usize = 512 * 1024;
+const MAX_IMAGE_BYTES: usize = 8 * 1024 * 1024;
+const USER_AGENT: &str = concat!(
+    "Mozilla/5.0 (compatible; Redoubt/",
+    env!("CARGO_PKG_VERSION"),
+    "; link preview)"
+);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    /// OpenGraph type, e.g. "article" or "video.other"
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub image: Option<Thumbnail>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LinkPreviewError {
+    #[error("invalid link: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("{0}")]
+    Guard(#[from] GuardError),
+    #[error("invalid proxy: {0}")]
+    Proxy(reqwest::Error),
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("server responded with {0}")]
+    Status(u16),
+    #[error("too many redirects")]
+    TooManyRedirects,
+    #[error("response is too large")]
+    TooLarge,
+    #[error("images aren't being fetched")]
+    ImagesOff,
+    #[error("{0}")]
+    Image(#[from] MediaError),
+    #[error("{0}")]
+    Task(#[from] tauri::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// Fetch settings shared by a page and its image
+pub struct Fetcher {
+    proxy: Option<Proxy>,
+    images: bool,
+    /// Body bytes read, for data usage
+    received: AtomicU64,
+}
+
+impl Fetcher {
+    pub fn new(proxy: Option<&str>) -> Result<Self, LinkPreviewError> {
+        let proxy = proxy
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| Proxy::all(p.trim()).map_err(LinkPreviewError::Proxy))
+            .transpose()?;
+        Ok(Self {
+            proxy,
+            images: true,
+            received: AtomicU64::new(0),
+        })
+    }
+
+    /// Build previews without fetching their image, e.g. on a metered
+    /// connection. Images already in the thumbnail cache are still used;
+    /// previews cached without one keep it that way until refreshed.
+    pub fn without_images(mut self) -> Self {
+        self.images = false;
+        self
+    }
+
+    /// Body bytes read so far
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// Fetch a page and build its preview. Returns `None` for pages that
+    /// aren't HTML or don't describe themselves.
+    pub async fn fetch(
+        &self,
+        thumbnail_dir: &Path,
+        url: &str,
+    ) -> Result<Option<Preview>, LinkPreviewError> {
+        let url = Url::parse(url)?;
+        let (final_url, response) = self
+            .get(url, "text/html,application/xhtml+xml;q=0.9,*/*;q=0.1")
+            .await?;
+
+        let is_html = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_ascii_lowercase())
+            .is_some_and(|v| v.starts_with("text/html") || v.starts_with("application/xhtml"));
+        if !is_html {
+            return Ok(None);
+        }
+
+        let body = self.read(response, MAX_PAGE_BYTES, true).await?;
+        let page = opengraph::parse(&String::from_utf8_lossy(&body));
+        if page.is_empty() {
+            return Ok(None);
+        }
+
+        // A broken or refused image shouldn't cost the whole preview
+        let image_key = match page.image.as_deref().and_then(|i| final_url.join(i).ok()) {
+            Some(image_url) => match self.fetch_image(thumbnail_dir, image_url.clone()).await {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    log::info!("Skipping preview image {}: {}", image_url, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Ok(Some(Preview {
+            title: page.title,
+            description: page.description,
+            site_name: page.site_name,
+            kind: page.kind,
+            image_key,
+        }))
+    }
+
+    /// Fetch an image into the thumbnail cache, returning its cache key
+    async fn fetch_image(
+        &self,
+        thumbnail_dir: &Path,
+        url: Url,
+    ) -> Result<String, LinkPreviewError> {
+        let key = image_key(&url);
+        if thumbnail::cached(thumbnail_dir, &key)
+            .ok()
+            .flatten()
+            .is_some()
+        {
+            return Ok(key);
+        }
+        if !self.images {
+            return Err(LinkPreviewError::ImagesOff);
+        }
+
+        let (_, response) = self.get(url, "image/*").await?;
+        let bytes = self.read(response, MAX_IMAGE_BYTES, false).await?;
+
+        let dir = thumbnail_dir.to_path_buf();
+        let cache_key = key.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            thumbnail::generate(&dir, &cache_key, &bytes, ThumbnailSize::Thumbnail)
+        })
+        .await??;
+        Ok(key)
+    }
+
+    /// GET a URL, following redirects by hand so each hop is checked
+    async fn get(
+        &self,
+        mut url: Url,
+        accept: &str,
+    ) -> Result<(Url, reqwest::Response), LinkPreviewError> {
+        for _ in 0..=MAX_REDIRECTS {
+            guard::check_url(&url)?;
+
+            let mut builder = reqwest::Client::builder()
+                .redirect(redirect::Policy::none())
+                .timeout(TIMEOUT)
+                .connect_timeout(CONNECT_TIMEOUT)
+                .user_agent(USER_AGENT);
+            builder = match &self.proxy {
+                Some(proxy) => builder.proxy(proxy.clone()),
+                None => {
+                    let addrs = guard::resolve(&url).await?;
+                    // Ignore proxies from the environment, which would do
+                    // their own unchecked lookup
+                    let builder = builder.no_proxy();
+                    match url.domain() {
+                        Some(domain) => builder.resolve_to_addrs(domain, &addrs),
+                        None => builder,
+                    }
+                }
+            };
+
+            let response = builder
+                .build()?
+                .get(url.clone())
+                .header(ACCEPT, accept)
+                .send()
+                .await?;
+
+            let status = response.status();
+            if status.is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .ok_or(LinkPreviewError::Status(status.as_u16()))?;
+                url = url.join(location)?;
+                continue;
+            }
+            if !status.is_success() {
+                return Err(LinkPreviewError::Status(status.as_u16()));
+            }
+            return Ok((url, response));
+        }
+
+        Err(LinkPreviewError::TooManyRedirects)
+    }
+
+    /// Read a body up to `limit` bytes, either cutting it off there or failing
+    async fn read(
+        &self,
+        mut response: reqwest::Response,
+        limit: usize,
+        truncate: bool,
+    ) -> Result<Vec<u8>, LinkPreviewError> {
+        if !truncate && response.content_length().is_some_and(|l| l > limit as u64) {
+            return Err(LinkPreviewError::TooLarge);
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            self.received
+                .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            if body.len() + chunk.len() > limit {
+                if !truncate {
+                    return Err(LinkPreviewError::TooLarge);
+                }
+                body.extend_from_slice(&chunk[..limit - body.len()]);
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+}
+
+/// A preview as cached, with the image referenced by its thumbnail key
+#[derive(Debug, Clone)]
+pub struct Preview {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    pub kind: Option<String>,
+    pub image_key: Option<String>,
+}
+
+impl Preview {
+    /// Attach the cached image, if it's still in the thumbnail cache
+    pub fn into_link_preview(self, url: &str, thumbnail_dir: &Path) -> LinkPreview {
+        let image = self
+            .image_key
+            .as_deref()
+            .and_then(|key| thumbnail::cached(thumbnail_dir, key).ok().flatten());
+        LinkPreview {
+            url: url.to_string(),
+            title: self.title,
+            description: self.description,
+            site_name: self.site_name,
+            kind: self.kind,
+            image,
+        }
+    }
+}
+
+fn image_key(url: &Url) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"link-preview\0");
+    hasher.update(url.as_str().as_bytes());
+    hasher.finalize()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A cached lookup that hasn't expired: `Some(None)` means the page had no preview
+pub fn cached(conn: &Connection, url: &str) -> rusqlite::Result<Option<Option<Preview>>> {
+    let row = conn
+        .prepare_cached("SELECT * FROM link_previews WHERE url = ?1")?
+        .query_row([url], |row| {
+            let fetched_at: DateTime<Utc> = row.get("fetched_at")?;
+            let preview = Preview {
+                title: row.get("title")?,
+                description: row.get("description")?,
+                site_name: row.get("site_name")?,
+                kind: row.get("kind")?,
+                image_key: row.get("image_key")?,
+            };
+            Ok((fetched_at, preview))
+        })
+        .optional()?;
+
+    let ttl = chrono::Duration::from_std(CACHE_TTL).expect("TTL fits");
+    Ok(row
+        .filter(|(fetched_at, _)| Utc::now() - *fetched_at < ttl)
+        .map(|(_, preview)| {
+            (preview.title.is_some() || preview.description.is_some()).then_some(preview)
+        }))
+}
+
+/// Drop the cached result for `url`. Returns `None` if there wasn't one,
+/// otherwise the key of its image in the thumbnail cache, if it had one.
+pub fn forget(conn: &Connection, url: &str) -> rusqlite::Result<Option<Option<String>>> {
+    let image_key = conn
+        .prepare_cached("DELETE FROM link_previews WHERE url = ?1 RETURNING image_key")?
+        .query_row([url], |row| row.get(0))
+        .optional()?;
+    Ok(image_key)
+}
+
+/// Cache the result of fetching `url`
+pub fn store(conn: &Connection, url: &str, preview: Option<&Preview>) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO link_previews (url, title, description, site_name, kind, image_key, fetched_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (url) DO UPDATE SET
+            title = excluded.title,
+            description = excluded.description,
+            site_name = excluded.site_name,
+            kind = excluded.kind,
+            image_key = excluded.image_key,
+            fetched_at = excluded.fetched_at",
+    )?
+    .execute(params![
+        url,
+        preview.and_then(|p| p.title.as_deref()),
+        preview.and_then(|p| p.description.as_deref()),
+        preview.and_then(|p| p.site_name.as_deref()),
+        preview.and_then(|p| p.kind.as_deref()),
+        preview.and_then(|p| p.image_key.as_deref()),
+        Utc::now(),
+    ])?;
+    Ok(())
+}
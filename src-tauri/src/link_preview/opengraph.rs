@@ -0,0 +1,238 @@
+//! OpenGraph extraction
+//!
+//! A small scanner over the document head rather than a full HTML parser:
+//! previews only need `<title>` and `<meta>` tags, which appear before
+//! `<body>` on any page worth previewing.
+
+use std::collections::HashMap;
+
+/// The tags a preview is built from, with values still unresolved
+#[derive(Debug, Default)]
+pub struct Page {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub site_name: Option<String>,
+    pub kind: Option<String>,
+}
+
+impl Page {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.description.is_none()
+    }
+}
+
+/// Extract preview metadata from the start of an HTML document
+pub fn parse(html: &str) -> Page {
+    let lower = html.to_ascii_lowercase();
+    let mut meta: HashMap<String, String> = HashMap::new();
+    let mut title = None;
+    let mut pos = 0;
+
+    while let Some(offset) = lower[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &lower[start + 1..];
+
+        // Skip comments, scripts and styles wholesale; their contents can
+        // contain anything, including fake tags
+        let skip_to = if rest.starts_with("!--") {
+            Some("-->")
+        } else if tag_is(rest, "script") {
+            Some("</script")
+        } else if tag_is(rest, "style") {
+            Some("</style")
+        } else {
+            None
+        };
+        if let Some(end) = skip_to {
+            match lower[start..].find(end) {
+                Some(found) => {
+                    pos = start + found + end.len();
+                    continue;
+                }
+                None => break,
+            }
+        }
+
+        let Some(length) = lower[start..].find('>') else {
+            break;
+        };
+        let end = start + length;
+        let tag = &html[start + 1..end];
+        pos = end + 1;
+
+        if tag_is(rest, "meta") {
+            let attributes = attributes(&tag[4..]);
+            let key = attributes
+                .get("property")
+                .or_else(|| attributes.get("name"))
+                .map(|k| k.to_ascii_lowercase());
+            if let (Some(key), Some(content)) = (key, attributes.get("content")) {
+                meta.entry(key).or_insert_with(|| content.clone());
+            }
+        } else if tag_is(rest, "title") && title.is_none() {
+            if let Some(close) = lower[pos..].find("</title") {
+                title = Some(decode_entities(&html[pos..pos + close]));
+                pos += close;
+            }
+        } else if tag_is(rest, "body") || rest.starts_with("/head") {
+            break;
+        }
+    }
+
+    let mut take = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| meta.remove(*key))
+            .map(|v| clean(&v))
+            .filter(|v| !v.is_empty())
+    };
+    Page {
+        title: take(&["og:title", "twitter:title"])
+            .or_else(|| title.map(|t| clean(&t)).filter(|t| !t.is_empty())),
+        description: take(&["og:description", "twitter:description", "description"]),
+        image: take(&[
+            "og:image:secure_url",
+            "og:image",
+            "og:image:url",
+            "twitter:image",
+            "twitter:image:src",
+        ]),
+        site_name: take(&["og:site_name", "application-name"]),
+        kind: take(&["og:type"]),
+    }
+}
+
+/// Whether `rest` (the text after `<`) opens the given tag
+fn tag_is(rest: &str, name: &str) -> bool {
+    rest.strip_prefix(name).is_some_and(|after| {
+        after
+            .chars()
+            .next()
+            .map_or(true, |c| c.is_whitespace() || c == '>' || c == '/')
+    })
+}
+
+/// Attributes of a tag, keyed by lowercase name, values entity-decoded
+fn attributes(tag: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut chars = tag.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() || c == '/' {
+            chars.next();
+            continue;
+        }
+
+        let mut name_end = start;
+        while let Some(&(i, c)) = chars.peek() {
+            if c.is_whitespace() || c == '=' || c == '/' {
+                break;
+            }
+            name_end = i + c.len_utf8();
+            chars.next();
+        }
+        let name = tag[start..name_end].to_ascii_lowercase();
+        if name.is_empty() {
+            chars.next();
+            continue;
+        }
+
+        while chars.peek().is_some_and(|&(_, c)| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().map(|&(_, c)| c) != Some('=') {
+            attributes.entry(name).or_insert_with(String::new);
+            continue;
+        }
+        chars.next();
+        while chars.peek().is_some_and(|&(_, c)| c.is_whitespace()) {
+            chars.next();
+        }
+
+        let value = match chars.peek().copied() {
+            Some((i, quote @ ('"' | '\''))) => {
+                chars.next();
+                let mut end = tag.len();
+                for (j, c) in chars.by_ref() {
+                    if c == quote {
+                        end = j;
+                        break;
+                    }
+                }
+                &tag[i + 1..end]
+            }
+            Some((i, _)) => {
+                let mut end = tag.len();
+                while let Some(&(j, c)) = chars.peek() {
+                    if c.is_whitespace() {
+                        end = j;
+                        break;
+                    }
+                    chars.next();
+                }
+                &tag[i..end]
+            }
+            None => "",
+        };
+        attributes
+            .entry(name)
+            .or_insert_with(|| decode_entities(value));
+    }
+
+    attributes
+}
+
+/// Collapse whitespace and cap the length of a text value
+fn clean(value: &str) -> String {
+    const MAX_CHARS: usize = 500;
+
+    let collapsed = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    match collapsed.char_indices().nth(MAX_CHARS) {
+        Some((cut, _)) => format!("{}…", &collapsed[..cut]),
+        None => collapsed,
+    }
+}
+
+/// Decode the character references that show up in titles and descriptions
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|n| n.parse().ok()))
+                    .and_then(char::from_u32),
+            }?;
+            Some((c, end + 1))
+        });
+
+        match decoded {
+            Some((c, length)) => {
+                out.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
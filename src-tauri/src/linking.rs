@@ -0,0 +1,153 @@
+//! Device linking codes
+//!
+//! A device that is already signed in shows a QR code carrying a
+//! provisioning payload: the instance to join and a one-time link code,
+//! plus optionally the public key the new device should verify its peer
+//! against. The new device scans it (from camera preview frames handed
+//! over by the webview, or from an image file) and gets the payload back
+//! instead of asking the user to type it.
+//!
+//! Payloads are encoded as `redoubt://link?...` URLs so scanning one with
+//! a phone camera opens the app as well.
+
+use image::DynamicImage;
+use qrcode::render::svg;
+use qrcode::{EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Frames are downscaled to at most this edge before scanning; QR codes
+/// held up to a camera are large enough that detail beyond it doesn't help
+const SCAN_EDGE: u32 = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkingPayload {
+    /// Base URL of the instance the new device should sign in to
+    pub instance_url: String,
+    /// One-time code that authorizes the new device
+    pub code: String,
+    /// Linking device's public key, if the new device should verify it
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+/// A rendered linking code
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkingQr {
+    pub uri: String,
+    /// QR code as a standalone SVG document
+    pub svg: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LinkingError {
+    #[error("failed to encode QR code: {0}")]
+    Encode(#[from] qrcode::types::QrError),
+    #[error("QR code isn't a Redoubt linking code")]
+    NotLinking,
+    #[error("invalid instance URL: {0}")]
+    InstanceUrl(String),
+}
+
+impl LinkingPayload {
+    pub fn to_uri(&self) -> String {
+        let mut uri = Url::parse("redoubt://link").expect("static URL is valid");
+        {
+            let mut query = uri.query_pairs_mut();
+            query.append_pair("v", "1");
+            query.append_pair("instance", &self.instance_url);
+            query.append_pair("code", &self.code);
+            if let Some(key) = &self.key {
+                query.append_pair("key", key);
+            }
+        }
+        uri.into()
+    }
+
+    /// Parse and validate a `redoubt://link` URI
+    pub fn parse(uri: &str) -> Result<Self, LinkingError> {
+        let uri = Url::parse(uri.trim()).map_err(|_| LinkingError::NotLinking)?;
+        if uri.scheme() != "redoubt" || uri.host_str() != Some("link") {
+            return Err(LinkingError::NotLinking);
+        }
+
+        let mut version = None;
+        let mut instance_url = None;
+        let mut code = None;
+        let mut key = None;
+        for (name, value) in uri.query_pairs() {
+            match &*name {
+                "v" => version = Some(value.into_owned()),
+                "instance" => instance_url = Some(value.into_owned()),
+                "code" => code = Some(value.into_owned()),
+                "key" => key = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        if version.as_deref() != Some("1") {
+            return Err(LinkingError::NotLinking);
+        }
+
+        let instance_url = instance_url.ok_or(LinkingError::NotLinking)?;
+        let parsed = Url::parse(&instance_url)
+            .map_err(|_| LinkingError::InstanceUrl(instance_url.clone()))?;
+        if !matches!(parsed.scheme(), "https" | "http") || parsed.host_str().is_none() {
+            return Err(LinkingError::InstanceUrl(instance_url));
+        }
+
+        let code = code
+            .filter(|c| !c.is_empty())
+            .ok_or(LinkingError::NotLinking)?;
+        Ok(Self {
+            instance_url,
+            code,
+            key: key.filter(|k| !k.is_empty()),
+        })
+    }
+}
+
+/// Render a payload as a QR code
+pub fn render(payload: &LinkingPayload) -> Result<LinkingQr, LinkingError> {
+    let uri = payload.to_uri();
+    let code = QrCode::with_error_correction_level(&uri, EcLevel::M)?;
+    let svg = code
+        .render::<svg::Color>()
+        .min_dimensions(256, 256)
+        .quiet_zone(true)
+        .build();
+    Ok(LinkingQr { uri, svg })
+}
+
+/// Look for a linking code in an image. Returns `None` if there's no
+/// readable QR code, so the caller can try the next camera frame.
+pub fn scan(image: &DynamicImage) -> Result<Option<LinkingPayload>, LinkingError> {
+    let image = if image.width() > SCAN_EDGE || image.height() > SCAN_EDGE {
+        image.thumbnail(SCAN_EDGE, SCAN_EDGE)
+    } else {
+        image.clone()
+    };
+    let luma = image.to_luma8();
+
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+        luma.width() as usize,
+        luma.height() as usize,
+        |x, y| luma.get_pixel(x as u32, y as u32).0[0],
+    );
+
+    // Another QR code in view shouldn't hide the linking one
+    let mut error = None;
+    for grid in prepared.detect_grids() {
+        let Ok((_, content)) = grid.decode() else {
+            continue;
+        };
+        match LinkingPayload::parse(&content) {
+            Ok(payload) => return Ok(Some(payload)),
+            Err(e) => error = Some(e),
+        }
+    }
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,256 @@
+//! Logging
+//!
+//! Events from `tracing` and from the `log` macros used across the app and
+//! its dependencies go through one subscriber:
+//! - JSON lines in the log directory, rotated daily with a week kept
+//! - readable lines on stdout
+//! - "log-event" to the frontend's debug console in batches (see
+//!   [`crate::coalesce`]), while it asks for them
+//!
+//! Levels can be set per target (a module path, matching everything under
+//! it) and are saved with the settings. `*` sets the level for targets
+//! without one of their own, `info` unless changed.
+//!
+//! Commands turn errors into the strings the frontend gets with
+//! [`LogErr::log_err`], which logs them along with where they came from.
+
+mod stream;
+
+pub(crate) use stream::without_streaming;
+
+use std::collections::BTreeMap;
+use std::panic::Location;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::Rotation;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Registry};
+
+use crate::db::Database;
+use crate::settings;
+
+/// Setting holding the levels set per target, by target
+pub const LEVELS_SETTING: &str = "logging.levels";
+
+/// Target that stands for every target without a level of its own
+pub const DEFAULT_TARGET: &str = "*";
+
+/// Log files are named `redoubt.<date>.log`
+const FILE_PREFIX: &str = "redoubt";
+const FILE_SUFFIX: &str = "log";
+/// A week of daily files
+const MAX_FILES: usize = 7;
+
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::INFO;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoggingError {
+    #[error("unknown log level: {0}")]
+    UnknownLevel(String),
+    #[error("invalid log target: {0}")]
+    InvalidTarget(String),
+    #[error("failed to open log file: {0}")]
+    File(#[from] tracing_appender::rolling::InitError),
+    #[error("failed to set up logging: {0}")]
+    Init(#[from] tracing_subscriber::util::TryInitError),
+    #[error("failed to change log levels: {0}")]
+    Reload(#[from] reload::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// A level set for a target
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLevel {
+    pub target: String,
+    pub level: String,
+}
+
+pub struct Logging {
+    filter: reload::Handle<Targets, Registry>,
+    levels: Mutex<BTreeMap<String, LevelFilter>>,
+    stream: Arc<stream::Stream>,
+    _guard: WorkerGuard,
+}
+
+impl Logging {
+    /// Levels set per target, `*` first
+    pub fn levels(&self) -> Vec<LogLevel> {
+        let levels = self.lock();
+        let default = levels.get(DEFAULT_TARGET).copied().unwrap_or(DEFAULT_LEVEL);
+        std::iter::once((DEFAULT_TARGET, default))
+            .chain(
+                levels
+                    .iter()
+                    .filter(|(target, _)| *target != DEFAULT_TARGET)
+                    .map(|(target, level)| (target.as_str(), *level)),
+            )
+            .map(|(target, level)| LogLevel {
+                target: target.to_string(),
+                level: level.to_string().to_lowercase(),
+            })
+            .collect()
+    }
+
+    /// Start or stop sending "log-event" to the frontend
+    pub fn set_streaming(&self, enabled: bool) {
+        self.stream.set_enabled(enabled);
+    }
+
+    fn apply(&self, levels: &BTreeMap<String, LevelFilter>) -> Result<(), LoggingError> {
+        self.filter.reload(targets(levels))?;
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, LevelFilter>> {
+        self.levels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Install the subscriber; call first thing during setup
+pub fn init(app: &AppHandle) -> Result<Logging, Box<dyn std::error::Error>> {
+    let dir = app.path().app_log_dir()?;
+    let appender = tracing_appender::rolling::Builder::new()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(FILE_PREFIX)
+        .filename_suffix(FILE_SUFFIX)
+        .max_log_files(MAX_FILES)
+        .build(&dir)
+        .map_err(LoggingError::from)?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let (filter, handle) = reload::Layer::new(targets(&BTreeMap::new()));
+    let stream = Arc::new(stream::Stream::new(app));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().json().with_writer(writer))
+        .with(fmt::layer().with_writer(std::io::stdout))
+        .with(stream::StreamLayer(stream.clone()))
+        .try_init()
+        .map_err(LoggingError::from)?;
+
+    Ok(Logging {
+        filter: handle,
+        levels: Mutex::default(),
+        stream,
+        _guard: guard,
+    })
+}
+
+/// Apply the levels saved in the settings; call once the database is managed
+pub fn restore_levels(app: &AppHandle) {
+    let saved = app
+        .state::<Database>()
+        .with(|conn| settings::get_value(conn, LEVELS_SETTING));
+    let saved = match saved {
+        Ok(saved) => saved
+            .and_then(|v| serde_json::from_value::<BTreeMap<String, String>>(v).ok())
+            .unwrap_or_default(),
+        Err(e) => {
+            log::error!("Failed to read log levels: {}", e);
+            return;
+        }
+    };
+
+    let logging = app.state::<Logging>();
+    let mut levels = logging.lock();
+    *levels = saved
+        .into_iter()
+        .filter_map(|(target, level)| Some((target, LevelFilter::from_str(&level).ok()?)))
+        .collect();
+    if let Err(e) = logging.apply(&levels) {
+        log::error!("Failed to apply log levels: {}", e);
+    }
+}
+
+/// Set the level for a target, or go back to the default with `None`
+pub fn set_level(app: &AppHandle, target: &str, level: Option<&str>) -> Result<(), LoggingError> {
+    let target = target.trim();
+    let valid = target == DEFAULT_TARGET
+        || (!target.is_empty()
+            && target
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':')));
+    if !valid {
+        return Err(LoggingError::InvalidTarget(target.to_string()));
+    }
+    let level = level
+        .map(|level| {
+            LevelFilter::from_str(level).map_err(|_| LoggingError::UnknownLevel(level.to_string()))
+        })
+        .transpose()?;
+
+    let logging = app.state::<Logging>();
+    let mut levels = logging.lock();
+    match level {
+        Some(level) => levels.insert(target.to_string(), level),
+        None => levels.remove(target),
+    };
+    logging.apply(&levels)?;
+
+    let saved = levels
+        .iter()
+        .map(|(target, level)| (target.clone(), level.to_string().to_lowercase()))
+        .collect::<BTreeMap<_, _>>();
+    app.state::<Database>()
+        .with(|conn| settings::set(conn, LEVELS_SETTING, &saved))?;
+    Ok(())
+}
+
+/// Log files, most recently written first
+pub fn recent_files(app: &AppHandle) -> std::io::Result<Vec<PathBuf>> {
+    let dir = app.path().app_log_dir().map_err(std::io::Error::other)?;
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !name.starts_with(FILE_PREFIX) || !name.ends_with(FILE_SUFFIX) {
+            continue;
+        }
+        if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+            files.push((modified, entry.path()));
+        }
+    }
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+fn targets(levels: &BTreeMap<String, LevelFilter>) -> Targets {
+    let default = levels.get(DEFAULT_TARGET).copied().unwrap_or(DEFAULT_LEVEL);
+    Targets::new().with_default(default).with_targets(
+        levels
+            .iter()
+            .filter(|(target, _)| *target != DEFAULT_TARGET)
+            .map(|(target, level)| (target.clone(), *level)),
+    )
+}
+
+/// Turns an error into the string a command returns, logging it
+pub trait LogErr<T> {
+    fn log_err(self) -> Result<T, String>;
+}
+
+impl<T, E: std::fmt::Display> LogErr<T> for Result<T, E> {
+    #[track_caller]
+    fn log_err(self) -> Result<T, String> {
+        let caller = Location::caller();
+        self.map_err(|e| {
+            let message = e.to_string();
+            tracing::warn!(
+                file = caller.file(),
+                line = caller.line(),
+                "Command failed: {}",
+                message
+            );
+            message
+        })
+    }
+}
@@ -0,0 +1,127 @@
+//! Live log events for the frontend's debug console
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+use tauri::AppHandle;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// Payload of "log-event", sent as arrays of them
+#[derive(Debug, Clone, Serialize)]
+struct LogEvent {
+    timestamp: chrono::DateTime<chrono::Utc>,
+    level: String,
+    target: String,
+    message: String,
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+pub(super) struct Stream {
+    app: AppHandle,
+    enabled: AtomicBool,
+}
+
+impl Stream {
+    pub(super) fn new(app: &AppHandle) -> Self {
+        Self {
+            app: app.clone(),
+            enabled: AtomicBool::new(false),
+        }
+    }
+
+    pub(super) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+pub(super) struct StreamLayer(pub(super) Arc<Stream>);
+
+thread_local! {
+    /// Set while an event is being emitted, so anything logged on the way
+    /// doesn't loop back into the stream
+    static EMITTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f` without streaming what it logs, for code that sends the stream
+pub(crate) fn without_streaming<T>(f: impl FnOnce() -> T) -> T {
+    let was = EMITTING.with(|emitting| emitting.replace(true));
+    let result = f();
+    EMITTING.with(|emitting| emitting.set(was));
+    result
+}
+
+impl<S: Subscriber> Layer<S> for StreamLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if !self.0.enabled.load(Ordering::Relaxed) || EMITTING.with(Cell::get) {
+            return;
+        }
+        without_streaming(|| {
+            let mut visitor = Visitor::default();
+            event.record(&mut visitor);
+            let metadata = event.metadata();
+            crate::coalesce::emit(
+                &self.0.app,
+                "log-event",
+                "",
+                LogEvent {
+                    timestamp: chrono::Utc::now(),
+                    level: metadata.level().to_string().to_lowercase(),
+                    target: metadata.target().to_string(),
+                    message: visitor.message,
+                    fields: visitor.fields,
+                },
+            );
+        });
+    }
+}
+
+#[derive(Default)]
+struct Visitor {
+    message: String,
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Visitor {
+    fn insert(&mut self, field: &Field, value: serde_json::Value) {
+        // Bookkeeping added to events that came from the `log` macros
+        if field.name().starts_with("log.") {
+            return;
+        }
+        self.fields.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for Visitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.insert(field, value.into());
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.insert(field, format!("{:?}", value).into());
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Animated images
+//!
+//! Animated GIF, APNG and WebP avatars and emoji are decoded once, scaled
+//! down and laid out as a grid of frames in a single PNG. The webview plays
+//! them by stepping through the grid with the returned frame delays instead
+//! of running an image decoder per instance on screen. Strips are cached
+//! next to their layout under the app cache directory.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::imageops::{self, FilterType};
+use image::{AnimationDecoder, Frames, ImageDecoder, ImageFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use super::MediaError;
+
+/// Default longest frame edge, in pixels
+pub const DEFAULT_EDGE: u32 = 96;
+/// Largest frame edge a caller may ask for
+pub const MAX_EDGE: u32 = 256;
+/// Frames after this many are dropped
+const MAX_FRAMES: usize = 300;
+/// Widest strip produced; frames wrap onto further rows beyond this
+const MAX_STRIP_WIDTH: u32 = 4096;
+/// Browsers play shorter GIF delays at this speed, so match them
+const DEFAULT_DELAY_MS: u32 = 100;
+
+/// How a strip's frames are laid out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripLayout {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    /// Frames per row; frame `i` is at column `i % columns`, row `i / columns`
+    pub columns: u32,
+    pub frame_count: u32,
+    /// How long each frame is shown
+    pub delays_ms: Vec<u32>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameStrip {
+    #[serde(flatten)]
+    pub layout: StripLayout,
+    pub mime_type: &'static str,
+    /// The grid as a `data:` URL
+    pub data_url: String,
+}
+
+/// Load a cached strip, if there is one
+pub fn cached(cache_dir: &Path, key: &str) -> Result<Option<FrameStrip>, MediaError> {
+    let image = cache_dir.join(format!("{}.png", key));
+    let layout = cache_dir.join(format!("{}.json", key));
+    if !image.exists() || !layout.exists() {
+        return Ok(None);
+    }
+
+    let layout = match serde_json::from_slice(&fs::read(layout)?) {
+        Ok(layout) => layout,
+        // Written by an older version or damaged; regenerate it
+        Err(_) => return Ok(None),
+    };
+    Ok(Some(strip(layout, &fs::read(image)?)))
+}
+
+/// Decode every frame of an animated image, scale the frames to fit `edge`
+/// and store the strip in the cache. Still images become a single frame.
+pub fn generate(
+    cache_dir: &Path,
+    key: &str,
+    bytes: &[u8],
+    edge: u32,
+) -> Result<FrameStrip, MediaError> {
+    let edge = edge.clamp(1, MAX_EDGE);
+
+    let (frames, delays) = match frames(bytes)? {
+        Some(frames) => collect(frames, edge)?,
+        None => {
+            let image = super::decode(bytes)?;
+            (vec![scale(&image.to_rgba8(), edge)], vec![0])
+        }
+    };
+
+    let (frame_width, frame_height) = frames[0].dimensions();
+    let columns = (MAX_STRIP_WIDTH / frame_width).clamp(1, frames.len() as u32);
+    let rows = (frames.len() as u32).div_ceil(columns);
+
+    let mut grid = RgbaImage::new(columns * frame_width, rows * frame_height);
+    for (i, frame) in frames.iter().enumerate() {
+        let i = i as u32;
+        let x = (i % columns) * frame_width;
+        let y = (i / columns) * frame_height;
+        imageops::replace(&mut grid, frame, x as i64, y as i64);
+    }
+
+    let mut encoded = Vec::new();
+    grid.write_to(&mut Cursor::new(&mut encoded), ImageFormat::Png)?;
+
+    let layout = StripLayout {
+        frame_width,
+        frame_height,
+        columns,
+        frame_count: frames.len() as u32,
+        duration_ms: delays.iter().map(|&d| d as u64).sum(),
+        delays_ms: delays,
+    };
+
+    fs::create_dir_all(cache_dir)?;
+    write_atomic(&cache_dir.join(format!("{}.png", key)), &encoded)?;
+    write_atomic(
+        &cache_dir.join(format!("{}.json", key)),
+        &serde_json::to_vec(&layout).expect("layouts always serialize"),
+    )?;
+
+    Ok(strip(layout, &encoded))
+}
+
+/// The frames of an animated image, or `None` if the image isn't animated
+fn frames(bytes: &[u8]) -> Result<Option<Frames<'_>>, MediaError> {
+    let frames = match image::guess_format(bytes)? {
+        ImageFormat::Gif => {
+            let mut decoder = GifDecoder::new(Cursor::new(bytes))?;
+            decoder.set_limits(super::limits())?;
+            decoder.into_frames()
+        }
+        ImageFormat::Png => {
+            let mut decoder = PngDecoder::new(Cursor::new(bytes))?;
+            decoder.set_limits(super::limits())?;
+            if !decoder.is_apng()? {
+                return Ok(None);
+            }
+            decoder.apng()?.into_frames()
+        }
+        ImageFormat::WebP => {
+            let mut decoder = WebPDecoder::new(Cursor::new(bytes))?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.set_limits(super::limits())?;
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(frames))
+}
+
+/// Scale each frame as it's decoded, so only one full-size frame is held
+fn collect(frames: Frames<'_>, edge: u32) -> Result<(Vec<RgbaImage>, Vec<u32>), MediaError> {
+    let mut scaled = Vec::new();
+    let mut delays = Vec::new();
+
+    for frame in frames.take(MAX_FRAMES) {
+        let frame = frame?;
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        let delay = numerator / denominator.max(1);
+        delays.push(if delay <= 10 { DEFAULT_DELAY_MS } else { delay });
+        scaled.push(scale(frame.buffer(), edge));
+    }
+
+    if scaled.is_empty() {
+        return Err(MediaError::NoFrames);
+    }
+    Ok((scaled, delays))
+}
+
+fn scale(frame: &RgbaImage, edge: u32) -> RgbaImage {
+    let (width, height) = frame.dimensions();
+    if width <= edge && height <= edge {
+        return frame.clone();
+    }
+    let ratio = edge as f64 / width.max(height) as f64;
+    let width = ((width as f64 * ratio).round() as u32).max(1);
+    let height = ((height as f64 * ratio).round() as u32).max(1);
+    imageops::resize(frame, width, height, FilterType::Triangle)
+}
+
+fn strip(layout: StripLayout, png: &[u8]) -> FrameStrip {
+    let mime_type = ImageFormat::Png.to_mime_type();
+    FrameStrip {
+        layout,
+        mime_type,
+        data_url: super::data_url(mime_type, png),
+    }
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_extension(format!("{}.tmp", extension));
+    fs::write(&tmp, bytes)?;
+    fs::rename(tmp, path)
+}
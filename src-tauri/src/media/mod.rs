@@ -0,0 +1,61 @@
+//! Native image processing
+//!
+//! Decoding and resizing happen here rather than in the webview so large
+//! photos never have to be decoded at full size just to be shown small, and
+//! animated images don't have to be decoded over and over to play.
+
+pub mod animation;
+pub mod placeholder;
+pub mod thumbnail;
+
+use std::io::Cursor;
+
+use base64::Engine;
+use image::{DynamicImage, ImageDecoder, ImageReader, Limits};
+
+/// Largest width or height accepted from an image
+const MAX_DIMENSION: u32 = 16384;
+/// Upper bound on decoder memory, so a crafted image can't exhaust RAM
+const MAX_ALLOC: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MediaError {
+    #[error("failed to read image: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unsupported or corrupt image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("animation has no frames")]
+    NoFrames,
+    #[error("failed to compute blurhash: {0}")]
+    Blurhash(String),
+}
+
+/// Decode an image, rotating it upright according to its EXIF orientation
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage, MediaError> {
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    reader.limits(limits());
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    Ok(image)
+}
+
+fn limits() -> Limits {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_ALLOC);
+    limits
+}
+
+/// Encoded image as a `data:` URL, ready for an `<img>` tag
+fn data_url(mime_type: &str, bytes: &[u8]) -> String {
+    format!(
+        "data:{};base64,{}",
+        mime_type,
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    )
+}
@@ -0,0 +1,31 @@
+//! Blurhash placeholders
+//!
+//! A blurhash is a short string recipients can render as a blurred preview
+//! while the real image loads.
+
+use image::DynamicImage;
+
+use super::MediaError;
+
+/// Images are shrunk to about this size first; blurhash only keeps a few
+/// components, so encoding the full image gains nothing
+const SAMPLE_EDGE: u32 = 64;
+
+/// Blurhash of an image, with more components along its longer side
+pub fn blurhash(image: &DynamicImage) -> Result<String, MediaError> {
+    let sample = image.thumbnail(SAMPLE_EDGE, SAMPLE_EDGE).to_rgba8();
+    let (components_x, components_y) = if sample.width() >= sample.height() {
+        (4, 3)
+    } else {
+        (3, 4)
+    };
+
+    blurhash::encode(
+        components_x,
+        components_y,
+        sample.width(),
+        sample.height(),
+        sample.as_raw(),
+    )
+    .map_err(|e| MediaError::Blurhash(e.to_string()))
+}
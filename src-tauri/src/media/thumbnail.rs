@@ -0,0 +1,196 @@
+//! Thumbnails and previews
+//!
+//! Generated images are cached on disk under the app cache directory, keyed
+//! by their source and size, so scrolling back through a media grid doesn't
+//! decode anything twice.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::MediaError;
+
+const JPEG_QUALITY: u8 = 82;
+
+/// Where the source image comes from
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ImageSource {
+    /// A file on this machine, e.g. an attachment about to be sent
+    File { path: PathBuf },
+    /// An attachment on a server
+    Attachment {
+        instance_id: String,
+        attachment_id: String,
+    },
+}
+
+/// Output size, as the longest edge in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThumbnailSize {
+    /// Media grid tiles
+    #[default]
+    Thumbnail,
+    /// Lightbox and attachment previews
+    Preview,
+}
+
+impl ThumbnailSize {
+    fn edge(self) -> u32 {
+        match self {
+            ThumbnailSize::Thumbnail => 320,
+            ThumbnailSize::Preview => 1280,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThumbnailSize::Thumbnail => "thumbnail",
+            ThumbnailSize::Preview => "preview",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub mime_type: &'static str,
+    /// Image as a `data:` URL, ready for an `<img>` tag
+    pub data_url: String,
+}
+
+/// Cache file name for a source; `version` changes whenever the source does
+/// and `variant` tells apart the outputs made from one source
+pub fn cache_key(source: &ImageSource, version: &str, variant: &str) -> String {
+    let mut hasher = Sha256::new();
+    match source {
+        ImageSource::File { path } => {
+            hasher.update(b"file\0");
+            hasher.update(path.to_string_lossy().as_bytes());
+        }
+        ImageSource::Attachment {
+            instance_id,
+            attachment_id,
+        } => {
+            hasher.update(b"attachment\0");
+            hasher.update(instance_id.as_bytes());
+            hasher.update([0]);
+            hasher.update(attachment_id.as_bytes());
+        }
+    }
+    hasher.update([0]);
+    hasher.update(version.as_bytes());
+    hasher.update([0]);
+    hasher.update(variant.as_bytes());
+
+    hasher.finalize()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Version of a local file for [`cache_key`], from its modification time
+/// and size so edits produce a new output
+pub fn file_version(metadata: &fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis());
+    format!("{}:{}", modified, metadata.len())
+}
+
+/// Load a cached thumbnail, if there is one
+pub fn cached(cache_dir: &Path, key: &str) -> Result<Option<Thumbnail>, MediaError> {
+    for (extension, format) in [("jpg", ImageFormat::Jpeg), ("png", ImageFormat::Png)] {
+        let path = cache_dir.join(format!("{}.{}", key, extension));
+        if path.exists() {
+            let (width, height) = image::image_dimensions(&path)?;
+            return Ok(Some(thumbnail(fs::read(path)?, format, width, height)));
+        }
+    }
+    Ok(None)
+}
+
+/// Delete a cached thumbnail. Returns false if there wasn't one.
+pub fn remove_cached(cache_dir: &Path, key: &str) -> std::io::Result<bool> {
+    let mut removed = false;
+    for extension in ["jpg", "png"] {
+        match fs::remove_file(cache_dir.join(format!("{}.{}", key, extension))) {
+            Ok(()) => removed = true,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(removed)
+}
+
+/// Scale an image down to `size` and store the result in the cache
+pub fn generate(
+    cache_dir: &Path,
+    key: &str,
+    bytes: &[u8],
+    size: ThumbnailSize,
+) -> Result<Thumbnail, MediaError> {
+    generate_from(cache_dir, key, super::decode(bytes)?, size)
+}
+
+/// Like [`generate`], for an image that's already decoded
+pub fn generate_from(
+    cache_dir: &Path,
+    key: &str,
+    image: DynamicImage,
+    size: ThumbnailSize,
+) -> Result<Thumbnail, MediaError> {
+    let edge = size.edge();
+    let image = if image.width() > edge || image.height() > edge {
+        image.thumbnail(edge, edge)
+    } else {
+        image
+    };
+
+    // Keep transparency where the source has it, otherwise JPEG is far smaller
+    let (format, extension) = if image.color().has_alpha() {
+        (ImageFormat::Png, "png")
+    } else {
+        (ImageFormat::Jpeg, "jpg")
+    };
+    let encoded = encode(&image, format)?;
+
+    fs::create_dir_all(cache_dir)?;
+    let path = cache_dir.join(format!("{}.{}", key, extension));
+    let tmp = path.with_extension(format!("{}.tmp", extension));
+    fs::write(&tmp, &encoded)?;
+    fs::rename(tmp, path)?;
+
+    Ok(thumbnail(encoded, format, image.width(), image.height()))
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, MediaError> {
+    let mut encoded = Vec::new();
+    match format {
+        ImageFormat::Jpeg => {
+            JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY)
+                .encode_image(&image.to_rgb8())?;
+        }
+        _ => image.write_to(&mut Cursor::new(&mut encoded), format)?,
+    }
+    Ok(encoded)
+}
+
+fn thumbnail(bytes: Vec<u8>, format: ImageFormat, width: u32, height: u32) -> Thumbnail {
+    let mime_type = format.to_mime_type();
+    Thumbnail {
+        width,
+        height,
+        mime_type,
+        data_url: super::data_url(mime_type, &bytes),
+    }
+}
@@ -0,0 +1,248 @@
+//! MPRIS player for the call
+//!
+//! The `org.mpris.MediaPlayer2.redoubt` name is only held during a call,
+//! so media widgets don't show an idle player the rest of the time. The
+//! player lives on the app's session bus connection (see
+//! [`crate::dbus`]).
+
+use std::collections::HashMap;
+
+use tauri::{AppHandle, Manager};
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use zbus::{fdo, interface, Connection};
+
+use super::{Button, Display};
+use crate::dbus::SessionBus;
+use crate::deep_link;
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.redoubt";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+/// The one track there is
+const TRACK_ID: &str = "/org/redoubt/Desktop/call";
+
+pub(super) fn update(app: &AppHandle, display: Option<Display>) {
+    let Some(connection) = app.state::<SessionBus>().connection().cloned() else {
+        log::debug!("No session bus for the media session");
+        return;
+    };
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = publish(&app, &connection, display).await {
+            log::warn!("Failed to update the MPRIS player: {}", e);
+        }
+    });
+}
+
+async fn publish(
+    app: &AppHandle,
+    connection: &Connection,
+    display: Option<Display>,
+) -> zbus::Result<()> {
+    let server = connection.object_server();
+    let Some(display) = display else {
+        let _ = connection.release_name(BUS_NAME).await;
+        server.remove::<Player, _>(OBJECT_PATH).await?;
+        server.remove::<MediaPlayer, _>(OBJECT_PATH).await?;
+        return Ok(());
+    };
+
+    match server.interface::<_, Player>(OBJECT_PATH).await {
+        Ok(player) => {
+            let mut iface = player.get_mut().await;
+            iface.display = display;
+            iface
+                .playback_status_changed(player.signal_emitter())
+                .await?;
+            iface.metadata_changed(player.signal_emitter()).await?;
+        }
+        Err(_) => {
+            server
+                .at(OBJECT_PATH, MediaPlayer { app: app.clone() })
+                .await?;
+            server
+                .at(
+                    OBJECT_PATH,
+                    Player {
+                        app: app.clone(),
+                        display,
+                    },
+                )
+                .await?;
+            connection.request_name(BUS_NAME).await?;
+        }
+    }
+    Ok(())
+}
+
+/// `org.mpris.MediaPlayer2`
+struct MediaPlayer {
+    app: AppHandle,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl MediaPlayer {
+    fn raise(&self) {
+        deep_link::focus_main_window(&self.app);
+    }
+
+    fn quit(&self) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(
+            "leave the call instead".to_string(),
+        ))
+    }
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> &str {
+        "Redoubt"
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// `org.mpris.MediaPlayer2.Player`, playing while unmuted
+struct Player {
+    app: AppHandle,
+    display: Display,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn play(&self) {
+        super::pressed(&self.app, Button::Play);
+    }
+
+    fn pause(&self) {
+        super::pressed(&self.app, Button::Pause);
+    }
+
+    fn play_pause(&self) {
+        super::pressed(&self.app, Button::PlayPause);
+    }
+
+    fn stop(&self) {
+        super::pressed(&self.app, Button::Pause);
+    }
+
+    fn next(&self) {}
+
+    fn previous(&self) {}
+
+    fn seek(&self, _offset: i64) {}
+
+    fn set_position(&self, _track_id: ObjectPath<'_>, _position: i64) {}
+
+    fn open_uri(&self, _uri: String) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(
+            "calls don't open URIs".to_string(),
+        ))
+    }
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &str {
+        if self.display.playing {
+            "Playing"
+        } else {
+            "Paused"
+        }
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let mut metadata = HashMap::new();
+        let values = [
+            (
+                "mpris:trackid",
+                Value::from(ObjectPath::from_static_str_unchecked(TRACK_ID)),
+            ),
+            ("xesam:title", Value::from(self.display.title.clone())),
+            (
+                "xesam:artist",
+                Value::from(vec![self.display.artist.clone()]),
+            ),
+        ];
+        for (key, value) in values {
+            if let Ok(value) = OwnedValue::try_from(value) {
+                metadata.insert(key.to_string(), value);
+            }
+        }
+        metadata
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn position(&self) -> i64 {
+        0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
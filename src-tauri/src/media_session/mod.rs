@@ -0,0 +1,124 @@
+//! Call media session
+//!
+//! During a call the OS media controls show it like something playing,
+//! with the channel as the title and the participants as the artist: the
+//! system media transport controls on Windows, and an MPRIS player on
+//! Linux that desktop media widgets and headset buttons talk to. Play and
+//! pause unmute and mute, through the same [`crate::actions`] as the
+//! shortcuts. macOS only shows apps that play media themselves, so nothing
+//! is published there.
+//!
+//! The frontend reports the session as the call, its participants or its
+//! mute state change, and clears it when the call is over;
+//! [`crate::calls::end`] clears it as well. In streamer mode only the fact
+//! that there's a call is shown.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::actions::{self, Action};
+use crate::streamer_mode::StreamerMode;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallSession {
+    pub channel_name: String,
+    pub participants: Vec<String>,
+    pub muted: bool,
+}
+
+/// What the OS is shown for a session
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq)]
+struct Display {
+    title: String,
+    artist: String,
+    playing: bool,
+}
+
+/// Media buttons the OS passes on
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+#[derive(Debug, Clone, Copy)]
+enum Button {
+    Play,
+    Pause,
+    #[cfg_attr(windows, allow(dead_code))]
+    PlayPause,
+}
+
+#[derive(Default)]
+pub struct MediaSession {
+    current: Mutex<Option<CallSession>>,
+    #[cfg(windows)]
+    controls: windows::Controls,
+}
+
+impl MediaSession {
+    pub fn current(&self) -> Option<CallSession> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<CallSession>> {
+        self.current.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Publish `session`, or remove it with `None`
+pub fn set(app: &AppHandle, session: Option<CallSession>) {
+    let changed = {
+        let media = app.state::<MediaSession>();
+        let mut current = media.lock();
+        let changed = *current != session;
+        *current = session.clone();
+        changed
+    };
+    if !changed {
+        return;
+    }
+
+    let display = session.map(|session| display(app, &session));
+    #[cfg(target_os = "linux")]
+    linux::update(app, display);
+    #[cfg(windows)]
+    windows::update(app, display);
+    #[cfg(not(any(windows, target_os = "linux")))]
+    let _ = display;
+}
+
+fn display(app: &AppHandle, session: &CallSession) -> Display {
+    let playing = !session.muted;
+    if app.state::<StreamerMode>().is_enabled() {
+        return Display {
+            title: "Call".to_string(),
+            artist: "Redoubt".to_string(),
+            playing,
+        };
+    }
+    Display {
+        title: session.channel_name.clone(),
+        artist: session.participants.join(", "),
+        playing,
+    }
+}
+
+/// Handle a media button by muting or unmuting
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+fn pressed(app: &AppHandle, button: Button) {
+    let Some(session) = app.state::<MediaSession>().current() else {
+        return;
+    };
+    let toggle = match button {
+        Button::Play => session.muted,
+        Button::Pause => !session.muted,
+        Button::PlayPause => true,
+    };
+    if toggle {
+        actions::dispatch(app, Action::ToggleMute);
+    }
+}
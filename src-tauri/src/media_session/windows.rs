@@ -0,0 +1,113 @@
+//! System media transport controls for the call
+//!
+//! The controls belong to the main window. They're made on the main
+//! thread the first time a call is published and kept from then on,
+//! turned off between calls.
+
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Manager};
+use windows::core::{factory, Ref, HSTRING};
+use windows::Foundation::TypedEventHandler;
+use windows::Media::{
+    MediaPlaybackStatus, MediaPlaybackType, SystemMediaTransportControls,
+    SystemMediaTransportControlsButton, SystemMediaTransportControlsButtonPressedEventArgs,
+};
+use windows::Win32::Foundation::HWND;
+use windows::Win32::System::WinRT::ISystemMediaTransportControlsInterop;
+
+use super::{Button, Display, MediaSession};
+
+#[derive(Default)]
+pub(super) struct Controls {
+    controls: Mutex<Option<SystemMediaTransportControls>>,
+}
+
+pub(super) fn update(app: &AppHandle, display: Option<Display>) {
+    let handle = app.clone();
+    let result = app.run_on_main_thread(move || {
+        if let Err(e) = apply(&handle, display) {
+            log::warn!("Failed to update the media controls: {}", e);
+        }
+    });
+    if let Err(e) = result {
+        log::warn!("Failed to update the media controls: {}", e);
+    }
+}
+
+fn apply(app: &AppHandle, display: Option<Display>) -> windows::core::Result<()> {
+    let media = app.state::<MediaSession>();
+    let mut controls = media
+        .controls
+        .controls
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+
+    let Some(display) = display else {
+        if let Some(controls) = controls.as_ref() {
+            controls.DisplayUpdater()?.ClearAll()?;
+            controls.SetIsEnabled(false)?;
+        }
+        return Ok(());
+    };
+    if controls.is_none() {
+        let Some(created) = create(app)? else {
+            return Ok(());
+        };
+        *controls = Some(created);
+    }
+    let Some(controls) = controls.as_ref() else {
+        return Ok(());
+    };
+
+    controls.SetIsEnabled(true)?;
+    controls.SetPlaybackStatus(if display.playing {
+        MediaPlaybackStatus::Playing
+    } else {
+        MediaPlaybackStatus::Paused
+    })?;
+    let updater = controls.DisplayUpdater()?;
+    updater.SetType(MediaPlaybackType::Music)?;
+    let properties = updater.MusicProperties()?;
+    properties.SetTitle(&HSTRING::from(display.title))?;
+    properties.SetArtist(&HSTRING::from(display.artist))?;
+    updater.Update()
+}
+
+/// The main window's controls, or `None` without a main window
+fn create(app: &AppHandle) -> windows::core::Result<Option<SystemMediaTransportControls>> {
+    let Some(window) = app.get_webview_window("main") else {
+        return Ok(None);
+    };
+    let hwnd = match window.hwnd() {
+        Ok(hwnd) => HWND(hwnd.0),
+        Err(e) => {
+            log::warn!("No window for the media controls: {}", e);
+            return Ok(None);
+        }
+    };
+
+    let interop = factory::<SystemMediaTransportControls, ISystemMediaTransportControlsInterop>()?;
+    // SAFETY: the window handle belongs to the live main window
+    let controls: SystemMediaTransportControls = unsafe { interop.GetForWindow(hwnd)? };
+    controls.SetIsPlayEnabled(true)?;
+    controls.SetIsPauseEnabled(true)?;
+
+    let handle = app.clone();
+    controls.ButtonPressed(&TypedEventHandler::new(
+        move |_, args: Ref<'_, SystemMediaTransportControlsButtonPressedEventArgs>| {
+            let Some(args) = args.as_ref() else {
+                return Ok(());
+            };
+            let button = match args.Button()? {
+                SystemMediaTransportControlsButton::Play => Button::Play,
+                SystemMediaTransportControlsButton::Pause => Button::Pause,
+                SystemMediaTransportControlsButton::Stop => Button::Pause,
+                _ => return Ok(()),
+            };
+            super::pressed(&handle, button);
+            Ok(())
+        },
+    ))?;
+    Ok(Some(controls))
+}
@@ -0,0 +1,82 @@
+//! GPU time from DRM fdinfo
+//!
+//! GPU drivers (amdgpu, i915, xe, nouveau, panfrost and others) report the
+//! time each client has kept each engine busy in `/proc/<pid>/fdinfo`.
+//! Several file descriptors can share a client, so clients are counted
+//! once however many are open.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// An engine as used by one client: device, client id, engine name
+type EngineKey = (String, u64, String);
+/// Nanoseconds each engine has been busy, and the process using it
+type Engines = HashMap<EngineKey, (u32, u64)>;
+
+#[derive(Default)]
+pub(super) struct GpuSampler {
+    last: Option<(Instant, Engines)>,
+}
+
+impl GpuSampler {
+    /// Busiest engine's utilization per process since the last sample, in
+    /// percent
+    pub(super) fn sample(&mut self, pids: &[u32]) -> HashMap<u32, f32> {
+        let now = Instant::now();
+        let mut engines = HashMap::new();
+        for &pid in pids {
+            read_process(pid, &mut engines);
+        }
+
+        let mut usage = HashMap::new();
+        if let Some((then, last)) = &self.last {
+            let elapsed = now.duration_since(*then).as_nanos() as f64;
+            for (key, (pid, busy)) in &engines {
+                let Some((_, before)) = last.get(key) else {
+                    continue;
+                };
+                let percent = (busy.saturating_sub(*before) as f64 / elapsed * 100.0) as f32;
+                let entry = usage.entry(*pid).or_insert(0.0_f32);
+                *entry = entry.max(percent.min(100.0));
+            }
+        }
+        self.last = Some((now, engines));
+        usage
+    }
+}
+
+fn read_process(pid: u32, engines: &mut Engines) {
+    let Ok(entries) = std::fs::read_dir(format!("/proc/{}/fdinfo", pid)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(info) = std::fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        let mut device = String::new();
+        let mut client = None;
+        let mut busy = Vec::new();
+        for line in info.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if key == "drm-pdev" {
+                device = value.to_string();
+            } else if key == "drm-client-id" {
+                client = value.parse::<u64>().ok();
+            } else if let Some(engine) = key.strip_prefix("drm-engine-") {
+                // Capacity lines describe the engine rather than its use
+                if let Some(ns) = value.strip_suffix(" ns").and_then(|ns| ns.parse().ok()) {
+                    busy.push((engine.to_string(), ns));
+                }
+            }
+        }
+        let Some(client) = client else {
+            continue;
+        };
+        for (engine, ns) in busy {
+            engines.insert((device.clone(), client, engine), (pid, ns));
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Resource usage
+//!
+//! Every few seconds the app's processes are sampled: the main process,
+//! the webview's processes and any other helpers it started, such as the
+//! crash monitor. CPU and memory come from the OS's process list, GPU
+//! utilization from DRM fdinfo on Linux and the GPU performance counters on
+//! Windows; macOS has no per-process GPU figures, and its webview runs in
+//! XPC services that aren't the app's children, so only the main process
+//! is sampled there. The load of the audio capture callbacks (see
+//! [`crate::audio::load`]) and the machine's overall CPU are sampled
+//! alongside, the latter for picking a performance tier (see
+//! [`crate::performance`]).
+//!
+//! The latest sample backs the "Performance" panel in the settings.
+//! "resource-warning" is emitted when usage stays over a threshold and
+//! again only after it has dropped back under it.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::audio;
+use crate::performance;
+
+#[cfg(target_os = "linux")]
+use linux::GpuSampler;
+#[cfg(windows)]
+use windows::GpuSampler;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Samples in a row over a threshold before warning
+const SUSTAINED_SAMPLES: u32 = 3;
+
+/// Total CPU, where 100 is one core fully in use
+const CPU_THRESHOLD: f32 = 80.0;
+const MEMORY_THRESHOLD: f32 = 2.0 * 1024.0 * 1024.0 * 1024.0;
+const GPU_THRESHOLD: f32 = 90.0;
+/// Capture callbacks using this share of their time budget risk dropouts
+const AUDIO_LOAD_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessKind {
+    Main,
+    Webview,
+    Helper,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    pub name: String,
+    pub kind: ProcessKind,
+    /// Where 100 is one core fully in use
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+    /// `None` where the OS doesn't report it
+    pub gpu_percent: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceUsage {
+    pub sampled_at: DateTime<Utc>,
+    pub processes: Vec<ProcessUsage>,
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+    pub gpu_percent: Option<f32>,
+    /// The whole machine's CPU, everything running, from 0 to 100
+    pub system_cpu_percent: f32,
+    /// Share of the time audio capture callbacks spent working; `None`
+    /// while nothing is being captured
+    pub audio_load: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    Cpu,
+    Memory,
+    Gpu,
+    AudioLoad,
+}
+
+/// Payload of "resource-warning"
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceWarning {
+    pub resource: Resource,
+    pub value: f32,
+    pub threshold: f32,
+}
+
+#[derive(Default)]
+pub struct ResourceMonitor {
+    latest: Mutex<Option<ResourceUsage>>,
+}
+
+impl ResourceMonitor {
+    /// The latest sample; `None` until the first one is taken
+    pub fn latest(&self) -> Option<ResourceUsage> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<ResourceUsage>> {
+        self.latest.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Sample resource usage for as long as the app runs
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("resource-monitor".to_string())
+        .spawn(move || {
+            let mut sampler = Sampler::default();
+            loop {
+                let usage = sampler.sample();
+                for warning in sampler.warnings(&usage) {
+                    log::warn!(
+                        "{:?} usage at {} is over {}",
+                        warning.resource,
+                        warning.value,
+                        warning.threshold
+                    );
+                    let _ = app.emit("resource-warning", warning);
+                }
+                performance::on_sample(&app, &usage);
+                *app.state::<ResourceMonitor>().lock() = Some(usage);
+                std::thread::sleep(SAMPLE_INTERVAL);
+            }
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to start resource monitor: {}", e);
+    }
+}
+
+#[derive(Default)]
+struct Sampler {
+    system: System,
+    #[cfg(any(windows, target_os = "linux"))]
+    gpu: GpuSampler,
+    /// Samples in a row each resource has been over its threshold
+    over: HashMap<Resource, u32>,
+}
+
+impl Sampler {
+    fn sample(&mut self) -> ResourceUsage {
+        self.system.refresh_cpu_usage();
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::nothing().with_cpu().with_memory(),
+        );
+
+        let main = Pid::from_u32(std::process::id());
+        let pids = self.descendants(main);
+        #[cfg(any(windows, target_os = "linux"))]
+        let gpu = Some(
+            self.gpu
+                .sample(&pids.iter().map(|pid| pid.as_u32()).collect::<Vec<_>>()),
+        );
+        #[cfg(not(any(windows, target_os = "linux")))]
+        let gpu: Option<HashMap<u32, f32>> = None;
+
+        let mut processes = Vec::new();
+        for pid in pids {
+            let Some(process) = self.system.process(pid) else {
+                continue;
+            };
+            let name = process.name().to_string_lossy().into_owned();
+            let kind = if pid == main {
+                ProcessKind::Main
+            } else if is_webview(&name) {
+                ProcessKind::Webview
+            } else {
+                ProcessKind::Helper
+            };
+            processes.push(ProcessUsage {
+                pid: pid.as_u32(),
+                name,
+                kind,
+                cpu_percent: process.cpu_usage(),
+                memory_bytes: process.memory(),
+                gpu_percent: gpu
+                    .as_ref()
+                    .map(|gpu| gpu.get(&pid.as_u32()).copied().unwrap_or(0.0)),
+            });
+        }
+
+        ResourceUsage {
+            sampled_at: Utc::now(),
+            cpu_percent: processes.iter().map(|p| p.cpu_percent).sum(),
+            memory_bytes: processes.iter().map(|p| p.memory_bytes).sum(),
+            gpu_percent: gpu.map(|_| processes.iter().filter_map(|p| p.gpu_percent).sum()),
+            system_cpu_percent: self.system.global_cpu_usage(),
+            audio_load: audio::load::take(),
+            processes,
+        }
+    }
+
+    /// A process and everything it started, directly or not
+    fn descendants(&self, root: Pid) -> Vec<Pid> {
+        let mut children: HashMap<Pid, Vec<Pid>> = HashMap::new();
+        for (pid, process) in self.system.processes() {
+            if let Some(parent) = process.parent() {
+                children.entry(parent).or_default().push(*pid);
+            }
+        }
+
+        let mut found = vec![root];
+        let mut seen = HashSet::from([root]);
+        let mut i = 0;
+        while i < found.len() {
+            for child in children.get(&found[i]).into_iter().flatten() {
+                if seen.insert(*child) {
+                    found.push(*child);
+                }
+            }
+            i += 1;
+        }
+        found
+    }
+
+    /// Warnings for resources that have just stayed over their threshold
+    /// long enough
+    fn warnings(&mut self, usage: &ResourceUsage) -> Vec<ResourceWarning> {
+        let checks = [
+            (Resource::Cpu, Some(usage.cpu_percent), CPU_THRESHOLD),
+            (
+                Resource::Memory,
+                Some(usage.memory_bytes as f32),
+                MEMORY_THRESHOLD,
+            ),
+            (Resource::Gpu, usage.gpu_percent, GPU_THRESHOLD),
+            (Resource::AudioLoad, usage.audio_load, AUDIO_LOAD_THRESHOLD),
+        ];
+
+        let mut warnings = Vec::new();
+        for (resource, value, threshold) in checks {
+            let Some(value) = value.filter(|value| *value > threshold) else {
+                self.over.remove(&resource);
+                continue;
+            };
+            let over = self.over.entry(resource).or_default();
+            *over += 1;
+            if *over == SUSTAINED_SAMPLES {
+                warnings.push(ResourceWarning {
+                    resource,
+                    value,
+                    threshold,
+                });
+            }
+        }
+        warnings
+    }
+}
+
+fn is_webview(name: &str) -> bool {
+    name.starts_with("WebKit") || name.starts_with("msedgewebview2")
+}
@@ -0,0 +1,136 @@
+//! GPU utilization from the "GPU Engine" performance counters
+//!
+//! Counter instances are named `pid_<pid>_luid_..._engtype_<type>`, one per
+//! engine a process has used. As in Task Manager, a process's usage is that
+//! of its busiest engine type.
+
+use std::collections::HashMap;
+
+use windows_sys::w;
+use windows_sys::Win32::System::Performance::{
+    PdhAddEnglishCounterW, PdhCloseQuery, PdhCollectQueryData, PdhGetFormattedCounterArrayW,
+    PdhOpenQueryW, PDH_CSTATUS_VALID_DATA, PDH_FMT_COUNTERVALUE_ITEM_W, PDH_FMT_DOUBLE,
+    PDH_HCOUNTER, PDH_HQUERY, PDH_MORE_DATA,
+};
+
+const ERROR_SUCCESS: u32 = 0;
+
+pub(super) struct GpuSampler {
+    query: PDH_HQUERY,
+    counter: PDH_HCOUNTER,
+}
+
+impl Default for GpuSampler {
+    fn default() -> Self {
+        let mut query = std::ptr::null_mut();
+        let mut counter = std::ptr::null_mut();
+        // SAFETY: the out pointers are valid and the path is NUL-terminated;
+        // a failed open leaves the handles null, which `sample` checks
+        unsafe {
+            if PdhOpenQueryW(std::ptr::null(), 0, &mut query) != ERROR_SUCCESS {
+                log::warn!("GPU performance counters are unavailable");
+                return Self {
+                    query: std::ptr::null_mut(),
+                    counter,
+                };
+            }
+            if PdhAddEnglishCounterW(
+                query,
+                w!("\\GPU Engine(*)\\Utilization Percentage"),
+                0,
+                &mut counter,
+            ) != ERROR_SUCCESS
+            {
+                log::warn!("GPU performance counters are unavailable");
+                counter = std::ptr::null_mut();
+            }
+            // Utilization is a rate, so it needs a first collection to
+            // compare the next one with
+            PdhCollectQueryData(query);
+        }
+        Self { query, counter }
+    }
+}
+
+impl Drop for GpuSampler {
+    fn drop(&mut self) {
+        if !self.query.is_null() {
+            // SAFETY: the query was opened by `default` and is closed once
+            unsafe { PdhCloseQuery(self.query) };
+        }
+    }
+}
+
+impl GpuSampler {
+    /// Busiest engine type's utilization per process since the last sample,
+    /// in percent
+    pub(super) fn sample(&mut self, pids: &[u32]) -> HashMap<u32, f32> {
+        let mut usage = HashMap::new();
+        if self.counter.is_null() {
+            return usage;
+        }
+
+        let mut by_engine: HashMap<(u32, String), f64> = HashMap::new();
+        // SAFETY: the buffer is sized as PDH asks for and aligned for the
+        // items; names point into the same buffer and are read before it's
+        // dropped
+        unsafe {
+            if PdhCollectQueryData(self.query) != ERROR_SUCCESS {
+                return usage;
+            }
+            let mut size = 0;
+            let mut count = 0;
+            let result = PdhGetFormattedCounterArrayW(
+                self.counter,
+                PDH_FMT_DOUBLE,
+                &mut size,
+                &mut count,
+                std::ptr::null_mut(),
+            );
+            if result != PDH_MORE_DATA {
+                return usage;
+            }
+            let item_size = std::mem::size_of::<PDH_FMT_COUNTERVALUE_ITEM_W>();
+            let mut buffer =
+                vec![PDH_FMT_COUNTERVALUE_ITEM_W::default(); (size as usize).div_ceil(item_size)];
+            let result = PdhGetFormattedCounterArrayW(
+                self.counter,
+                PDH_FMT_DOUBLE,
+                &mut size,
+                &mut count,
+                buffer.as_mut_ptr(),
+            );
+            if result != ERROR_SUCCESS {
+                return usage;
+            }
+
+            for item in &buffer[..count as usize] {
+                if item.FmtValue.CStatus != PDH_CSTATUS_VALID_DATA || item.szName.is_null() {
+                    continue;
+                }
+                let len = (0..).take_while(|&i| *item.szName.add(i) != 0).count();
+                let name = String::from_utf16_lossy(std::slice::from_raw_parts(item.szName, len));
+                let Some((pid, engine)) = parse_instance(&name) else {
+                    continue;
+                };
+                if pids.contains(&pid) {
+                    *by_engine.entry((pid, engine.to_string())).or_default() +=
+                        item.FmtValue.Anonymous.doubleValue;
+                }
+            }
+        }
+
+        for ((pid, _), percent) in by_engine {
+            let entry = usage.entry(pid).or_insert(0.0_f32);
+            *entry = entry.max((percent as f32).min(100.0));
+        }
+        usage
+    }
+}
+
+/// The process id and engine type in a counter instance name
+fn parse_instance(name: &str) -> Option<(u32, &str)> {
+    let pid = name.strip_prefix("pid_")?.split('_').next()?.parse().ok()?;
+    let (_, engine) = name.rsplit_once("engtype_")?;
+    Some((pid, engine))
+}
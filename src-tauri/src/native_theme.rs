@@ -0,0 +1,608 @@
+//! Theme packs for the native surfaces
+//!
+//! The webview themes itself, but the tray icon, the ringtone and the
+//! overlay's colors come from the backend. A theme pack replaces them: a
+//! directory under `<app data>/themes/<pack id>/` holding a `theme.json`
+//! manifest and the files it names, e.g.
+//!
+//! ```json
+//! {
+//!   "name": "Night Watch",
+//!   "tray": { "default": "tray.png", "muted": "tray-muted.png" },
+//!   "overlay": { "background": "#101418e0", "accent": "#5fb3ff" },
+//!   "sounds": { "ringtone": "ring.wav", "message": "ping.wav" }
+//! }
+//! ```
+//!
+//! Everything is optional; what a pack leaves out stays built in. Packs are
+//! validated as a whole when loaded, and one that fails isn't applied at
+//! all: icons have to decode and fit [`MAX_ICON_DIMENSION`], colors are
+//! hex, and sounds are PCM WAV, the one format read here without a
+//! decoder. The ringtone plays natively; the other sounds are handed to
+//! the frontend with the overlay colors in the "native-theme" payload.
+//!
+//! The applied pack is watched, so editing its files shows up within
+//! [`WATCH_INTERVAL`]. An edit that breaks it keeps the last good version
+//! and emits "native-theme-error".
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tauri::image::Image;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::audio::playback::Source;
+use crate::calls::{CallState, Calls};
+use crate::db::Database;
+use crate::launcher::unread;
+use crate::launcher::{Launcher, TRAY_ID};
+use crate::{dnd, media, settings};
+
+/// Setting holding the applied pack's id, if any; only [`apply`] writes it
+pub const THEME_SETTING: &str = "appearance.native_theme";
+
+/// How often the applied pack is checked for changes
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+pub const MAX_ICON_DIMENSION: u32 = 256;
+const MAX_ICON_BYTES: u64 = 1024 * 1024;
+const MAX_SOUND_BYTES: u64 = 5 * 1024 * 1024;
+const MAX_SOUND_SECS: f32 = 30.0;
+const MANIFEST: &str = "theme.json";
+
+/// Sounds the frontend plays, alongside the native ringtone
+const WEBVIEW_SOUNDS: &[&str] = &["message", "mention", "call_join", "call_leave"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ThemeError {
+    #[error("a theme pack id may only contain letters, digits, '-' and '_'")]
+    InvalidId,
+    #[error("theme pack {0} isn't installed")]
+    NotFound(String),
+    #[error("no app data directory")]
+    NoDir,
+    #[error("theme.json is malformed: {0}")]
+    Manifest(#[from] serde_json::Error),
+    #[error("{0} must be a file inside the pack")]
+    OutsidePack(String),
+    #[error("{0}: {1}")]
+    File(String, std::io::Error),
+    #[error("{0} is larger than {1} bytes")]
+    TooLarge(String, u64),
+    #[error("{0} isn't a usable icon: {1}")]
+    Icon(String, media::MediaError),
+    #[error("{0} is {1}x{2}; icons can be at most {MAX_ICON_DIMENSION}x{MAX_ICON_DIMENSION}")]
+    IconSize(String, u32, u32),
+    #[error("{0} isn't a hex color like #5fb3ff or #101418e0")]
+    Color(String),
+    #[error("{0} isn't a PCM WAV file: {1}")]
+    Sound(String, &'static str),
+    #[error("{0} is longer than {MAX_SOUND_SECS} seconds")]
+    SoundLength(String),
+    #[error("unknown sound {0}")]
+    UnknownSound(String),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// What the tray icon shows, most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrayState {
+    Ringing,
+    DoNotDisturb,
+    Muted,
+    Unread,
+    Default,
+}
+
+/// Overlay colors; unset ones keep the frontend's own
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OverlayColors {
+    pub background: Option<String>,
+    pub foreground: Option<String>,
+    pub accent: Option<String>,
+    pub speaking: Option<String>,
+    pub muted: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    name: String,
+    #[serde(default)]
+    tray: BTreeMap<TrayState, String>,
+    #[serde(default)]
+    overlay: OverlayColors,
+    #[serde(default)]
+    sounds: BTreeMap<String, String>,
+}
+
+/// An installed pack, for `list_native_themes`
+#[derive(Debug, Clone, Serialize)]
+pub struct ThemeSummary {
+    pub id: String,
+    pub name: Option<String>,
+    /// Why it can't be applied, if it can't
+    pub error: Option<String>,
+}
+
+/// The applied pack as the frontend needs it, and the payload of
+/// "native-theme"; `pack_id` is `None` for the built-in look
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ThemePayload {
+    pub pack_id: Option<String>,
+    pub name: Option<String>,
+    pub overlay: OverlayColors,
+    /// Sound name to a `data:audio/wav` URL
+    pub sounds: BTreeMap<String, String>,
+}
+
+/// Payload of "native-theme-error"
+#[derive(Debug, Clone, Serialize)]
+struct ReloadFailed {
+    pack_id: String,
+    error: String,
+}
+
+/// A decoded sound, mono
+pub struct Sound {
+    samples: Arc<[f32]>,
+    sample_rate: u32,
+}
+
+impl Sound {
+    /// The sound looped at the device's sample rate
+    pub fn looping(&self, sample_rate: u32) -> Source {
+        let samples = self.samples.clone();
+        let step = self.sample_rate as f64 / sample_rate.max(1) as f64;
+        let mut position = 0.0_f64;
+
+        Box::new(move |out: &mut [f32]| {
+            let len = samples.len();
+            for sample in out {
+                let index = position as usize;
+                let frac = (position - index as f64) as f32;
+                let a = samples[index % len];
+                let b = samples[(index + 1) % len];
+                *sample = a + (b - a) * frac;
+                position += step;
+                if position >= len as f64 {
+                    position -= len as f64;
+                }
+            }
+        })
+    }
+}
+
+struct Loaded {
+    payload: ThemePayload,
+    tray: BTreeMap<TrayState, Image<'static>>,
+    ringtone: Option<Arc<Sound>>,
+    /// What the pack's files looked like when loaded
+    fingerprint: Vec<(PathBuf, u64, Option<SystemTime>)>,
+}
+
+/// The applied pack
+#[derive(Default)]
+pub struct NativeTheme {
+    applied: Mutex<Option<Loaded>>,
+}
+
+impl NativeTheme {
+    fn lock(&self) -> MutexGuard<'_, Option<Loaded>> {
+        self.applied.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Installed packs, by id
+pub fn list(app: &AppHandle) -> Result<Vec<ThemeSummary>, ThemeError> {
+    let dir = themes_dir(app)?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(ThemeError::File(dir.display().to_string(), e)),
+    };
+    let mut themes: Vec<ThemeSummary> = entries
+        .flatten()
+        .filter(|entry| entry.path().join(MANIFEST).is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|id| valid_id(id))
+        .map(|id| match load(&dir.join(&id), &id) {
+            Ok(loaded) => ThemeSummary {
+                id,
+                name: loaded.payload.name,
+                error: None,
+            },
+            Err(e) => ThemeSummary {
+                id,
+                name: None,
+                error: Some(e.to_string()),
+            },
+        })
+        .collect();
+    themes.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(themes)
+}
+
+/// Apply a pack, or go back to the built-in look with `None`
+/// Emits "native-theme" once it's applied
+pub fn apply(app: &AppHandle, pack_id: Option<&str>) -> Result<ThemePayload, ThemeError> {
+    let loaded = match pack_id {
+        Some(id) => {
+            if !valid_id(id) {
+                return Err(ThemeError::InvalidId);
+            }
+            let dir = themes_dir(app)?.join(id);
+            if !dir.join(MANIFEST).is_file() {
+                return Err(ThemeError::NotFound(id.to_string()));
+            }
+            Some(load(&dir, id)?)
+        }
+        None => None,
+    };
+
+    let value = pack_id.map_or(serde_json::Value::Null, |id| id.into());
+    app.state::<Database>()
+        .with(|conn| settings::set(conn, THEME_SETTING, &value))?;
+    Ok(set(app, loaded))
+}
+
+/// The applied pack, or the built-in look
+pub fn current(app: &AppHandle) -> ThemePayload {
+    app.state::<NativeTheme>()
+        .lock()
+        .as_ref()
+        .map(|loaded| loaded.payload.clone())
+        .unwrap_or_default()
+}
+
+/// The ringtone, if the applied pack has one
+pub fn ringtone(app: &AppHandle) -> Option<Arc<Sound>> {
+    app.state::<NativeTheme>()
+        .lock()
+        .as_ref()
+        .and_then(|loaded| loaded.ringtone.clone())
+}
+
+/// Show the tray icon for the current call, do not disturb, mute and
+/// unread state
+pub fn refresh_tray(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let state = if matches!(app.state::<Calls>().state(), CallState::Ringing(_)) {
+        TrayState::Ringing
+    } else if dnd::is_active(app) {
+        TrayState::DoNotDisturb
+    } else if app.state::<Launcher>().is_muted() {
+        TrayState::Muted
+    } else if unread::any(app) {
+        TrayState::Unread
+    } else {
+        TrayState::Default
+    };
+
+    let icon = app
+        .state::<NativeTheme>()
+        .lock()
+        .as_ref()
+        .and_then(|loaded| {
+            loaded
+                .tray
+                .get(&state)
+                .or_else(|| loaded.tray.get(&TrayState::Default))
+                .cloned()
+        });
+    let icon = icon.or_else(|| app.default_window_icon().cloned());
+    if let Err(e) = tray.set_icon(icon) {
+        log::warn!("Failed to set the tray icon: {}", e);
+    }
+}
+
+/// Apply the saved pack and watch it for changes; call once at startup,
+/// after the tray is installed
+pub fn spawn(app: &AppHandle) {
+    let saved = app
+        .state::<Database>()
+        .with(|conn| settings::get_value(conn, THEME_SETTING))
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to read the native theme: {}", e);
+            None
+        })
+        .and_then(|v| v.as_str().map(str::to_string));
+    if let Some(id) = saved {
+        let loaded = if valid_id(&id) {
+            themes_dir(app).and_then(|dir| load(&dir.join(&id), &id))
+        } else {
+            Err(ThemeError::InvalidId)
+        };
+        match loaded {
+            Ok(loaded) => {
+                set(app, Some(loaded));
+            }
+            Err(e) => log::warn!("Not applying theme pack {}: {}", id, e),
+        }
+    }
+
+    let app = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("native-theme".to_string())
+        .spawn(move || loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            reload_if_changed(&app);
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to start watching the theme pack: {}", e);
+    }
+}
+
+fn reload_if_changed(app: &AppHandle) {
+    let Ok(dir) = themes_dir(app) else {
+        return;
+    };
+    let (id, unchanged) = {
+        let theme = app.state::<NativeTheme>();
+        let applied = theme.lock();
+        let Some(loaded) = applied.as_ref() else {
+            return;
+        };
+        let Some(id) = loaded.payload.pack_id.clone().filter(|id| valid_id(id)) else {
+            return;
+        };
+        let unchanged = fingerprint(&dir.join(&id)) == loaded.fingerprint;
+        (id, unchanged)
+    };
+    if unchanged {
+        return;
+    }
+
+    match load(&dir.join(&id), &id) {
+        Ok(loaded) => {
+            log::info!("Reloaded theme pack {}", id);
+            set(app, Some(loaded));
+        }
+        Err(e) => {
+            log::warn!("Keeping the last good version of theme pack {}: {}", id, e);
+            // Not retried until the files change again
+            if let Some(loaded) = app.state::<NativeTheme>().lock().as_mut() {
+                loaded.fingerprint = fingerprint(&dir.join(&id));
+            }
+            let _ = app.emit(
+                "native-theme-error",
+                ReloadFailed {
+                    pack_id: id,
+                    error: e.to_string(),
+                },
+            );
+        }
+    }
+}
+
+fn set(app: &AppHandle, loaded: Option<Loaded>) -> ThemePayload {
+    let payload = loaded
+        .as_ref()
+        .map(|loaded| loaded.payload.clone())
+        .unwrap_or_default();
+    *app.state::<NativeTheme>().lock() = loaded;
+    refresh_tray(app);
+    let _ = app.emit("native-theme", &payload);
+    payload
+}
+
+fn themes_dir(app: &AppHandle) -> Result<PathBuf, ThemeError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join("themes"))
+        .map_err(|_| ThemeError::NoDir)
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Read and validate a whole pack
+fn load(dir: &Path, id: &str) -> Result<Loaded, ThemeError> {
+    let fingerprint = fingerprint(dir);
+    let manifest_path = dir.join(MANIFEST);
+    let manifest =
+        std::fs::read(&manifest_path).map_err(|e| ThemeError::File(MANIFEST.to_string(), e))?;
+    let manifest: Manifest = serde_json::from_slice(&manifest)?;
+
+    let mut tray = BTreeMap::new();
+    for (state, file) in &manifest.tray {
+        let bytes = read(dir, file, MAX_ICON_BYTES)?;
+        let image = media::decode(&bytes).map_err(|e| ThemeError::Icon(file.clone(), e))?;
+        if image.width() > MAX_ICON_DIMENSION || image.height() > MAX_ICON_DIMENSION {
+            return Err(ThemeError::IconSize(
+                file.clone(),
+                image.width(),
+                image.height(),
+            ));
+        }
+        let (width, height) = (image.width(), image.height());
+        tray.insert(
+            *state,
+            Image::new_owned(image.into_rgba8().into_raw(), width, height),
+        );
+    }
+
+    let colors = &manifest.overlay;
+    for color in [
+        &colors.background,
+        &colors.foreground,
+        &colors.accent,
+        &colors.speaking,
+        &colors.muted,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if !valid_color(color) {
+            return Err(ThemeError::Color(color.clone()));
+        }
+    }
+
+    let mut ringtone = None;
+    let mut sounds = BTreeMap::new();
+    for (name, file) in &manifest.sounds {
+        let bytes = read(dir, file, MAX_SOUND_BYTES)?;
+        let sound = decode_wav(&bytes).map_err(|e| ThemeError::Sound(file.clone(), e))?;
+        if sound.samples.len() as f32 / sound.sample_rate as f32 > MAX_SOUND_SECS {
+            return Err(ThemeError::SoundLength(file.clone()));
+        }
+        if name == "ringtone" {
+            ringtone = Some(Arc::new(sound));
+        } else if WEBVIEW_SOUNDS.contains(&name.as_str()) {
+            let url = format!(
+                "data:audio/wav;base64,{}",
+                base64::engine::general_purpose::STANDARD.encode(&bytes)
+            );
+            sounds.insert(name.clone(), url);
+        } else {
+            return Err(ThemeError::UnknownSound(name.clone()));
+        }
+    }
+
+    Ok(Loaded {
+        payload: ThemePayload {
+            pack_id: Some(id.to_string()),
+            name: Some(manifest.name),
+            overlay: manifest.overlay,
+            sounds,
+        },
+        tray,
+        ringtone,
+        fingerprint,
+    })
+}
+
+/// Read a file the manifest names, which has to stay inside the pack
+fn read(dir: &Path, file: &str, max_bytes: u64) -> Result<Vec<u8>, ThemeError> {
+    let relative = Path::new(file);
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(ThemeError::OutsidePack(file.to_string()));
+    }
+    let path = dir.join(relative);
+    let len = std::fs::metadata(&path)
+        .map_err(|e| ThemeError::File(file.to_string(), e))?
+        .len();
+    if len > max_bytes {
+        return Err(ThemeError::TooLarge(file.to_string(), max_bytes));
+    }
+    std::fs::read(&path).map_err(|e| ThemeError::File(file.to_string(), e))
+}
+
+/// `#rgb`, `#rrggbb` or `#rrggbbaa`
+fn valid_color(color: &str) -> bool {
+    color.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+/// Every file in the pack with its size and modification time
+fn fingerprint(dir: &Path) -> Vec<(PathBuf, u64, Option<SystemTime>)> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                files.push((entry.path(), metadata.len(), metadata.modified().ok()));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Decode 16-bit or 32-bit float PCM WAV, mixed down to mono
+fn decode_wav(bytes: &[u8]) -> Result<Sound, &'static str> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("no RIFF/WAVE header");
+    }
+    let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let u32_at =
+        |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+
+    let mut format = None;
+    let mut data = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let id = &bytes[at..at + 4];
+        let len = u32_at(at + 4) as usize;
+        let body = at + 8;
+        let end = body.checked_add(len).filter(|&end| end <= bytes.len());
+        let Some(end) = end else {
+            // Some writers leave the data length unset; take the rest
+            if id == b"data" {
+                data = Some(&bytes[body..]);
+            }
+            break;
+        };
+        match id {
+            b"fmt " if len >= 16 => {
+                let mut tag = u16_at(body);
+                // WAVE_FORMAT_EXTENSIBLE keeps the real tag in its sub-format
+                if tag == 0xfffe && len >= 26 {
+                    tag = u16_at(body + 24);
+                }
+                format = Some((tag, u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+            }
+            b"data" => data = Some(&bytes[body..end]),
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        at = end + (len & 1);
+    }
+
+    let (tag, channels, sample_rate, bits) = format.ok_or("no format chunk")?;
+    let data = data.ok_or("no data chunk")?;
+    if !(1..=2).contains(&channels) {
+        return Err("only mono and stereo are supported");
+    }
+    if !(8000..=192_000).contains(&sample_rate) {
+        return Err("unsupported sample rate");
+    }
+    let samples: Vec<f32> = match (tag, bits) {
+        (1, 16) => data
+            .chunks_exact(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0)
+            .collect(),
+        (3, 32) => data
+            .chunks_exact(4)
+            .map(|s| f32::from_le_bytes([s[0], s[1], s[2], s[3]]).clamp(-1.0, 1.0))
+            .collect(),
+        _ => return Err("only 16-bit and 32-bit float PCM are supported"),
+    };
+    let samples: Vec<f32> = samples
+        .chunks_exact(channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    if samples.is_empty() {
+        return Err("no audio");
+    }
+    Ok(Sound {
+        samples: samples.into(),
+        sample_rate,
+    })
+}
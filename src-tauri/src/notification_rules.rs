@@ -0,0 +1,237 @@
+//! Notification rules
+//!
+//! Which message notifications get posted, decided here rather than in
+//! the frontend so the rules also hold for notifications raised in the
+//! background (see [`crate::background`]) when there's no webview to ask.
+//! Each conversation can notify for everything, only for mentions, or not
+//! at all. Highlights are words, or regular expressions, that count as a
+//! mention wherever they appear in a message.
+//!
+//! The rules are kept in the settings. [`crate::notifications::show`]
+//! checks them after do not disturb and before anything is posted; call
+//! notifications aren't affected.
+
+use std::sync::{Mutex, MutexGuard};
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+
+use crate::db::Database;
+use crate::notifications::Notification;
+use crate::settings;
+
+/// Setting holding the [`NotificationRules`]
+pub const RULES_SETTING: &str = "notifications.rules";
+
+/// Most conversation rules and highlights there can be
+pub const MAX_CONVERSATIONS: usize = 1024;
+pub const MAX_HIGHLIGHTS: usize = 64;
+
+/// Longest a highlight's pattern can be
+pub const MAX_PATTERN_LEN: usize = 256;
+
+/// Most memory a compiled highlight can take, so a pathological pattern
+/// fails to save instead of slowing every notification down
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RulesError {
+    #[error("there can be at most {MAX_CONVERSATIONS} conversation rules and {MAX_HIGHLIGHTS} highlights")]
+    TooMany,
+    #[error("a highlight can't be empty or longer than {MAX_PATTERN_LEN} characters")]
+    PatternLength,
+    #[error("invalid highlight \"{pattern}\": {source}")]
+    InvalidPattern {
+        pattern: String,
+        source: regex::Error,
+    },
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationRules {
+    pub conversations: Vec<ConversationRule>,
+    pub highlights: Vec<Highlight>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationRule {
+    pub instance_id: String,
+    pub channel_id: String,
+    pub level: Level,
+}
+
+/// What a conversation notifies for; conversations without a rule
+/// notify for everything
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    #[default]
+    All,
+    MentionsOnly,
+    Muted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Highlight {
+    pub pattern: String,
+    /// Whether `pattern` is a regular expression rather than a word.
+    /// Either way it's matched ignoring case.
+    #[serde(default)]
+    pub regex: bool,
+}
+
+impl Highlight {
+    fn compile(&self) -> Result<Regex, RulesError> {
+        let len = self.pattern.chars().count();
+        if len == 0 || len > MAX_PATTERN_LEN {
+            return Err(RulesError::PatternLength);
+        }
+        let pattern = if self.regex {
+            self.pattern.clone()
+        } else {
+            format!(r"\b{}\b", regex::escape(&self.pattern))
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .map_err(|source| RulesError::InvalidPattern {
+                pattern: self.pattern.clone(),
+                source,
+            })
+    }
+}
+
+impl NotificationRules {
+    fn validate(&self) -> Result<Vec<Regex>, RulesError> {
+        if self.conversations.len() > MAX_CONVERSATIONS || self.highlights.len() > MAX_HIGHLIGHTS {
+            return Err(RulesError::TooMany);
+        }
+        self.highlights.iter().map(Highlight::compile).collect()
+    }
+
+    fn level(&self, instance_id: &str, channel_id: &str) -> Level {
+        self.conversations
+            .iter()
+            .find(|rule| rule.instance_id == instance_id && rule.channel_id == channel_id)
+            .map_or(Level::All, |rule| rule.level)
+    }
+}
+
+/// The stored rules with their highlights compiled, kept until the
+/// setting changes
+struct Compiled {
+    stored: Option<Value>,
+    rules: NotificationRules,
+    highlights: Vec<Regex>,
+}
+
+#[derive(Default)]
+pub struct Rules {
+    compiled: Mutex<Option<Compiled>>,
+}
+
+impl Rules {
+    fn lock(&self) -> MutexGuard<'_, Option<Compiled>> {
+        self.compiled.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub fn rules(db: &Database) -> Result<NotificationRules, RulesError> {
+    let stored = db.with(|conn| settings::get_value(conn, RULES_SETTING))?;
+    Ok(parse(stored).0)
+}
+
+/// Store new rules, refusing them if a highlight doesn't compile
+pub fn set_rules(app: &AppHandle, rules: NotificationRules) -> Result<(), RulesError> {
+    rules.validate()?;
+    let value = serde_json::to_value(&rules).expect("rules serialize");
+    app.state::<Database>()
+        .with(|conn| settings::set(conn, RULES_SETTING, &value))?;
+    Ok(())
+}
+
+/// Whether `notification` may be posted
+pub fn allows(app: &AppHandle, notification: &Notification) -> bool {
+    if notification.call_id.is_some() {
+        return true;
+    }
+    with_compiled(app, |compiled| {
+        match compiled
+            .rules
+            .level(&notification.instance_id, &notification.channel_id)
+        {
+            Level::All => true,
+            Level::Muted => false,
+            Level::MentionsOnly => {
+                notification.mentioned || matches(&compiled.highlights, &notification.body)
+            }
+        }
+    })
+    .unwrap_or(true)
+}
+
+/// Whether `content` has one of the highlights in it
+pub fn highlighted(app: &AppHandle, content: &str) -> bool {
+    with_compiled(app, |compiled| matches(&compiled.highlights, content)).unwrap_or(false)
+}
+
+fn matches(highlights: &[Regex], content: &str) -> bool {
+    highlights
+        .iter()
+        .any(|highlight| highlight.is_match(content))
+}
+
+/// Run `f` on the current rules, or `None` if they couldn't be read
+fn with_compiled<T>(app: &AppHandle, f: impl FnOnce(&Compiled) -> T) -> Option<T> {
+    let stored = match app
+        .state::<Database>()
+        .with(|conn| settings::get_value(conn, RULES_SETTING))
+    {
+        Ok(stored) => stored,
+        Err(e) => {
+            log::error!("Failed to read the notification rules: {}", e);
+            return None;
+        }
+    };
+
+    let state = app.state::<Rules>();
+    let mut compiled = state.lock();
+    if compiled.as_ref().map_or(true, |c| c.stored != stored) {
+        let (rules, highlights) = parse(stored.clone());
+        *compiled = Some(Compiled {
+            stored,
+            rules,
+            highlights,
+        });
+    }
+    compiled.as_ref().map(f)
+}
+
+/// The rules in a stored value and their highlights, skipping anything
+/// that doesn't parse or compile
+fn parse(stored: Option<Value>) -> (NotificationRules, Vec<Regex>) {
+    let rules: NotificationRules = stored
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| log::warn!("Ignoring malformed notification rules: {}", e))
+                .ok()
+        })
+        .unwrap_or_default();
+    let highlights = rules
+        .highlights
+        .iter()
+        .filter_map(|highlight| {
+            highlight
+                .compile()
+                .map_err(|e| log::warn!("Ignoring a highlight: {}", e))
+                .ok()
+        })
+        .collect();
+    (rules, highlights)
+}
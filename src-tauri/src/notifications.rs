@@ -0,0 +1,154 @@
+//! Native notifications
+//!
+//! On Linux message notifications go straight to the desktop's
+//! notification server, so clicking one opens its conversation even when
+//! the webview has been suspended, and buttons on it ("Mark as Read") can
+//! be handled without raising the window. Other platforms keep using the
+//! webview's notifications.
+//!
+//! Incoming calls use the same path but are shown as critical, with
+//! buttons that answer or decline the call.
+//!
+//! In streamer mode notifications only say that a message or call arrived.
+//! During do not disturb (see [`crate::dnd`]) they aren't shown at all,
+//! and message notifications the conversation's rules filter out (see
+//! [`crate::notification_rules`]) aren't either.
+
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::{automation, dnd, notification_rules};
+
+#[cfg(target_os = "linux")]
+use crate::streamer_mode::StreamerMode;
+
+#[derive(Debug, thiserror::Error)]
+pub enum NotificationError {
+    #[cfg(target_os = "linux")]
+    #[error("not connected to the session bus")]
+    NoBus,
+    #[cfg(target_os = "linux")]
+    #[error("D-Bus error: {0}")]
+    DBus(#[from] zbus::Error),
+}
+
+/// A notification about a conversation
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+#[derive(Debug, Clone, Deserialize)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub instance_id: String,
+    pub channel_id: String,
+    /// Shown besides opening the conversation, which clicking does
+    #[serde(default)]
+    pub buttons: Vec<NotificationButton>,
+    /// Whether the message mentions the user, for conversations that only
+    /// notify for mentions
+    #[serde(default)]
+    pub mentioned: bool,
+    /// Set for incoming call notifications (see [`crate::calls`]), which
+    /// are critical and stay up until the call stops ringing
+    #[serde(skip)]
+    pub call_id: Option<String>,
+}
+
+impl Notification {
+    /// The notification without its sender or content, which still opens
+    /// the conversation
+    pub(crate) fn masked(self) -> Self {
+        Self {
+            title: "Redoubt".to_string(),
+            body: if self.call_id.is_some() {
+                "Incoming call"
+            } else {
+                "New message"
+            }
+            .to_string(),
+            ..self
+        }
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+#[derive(Debug, Clone, Deserialize)]
+pub struct NotificationButton {
+    pub id: String,
+    pub label: String,
+}
+
+/// Payload of "notification-action"
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+#[derive(Debug, Clone, Serialize)]
+pub struct NotificationAction {
+    /// The button's id
+    pub action: String,
+    pub instance_id: String,
+    pub channel_id: String,
+}
+
+/// What became of a notification
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(tag = "shown", rename_all = "snake_case")]
+pub enum Shown {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    Native { id: u32 },
+    /// The webview should show it instead
+    #[cfg_attr(target_os = "linux", allow(dead_code))]
+    Webview,
+    /// Held back for do not disturb
+    Suppressed,
+    /// Held back by the conversation's notification rules
+    Filtered,
+}
+
+/// Show a notification natively where the platform allows
+pub async fn show(app: &AppHandle, notification: Notification) -> Result<Shown, NotificationError> {
+    if dnd::is_active(app) {
+        return Ok(Shown::Suppressed);
+    }
+    if !notification_rules::allows(app, &notification) {
+        return Ok(Shown::Filtered);
+    }
+    if notification.mentioned {
+        automation::mentioned(app, &notification);
+    }
+    #[cfg(target_os = "linux")]
+    {
+        use tauri::Manager;
+
+        let notification = if app.state::<StreamerMode>().is_enabled() {
+            notification.masked()
+        } else {
+            notification
+        };
+        let bus = app.state::<crate::dbus::SessionBus>();
+        let connection = bus.connection().ok_or(NotificationError::NoBus)?;
+        let id =
+            crate::dbus::notifications::show(connection, &bus.notifications, notification).await?;
+        Ok(Shown::Native { id })
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = notification;
+        Ok(Shown::Webview)
+    }
+}
+
+/// Remove a notification shown by [`show`]
+pub async fn close(app: &AppHandle, id: u32) -> Result<(), NotificationError> {
+    #[cfg(target_os = "linux")]
+    {
+        use tauri::Manager;
+
+        let bus = app.state::<crate::dbus::SessionBus>();
+        let connection = bus.connection().ok_or(NotificationError::NoBus)?;
+        crate::dbus::notifications::close(connection, &bus.notifications, id).await?;
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (app, id);
+        Ok(())
+    }
+}
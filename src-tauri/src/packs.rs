@@ -0,0 +1,482 @@
+//! Emoji and sticker packs
+//!
+//! A pack is a zip archive with a `pack.json` manifest listing its images
+//! by name, with keywords to find them by. Installing one, from a URL or a
+//! local file, checks it before anything is kept: the archive's SHA-256
+//! when one is given, size and item limits, a manifest naming only files
+//! the archive has, and images that actually decode. It's then unpacked
+//! into the profile's `packs` directory and its items indexed in the
+//! database for search. Installing a pack again replaces it.
+//!
+//! Items are shown through the thumbnail and animation commands with their
+//! file as the source, so they go through the media cache like any other
+//! local image.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::Proxy;
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::db::Database;
+use crate::media::{self, MediaError};
+
+/// Largest pack archive accepted
+pub const MAX_ARCHIVE_BYTES: u64 = 64 * 1024 * 1024;
+/// Most items one pack can have
+pub const MAX_ITEMS: usize = 2000;
+/// Largest single image in a pack
+const MAX_ITEM_BYTES: u64 = 4 * 1024 * 1024;
+const MAX_MANIFEST_BYTES: u64 = 1024 * 1024;
+const MANIFEST_FILE: &str = "pack.json";
+const IMAGE_EXTENSIONS: &[&str] = &["png", "gif", "webp", "jpg", "jpeg"];
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum PackError {
+    #[error("pack not found: {0}")]
+    NotFound(String),
+    #[error("invalid pack URL: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("invalid proxy: {0}")]
+    Proxy(reqwest::Error),
+    #[error("download failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("server responded with {0}")]
+    Status(u16),
+    #[error("pack is larger than {} MiB", MAX_ARCHIVE_BYTES / 1024 / 1024)]
+    TooLarge,
+    #[error("pack checksum is {actual}, expected {expected}")]
+    Checksum { expected: String, actual: String },
+    #[error("failed to read pack archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("pack has no {MANIFEST_FILE}")]
+    NoManifest,
+    #[error("invalid {MANIFEST_FILE}: {0}")]
+    Manifest(#[from] serde_json::Error),
+    #[error("invalid pack: {0}")]
+    Invalid(String),
+    #[error("{file}: {source}")]
+    Image { file: String, source: MediaError },
+    #[error("failed to store pack: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Task(#[from] tauri::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackKind {
+    Emoji,
+    Sticker,
+}
+
+impl PackKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            PackKind::Emoji => "emoji",
+            PackKind::Sticker => "sticker",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "emoji" => Some(PackKind::Emoji),
+            "sticker" => Some(PackKind::Sticker),
+            _ => None,
+        }
+    }
+}
+
+/// `pack.json`
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    /// Letters, digits, `-`, `_` and `.`; installing a pack with the same
+    /// id replaces it
+    id: String,
+    name: String,
+    kind: PackKind,
+    #[serde(default)]
+    version: String,
+    items: Vec<ManifestItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestItem {
+    /// Unique within the pack, e.g. "party_parrot"
+    name: String,
+    /// Path of the image inside the archive
+    file: String,
+    #[serde(default)]
+    keywords: Vec<String>,
+}
+
+/// An installed pack
+#[derive(Debug, Clone, Serialize)]
+pub struct Pack {
+    pub id: String,
+    pub name: String,
+    pub kind: PackKind,
+    pub version: String,
+    /// URL or file it was installed from
+    pub source: String,
+    pub sha256: String,
+    pub item_count: u32,
+    pub installed_at: DateTime<Utc>,
+}
+
+impl Pack {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let kind: String = row.get("kind")?;
+        Ok(Self {
+            id: row.get("id")?,
+            name: row.get("name")?,
+            kind: PackKind::parse(&kind).ok_or_else(|| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    0,
+                    rusqlite::types::Type::Text,
+                    format!("unknown pack kind {}", kind).into(),
+                )
+            })?,
+            version: row.get("version")?,
+            source: row.get("source")?,
+            sha256: row.get("sha256")?,
+            item_count: row.get("item_count")?,
+            installed_at: row.get("installed_at")?,
+        })
+    }
+}
+
+/// An emoji or sticker in an installed pack
+#[derive(Debug, Clone, Serialize)]
+pub struct PackItem {
+    pub pack_id: String,
+    pub name: String,
+    pub keywords: Vec<String>,
+    /// The image, for a file thumbnail or animation source
+    pub path: PathBuf,
+}
+
+/// An item as unpacked, before it's indexed
+struct Unpacked {
+    name: String,
+    keywords: Vec<String>,
+    file: String,
+}
+
+/// Install a pack from an `http(s)` URL or a local file into `packs_dir`,
+/// first checking the archive against `sha256` when one is given
+pub async fn install(
+    db: &Database,
+    packs_dir: &Path,
+    source: &str,
+    sha256: Option<&str>,
+    proxy: Option<&str>,
+) -> Result<Pack, PackError> {
+    let bytes = if source.starts_with("https://") || source.starts_with("http://") {
+        download(Url::parse(source)?, proxy).await?
+    } else {
+        let metadata = tokio::fs::metadata(source).await?;
+        if metadata.len() > MAX_ARCHIVE_BYTES {
+            return Err(PackError::TooLarge);
+        }
+        tokio::fs::read(source).await?
+    };
+
+    let digest: String = Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if let Some(expected) = sha256 {
+        if !expected.trim().eq_ignore_ascii_case(&digest) {
+            return Err(PackError::Checksum {
+                expected: expected.trim().to_ascii_lowercase(),
+                actual: digest,
+            });
+        }
+    }
+
+    let dir = packs_dir.to_path_buf();
+    let (manifest, items) =
+        tauri::async_runtime::spawn_blocking(move || unpack(&dir, bytes)).await??;
+
+    let pack = Pack {
+        id: manifest.id,
+        name: manifest.name,
+        kind: manifest.kind,
+        version: manifest.version,
+        source: source.to_string(),
+        sha256: digest,
+        item_count: items.len() as u32,
+        installed_at: Utc::now(),
+    };
+    db.with(|conn| index(conn, &pack, &items))?;
+    Ok(pack)
+}
+
+/// Remove a pack's items from the index and its images from disk
+pub fn remove(db: &Database, packs_dir: &Path, id: &str) -> Result<(), PackError> {
+    let removed = db.with(|conn| {
+        conn.prepare_cached("DELETE FROM packs WHERE id = ?1")?
+            .execute([id])
+    })?;
+    if removed == 0 {
+        return Err(PackError::NotFound(id.to_string()));
+    }
+
+    let dir = packs_dir.join(id);
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+pub fn list(conn: &Connection) -> rusqlite::Result<Vec<Pack>> {
+    let mut stmt = conn.prepare_cached("SELECT * FROM packs ORDER BY name COLLATE NOCASE")?;
+    let rows = stmt.query_map([], Pack::from_row)?;
+    rows.collect()
+}
+
+/// Items whose name or keywords contain `query`, names that start with it
+/// first; every item when `query` is empty
+pub fn search(
+    conn: &Connection,
+    packs_dir: &Path,
+    query: &str,
+    kind: Option<PackKind>,
+    limit: u32,
+) -> rusqlite::Result<Vec<PackItem>> {
+    let query = query.trim().to_lowercase();
+    let escaped = query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    let mut stmt = conn.prepare_cached(
+        "SELECT pack_items.* FROM pack_items JOIN packs ON packs.id = pack_items.pack_id
+         WHERE (?1 IS NULL OR packs.kind = ?1)
+           AND (lower(pack_items.name) LIKE '%' || ?2 || '%' ESCAPE '\\'
+                OR pack_items.keywords LIKE '%' || ?2 || '%' ESCAPE '\\')
+         ORDER BY lower(pack_items.name) LIKE ?2 || '%' ESCAPE '\\' DESC,
+                  packs.name COLLATE NOCASE, pack_items.position
+         LIMIT ?3",
+    )?;
+
+    let rows = stmt.query_map(params![kind.map(PackKind::as_str), escaped, limit], |row| {
+        let pack_id: String = row.get("pack_id")?;
+        let file: String = row.get("file")?;
+        let keywords: String = row.get("keywords")?;
+        Ok(PackItem {
+            path: packs_dir.join(&pack_id).join(file),
+            pack_id,
+            name: row.get("name")?,
+            keywords: keywords.split_whitespace().map(str::to_string).collect(),
+        })
+    })?;
+    rows.collect()
+}
+
+/// Fetch an archive, refusing anything over [`MAX_ARCHIVE_BYTES`]
+async fn download(url: Url, proxy: Option<&str>) -> Result<Vec<u8>, PackError> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .connect_timeout(CONNECT_TIMEOUT);
+    if let Some(proxy) = proxy.map(str::trim).filter(|p| !p.is_empty()) {
+        builder = builder.proxy(Proxy::all(proxy).map_err(PackError::Proxy)?);
+    }
+
+    let mut response = builder.build()?.get(url).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(PackError::Status(status.as_u16()));
+    }
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_ARCHIVE_BYTES)
+    {
+        return Err(PackError::TooLarge);
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if (bytes.len() + chunk.len()) as u64 > MAX_ARCHIVE_BYTES {
+            return Err(PackError::TooLarge);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Check an archive and unpack its images into `packs_dir/<id>`, replacing
+/// whatever was there only once every image has been read
+fn unpack(packs_dir: &Path, bytes: Vec<u8>) -> Result<(Manifest, Vec<Unpacked>), PackError> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let manifest: Manifest = {
+        let entry = archive
+            .by_name(MANIFEST_FILE)
+            .map_err(|_| PackError::NoManifest)?;
+        let mut contents = Vec::new();
+        entry
+            .take(MAX_MANIFEST_BYTES + 1)
+            .read_to_end(&mut contents)?;
+        if contents.len() as u64 > MAX_MANIFEST_BYTES {
+            return Err(PackError::Invalid(format!(
+                "{} is too large",
+                MANIFEST_FILE
+            )));
+        }
+        serde_json::from_slice(&contents)?
+    };
+    validate(&manifest)?;
+
+    let staging = packs_dir.join(format!(".{}.partial", manifest.id));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+
+    let result = (|| {
+        let mut items = Vec::with_capacity(manifest.items.len());
+        for (position, item) in manifest.items.iter().enumerate() {
+            let Some(extension) = image_extension(&item.file) else {
+                return Err(PackError::Invalid(format!(
+                    "{} isn't a PNG, GIF, WebP or JPEG image",
+                    item.file
+                )));
+            };
+            let entry = archive
+                .by_name(&item.file)
+                .map_err(|_| PackError::Invalid(format!("{} is missing", item.file)))?;
+            let mut contents = Vec::new();
+            entry.take(MAX_ITEM_BYTES + 1).read_to_end(&mut contents)?;
+            if contents.len() as u64 > MAX_ITEM_BYTES {
+                return Err(PackError::Invalid(format!("{} is too large", item.file)));
+            }
+            media::decode(&contents).map_err(|source| PackError::Image {
+                file: item.file.clone(),
+                source,
+            })?;
+
+            // Stored under a name of our own so archive paths never reach the disk
+            let file = format!("{}.{}", position, extension);
+            fs::write(staging.join(&file), &contents)?;
+            items.push(Unpacked {
+                name: item.name.clone(),
+                keywords: item
+                    .keywords
+                    .iter()
+                    .flat_map(|k| k.split_whitespace())
+                    .map(str::to_lowercase)
+                    .collect(),
+                file,
+            });
+        }
+        Ok(items)
+    })();
+    let items = match result {
+        Ok(items) => items,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    };
+
+    let dir = packs_dir.join(&manifest.id);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::rename(&staging, &dir)?;
+    Ok((manifest, items))
+}
+
+fn validate(manifest: &Manifest) -> Result<(), PackError> {
+    let id_ok = !manifest.id.is_empty()
+        && manifest.id.len() <= 64
+        && !manifest.id.starts_with('.')
+        && manifest
+            .id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !id_ok {
+        return Err(PackError::Invalid(format!(
+            "bad pack id \"{}\"",
+            manifest.id
+        )));
+    }
+    if manifest.name.trim().is_empty() {
+        return Err(PackError::Invalid("the pack has no name".to_string()));
+    }
+    if manifest.items.is_empty() || manifest.items.len() > MAX_ITEMS {
+        return Err(PackError::Invalid(format!(
+            "a pack needs between 1 and {} items",
+            MAX_ITEMS
+        )));
+    }
+
+    let mut names = HashSet::new();
+    for item in &manifest.items {
+        if item.name.trim().is_empty() {
+            return Err(PackError::Invalid(format!("{} has no name", item.file)));
+        }
+        if !names.insert(item.name.as_str()) {
+            return Err(PackError::Invalid(format!(
+                "more than one item is called \"{}\"",
+                item.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn image_extension(file: &str) -> Option<String> {
+    let extension = Path::new(file).extension()?.to_str()?.to_ascii_lowercase();
+    IMAGE_EXTENSIONS
+        .contains(&extension.as_str())
+        .then_some(extension)
+}
+
+/// Replace a pack's rows with `items`
+fn index(conn: &mut Connection, pack: &Pack, items: &[Unpacked]) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM packs WHERE id = ?1", [&pack.id])?;
+    tx.execute(
+        "INSERT INTO packs (id, name, kind, version, source, sha256, item_count, installed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            pack.id,
+            pack.name,
+            pack.kind.as_str(),
+            pack.version,
+            pack.source,
+            pack.sha256,
+            pack.item_count,
+            pack.installed_at,
+        ],
+    )?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO pack_items (pack_id, position, name, keywords, file)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (position, item) in items.iter().enumerate() {
+            stmt.execute(params![
+                pack.id,
+                position as i64,
+                item.name,
+                item.keywords.join(" "),
+                item.file,
+            ])?;
+        }
+    }
+    tx.commit()
+}
@@ -0,0 +1,231 @@
+//! Performance profile
+//!
+//! [`PROFILE_SETTING`] picks how hard the app works for quality: `high`,
+//! `low` for weak hardware, or `auto`. On the low tier:
+//! - video transcodes use the fastest encoder preset whatever the quality,
+//!   and voice messages and call recordings encode at a lower complexity
+//! - coalesced events (see [`crate::coalesce`]) go out every
+//!   [`LOW_TIER_FRAME`] rather than every display frame
+//! - the webview, which owns call media, is told to turn ML noise
+//!   suppression off, update level meters less often and cap screen shares;
+//!   it reads [`CallMedia`] from `get_performance_profile` and
+//!   "performance-profile"
+//!
+//! On auto the tier starts high and drops to low when, during a call, the
+//! machine's CPU or the audio capture callbacks stay over their threshold
+//! for [`SUSTAINED_SAMPLES`] resource samples in a row (see
+//! [`crate::metrics`]). It then stays low until the app restarts or the
+//! setting changes, so a call doesn't flip between the two.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::audio::channel_overrides::ChannelAudio;
+use crate::calls::{CallState, Calls};
+use crate::db::Database;
+use crate::metrics::ResourceUsage;
+use crate::settings;
+
+/// Setting holding the [`PerformanceProfile`]
+pub const PROFILE_SETTING: &str = "performance.profile";
+
+/// How long coalesced events are collected on the low tier
+pub const LOW_TIER_FRAME: Duration = Duration::from_millis(100);
+
+/// Samples in a row over a threshold before auto drops to low
+pub const SUSTAINED_SAMPLES: u32 = 3;
+
+/// Machine-wide CPU, from 0 to 100, that counts as struggling
+const CPU_THRESHOLD: f32 = 85.0;
+/// Share of their time budget capture callbacks can use before audio
+/// risks dropping out
+const AUDIO_LOAD_THRESHOLD: f32 = 0.5;
+
+/// On the low tier; read wherever work is scaled down
+static LOW: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PerformanceProfile {
+    #[default]
+    Auto,
+    Low,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tier {
+    Low,
+    High,
+}
+
+/// How the webview should run call media on the current tier
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CallMedia {
+    pub noise_suppression: bool,
+    pub meter_interval_ms: u32,
+    /// Tallest screen share to send, in pixels; `None` is the source's
+    pub max_share_height: Option<u32>,
+    pub max_share_fps: u32,
+}
+
+impl Tier {
+    fn call_media(self) -> CallMedia {
+        match self {
+            Tier::Low => CallMedia {
+                noise_suppression: false,
+                meter_interval_ms: 250,
+                max_share_height: Some(720),
+                max_share_fps: 15,
+            },
+            Tier::High => CallMedia {
+                noise_suppression: true,
+                meter_interval_ms: 50,
+                max_share_height: None,
+                max_share_fps: 30,
+            },
+        }
+    }
+}
+
+/// Result of `get_performance_profile`, and the payload of
+/// "performance-profile"
+#[derive(Debug, Clone, Serialize)]
+pub struct PerformanceState {
+    pub profile: PerformanceProfile,
+    pub tier: Tier,
+    /// When auto dropped to low, if it has
+    pub detected_at: Option<DateTime<Utc>>,
+    pub call_media: CallMedia,
+}
+
+#[derive(Default)]
+pub struct Performance {
+    detection: Mutex<Detection>,
+}
+
+#[derive(Default)]
+struct Detection {
+    /// Samples in a row over a threshold during a call
+    over: u32,
+    detected_at: Option<DateTime<Utc>>,
+}
+
+impl Performance {
+    fn lock(&self) -> MutexGuard<'_, Detection> {
+        self.detection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Whether work should be scaled down
+pub fn is_low() -> bool {
+    LOW.load(Ordering::Relaxed)
+}
+
+pub fn state(app: &AppHandle) -> rusqlite::Result<PerformanceState> {
+    let profile = profile(&app.state::<Database>())?;
+    let detected_at = app.state::<Performance>().lock().detected_at;
+    let tier = match profile {
+        PerformanceProfile::Low => Tier::Low,
+        PerformanceProfile::High => Tier::High,
+        PerformanceProfile::Auto if detected_at.is_some() => Tier::Low,
+        PerformanceProfile::Auto => Tier::High,
+    };
+    Ok(PerformanceState {
+        profile,
+        tier,
+        detected_at,
+        call_media: tier.call_media(),
+    })
+}
+
+/// Emits "performance-profile"
+pub fn set_profile(
+    app: &AppHandle,
+    profile: PerformanceProfile,
+) -> rusqlite::Result<PerformanceState> {
+    app.state::<Database>()
+        .with(|conn| settings::set(conn, PROFILE_SETTING, &profile))?;
+    {
+        let performance = app.state::<Performance>();
+        let mut detection = performance.lock();
+        detection.over = 0;
+        detection.detected_at = None;
+    }
+    changed(app)
+}
+
+/// Take up the stored profile; call once during setup
+pub fn apply(app: &AppHandle) {
+    match state(app) {
+        Ok(state) => LOW.store(state.tier == Tier::Low, Ordering::Relaxed),
+        Err(e) => log::error!("Failed to read performance profile: {}", e),
+    }
+}
+
+/// Look for sustained load during calls; called with each resource sample
+pub fn on_sample(app: &AppHandle, usage: &ResourceUsage) {
+    let Some(performance) = app.try_state::<Performance>() else {
+        return;
+    };
+    {
+        let mut detection = performance.lock();
+        if detection.detected_at.is_some() {
+            return;
+        }
+        let struggling = usage.system_cpu_percent > CPU_THRESHOLD
+            || usage
+                .audio_load
+                .is_some_and(|load| load > AUDIO_LOAD_THRESHOLD);
+        if !struggling || !in_call(app) {
+            detection.over = 0;
+            return;
+        }
+        detection.over += 1;
+        if detection.over < SUSTAINED_SAMPLES {
+            return;
+        }
+        match profile(&app.state::<Database>()) {
+            Ok(PerformanceProfile::Auto) => {}
+            Ok(_) => return,
+            Err(e) => {
+                log::error!("Failed to read performance profile: {}", e);
+                return;
+            }
+        }
+        detection.detected_at = Some(Utc::now());
+    }
+    log::info!(
+        "Dropping to the low performance tier: CPU at {:.0}%, audio load {:?}",
+        usage.system_cpu_percent,
+        usage.audio_load
+    );
+    if let Err(e) = changed(app) {
+        log::error!("Failed to read performance profile: {}", e);
+    }
+}
+
+fn changed(app: &AppHandle) -> rusqlite::Result<PerformanceState> {
+    let state = state(app)?;
+    LOW.store(state.tier == Tier::Low, Ordering::Relaxed);
+    let _ = app.emit("performance-profile", &state);
+    Ok(state)
+}
+
+fn in_call(app: &AppHandle) -> bool {
+    app.state::<ChannelAudio>().is_joined()
+        || matches!(app.state::<Calls>().state(), CallState::Active(_))
+}
+
+fn profile(db: &Database) -> rusqlite::Result<PerformanceProfile> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, PROFILE_SETTING))?
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
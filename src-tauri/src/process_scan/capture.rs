@@ -0,0 +1,28 @@
+//! Capture and streaming software, by executable name
+
+/// Name shown to the user and file names of its executable on any
+/// platform, matched case-insensitively
+pub const CAPTURE_SOFTWARE: &[(&str, &[&str])] = &[
+    ("OBS Studio", &["obs64.exe", "obs32.exe", "obs"]),
+    (
+        "Streamlabs Desktop",
+        &[
+            "Streamlabs OBS.exe",
+            "Streamlabs Desktop.exe",
+            "Streamlabs Desktop",
+        ],
+    ),
+    ("XSplit Broadcaster", &["XSplit.Core.exe"]),
+];
+
+/// The capture software an executable belongs to
+pub fn find(executable: &str) -> Option<&'static str> {
+    CAPTURE_SOFTWARE
+        .iter()
+        .find(|(_, executables)| {
+            executables
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(executable))
+        })
+        .map(|(name, _)| *name)
+}
@@ -0,0 +1,112 @@
+//! The games recognized, by executable name
+
+pub struct Game {
+    /// Stable id the frontend can key presence and artwork on
+    pub id: &'static str,
+    pub name: &'static str,
+    /// File names of the game's main executable on any platform, matched
+    /// case-insensitively
+    pub executables: &'static [&'static str],
+}
+
+pub const GAMES: &[Game] = &[
+    Game {
+        id: "apex-legends",
+        name: "Apex Legends",
+        executables: &["r5apex.exe", "r5apex_dx12.exe"],
+    },
+    Game {
+        id: "baldurs-gate-3",
+        name: "Baldur's Gate 3",
+        executables: &["bg3.exe", "bg3_dx11.exe", "Baldur's Gate 3"],
+    },
+    Game {
+        id: "counter-strike-2",
+        name: "Counter-Strike 2",
+        executables: &["cs2.exe", "cs2"],
+    },
+    Game {
+        id: "dota-2",
+        name: "Dota 2",
+        executables: &["dota2.exe", "dota2"],
+    },
+    Game {
+        id: "elden-ring",
+        name: "Elden Ring",
+        executables: &["eldenring.exe"],
+    },
+    Game {
+        id: "factorio",
+        name: "Factorio",
+        executables: &["factorio.exe", "factorio"],
+    },
+    Game {
+        id: "fortnite",
+        name: "Fortnite",
+        executables: &["FortniteClient-Win64-Shipping.exe"],
+    },
+    Game {
+        id: "gta-v",
+        name: "Grand Theft Auto V",
+        executables: &["GTA5.exe", "GTA5_Enhanced.exe"],
+    },
+    Game {
+        id: "league-of-legends",
+        name: "League of Legends",
+        executables: &["League of Legends.exe", "LeagueofLegends"],
+    },
+    Game {
+        id: "minecraft",
+        name: "Minecraft",
+        executables: &["Minecraft.Windows.exe", "minecraft-launcher"],
+    },
+    Game {
+        id: "overwatch-2",
+        name: "Overwatch 2",
+        executables: &["Overwatch.exe"],
+    },
+    Game {
+        id: "rocket-league",
+        name: "Rocket League",
+        executables: &["RocketLeague.exe"],
+    },
+    Game {
+        id: "rust",
+        name: "Rust",
+        executables: &["RustClient.exe", "RustClient"],
+    },
+    Game {
+        id: "stardew-valley",
+        name: "Stardew Valley",
+        executables: &["Stardew Valley.exe", "StardewValley"],
+    },
+    Game {
+        id: "team-fortress-2",
+        name: "Team Fortress 2",
+        executables: &["tf_win64.exe", "tf_linux64", "tf_osx64"],
+    },
+    Game {
+        id: "terraria",
+        name: "Terraria",
+        executables: &["Terraria.exe", "Terraria.bin.x86_64", "Terraria"],
+    },
+    Game {
+        id: "valorant",
+        name: "Valorant",
+        executables: &["VALORANT-Win64-Shipping.exe"],
+    },
+    Game {
+        id: "world-of-warcraft",
+        name: "World of Warcraft",
+        executables: &["Wow.exe", "World of Warcraft"],
+    },
+];
+
+/// The game an executable belongs to
+pub fn find(executable: &str) -> Option<&'static Game> {
+    GAMES.iter().find(|game| {
+        game.executables
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(executable))
+    })
+}
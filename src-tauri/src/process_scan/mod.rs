@@ -0,0 +1,203 @@
+//! Running process scan
+//!
+//! Every few seconds the process list is checked against a curated list of
+//! games (see [`games`]), so presence can show what the user is playing.
+//! "game-detected" is emitted when one starts and "game-stopped" when it
+//! exits. A game starting can also turn on the overlay or streamer mode,
+//! as chosen in the settings: streamer mode is turned on here, the overlay
+//! by the frontend when the event asks for it.
+//!
+//! The same scan looks for capture software ([`capture`]), which streamer
+//! mode follows (see [`crate::streamer_mode`]).
+//!
+//! Only executable names are read, never command lines or window titles,
+//! and the scan stops entirely while it's turned off in the privacy
+//! settings.
+
+pub mod capture;
+pub mod games;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::db::Database;
+use crate::{settings, streamer_mode};
+
+/// Setting that turns scanning off; on unless set to `false`
+pub const PROCESS_SCAN_SETTING: &str = "privacy.process_scan";
+/// Setting holding what to turn on while a game runs: `"overlay"`,
+/// `"streamer_mode"` or nothing when unset
+pub const GAME_AUTO_MODE_SETTING: &str = "presence.game_auto_mode";
+
+const SCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What a game starting turns on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoMode {
+    Overlay,
+    StreamerMode,
+}
+
+/// Payload of "game-detected"
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectedGame {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    /// Set if this is turned on until the game stops: the frontend turns on
+    /// the overlay, the backend streamer mode
+    pub auto_mode: Option<AutoMode>,
+}
+
+/// Payload of "game-stopped"
+#[derive(Debug, Clone, Serialize)]
+pub struct StoppedGame {
+    pub id: &'static str,
+    pub name: &'static str,
+}
+
+/// Games detected and still running
+#[derive(Default)]
+pub struct RunningGames {
+    games: Mutex<HashMap<&'static str, DetectedGame>>,
+}
+
+impl RunningGames {
+    pub fn list(&self) -> Vec<DetectedGame> {
+        let mut games = self.lock().values().cloned().collect::<Vec<_>>();
+        games.sort_by_key(|game| game.started_at);
+        games
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<&'static str, DetectedGame>> {
+        self.games.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Scan for games for as long as the app runs
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("process-scan".to_string())
+        .spawn(move || {
+            let mut system = System::new();
+            loop {
+                if enabled(&app.state::<Database>()) {
+                    scan(&app, &mut system);
+                } else {
+                    // Forget everything so nothing stale is left when it's
+                    // turned back on
+                    system = System::new();
+                    update(&app, HashMap::new(), None);
+                }
+                std::thread::sleep(SCAN_INTERVAL);
+            }
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to start process scan: {}", e);
+    }
+}
+
+fn scan(app: &AppHandle, system: &mut System) {
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing().with_exe(UpdateKind::OnlyIfNotSet),
+    );
+
+    let mut running = HashMap::new();
+    let mut capture = None;
+    for (pid, process) in system.processes() {
+        // Linux truncates process names to 15 bytes, so prefer the
+        // executable's file name when it can be read
+        let Some(executable) = process
+            .exe()
+            .and_then(Path::file_name)
+            .unwrap_or_else(|| process.name())
+            .to_str()
+        else {
+            continue;
+        };
+        if let Some(game) = games::find(executable) {
+            running.entry(game.id).or_insert((game, pid.as_u32()));
+        } else if let Some(software) = capture::find(executable) {
+            capture = Some(software);
+        }
+    }
+    update(app, running, capture);
+}
+
+/// Emit events for games that started or stopped since the last scan and
+/// let streamer mode follow what's running
+fn update(
+    app: &AppHandle,
+    running: HashMap<&'static str, (&'static games::Game, u32)>,
+    capture: Option<&'static str>,
+) {
+    let state = app.state::<RunningGames>();
+    let mut known = state.lock();
+
+    let stopped = known
+        .keys()
+        .filter(|id| !running.contains_key(*id))
+        .copied()
+        .collect::<Vec<_>>();
+    for id in stopped {
+        if let Some(game) = known.remove(id) {
+            let _ = app.emit(
+                "game-stopped",
+                StoppedGame {
+                    id: game.id,
+                    name: game.name,
+                },
+            );
+        }
+    }
+
+    for (id, (game, pid)) in running {
+        if known.contains_key(id) {
+            continue;
+        }
+        let detected = DetectedGame {
+            id,
+            name: game.name,
+            pid,
+            started_at: Utc::now(),
+            auto_mode: auto_mode(&app.state::<Database>()),
+        };
+        let _ = app.emit("game-detected", detected.clone());
+        known.insert(id, detected);
+    }
+
+    let game = known
+        .values()
+        .any(|game| game.auto_mode == Some(AutoMode::StreamerMode));
+    drop(known);
+    streamer_mode::update_triggers(app, capture, game);
+}
+
+fn enabled(db: &Database) -> bool {
+    db.with(|conn| settings::get_value(conn, PROCESS_SCAN_SETTING))
+        .map_err(|e| log::error!("Failed to read process scan setting: {}", e))
+        .ok()
+        .flatten()
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+}
+
+fn auto_mode(db: &Database) -> Option<AutoMode> {
+    db.with(|conn| settings::get_value(conn, GAME_AUTO_MODE_SETTING))
+        .map_err(|e| log::error!("Failed to read game auto mode setting: {}", e))
+        .ok()
+        .flatten()
+        .and_then(|v| serde_json::from_value(v).ok())
+}
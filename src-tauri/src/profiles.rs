@@ -0,0 +1,279 @@
+//! Account profiles
+//!
+//! A profile is a fully separate set of local state: its own data
+//! directory and database, its own keychain service for instance
+//! credentials, and its own gateway connections. Exactly one profile is
+//! active at a time; the shared [`Database`] handle always points at the
+//! active profile's database.
+//!
+//! The profile list lives in `profiles.json` in the app data directory,
+//! with each profile's data under `profiles/<id>/`. Media cached from a
+//! profile's instances (thumbnails, link preview images) goes under
+//! `profiles/<id>/` in the app cache directory, so it's never served to
+//! another profile and goes with the profile when it's deleted.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+
+const DEFAULT_PROFILE: &str = "default";
+const REGISTRY_FILE: &str = "profiles.json";
+const DATABASE_FILE: &str = "redoubt.db";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Keep this profile's gateway connections open while another profile is active
+    #[serde(default)]
+    pub stay_connected: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Registry {
+    active: String,
+    profiles: Vec<Profile>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileError {
+    #[error("profile not found: {0}")]
+    NotFound(String),
+    #[error("the active profile can't be deleted")]
+    Active,
+    #[error("profile name can't be empty")]
+    EmptyName,
+    #[error("failed to access profile data: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed profile list: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+pub struct Profiles {
+    root: PathBuf,
+    cache_root: PathBuf,
+    registry: Mutex<Registry>,
+    database: Database,
+}
+
+impl Profiles {
+    /// Load the profile list from `root`, creating the default profile on
+    /// first run; profiles' caches go under `cache_root`
+    pub fn load(root: &Path, cache_root: &Path) -> Result<Self, ProfileError> {
+        let registry_path = root.join(REGISTRY_FILE);
+        let registry = if registry_path.exists() {
+            serde_json::from_slice(&fs::read(&registry_path)?)?
+        } else {
+            let registry = Registry {
+                active: DEFAULT_PROFILE.to_string(),
+                profiles: vec![Profile {
+                    id: DEFAULT_PROFILE.to_string(),
+                    name: "Default".to_string(),
+                    created_at: Utc::now(),
+                    stay_connected: false,
+                }],
+            };
+            fs::write(&registry_path, serde_json::to_vec_pretty(&registry)?)?;
+            registry
+        };
+
+        let dir = profile_dir(root, &registry.active);
+        fs::create_dir_all(&dir)?;
+
+        // Before profiles existed the database lived directly in the data directory
+        let legacy_database = root.join(DATABASE_FILE);
+        if registry.active == DEFAULT_PROFILE
+            && legacy_database.exists()
+            && !dir.join(DATABASE_FILE).exists()
+        {
+            fs::rename(&legacy_database, dir.join(DATABASE_FILE))?;
+        }
+
+        let database = Database::open(&dir.join(DATABASE_FILE))?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            cache_root: cache_root.to_path_buf(),
+            registry: Mutex::new(registry),
+            database,
+        })
+    }
+
+    /// Handle to the active profile's database
+    pub fn database(&self) -> &Database {
+        &self.database
+    }
+
+    pub fn active(&self) -> Profile {
+        let registry = self.lock();
+        registry
+            .profiles
+            .iter()
+            .find(|p| p.id == registry.active)
+            .cloned()
+            .expect("active profile is always registered")
+    }
+
+    pub fn active_id(&self) -> String {
+        self.lock().active.clone()
+    }
+
+    pub fn list(&self) -> Vec<Profile> {
+        self.lock().profiles.clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<Profile, ProfileError> {
+        self.lock()
+            .profiles
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+            .ok_or_else(|| ProfileError::NotFound(id.to_string()))
+    }
+
+    /// Data directory of a profile
+    pub fn dir(&self, id: &str) -> PathBuf {
+        profile_dir(&self.root, id)
+    }
+
+    /// Cache directory of a profile
+    pub fn cache_dir(&self, id: &str) -> PathBuf {
+        profile_dir(&self.cache_root, id)
+    }
+
+    /// Cache directory of the active profile
+    pub fn active_cache_dir(&self) -> PathBuf {
+        self.cache_dir(&self.lock().active)
+    }
+
+    /// Open a separate handle to a profile's database, e.g. for a profile
+    /// connected in the background
+    pub fn open_database(&self, id: &str) -> Result<Database, ProfileError> {
+        self.get(id)?;
+        let dir = self.dir(id);
+        fs::create_dir_all(&dir)?;
+        Ok(Database::open(&dir.join(DATABASE_FILE))?)
+    }
+
+    pub fn create(&self, name: &str) -> Result<Profile, ProfileError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ProfileError::EmptyName);
+        }
+
+        let profile = Profile {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            created_at: Utc::now(),
+            stay_connected: false,
+        };
+        fs::create_dir_all(self.dir(&profile.id))?;
+
+        let mut registry = self.lock();
+        registry.profiles.push(profile.clone());
+        self.save(&registry)?;
+
+        Ok(profile)
+    }
+
+    pub fn rename(&self, id: &str, name: &str) -> Result<Profile, ProfileError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ProfileError::EmptyName);
+        }
+
+        self.update(id, |profile| profile.name = name.to_string())
+    }
+
+    pub fn set_stay_connected(
+        &self,
+        id: &str,
+        stay_connected: bool,
+    ) -> Result<Profile, ProfileError> {
+        self.update(id, |profile| profile.stay_connected = stay_connected)
+    }
+
+    /// Remove a profile and its data and cache directories. Keychain entries are the
+    /// caller's responsibility since they're keyed by instance.
+    pub fn delete(&self, id: &str) -> Result<(), ProfileError> {
+        let mut registry = self.lock();
+        if registry.active == id {
+            return Err(ProfileError::Active);
+        }
+
+        let index = registry
+            .profiles
+            .iter()
+            .position(|p| p.id == id)
+            .ok_or_else(|| ProfileError::NotFound(id.to_string()))?;
+        registry.profiles.remove(index);
+        self.save(&registry)?;
+        drop(registry);
+
+        for dir in [self.dir(id), self.cache_dir(id)] {
+            if dir.exists() {
+                fs::remove_dir_all(dir)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Make `id` the active profile, pointing the shared database handle at its database
+    pub fn switch(&self, id: &str) -> Result<Profile, ProfileError> {
+        let mut registry = self.lock();
+        let profile = registry
+            .profiles
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+            .ok_or_else(|| ProfileError::NotFound(id.to_string()))?;
+
+        let dir = self.dir(id);
+        fs::create_dir_all(&dir)?;
+        self.database.reopen(&dir.join(DATABASE_FILE))?;
+
+        registry.active = profile.id.clone();
+        self.save(&registry)?;
+
+        Ok(profile)
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut Profile)) -> Result<Profile, ProfileError> {
+        let mut registry = self.lock();
+        let profile = registry
+            .profiles
+            .iter_mut()
+            .find(|p| p.id == id)
+            .ok_or_else(|| ProfileError::NotFound(id.to_string()))?;
+        f(profile);
+        let profile = profile.clone();
+        self.save(&registry)?;
+
+        Ok(profile)
+    }
+
+    fn save(&self, registry: &Registry) -> Result<(), ProfileError> {
+        let path = self.root.join(REGISTRY_FILE);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(registry)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn profile_dir(root: &Path, id: &str) -> PathBuf {
+    root.join("profiles").join(id)
+}
@@ -0,0 +1,268 @@
+//! Voice relay selection
+//!
+//! Calls that can't connect directly go through a relay, and which one
+//! matters: a relay on another continent costs a couple of hundred
+//! milliseconds each way. The relays to choose from are configured per
+//! region in [`RELAYS_SETTING`]. Probing times a few STUN binding requests
+//! to each over UDP, the path call audio takes (ICMP would need raw
+//! sockets), and ranks them by median round trip, then loss. The user can
+//! pin a region, for instance where a relay outside their country is
+//! blocked or mustn't be used, and then only that region's relays are
+//! picked.
+//!
+//! Each result is emitted as "relay-probe" as soon as that relay is done,
+//! so the UI can fill in per-region ping while slower relays are still
+//! being timed. The call transport in the frontend takes the last
+//! selection's relay when it connects.
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::db::Database;
+use crate::diagnostics::stun;
+use crate::settings;
+
+/// Setting holding the configured [`Relay`]s
+pub const RELAYS_SETTING: &str = "voice.relays";
+/// Setting holding the pinned region, if any
+pub const PINNED_REGION_SETTING: &str = "voice.relay_region";
+
+/// Most relays that can be configured
+pub const MAX_RELAYS: usize = 32;
+
+/// Round trips timed per relay
+const PROBES: u32 = 5;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Pause between a relay's probes, so one lost burst doesn't fail them all
+const PROBE_SPACING: Duration = Duration::from_millis(50);
+
+#[derive(Debug, thiserror::Error)]
+pub enum RelayError {
+    #[error("at most {MAX_RELAYS} relays can be configured")]
+    TooMany,
+    #[error("a relay needs a region and a host:port address")]
+    Incomplete,
+    #[error("no relay is configured in region {0}")]
+    UnknownRegion(String),
+    #[error("{0}")]
+    Task(#[from] tauri::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Relay {
+    /// e.g. "eu-west"
+    pub region: String,
+    /// `host:port` of the relay's STUN/TURN listener
+    pub address: String,
+}
+
+/// How a relay did, and the payload of "relay-probe"
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    #[serde(flatten)]
+    pub relay: Relay,
+    /// Median round trip of the probes that came back
+    pub rtt_ms: Option<f64>,
+    /// Share of probes that didn't come back, from 0 to 1
+    pub loss: f32,
+    /// Why none came back, if none did
+    pub error: Option<String>,
+}
+
+impl ProbeResult {
+    fn reachable(&self) -> bool {
+        self.rtt_ms.is_some()
+    }
+}
+
+/// Result of probing, best first
+#[derive(Debug, Clone, Serialize)]
+pub struct RelaySelection {
+    pub results: Vec<ProbeResult>,
+    pub pinned_region: Option<String>,
+    /// The best reachable relay, in the pinned region if there is one
+    pub selected: Option<ProbeResult>,
+    pub probed_at: DateTime<Utc>,
+}
+
+/// The last probe, kept until the relays change
+#[derive(Default)]
+pub struct Relays {
+    last: Mutex<Option<RelaySelection>>,
+}
+
+impl Relays {
+    fn lock(&self) -> MutexGuard<'_, Option<RelaySelection>> {
+        self.last.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub fn relays(db: &Database) -> Result<Vec<Relay>, RelayError> {
+    let stored = db.with(|conn| settings::get_value(conn, RELAYS_SETTING))?;
+    Ok(stored
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| log::warn!("Ignoring malformed relay list: {}", e))
+                .ok()
+        })
+        .unwrap_or_default())
+}
+
+/// Replace the configured relays; the last probe is dropped, since it was
+/// of other relays
+pub fn set_relays(app: &AppHandle, relays: Vec<Relay>) -> Result<(), RelayError> {
+    if relays.len() > MAX_RELAYS {
+        return Err(RelayError::TooMany);
+    }
+    if relays
+        .iter()
+        .any(|r| r.region.trim().is_empty() || !r.address.contains(':'))
+    {
+        return Err(RelayError::Incomplete);
+    }
+    let value = serde_json::to_value(&relays).expect("relays serialize");
+    app.state::<Database>()
+        .with(|conn| settings::set(conn, RELAYS_SETTING, &value))?;
+    *app.state::<Relays>().lock() = None;
+    Ok(())
+}
+
+pub fn pinned_region(db: &Database) -> Result<Option<String>, RelayError> {
+    let stored = db.with(|conn| settings::get_value(conn, PINNED_REGION_SETTING))?;
+    Ok(stored.and_then(|v| v.as_str().map(str::to_string)))
+}
+
+/// Only pick relays in `region`, or any region again with `None`. Returns
+/// the last probe with the selection redone.
+pub fn pin_region(
+    app: &AppHandle,
+    region: Option<String>,
+) -> Result<Option<RelaySelection>, RelayError> {
+    let db = app.state::<Database>();
+    if let Some(region) = &region {
+        if !relays(&db)?.iter().any(|r| &r.region == region) {
+            return Err(RelayError::UnknownRegion(region.clone()));
+        }
+    }
+    let value = region
+        .clone()
+        .map_or(serde_json::Value::Null, serde_json::Value::String);
+    db.with(|conn| settings::set(conn, PINNED_REGION_SETTING, &value))?;
+
+    let relays = app.state::<Relays>();
+    let mut last = relays.lock();
+    if let Some(selection) = last.as_mut() {
+        selection.selected = select(&selection.results, region.as_deref());
+        selection.pinned_region = region;
+    }
+    Ok(last.clone())
+}
+
+/// The last probe and its pick, if there's been one
+pub fn last(app: &AppHandle) -> Option<RelaySelection> {
+    app.state::<Relays>().lock().clone()
+}
+
+/// Time every configured relay and pick one
+/// Emits "relay-probe" for each relay as it finishes
+pub async fn probe(app: &AppHandle) -> Result<RelaySelection, RelayError> {
+    let (configured, pinned) = {
+        let db = app.state::<Database>();
+        (relays(&db)?, pinned_region(&db)?)
+    };
+
+    let tasks: Vec<_> = configured
+        .into_iter()
+        .map(|relay| {
+            let app = app.clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                let result = probe_relay(relay);
+                let _ = app.emit("relay-probe", &result);
+                result
+            })
+        })
+        .collect();
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        results.push(task.await?);
+    }
+
+    results.sort_by(|a, b| {
+        let rank = |r: &ProbeResult| (!r.reachable(), r.rtt_ms.unwrap_or(f64::MAX), r.loss);
+        let (a, b) = (rank(a), rank(b));
+        a.0.cmp(&b.0)
+            .then(a.1.total_cmp(&b.1))
+            .then(a.2.total_cmp(&b.2))
+    });
+    let selection = RelaySelection {
+        selected: select(&results, pinned.as_deref()),
+        results,
+        pinned_region: pinned,
+        probed_at: Utc::now(),
+    };
+    *app.state::<Relays>().lock() = Some(selection.clone());
+    Ok(selection)
+}
+
+/// The first reachable result in `region`, or in any region
+fn select(ranked: &[ProbeResult], region: Option<&str>) -> Option<ProbeResult> {
+    ranked
+        .iter()
+        .filter(|r| r.reachable())
+        .find(|r| region.map_or(true, |region| r.relay.region == region))
+        .cloned()
+}
+
+fn probe_relay(relay: Relay) -> ProbeResult {
+    let unreachable = |relay, error: String| ProbeResult {
+        relay,
+        rtt_ms: None,
+        loss: 1.0,
+        error: Some(error),
+    };
+
+    let server = match relay.address.to_socket_addrs().map(|mut a| a.next()) {
+        Ok(Some(server)) => server,
+        Ok(None) => return unreachable(relay, "the address doesn't resolve".to_string()),
+        Err(e) => return unreachable(relay, e.to_string()),
+    };
+    let local: SocketAddr = if server.is_ipv6() {
+        "[::]:0".parse().expect("valid address")
+    } else {
+        "0.0.0.0:0".parse().expect("valid address")
+    };
+    let socket = match UdpSocket::bind(local) {
+        Ok(socket) => socket,
+        Err(e) => return unreachable(relay, e.to_string()),
+    };
+
+    let mut rtts = Vec::new();
+    let mut last_error = None;
+    for probe in 0..PROBES {
+        if probe > 0 {
+            std::thread::sleep(PROBE_SPACING);
+        }
+        match stun::round_trip(&socket, server, PROBE_TIMEOUT) {
+            Ok(rtt) => rtts.push(rtt.as_secs_f64() * 1000.0),
+            Err(e) => last_error = Some(e.to_string()),
+        }
+    }
+    if rtts.is_empty() {
+        return unreachable(relay, last_error.unwrap_or_default());
+    }
+
+    rtts.sort_by(f64::total_cmp);
+    ProbeResult {
+        relay,
+        rtt_ms: Some(rtts[rtts.len() / 2]),
+        loss: 1.0 - rtts.len() as f32 / PROBES as f32,
+        error: None,
+    }
+}
@@ -0,0 +1,118 @@
+//! The XTEST extension fakes input as if from the core devices. X keycodes
+//! are evdev codes plus 8, and buttons 4 to 7 are the scroll wheel.
+//! Wayland compositors don't let clients do this, and faking it through
+//! XWayland would only reach X clients, so it's refused there.
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{
+    Window, BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, KEY_PRESS_EVENT, KEY_RELEASE_EVENT,
+    MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::{self, ConnectionExt};
+use x11rb::rust_connection::RustConnection;
+use x11rb::CURRENT_TIME;
+
+use super::{MouseButton, RemoteControlError};
+use crate::keyboard_layout::Position;
+
+/// Offset between evdev codes and X keycodes
+const X_KEYCODE_OFFSET: u8 = 8;
+
+const SCROLL_UP: u8 = 4;
+const SCROLL_DOWN: u8 = 5;
+const SCROLL_LEFT: u8 = 6;
+const SCROLL_RIGHT: u8 = 7;
+
+pub(super) struct Injector {
+    conn: RustConnection,
+    root: Window,
+}
+
+impl Injector {
+    pub(super) fn check() -> Result<(), RemoteControlError> {
+        if std::env::var("XDG_SESSION_TYPE").is_ok_and(|t| t == "wayland") {
+            return Err(RemoteControlError::Unsupported(
+                "Wayland doesn't allow it".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub(super) fn new() -> Result<Self, RemoteControlError> {
+        Self::check()?;
+        let (conn, screen) = x11rb::connect(None).map_err(unsupported)?;
+        if conn
+            .extension_information(xtest::X11_EXTENSION_NAME)
+            .map_err(unsupported)?
+            .is_none()
+        {
+            return Err(RemoteControlError::Unsupported(
+                "the X server has no XTEST extension".to_string(),
+            ));
+        }
+        let root = conn.setup().roots[screen].root;
+        Ok(Self { conn, root })
+    }
+
+    pub(super) fn move_to(&mut self, x: f64, y: f64) -> Result<(), RemoteControlError> {
+        self.fake(MOTION_NOTIFY_EVENT, 0, x.round() as i16, y.round() as i16)
+    }
+
+    pub(super) fn button(
+        &mut self,
+        button: MouseButton,
+        pressed: bool,
+    ) -> Result<(), RemoteControlError> {
+        let detail = match button {
+            MouseButton::Left => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Right => 3,
+        };
+        let kind = if pressed {
+            BUTTON_PRESS_EVENT
+        } else {
+            BUTTON_RELEASE_EVENT
+        };
+        self.fake(kind, detail, 0, 0)
+    }
+
+    pub(super) fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), RemoteControlError> {
+        let vertical = if dy < 0 { SCROLL_UP } else { SCROLL_DOWN };
+        let horizontal = if dx < 0 { SCROLL_LEFT } else { SCROLL_RIGHT };
+        for (button, clicks) in [
+            (vertical, dy.unsigned_abs()),
+            (horizontal, dx.unsigned_abs()),
+        ] {
+            for _ in 0..clicks {
+                self.fake(BUTTON_PRESS_EVENT, button, 0, 0)?;
+                self.fake(BUTTON_RELEASE_EVENT, button, 0, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn key(&mut self, key: Position, pressed: bool) -> Result<(), RemoteControlError> {
+        let kind = if pressed {
+            KEY_PRESS_EVENT
+        } else {
+            KEY_RELEASE_EVENT
+        };
+        self.fake(kind, key.evdev + X_KEYCODE_OFFSET, 0, 0)
+    }
+
+    fn fake(&self, kind: u8, detail: u8, x: i16, y: i16) -> Result<(), RemoteControlError> {
+        self.conn
+            .xtest_fake_input(kind, detail, CURRENT_TIME, self.root, x, y, 0)
+            .map_err(inject)?;
+        self.conn.flush().map_err(inject)?;
+        Ok(())
+    }
+}
+
+fn unsupported(e: impl std::fmt::Display) -> RemoteControlError {
+    RemoteControlError::Unsupported(e.to_string())
+}
+
+fn inject(e: impl std::fmt::Display) -> RemoteControlError {
+    RemoteControlError::Inject(e.to_string())
+}
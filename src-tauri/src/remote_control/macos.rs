@@ -0,0 +1,158 @@
+//! Quartz events posted at the HID level reach whatever has focus. macOS
+//! drops them unless the user has given the app the Accessibility
+//! permission, so that's checked first. Event coordinates are global
+//! points; moving with a button down has to be posted as a drag.
+
+use std::ffi::c_void;
+
+use super::{MouseButton, RemoteControlError};
+use crate::keyboard_layout::Position;
+
+type CGEventRef = *mut c_void;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGPoint {
+    x: f64,
+    y: f64,
+}
+
+/// `kCGHIDEventTap`
+const HID_EVENT_TAP: u32 = 0;
+/// `kCGScrollEventUnitLine`
+const SCROLL_UNIT_LINE: u32 = 1;
+
+// `CGEventType`s
+const LEFT_MOUSE_DOWN: u32 = 1;
+const LEFT_MOUSE_UP: u32 = 2;
+const RIGHT_MOUSE_DOWN: u32 = 3;
+const RIGHT_MOUSE_UP: u32 = 4;
+const MOUSE_MOVED: u32 = 5;
+const LEFT_MOUSE_DRAGGED: u32 = 6;
+const RIGHT_MOUSE_DRAGGED: u32 = 7;
+const OTHER_MOUSE_DOWN: u32 = 25;
+const OTHER_MOUSE_UP: u32 = 26;
+const OTHER_MOUSE_DRAGGED: u32 = 27;
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> u8;
+    fn CGEventCreateMouseEvent(
+        source: *const c_void,
+        kind: u32,
+        position: CGPoint,
+        button: u32,
+    ) -> CGEventRef;
+    fn CGEventCreateKeyboardEvent(source: *const c_void, keycode: u16, down: bool) -> CGEventRef;
+    fn CGEventCreateScrollWheelEvent2(
+        source: *const c_void,
+        units: u32,
+        wheel_count: u32,
+        wheel1: i32,
+        wheel2: i32,
+        wheel3: i32,
+    ) -> CGEventRef;
+    fn CGEventPost(tap: u32, event: CGEventRef);
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRelease(object: *const c_void);
+}
+
+pub(super) struct Injector {
+    position: CGPoint,
+    /// Button held, for posting moves as drags
+    held: Option<MouseButton>,
+}
+
+impl Injector {
+    pub(super) fn check() -> Result<(), RemoteControlError> {
+        // SAFETY: no preconditions
+        if unsafe { AXIsProcessTrusted() } == 0 {
+            return Err(RemoteControlError::NotPermitted);
+        }
+        Ok(())
+    }
+
+    pub(super) fn new() -> Result<Self, RemoteControlError> {
+        Self::check()?;
+        Ok(Self {
+            position: CGPoint { x: 0.0, y: 0.0 },
+            held: None,
+        })
+    }
+
+    pub(super) fn move_to(&mut self, x: f64, y: f64) -> Result<(), RemoteControlError> {
+        self.position = CGPoint { x, y };
+        let kind = match self.held {
+            Some(MouseButton::Left) => LEFT_MOUSE_DRAGGED,
+            Some(MouseButton::Right) => RIGHT_MOUSE_DRAGGED,
+            Some(MouseButton::Middle) => OTHER_MOUSE_DRAGGED,
+            None => MOUSE_MOVED,
+        };
+        self.mouse(kind, self.held.unwrap_or(MouseButton::Left))
+    }
+
+    pub(super) fn button(
+        &mut self,
+        button: MouseButton,
+        pressed: bool,
+    ) -> Result<(), RemoteControlError> {
+        let kind = match (button, pressed) {
+            (MouseButton::Left, true) => LEFT_MOUSE_DOWN,
+            (MouseButton::Left, false) => LEFT_MOUSE_UP,
+            (MouseButton::Right, true) => RIGHT_MOUSE_DOWN,
+            (MouseButton::Right, false) => RIGHT_MOUSE_UP,
+            (MouseButton::Middle, true) => OTHER_MOUSE_DOWN,
+            (MouseButton::Middle, false) => OTHER_MOUSE_UP,
+        };
+        if pressed {
+            self.held = Some(button);
+        } else if self.held == Some(button) {
+            self.held = None;
+        }
+        self.mouse(kind, button)
+    }
+
+    pub(super) fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), RemoteControlError> {
+        // SAFETY: a null source is allowed; the event is released by `post`.
+        // Positive wheel values scroll up and left.
+        let event = unsafe {
+            CGEventCreateScrollWheelEvent2(std::ptr::null(), SCROLL_UNIT_LINE, 2, -dy, -dx, 0)
+        };
+        post(event)
+    }
+
+    pub(super) fn key(&mut self, key: Position, pressed: bool) -> Result<(), RemoteControlError> {
+        // SAFETY: a null source is allowed; the event is released by `post`
+        let event = unsafe { CGEventCreateKeyboardEvent(std::ptr::null(), key.mac, pressed) };
+        post(event)
+    }
+
+    fn mouse(&self, kind: u32, button: MouseButton) -> Result<(), RemoteControlError> {
+        let button = match button {
+            MouseButton::Left => 0,
+            MouseButton::Right => 1,
+            MouseButton::Middle => 2,
+        };
+        // SAFETY: a null source is allowed; the event is released by `post`
+        let event =
+            unsafe { CGEventCreateMouseEvent(std::ptr::null(), kind, self.position, button) };
+        post(event)
+    }
+}
+
+fn post(event: CGEventRef) -> Result<(), RemoteControlError> {
+    if event.is_null() {
+        return Err(RemoteControlError::Inject(
+            "couldn't create the event".to_string(),
+        ));
+    }
+    // SAFETY: `event` was created for us, and is released once posted
+    unsafe {
+        CGEventPost(HID_EVENT_TAP, event);
+        CFRelease(event);
+    }
+    Ok(())
+}
@@ -0,0 +1,566 @@
+//! Remote assistance
+//!
+//! While sharing their screen the user can let the person watching take
+//! over the mouse and keyboard, to show them where a setting is or fix
+//! something for them. It's off unless [`ENABLED_SETTING`] is on, which
+//! only `set_remote_control_enabled` can turn on after the user confirms
+//! in a native dialog, and every session needs its own consent:
+//! 1. the peer's request arrives over the call's encrypted data channel,
+//!    which the webview owns; it reports it with `request_remote_control`,
+//!    which raises the window and emits "remote-control-state" as pending
+//! 2. the user answers in the app, and `grant_remote_control` for that
+//!    pending session asks once more in a native dialog naming the peer
+//!    and the monitor, so the webview can't answer for them. Only a yes
+//!    there starts it, and nothing is remembered for next time.
+//! 3. events from the data channel are handed over with
+//!    `inject_remote_input` and injected into the shared monitor, in
+//!    coordinates relative to it so the peer's view size doesn't matter
+//!
+//! However the session ends, keys and buttons the peer left held are
+//! released. [`KILL_SHORTCUT_SETTING`] is registered for the whole
+//! session and ends it from anywhere, whatever the peer is doing with the
+//! keyboard; a session doesn't start if the shortcut can't be registered.
+//! The tray menu offers stopping too (see [`crate::launcher`]), and every
+//! change is emitted as "remote-control-state" for the frontend to show.
+//!
+//! Injection goes through `SendInput` on Windows, Quartz events on macOS
+//! (which need the Accessibility permission) and XTEST on X11. Wayland
+//! doesn't let applications inject input, so it isn't offered there.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+use linux::Injector;
+#[cfg(target_os = "macos")]
+use macos::Injector;
+#[cfg(windows)]
+use windows::Injector;
+
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+use crate::db::Database;
+use crate::keyboard_layout::{self, Position};
+use crate::launcher::Launcher;
+use crate::{deep_link, launcher, settings};
+
+/// Setting that allows remote assistance at all; off unless set to `true`.
+/// It stays on this machine and only [`set_enabled`] writes it
+pub const ENABLED_SETTING: &str = "local.remote_control.enabled";
+
+/// Setting holding the shortcut that ends a session
+pub const KILL_SHORTCUT_SETTING: &str = "shortcuts.remote_control_kill";
+const DEFAULT_KILL_SHORTCUT: &str = "Control+Alt+Shift+Escape";
+
+/// Most events taken in one `inject_remote_input`
+pub const MAX_EVENTS: usize = 256;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteControlError {
+    #[error("remote assistance is turned off in settings")]
+    Disabled,
+    #[error("remote control isn't supported here: {0}")]
+    Unsupported(String),
+    #[cfg(target_os = "macos")]
+    #[error("Redoubt needs the Accessibility permission to control this Mac")]
+    NotPermitted,
+    #[error("remote control session {0} isn't waiting for consent")]
+    NotRequested(String),
+    #[error("remote control session {0} wasn't allowed")]
+    Declined(String),
+    #[error("remote control session {0} isn't active")]
+    NotActive(String),
+    #[error("another remote control session is active")]
+    Busy,
+    #[error("no monitor named {0}")]
+    UnknownMonitor(String),
+    #[error("at most {MAX_EVENTS} events can be sent at once")]
+    TooManyEvents,
+    #[error("couldn't register the stop shortcut {0}: {1}")]
+    KillShortcut(String, String),
+    #[error("failed to inject input: {0}")]
+    Inject(String),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+/// An event from the peer
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RemoteInput {
+    /// `x` and `y` from 0 to 1 across the shared monitor
+    MouseMove {
+        x: f64,
+        y: f64,
+    },
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+    },
+    /// In lines; positive is down and right
+    Scroll {
+        dx: i32,
+        dy: i32,
+    },
+    /// `code` is the W3C `KeyboardEvent.code`, so it's the same key
+    /// whatever layout either side has
+    Key {
+        code: String,
+        pressed: bool,
+    },
+}
+
+/// Why a session ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndReason {
+    /// Stopped from the app, the tray or by the frontend
+    Stopped,
+    KillShortcut,
+    /// The user said no
+    Denied,
+    /// Injecting failed
+    Failed,
+}
+
+/// The monitor being controlled, in the units the injector uses
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Bounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Payload of "remote-control-state", and the result of
+/// `get_remote_control_state`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RemoteControlState {
+    /// The peer is in control
+    pub active: bool,
+    /// Waiting for the user to grant or deny
+    pub pending: bool,
+    pub session_id: Option<String>,
+    /// Who asked, as the frontend named them
+    pub peer: Option<String>,
+    pub monitor: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    /// Set when a session has just ended
+    pub ended: Option<EndReason>,
+    /// How to end it, for showing while it's active
+    pub kill_shortcut: Option<String>,
+}
+
+struct Request {
+    session_id: String,
+    peer: String,
+}
+
+struct Session {
+    session_id: String,
+    peer: String,
+    monitor: Option<String>,
+    bounds: Bounds,
+    started_at: DateTime<Utc>,
+    kill_shortcut: Shortcut,
+    kill_label: String,
+    injector: Injector,
+    /// Held down by the peer, released when the session ends
+    keys: HashSet<Position>,
+    buttons: HashSet<MouseButton>,
+}
+
+#[derive(Default)]
+struct Inner {
+    request: Option<Request>,
+    session: Option<Session>,
+}
+
+#[derive(Default)]
+pub struct RemoteControl {
+    inner: Mutex<Inner>,
+}
+
+impl RemoteControl {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Allow or disallow remote assistance. Allowing it asks the user to confirm
+/// in a native dialog first; returns whether it's now allowed
+/// Blocks until the dialog is answered, so don't call it on the main thread
+pub fn set_enabled(app: &AppHandle, enabled: bool) -> Result<bool, RemoteControlError> {
+    let db = app.state::<Database>();
+    if !enabled {
+        stop(app, EndReason::Stopped);
+        db.with(|conn| settings::set(conn, ENABLED_SETTING, &false))?;
+        return Ok(false);
+    }
+
+    let confirmed = app
+        .dialog()
+        .message(
+            "People you share your screen with will be able to ask to control \
+             your mouse and keyboard. You'll be asked every time.",
+        )
+        .title("Allow remote assistance?")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Allow".to_string(),
+            "Cancel".to_string(),
+        ))
+        .blocking_show();
+    if confirmed {
+        db.with(|conn| settings::set(conn, ENABLED_SETTING, &true))?;
+    }
+    Ok(confirmed)
+}
+
+pub fn state(app: &AppHandle) -> RemoteControlState {
+    let remote = app.state::<RemoteControl>();
+    let inner = remote.lock();
+    snapshot(&inner, None)
+}
+
+/// Record a peer's request and bring the window up for the user to answer
+/// Emits "remote-control-state"
+pub fn request(
+    app: &AppHandle,
+    session_id: &str,
+    peer: &str,
+) -> Result<RemoteControlState, RemoteControlError> {
+    if !enabled(&app.state::<Database>())? {
+        return Err(RemoteControlError::Disabled);
+    }
+    Injector::check()?;
+    let state = {
+        let remote = app.state::<RemoteControl>();
+        let mut inner = remote.lock();
+        if inner.session.is_some() {
+            return Err(RemoteControlError::Busy);
+        }
+        inner.request = Some(Request {
+            session_id: session_id.to_string(),
+            peer: peer.to_string(),
+        });
+        snapshot(&inner, None)
+    };
+    log::info!("{} asked to control this computer", peer);
+    deep_link::focus_main_window(app);
+    let _ = app.emit("remote-control-state", &state);
+    Ok(state)
+}
+
+/// Turn down a pending request
+/// Emits "remote-control-state"
+pub fn deny(app: &AppHandle, session_id: &str) -> Result<(), RemoteControlError> {
+    let state = {
+        let remote = app.state::<RemoteControl>();
+        let mut inner = remote.lock();
+        if inner.request.as_ref().map(|r| r.session_id.as_str()) != Some(session_id) {
+            return Err(RemoteControlError::NotRequested(session_id.to_string()));
+        }
+        inner.request = None;
+        snapshot(&inner, Some(EndReason::Denied))
+    };
+    let _ = app.emit("remote-control-state", &state);
+    Ok(())
+}
+
+/// Hand control to the peer of a pending request, on `monitor` or the
+/// primary one, once the user has said yes in a native dialog
+/// Blocks until the dialog is answered, so don't call it on the main thread
+/// Emits "remote-control-state"
+pub fn grant(
+    app: &AppHandle,
+    session_id: &str,
+    monitor: Option<String>,
+) -> Result<RemoteControlState, RemoteControlError> {
+    if !enabled(&app.state::<Database>())? {
+        return Err(RemoteControlError::Disabled);
+    }
+    let remote = app.state::<RemoteControl>();
+    let peer = {
+        let inner = remote.lock();
+        match &inner.request {
+            Some(request) if request.session_id == session_id => request.peer.clone(),
+            _ => return Err(RemoteControlError::NotRequested(session_id.to_string())),
+        }
+    };
+    let bounds = bounds(app, monitor.as_deref())?;
+
+    let allowed = app
+        .dialog()
+        .message(format!(
+            "{} is asking to control your mouse and keyboard on {}.",
+            peer,
+            monitor.as_deref().unwrap_or("your primary monitor")
+        ))
+        .title("Allow remote control?")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Allow".to_string(),
+            "Deny".to_string(),
+        ))
+        .blocking_show();
+    if !allowed {
+        let _ = deny(app, session_id);
+        return Err(RemoteControlError::Declined(session_id.to_string()));
+    }
+
+    let injector = Injector::new()?;
+    let (kill_shortcut, kill_label) = register_kill_shortcut(app)?;
+
+    let state = {
+        let mut inner = remote.lock();
+        // The request may have been stopped or replaced while the dialog
+        // was up
+        let still_requested = inner.session.is_none()
+            && inner.request.as_ref().map(|r| r.session_id.as_str()) == Some(session_id);
+        if !still_requested {
+            drop(inner);
+            if let Err(e) = app.global_shortcut().unregister(kill_shortcut) {
+                log::warn!(
+                    "Failed to unregister the remote control stop shortcut: {}",
+                    e
+                );
+            }
+            return Err(RemoteControlError::NotRequested(session_id.to_string()));
+        }
+        inner.request = None;
+        inner.session = Some(Session {
+            session_id: session_id.to_string(),
+            peer: peer.clone(),
+            monitor,
+            bounds,
+            started_at: Utc::now(),
+            kill_shortcut,
+            kill_label,
+            injector,
+            keys: HashSet::new(),
+            buttons: HashSet::new(),
+        });
+        snapshot(&inner, None)
+    };
+    log::warn!("{} is controlling this computer", peer);
+    app.state::<Launcher>().set_remote_controller(Some(peer));
+    launcher::refresh(app);
+    let _ = app.emit("remote-control-state", &state);
+    Ok(state)
+}
+
+/// Inject events from the peer; returns how many were injected. A failure
+/// ends the session.
+pub fn inject(
+    app: &AppHandle,
+    session_id: &str,
+    events: Vec<RemoteInput>,
+) -> Result<usize, RemoteControlError> {
+    if events.len() > MAX_EVENTS {
+        return Err(RemoteControlError::TooManyEvents);
+    }
+    let result = {
+        let remote = app.state::<RemoteControl>();
+        let mut inner = remote.lock();
+        let session = match &mut inner.session {
+            Some(session) if session.session_id == session_id => session,
+            _ => return Err(RemoteControlError::NotActive(session_id.to_string())),
+        };
+        events
+            .iter()
+            .try_for_each(|event| apply(session, event))
+            .map(|()| events.len())
+    };
+    if let Err(e) = &result {
+        log::error!("Ending remote control: {}", e);
+        stop(app, EndReason::Failed);
+    }
+    result
+}
+
+/// End the session or turn down the pending request, if there is one
+/// Emits "remote-control-state"
+pub fn stop(app: &AppHandle, reason: EndReason) {
+    let (session, state) = {
+        let remote = app.state::<RemoteControl>();
+        let mut inner = remote.lock();
+        let session = inner.session.take();
+        let request = inner.request.take();
+        if session.is_none() && request.is_none() {
+            return;
+        }
+        (session, snapshot(&inner, Some(reason)))
+    };
+    if let Some(mut session) = session {
+        release(&mut session);
+        if let Err(e) = app.global_shortcut().unregister(session.kill_shortcut) {
+            log::warn!(
+                "Failed to unregister the remote control stop shortcut: {}",
+                e
+            );
+        }
+        log::warn!(
+            "{} stopped controlling this computer ({:?})",
+            session.peer,
+            reason
+        );
+        app.state::<Launcher>().set_remote_controller(None);
+        launcher::refresh(app);
+    }
+    let _ = app.emit("remote-control-state", &state);
+}
+
+fn apply(session: &mut Session, event: &RemoteInput) -> Result<(), RemoteControlError> {
+    match event {
+        RemoteInput::MouseMove { x, y } => {
+            let bounds = session.bounds;
+            // NaN clamps to NaN, so it's sent to the corner instead
+            let fraction = |v: f64| if v.is_nan() { 0.0 } else { v.clamp(0.0, 1.0) };
+            session.injector.move_to(
+                bounds.x + fraction(*x) * (bounds.width - 1.0).max(0.0),
+                bounds.y + fraction(*y) * (bounds.height - 1.0).max(0.0),
+            )
+        }
+        RemoteInput::MouseButton { button, pressed } => {
+            if *pressed {
+                session.buttons.insert(*button);
+            } else {
+                session.buttons.remove(button);
+            }
+            session.injector.button(*button, *pressed)
+        }
+        RemoteInput::Scroll { dx, dy } => session
+            .injector
+            .scroll((*dx).clamp(-100, 100), (*dy).clamp(-100, 100)),
+        RemoteInput::Key { code, pressed } => {
+            // Unknown keys are skipped rather than ending the session over
+            // a key this side doesn't have
+            let Some(position) = keyboard_layout::position(code) else {
+                log::debug!("Ignoring remote key {}", code);
+                return Ok(());
+            };
+            if *pressed {
+                session.keys.insert(position);
+            } else {
+                session.keys.remove(&position);
+            }
+            session.injector.key(position, *pressed)
+        }
+    }
+}
+
+/// Let go of everything the peer held down
+fn release(session: &mut Session) {
+    for position in std::mem::take(&mut session.keys) {
+        if let Err(e) = session.injector.key(position, false) {
+            log::warn!("Failed to release a remote key: {}", e);
+        }
+    }
+    for button in std::mem::take(&mut session.buttons) {
+        if let Err(e) = session.injector.button(button, false) {
+            log::warn!("Failed to release a remote mouse button: {}", e);
+        }
+    }
+}
+
+fn snapshot(inner: &Inner, ended: Option<EndReason>) -> RemoteControlState {
+    if let Some(session) = &inner.session {
+        return RemoteControlState {
+            active: true,
+            pending: false,
+            session_id: Some(session.session_id.clone()),
+            peer: Some(session.peer.clone()),
+            monitor: session.monitor.clone(),
+            started_at: Some(session.started_at),
+            ended: None,
+            kill_shortcut: Some(session.kill_label.clone()),
+        };
+    }
+    match &inner.request {
+        Some(request) => RemoteControlState {
+            pending: true,
+            session_id: Some(request.session_id.clone()),
+            peer: Some(request.peer.clone()),
+            ..RemoteControlState::default()
+        },
+        None => RemoteControlState {
+            ended,
+            ..RemoteControlState::default()
+        },
+    }
+}
+
+fn register_kill_shortcut(app: &AppHandle) -> Result<(Shortcut, String), RemoteControlError> {
+    let configured = app
+        .state::<Database>()
+        .with(|conn| settings::get_value(conn, KILL_SHORTCUT_SETTING))?
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_else(|| DEFAULT_KILL_SHORTCUT.to_string());
+    let failed = |e: String| RemoteControlError::KillShortcut(configured.clone(), e);
+    let normalized =
+        keyboard_layout::normalize(app, &configured).map_err(|e| failed(e.to_string()))?;
+    let shortcut: Shortcut = normalized.parse().map_err(|e| failed(format!("{}", e)))?;
+    app.global_shortcut()
+        .on_shortcut(shortcut, |app, _shortcut, event| {
+            if event.state() == ShortcutState::Pressed {
+                stop(app, EndReason::KillShortcut);
+            }
+        })
+        .map_err(|e| failed(e.to_string()))?;
+    Ok((shortcut, keyboard_layout::render(app, &normalized)))
+}
+
+fn bounds(app: &AppHandle, name: Option<&str>) -> Result<Bounds, RemoteControlError> {
+    let unavailable = |e: tauri::Error| RemoteControlError::Unsupported(e.to_string());
+    let monitor = match name {
+        Some(name) => app
+            .available_monitors()
+            .map_err(unavailable)?
+            .into_iter()
+            .find(|m| m.name().map(String::as_str) == Some(name))
+            .ok_or_else(|| RemoteControlError::UnknownMonitor(name.to_string()))?,
+        None => app
+            .primary_monitor()
+            .map_err(unavailable)?
+            .ok_or_else(|| RemoteControlError::Unsupported("no monitor".to_string()))?,
+    };
+    let (position, size) = (monitor.position(), monitor.size());
+    // Quartz events are in points, the others in pixels
+    #[cfg(target_os = "macos")]
+    let scale = monitor.scale_factor();
+    #[cfg(not(target_os = "macos"))]
+    let scale = 1.0;
+    Ok(Bounds {
+        x: position.x as f64 / scale,
+        y: position.y as f64 / scale,
+        width: size.width as f64 / scale,
+        height: size.height as f64 / scale,
+    })
+}
+
+fn enabled(db: &Database) -> rusqlite::Result<bool> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, ENABLED_SETTING))?
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false))
+}
@@ -0,0 +1,123 @@
+//! `SendInput` injects into whatever has focus, as if from the hardware.
+//! Keys are sent as scancodes so the local layout turns them into the same
+//! characters a local press would. Windows won't inject into elevated
+//! windows or the secure desktop from an unelevated app, which is as it
+//! should be.
+
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT, KEYEVENTF_EXTENDEDKEY,
+    KEYEVENTF_KEYUP, KEYEVENTF_SCANCODE, MOUSEEVENTF_HWHEEL, MOUSEEVENTF_LEFTDOWN,
+    MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_RIGHTDOWN,
+    MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_WHEEL, MOUSEINPUT, MOUSE_EVENT_FLAGS,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{SetCursorPos, WHEEL_DELTA};
+
+use super::{MouseButton, RemoteControlError};
+use crate::keyboard_layout::Position;
+
+/// High byte of an extended key's scancode
+const EXTENDED: u16 = 0xe000;
+
+pub(super) struct Injector;
+
+impl Injector {
+    pub(super) fn check() -> Result<(), RemoteControlError> {
+        Ok(())
+    }
+
+    pub(super) fn new() -> Result<Self, RemoteControlError> {
+        Ok(Injector)
+    }
+
+    pub(super) fn move_to(&mut self, x: f64, y: f64) -> Result<(), RemoteControlError> {
+        // SAFETY: no preconditions; the app is per-monitor DPI aware, so
+        // these are physical pixels like the bounds
+        if unsafe { SetCursorPos(x.round() as i32, y.round() as i32) } == 0 {
+            return Err(last_error("SetCursorPos"));
+        }
+        Ok(())
+    }
+
+    pub(super) fn button(
+        &mut self,
+        button: MouseButton,
+        pressed: bool,
+    ) -> Result<(), RemoteControlError> {
+        let flags = match (button, pressed) {
+            (MouseButton::Left, true) => MOUSEEVENTF_LEFTDOWN,
+            (MouseButton::Left, false) => MOUSEEVENTF_LEFTUP,
+            (MouseButton::Right, true) => MOUSEEVENTF_RIGHTDOWN,
+            (MouseButton::Right, false) => MOUSEEVENTF_RIGHTUP,
+            (MouseButton::Middle, true) => MOUSEEVENTF_MIDDLEDOWN,
+            (MouseButton::Middle, false) => MOUSEEVENTF_MIDDLEUP,
+        };
+        send(mouse(flags, 0))
+    }
+
+    pub(super) fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), RemoteControlError> {
+        // Positive wheel data is up, the opposite of `dy`
+        if dy != 0 {
+            send(mouse(MOUSEEVENTF_WHEEL, -dy * WHEEL_DELTA as i32))?;
+        }
+        if dx != 0 {
+            send(mouse(MOUSEEVENTF_HWHEEL, dx * WHEEL_DELTA as i32))?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn key(&mut self, key: Position, pressed: bool) -> Result<(), RemoteControlError> {
+        let mut flags = KEYEVENTF_SCANCODE;
+        if key.scancode & EXTENDED == EXTENDED {
+            flags |= KEYEVENTF_EXTENDEDKEY;
+        }
+        if !pressed {
+            flags |= KEYEVENTF_KEYUP;
+        }
+        send(INPUT {
+            r#type: INPUT_KEYBOARD,
+            Anonymous: INPUT_0 {
+                ki: KEYBDINPUT {
+                    wVk: 0,
+                    wScan: key.scancode & 0xff,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: 0,
+                },
+            },
+        })
+    }
+}
+
+fn mouse(flags: MOUSE_EVENT_FLAGS, data: i32) -> INPUT {
+    INPUT {
+        r#type: INPUT_MOUSE,
+        Anonymous: INPUT_0 {
+            mi: MOUSEINPUT {
+                dx: 0,
+                dy: 0,
+                // Wheel data is signed, in a field declared unsigned
+                mouseData: data as u32,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    }
+}
+
+fn send(input: INPUT) -> Result<(), RemoteControlError> {
+    // SAFETY: one valid INPUT, with its size
+    let sent = unsafe { SendInput(1, &input, std::mem::size_of::<INPUT>() as i32) };
+    if sent != 1 {
+        return Err(last_error("SendInput"));
+    }
+    Ok(())
+}
+
+fn last_error(function: &str) -> RemoteControlError {
+    RemoteControlError::Inject(format!(
+        "{} failed: {}",
+        function,
+        std::io::Error::last_os_error()
+    ))
+}
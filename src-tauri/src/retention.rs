@@ -0,0 +1,333 @@
+//! Message retention
+//!
+//! Conversations can have a disappearing-message policy: cached messages
+//! older than its maximum age are deleted from every profile's database,
+//! along with the link previews and preview images cached for links in
+//! them. A thread purges every [`PURGE_INTERVAL`], so the policy holds with
+//! the frontend closed, and the deletes are made with `secure_delete` and
+//! checkpointed out of the write-ahead log so the text doesn't linger in
+//! the database files.
+//!
+//! Other cached media (thumbnails, animations) is keyed by attachment
+//! rather than message and is left to the cache's own limits.
+
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::db::Database;
+use crate::link_preview;
+use crate::media::thumbnail;
+use crate::profiles::Profiles;
+
+/// How often expired messages are purged
+pub const PURGE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Shortest and longest maximum age a policy can have
+pub const MIN_MAX_AGE: Duration = Duration::from_secs(60);
+pub const MAX_MAX_AGE: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum RetentionError {
+    #[error("a retention policy has to keep messages between a minute and ten years")]
+    MaxAge,
+    #[error("{0}")]
+    Profile(#[from] crate::profiles::ProfileError),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// How long a conversation's messages are kept
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionPolicy {
+    pub instance_id: String,
+    pub channel_id: String,
+    pub max_age_secs: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl RetentionPolicy {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            instance_id: row.get("instance_id")?,
+            channel_id: row.get("channel_id")?,
+            max_age_secs: row.get::<_, i64>("max_age_secs")? as u64,
+            updated_at: row.get("updated_at")?,
+        })
+    }
+
+    fn cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - chrono::Duration::seconds(self.max_age_secs.min(i64::MAX as u64) as i64)
+    }
+}
+
+/// What one purge removed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Purged {
+    pub messages: u64,
+    pub link_previews: u64,
+    pub images: u64,
+}
+
+impl Purged {
+    fn add(&mut self, other: &Purged) {
+        self.messages += other.messages;
+        self.link_previews += other.link_previews;
+        self.images += other.images;
+    }
+}
+
+/// A policy in the active profile and where it stands
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyReport {
+    #[serde(flatten)]
+    pub policy: RetentionPolicy,
+    /// Messages cached for the conversation right now
+    pub cached_messages: u64,
+    /// When the oldest of those expires
+    pub next_expiry: Option<DateTime<Utc>>,
+}
+
+/// Result of `get_retention_report`
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionReport {
+    pub policies: Vec<PolicyReport>,
+    pub last_purge: Option<DateTime<Utc>>,
+    /// Removed by the last purge, across every profile
+    pub last_purged: Purged,
+    /// Removed since the app started
+    pub total_purged: Purged,
+}
+
+#[derive(Default)]
+struct Inner {
+    last_purge: Option<DateTime<Utc>>,
+    last_purged: Purged,
+    total_purged: Purged,
+}
+
+/// Purge history for the report
+#[derive(Default)]
+pub struct Retention {
+    inner: Mutex<Inner>,
+}
+
+impl Retention {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub fn policies(conn: &Connection) -> rusqlite::Result<Vec<RetentionPolicy>> {
+    let mut stmt = conn.prepare_cached("SELECT * FROM retention_policies")?;
+    let rows = stmt.query_map([], RetentionPolicy::from_row)?;
+    rows.collect()
+}
+
+/// Set a conversation's maximum message age, or remove its policy with
+/// `None`. Messages already past the new age go with the next purge.
+pub fn set_policy(
+    app: &AppHandle,
+    instance_id: &str,
+    channel_id: &str,
+    max_age_secs: Option<u64>,
+) -> Result<(), RetentionError> {
+    let db = app.state::<Database>();
+    let Some(max_age_secs) = max_age_secs else {
+        db.with(|conn| {
+            conn.prepare_cached(
+                "DELETE FROM retention_policies WHERE instance_id = ?1 AND channel_id = ?2",
+            )?
+            .execute([instance_id, channel_id])
+        })?;
+        return Ok(());
+    };
+
+    if !(MIN_MAX_AGE.as_secs()..=MAX_MAX_AGE.as_secs()).contains(&max_age_secs) {
+        return Err(RetentionError::MaxAge);
+    }
+    db.with(|conn| {
+        conn.prepare_cached(
+            "INSERT INTO retention_policies (instance_id, channel_id, max_age_secs, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (instance_id, channel_id) DO UPDATE SET
+                max_age_secs = excluded.max_age_secs,
+                updated_at = excluded.updated_at",
+        )?
+        .execute(params![
+            instance_id,
+            channel_id,
+            max_age_secs as i64,
+            Utc::now()
+        ])
+    })?;
+    Ok(())
+}
+
+/// The active profile's policies and what the purges have done
+pub fn report(app: &AppHandle) -> Result<RetentionReport, RetentionError> {
+    let now = Utc::now();
+    let policies = app.state::<Database>().with(|conn| {
+        policies(conn)?
+            .into_iter()
+            .map(|policy| {
+                let (count, oldest): (i64, Option<DateTime<Utc>>) = conn
+                    .prepare_cached(
+                        "SELECT COUNT(*), MIN(created_at) FROM messages
+                         WHERE instance_id = ?1 AND channel_id = ?2",
+                    )?
+                    .query_row([&policy.instance_id, &policy.channel_id], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?;
+                let max_age = policy.max_age_secs.min(i64::MAX as u64) as i64;
+                Ok(PolicyReport {
+                    cached_messages: count as u64,
+                    next_expiry: oldest
+                        .map(|oldest| (oldest + chrono::Duration::seconds(max_age)).max(now)),
+                    policy,
+                })
+            })
+            .collect::<rusqlite::Result<Vec<_>>>()
+    })?;
+
+    let retention = app.state::<Retention>();
+    let inner = retention.lock();
+    Ok(RetentionReport {
+        policies,
+        last_purge: inner.last_purge,
+        last_purged: inner.last_purged.clone(),
+        total_purged: inner.total_purged.clone(),
+    })
+}
+
+/// Start purging expired messages; call once during setup
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("retention".to_string())
+        .spawn(move || loop {
+            let purged = purge_all(&app);
+            {
+                let retention = app.state::<Retention>();
+                let mut inner = retention.lock();
+                inner.last_purge = Some(Utc::now());
+                inner.total_purged.add(&purged);
+                inner.last_purged = purged;
+            }
+            std::thread::sleep(PURGE_INTERVAL);
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to start message retention: {}", e);
+    }
+}
+
+fn purge_all(app: &AppHandle) -> Purged {
+    let profiles = app.state::<Profiles>();
+    let active = profiles.active().id;
+    let mut total = Purged::default();
+    for profile in profiles.list() {
+        let db = if profile.id == active {
+            Ok(app.state::<Database>().inner().clone())
+        } else {
+            profiles.open_database(&profile.id)
+        };
+        let result = db.map_err(RetentionError::from).and_then(|db| {
+            let thumbnail_dir = profiles.cache_dir(&profile.id).join("thumbnails");
+            Ok(db.with(|conn| purge(conn, &thumbnail_dir))?)
+        });
+        match result {
+            Ok(purged) => total.add(&purged),
+            Err(e) => log::error!(
+                "Failed to purge expired messages for profile {}: {}",
+                profile.id,
+                e
+            ),
+        }
+    }
+    if total.messages > 0 {
+        log::info!(
+            "Purged {} expired messages, {} link previews and {} images",
+            total.messages,
+            total.link_previews,
+            total.images
+        );
+    }
+    total
+}
+
+/// Delete every message past its conversation's policy, and the link
+/// previews cached for it
+fn purge(conn: &mut Connection, thumbnail_dir: &Path) -> rusqlite::Result<Purged> {
+    let policies = policies(conn)?;
+    if policies.is_empty() {
+        return Ok(Purged::default());
+    }
+
+    let now = Utc::now();
+    let mut purged = Purged::default();
+    let mut images = Vec::new();
+    conn.pragma_update(None, "secure_delete", true)?;
+    let tx = conn.transaction()?;
+    for policy in &policies {
+        let cutoff = policy.cutoff(now);
+        let contents = {
+            let mut stmt = tx.prepare_cached(
+                "SELECT content FROM messages
+                 WHERE instance_id = ?1 AND channel_id = ?2 AND created_at < ?3",
+            )?;
+            let rows = stmt.query_map(
+                params![policy.instance_id, policy.channel_id, cutoff],
+                |row| row.get::<_, String>(0),
+            )?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        if contents.is_empty() {
+            continue;
+        }
+
+        for url in contents.iter().flat_map(|content| links(content)) {
+            if let Some(image_key) = link_preview::forget(&tx, url)? {
+                purged.link_previews += 1;
+                if let Some(key) = image_key {
+                    images.push(key);
+                }
+            }
+        }
+        purged.messages +=
+            tx.prepare_cached(
+                "DELETE FROM messages
+                 WHERE instance_id = ?1 AND channel_id = ?2 AND created_at < ?3",
+            )?
+            .execute(params![policy.instance_id, policy.channel_id, cutoff])? as u64;
+    }
+    tx.commit()?;
+    if purged.messages > 0 {
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    }
+
+    for key in images {
+        match thumbnail::remove_cached(thumbnail_dir, &key) {
+            Ok(true) => purged.images += 1,
+            Ok(false) => {}
+            Err(e) => log::warn!("Failed to remove preview image {}: {}", key, e),
+        }
+    }
+    Ok(purged)
+}
+
+/// Links in a message, as they'd have been looked up for previews
+fn links(content: &str) -> impl Iterator<Item = &str> {
+    static LINK: OnceLock<Regex> = OnceLock::new();
+    LINK.get_or_init(|| Regex::new(r#"https?://[^\s<>"'`]+"#).expect("static pattern is valid"))
+        .find_iter(content)
+        .map(|m| {
+            m.as_str()
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']'])
+        })
+}
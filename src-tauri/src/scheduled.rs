@@ -0,0 +1,260 @@
+//! Scheduled messages
+//!
+//! Messages written now to be sent later are kept in the database until
+//! they're due, so they survive restarts, and sent from here so they go
+//! out on time whether or not the frontend is awake. When a message comes
+//! due it's moved into the outbox and posted straight away, with its id as
+//! the outbox nonce; if posting fails it stays in the outbox for the
+//! frontend to deliver as usual, and the server drops whichever copy
+//! arrives second.
+//!
+//! Only the active profile's messages are sent. Another profile's wait
+//! until it's switched to, and go out then if they're overdue.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+
+use crate::api::{ApiClient, ApiError};
+use crate::auth::{self, AuthError};
+use crate::cache;
+use crate::db::Database;
+use crate::instances;
+use crate::profiles::Profiles;
+
+/// Most messages that can be waiting at once
+pub const MAX_SCHEDULED: usize = 500;
+
+/// Longest the scheduler sleeps between checks, so a clock change or a
+/// profile switch is noticed without waking it
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+    #[error("a scheduled message can't be empty")]
+    Empty,
+    #[error("at most {MAX_SCHEDULED} messages can be scheduled")]
+    TooMany,
+    #[error("unknown instance: {0}")]
+    UnknownInstance(String),
+    #[error("{0}")]
+    Auth(#[from] AuthError),
+    #[error("{0}")]
+    Api(#[from] ApiError),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledMessage {
+    pub id: String,
+    pub instance_id: String,
+    pub channel_id: String,
+    pub content: String,
+    pub send_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ScheduledMessage {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            instance_id: row.get("instance_id")?,
+            channel_id: row.get("channel_id")?,
+            content: row.get("content")?,
+            send_at: row.get("send_at")?,
+            created_at: row.get("created_at")?,
+        })
+    }
+}
+
+/// Payload of "scheduled-message-sent"
+#[derive(Debug, Clone, Serialize)]
+struct Sent<'a> {
+    #[serde(flatten)]
+    message: &'a ScheduledMessage,
+    /// False when it was left in the outbox for the frontend to send
+    delivered: bool,
+}
+
+/// Wakes the scheduler when messages are added
+#[derive(Default)]
+pub struct Scheduler {
+    wake: Notify,
+}
+
+/// Keep a message to be sent at `send_at`; one that's already due is sent
+/// right away
+pub fn schedule(
+    app: &AppHandle,
+    instance_id: &str,
+    channel_id: &str,
+    content: &str,
+    send_at: DateTime<Utc>,
+) -> Result<ScheduledMessage, ScheduleError> {
+    if content.trim().is_empty() {
+        return Err(ScheduleError::Empty);
+    }
+    let message = ScheduledMessage {
+        id: uuid::Uuid::new_v4().to_string(),
+        instance_id: instance_id.to_string(),
+        channel_id: channel_id.to_string(),
+        content: content.to_string(),
+        send_at,
+        created_at: Utc::now(),
+    };
+
+    let db = app.state::<Database>();
+    if db.with(|conn| instances::get(conn, instance_id))?.is_none() {
+        return Err(ScheduleError::UnknownInstance(instance_id.to_string()));
+    }
+    let count: i64 = db.with(|conn| {
+        conn.query_row("SELECT COUNT(*) FROM scheduled_messages", [], |row| {
+            row.get(0)
+        })
+    })?;
+    if count as usize >= MAX_SCHEDULED {
+        return Err(ScheduleError::TooMany);
+    }
+    db.with(|conn| insert(conn, &message))?;
+
+    app.state::<Scheduler>().wake.notify_one();
+    Ok(message)
+}
+
+/// Messages waiting to be sent, soonest first
+pub fn list(
+    conn: &Connection,
+    instance_id: Option<&str>,
+) -> rusqlite::Result<Vec<ScheduledMessage>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT * FROM scheduled_messages
+         WHERE ?1 IS NULL OR instance_id = ?1
+         ORDER BY send_at",
+    )?;
+    let rows = stmt.query_map([instance_id], ScheduledMessage::from_row)?;
+    rows.collect()
+}
+
+/// Drop a message before it's sent. Returns false if it's already gone.
+pub fn cancel(conn: &Connection, id: &str) -> rusqlite::Result<bool> {
+    let removed = conn
+        .prepare_cached("DELETE FROM scheduled_messages WHERE id = ?1")?
+        .execute([id])?;
+    Ok(removed > 0)
+}
+
+/// Start sending messages as they come due; call once during setup
+/// Emits "scheduled-message-sent" for each
+pub fn spawn(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let next = match send_due(&app).await {
+                Ok(next) => next,
+                Err(e) => {
+                    log::error!("Failed to send scheduled messages: {}", e);
+                    None
+                }
+            };
+            let sleep = next
+                .and_then(|at| (at - Utc::now()).to_std().ok())
+                .map_or(MAX_SLEEP, |wait| wait.min(MAX_SLEEP));
+
+            let scheduler = app.state::<Scheduler>();
+            tokio::select! {
+                _ = scheduler.wake.notified() => {}
+                _ = tokio::time::sleep(sleep) => {}
+            }
+        }
+    });
+}
+
+/// Send whatever is due, returning when the next message is
+async fn send_due(app: &AppHandle) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    let db = app.state::<Database>().inner().clone();
+    let now = Utc::now();
+    let due = db.with(|conn| take_due(conn, now))?;
+
+    for message in &due {
+        let delivered = match deliver(app, &db, message).await {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Left scheduled message {} in the outbox: {}", message.id, e);
+                false
+            }
+        };
+        let _ = app.emit("scheduled-message-sent", Sent { message, delivered });
+    }
+
+    db.with(|conn| {
+        conn.prepare_cached("SELECT MIN(send_at) FROM scheduled_messages")?
+            .query_row([], |row| row.get(0))
+    })
+}
+
+/// Move every message due by `now` into the outbox
+fn take_due(conn: &mut Connection, now: DateTime<Utc>) -> rusqlite::Result<Vec<ScheduledMessage>> {
+    let tx = conn.transaction()?;
+    let due = {
+        let mut stmt = tx.prepare_cached(
+            "SELECT * FROM scheduled_messages WHERE send_at <= ?1 ORDER BY send_at",
+        )?;
+        let rows = stmt.query_map([now], ScheduledMessage::from_row)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    for message in &due {
+        cache::enqueue_outbox(
+            &tx,
+            &message.instance_id,
+            &message.channel_id,
+            &message.content,
+            &message.id,
+        )?;
+        tx.execute(
+            "DELETE FROM scheduled_messages WHERE id = ?1",
+            [&message.id],
+        )?;
+    }
+    tx.commit()?;
+    Ok(due)
+}
+
+/// Post a message that's been moved into the outbox, and take it out again
+async fn deliver(
+    app: &AppHandle,
+    db: &Database,
+    message: &ScheduledMessage,
+) -> Result<(), ScheduleError> {
+    let instance = db
+        .with(|conn| instances::get(conn, &message.instance_id))?
+        .ok_or_else(|| ScheduleError::UnknownInstance(message.instance_id.clone()))?;
+    let profile_id = app.state::<Profiles>().active().id;
+    let token = auth::access_token(app, &profile_id, &instance).await?;
+    ApiClient::new(&instance.url)
+        .send_message(&token, &message.channel_id, &message.content, &message.id)
+        .await?;
+
+    db.with(|conn| cache::remove_outbox_nonce(conn, &message.id))?;
+    Ok(())
+}
+
+fn insert(conn: &Connection, message: &ScheduledMessage) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO scheduled_messages (id, instance_id, channel_id, content, send_at, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?
+    .execute(params![
+        message.id,
+        message.instance_id,
+        message.channel_id,
+        message.content,
+        message.send_at,
+        message.created_at,
+    ])?;
+    Ok(())
+}
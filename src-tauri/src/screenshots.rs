@@ -0,0 +1,327 @@
+//! Screenshot folder
+//!
+//! When a folder is set in [`FOLDER_SETTING`], new images saved into it
+//! (by the OS screenshot tool, ShareX, Flameshot and the like) are offered
+//! for sending to whatever conversation is open: "screenshot-offered"
+//! carries an attachment described the same way as a dropped file (see
+//! [`crate::uploads::ingest`]), so the size limits apply, and
+//! `send_screenshot` queues it with metadata stripped whatever the privacy
+//! setting says, since screenshot tools write window titles and software
+//! names into the file.
+//!
+//! The user picks the folder in a native dialog, so the webview can't point
+//! the watcher somewhere else and have its files offered for upload.
+//! Changes to it are watched with `notify`, debounced per file: screenshot
+//! tools write a file in more than one go, so one is only looked at once
+//! nothing has happened to it for [`SETTLE`]. Files that were already there
+//! when watching started, or that are old when they turn up (moved in
+//! rather than just taken), aren't offered at all.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, DebouncedEventKind, Debouncer};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::db::Database;
+use crate::instances;
+use crate::profiles::Profiles;
+use crate::settings;
+use crate::uploads::ingest::{self, AttachmentDescriptor};
+use crate::uploads::{Upload, UploadRequest, Uploads};
+
+/// Setting holding the folder to watch; unset is off
+pub const FOLDER_SETTING: &str = "uploads.screenshot_folder";
+
+/// How long a file has to be left alone before it's offered
+pub const SETTLE: Duration = Duration::from_secs(1);
+
+/// Files older than this when they first appear aren't new screenshots
+const MAX_AGE: Duration = Duration::from_secs(2 * 60);
+
+/// Offers kept for `list_screenshot_offers`; older ones are dropped
+const MAX_OFFERS: usize = 10;
+
+const EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "webp", "gif"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScreenshotError {
+    #[error("{0} is not a folder")]
+    NotAFolder(String),
+    #[error("no screenshot offer {0}")]
+    NoOffer(String),
+    #[error("unknown instance: {0}")]
+    UnknownInstance(String),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// A new screenshot, and the payload of "screenshot-offered"
+#[derive(Debug, Clone, Serialize)]
+pub struct ScreenshotOffer {
+    pub id: String,
+    pub attachment: AttachmentDescriptor,
+}
+
+/// Result of `get_screenshot_folder`
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderStatus {
+    pub folder: Option<PathBuf>,
+    /// Where the OS screenshot tool saves by default, to suggest
+    pub suggested: Option<PathBuf>,
+}
+
+#[derive(Default)]
+pub struct Screenshots {
+    offers: Mutex<Vec<ScreenshotOffer>>,
+    /// Watching the configured folder, while there is one
+    watcher: Mutex<Option<Debouncer<RecommendedWatcher>>>,
+}
+
+impl Screenshots {
+    fn lock(&self) -> MutexGuard<'_, Vec<ScreenshotOffer>> {
+        self.offers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_watcher(&self) -> MutexGuard<'_, Option<Debouncer<RecommendedWatcher>>> {
+        self.watcher.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A file's size and modification time
+type Stamp = (u64, Option<SystemTime>);
+
+pub fn status(app: &AppHandle) -> Result<FolderStatus, ScreenshotError> {
+    Ok(FolderStatus {
+        folder: folder(&app.state::<Database>())?,
+        suggested: suggested(app),
+    })
+}
+
+/// Let the user pick a folder to watch in a native dialog, starting in the
+/// suggested one; the folder is left as it was if the dialog is cancelled
+/// Blocks until the dialog is answered, so don't call it on the main thread
+pub fn pick_folder(app: &AppHandle) -> Result<FolderStatus, ScreenshotError> {
+    let mut dialog = app
+        .dialog()
+        .file()
+        .set_title("Choose the folder screenshots are saved to");
+    if let Some(suggested) = suggested(app) {
+        dialog = dialog.set_directory(suggested);
+    }
+    let Some(folder) = dialog
+        .blocking_pick_folder()
+        .and_then(|f| f.into_path().ok())
+    else {
+        return status(app);
+    };
+    if !folder.is_dir() {
+        return Err(ScreenshotError::NotAFolder(folder.display().to_string()));
+    }
+
+    app.state::<Database>().with(|conn| {
+        settings::set(
+            conn,
+            FOLDER_SETTING,
+            &serde_json::Value::String(folder.to_string_lossy().into_owned()),
+        )
+    })?;
+    watch(app, Some(folder));
+    status(app)
+}
+
+/// Stop watching for screenshots
+pub fn clear_folder(app: &AppHandle) -> Result<FolderStatus, ScreenshotError> {
+    app.state::<Database>()
+        .with(|conn| settings::remove(conn, FOLDER_SETTING))?;
+    watch(app, None);
+    app.state::<Screenshots>().lock().clear();
+    status(app)
+}
+
+pub fn offers(app: &AppHandle) -> Vec<ScreenshotOffer> {
+    app.state::<Screenshots>().lock().clone()
+}
+
+pub fn dismiss(app: &AppHandle, offer_id: &str) -> Result<(), ScreenshotError> {
+    take(app, offer_id).map(drop)
+}
+
+/// Queue an offered screenshot as an attachment to a message the frontend
+/// has created in the open conversation
+/// Emits "upload-status" as the upload goes
+pub fn send(
+    app: &AppHandle,
+    offer_id: &str,
+    instance_id: &str,
+    message_id: String,
+) -> Result<Upload, ScreenshotError> {
+    let instance = app
+        .state::<Database>()
+        .with(|conn| instances::get(conn, instance_id))?
+        .ok_or_else(|| ScreenshotError::UnknownInstance(instance_id.to_string()))?;
+    let offer = take(app, offer_id)?;
+    Ok(app.state::<Uploads>().queue(
+        app,
+        &app.state::<Profiles>().active().id,
+        instance,
+        UploadRequest {
+            message_id,
+            path: offer.attachment.path,
+            order: None,
+            strip_metadata: true,
+            transcode: None,
+        },
+    ))
+}
+
+/// Watch the configured folder, if there is one; call once at startup
+/// Emits "screenshot-offered", and "screenshot-rejected" for ones that
+/// can't be sent
+pub fn spawn(app: &AppHandle) {
+    match folder(&app.state::<Database>()) {
+        Ok(folder) => watch(app, folder),
+        Err(e) => log::error!("Failed to read screenshot folder: {}", e),
+    }
+}
+
+/// Replace the watcher with one for `folder`, or none
+fn watch(app: &AppHandle, folder: Option<PathBuf>) {
+    let screenshots = app.state::<Screenshots>();
+    let mut watcher = screenshots.lock_watcher();
+    // Dropping the old one stops it
+    *watcher = None;
+    let Some(folder) = folder else {
+        return;
+    };
+
+    let handle = app.clone();
+    // Stamps of files offered, so touching one again doesn't offer it twice
+    let mut offered: HashMap<PathBuf, Stamp> = HashMap::new();
+    let debouncer = new_debouncer(SETTLE, move |result: DebounceEventResult| match result {
+        Ok(events) => {
+            for event in events {
+                // Continuous events mean it's still being written
+                if event.kind == DebouncedEventKind::Any {
+                    settled(&handle, &mut offered, event.path);
+                }
+            }
+        }
+        Err(e) => log::warn!("Screenshot folder watcher failed: {}", e),
+    });
+    let mut debouncer = match debouncer {
+        Ok(debouncer) => debouncer,
+        Err(e) => {
+            log::error!("Failed to start screenshot folder watcher: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = debouncer
+        .watcher()
+        .watch(&folder, RecursiveMode::NonRecursive)
+    {
+        log::error!(
+            "Failed to watch {} for screenshots: {}",
+            folder.display(),
+            e
+        );
+        return;
+    }
+    log::info!("Watching {} for screenshots", folder.display());
+    *watcher = Some(debouncer);
+}
+
+/// A file in the folder has been left alone for [`SETTLE`]; offer it if
+/// it's a new image
+fn settled(app: &AppHandle, offered: &mut HashMap<PathBuf, Stamp>, path: PathBuf) {
+    if !is_image(&path) {
+        return;
+    }
+    let Some(metadata) = std::fs::metadata(&path).ok().filter(|m| m.is_file()) else {
+        return;
+    };
+    let stamp = (metadata.len(), metadata.modified().ok());
+    if offered.get(&path) == Some(&stamp) {
+        return;
+    }
+    let fresh = stamp
+        .1
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < MAX_AGE);
+    if !fresh {
+        return;
+    }
+
+    offered.retain(|path, _| path.exists());
+    offered.insert(path.clone(), stamp);
+    offer(app, path);
+}
+
+fn offer(app: &AppHandle, path: PathBuf) {
+    let Some(ingested) = tauri::async_runtime::block_on(ingest::ingest_for(app, vec![path])) else {
+        return;
+    };
+    for rejected in ingested.rejected {
+        log::info!(
+            "Not offering screenshot {}: {}",
+            rejected.path.display(),
+            rejected.reason
+        );
+        let _ = app.emit("screenshot-rejected", rejected);
+    }
+    for attachment in ingested.attachments {
+        let offer = ScreenshotOffer {
+            id: uuid::Uuid::new_v4().to_string(),
+            attachment,
+        };
+        {
+            let screenshots = app.state::<Screenshots>();
+            let mut offers = screenshots.lock();
+            offers.push(offer.clone());
+            let excess = offers.len().saturating_sub(MAX_OFFERS);
+            offers.drain(..excess);
+        }
+        let _ = app.emit("screenshot-offered", offer);
+    }
+}
+
+fn take(app: &AppHandle, offer_id: &str) -> Result<ScreenshotOffer, ScreenshotError> {
+    let screenshots = app.state::<Screenshots>();
+    let mut offers = screenshots.lock();
+    let index = offers
+        .iter()
+        .position(|offer| offer.id == offer_id)
+        .ok_or_else(|| ScreenshotError::NoOffer(offer_id.to_string()))?;
+    Ok(offers.remove(index))
+}
+
+fn folder(db: &Database) -> rusqlite::Result<Option<PathBuf>> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, FOLDER_SETTING))?
+        .and_then(|v| v.as_str().map(PathBuf::from)))
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Where screenshots go by default: `Pictures\Screenshots` on Windows and
+/// GNOME and KDE, the desktop on macOS
+fn suggested(app: &AppHandle) -> Option<PathBuf> {
+    #[cfg(target_os = "macos")]
+    let dir = app.path().desktop_dir().ok();
+    #[cfg(not(target_os = "macos"))]
+    let dir = app
+        .path()
+        .picture_dir()
+        .ok()
+        .map(|pictures| pictures.join("Screenshots"));
+    dir.filter(|dir| dir.is_dir())
+}
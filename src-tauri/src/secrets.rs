@@ -0,0 +1,54 @@
+//! Instance credentials in the platform keychain
+//!
+//! Each profile has its own keychain service, so signing in to the same
+//! server from two profiles keeps two independent sets of tokens. The
+//! keychain backends block, so call these from a blocking task.
+
+use chrono::{DateTime, Utc};
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+
+/// Same shape as the frontend's `InstanceCredentials`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Credentials {
+    pub access_token: Option<String>,
+    pub refresh_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Credentials {
+    /// True when the access token is missing or expires within the next 30 seconds
+    pub fn needs_refresh(&self) -> bool {
+        self.access_token.is_none() || self.expires_at - chrono::Duration::seconds(30) <= Utc::now()
+    }
+}
+
+fn entry(profile_id: &str, instance_id: &str) -> keyring::Result<Entry> {
+    Entry::new(&format!("com.redoubt.desktop.{}", profile_id), instance_id)
+}
+
+pub fn load(profile_id: &str, instance_id: &str) -> keyring::Result<Option<Credentials>> {
+    match entry(profile_id, instance_id)?.get_password() {
+        Ok(secret) => Ok(serde_json::from_str(&secret).ok()),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn store(
+    profile_id: &str,
+    instance_id: &str,
+    credentials: &Credentials,
+) -> keyring::Result<()> {
+    let secret = serde_json::to_string(credentials)
+        .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
+    entry(profile_id, instance_id)?.set_password(&secret)
+}
+
+pub fn delete(profile_id: &str, instance_id: &str) -> keyring::Result<()> {
+    match entry(profile_id, instance_id)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
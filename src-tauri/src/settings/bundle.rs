@@ -0,0 +1,240 @@
+//! Encrypted settings bundles
+//!
+//! A bundle packages settings into a single passphrase-protected file for
+//! moving them to another machine. The file is a short header (magic,
+//! format version, Argon2id salt, XChaCha20-Poly1305 nonce) followed by the
+//! encrypted JSON payload, with the header bound in as associated data.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+
+const MAGIC: &[u8; 8] = b"RDBTBNDL";
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Keys under this prefix describe this machine only and never leave it
+const LOCAL_PREFIX: &str = "local.";
+
+/// Whether `key` stays out of bundles, either because it only describes this
+/// machine or because only the backend may write it
+fn stays_local(key: &str) -> bool {
+    key.starts_with(LOCAL_PREFIX) || super::is_protected(key)
+}
+
+/// Groups of settings a bundle carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    /// Everything not covered by a more specific section
+    Settings,
+    Shortcuts,
+    /// Audio devices chosen, overall and per voice channel
+    Devices,
+    NotificationRules,
+}
+
+impl Section {
+    const ALL: [Section; 4] = [
+        Section::Settings,
+        Section::Shortcuts,
+        Section::Devices,
+        Section::NotificationRules,
+    ];
+
+    fn prefix(self) -> Option<&'static str> {
+        match self {
+            Section::Settings => None,
+            Section::Shortcuts => Some("shortcuts."),
+            Section::Devices => Some("devices."),
+            Section::NotificationRules => Some("notifications."),
+        }
+    }
+
+    fn of(key: &str) -> Section {
+        Section::ALL
+            .into_iter()
+            .find(|section| section.prefix().is_some_and(|p| key.starts_with(p)))
+            .unwrap_or(Section::Settings)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    app_version: String,
+    created_at: DateTime<Utc>,
+    sections: BTreeMap<Section, BTreeMap<String, serde_json::Value>>,
+}
+
+/// What a bundle contains
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleSummary {
+    pub app_version: String,
+    pub created_at: DateTime<Utc>,
+    /// Number of settings per section
+    pub sections: BTreeMap<Section, usize>,
+}
+
+impl From<&Bundle> for BundleSummary {
+    fn from(bundle: &Bundle) -> Self {
+        Self {
+            app_version: bundle.app_version.clone(),
+            created_at: bundle.created_at,
+            sections: bundle
+                .sections
+                .iter()
+                .map(|(section, values)| (*section, values.len()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    #[error("passphrase must be at least {MIN_PASSPHRASE_LEN} characters")]
+    WeakPassphrase,
+    #[error("not a settings bundle")]
+    InvalidFile,
+    #[error("settings bundle version {0} is not supported by this version of Redoubt")]
+    UnsupportedVersion(u8),
+    #[error("encryption failed")]
+    Encryption,
+    #[error("incorrect passphrase or corrupted bundle")]
+    Decryption,
+    #[error("key derivation failed: {0}")]
+    Kdf(String),
+    #[error("failed to access bundle: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed bundle contents: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// Write every exportable setting to an encrypted bundle at `path`
+pub fn export(
+    db: &Database,
+    path: &Path,
+    passphrase: &str,
+    app_version: &str,
+) -> Result<BundleSummary, BundleError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(BundleError::WeakPassphrase);
+    }
+
+    let mut sections: BTreeMap<Section, BTreeMap<String, serde_json::Value>> = BTreeMap::new();
+    for (key, value) in db.with(|conn| super::list(conn, ""))? {
+        if stays_local(&key) {
+            continue;
+        }
+        sections
+            .entry(Section::of(&key))
+            .or_default()
+            .insert(key, value);
+    }
+
+    let bundle = Bundle {
+        app_version: app_version.to_string(),
+        created_at: Utc::now(),
+        sections,
+    };
+    let plaintext = serde_json::to_vec(&bundle)?;
+
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut file = Vec::with_capacity(HEADER_LEN + plaintext.len() + 16);
+    file.extend_from_slice(MAGIC);
+    file.push(FORMAT_VERSION);
+    file.extend_from_slice(&salt);
+    file.extend_from_slice(&nonce);
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &plaintext,
+                aad: &file,
+            },
+        )
+        .map_err(|_| BundleError::Encryption)?;
+    file.extend_from_slice(&ciphertext);
+
+    fs::write(path, file)?;
+
+    Ok(BundleSummary::from(&bundle))
+}
+
+/// Decrypt the bundle at `path` and replace the settings in each section it carries
+pub fn import(db: &Database, path: &Path, passphrase: &str) -> Result<BundleSummary, BundleError> {
+    let file = fs::read(path)?;
+    if file.len() < HEADER_LEN || &file[..MAGIC.len()] != MAGIC {
+        return Err(BundleError::InvalidFile);
+    }
+
+    let version = file[MAGIC.len()];
+    if version != FORMAT_VERSION {
+        return Err(BundleError::UnsupportedVersion(version));
+    }
+
+    let (header, ciphertext) = file.split_at(HEADER_LEN);
+    let salt = &header[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN];
+    let nonce = XNonce::from_slice(&header[MAGIC.len() + 1 + SALT_LEN..]);
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    let plaintext = cipher
+        .decrypt(
+            nonce,
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| BundleError::Decryption)?;
+    let bundle: Bundle = serde_json::from_slice(&plaintext)?;
+
+    db.with(|conn| {
+        let tx = conn.transaction()?;
+
+        for (section, values) in &bundle.sections {
+            for (key, _) in super::list(&tx, section.prefix().unwrap_or(""))? {
+                if Section::of(&key) == *section && !stays_local(&key) {
+                    super::remove(&tx, &key)?;
+                }
+            }
+
+            for (key, value) in values {
+                // Ignore keys filed under the wrong section or meant to stay local
+                if Section::of(key) == *section && !stays_local(key) {
+                    super::set(&tx, key, value)?;
+                }
+            }
+        }
+
+        tx.commit()
+    })?;
+
+    Ok(BundleSummary::from(&bundle))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, BundleError> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+        .map_err(|e| BundleError::Kdf(e.to_string()))?;
+    Ok(key)
+}
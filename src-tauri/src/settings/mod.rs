@@ -0,0 +1,87 @@
+//! Settings store
+//!
+//! Settings are JSON values stored under dotted keys. The first segment
+//! groups related keys (`shortcuts.mute`, `devices.audio_input`, ...) so
+//! whole groups can be read, exported or replaced at once; device choices
+//! all live under `devices.` for that reason.
+
+pub mod bundle;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+/// Keys that launch programs, open the app to other machines or pick files
+/// to send or load. The webview can't write them through `set_setting` and
+/// bundles never carry them; each has a dedicated command instead. Entries
+/// ending in `.` cover a whole group
+const PROTECTED: &[&str] = &[
+    crate::uploads::transcode::FFMPEG_PATH_SETTING,
+    crate::control_api::CONTROL_API_SETTING,
+    crate::remote_control::ENABLED_SETTING,
+    crate::screenshots::FOLDER_SETTING,
+    crate::native_theme::THEME_SETTING,
+    "automation.",
+    "local.automation.",
+];
+
+/// Whether `key` may only be written by the backend
+pub fn is_protected(key: &str) -> bool {
+    PROTECTED.iter().any(|protected| {
+        if protected.ends_with('.') {
+            key.starts_with(protected)
+        } else {
+            key == *protected
+        }
+    })
+}
+
+/// Read a setting as raw JSON
+pub fn get_value(conn: &Connection, key: &str) -> rusqlite::Result<Option<serde_json::Value>> {
+    let value: Option<String> = conn
+        .prepare_cached("SELECT value FROM settings WHERE key = ?1")?
+        .query_row([key], |row| row.get(0))
+        .optional()?;
+
+    Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
+}
+
+/// Write a setting
+pub fn set<T: Serialize>(conn: &Connection, key: &str, value: &T) -> rusqlite::Result<()> {
+    let value = serde_json::to_string(value)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    conn.prepare_cached(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+    )?
+    .execute(params![key, value])?;
+
+    Ok(())
+}
+
+/// Remove a setting
+pub fn remove(conn: &Connection, key: &str) -> rusqlite::Result<()> {
+    conn.prepare_cached("DELETE FROM settings WHERE key = ?1")?
+        .execute([key])?;
+    Ok(())
+}
+
+/// Read every setting whose key starts with `prefix`, ordered by key
+pub fn list(conn: &Connection, prefix: &str) -> rusqlite::Result<Vec<(String, serde_json::Value)>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT key, value FROM settings WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key",
+    )?;
+    let rows = stmt.query_map([prefix], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut settings = Vec::new();
+    for row in rows {
+        let (key, value) = row?;
+        if let Ok(value) = serde_json::from_str(&value) {
+            settings.push((key, value));
+        }
+    }
+
+    Ok(settings)
+}
@@ -0,0 +1,128 @@
+//! "Send to Redoubt"
+//!
+//! Files the user sends to the app from the file manager end up in the
+//! composer, described the same way as files dropped on the window (see
+//! [`crate::uploads::ingest`]):
+//! - Windows: a shortcut in the Explorer "Send to" menu, which starts the
+//!   app with [`SHARE_ARG`] followed by the selected files
+//! - Linux: a Dolphin service menu and a Nemo action that do the same
+//! - macOS: files opened with the app ("Open With", or dropped on the dock
+//!   icon), which the OS hands to the running app. A share extension would
+//!   need a separately signed app extension target, which the bundle
+//!   doesn't have yet.
+//!
+//! When the app is already running the second instance's arguments come
+//! through the single-instance channel. The frontend receives the result as
+//! "files-shared"; what arrives before it has loaded is held until it calls
+//! `take_pending_shares`.
+
+#[cfg(windows)]
+mod windows;
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::deep_link;
+use crate::uploads::ingest::{self, Ingested};
+
+/// Argument the file manager integrations start the app with, followed by
+/// the files
+pub const SHARE_ARG: &str = "--share";
+
+/// Files shared before the frontend was ready for them
+#[derive(Default)]
+pub struct Shares {
+    queue: Mutex<Queue>,
+}
+
+#[derive(Default)]
+struct Queue {
+    frontend_ready: bool,
+    pending: Vec<Ingested>,
+}
+
+impl Shares {
+    /// Hand over files shared since startup; later ones arrive as events
+    pub fn take_pending(&self) -> Vec<Ingested> {
+        let mut queue = self.lock();
+        queue.frontend_ready = true;
+        std::mem::take(&mut queue.pending)
+    }
+
+    /// Hold files again until a new frontend collects them
+    pub fn frontend_gone(&self) {
+        self.lock().frontend_ready = false;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Add the "Send to" entry where the OS has no other way to register it;
+/// call once during setup
+pub fn install(app: &AppHandle) {
+    #[cfg(windows)]
+    {
+        let send_to = app.path().data_dir().map(|dir| {
+            dir.join("Microsoft")
+                .join("Windows")
+                .join("SendTo")
+                .join("Redoubt.lnk")
+        });
+        let spawned = std::thread::Builder::new()
+            .name("send-to".to_string())
+            .spawn(move || {
+                let result = send_to.map_err(|e| e.to_string()).and_then(|path| {
+                    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+                    windows::create_shortcut(&path, &exe).map_err(|e| e.to_string())
+                });
+                if let Err(e) = result {
+                    log::warn!("Failed to add the Send to shortcut: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            log::error!("Failed to start Send to setup: {}", e);
+        }
+    }
+    #[cfg(not(windows))]
+    let _ = app;
+}
+
+/// The files in a command line, if it's a share. Relative paths are
+/// resolved against `cwd`, the directory the app was started in.
+pub fn files_from_args(args: &[String], cwd: &Path) -> Option<Vec<PathBuf>> {
+    let position = args.iter().position(|arg| arg == SHARE_ARG)?;
+    Some(
+        args[position + 1..]
+            .iter()
+            .map(|arg| cwd.join(arg))
+            .collect(),
+    )
+}
+
+/// Describe shared files, raise the window and emit "files-shared"
+pub fn handle(app: &AppHandle, paths: Vec<PathBuf>) {
+    if paths.is_empty() {
+        deep_link::focus_main_window(app);
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let Some(ingested) = ingest::ingest_for(&app, paths).await else {
+            return;
+        };
+        {
+            let shares = app.state::<Shares>();
+            let mut queue = shares.lock();
+            if queue.frontend_ready {
+                let _ = app.emit("files-shared", ingested);
+            } else {
+                queue.pending.push(ingested);
+            }
+        }
+        deep_link::focus_main_window(&app);
+    });
+}
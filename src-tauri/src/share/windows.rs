@@ -0,0 +1,39 @@
+//! The "Send to" menu lists the shortcuts in the user's SendTo folder and
+//! starts the one chosen with the selected files appended to its arguments
+
+use std::path::Path;
+
+use windows::core::{Interface, HSTRING};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoUninitialize, IPersistFile, CLSCTX_INPROC_SERVER,
+    COINIT_APARTMENTTHREADED,
+};
+use windows::Win32::UI::Shell::{IShellLinkW, ShellLink};
+
+use super::SHARE_ARG;
+
+/// Write a shortcut that starts `exe` to share files. COM is set up for
+/// the calling thread, so call this from a thread of its own.
+pub(super) fn create_shortcut(path: &Path, exe: &Path) -> windows::core::Result<()> {
+    // SAFETY: balanced by CoUninitialize below; every COM object is
+    // released before it
+    unsafe {
+        CoInitializeEx(None, COINIT_APARTMENTTHREADED).ok()?;
+        let result = save(path, exe);
+        CoUninitialize();
+        result
+    }
+}
+
+unsafe fn save(path: &Path, exe: &Path) -> windows::core::Result<()> {
+    let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+    let exe = HSTRING::from(exe);
+    link.SetPath(&exe)?;
+    link.SetIconLocation(&exe, 0)?;
+    link.SetArguments(&HSTRING::from(SHARE_ARG))?;
+    link.SetDescription(&HSTRING::from("Send to Redoubt"))?;
+
+    // Rewritten on every start so it follows the app if it moves
+    let file: IPersistFile = link.cast()?;
+    file.Save(&HSTRING::from(path), true)
+}
@@ -0,0 +1,28 @@
+//! WebKitGTK checks the languages it's given, each through Enchant, and
+//! accepts a word if any of them knows it
+
+use tauri::{AppHandle, Manager};
+use webkit2gtk::{WebContextExt, WebViewExt};
+
+pub(super) fn set_languages(app: &AppHandle, languages: &[String]) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    // Enchant expects the locale with an underscore
+    let languages = languages
+        .iter()
+        .map(|l| l.replace('-', "_"))
+        .collect::<Vec<_>>();
+    let result = window.with_webview(move |webview| {
+        let Some(context) = webview.inner().context() else {
+            return;
+        };
+        // With none chosen, WebKit falls back to the system locale
+        let languages = languages.iter().map(String::as_str).collect::<Vec<_>>();
+        context.set_spell_checking_languages(&languages);
+        context.set_spell_checking_enabled(true);
+    });
+    if let Err(e) = result {
+        log::error!("Failed to set spellcheck languages: {}", e);
+    }
+}
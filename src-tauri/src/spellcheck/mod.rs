@@ -0,0 +1,330 @@
+//! Spellcheck dictionaries
+//!
+//! The webview's spellchecker only knows the dictionaries the OS has, which
+//! often means English alone. Hunspell dictionaries from a fixed catalog
+//! can be downloaded here and installed where the OS spellchecker looks
+//! for user dictionaries:
+//! - Linux: `~/.config/enchant/hunspell`, read by WebKitGTK through
+//!   Enchant. The languages to check are set on the webview.
+//! - macOS: `~/Library/Spelling`, after which the language can be picked
+//!   in the system's spelling settings
+//! - Windows: `%APPDATA%\Microsoft\Spelling\<language>`, used by WebView2
+//!   once the language is added in Windows' language settings
+//!
+//! Words the user adds to their custom list are kept in the settings and
+//! merged into each downloaded dictionary when it's installed, so they are
+//! accepted in every language downloaded through the app.
+
+#[cfg(target_os = "linux")]
+mod linux;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::data_usage::{self, Category};
+use crate::db::Database;
+use crate::settings;
+
+/// Setting holding the codes of the languages to check, for platforms where
+/// the app rather than the OS picks them
+pub const LANGUAGES_SETTING: &str = "spellcheck.languages";
+/// Setting holding the user's custom words
+pub const CUSTOM_WORDS_SETTING: &str = "spellcheck.custom_words";
+
+/// Dictionaries that can be downloaded: code, name and directory in the
+/// source repository
+const CATALOG: &[(&str, &str, &str)] = &[
+    ("cs", "Czech", "cs"),
+    ("da", "Danish", "da"),
+    ("de", "German", "de"),
+    ("en-GB", "English (UK)", "en-GB"),
+    ("en-US", "English (US)", "en"),
+    ("es", "Spanish", "es"),
+    ("fr", "French", "fr"),
+    ("it", "Italian", "it"),
+    ("nb", "Norwegian Bokmål", "nb"),
+    ("nl", "Dutch", "nl"),
+    ("pl", "Polish", "pl"),
+    ("pt-BR", "Portuguese (Brazil)", "pt"),
+    ("pt-PT", "Portuguese (Portugal)", "pt-PT"),
+    ("ru", "Russian", "ru"),
+    ("sv", "Swedish", "sv"),
+    ("tr", "Turkish", "tr"),
+    ("uk", "Ukrainian", "uk"),
+];
+const SOURCE_URL: &str = "https://raw.githubusercontent.com/wooorm/dictionaries/main/dictionaries";
+
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+/// Larger than any dictionary in the catalog
+const MAX_FILE_BYTES: u64 = 32 * 1024 * 1024;
+const MAX_WORD_LEN: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SpellcheckError {
+    #[error("unknown dictionary: {0}")]
+    Unknown(String),
+    #[error("dictionary {0} isn't downloaded")]
+    NotDownloaded(String),
+    #[error("{0} isn't a single word")]
+    InvalidWord(String),
+    #[error("dictionary download failed with status {0}")]
+    Status(u16),
+    #[error("dictionary file is too large")]
+    TooLarge,
+    #[error("no directory for dictionaries")]
+    NoDir,
+    #[error("dictionary download failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("failed to save dictionary: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// A catalog dictionary and its state
+#[derive(Debug, Clone, Serialize)]
+pub struct Dictionary {
+    pub code: String,
+    pub name: String,
+    pub downloaded: bool,
+    pub enabled: bool,
+}
+
+/// List the catalog, marking what's downloaded and enabled
+pub fn list(app: &AppHandle, db: &Database) -> Result<Vec<Dictionary>, SpellcheckError> {
+    let dir = download_dir(app)?;
+    let enabled = languages(db)?;
+    Ok(CATALOG
+        .iter()
+        .map(|(code, name, _)| Dictionary {
+            code: code.to_string(),
+            name: name.to_string(),
+            downloaded: dir.join(format!("{}.dic", code)).is_file(),
+            enabled: enabled.iter().any(|l| l == code),
+        })
+        .collect())
+}
+
+/// Download a dictionary from the catalog and install it
+pub async fn download(app: &AppHandle, db: &Database, code: &str) -> Result<(), SpellcheckError> {
+    let (code, _, source) = CATALOG
+        .iter()
+        .find(|(c, _, _)| *c == code)
+        .ok_or_else(|| SpellcheckError::Unknown(code.to_string()))?;
+    let dir = download_dir(app)?;
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let http = reqwest::Client::builder()
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()?;
+    // Both files are fetched before either is written, so a failed download
+    // doesn't leave half a dictionary behind
+    let mut files = Vec::new();
+    for ext in ["aff", "dic"] {
+        let url = format!("{}/{}/index.{}", SOURCE_URL, source, ext);
+        let bytes = fetch(&http, &url).await?;
+        data_usage::record(app, Category::Updates, 0, bytes.len() as u64);
+        files.push((ext, bytes));
+    }
+    for (ext, bytes) in files {
+        tokio::fs::write(dir.join(format!("{}.{}", code, ext)), bytes).await?;
+    }
+
+    let words = custom_words(db)?;
+    install(app, code, &words)?;
+    apply(app, db);
+    Ok(())
+}
+
+/// Delete a downloaded dictionary, uninstalling it and disabling it
+pub fn remove(app: &AppHandle, db: &Database, code: &str) -> Result<(), SpellcheckError> {
+    let dir = download_dir(app)?;
+    for ext in ["aff", "dic"] {
+        remove_file(&dir.join(format!("{}.{}", code, ext)))?;
+    }
+    let (installed, name) = install_dir(app, code)?;
+    for ext in ["aff", "dic"] {
+        remove_file(&installed.join(format!("{}.{}", name, ext)))?;
+    }
+
+    let mut enabled = languages(db)?;
+    enabled.retain(|l| l != code);
+    set_languages(app, db, enabled)
+}
+
+/// The languages the webview checks, where the app picks them
+pub fn languages(db: &Database) -> Result<Vec<String>, SpellcheckError> {
+    Ok(string_list(db, LANGUAGES_SETTING)?)
+}
+
+pub fn set_languages(
+    app: &AppHandle,
+    db: &Database,
+    languages: Vec<String>,
+) -> Result<(), SpellcheckError> {
+    db.with(|conn| settings::set(conn, LANGUAGES_SETTING, &languages))?;
+    apply(app, db);
+    Ok(())
+}
+
+pub fn custom_words(db: &Database) -> Result<Vec<String>, SpellcheckError> {
+    Ok(string_list(db, CUSTOM_WORDS_SETTING)?)
+}
+
+pub fn add_custom_word(app: &AppHandle, db: &Database, word: &str) -> Result<(), SpellcheckError> {
+    let word = word.trim();
+    if word.is_empty() || word.len() > MAX_WORD_LEN || word.contains(char::is_whitespace) {
+        return Err(SpellcheckError::InvalidWord(word.to_string()));
+    }
+    let mut words = custom_words(db)?;
+    if words.iter().any(|w| w == word) {
+        return Ok(());
+    }
+    words.push(word.to_string());
+    words.sort();
+    save_custom_words(app, db, words)
+}
+
+pub fn remove_custom_word(
+    app: &AppHandle,
+    db: &Database,
+    word: &str,
+) -> Result<(), SpellcheckError> {
+    let mut words = custom_words(db)?;
+    words.retain(|w| w != word);
+    save_custom_words(app, db, words)
+}
+
+/// Tell the webview which languages to check; call during setup and
+/// whenever they change. Only Linux lets the app choose.
+pub fn apply(app: &AppHandle, db: &Database) {
+    #[cfg(target_os = "linux")]
+    match languages(db) {
+        Ok(languages) => linux::set_languages(app, &languages),
+        Err(e) => log::error!("Failed to read spellcheck languages: {}", e),
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (app, db);
+}
+
+fn save_custom_words(
+    app: &AppHandle,
+    db: &Database,
+    words: Vec<String>,
+) -> Result<(), SpellcheckError> {
+    db.with(|conn| settings::set(conn, CUSTOM_WORDS_SETTING, &words))?;
+
+    // Reinstall so every downloaded dictionary picks up the change
+    let dir = download_dir(app)?;
+    for (code, _, _) in CATALOG {
+        if dir.join(format!("{}.dic", code)).is_file() {
+            install(app, code, &words)?;
+        }
+    }
+    apply(app, db);
+    Ok(())
+}
+
+/// Copy a downloaded dictionary to where the OS spellchecker reads it,
+/// with the custom words added
+fn install(app: &AppHandle, code: &str, words: &[String]) -> Result<(), SpellcheckError> {
+    let downloaded = download_dir(app)?;
+    if !downloaded.join(format!("{}.dic", code)).is_file() {
+        return Err(SpellcheckError::NotDownloaded(code.to_string()));
+    }
+    let aff = fs::read(downloaded.join(format!("{}.aff", code)))?;
+    let dic = fs::read_to_string(downloaded.join(format!("{}.dic", code)))?;
+
+    let (dir, name) = install_dir(app, code)?;
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(format!("{}.aff", name)), aff)?;
+    fs::write(dir.join(format!("{}.dic", name)), with_words(&dic, words))?;
+    Ok(())
+}
+
+/// Add words to a `.dic` file, whose first line is its word count
+fn with_words(dic: &str, words: &[String]) -> String {
+    let (count, entries) = dic.split_once('\n').unwrap_or((dic, ""));
+    let Ok(count) = count.trim().parse::<usize>() else {
+        return dic.to_string();
+    };
+    let mut merged = format!("{}\n{}", count + words.len(), entries);
+    if !merged.ends_with('\n') {
+        merged.push('\n');
+    }
+    for word in words {
+        merged.push_str(word);
+        merged.push('\n');
+    }
+    merged
+}
+
+/// Where dictionaries are kept as downloaded
+fn download_dir(app: &AppHandle) -> Result<PathBuf, SpellcheckError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join("dictionaries"))
+        .map_err(|_| SpellcheckError::NoDir)
+}
+
+/// Directory the OS spellchecker reads a dictionary from, and the file name
+/// it's installed under without an extension
+fn install_dir(app: &AppHandle, code: &str) -> Result<(PathBuf, String), SpellcheckError> {
+    // Hunspell names files after the locale with an underscore
+    let name = code.replace('-', "_");
+    #[cfg(target_os = "linux")]
+    let dir = app
+        .path()
+        .config_dir()
+        .map(|dir| dir.join("enchant").join("hunspell"));
+    #[cfg(target_os = "macos")]
+    let dir = app
+        .path()
+        .home_dir()
+        .map(|dir| dir.join("Library").join("Spelling"));
+    #[cfg(windows)]
+    let dir = app
+        .path()
+        .data_dir()
+        .map(|dir| dir.join("Microsoft").join("Spelling").join(code));
+    #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+    let dir: tauri::Result<PathBuf> = Err(tauri::Error::UnknownPath);
+    dir.map(|dir| (dir, name))
+        .map_err(|_| SpellcheckError::NoDir)
+}
+
+async fn fetch(http: &reqwest::Client, url: &str) -> Result<Vec<u8>, SpellcheckError> {
+    let response = http.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(SpellcheckError::Status(response.status().as_u16()));
+    }
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_FILE_BYTES)
+    {
+        return Err(SpellcheckError::TooLarge);
+    }
+    let bytes = response.bytes().await?;
+    if bytes.len() as u64 > MAX_FILE_BYTES {
+        return Err(SpellcheckError::TooLarge);
+    }
+    Ok(bytes.to_vec())
+}
+
+fn remove_file(path: &Path) -> Result<(), SpellcheckError> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn string_list(db: &Database, key: &str) -> rusqlite::Result<Vec<String>> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, key))?
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
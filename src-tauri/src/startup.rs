@@ -0,0 +1,267 @@
+//! Deferred startup
+//!
+//! The window is created before `setup` runs but can't paint until it
+//! returns, so anything slow there holds up the first frame. Subsystems
+//! that aren't needed to draw it start here instead, on a thread of their
+//! own once the main window's page has loaded, or after
+//! [`FIRST_PAINT_TIMEOUT`] if it never does (e.g. when started in the
+//! background). Each emits "subsystem-ready" as it comes up, and commands
+//! that need one await it with [`ready`].
+//!
+//! The profile database isn't deferred: it's managed state every command
+//! and most subsystems read, and its migrations have to be done before
+//! any of them do. Crash reporting is deferred as well, since its monitor
+//! process can take up to a second to answer, but it comes up first.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+#[cfg(any(windows, target_os = "linux"))]
+use tauri_plugin_deep_link::DeepLinkExt;
+use tokio::sync::Notify;
+
+use crate::audio::{self, call_recording, capture, playback};
+use crate::db::Database;
+use crate::gateway::Gateway;
+use crate::profiles::Profiles;
+use crate::{
+    crash_reports, keyboard_layout, native_theme, screenshots, spellcheck, temp_files, updater,
+};
+
+/// How long startup waits for the first paint before going ahead anyway
+pub const FIRST_PAINT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Longest `await_subsystem` waits
+pub const MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// What's brought up after the first paint, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    /// The crash monitor process
+    CrashReports,
+    /// Connections for stay-connected profiles other than the active one
+    Connections,
+    /// Spellcheck languages for the webview
+    Spellcheck,
+    /// Audio host, output and input devices, and the noise gate
+    Audio,
+    /// The theme pack for the tray icon, ringtone and overlay
+    Theme,
+    /// Keyboard layout tracking for shortcut labels
+    KeyboardLayout,
+    /// Call recordings a crash cut off
+    Recordings,
+    /// Temporary files a crash left behind
+    TempFiles,
+    /// `redoubt://` link registration, and the session bus on Linux
+    Integration,
+    /// The screenshot folder watcher
+    Screenshots,
+    /// Background update checks
+    Updater,
+}
+
+const ORDER: [Subsystem; 11] = [
+    Subsystem::CrashReports,
+    Subsystem::Connections,
+    Subsystem::Spellcheck,
+    Subsystem::Audio,
+    Subsystem::Theme,
+    Subsystem::KeyboardLayout,
+    Subsystem::Recordings,
+    Subsystem::TempFiles,
+    Subsystem::Integration,
+    Subsystem::Screenshots,
+    Subsystem::Updater,
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum StartupError {
+    #[error("{0:?} didn't start within {1} seconds")]
+    TimedOut(Subsystem, u64),
+}
+
+/// A subsystem's progress, for `get_startup_status`, and the payload of
+/// "subsystem-ready"
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemStatus {
+    pub subsystem: Subsystem,
+    pub ready: bool,
+    /// Time from setup to it being ready
+    pub ready_after_ms: Option<u64>,
+}
+
+struct Inner {
+    started: Instant,
+    /// Milliseconds after `started` each subsystem was ready
+    ready: BTreeMap<Subsystem, u64>,
+}
+
+pub struct Startup {
+    inner: Mutex<Inner>,
+    begun: AtomicBool,
+    changed: Notify,
+}
+
+impl Default for Startup {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                started: Instant::now(),
+                ready: BTreeMap::new(),
+            }),
+            begun: AtomicBool::new(false),
+            changed: Notify::new(),
+        }
+    }
+}
+
+impl Startup {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_ready(&self, subsystem: Subsystem) -> bool {
+        self.lock().ready.contains_key(&subsystem)
+    }
+}
+
+/// Start the deferred subsystems once the page has loaded, or after the
+/// timeout; call once at the end of setup
+pub fn schedule(app: &AppHandle) {
+    let handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(FIRST_PAINT_TIMEOUT).await;
+        begin(&handle);
+    });
+}
+
+/// The main window's page has loaded
+pub fn first_paint(app: &AppHandle) {
+    begin(app);
+}
+
+/// Wait for a subsystem to be up
+pub async fn ready(app: &AppHandle, subsystem: Subsystem) {
+    let startup = app.state::<Startup>();
+    loop {
+        let changed = startup.changed.notified();
+        tokio::pin!(changed);
+        changed.as_mut().enable();
+        if startup.is_ready(subsystem) {
+            return;
+        }
+        changed.await;
+    }
+}
+
+/// Wait for a subsystem, giving up after [`MAX_WAIT`]
+pub async fn wait(app: &AppHandle, subsystem: Subsystem) -> Result<(), StartupError> {
+    tokio::time::timeout(MAX_WAIT, ready(app, subsystem))
+        .await
+        .map_err(|_| StartupError::TimedOut(subsystem, MAX_WAIT.as_secs()))
+}
+
+pub fn status(app: &AppHandle) -> Vec<SubsystemStatus> {
+    let startup = app.state::<Startup>();
+    let inner = startup.lock();
+    ORDER
+        .iter()
+        .map(|&subsystem| SubsystemStatus {
+            subsystem,
+            ready: inner.ready.contains_key(&subsystem),
+            ready_after_ms: inner.ready.get(&subsystem).copied(),
+        })
+        .collect()
+}
+
+fn begin(app: &AppHandle) {
+    if app.state::<Startup>().begun.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let handle = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("deferred-startup".to_string())
+        .spawn(move || start_all(&handle));
+    if let Err(e) = spawned {
+        log::error!("Failed to start deferred startup, starting inline: {}", e);
+        start_all(app);
+    }
+}
+
+fn start_all(app: &AppHandle) {
+    for subsystem in ORDER {
+        start(app, subsystem);
+        mark_ready(app, subsystem);
+    }
+}
+
+fn start(app: &AppHandle, subsystem: Subsystem) {
+    match subsystem {
+        Subsystem::CrashReports => crash_reports::apply(app),
+        Subsystem::Connections => {
+            let profiles = app.state::<Profiles>();
+            let active = profiles.active().id;
+            for profile in profiles.list() {
+                if profile.stay_connected && profile.id != active {
+                    let gateway = app.state::<Gateway>();
+                    if let Err(e) = gateway.connect_profile(app, &profiles, &profile.id) {
+                        log::error!("Failed to connect profile {}: {}", profile.id, e);
+                    }
+                }
+            }
+        }
+        Subsystem::Spellcheck => spellcheck::apply(app, &app.state::<Database>()),
+        Subsystem::Audio => {
+            audio::gate::apply(&app.state::<Database>());
+            // Listing brings up the audio host, which is the slow part the
+            // first time on every platform
+            if let Err(e) = playback::output_devices() {
+                log::warn!("Failed to list output devices: {}", e);
+            }
+            if let Err(e) = capture::input_devices() {
+                log::warn!("Failed to list input devices: {}", e);
+            }
+        }
+        Subsystem::Theme => native_theme::spawn(app),
+        Subsystem::KeyboardLayout => keyboard_layout::spawn(app),
+        Subsystem::Recordings => call_recording::recover(app),
+        Subsystem::TempFiles => temp_files::sweep(app),
+        Subsystem::Integration => {
+            // macOS registers the scheme from the bundle's Info.plist
+            #[cfg(any(windows, target_os = "linux"))]
+            if let Err(e) = app.deep_link().register_all() {
+                log::error!("Failed to register redoubt:// links: {}", e);
+            }
+            #[cfg(target_os = "linux")]
+            crate::dbus::spawn(app);
+        }
+        Subsystem::Screenshots => screenshots::spawn(app),
+        Subsystem::Updater => updater::spawn(app),
+    }
+}
+
+fn mark_ready(app: &AppHandle, subsystem: Subsystem) {
+    let startup = app.state::<Startup>();
+    let after = {
+        let mut inner = startup.lock();
+        let after = inner.started.elapsed().as_millis() as u64;
+        inner.ready.insert(subsystem, after);
+        after
+    };
+    startup.changed.notify_waiters();
+    log::debug!("{:?} ready {} ms after setup", subsystem, after);
+    let _ = app.emit(
+        "subsystem-ready",
+        SubsystemStatus {
+            subsystem,
+            ready: true,
+            ready_after_ms: Some(after),
+        },
+    );
+}
@@ -0,0 +1,168 @@
+//! Streamer mode
+//!
+//! A single flag, kept here rather than in the frontend, that hides what
+//! shouldn't end up on a stream:
+//! - native notifications show that a message arrived but not its sender
+//!   or content
+//! - the app's windows are protected from screen capture, so a full-screen
+//!   capture shows them blanked out
+//! - "deep-link" events are marked as masked, so the frontend doesn't
+//!   preview invite codes and tokens in them
+//!
+//! The user turns it on and off, or it follows what's running (see
+//! [`crate::process_scan`]): when OBS, Streamlabs or XSplit starts,
+//! streamer mode is turned on if the setting allows it, otherwise
+//! "streamer-mode-suggested" is emitted, and games can be set to turn it
+//! on while they run. Streamer mode that was turned on automatically goes
+//! off again once nothing that turned it on is running; if the user turned
+//! it on, it stays on.
+
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::db::Database;
+use crate::settings;
+
+/// Setting that turns streamer mode on by itself when capture software
+/// starts, instead of suggesting it; off unless set to `true`
+pub const AUTO_ENABLE_SETTING: &str = "streamer_mode.auto_enable";
+
+/// Payload of "streamer-mode-changed"
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct StreamerModeState {
+    pub enabled: bool,
+    /// Turned on because capture software or a game started, rather than
+    /// by the user
+    pub automatic: bool,
+}
+
+/// Payload of "streamer-mode-suggested"
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamerModeSuggestion {
+    /// Name of the capture software that started
+    pub software: &'static str,
+}
+
+#[derive(Default)]
+pub struct StreamerMode {
+    state: Mutex<StreamerModeState>,
+    triggers: Mutex<Triggers>,
+}
+
+/// What was running at the last process scan
+#[derive(Default)]
+struct Triggers {
+    capture: Option<&'static str>,
+    game: bool,
+}
+
+impl StreamerMode {
+    pub fn state(&self) -> StreamerModeState {
+        *self.lock()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.lock().enabled
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, StreamerModeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn triggers(&self) -> std::sync::MutexGuard<'_, Triggers> {
+        self.triggers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Turn streamer mode on or off at the user's request
+pub fn set(app: &AppHandle, enabled: bool) {
+    apply(
+        app,
+        StreamerModeState {
+            enabled,
+            automatic: false,
+        },
+    );
+}
+
+/// Turn streamer mode on for something that started, unless it's on already
+fn enable_automatically(app: &AppHandle) {
+    if app.state::<StreamerMode>().is_enabled() {
+        return;
+    }
+    apply(
+        app,
+        StreamerModeState {
+            enabled: true,
+            automatic: true,
+        },
+    );
+}
+
+/// Turn streamer mode off once what turned it on has stopped; the user's
+/// own choice is left alone
+fn disable_automatic(app: &AppHandle) {
+    if app.state::<StreamerMode>().state().automatic {
+        apply(app, StreamerModeState::default());
+    }
+}
+
+/// Called by the process scan with the capture software running and
+/// whether a game that should turn streamer mode on is running
+pub fn update_triggers(app: &AppHandle, capture: Option<&'static str>, game: bool) {
+    let streamer_mode = app.state::<StreamerMode>();
+    let previous = std::mem::replace(&mut *streamer_mode.triggers(), Triggers { capture, game });
+    let capture_enables = capture.is_some() && auto_enable(&app.state::<Database>());
+
+    if let (None, Some(software)) = (previous.capture, capture) {
+        if !capture_enables && !streamer_mode.is_enabled() {
+            let _ = app.emit(
+                "streamer-mode-suggested",
+                StreamerModeSuggestion { software },
+            );
+        }
+    }
+    // Only a trigger that has just started turns it on, so it stays off if
+    // the user turned it off while one was running
+    let started = (capture_enables && previous.capture.is_none()) || (game && !previous.game);
+    if started {
+        enable_automatically(app);
+    } else if !capture_enables && !game {
+        disable_automatic(app);
+    }
+}
+
+/// Apply streamer mode to every window; call for windows created later
+pub fn protect_windows(app: &AppHandle) {
+    let enabled = app.state::<StreamerMode>().is_enabled();
+    for window in app.webview_windows().values() {
+        if let Err(e) = window.set_content_protected(enabled) {
+            log::warn!("Failed to set capture protection: {}", e);
+        }
+    }
+}
+
+/// Emits "streamer-mode-changed" if the state changed
+fn apply(app: &AppHandle, state: StreamerModeState) {
+    {
+        let streamer_mode = app.state::<StreamerMode>();
+        let mut current = streamer_mode.lock();
+        if current.enabled == state.enabled && current.automatic == state.automatic {
+            return;
+        }
+        *current = state;
+    }
+    protect_windows(app);
+    let _ = app.emit("streamer-mode-changed", state);
+}
+
+fn auto_enable(db: &Database) -> bool {
+    db.with(|conn| settings::get_value(conn, AUTO_ENABLE_SETTING))
+        .map_err(|e| log::error!("Failed to read streamer mode setting: {}", e))
+        .ok()
+        .flatten()
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
@@ -0,0 +1,279 @@
+//! Temporary files
+//!
+//! Intermediates that hold user content, like transcoded uploads, are
+//! created here rather than wherever is handy, so there's one place that
+//! knows about them and gets rid of them. Each run of the app has its own
+//! directory under the cache, named for the session; files in it are
+//! handed out as [`TempFile`]s that securely delete the file when dropped,
+//! and whatever is left when the app exits is deleted then. A directory
+//! from another session can only be left over from a crash (the app is
+//! single-instance), so those are swept once startup is done.
+//!
+//! Secure deletion overwrites the contents with zeros and syncs before
+//! unlinking, under a throwaway name so the original doesn't linger in
+//! the directory either. Copy-on-write filesystems and SSD wear levelling
+//! can keep old blocks around regardless; this is about what's reachable
+//! through the filesystem, not forensics.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+/// Directory under the app cache holding each session's files
+const TEMP_DIR: &str = "temp";
+
+/// Where older versions wrote transcodes and media cached for all
+/// profiles, swept with the orphans
+const LEGACY_DIRS: [&str; 3] = ["transcodes", "thumbnails", "animations"];
+
+/// Zeros written per chunk when overwriting
+const OVERWRITE_CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TempFileError {
+    #[error("no cache directory: {0}")]
+    NoCacheDir(#[from] tauri::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Result of `purge_temp_files`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PurgeReport {
+    pub files: usize,
+    pub bytes: u64,
+    /// Files still being used, left alone
+    pub in_use: usize,
+    /// Files that couldn't be deleted
+    pub failed: usize,
+}
+
+impl PurgeReport {
+    fn add(&mut self, other: &PurgeReport) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.in_use += other.in_use;
+        self.failed += other.failed;
+    }
+}
+
+struct Inner {
+    session: String,
+    /// Files handed out and not yet dropped
+    held: HashSet<PathBuf>,
+}
+
+pub struct TempFiles {
+    inner: Mutex<Inner>,
+}
+
+impl Default for TempFiles {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                session: uuid::Uuid::new_v4().to_string(),
+                held: HashSet::new(),
+            }),
+        }
+    }
+}
+
+impl TempFiles {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A temporary file, securely deleted when dropped. The file itself isn't
+/// created; the path is for whatever writes it.
+pub struct TempFile {
+    app: AppHandle,
+    path: PathBuf,
+}
+
+impl TempFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        self.app.state::<TempFiles>().lock().held.remove(&self.path);
+        // Overwriting a large file shouldn't hold up whoever dropped it,
+        // which is often an async task
+        let path = std::mem::take(&mut self.path);
+        tauri::async_runtime::spawn_blocking(move || {
+            if let Err(e) = secure_delete(&path) {
+                log::warn!("Failed to delete temporary file {}: {}", path.display(), e);
+            }
+        });
+    }
+}
+
+/// Reserve a temporary file with the given extension
+pub fn create(app: &AppHandle, extension: &str) -> Result<TempFile, TempFileError> {
+    let dir = session_dir(app)?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.{}", uuid::Uuid::new_v4(), extension));
+    app.state::<TempFiles>().lock().held.insert(path.clone());
+    Ok(TempFile {
+        app: app.clone(),
+        path,
+    })
+}
+
+/// Delete every temporary file that isn't in use, this session's and any
+/// a crash left behind
+pub fn purge(app: &AppHandle) -> Result<PurgeReport, TempFileError> {
+    let mut report = sweep_orphans(app)?;
+    report.add(&purge_session(app, false)?);
+    if report.files > 0 {
+        log::info!(
+            "Deleted {} temporary files ({} bytes)",
+            report.files,
+            report.bytes
+        );
+    }
+    Ok(report)
+}
+
+/// Delete what crashed sessions left behind; run once at startup
+pub fn sweep(app: &AppHandle) {
+    match sweep_orphans(app) {
+        Ok(report) if report.files > 0 => log::info!(
+            "Deleted {} temporary files left by an earlier session",
+            report.files
+        ),
+        Ok(_) => {}
+        Err(e) => log::warn!("Failed to sweep temporary files: {}", e),
+    }
+}
+
+/// Delete this session's files, in use or not, as the app exits
+pub fn on_exit(app: &AppHandle) {
+    if let Err(e) = purge_session(app, true) {
+        log::warn!("Failed to delete temporary files: {}", e);
+    }
+}
+
+fn session_dir(app: &AppHandle) -> Result<PathBuf, TempFileError> {
+    let session = app.state::<TempFiles>().lock().session.clone();
+    Ok(temp_root(app)?.join(session))
+}
+
+fn temp_root(app: &AppHandle) -> Result<PathBuf, TempFileError> {
+    Ok(app.path().app_cache_dir()?.join(TEMP_DIR))
+}
+
+fn purge_session(app: &AppHandle, everything: bool) -> Result<PurgeReport, TempFileError> {
+    let dir = session_dir(app)?;
+    let held = app.state::<TempFiles>().lock().held.clone();
+    let mut report = PurgeReport::default();
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(report);
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !everything && held.contains(&path) {
+            report.in_use += 1;
+            continue;
+        }
+        delete_counted(&path, &mut report);
+    }
+    if everything {
+        let _ = fs::remove_dir(&dir);
+    }
+    Ok(report)
+}
+
+fn sweep_orphans(app: &AppHandle) -> Result<PurgeReport, TempFileError> {
+    let root = temp_root(app)?;
+    let session = app.state::<TempFiles>().lock().session.clone();
+    let mut report = PurgeReport::default();
+
+    let mut dirs: Vec<PathBuf> = fs::read_dir(&root)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_name().to_str() != Some(session.as_str()))
+                .map(|entry| entry.path())
+                .collect()
+        })
+        .unwrap_or_default();
+    let cache = app.path().app_cache_dir()?;
+    dirs.extend(LEGACY_DIRS.iter().map(|dir| cache.join(dir)));
+
+    for dir in dirs {
+        if dir.is_file() {
+            delete_counted(&dir, &mut report);
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            delete_counted(&entry.path(), &mut report);
+        }
+        let _ = fs::remove_dir(&dir);
+    }
+    Ok(report)
+}
+
+fn delete_counted(path: &Path, report: &mut PurgeReport) {
+    let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    match secure_delete(path) {
+        Ok(()) => {
+            report.files += 1;
+            report.bytes += size;
+        }
+        Err(e) => {
+            log::warn!("Failed to delete temporary file {}: {}", path.display(), e);
+            report.failed += 1;
+        }
+    }
+}
+
+/// Overwrite, rename and remove a file; missing files are already gone
+fn secure_delete(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)?.flatten() {
+            secure_delete(&entry.path())?;
+        }
+        return fs::remove_dir(path);
+    }
+    if metadata.is_file() {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        overwrite(&mut file, metadata.len())?;
+    }
+
+    let renamed = path.with_file_name(uuid::Uuid::new_v4().to_string());
+    let target = match fs::rename(path, &renamed) {
+        Ok(()) => renamed,
+        Err(_) => path.to_path_buf(),
+    };
+    fs::remove_file(target)
+}
+
+fn overwrite(file: &mut File, len: u64) -> io::Result<()> {
+    let zeros = [0u8; OVERWRITE_CHUNK];
+    file.seek(SeekFrom::Start(0))?;
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = remaining.min(OVERWRITE_CHUNK as u64) as usize;
+        file.write_all(&zeros[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all()?;
+    file.set_len(0)
+}
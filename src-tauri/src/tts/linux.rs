@@ -0,0 +1,116 @@
+//! Speech Dispatcher's SSIP protocol over its Unix socket: text commands
+//! ending in CRLF, answered with lines of a status code, `-` for more lines
+//! to come or a space on the last one, and a message
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use super::TtsError;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+pub(super) struct Speaker {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Speaker {
+    pub(super) fn new() -> Result<Self, TtsError> {
+        let path = socket_path()
+            .ok_or_else(|| TtsError::Unavailable("no runtime directory".to_string()))?;
+        let stream = match UnixStream::connect(&path) {
+            Ok(stream) => stream,
+            // Speech Dispatcher is usually started on demand by its client
+            // library, so do what it would
+            Err(_) => {
+                let _ = Command::new("speech-dispatcher").arg("--spawn").status();
+                UnixStream::connect(&path)
+                    .map_err(|e| TtsError::Unavailable(format!("Speech Dispatcher: {}", e)))?
+            }
+        };
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .map_err(speak_error)?;
+        stream
+            .set_write_timeout(Some(TIMEOUT))
+            .map_err(speak_error)?;
+
+        let mut speaker = Self {
+            reader: BufReader::new(stream.try_clone().map_err(speak_error)?),
+            writer: stream,
+        };
+        speaker.command("SET self CLIENT_NAME user:redoubt:main")?;
+        // Announcements queue behind each other rather than replacing one
+        // another
+        speaker.command("SET self PRIORITY message")?;
+        Ok(speaker)
+    }
+
+    pub(super) fn speak(&mut self, text: &str, interrupt: bool) -> Result<(), TtsError> {
+        if interrupt {
+            self.stop()?;
+        }
+        self.command("SPEAK")?;
+        // A line holding just "." ends the text, so lines starting with one
+        // get it doubled
+        let mut body = String::new();
+        for line in text.lines() {
+            if line.starts_with('.') {
+                body.push('.');
+            }
+            body.push_str(line);
+            body.push_str("\r\n");
+        }
+        body.push('.');
+        self.command(&body)?;
+        Ok(())
+    }
+
+    pub(super) fn stop(&mut self) -> Result<(), TtsError> {
+        self.command("CANCEL self")
+    }
+
+    /// Send a command and read its reply, failing on anything but a 2xx
+    fn command(&mut self, command: &str) -> Result<(), TtsError> {
+        self.writer
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .map_err(speak_error)?;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line).map_err(speak_error)? == 0 {
+                return Err(TtsError::Speak(
+                    "Speech Dispatcher closed the connection".to_string(),
+                ));
+            }
+            let line = line.trim_end();
+            if !line.starts_with('2') {
+                return Err(TtsError::Speak(line.to_string()));
+            }
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Where Speech Dispatcher listens, as its client library finds it
+fn socket_path() -> Option<PathBuf> {
+    if let Ok(address) = std::env::var("SPEECHD_ADDRESS") {
+        if let Some(path) = address.strip_prefix("unix_socket:") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    let runtime = std::env::var_os("XDG_RUNTIME_DIR")?;
+    Some(
+        PathBuf::from(runtime)
+            .join("speech-dispatcher")
+            .join("speechd.sock"),
+    )
+}
+
+fn speak_error(e: std::io::Error) -> TtsError {
+    TtsError::Speak(e.to_string())
+}
@@ -0,0 +1,238 @@
+//! Text-to-speech announcements
+//!
+//! Speaks messages and voice channel events through the system's voices,
+//! so they can be heard without the window being focused: SAPI/OneCore on
+//! Windows and AVSpeechSynthesizer on macOS through the `tts` crate, and
+//! Speech Dispatcher on Linux, spoken to over its socket so the app doesn't
+//! link against libspeechd.
+//!
+//! The frontend reports what happened as an [`Announcement`]; whether it's
+//! spoken, and how much of it, follows the rule for its channel, or the
+//! default rule when the channel has none.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(any(windows, target_os = "macos"))]
+mod native;
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use crate::settings;
+
+#[cfg(target_os = "linux")]
+use linux::Speaker;
+#[cfg(any(windows, target_os = "macos"))]
+use native::Speaker;
+
+/// Other platforms have no voices to speak with
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+struct Speaker;
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+impl Speaker {
+    fn new() -> Result<Self, TtsError> {
+        Err(TtsError::Unavailable(
+            "not supported on this platform".to_string(),
+        ))
+    }
+
+    fn speak(&mut self, _text: &str, _interrupt: bool) -> Result<(), TtsError> {
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), TtsError> {
+        Ok(())
+    }
+}
+
+/// Setting holding the rule for channels without one of their own
+pub const DEFAULT_RULE_SETTING: &str = "tts.default";
+/// Prefix of the settings holding a channel's rule, followed by
+/// `<instance id>.<channel id>`
+pub const CHANNEL_RULE_PREFIX: &str = "tts.channels.";
+
+/// Longest text spoken; the rest of a long message is cut off
+const MAX_SPOKEN_CHARS: usize = 500;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TtsError {
+    #[error("text-to-speech is unavailable: {0}")]
+    Unavailable(String),
+    #[error("failed to speak: {0}")]
+    Speak(String),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// What gets announced in a channel; by default nothing is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TtsRule {
+    /// Announce new messages
+    pub messages: bool,
+    /// Read messages out rather than just who sent them
+    pub read_content: bool,
+    /// Announce people joining and leaving the voice channel
+    pub voice_events: bool,
+}
+
+/// Something that happened in a channel
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Announcement {
+    Message {
+        instance_id: String,
+        channel_id: String,
+        author: String,
+        content: String,
+    },
+    VoiceJoined {
+        instance_id: String,
+        channel_id: String,
+        user: String,
+    },
+    VoiceLeft {
+        instance_id: String,
+        channel_id: String,
+        user: String,
+    },
+}
+
+impl Announcement {
+    fn channel(&self) -> (&str, &str) {
+        match self {
+            Announcement::Message {
+                instance_id,
+                channel_id,
+                ..
+            }
+            | Announcement::VoiceJoined {
+                instance_id,
+                channel_id,
+                ..
+            }
+            | Announcement::VoiceLeft {
+                instance_id,
+                channel_id,
+                ..
+            } => (instance_id, channel_id),
+        }
+    }
+
+    /// What to say for this under `rule`, if anything
+    fn text(&self, rule: TtsRule) -> Option<String> {
+        match self {
+            Announcement::Message {
+                author, content, ..
+            } if rule.messages => Some(if rule.read_content {
+                format!("{} says {}", author, content)
+            } else {
+                format!("Message from {}", author)
+            }),
+            Announcement::VoiceJoined { user, .. } if rule.voice_events => {
+                Some(format!("{} joined the channel", user))
+            }
+            Announcement::VoiceLeft { user, .. } if rule.voice_events => {
+                Some(format!("{} left the channel", user))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The system voice, set up the first time something is spoken
+#[derive(Default)]
+pub struct Tts {
+    speaker: Mutex<Option<Speaker>>,
+}
+
+impl Tts {
+    /// Speak `text`, cutting off whatever is being spoken if `interrupt`,
+    /// otherwise after it
+    pub fn speak(&self, text: &str, interrupt: bool) -> Result<(), TtsError> {
+        let text = truncate(text.trim(), MAX_SPOKEN_CHARS);
+        if text.is_empty() {
+            return Ok(());
+        }
+        let mut speaker = self.lock();
+        let result = match &mut *speaker {
+            Some(speaker) => speaker.speak(text, interrupt),
+            None => {
+                let mut created = Speaker::new()?;
+                let result = created.speak(text, interrupt);
+                *speaker = Some(created);
+                result
+            }
+        };
+        // Set up again next time in case the voice service restarted
+        if result.is_err() {
+            *speaker = None;
+        }
+        result
+    }
+
+    /// Stop speaking and drop anything queued
+    pub fn stop(&self) -> Result<(), TtsError> {
+        match &mut *self.lock() {
+            Some(speaker) => speaker.stop(),
+            None => Ok(()),
+        }
+    }
+
+    /// Speak an announcement if its channel's rule asks for it. Returns
+    /// whether anything was spoken.
+    pub fn announce(&self, db: &Database, announcement: &Announcement) -> Result<bool, TtsError> {
+        let (instance_id, channel_id) = announcement.channel();
+        let rule = rule(db, instance_id, channel_id)?;
+        match announcement.text(rule) {
+            Some(text) => {
+                self.speak(&text, false)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Speaker>> {
+        self.speaker.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The rule for a channel, falling back to the default rule
+pub fn rule(db: &Database, instance_id: &str, channel_id: &str) -> Result<TtsRule, TtsError> {
+    let key = channel_key(instance_id, channel_id);
+    let rule = db
+        .with(|conn| settings::get_value(conn, &key))?
+        .or(db.with(|conn| settings::get_value(conn, DEFAULT_RULE_SETTING))?)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    Ok(rule)
+}
+
+/// Set a channel's rule, or go back to the default rule with `None`
+pub fn set_rule(
+    db: &Database,
+    instance_id: &str,
+    channel_id: &str,
+    rule: Option<TtsRule>,
+) -> Result<(), TtsError> {
+    let key = channel_key(instance_id, channel_id);
+    db.with(|conn| match rule {
+        Some(rule) => settings::set(conn, &key, &rule),
+        None => settings::remove(conn, &key),
+    })?;
+    Ok(())
+}
+
+fn channel_key(instance_id: &str, channel_id: &str) -> String {
+    format!("{}{}.{}", CHANNEL_RULE_PREFIX, instance_id, channel_id)
+}
+
+fn truncate(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
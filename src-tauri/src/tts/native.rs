@@ -0,0 +1,28 @@
+//! The system voice through the `tts` crate
+
+use super::TtsError;
+
+pub(super) struct Speaker {
+    tts: tts::Tts,
+}
+
+impl Speaker {
+    pub(super) fn new() -> Result<Self, TtsError> {
+        let tts = tts::Tts::default().map_err(|e| TtsError::Unavailable(e.to_string()))?;
+        Ok(Self { tts })
+    }
+
+    pub(super) fn speak(&mut self, text: &str, interrupt: bool) -> Result<(), TtsError> {
+        self.tts
+            .speak(text, interrupt)
+            .map_err(|e| TtsError::Speak(e.to_string()))?;
+        Ok(())
+    }
+
+    pub(super) fn stop(&mut self) -> Result<(), TtsError> {
+        self.tts
+            .stop()
+            .map_err(|e| TtsError::Speak(e.to_string()))?;
+        Ok(())
+    }
+}
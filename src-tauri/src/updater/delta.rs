@@ -0,0 +1,130 @@
+//! Delta updates
+//!
+//! An update package is rebuilt from the one the running version was
+//! installed from plus a bsdiff patch, which is usually a small fraction of
+//! the full download. The release manifest lists patches under `deltas`,
+//! by the version they apply to and then by target:
+//!
+//! ```json
+//! "deltas": { "0.1.1": { "linux-x86_64": { "url": "https://..." } } }
+//! ```
+//!
+//! The rebuilt package has to match the full package's signature, so a
+//! wrong base or a corrupt patch fails verification and the full package is
+//! downloaded instead.
+
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use tauri_plugin_updater::Update;
+
+use super::UpdateError;
+
+/// Largest patch downloaded; anything bigger isn't worth it over the full
+/// package
+const MAX_PATCH_BYTES: u64 = 256 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Patch {
+    url: String,
+}
+
+/// The package the running version was installed from, kept to patch
+pub(super) struct Base {
+    dir: PathBuf,
+}
+
+impl Base {
+    pub(super) fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Keep a package to patch once its version is running
+    pub(super) fn save(&self, version: &str, package: &[u8]) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.dir.join("base"), package)?;
+        std::fs::write(self.dir.join("base.version"), version)
+    }
+
+    fn load(&self, version: &str) -> Option<Vec<u8>> {
+        let saved = std::fs::read_to_string(self.dir.join("base.version")).ok()?;
+        if saved.trim() != version {
+            return None;
+        }
+        std::fs::read(self.dir.join("base")).ok()
+    }
+}
+
+/// The patch URL for going from the running version to `update`, if the
+/// manifest has one and the base package is still around
+pub(super) fn patch_url(update: &Update, base: &Base) -> Option<(String, Vec<u8>)> {
+    let patch = update
+        .raw_json
+        .get("deltas")?
+        .get(&update.current_version)?
+        .get(&update.target)?;
+    let patch: Patch = serde_json::from_value(patch.clone()).ok()?;
+    let base = base.load(&update.current_version)?;
+    Some((patch.url, base))
+}
+
+/// Download a patch and apply it to `base`, reporting bytes received as
+/// they arrive. The result is verified against the update's signature.
+pub(super) async fn download<F: FnMut(u64, Option<u64>)>(
+    update: &Update,
+    pubkey: &str,
+    url: &str,
+    base: &[u8],
+    mut on_progress: F,
+) -> Result<Vec<u8>, UpdateError> {
+    let mut response = reqwest::get(url).await?;
+    if !response.status().is_success() {
+        return Err(UpdateError::Status(response.status().as_u16()));
+    }
+    let total = response.content_length();
+    if total.is_some_and(|len| len > MAX_PATCH_BYTES) {
+        return Err(UpdateError::TooLarge);
+    }
+
+    let mut patch = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        patch.extend_from_slice(&chunk);
+        if patch.len() as u64 > MAX_PATCH_BYTES {
+            return Err(UpdateError::TooLarge);
+        }
+        on_progress(patch.len() as u64, total);
+    }
+
+    let base = base.to_vec();
+    let package = tauri::async_runtime::spawn_blocking(move || {
+        let mut package = Vec::new();
+        bsdiff::patch(&base, &mut patch.as_slice(), &mut package).map(|()| package)
+    })
+    .await
+    .map_err(|e| UpdateError::Patch(e.to_string()))?
+    .map_err(|e| UpdateError::Patch(e.to_string()))?;
+
+    verify(&package, &update.signature, pubkey)?;
+    Ok(package)
+}
+
+/// Check a package against a minisign signature, both base64 encoded as
+/// in the manifest and the updater config
+fn verify(package: &[u8], signature: &str, pubkey: &str) -> Result<(), UpdateError> {
+    let decode = |value: &str| {
+        base64::engine::general_purpose::STANDARD
+            .decode(value)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or(UpdateError::Signature)
+    };
+    let pubkey = PublicKey::decode(&decode(pubkey)?).map_err(|_| UpdateError::Signature)?;
+    let signature = Signature::decode(&decode(signature)?).map_err(|_| UpdateError::Signature)?;
+    pubkey
+        .verify(package, &signature, false)
+        .map_err(|_| UpdateError::Signature)
+}
@@ -0,0 +1,348 @@
+//! App updates
+//!
+//! Built on the updater plugin, which checks a release manifest, verifies
+//! minisign signatures and installs packages. On top of it:
+//! - release channels: the manifest is read from the channel in the
+//!   settings, `stable` unless `beta` is chosen
+//! - delta updates through bsdiff patches, see [`delta`]
+//! - checks and downloads in the background, reported with
+//!   "update-available", "update-progress" and "update-ready"; in metered
+//!   mode (see [`crate::data_usage`]) updates are found but not downloaded
+//! - installing straight away (and restarting) or when the app quits
+//!
+//! Updates are only offered by builds made with the updater's public key in
+//! `REDOUBT_UPDATER_PUBKEY`; others, such as distribution packages that
+//! update through the package manager, report updates as unavailable.
+
+mod delta;
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_updater::{Update, UpdaterExt};
+use url::Url;
+
+use crate::coalesce;
+use crate::data_usage::{self, Category};
+use crate::db::Database;
+use crate::settings;
+
+/// Public key release packages are signed with, baked in at build time
+pub const PUBKEY: Option<&str> = option_env!("REDOUBT_UPDATER_PUBKEY");
+
+/// Setting holding the release channel: `"stable"` or `"beta"`
+pub const CHANNEL_SETTING: &str = "updates.channel";
+/// Setting that stops checking and downloading in the background; on
+/// unless set to `false`
+pub const AUTO_UPDATE_SETTING: &str = "updates.auto_update";
+
+/// Release manifests, one per channel
+const MANIFEST_URL: &str =
+    "https://github.com/michaelpeterswa/redoubt/releases/download/updater/{channel}.json";
+
+/// Gives startup a head start before the first check
+const FIRST_CHECK_DELAY: Duration = Duration::from_secs(60);
+const CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum UpdateError {
+    #[error("updates aren't available in this build")]
+    Disabled,
+    #[error("no update has been found")]
+    NoUpdate,
+    #[error("the update hasn't been downloaded")]
+    NotDownloaded,
+    #[error("an update is already being downloaded")]
+    Busy,
+    #[error("update download failed with status {0}")]
+    Status(u16),
+    #[error("update patch is too large")]
+    TooLarge,
+    #[error("failed to apply update patch: {0}")]
+    Patch(String),
+    #[error("update signature doesn't match")]
+    Signature,
+    #[error("{0}")]
+    Updater(#[from] tauri_plugin_updater::Error),
+    #[error("update download failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    #[default]
+    Stable,
+    Beta,
+}
+
+impl Channel {
+    fn as_str(self) -> &'static str {
+        match self {
+            Channel::Stable => "stable",
+            Channel::Beta => "beta",
+        }
+    }
+}
+
+/// An available update; payload of "update-available" and "update-ready"
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateInfo {
+    pub version: String,
+    pub current_version: String,
+    pub channel: Channel,
+    pub notes: Option<String>,
+    /// RFC 3339
+    pub date: Option<String>,
+    /// A patch against the installed version can be downloaded instead of
+    /// the full package
+    pub delta: bool,
+}
+
+/// Payload of "update-progress"
+#[derive(Debug, Clone, Serialize)]
+struct UpdateProgress {
+    version: String,
+    received: u64,
+    total: Option<u64>,
+    delta: bool,
+}
+
+#[derive(Default)]
+pub struct Updates {
+    state: Mutex<State>,
+    downloading: AtomicBool,
+}
+
+#[derive(Default)]
+struct State {
+    update: Option<(Update, UpdateInfo)>,
+    /// Verified package for `update`
+    package: Option<Vec<u8>>,
+    install_on_quit: bool,
+}
+
+impl Updates {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Look for an update on the chosen channel
+/// Emits "update-available" if there is one
+pub async fn check(app: &AppHandle) -> Result<Option<UpdateInfo>, UpdateError> {
+    let pubkey = PUBKEY.ok_or(UpdateError::Disabled)?;
+    let channel = channel(&app.state::<Database>())?;
+    let url = Url::parse(&MANIFEST_URL.replace("{channel}", channel.as_str()))
+        .expect("manifest URL is valid");
+
+    let updater = app
+        .updater_builder()
+        .pubkey(pubkey)
+        .endpoints(vec![url])?
+        .build()?;
+    let Some(update) = updater.check().await? else {
+        app.state::<Updates>().lock().update = None;
+        return Ok(None);
+    };
+
+    let info = UpdateInfo {
+        version: update.version.clone(),
+        current_version: update.current_version.clone(),
+        channel,
+        notes: update.body.clone(),
+        date: update.date.and_then(|date| {
+            chrono::DateTime::from_timestamp(date.unix_timestamp(), 0).map(|d| d.to_rfc3339())
+        }),
+        delta: delta::patch_url(&update, &delta::Base::new(&updates_dir(app))).is_some(),
+    };
+
+    let updates = app.state::<Updates>();
+    let mut state = updates.lock();
+    let known = state
+        .update
+        .as_ref()
+        .is_some_and(|(_, known)| known.version == info.version);
+    if !known {
+        state.update = Some((update, info.clone()));
+        state.package = None;
+        let _ = app.emit("update-available", info.clone());
+    }
+    Ok(Some(info))
+}
+
+/// Download the update found by [`check`], from a patch if possible
+/// Emits "update-progress" while downloading and "update-ready" once the
+/// package has been verified
+pub async fn download(app: &AppHandle) -> Result<(), UpdateError> {
+    let pubkey = PUBKEY.ok_or(UpdateError::Disabled)?;
+    let updates = app.state::<Updates>();
+    let (update, info) = {
+        let state = updates.lock();
+        if state.package.is_some() {
+            return Ok(());
+        }
+        state.update.clone().ok_or(UpdateError::NoUpdate)?
+    };
+    if updates.downloading.swap(true, Ordering::SeqCst) {
+        return Err(UpdateError::Busy);
+    }
+    let result = fetch(app, pubkey, &update).await;
+    updates.downloading.store(false, Ordering::SeqCst);
+    let package = result?;
+
+    let mut state = updates.lock();
+    // A newer update may have been found while this one downloaded
+    if state
+        .update
+        .as_ref()
+        .is_some_and(|(_, current)| current.version == info.version)
+    {
+        state.package = Some(package);
+        let _ = app.emit("update-ready", info);
+    }
+    Ok(())
+}
+
+/// Install the downloaded update and restart into it
+pub fn install_now(app: &AppHandle) -> Result<(), UpdateError> {
+    install(app)?;
+    app.restart();
+}
+
+/// Install the downloaded update once the app quits, or stop doing so
+pub fn set_install_on_quit(app: &AppHandle, enabled: bool) -> Result<(), UpdateError> {
+    let updates = app.state::<Updates>();
+    let mut state = updates.lock();
+    if enabled && state.package.is_none() {
+        return Err(UpdateError::NotDownloaded);
+    }
+    state.install_on_quit = enabled;
+    Ok(())
+}
+
+/// Install the update if it was set to be installed on quit; call on exit
+pub fn on_exit(app: &AppHandle) {
+    if !app.state::<Updates>().lock().install_on_quit {
+        return;
+    }
+    if let Err(e) = install(app) {
+        log::error!("Failed to install update: {}", e);
+    }
+}
+
+/// Check for updates and download them in the background for as long as
+/// the app runs
+pub fn spawn(app: &AppHandle) {
+    if PUBKEY.is_none() {
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(FIRST_CHECK_DELAY).await;
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if !auto_update(&app.state::<Database>()) {
+                continue;
+            }
+            let result = match check(&app).await {
+                Ok(Some(_)) if data_usage::is_metered(&app.state::<Database>()) => Ok(()),
+                Ok(Some(_)) => download(&app).await,
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                log::warn!("Background update failed: {}", e);
+            }
+        }
+    });
+}
+
+fn install(app: &AppHandle) -> Result<(), UpdateError> {
+    let updates = app.state::<Updates>();
+    let (update, package) = {
+        let state = updates.lock();
+        let (update, _) = state.update.clone().ok_or(UpdateError::NoUpdate)?;
+        let package = state.package.clone().ok_or(UpdateError::NotDownloaded)?;
+        (update, package)
+    };
+    // Kept so the next update can be a patch against it
+    if let Err(e) = delta::Base::new(&updates_dir(app)).save(&update.version, &package) {
+        log::warn!("Failed to keep update package for delta updates: {}", e);
+    }
+    update.install(&package)?;
+    Ok(())
+}
+
+async fn fetch(app: &AppHandle, pubkey: &str, update: &Update) -> Result<Vec<u8>, UpdateError> {
+    let progress = |received: u64, total: Option<u64>, delta: bool| {
+        coalesce::emit(
+            app,
+            "update-progress",
+            &update.version,
+            UpdateProgress {
+                version: update.version.clone(),
+                received,
+                total,
+                delta,
+            },
+        );
+    };
+
+    if let Some((url, base)) = delta::patch_url(update, &delta::Base::new(&updates_dir(app))) {
+        let mut counted = 0;
+        let patched = delta::download(update, pubkey, &url, &base, |received, total| {
+            data_usage::record(app, Category::Updates, 0, received - counted);
+            counted = received;
+            progress(received, total, true)
+        })
+        .await;
+        match patched {
+            Ok(package) => return Ok(package),
+            Err(e) => log::warn!("Delta update failed, downloading the full package: {}", e),
+        }
+    }
+
+    let mut received = 0;
+    let package = update
+        .download(
+            |chunk, total| {
+                received += chunk as u64;
+                data_usage::record(app, Category::Updates, 0, chunk as u64);
+                progress(received, total, false);
+            },
+            || {},
+        )
+        .await?;
+    Ok(package)
+}
+
+fn updates_dir(app: &AppHandle) -> PathBuf {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join("updates"))
+        .unwrap_or_else(|_| std::env::temp_dir().join("redoubt-updates"))
+}
+
+fn channel(db: &Database) -> rusqlite::Result<Channel> {
+    Ok(db
+        .with(|conn| settings::get_value(conn, CHANNEL_SETTING))?
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
+
+fn auto_update(db: &Database) -> bool {
+    db.with(|conn| settings::get_value(conn, AUTO_UPDATE_SETTING))
+        .map_err(|e| log::error!("Failed to read auto update setting: {}", e))
+        .ok()
+        .flatten()
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+}
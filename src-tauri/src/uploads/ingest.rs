@@ -0,0 +1,316 @@
+//! Dropped files
+//!
+//! Files dropped on the window are looked at here before the frontend sees
+//! them: paths are resolved, sizes checked against the attachment limit,
+//! contents hashed and images decoded for their dimensions, placeholder and
+//! thumbnail. The frontend gets descriptors it can show in the composer
+//! straight away and later pass to `queue_upload`, instead of `File`
+//! objects it would have to read into the webview. Files that can't be
+//! sent are rejected with a reason up front rather than failing once the
+//! message is sent.
+//!
+//! Only files the user handed over this way, by dropping, picking, pasting,
+//! sharing or recording them, are remembered in [`Offered`] and can be
+//! queued; the webview can't name any other file on disk. Drafts only keep
+//! attachments that were offered, and loading a draft offers them again,
+//! so they survive a restart.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::{MAX_ATTACHMENT_BYTES, TRANSCODE_LARGE_VIDEOS_SETTING};
+use crate::db::Database;
+use crate::media::thumbnail::{self, ImageSource, Thumbnail, ThumbnailSize};
+use crate::media::{self, placeholder};
+use crate::profiles::Profiles;
+use crate::settings;
+
+/// Setting holding a smaller attachment limit in bytes; the server's limit
+/// applies when unset or larger
+pub const MAX_SIZE_SETTING: &str = "uploads.max_size";
+
+/// Most files taken from one drop; the rest are rejected
+const MAX_FILES: usize = 32;
+/// Enough of a file to tell its type
+const HEAD_BYTES: u64 = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentKind {
+    Image,
+    Video,
+    Audio,
+    File,
+}
+
+/// What the composer needs to show a file and send it
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentDescriptor {
+    pub path: PathBuf,
+    pub filename: String,
+    pub mime_type: String,
+    pub kind: AttachmentKind,
+    pub size: u64,
+    /// Hex SHA-256 of the file as it is on disk
+    pub sha256: String,
+    /// Upright dimensions, for images
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub blurhash: Option<String>,
+    pub thumbnail: Option<Thumbnail>,
+    /// A video over the limit that will be transcoded when queued
+    pub transcode: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// The outcome for a set of files; also the payload of "files-dropped"
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Ingested {
+    pub attachments: Vec<AttachmentDescriptor>,
+    pub rejected: Vec<RejectedFile>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IngestError {
+    #[error("{0} is not a file")]
+    NotAFile(String),
+    #[error("{0} is larger than the {1} MB attachment limit")]
+    TooLarge(String, u64),
+    #[error("only {0} files can be attached at once")]
+    TooMany(usize),
+    #[error("failed to read file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Files the user handed to the app, which `queue_upload` may send
+#[derive(Default)]
+pub struct Offered {
+    paths: Mutex<HashSet<PathBuf>>,
+}
+
+impl Offered {
+    /// Remember a file the backend produced or the user picked
+    pub fn add(&self, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.lock().insert(path);
+    }
+
+    /// Remember every file accepted in `ingested`
+    pub fn add_ingested(&self, ingested: &Ingested) {
+        let mut paths = self.lock();
+        for attachment in &ingested.attachments {
+            paths.insert(attachment.path.clone());
+        }
+    }
+
+    /// Whether `path` was handed over, however it's spelled
+    pub fn contains(&self, path: &Path) -> bool {
+        fs::canonicalize(path).is_ok_and(|path| self.lock().contains(&path))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashSet<PathBuf>> {
+        self.paths.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Limits files are checked against
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_size: u64,
+    /// Videos over `max_size` are accepted because they'll be transcoded
+    pub transcode_videos: bool,
+}
+
+impl Limits {
+    pub fn load(db: &Database) -> rusqlite::Result<Self> {
+        let max_size = db
+            .with(|conn| settings::get_value(conn, MAX_SIZE_SETTING))?
+            .and_then(|v| v.as_u64())
+            .map_or(MAX_ATTACHMENT_BYTES, |size| size.min(MAX_ATTACHMENT_BYTES));
+        let transcode_videos = db
+            .with(|conn| settings::get_value(conn, TRANSCODE_LARGE_VIDEOS_SETTING))?
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+        Ok(Self {
+            max_size,
+            transcode_videos,
+        })
+    }
+}
+
+/// Describe files for the composer, each on its own blocking task
+pub async fn ingest(paths: Vec<PathBuf>, limits: Limits, thumbnail_dir: PathBuf) -> Ingested {
+    let mut ingested = Ingested::default();
+
+    let mut tasks = Vec::new();
+    for (i, path) in paths.into_iter().enumerate() {
+        if i >= MAX_FILES {
+            ingested.rejected.push(RejectedFile {
+                path,
+                reason: IngestError::TooMany(MAX_FILES).to_string(),
+            });
+            continue;
+        }
+        let dir = thumbnail_dir.clone();
+        let file = path.clone();
+        let task = tauri::async_runtime::spawn_blocking(move || describe(&file, limits, &dir));
+        tasks.push((path, task));
+    }
+
+    for (path, task) in tasks {
+        let result = task
+            .await
+            .map_err(|e| format!("{}", e))
+            .and_then(|r| r.map_err(|e| format!("{}", e)));
+        match result {
+            Ok(attachment) => ingested.attachments.push(attachment),
+            Err(reason) => ingested.rejected.push(RejectedFile { path, reason }),
+        }
+    }
+
+    ingested
+}
+
+/// Describe files with the current profile's limits and remember the ones
+/// accepted, logging what keeps that from happening
+pub async fn ingest_for(app: &AppHandle, paths: Vec<PathBuf>) -> Option<Ingested> {
+    let limits = match Limits::load(&app.state::<Database>()) {
+        Ok(limits) => limits,
+        Err(e) => {
+            log::error!("Failed to read upload limits: {}", e);
+            return None;
+        }
+    };
+    let thumbnail_dir = app
+        .state::<Profiles>()
+        .active_cache_dir()
+        .join("thumbnails");
+
+    let ingested = ingest(paths, limits, thumbnail_dir).await;
+    app.state::<Offered>().add_ingested(&ingested);
+    Some(ingested)
+}
+
+/// Describe files dropped on a window and emit "files-dropped" with the result
+pub fn handle_drop(app: &AppHandle, paths: Vec<PathBuf>) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Some(ingested) = ingest_for(&app, paths).await {
+            let _ = app.emit("files-dropped", ingested);
+        }
+    });
+}
+
+fn describe(
+    path: &Path,
+    limits: Limits,
+    thumbnail_dir: &Path,
+) -> Result<AttachmentDescriptor, IngestError> {
+    // Some file managers drop symlinks or paths with `..` in them
+    let path = fs::canonicalize(path)?;
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+
+    let metadata = fs::metadata(&path)?;
+    if !metadata.is_file() {
+        return Err(IngestError::NotAFile(filename));
+    }
+    let size = metadata.len();
+
+    let mut file = File::open(&path)?;
+    let mut head = Vec::new();
+    file.by_ref().take(HEAD_BYTES).read_to_end(&mut head)?;
+
+    let detected = infer::get(&head);
+    let mime_type = detected
+        .map(|t| t.mime_type().to_string())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let kind = match detected.map(|t| t.matcher_type()) {
+        Some(infer::MatcherType::Image) => AttachmentKind::Image,
+        Some(infer::MatcherType::Video) => AttachmentKind::Video,
+        Some(infer::MatcherType::Audio) => AttachmentKind::Audio,
+        _ => AttachmentKind::File,
+    };
+
+    let transcode =
+        kind == AttachmentKind::Video && size > limits.max_size && limits.transcode_videos;
+    if size > limits.max_size && !transcode {
+        return Err(IngestError::TooLarge(
+            filename,
+            limits.max_size / (1024 * 1024),
+        ));
+    }
+
+    let mut descriptor = AttachmentDescriptor {
+        path,
+        filename,
+        mime_type,
+        kind,
+        size,
+        sha256: String::new(),
+        width: None,
+        height: None,
+        blurhash: None,
+        thumbnail: None,
+        transcode,
+    };
+
+    if kind != AttachmentKind::Image {
+        let mut hasher = Sha256::new();
+        hasher.update(&head);
+        std::io::copy(&mut file, &mut hasher)?;
+        descriptor.sha256 = hex(&hasher.finalize());
+        return Ok(descriptor);
+    }
+
+    // Images are under the limit, so read them whole and decode them once
+    let mut bytes = head;
+    file.read_to_end(&mut bytes)?;
+    descriptor.sha256 = hex(&Sha256::digest(&bytes));
+
+    // Formats we can't decode are still sent, just without a preview
+    match media::decode(&bytes) {
+        Ok(image) => {
+            descriptor.width = Some(image.width());
+            descriptor.height = Some(image.height());
+            descriptor.blurhash = placeholder::blurhash(&image)
+                .map_err(|e| log::warn!("No blurhash for {}: {}", descriptor.filename, e))
+                .ok();
+
+            // Keyed like `get_thumbnail`, so the composer's later request hits the cache
+            let source = ImageSource::File {
+                path: descriptor.path.clone(),
+            };
+            let key = thumbnail::cache_key(
+                &source,
+                &thumbnail::file_version(&metadata),
+                ThumbnailSize::Thumbnail.as_str(),
+            );
+            descriptor.thumbnail =
+                thumbnail::generate_from(thumbnail_dir, &key, image, ThumbnailSize::Thumbnail)
+                    .map_err(|e| log::warn!("No thumbnail for {}: {}", descriptor.filename, e))
+                    .ok();
+        }
+        Err(e) => log::warn!("No preview for {}: {}", descriptor.filename, e),
+    }
+
+    Ok(descriptor)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,423 @@
+//! Attachment uploads
+//!
+//! Files queued for a message are prepared in the background (type
+//! detection, video transcoding, image dimensions and blurhash
+//! placeholder, metadata stripping) and then posted to the server. Each
+//! upload runs as its own task and can be cancelled until the server has
+//! accepted it. Progress is reported through "upload-status" events
+//! carrying the upload's current state.
+
+pub mod ingest;
+pub mod strip;
+pub mod transcode;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio::sync::watch;
+
+use crate::api::{ApiClient, ApiError, Attachment};
+use crate::auth::{self, AuthError};
+use crate::coalesce;
+use crate::data_usage::{self, Category};
+use crate::instances::Instance;
+use crate::media;
+use crate::temp_files::{self, TempFileError};
+use transcode::TranscodeError;
+
+/// Setting that turns metadata stripping off; on unless set to `false`
+pub const STRIP_METADATA_SETTING: &str = "privacy.strip_metadata";
+/// Setting that stops videos over the attachment limit being transcoded
+/// automatically; on unless set to `false`
+pub const TRANSCODE_LARGE_VIDEOS_SETTING: &str = "media.transcode_large_videos";
+
+/// Minimum time between progress updates while transcoding
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Largest attachment the server accepts
+pub const MAX_ATTACHMENT_BYTES: u64 = 25 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadStatus {
+    Queued,
+    Preparing,
+    Transcoding,
+    Uploading,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl UploadStatus {
+    fn is_finished(self) -> bool {
+        matches!(
+            self,
+            UploadStatus::Done | UploadStatus::Failed | UploadStatus::Cancelled
+        )
+    }
+}
+
+/// What's known about a file once it has been prepared
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadMetadata {
+    pub filename: String,
+    pub mime_type: String,
+    pub size: u64,
+    /// Upright dimensions, for images
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub blurhash: Option<String>,
+}
+
+impl UploadMetadata {
+    /// Extra form fields sent with the file
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            fields.push(("width", width.to_string()));
+            fields.push(("height", height.to_string()));
+        }
+        if let Some(blurhash) = &self.blurhash {
+            fields.push(("blurhash", blurhash.clone()));
+        }
+        fields
+    }
+}
+
+/// An upload and its current state; also the payload of "upload-status"
+#[derive(Debug, Clone, Serialize)]
+pub struct Upload {
+    pub id: String,
+    pub instance_id: String,
+    pub message_id: String,
+    pub path: PathBuf,
+    /// Position among the message's attachments
+    pub order: Option<u32>,
+    /// Remove location and device metadata before sending
+    pub strip_metadata: bool,
+    pub status: UploadStatus,
+    /// Fraction of the current step done, while transcoding
+    pub progress: Option<f64>,
+    pub metadata: Option<UploadMetadata>,
+    /// Set once the server has accepted the file
+    pub attachment: Option<Attachment>,
+    pub error: Option<String>,
+}
+
+/// A file to upload to a message
+#[derive(Debug, Clone)]
+pub struct UploadRequest {
+    pub message_id: String,
+    pub path: PathBuf,
+    pub order: Option<u32>,
+    pub strip_metadata: bool,
+    pub transcode: Option<TranscodePlan>,
+}
+
+/// When and how to re-encode a video before sending it
+#[derive(Debug, Clone)]
+pub struct TranscodePlan {
+    pub options: transcode::TranscodeOptions,
+    /// Transcode even if the video is already under the attachment limit
+    pub always: bool,
+}
+
+/// A file ready to be sent
+struct Prepared {
+    bytes: Vec<u8>,
+    metadata: UploadMetadata,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    #[error("{0} is not a file")]
+    NotAFile(String),
+    #[error("{0} is larger than the {1} MB attachment limit")]
+    TooLarge(String, u64),
+    #[error("failed to read file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("couldn't remove metadata from {0}: {1}")]
+    Strip(String, strip::StripError),
+    #[error("{0}")]
+    Transcode(#[from] TranscodeError),
+    #[error("{0}")]
+    TempFile(#[from] TempFileError),
+    #[error("{0}")]
+    Task(#[from] tauri::Error),
+    #[error("{0}")]
+    Auth(#[from] AuthError),
+    #[error("invalid attachment type: {0}")]
+    MimeType(#[from] reqwest::Error),
+    #[error("{0}")]
+    Api(#[from] ApiError),
+}
+
+struct Job {
+    upload: Upload,
+    cancel: watch::Sender<bool>,
+}
+
+#[derive(Default)]
+pub struct Uploads {
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+impl Uploads {
+    /// Queue a file for upload to a message and start working on it
+    pub fn queue(
+        &self,
+        app: &AppHandle,
+        profile_id: &str,
+        instance: Instance,
+        request: UploadRequest,
+    ) -> Upload {
+        let upload = Upload {
+            id: uuid::Uuid::new_v4().to_string(),
+            instance_id: instance.id.clone(),
+            message_id: request.message_id,
+            path: request.path,
+            order: request.order,
+            strip_metadata: request.strip_metadata,
+            status: UploadStatus::Queued,
+            progress: None,
+            metadata: None,
+            attachment: None,
+            error: None,
+        };
+
+        let (cancel, cancelled) = watch::channel(false);
+        self.lock().insert(
+            upload.id.clone(),
+            Job {
+                upload: upload.clone(),
+                cancel,
+            },
+        );
+        coalesce::emit(app, "upload-status", &upload.id, &upload);
+
+        tauri::async_runtime::spawn(run(
+            app.clone(),
+            profile_id.to_string(),
+            instance,
+            upload.id.clone(),
+            request.transcode,
+            cancelled,
+        ));
+
+        upload
+    }
+
+    /// Uploads that haven't finished yet
+    pub fn list(&self) -> Vec<Upload> {
+        self.lock().values().map(|job| job.upload.clone()).collect()
+    }
+
+    /// Stop an upload; returns false if it isn't running
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.lock().get(id) {
+            Some(job) => {
+                let _ = job.cancel.send(true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply a change to an upload and report its new state
+    fn update(&self, app: &AppHandle, id: &str, f: impl FnOnce(&mut Upload)) {
+        let mut jobs = self.lock();
+        let Some(job) = jobs.get_mut(id) else {
+            return;
+        };
+        f(&mut job.upload);
+        let upload = job.upload.clone();
+        if upload.status.is_finished() {
+            jobs.remove(id);
+        }
+        drop(jobs);
+
+        coalesce::emit(app, "upload-status", &upload.id, &upload);
+    }
+
+    fn get(&self, id: &str) -> Option<Upload> {
+        self.lock().get(id).map(|job| job.upload.clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Job>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+async fn run(
+    app: AppHandle,
+    profile_id: String,
+    instance: Instance,
+    id: String,
+    transcode: Option<TranscodePlan>,
+    mut cancelled: watch::Receiver<bool>,
+) {
+    let uploads = app.state::<Uploads>();
+    let Some(upload) = uploads.get(&id) else {
+        return;
+    };
+
+    // Dropping `process` on cancel also kills a running ffmpeg
+    let result = tokio::select! {
+        result = process(&app, &profile_id, &instance, &upload, transcode.as_ref()) => result,
+        _ = cancelled.changed() => {
+            uploads.update(&app, &id, |u| u.status = UploadStatus::Cancelled);
+            return;
+        }
+    };
+
+    uploads.update(&app, &id, |u| match result {
+        Ok(attachment) => {
+            u.status = UploadStatus::Done;
+            u.attachment = Some(attachment);
+        }
+        Err(e) => {
+            log::warn!("Upload of {} failed: {}", u.path.display(), e);
+            u.status = UploadStatus::Failed;
+            u.error = Some(e.to_string());
+        }
+    });
+}
+
+async fn process(
+    app: &AppHandle,
+    profile_id: &str,
+    instance: &Instance,
+    upload: &Upload,
+    transcode: Option<&TranscodePlan>,
+) -> Result<Attachment, UploadError> {
+    let uploads = app.state::<Uploads>();
+    uploads.update(app, &upload.id, |u| u.status = UploadStatus::Preparing);
+
+    let mut path = upload.path.clone();
+    // Deleted once it's been read, or when this is dropped on cancel
+    let mut transcoded = None;
+    let mut filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| UploadError::NotAFile(path.display().to_string()))?;
+
+    if let Some(plan) = transcode {
+        if needs_transcode(&path, plan).await? {
+            let extension = plan.options.codec.extension();
+            let output = temp_files::create(app, extension)?;
+            uploads.update(app, &upload.id, |u| {
+                u.status = UploadStatus::Transcoding;
+                u.progress = Some(0.0);
+            });
+
+            let mut last_progress = Instant::now();
+            transcode::transcode(&path, output.path(), &plan.options, |progress| {
+                if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                    last_progress = Instant::now();
+                    uploads.update(app, &upload.id, |u| u.progress = Some(progress));
+                }
+            })
+            .await?;
+
+            filename = Path::new(&filename)
+                .with_extension(extension)
+                .to_string_lossy()
+                .into_owned();
+            path = output.path().to_path_buf();
+            transcoded = Some(output);
+        }
+    }
+
+    let strip_metadata = upload.strip_metadata;
+    let prepared =
+        tauri::async_runtime::spawn_blocking(move || prepare(&path, filename, strip_metadata))
+            .await??;
+    drop(transcoded);
+
+    uploads.update(app, &upload.id, |u| {
+        u.status = UploadStatus::Uploading;
+        u.progress = None;
+        u.metadata = Some(prepared.metadata.clone());
+    });
+
+    let token = auth::access_token(app, profile_id, instance).await?;
+
+    let mut fields = prepared.metadata.fields();
+    if let Some(order) = upload.order {
+        fields.push(("order", order.to_string()));
+    }
+    let size = prepared.bytes.len() as u64;
+    let file = reqwest::multipart::Part::bytes(prepared.bytes)
+        .file_name(prepared.metadata.filename.clone())
+        .mime_str(&prepared.metadata.mime_type)?;
+
+    let attachment = ApiClient::new(&instance.url)
+        .upload_attachment(&token, &upload.message_id, file, fields)
+        .await;
+    // Went out even if the server then refused it
+    data_usage::record_for(app, profile_id, Category::Media, size, 0);
+    Ok(attachment?)
+}
+
+/// Whether a video should be transcoded before sending; other files never are
+async fn needs_transcode(path: &Path, plan: &TranscodePlan) -> Result<bool, UploadError> {
+    let mut head = [0u8; 8192];
+    let mut file = tokio::fs::File::open(path).await?;
+    let read = tokio::io::AsyncReadExt::read(&mut file, &mut head).await?;
+    if !infer::is_video(&head[..read]) {
+        return Ok(false);
+    }
+
+    Ok(plan.always || file.metadata().await?.len() > MAX_ATTACHMENT_BYTES)
+}
+
+/// Read a file and work out what's needed to send it as `filename`
+fn prepare(path: &Path, filename: String, strip_metadata: bool) -> Result<Prepared, UploadError> {
+    let bytes = std::fs::read(path)?;
+    if bytes.len() as u64 > MAX_ATTACHMENT_BYTES {
+        return Err(UploadError::TooLarge(
+            filename,
+            MAX_ATTACHMENT_BYTES / (1024 * 1024),
+        ));
+    }
+
+    let mime_type = infer::get(&bytes)
+        .map(|t| t.mime_type().to_string())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    let bytes = if strip_metadata {
+        strip::strip(bytes, &mime_type).map_err(|e| UploadError::Strip(filename.clone(), e))?
+    } else {
+        bytes
+    };
+
+    let mut metadata = UploadMetadata {
+        filename,
+        mime_type,
+        size: bytes.len() as u64,
+        width: None,
+        height: None,
+        blurhash: None,
+    };
+
+    // The placeholder is nice to have; formats we can't decode are sent without one
+    if infer::is_image(&bytes) {
+        match media::decode(&bytes) {
+            Ok(image) => {
+                metadata.width = Some(image.width());
+                metadata.height = Some(image.height());
+                metadata.blurhash = media::placeholder::blurhash(&image)
+                    .map_err(|e| log::warn!("No blurhash for {}: {}", path.display(), e))
+                    .ok();
+            }
+            Err(e) => log::warn!("No placeholder for {}: {}", path.display(), e),
+        }
+    }
+
+    Ok(Prepared { bytes, metadata })
+}
@@ -0,0 +1,635 @@
+//! Metadata stripping
+//!
+//! Removes EXIF, XMP, IPTC and comments from JPEG, PNG and WebP images, the
+//! Exif and XMP items from HEIF/HEIC and AVIF images, the EXIF, GPS and
+//! descriptive tags from TIFF images, and the user-data and metadata atoms
+//! from MP4/QuickTime videos, which is where cameras and phones record
+//! location, device and author details. Image data is copied byte for byte;
+//! nothing is re-encoded. A JPEG's EXIF orientation is kept so the photo
+//! doesn't arrive sideways; HEIF keeps its orientation in a separate
+//! property, which is left alone.
+//!
+//! Other formats are passed through unchanged.
+
+#[derive(Debug, thiserror::Error)]
+#[error("malformed {0} file")]
+pub struct StripError(&'static str);
+
+/// Strip metadata from a file of the given MIME type
+pub fn strip(bytes: Vec<u8>, mime_type: &str) -> Result<Vec<u8>, StripError> {
+    match mime_type {
+        "image/jpeg" => jpeg(&bytes),
+        "image/png" => png(&bytes),
+        "image/webp" => webp(&bytes),
+        "image/heif" | "image/heic" | "image/avif" => heif(bytes),
+        "image/tiff" => tiff(bytes),
+        "video/mp4" | "video/quicktime" | "video/x-m4v" | "audio/mp4" | "audio/m4a" => mp4(bytes),
+        _ => Ok(bytes),
+    }
+}
+
+fn jpeg(bytes: &[u8]) -> Result<Vec<u8>, StripError> {
+    const ERROR: StripError = StripError("JPEG");
+
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return Err(ERROR);
+    }
+
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    let mut orientation = None;
+    let mut orientation_at = out.len();
+    let mut pos = 2;
+
+    loop {
+        // Segments start with 0xFF, optionally padded with more 0xFF bytes
+        if bytes.get(pos) != Some(&0xFF) {
+            return Err(ERROR);
+        }
+        while bytes.get(pos) == Some(&0xFF) {
+            pos += 1;
+        }
+        let marker = *bytes.get(pos).ok_or(ERROR)?;
+        pos += 1;
+
+        match marker {
+            // End of image; anything after it (e.g. an embedded secondary
+            // image with its own EXIF) is dropped
+            0xD9 => {
+                out.extend_from_slice(&[0xFF, 0xD9]);
+                break;
+            }
+            // Markers without a payload
+            0x01 | 0xD0..=0xD7 => {
+                out.extend_from_slice(&[0xFF, marker]);
+                continue;
+            }
+            _ => {}
+        }
+
+        let length = u16::from_be_bytes([
+            *bytes.get(pos).ok_or(ERROR)?,
+            *bytes.get(pos + 1).ok_or(ERROR)?,
+        ]) as usize;
+        let end = pos + length;
+        if length < 2 || end > bytes.len() {
+            return Err(ERROR);
+        }
+        let payload = &bytes[pos + 2..end];
+
+        let keep = match marker {
+            // JFIF header; the orientation tag goes right after it
+            0xE0 => {
+                orientation_at = out.len() + 2 + length;
+                true
+            }
+            0xE1 => {
+                if payload.starts_with(b"Exif\0\0") {
+                    orientation = orientation.or_else(|| exif_orientation(&payload[6..]));
+                }
+                false
+            }
+            // ICC colour profile, needed to show colours correctly
+            0xE2 => payload.starts_with(b"ICC_PROFILE\0"),
+            // Adobe colour transform flags
+            0xEE => true,
+            0xE3..=0xEF | 0xFE => false,
+            _ => true,
+        };
+
+        if keep {
+            out.extend_from_slice(&[0xFF, marker]);
+            out.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+
+        // Start of scan: copy entropy-coded data up to the next real marker
+        if marker == 0xDA {
+            let start = pos;
+            while pos + 1 < bytes.len()
+                && !(bytes[pos] == 0xFF
+                    && bytes[pos + 1] != 0
+                    && !(0xD0..=0xD7).contains(&bytes[pos + 1]))
+            {
+                pos += 1;
+            }
+            if pos + 1 >= bytes.len() {
+                return Err(ERROR);
+            }
+            out.extend_from_slice(&bytes[start..pos]);
+        }
+    }
+
+    if let Some(orientation) = orientation.filter(|&o| o != 1) {
+        let segment = orientation_segment(orientation);
+        out.splice(orientation_at..orientation_at, segment);
+    }
+
+    Ok(out)
+}
+
+/// Orientation tag from a TIFF-structured EXIF block
+fn exif_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |at: usize| -> Option<u16> {
+        let b = [*tiff.get(at)?, *tiff.get(at + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    };
+    let u32_at = |at: usize| -> Option<u32> {
+        let b: [u8; 4] = tiff.get(at..at + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let count = u16_at(ifd)? as usize;
+    (0..count)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == Some(0x0112))
+        .and_then(|entry| u16_at(entry + 8))
+        .filter(|o| (1..=8).contains(o))
+}
+
+/// APP1 segment holding an EXIF block with nothing but the orientation tag
+fn orientation_segment(orientation: u16) -> Vec<u8> {
+    let mut segment = vec![0xFF, 0xE1, 0x00, 34];
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(b"MM\0\x2A\0\0\0\x08");
+    segment.extend_from_slice(&[0x00, 0x01]);
+    segment.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+    segment.extend_from_slice(&orientation.to_be_bytes());
+    segment.extend_from_slice(&[0x00, 0x00]);
+    segment.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+    segment
+}
+
+fn png(bytes: &[u8]) -> Result<Vec<u8>, StripError> {
+    const ERROR: StripError = StripError("PNG");
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    if !bytes.starts_with(SIGNATURE) {
+        return Err(ERROR);
+    }
+
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(SIGNATURE);
+    let mut pos = SIGNATURE.len();
+
+    while pos < bytes.len() {
+        let header = bytes.get(pos..pos + 8).ok_or(ERROR)?;
+        let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let kind = &header[4..8];
+        let end = pos + 12 + length;
+        if end > bytes.len() {
+            return Err(ERROR);
+        }
+
+        if !matches!(kind, b"tEXt" | b"zTXt" | b"iTXt" | b"eXIf" | b"tIME") {
+            out.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+
+        if kind == b"IEND" {
+            break;
+        }
+    }
+
+    Ok(out)
+}
+
+fn webp(bytes: &[u8]) -> Result<Vec<u8>, StripError> {
+    const ERROR: StripError = StripError("WebP");
+    const VP8X_EXIF: u8 = 0x08;
+    const VP8X_XMP: u8 = 0x04;
+
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WEBP" {
+        return Err(ERROR);
+    }
+
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[..12]);
+    let mut pos = 12;
+
+    while pos < bytes.len() {
+        let header = bytes.get(pos..pos + 8).ok_or(ERROR)?;
+        let kind = &header[..4];
+        let length = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+        // Chunks are padded to an even length
+        let end = (pos + 8 + length + (length & 1)).min(bytes.len());
+        if pos + 8 + length > bytes.len() {
+            return Err(ERROR);
+        }
+
+        match kind {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" => {
+                let start = out.len();
+                out.extend_from_slice(&bytes[pos..end]);
+                if let Some(flags) = out.get_mut(start + 8) {
+                    *flags &= !(VP8X_EXIF | VP8X_XMP);
+                }
+            }
+            _ => out.extend_from_slice(&bytes[pos..end]),
+        }
+        pos = end;
+    }
+
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    Ok(out)
+}
+
+/// Blank out the Exif and XMP items of a HEIF or AVIF image in place. The
+/// items are listed in the top-level `meta` box, whose `iloc` says where
+/// their data is, usually in `mdat`; only that data is zeroed, so every
+/// offset in the file stays valid.
+fn heif(mut bytes: Vec<u8>) -> Result<Vec<u8>, StripError> {
+    const ERROR: StripError = StripError("HEIF");
+
+    let mut pos = 0;
+    let (meta, meta_end) = loop {
+        let (kind, body, end) = box_at(&bytes, pos, bytes.len()).ok_or(ERROR)?;
+        if &kind == b"meta" {
+            break (body, end);
+        }
+        pos = end;
+    };
+
+    // `meta` is a full box, so its children follow a version and flags
+    let mut pos = meta + 4;
+    let (mut items, mut iloc, mut idat) = (None, None, None);
+    while pos < meta_end {
+        let (kind, body, end) = box_at(&bytes, pos, meta_end).ok_or(ERROR)?;
+        match &kind {
+            b"iinf" => items = Some(metadata_items(&bytes[body..end]).ok_or(ERROR)?),
+            b"iloc" => iloc = Some((body, end)),
+            b"idat" => idat = Some((body, end)),
+            _ => {}
+        }
+        pos = end;
+    }
+    let Some(items) = items.filter(|items| !items.is_empty()) else {
+        return Ok(bytes);
+    };
+    let (iloc, iloc_end) = iloc.ok_or(ERROR)?;
+    let extents = item_extents(&bytes[iloc..iloc_end], &items).ok_or(ERROR)?;
+
+    for (construction_method, offset, length) in extents {
+        let (start, limit) = match construction_method {
+            // From the start of the file
+            0 => (offset, bytes.len()),
+            // From the start of `idat`
+            1 => {
+                let (idat, idat_end) = idat.ok_or(ERROR)?;
+                ((idat as u64).checked_add(offset).ok_or(ERROR)?, idat_end)
+            }
+            // Built out of other items, which aren't followed
+            _ => return Err(ERROR),
+        };
+        let end = match length {
+            0 => limit as u64,
+            length => start.checked_add(length).ok_or(ERROR)?,
+        };
+        if start > end || end > limit as u64 {
+            return Err(ERROR);
+        }
+        bytes[start as usize..end as usize].fill(0);
+    }
+    Ok(bytes)
+}
+
+/// Kind, body start and end of the ISOBMFF box at `pos`, which has to end
+/// by `limit`
+fn box_at(bytes: &[u8], pos: usize, limit: usize) -> Option<([u8; 4], usize, usize)> {
+    let mut header = Reader::new(bytes.get(pos..limit)?);
+    let size = header.uint(4)?;
+    let kind: [u8; 4] = (header.uint(4)? as u32).to_be_bytes();
+    let size = match size {
+        0 => (limit - pos) as u64,
+        1 => header.uint(8)?,
+        size => size,
+    };
+    let end = pos.checked_add(usize::try_from(size).ok()?)?;
+    if size < header.pos as u64 || end > limit {
+        return None;
+    }
+    Some((kind, pos + header.pos, end))
+}
+
+/// Ids of the Exif and XMP items in the body of an `iinf` box
+fn metadata_items(iinf: &[u8]) -> Option<Vec<u32>> {
+    let mut header = Reader::new(iinf);
+    let version = header.uint(1)?;
+    header.uint(3)?;
+    let count = header.uint(if version == 0 { 2 } else { 4 })?;
+
+    let mut items = Vec::new();
+    let mut pos = header.pos;
+    for _ in 0..count {
+        let (kind, body, end) = box_at(iinf, pos, iinf.len())?;
+        pos = end;
+        if &kind != b"infe" {
+            continue;
+        }
+        let mut infe = Reader::new(&iinf[body..end]);
+        let version = infe.uint(1)?;
+        infe.uint(3)?;
+        // Older entries have no item type, and aren't used for metadata
+        if version < 2 {
+            continue;
+        }
+        let id = infe.uint(if version == 2 { 2 } else { 4 })? as u32;
+        infe.uint(2)?;
+        let is_metadata = match &(infe.uint(4)? as u32).to_be_bytes() {
+            b"Exif" => true,
+            // XMP is stored as a MIME item after the item's name
+            b"mime" => {
+                let mut strings = infe.rest().split(|&b| b == 0).skip(1);
+                strings.next() == Some(b"application/rdf+xml")
+            }
+            _ => false,
+        };
+        if is_metadata {
+            items.push(id);
+        }
+    }
+    Some(items)
+}
+
+/// Construction method, offset and length of each extent of `items`, from
+/// the body of an `iloc` box
+fn item_extents(iloc: &[u8], items: &[u32]) -> Option<Vec<(u64, u64, u64)>> {
+    let mut iloc = Reader::new(iloc);
+    let version = iloc.uint(1)?;
+    iloc.uint(3)?;
+    let sizes = iloc.uint(1)?;
+    let (offset_size, length_size) = (sizes >> 4, sizes & 0x0F);
+    let sizes = iloc.uint(1)?;
+    let base_offset_size = sizes >> 4;
+    let index_size = if version == 0 { 0 } else { sizes & 0x0F };
+    let id_size = if version < 2 { 2 } else { 4 };
+
+    let mut extents = Vec::new();
+    for _ in 0..iloc.uint(id_size)? {
+        let id = iloc.uint(id_size)? as u32;
+        let construction_method = if version == 0 {
+            0
+        } else {
+            iloc.uint(2)? & 0x0F
+        };
+        // Data reference index
+        iloc.uint(2)?;
+        let base_offset = iloc.uint(base_offset_size as usize)?;
+        for _ in 0..iloc.uint(2)? {
+            iloc.uint(index_size as usize)?;
+            let offset = iloc.uint(offset_size as usize)?;
+            let length = iloc.uint(length_size as usize)?;
+            if items.contains(&id) {
+                extents.push((
+                    construction_method,
+                    base_offset.checked_add(offset)?,
+                    length,
+                ));
+            }
+        }
+    }
+    Some(extents)
+}
+
+/// Big-endian fields read one after another
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    /// Unsigned integer of `size` bytes, up to 8; a size of 0 reads as 0
+    fn uint(&mut self, size: usize) -> Option<u64> {
+        if size > 8 {
+            return None;
+        }
+        let field = self.bytes.get(self.pos..self.pos + size)?;
+        self.pos += size;
+        Some(field.iter().fold(0, |n, &b| n << 8 | b as u64))
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.pos..]
+    }
+}
+
+/// TIFF tags recording where, when, with what and by whom an image was made
+/// rather than how to show it
+const TIFF_METADATA_TAGS: &[u16] = &[
+    0x010D, // DocumentName
+    0x010E, // ImageDescription
+    0x010F, // Make
+    0x0110, // Model
+    0x0131, // Software
+    0x0132, // DateTime
+    0x013B, // Artist
+    0x013C, // HostComputer
+    0x02BC, // XMP
+    0x8298, // Copyright
+    0x83BB, // IPTC
+    0x8649, // Photoshop image resources
+    TIFF_EXIF_IFD,
+    TIFF_GPS_IFD,
+];
+const TIFF_EXIF_IFD: u16 = 0x8769;
+const TIFF_GPS_IFD: u16 = 0x8825;
+/// Most images followed in one TIFF file, so a looping chain ends
+const MAX_TIFF_IFDS: usize = 64;
+
+/// Remove metadata tags from every image of a TIFF file in place. Removed
+/// entries are dropped from their directory and what they point to,
+/// including the whole EXIF and GPS directories, is zeroed, so image data
+/// doesn't move. BigTIFF isn't handled.
+fn tiff(mut bytes: Vec<u8>) -> Result<Vec<u8>, StripError> {
+    const ERROR: StripError = StripError("TIFF");
+
+    let big_endian = match bytes.get(..4) {
+        Some(b"MM\0\x2A") => true,
+        Some(b"II\x2A\0") => false,
+        _ => return Err(ERROR),
+    };
+    let tiff = Tiff { big_endian };
+
+    let mut ifd = tiff.u32(&bytes, 4).ok_or(ERROR)?;
+    for _ in 0..MAX_TIFF_IFDS {
+        if ifd == 0 {
+            return Ok(bytes);
+        }
+        ifd = tiff.strip_ifd(&mut bytes, ifd as usize).ok_or(ERROR)?;
+    }
+    Err(ERROR)
+}
+
+struct Tiff {
+    big_endian: bool,
+}
+
+impl Tiff {
+    fn u16(&self, bytes: &[u8], at: usize) -> Option<u16> {
+        let b = bytes.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    fn u32(&self, bytes: &[u8], at: usize) -> Option<u32> {
+        let b = bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    fn put_u16(&self, bytes: &mut [u8], at: usize, value: u16) {
+        let b = if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        bytes[at..at + 2].copy_from_slice(&b);
+    }
+
+    /// Drop the metadata entries of the directory at `at`, returning the
+    /// offset of the next one
+    fn strip_ifd(&self, bytes: &mut [u8], at: usize) -> Option<u32> {
+        let count = self.u16(bytes, at)? as usize;
+        let entries = at + 2;
+        let next_at = entries + count * 12;
+        let next = self.u32(bytes, next_at)?;
+
+        let mut kept = 0;
+        for i in 0..count {
+            let entry = entries + i * 12;
+            let tag = self.u16(bytes, entry)?;
+            if TIFF_METADATA_TAGS.contains(&tag) {
+                if matches!(tag, TIFF_EXIF_IFD | TIFF_GPS_IFD) {
+                    let sub = self.u32(bytes, entry + 8)? as usize;
+                    self.clear_ifd(bytes, sub);
+                }
+                self.clear_value(bytes, entry);
+            } else {
+                bytes.copy_within(entry..entry + 12, entries + kept * 12);
+                kept += 1;
+            }
+        }
+
+        // Entries stay sorted; the next-directory offset moves up behind them
+        self.put_u16(bytes, at, kept as u16);
+        let moved_to = entries + kept * 12;
+        bytes.copy_within(next_at..next_at + 4, moved_to);
+        bytes[moved_to + 4..next_at + 4].fill(0);
+        Some(next)
+    }
+
+    /// Zero a directory that's no longer referenced, and its values
+    fn clear_ifd(&self, bytes: &mut [u8], at: usize) {
+        let Some(count) = self.u16(bytes, at) else {
+            return;
+        };
+        let end = (at + 2 + count as usize * 12 + 4).min(bytes.len());
+        for i in 0..count as usize {
+            self.clear_value(bytes, at + 2 + i * 12);
+        }
+        bytes[at..end].fill(0);
+    }
+
+    /// Zero an entry's value where it's stored outside the entry
+    fn clear_value(&self, bytes: &mut [u8], entry: usize) {
+        let (Some(kind), Some(count), Some(offset)) = (
+            self.u16(bytes, entry + 2),
+            self.u32(bytes, entry + 4),
+            self.u32(bytes, entry + 8),
+        ) else {
+            return;
+        };
+        let width = match kind {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 | 13 => 4,
+            5 | 10 | 12 => 8,
+            _ => return,
+        };
+        let size = width * count as u64;
+        let (start, end) = (offset as u64, offset as u64 + size);
+        if size > 4 && end <= bytes.len() as u64 {
+            bytes[start as usize..end as usize].fill(0);
+        }
+    }
+}
+
+/// Blank out metadata boxes in place. Boxes keep their size and are renamed
+/// to `free`, so the sample offsets in the movie header stay valid.
+fn mp4(mut bytes: Vec<u8>) -> Result<Vec<u8>, StripError> {
+    /// UUID Adobe uses for top-level XMP boxes
+    const XMP_UUID: [u8; 16] = [
+        0xBE, 0x7A, 0xCF, 0xCB, 0x97, 0xA9, 0x42, 0xE8, 0x9C, 0x71, 0x99, 0x94, 0x91, 0xE3, 0xAF,
+        0xAC,
+    ];
+
+    fn walk(bytes: &mut [u8], top_level: bool) -> Result<(), StripError> {
+        const ERROR: StripError = StripError("MP4");
+
+        let mut pos = 0;
+        while pos + 8 <= bytes.len() {
+            let size = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as u64;
+            let (size, header) = match size {
+                0 => ((bytes.len() - pos) as u64, 8),
+                1 => {
+                    let large = bytes.get(pos + 8..pos + 16).ok_or(ERROR)?;
+                    (u64::from_be_bytes(large.try_into().unwrap()), 16)
+                }
+                size => (size, 8),
+            };
+            if size < header as u64 || pos as u64 + size > bytes.len() as u64 {
+                return Err(ERROR);
+            }
+            let end = pos + size as usize;
+            let kind: [u8; 4] = bytes[pos + 4..pos + 8].try_into().unwrap();
+
+            let is_metadata = match &kind {
+                b"udta" | b"meta" => true,
+                b"uuid" => {
+                    top_level && bytes.get(pos + header..pos + header + 16) == Some(&XMP_UUID)
+                }
+                _ => false,
+            };
+
+            if is_metadata {
+                bytes[pos + 4..pos + 8].copy_from_slice(b"free");
+                bytes[pos + header..end].fill(0);
+            } else if matches!(&kind, b"moov" | b"trak") {
+                walk(&mut bytes[pos + header..end], false)?;
+            }
+
+            pos = end;
+        }
+        Ok(())
+    }
+
+    walk(&mut bytes, true)?;
+    Ok(bytes)
+}
@@ -0,0 +1,297 @@
+//! Video transcoding
+//!
+//! Re-encodes videos with ffmpeg so long screen recordings fit under the
+//! attachment limit. ffmpeg isn't bundled; the binary configured under
+//! [`FFMPEG_PATH_SETTING`] is used, or the first `ffmpeg` on the `PATH`.
+//! The video bitrate is chosen from the clip's duration so the output
+//! lands just under the target size.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+
+/// Setting holding the path to the ffmpeg binary
+pub const FFMPEG_PATH_SETTING: &str = "media.ffmpeg_path";
+/// Setting holding the [`VideoCodec`] to encode with
+pub const CODEC_SETTING: &str = "media.video_codec";
+/// Setting holding the [`VideoQuality`] to encode at
+pub const QUALITY_SETTING: &str = "media.video_quality";
+
+/// Audio bitrate for transcoded videos, in kbit/s
+const AUDIO_KBPS: f64 = 96.0;
+/// Lowest video bitrate worth producing, in kbit/s
+const MIN_VIDEO_KBPS: f64 = 150.0;
+/// Share of the target size left for container overhead and rate control overshoot
+const SIZE_MARGIN: f64 = 0.92;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VideoCodec {
+    /// H.264 in MP4, playable everywhere
+    #[default]
+    H264,
+    /// VP9 in WebM, smaller at the same quality but slower to encode
+    Vp9,
+}
+
+impl VideoCodec {
+    pub fn extension(self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "mp4",
+            VideoCodec::Vp9 => "webm",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VideoQuality {
+    /// 720p, fastest encode
+    Low,
+    /// 1080p
+    #[default]
+    Medium,
+    /// 1440p, slowest encode
+    High,
+}
+
+impl VideoQuality {
+    fn max_width(self) -> u32 {
+        match self {
+            VideoQuality::Low => 1280,
+            VideoQuality::Medium => 1920,
+            VideoQuality::High => 2560,
+        }
+    }
+
+    /// Bitrate beyond which more bits don't visibly help at this resolution, in kbit/s
+    fn max_video_kbps(self) -> f64 {
+        match self {
+            VideoQuality::Low => 2000.0,
+            VideoQuality::Medium => 5000.0,
+            VideoQuality::High => 9000.0,
+        }
+    }
+
+    fn x264_preset(self) -> &'static str {
+        match self {
+            VideoQuality::Low => "veryfast",
+            VideoQuality::Medium => "medium",
+            VideoQuality::High => "slow",
+        }
+    }
+
+    fn vp9_cpu_used(self) -> &'static str {
+        match self {
+            VideoQuality::Low => "5",
+            VideoQuality::Medium => "3",
+            VideoQuality::High => "2",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscodeOptions {
+    /// Configured ffmpeg location; found on the `PATH` if unset
+    pub ffmpeg_path: Option<PathBuf>,
+    pub codec: VideoCodec,
+    pub quality: VideoQuality,
+    /// Size the output should stay under, in bytes
+    pub target_size: u64,
+    /// Drop container metadata (creation time, location, device)
+    pub strip_metadata: bool,
+    /// Encode with the fastest preset whatever the quality, for low-end
+    /// hardware
+    pub fast: bool,
+}
+
+impl TranscodeOptions {
+    /// Quality the encoder preset is picked for
+    fn preset(&self) -> VideoQuality {
+        if self.fast {
+            VideoQuality::Low
+        } else {
+            self.quality
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TranscodeError {
+    #[error("ffmpeg not found; install it or set its location in settings")]
+    NotFound,
+    #[error("failed to run ffmpeg: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("couldn't read the video's duration")]
+    UnknownDuration,
+    #[error("video is too long to fit in {0} MB")]
+    TooLong(u64),
+    #[error("ffmpeg failed: {0}")]
+    Failed(String),
+}
+
+/// Locate ffmpeg, preferring a configured path
+fn find_ffmpeg(configured: Option<PathBuf>) -> Option<PathBuf> {
+    if let Some(path) = configured {
+        return path.is_file().then_some(path);
+    }
+    find_on_path("ffmpeg")
+}
+
+/// Transcode `input` into `output`, reporting progress between 0 and 1
+pub async fn transcode(
+    input: &Path,
+    output: &Path,
+    options: &TranscodeOptions,
+    mut on_progress: impl FnMut(f64),
+) -> Result<(), TranscodeError> {
+    let ffmpeg = find_ffmpeg(options.ffmpeg_path.clone()).ok_or(TranscodeError::NotFound)?;
+    let duration = probe_duration(&ffmpeg, input).await?;
+
+    let total_kbps = options.target_size as f64 * 8.0 * SIZE_MARGIN / duration / 1000.0;
+    let video_kbps = (total_kbps - AUDIO_KBPS).min(options.quality.max_video_kbps());
+    if video_kbps < MIN_VIDEO_KBPS {
+        return Err(TranscodeError::TooLong(options.target_size / (1024 * 1024)));
+    }
+    let video_kbps = video_kbps.round() as u64;
+
+    let mut command = command(&ffmpeg);
+    command
+        .args(["-y", "-nostdin", "-hide_banner", "-loglevel", "error"])
+        .args(["-progress", "pipe:1", "-nostats"])
+        .arg("-i")
+        .arg(input)
+        .args(["-map", "0:v:0", "-map", "0:a:0?"])
+        .arg("-vf")
+        .arg(format!(
+            "scale='min({},iw)':-2",
+            options.quality.max_width()
+        ))
+        .arg("-b:v")
+        .arg(format!("{}k", video_kbps))
+        .arg("-maxrate")
+        .arg(format!("{}k", video_kbps * 3 / 2))
+        .arg("-bufsize")
+        .arg(format!("{}k", video_kbps * 2));
+
+    match options.codec {
+        VideoCodec::H264 => command
+            .args(["-c:v", "libx264", "-preset", options.preset().x264_preset()])
+            .args(["-pix_fmt", "yuv420p", "-c:a", "aac"])
+            .args(["-movflags", "+faststart", "-f", "mp4"]),
+        VideoCodec::Vp9 => command
+            .args(["-c:v", "libvpx-vp9", "-deadline", "good", "-row-mt", "1"])
+            .args(["-cpu-used", options.preset().vp9_cpu_used()])
+            .args(["-c:a", "libopus", "-f", "webm"]),
+    };
+    command.arg("-b:a").arg(format!("{}k", AUDIO_KBPS));
+    if options.strip_metadata {
+        command.args(["-map_metadata", "-1", "-map_chapters", "-1"]);
+    }
+    command.arg(output);
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let errors = tauri::async_runtime::spawn(async move {
+        let mut errors = String::new();
+        let _ = stderr.read_to_string(&mut errors).await;
+        errors
+    });
+
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(line) = lines.next_line().await? {
+        if let Some(micros) = line.strip_prefix("out_time_us=") {
+            if let Ok(micros) = micros.trim().parse::<f64>() {
+                on_progress((micros / 1_000_000.0 / duration).clamp(0.0, 1.0));
+            }
+        }
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        let _ = tokio::fs::remove_file(output).await;
+        let errors = errors.await.unwrap_or_default();
+        return Err(TranscodeError::Failed(
+            errors.lines().last().unwrap_or("unknown error").to_string(),
+        ));
+    }
+
+    on_progress(1.0);
+    Ok(())
+}
+
+/// Duration of a video in seconds
+async fn probe_duration(ffmpeg: &Path, input: &Path) -> Result<f64, TranscodeError> {
+    let ffprobe = sibling(ffmpeg, "ffprobe")
+        .or_else(|| find_on_path("ffprobe"))
+        .ok_or(TranscodeError::NotFound)?;
+
+    let output = command(&ffprobe)
+        .args(["-v", "error", "-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(input)
+        .output()
+        .await?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|d| *d > 0.0)
+        .ok_or(TranscodeError::UnknownDuration)
+}
+
+fn command(program: &Path) -> Command {
+    let mut command = Command::new(program);
+    command.kill_on_drop(true);
+
+    // Don't flash a console window for every encode
+    #[cfg(windows)]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    command
+}
+
+/// A tool installed next to `program`, as ffprobe usually is next to ffmpeg
+fn sibling(program: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = program.with_file_name(name);
+    if let Some(extension) = program.extension() {
+        path.set_extension(extension);
+    }
+    path.is_file().then_some(path)
+}
+
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    let file = if cfg!(windows) {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    };
+
+    // Apps launched from Finder don't get the shell's PATH, so also look
+    // where Homebrew installs
+    let extra: &[&str] = if cfg!(target_os = "macos") {
+        &["/opt/homebrew/bin", "/usr/local/bin"]
+    } else {
+        &[]
+    };
+
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .chain(extra.iter().map(PathBuf::from))
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+}
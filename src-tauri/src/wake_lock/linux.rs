@@ -0,0 +1,31 @@
+//! A logind inhibitor lock, as `systemd-inhibit` takes: logind holds off
+//! sleep and idle actions for as long as the returned file descriptor is
+//! open. It shows up in `systemd-inhibit --list` under the lock's reason.
+
+use zbus::zvariant::OwnedFd;
+use zbus::Connection;
+
+use super::WakeLockError;
+
+pub(super) struct Inhibitor {
+    /// Closed when dropped, which releases the lock
+    _fd: OwnedFd,
+}
+
+impl Inhibitor {
+    pub(super) async fn acquire(reason: &str) -> Result<Self, WakeLockError> {
+        let system = Connection::system().await?;
+        let reply = system
+            .call_method(
+                Some("org.freedesktop.login1"),
+                "/org/freedesktop/login1",
+                Some("org.freedesktop.login1.Manager"),
+                "Inhibit",
+                &("sleep:idle", "Redoubt", reason, "block"),
+            )
+            .await?;
+        Ok(Self {
+            _fd: reply.body().deserialize()?,
+        })
+    }
+}
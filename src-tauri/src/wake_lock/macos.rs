@@ -0,0 +1,58 @@
+use core_foundation::base::TCFType;
+use core_foundation::string::CFString;
+use core_foundation_sys::string::CFStringRef;
+
+use super::WakeLockError;
+
+type AssertionId = u32;
+
+/// `kIOPMAssertionLevelOn`
+const ASSERTION_LEVEL_ON: u32 = 255;
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOPMAssertionCreateWithName(
+        assertion_type: CFStringRef,
+        level: u32,
+        name: CFStringRef,
+        id: *mut AssertionId,
+    ) -> i32;
+    fn IOPMAssertionRelease(id: AssertionId) -> i32;
+}
+
+/// A power assertion that stops idle sleep; it also shows up in
+/// `pmset -g assertions` under the lock's reason
+pub(super) struct Inhibitor {
+    id: AssertionId,
+}
+
+impl Inhibitor {
+    pub(super) async fn acquire(reason: &str) -> Result<Self, WakeLockError> {
+        let assertion_type = CFString::from_static_string("PreventUserIdleSystemSleep");
+        let name = CFString::new(&format!("Redoubt: {}", reason));
+        let mut id = 0;
+        // SAFETY: both strings are valid CFStrings and `id` is writable
+        let result = unsafe {
+            IOPMAssertionCreateWithName(
+                assertion_type.as_concrete_TypeRef(),
+                ASSERTION_LEVEL_ON,
+                name.as_concrete_TypeRef(),
+                &mut id,
+            )
+        };
+        if result != 0 {
+            return Err(WakeLockError::Platform(format!(
+                "IOPMAssertionCreateWithName failed with {:#x}",
+                result
+            )));
+        }
+        Ok(Self { id })
+    }
+}
+
+impl Drop for Inhibitor {
+    fn drop(&mut self) {
+        // SAFETY: the assertion was created by `acquire` and is released once
+        unsafe { IOPMAssertionRelease(self.id) };
+    }
+}
@@ -0,0 +1,109 @@
+//! Sleep inhibitors
+//!
+//! Keeps the system awake while something that mustn't be cut off is
+//! running, such as a call. Each lock is held until it's released or the
+//! app exits, and several can be held at once for different reasons; the
+//! system may sleep again once none are left.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(windows)]
+mod windows;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[cfg(target_os = "linux")]
+use linux::Inhibitor;
+#[cfg(target_os = "macos")]
+use macos::Inhibitor;
+#[cfg(windows)]
+use windows::Inhibitor;
+
+/// Other platforms can't keep the system awake
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+struct Inhibitor;
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+impl Inhibitor {
+    async fn acquire(_reason: &str) -> Result<Self, WakeLockError> {
+        Err(WakeLockError::Platform(
+            "not supported on this platform".to_string(),
+        ))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WakeLockError {
+    #[cfg(not(target_os = "linux"))]
+    #[error("couldn't keep the system awake: {0}")]
+    Platform(String),
+    #[cfg(target_os = "linux")]
+    #[error("D-Bus error: {0}")]
+    DBus(#[from] zbus::Error),
+}
+
+/// A held lock, as listed for debugging
+#[derive(Debug, Clone, Serialize)]
+pub struct WakeLockInfo {
+    pub id: String,
+    pub reason: String,
+    pub acquired_at: DateTime<Utc>,
+}
+
+struct WakeLock {
+    info: WakeLockInfo,
+    /// Released when dropped
+    _inhibitor: Inhibitor,
+}
+
+#[derive(Default)]
+pub struct WakeLocks {
+    locks: Mutex<HashMap<String, WakeLock>>,
+}
+
+impl WakeLocks {
+    /// Keep the system from sleeping until the returned lock is released
+    pub async fn acquire(&self, reason: &str) -> Result<WakeLockInfo, WakeLockError> {
+        let inhibitor = Inhibitor::acquire(reason).await?;
+        let info = WakeLockInfo {
+            id: uuid::Uuid::new_v4().to_string(),
+            reason: reason.to_string(),
+            acquired_at: Utc::now(),
+        };
+        self.lock().insert(
+            info.id.clone(),
+            WakeLock {
+                info: info.clone(),
+                _inhibitor: inhibitor,
+            },
+        );
+        log::info!("Keeping the system awake: {}", reason);
+        Ok(info)
+    }
+
+    /// Release a lock; returns false if it isn't held
+    pub fn release(&self, id: &str) -> bool {
+        let lock = self.lock().remove(id);
+        if let Some(lock) = &lock {
+            log::info!("No longer keeping the system awake: {}", lock.info.reason);
+        }
+        lock.is_some()
+    }
+
+    /// Locks currently held, oldest first
+    pub fn list(&self) -> Vec<WakeLockInfo> {
+        let mut locks: Vec<_> = self.lock().values().map(|l| l.info.clone()).collect();
+        locks.sort_by_key(|l| l.acquired_at);
+        locks
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, WakeLock>> {
+        self.locks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,47 @@
+//! The execution state set by `SetThreadExecutionState` belongs to the
+//! calling thread and is cleared when it exits, so each lock gets a thread
+//! that sets it and then waits to be released.
+
+use std::sync::mpsc;
+use std::thread;
+
+use windows_sys::Win32::System::Power::{
+    SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED,
+};
+
+use super::WakeLockError;
+
+pub(super) struct Inhibitor {
+    /// Dropping this wakes the thread so it clears its state and exits
+    _release: mpsc::Sender<()>,
+}
+
+impl Inhibitor {
+    pub(super) async fn acquire(_reason: &str) -> Result<Self, WakeLockError> {
+        let (release, released) = mpsc::channel::<()>();
+        let (started, result) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("wake-lock".to_string())
+            .spawn(move || {
+                // SAFETY: no preconditions; returns 0 on failure
+                let set = unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) };
+                let _ = started.send(set != 0);
+                if set == 0 {
+                    return;
+                }
+                // Blocks until the sender is dropped
+                let _ = released.recv();
+                // SAFETY: as above
+                unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+            })
+            .map_err(|e| WakeLockError::Platform(e.to_string()))?;
+
+        match result.recv() {
+            Ok(true) => Ok(Self { _release: release }),
+            _ => Err(WakeLockError::Platform(
+                "SetThreadExecutionState failed".to_string(),
+            )),
+        }
+    }
+}